@@ -1,9 +1,14 @@
 use super::*;
 
-use core::VerifierCore;
+use super::verifier::check_role_access;
+use core::{GuildSetting, RawConfigValue, VerifierCore, check_webhook_url, parse_colour};
+use error_report::{ReportSink, ReportSinkKind};
+use i18n;
 use std::fmt::Display;
 use util;
 
+const MAX_PREFIX_LENGTH: usize = 10;
+
 #[derive(Copy, Clone, Eq, PartialEq)]
 enum GuildShowType {
     AlwaysShow, OnlyInTerminal, OnlyInGuild, AlwaysHidden,
@@ -34,6 +39,91 @@ fn parse_u32(s: &str) -> Result<u32> {
 fn parse_u64(s: &str) -> Result<u64> {
     parse_as(s, "Setting must be a non-negative number.")
 }
+fn parse_channel(s: &str) -> Result<ChannelId> {
+    let s = s.trim();
+    let s = if s.starts_with("<#") && s.ends_with(">") { &s[2..s.len()-1] } else { s };
+    Ok(ChannelId(parse_as(s, "Setting must be a channel mention or channel ID.")?))
+}
+fn parse_role(s: &str) -> Result<RoleId> {
+    let s = s.trim();
+    let s = if s.starts_with("<@&") && s.ends_with(">") { &s[3..s.len()-1] } else { s };
+    Ok(RoleId(parse_as(s, "Setting must be a role mention or role ID.")?))
+}
+fn print_role(role: Option<RoleId>) -> String {
+    role.map_or_else(|| "*(none set)*".to_owned(), |x| format!("<@&{}>", x.0))
+}
+// Role lists are stored as comma separated role IDs.
+fn parse_role_list(s: &str) -> Result<Option<String>> {
+    let roles = s.split(',').filter(|x| !x.trim().is_empty())
+        .map(|x| parse_role(x).map(|x| x.0.to_string()))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(if roles.is_empty() { None } else { Some(roles.join(",")) })
+}
+fn print_role_list(roles: Option<String>) -> String {
+    match roles {
+        Some(roles) =>
+            roles.split(',').map(|x| format!("<@&{}>", x)).collect::<Vec<_>>().join(", "),
+        None => "*(none set)*".to_owned(),
+    }
+}
+fn parse_user(s: &str) -> Result<UserId> {
+    let s = s.trim();
+    let s = if s.starts_with("<@!") && s.ends_with(">") {
+        &s[3..s.len()-1]
+    } else if s.starts_with("<@") && s.ends_with(">") {
+        &s[2..s.len()-1]
+    } else {
+        s
+    };
+    Ok(UserId(parse_as(s, "Setting must be a user mention or user ID.")?))
+}
+// User lists are stored as comma separated user IDs.
+fn parse_user_list(s: &str) -> Result<Option<String>> {
+    let users = s.split(',').filter(|x| !x.trim().is_empty())
+        .map(|x| parse_user(x).map(|x| x.0.to_string()))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(if users.is_empty() { None } else { Some(users.join(",")) })
+}
+fn print_user_list(users: Option<String>) -> String {
+    match users {
+        Some(users) => users.split(',').collect::<Vec<_>>().join(", "),
+        None => "*(none set)*".to_owned(),
+    }
+}
+// The text a setting is exported as, which its parser reads back. Settings whose parser takes
+// another form give their own with `export = ...`.
+trait ConfigText {
+    fn config_text(&self) -> Option<String>;
+}
+macro_rules! config_text_display {
+    ($($tp:ty),*) => {$(
+        impl ConfigText for $tp {
+            fn config_text(&self) -> Option<String> {
+                Some(self.to_string())
+            }
+        }
+    )*}
+}
+config_text_display!(bool, u32, u64, String);
+impl ConfigText for RoleId {
+    fn config_text(&self) -> Option<String> {
+        Some(self.0.to_string())
+    }
+}
+impl ConfigText for ChannelId {
+    fn config_text(&self) -> Option<String> {
+        Some(self.0.to_string())
+    }
+}
+impl<T: ConfigText> ConfigText for Option<T> {
+    fn config_text(&self) -> Option<String> {
+        self.as_ref().and_then(|x| x.config_text())
+    }
+}
+fn config_text<T: ConfigText>(value: &T) -> Option<String> {
+    value.config_text()
+}
+
 fn print_display<T : Display>(_: &VerifierCore, t: T) -> Result<String> {
     Ok(format!("{}", t))
 }
@@ -69,7 +159,7 @@ fn show_in_terminal_if(core: &VerifierCore, key: ConfigKey<bool>) -> Result<Guil
 macro_rules! config_values {
     ($($config_name:ident<$tp:ty>(
         $config_key:ident, $allow_guild:expr, $show_type:expr,
-        $help:expr, $from_str:expr, $to_str:expr $(,)*
+        $help:expr, $from_str:expr, $to_str:expr $(, export = $export:expr)* $(,)*
     );)*) => {
         fn set_config(
             core: &VerifierCore, guild: Option<GuildId>, key: &str, value: Option<&str>
@@ -94,6 +184,31 @@ macro_rules! config_values {
             Ok(())
         }
 
+        fn print_config_value(
+            core: &VerifierCore, guild: Option<GuildId>, key: &str,
+        ) -> Result<String> {
+            match key {
+                $(
+                    stringify!($config_name) => {
+                        let to_str: fn(&VerifierCore, $tp) -> Result<String> = $to_str;
+                        let value = core.config().get(guild, ConfigKeys::$config_key)?;
+                        let source = match guild {
+                            Some(_) if !$allow_guild =>
+                                " *(This option cannot be overwritten per-server.)*",
+                            Some(guild) if core.config().is_overridden(guild,
+                                                                     ConfigKeys::$config_key)? =>
+                                " *(Set for this server.)*",
+                            Some(_) => " *(Inherited from the global configuration.)*",
+                            None => "",
+                        };
+                        Ok(format!("• {} = {}{}\n   {}",
+                                   stringify!($config_name), to_str(core, value)?, source, $help))
+                    }
+                )*
+                name => cmd_error!("No such configuration option '{}'.", name),
+            }
+        }
+
         fn print_config(core: &VerifierCore, guild: Option<GuildId>) -> Result<String> {
             let mut config = String::new();
             let align = if guild.is_some() { "   " } else { "  " };
@@ -115,23 +230,196 @@ macro_rules! config_values {
             })*
             Ok(config)
         }
+
+        // The settings that can be set per-server, for exporting and importing them.
+        pub(super) fn guild_settings() -> Vec<GuildSetting> {
+            let mut settings = Vec::new();
+            $(
+                if $allow_guild {
+                    settings.push(GuildSetting {
+                        name: stringify!($config_name), key_name: stringify!($config_key),
+                        parse: |x| {
+                            let from_str: fn(&str) -> Result<$tp> = $from_str;
+                            RawConfigValue::from_value(&from_str(x)?)
+                        },
+                        export: |core, guild| {
+                            if !core.config().is_overridden(guild, ConfigKeys::$config_key)? {
+                                return Ok(None)
+                            }
+                            let exports: &[fn(&$tp) -> Option<String>] = &[$($export),*];
+                            let export = exports.first().cloned().unwrap_or(config_text::<$tp>);
+                            Ok(export(&core.config().get(Some(guild), ConfigKeys::$config_key)?))
+                        },
+                    });
+                }
+            )*
+            settings
+        }
     }
 }
 config_values! {
     prefix<String>(
-        CommandPrefix, false, |_| Ok(GuildShowType::AlwaysShow),
-        "The prefix used before commands.",
-        |x| Ok(x.to_owned()), print_quoted);
+        CommandPrefix, true, |_| Ok(GuildShowType::AlwaysShow),
+        "The prefix used before commands. Mentioning the bot also works as a prefix.",
+        |x| {
+            let x = x.trim();
+            cmd_ensure!(!x.is_empty() && x.len() <= MAX_PREFIX_LENGTH &&
+                        !x.contains(char::is_whitespace),
+                        "The prefix must be 1 to {} characters long, with no spaces.",
+                        MAX_PREFIX_LENGTH);
+            Ok(x.to_owned())
+        }, print_quoted);
+    language<String>(
+        Language, true, |_| Ok(GuildShowType::AlwaysShow),
+        "The language used for messages shown while verifying.",
+        |x| {
+            let code = x.trim().to_lowercase();
+            cmd_ensure!(i18n::is_language(&code), "Unknown language. Available languages: {}",
+                        i18n::languages().iter().map(|&(code, name)| format!("{} ({})", code, name))
+                            .collect::<Vec<_>>().join(", "));
+            Ok(code)
+        },
+        print_quoted);
+    command_rate_limit<u32>(
+        CommandRateLimitPerMinute, true, |_| Ok(GuildShowType::OnlyInGuild),
+        "How many commands each user can use per minute, after using up their burst. Set to 0 \
+         for no limit.",
+        parse_u32, print_display);
+    command_rate_limit_burst<u32>(
+        CommandRateLimitBurst, true, |_| Ok(GuildShowType::OnlyInGuild),
+        "How many commands each user can use at once before being rate limited.",
+        parse_u32, print_display);
+    command_rate_limit_exempt_roles<Option<String>>(
+        CommandRateLimitExemptRoles, true, |_| Ok(GuildShowType::OnlyInGuild),
+        "A comma separated list of roles whose members are never rate limited.",
+        parse_role_list, |_, x| Ok(print_role_list(x)));
     discord_token<Option<String>>(
         DiscordToken, false, |_| Ok(GuildShowType::OnlyInTerminal),
         "The bot token used to connect to Discord.",
         |x|    Ok(Some(x.to_owned())),
         |_, x| Ok(x.map_or("(not set)", |_| "<token redacted>").to_owned()));
+    discord_shards<u64>(
+        DiscordShardCount, false, |_| Ok(GuildShowType::OnlyInTerminal),
+        "The number of shards the bot connects to Discord with. Set to 0 to use the number \
+         Discord recommends. Bots in more than 2,500 servers must use more than one shard.",
+        parse_u64, print_display);
     bot_owner_id<Option<u64>>(
         BotOwnerId, false, |_| Ok(GuildShowType::OnlyInTerminal),
         "The user ID of the bot's owner. That account can bypass permissions on any server.",
         |x|    parse_u64(x).map(Some),
         |_, x| Ok(x.map_or("(not set)".to_string(), |x| format!("{}", x)).to_owned()));
+    action_approvers<Option<String>>(
+        ActionApprovers, false, |_| Ok(GuildShowType::OnlyInTerminal),
+        "A comma separated list of user IDs. If set, emergency rekeys, data purges and \
+         verification resets must be confirmed by one of these users other than the one who \
+         requested them.",
+        parse_user_list, |_, x| Ok(print_user_list(x)));
+    action_approval_channel<Option<ChannelId>>(
+        ActionApprovalChannel, false, |_| Ok(GuildShowType::OnlyInTerminal),
+        "The channel admin actions awaiting confirmation are posted to, with buttons to confirm \
+         or cancel them.",
+        |x| parse_channel(x).map(Some),
+        |_, x| Ok(x.map_or_else(|| "*(none set)*".to_owned(), |x| format!("<#{}>", x.0))));
+    action_approval_timeout<u32>(
+        ActionApprovalTimeoutMinutes, false, |_| Ok(GuildShowType::OnlyInTerminal),
+        "How many minutes admin actions can wait to be confirmed before they expire.",
+        parse_u32, print_display);
+
+    web_server_address<Option<String>>(
+        WebServerAddress, false, |_| Ok(GuildShowType::OnlyInTerminal),
+        "The address the web API listens on, e.g. `127.0.0.1:8080`. Unset to disable it.",
+        |x|    Ok(Some(x.to_owned())),
+        |_, x| Ok(x.unwrap_or_else(|| "(disabled)".to_owned())));
+    web_api_key<Option<String>>(
+        WebApiKey, false, |_| Ok(GuildShowType::OnlyInTerminal),
+        "The bearer token required to access the web API.",
+        |x|    Ok(Some(x.to_owned())),
+        |_, x| Ok(x.map_or("(not set)", |_| "<key redacted>").to_owned()));
+    web_badge_secret<Option<String>>(
+        WebBadgeSecret, false, |_| Ok(GuildShowType::OnlyInTerminal),
+        "The secret used to sign verification badge URLs. Unset to disable badges.",
+        |x|    Ok(Some(x.to_owned())),
+        |_, x| Ok(x.map_or("(not set)", |_| "<secret redacted>").to_owned()));
+    web_badge_rate_limit<u32>(
+        WebBadgeRateLimit, false, |_| Ok(GuildShowType::OnlyInTerminal),
+        "How many badge requests each IP address can make per minute. Set to 0 for no limit.",
+        parse_u32, print_display);
+    web_api_rate_limit<u32>(
+        WebApiRateLimit, false, |_| Ok(GuildShowType::OnlyInTerminal),
+        "How many private API requests each IP address can make per minute. Set to 0 for no \
+         limit.",
+        parse_u32, print_display);
+    web_public_url<Option<String>>(
+        WebPublicUrl, false, |_| Ok(GuildShowType::OnlyInTerminal),
+        "The URL the web API can be reached at from the internet, used in badge links.",
+        |x|    Ok(Some(x.trim_right_matches('/').to_owned())),
+        |_, x| Ok(x.unwrap_or_else(|| "(not set)".to_owned())));
+    discord_oauth_client_id<Option<String>>(
+        DiscordOAuthClientId, false, |_| Ok(GuildShowType::OnlyInTerminal),
+        "The client ID of the Discord application used to sign users in on the website.",
+        |x|    Ok(Some(x.to_owned())),
+        |_, x| Ok(x.unwrap_or_else(|| "(not set)".to_owned())));
+    discord_oauth_client_secret<Option<String>>(
+        DiscordOAuthClientSecret, false, |_| Ok(GuildShowType::OnlyInTerminal),
+        "The client secret of the Discord application used to sign users in on the website.",
+        |x|    Ok(Some(x.to_owned())),
+        |_, x| Ok(x.map_or("(not set)", |_| "<secret redacted>").to_owned()));
+    roblox_oauth_client_id<Option<String>>(
+        RobloxOAuthClientId, false, |_| Ok(GuildShowType::OnlyInTerminal),
+        "The client ID of the Roblox OAuth application used to sign users in on the website.",
+        |x|    Ok(Some(x.to_owned())),
+        |_, x| Ok(x.unwrap_or_else(|| "(not set)".to_owned())));
+    roblox_oauth_client_secret<Option<String>>(
+        RobloxOAuthClientSecret, false, |_| Ok(GuildShowType::OnlyInTerminal),
+        "The client secret of the Roblox OAuth application used to sign users in on the \
+         website.",
+        |x|    Ok(Some(x.to_owned())),
+        |_, x| Ok(x.map_or("(not set)", |_| "<secret redacted>").to_owned()));
+    roblox_oauth_redirect_uri<Option<String>>(
+        RobloxOAuthRedirectUri, false, |_| Ok(GuildShowType::OnlyInTerminal),
+        "The page Roblox sends users to after signing in with the verify_oauth command. It must \
+         show users the code it is opened with. Defaults to the page on the bot's website.",
+        |x|    Ok(Some(x.to_owned())),
+        |_, x| Ok(x.unwrap_or_else(|| "(website default)".to_owned())));
+
+    backup_directory<Option<String>>(
+        BackupDirectory, false, |_| Ok(GuildShowType::OnlyInTerminal),
+        "The directory database backups are written to. Relative paths are resolved from the \
+         bot's directory.",
+        |x|    Ok(Some(x.to_owned())),
+        |_, x| Ok(x.unwrap_or_else(|| format!("(default: {})", BACKUP_DIR_NAME))));
+
+    error_report_url<Option<String>>(
+        ErrorReportUrl, false, |_| Ok(GuildShowType::OnlyInTerminal),
+        "A Sentry DSN or webhook URL that error and panic reports are sent to, as well as being \
+         written to the logs directory. Credentials are removed from reports before sending.",
+        |x| {
+            ReportSink::new(ReportSinkKind::Sentry, x)
+                .or_else(|_| ReportSink::new(ReportSinkKind::Webhook, x))?;
+            Ok(Some(x.to_owned()))
+        },
+        |_, x| Ok(x.map_or("(not set)", |_| "<secret redacted>").to_owned()));
+    error_report_format<String>(
+        ErrorReportFormat, false, |_| Ok(GuildShowType::OnlyInTerminal),
+        "How reports are sent to error_report_url. `sentry` sends Sentry events, and `webhook` \
+         posts the report as JSON.",
+        |x| Ok(ReportSinkKind::from_name(&x.to_lowercase())?.name().to_owned()),
+        print_display);
+
+    roblox_cache_time<u32>(
+        RobloxCacheSeconds, false, |_| Ok(GuildShowType::OnlyInTerminal),
+        "How many seconds results from the Roblox API, such as group ranks and usernames, are \
+         reused before they are looked up again.",
+        parse_u32, print_display);
+    roblox_headshot_cache_time<u32>(
+        RobloxHeadshotCacheSeconds, false, |_| Ok(GuildShowType::OnlyInTerminal),
+        "How many seconds avatar headshot URLs are reused before they are looked up again.",
+        parse_u32, print_display);
+    roblox_cache_stale_time<u32>(
+        RobloxCacheStaleSeconds, false, |_| Ok(GuildShowType::OnlyInTerminal),
+        "How many seconds past their cache time Roblox API results are still used while they \
+         are looked up again in the background. 0 always waits for the new result.",
+        parse_u32, print_display);
 
     roles_enable_limits<bool>(
         RolesEnableLimits, false, |_| Ok(GuildShowType::OnlyInTerminal),
@@ -158,6 +446,34 @@ config_values! {
         SetNickname, true, |_| Ok(GuildShowType::AlwaysShow),
         "Whether to set a user's nickname to their Roblox username while updating their roles.",
         parse_bool, print_display);
+    nickname_format<String>(
+        NicknameFormat, true, |_| Ok(GuildShowType::AlwaysShow),
+        "The format used for nicknames. `{roblox_name}` and `{discord_name}` are replaced with \
+         the user's Roblox and Discord usernames.",
+        |x| {
+            check_nickname_format(x)?;
+            Ok(x.to_owned())
+        },
+        print_quoted);
+    nickname_source<String>(
+        NicknameSource, true, |_| Ok(GuildShowType::AlwaysShow),
+        "Which Roblox name `{roblox_name}` is replaced with in nicknames: `username`, \
+         `display_name`, or `combined` for both, e.g. `Display Name (@username)`.",
+        |x| Ok(NicknameSource::from_name(&x.to_lowercase())?.name().to_owned()),
+        print_display);
+
+    set_roles_on_verify<bool>(
+        SetRolesOnVerify, true, |_| Ok(GuildShowType::AlwaysShow),
+        "Whether to update a user's roles in this server when they verify in another server.",
+        parse_bool, print_display);
+    verified_role<Option<RoleId>>(
+        VerifiedRole, true, |_| Ok(GuildShowType::OnlyInGuild),
+        "A role given to every verified user, and removed when they unverify.",
+        |x| parse_role(x).map(Some), |_, x| Ok(print_role(x)));
+    unverified_role<Option<RoleId>>(
+        UnverifiedRole, true, |_| Ok(GuildShowType::OnlyInGuild),
+        "A role removed when a user verifies, and given back when they unverify.",
+        |x| parse_role(x).map(Some), |_, x| Ok(print_role(x)));
 
     allow_set_roles_on_join<bool>(
         AllowSetRolesOnJoin, false, |_| Ok(GuildShowType::OnlyInTerminal),
@@ -167,6 +483,51 @@ config_values! {
         SetRolesOnJoin, true, |x| show_if(x, ConfigKeys::AllowSetRolesOnJoin),
         "Whether to set a user's roles on server join based on an existing verification.",
         parse_bool, print_display);
+    join_gate<bool>(
+        JoinGate, true, |_| Ok(GuildShowType::OnlyInGuild),
+        "Whether new members who are not verified are given the unverified role and sent \
+         verification instructions in direct messages. The message can be changed with \
+         `set message join_welcome`.",
+        parse_bool, print_display);
+    unverified_grace_period<u32>(
+        UnverifiedGracePeriodHours, true, |_| Ok(GuildShowType::OnlyInGuild),
+        "How many hours new members have to verify before unverified_action is taken against \
+         them. 0 disables this.",
+        parse_u32, print_display);
+    unverified_warning<u32>(
+        UnverifiedWarningHours, true, |_| Ok(GuildShowType::OnlyInGuild),
+        "How many hours before the grace period ends unverified members are warned in direct \
+         messages. 0 disables the warning.",
+        parse_u32, print_display);
+    unverified_action<String>(
+        UnverifiedAction, true, |_| Ok(GuildShowType::OnlyInGuild),
+        "What happens to members who have not verified after the grace period: `kick` or \
+         `timeout`. Timeouts last a week, and are renewed until the member verifies.",
+        |x| Ok(EnforcementAction::from_name(&x.to_lowercase())?.name().to_owned()),
+        print_display);
+    unverified_exempt_roles<Option<String>>(
+        UnverifiedExemptRoles, true, |_| Ok(GuildShowType::OnlyInGuild),
+        "A comma separated list of roles whose members are never kicked or timed out for not \
+         verifying.",
+        parse_role_list, |_, x| Ok(print_role_list(x)));
+    verification_reminders<bool>(
+        VerificationReminders, true, |_| Ok(GuildShowType::OnlyInGuild),
+        "Whether members who have not verified are reminded to in direct messages, after the \
+         number of days in verification_reminder_days.",
+        parse_bool, print_display);
+    verification_reminder_days<String>(
+        VerificationReminderDays, true, |_| Ok(GuildShowType::OnlyInGuild),
+        "A comma separated list of how many days after joining unverified members are reminded \
+         to verify, e.g. `1,3,7`. Each reminder is only sent once.",
+        |x| Ok(parse_reminder_days(x)?.iter().map(|x| x.to_string())
+                   .collect::<Vec<_>>().join(",")),
+        print_display);
+    role_decay_days<u32>(
+        RoleDecayDays, true, |_| Ok(GuildShowType::OnlyInGuild),
+        "How many days members keep their verification roles without verifying again or \
+         updating their roles. After this, the roles are removed until they update them. \
+         0 to disable.",
+        parse_u32, print_display);
 
     allow_auto_update_roles<bool>(
         AllowEnableAutoUpdate, false, |_| Ok(GuildShowType::OnlyInTerminal),
@@ -212,20 +573,235 @@ config_values! {
         "The Asset ID shown in the verification place UI background.",
         |x|    Ok(Some(x.to_owned())),
         |_, x| Ok(x.unwrap_or_else(|| "(default)".to_owned())));
+    place_ui_token_countdown<bool>(
+        PlaceUIShowTokenCountdown, false, |_| Ok(GuildShowType::OnlyInTerminal),
+        "Whether the verification place UI shows how long until the code shown changes.",
+        parse_bool, print_display);
     place_id<Option<u64>>(
         PlaceID, false, |_| Ok(GuildShowType::OnlyInTerminal),
         "The ID of the verification place. This is displayed in verification channel messages.",
         |x| parse_u64(x).map(Some),
         |_, x| Ok(x.map_or_else(|| "*(none set)*".to_owned(), |x| format!("{}", x))));
+    universe_id<Option<u64>>(
+        PlaceUniverseID, false, |_| Ok(GuildShowType::OnlyInTerminal),
+        "The ID of the experience containing the verification place. Used to publish the place.",
+        |x| parse_u64(x).map(Some),
+        |_, x| Ok(x.map_or_else(|| "*(none set)*".to_owned(), |x| format!("{}", x))));
+    open_cloud_api_key<Option<String>>(
+        OpenCloudApiKey, false, |_| Ok(GuildShowType::OnlyInTerminal),
+        "A Roblox Open Cloud API key with place publishing access. If this, universe_id and \
+         place_id are set, the verification place is published automatically when it changes.",
+        |x|    Ok(Some(x.to_owned())),
+        |_, x| Ok(x.map_or("(not set)", |_| "<secret redacted>").to_owned()));
+    require_issued_tokens<bool>(
+        RequireIssuedTokens, false, |_| Ok(GuildShowType::OnlyInTerminal),
+        "Whether tokens are only accepted if the verification place showed them to the account \
+         they are for. The place records tokens in a data store, so this requires \
+         open_cloud_api_key to have data store read access.",
+        parse_bool, print_display);
+
+    verify_with_token<bool>(
+        VerificationMethodToken, true, |_| Ok(GuildShowType::AlwaysShow),
+        "Whether users can verify with the code shown in the verification place.",
+        parse_bool, print_display);
+    verify_with_profile<bool>(
+        VerificationMethodProfile, true, |_| Ok(GuildShowType::AlwaysShow),
+        "Whether users can verify by adding a phrase to their Roblox profile.",
+        parse_bool, print_display);
+    verify_with_oauth<bool>(
+        VerificationMethodOAuth, true, |_| Ok(GuildShowType::AlwaysShow),
+        "Whether users can verify by signing in to Discord and Roblox on the bot's website.",
+        parse_bool, print_display);
+    verify_with_roblox_oauth<bool>(
+        VerificationMethodRobloxOAuth, true, |_| Ok(GuildShowType::AlwaysShow),
+        "Whether users can verify with a code from signing in to their Roblox account.",
+        parse_bool, print_display);
+    verify_with_friend_request<bool>(
+        VerificationMethodFriend, true, |_| Ok(GuildShowType::AlwaysShow),
+        "Whether users can verify by sending a friend request to the bot's Roblox account.",
+        parse_bool, print_display);
+    friend_verification_cookie<Option<String>>(
+        FriendVerificationCookie, false, |_| Ok(GuildShowType::OnlyInTerminal),
+        "The .ROBLOSECURITY cookie of the Roblox account users send friend requests to when \
+         verifying with the verify_friend command.",
+        |x|    Ok(Some(x.trim_left_matches(".ROBLOSECURITY=").to_owned())),
+        |_, x| Ok(x.map_or("(not set)", |_| "<secret redacted>").to_owned()));
+    verify_with_place_code<bool>(
+        VerificationMethodPlaceCode, true, |_| Ok(GuildShowType::AlwaysShow),
+        "Whether users can verify by entering a code from the bot in the verification place.",
+        parse_bool, print_display);
+    place_push_secret<Option<String>>(
+        PlacePushSecret, false, |_| Ok(GuildShowType::OnlyInTerminal),
+        "The secret the verification place uses to send entered codes to the bot's web server.",
+        |x|    Ok(Some(x.to_owned())),
+        |_, x| Ok(x.map_or("(not set)", |_| "<secret redacted>").to_owned()));
+
+    minimum_account_age<u32>(
+        MinimumRobloxAccountAgeDays, true, |_| Ok(GuildShowType::AlwaysShow),
+        "The minimum age in days a Roblox account must have before it can be verified.",
+        parse_u32, print_display);
+    required_group<Option<u64>>(
+        RequiredGroupID, true, |_| Ok(GuildShowType::AlwaysShow),
+        "A Roblox group that accounts must be a member of before they can be verified.",
+        |x| parse_u64(x).map(Some),
+        |_, x| Ok(x.map_or_else(|| "*(none set)*".to_owned(), |x| format!("{}", x))));
+    required_group_rank<Option<u32>>(
+        RequiredGroupMinimumRank, true, |_| Ok(GuildShowType::OnlyInGuild),
+        "The minimum rank (1-255) accounts must have in required_group before they can be \
+         verified. If not set, any member of the group can verify.",
+        |x| {
+            let rank = parse_u32(x)?;
+            cmd_ensure!(rank > 0 && rank < 256, "The rank must be between 1 and 255.");
+            Ok(Some(rank))
+        },
+        |_, x| Ok(x.map_or_else(|| "*(none set)*".to_owned(), |x| format!("{}", x))));
+
+    blacklist_message<String>(
+        BlacklistMessage, true, |_| Ok(GuildShowType::OnlyInGuild),
+        "The message shown when a blacklisted Roblox account tries to verify.",
+        |x| Ok(x.to_owned()), print_quoted);
+    sync_bans<bool>(
+        SyncBans, true, |_| Ok(GuildShowType::OnlyInGuild),
+        "Whether the Roblox accounts of users banned from this server are stopped from being \
+         verified here again by other Discord accounts. They are allowed again on unban.",
+        parse_bool, print_display);
+    audit_channel<Option<ChannelId>>(
+        AuditChannel, true, |_| Ok(GuildShowType::AlwaysShow),
+        "The channel verifications, unverifications and failed attempts are logged to.",
+        |x| parse_channel(x).map(Some),
+        |_, x| Ok(x.map_or_else(|| "*(none set)*".to_owned(), |x| format!("<#{}>", x.0))));
+
+    require_approval<bool>(
+        RequireApproval, true, |_| Ok(GuildShowType::OnlyInGuild),
+        "Whether verified users must be approved by a moderator before they are given roles. \
+         Users that are already verified when this is turned on are approved automatically.",
+        parse_bool, print_display);
+    approval_channel<Option<ChannelId>>(
+        ApprovalChannel, true, |_| Ok(GuildShowType::OnlyInGuild),
+        "The channel approval requests are posted to, with buttons to approve or reject them.",
+        |x| parse_channel(x).map(Some),
+        |_, x| Ok(x.map_or_else(|| "*(none set)*".to_owned(), |x| format!("<#{}>", x.0))));
+
+    webhook_url<Option<String>>(
+        WebhookUrl, true, |_| Ok(GuildShowType::AlwaysShow),
+        "An HTTPS URL that is sent a JSON payload when a user verifies, reverifies or unverifies \
+         on this server.",
+        |x| {
+            check_webhook_url(x)?;
+            Ok(Some(x.to_owned()))
+        },
+        |_, x| Ok(x.unwrap_or_else(|| "*(none set)*".to_owned())));
+    webhook_secret<Option<String>>(
+        WebhookSecret, true, |_| Ok(GuildShowType::AlwaysShow),
+        "The secret used to sign webhook payloads with HMAC-SHA256 in the X-Sylph-Signature \
+         header.",
+        |x|    Ok(Some(x.to_owned())),
+        |_, x| Ok(x.map_or("(not set)", |_| "<secret redacted>").to_owned()));
+    protected_names<Option<String>>(
+        ProtectedRobloxNames, true, |_| Ok(GuildShowType::OnlyInGuild),
+        "A comma separated list of Roblox usernames, such as group owners and admins. A warning \
+         is posted in the audit channel when a user verifies as a similarly named account.",
+        |x| Ok(Some(x.split(',').map(|x| x.trim()).filter(|x| !x.is_empty())
+                         .collect::<Vec<_>>().join(", "))),
+        |_, x| Ok(x.unwrap_or_else(|| "*(none set)*".to_owned())));
+    protected_name_distance<u32>(
+        ProtectedNameMaxDistance, true, |_| Ok(GuildShowType::OnlyInGuild),
+        "How many characters a username can differ by from a protected name to be warned about.",
+        parse_u32, print_display);
+    denied_names<Option<String>>(
+        NamePolicyPatterns, true, |_| Ok(GuildShowType::OnlyInGuild),
+        "A comma separated list of words users may not have in their Roblox username or \
+         display name, ignoring case. `*` matches any characters, e.g. `bad*word`.",
+        |x| {
+            check_name_patterns(x)?;
+            Ok(Some(x.split(',').map(|x| x.trim()).filter(|x| !x.is_empty())
+                     .collect::<Vec<_>>().join(", ")))
+        },
+        |_, x| Ok(x.unwrap_or_else(|| "*(none set)*".to_owned())));
+    denied_names_need_approval<bool>(
+        NamePolicyRequireApproval, true, |_| Ok(GuildShowType::OnlyInGuild),
+        "Whether users with a name matching denied_names need to be approved by moderators \
+         instead of being unable to verify.",
+        parse_bool, print_display);
+    audit_renames<bool>(
+        AuditRenames, true, |_| Ok(GuildShowType::AlwaysShow),
+        "Whether Roblox username changes of verified members are posted in the audit channel.",
+        parse_bool, print_display);
+    starter_rank_group<Option<u64>>(
+        StarterRankGroupID, true, |_| Ok(GuildShowType::OnlyInGuild),
+        "The Roblox group newly verified members are promoted in. Members already at or above \
+         starter_rank are not changed.",
+        |x| parse_u64(x).map(Some),
+        |_, x| Ok(x.map_or_else(|| "*(none set)*".to_owned(), |x| format!("{}", x))));
+    starter_rank<Option<u32>>(
+        StarterRank, true, |_| Ok(GuildShowType::OnlyInGuild),
+        "The rank (1-254) newly verified members are given in starter_rank_group. Failures are \
+         posted in the audit channel.",
+        |x| {
+            let rank = parse_u32(x)?;
+            cmd_ensure!(rank > 0 && rank < 255, "The starter rank must be between 1 and 254.");
+            Ok(Some(rank))
+        },
+        |_, x| Ok(x.map_or_else(|| "*(none set)*".to_owned(), |x| format!("{}", x))));
+    join_request_group<Option<u64>>(
+        JoinRequestGroupID, true, |_| Ok(GuildShowType::OnlyInGuild),
+        "The Roblox group whose join requests are answered automatically, using the group admin \
+         credentials.",
+        |x| parse_u64(x).map(Some),
+        |_, x| Ok(x.map_or_else(|| "*(none set)*".to_owned(), |x| format!("{}", x))));
+    join_request_auto_accept<bool>(
+        JoinRequestAutoAccept, true, |_| Ok(GuildShowType::OnlyInGuild),
+        "Whether requests to join join_request_group from members of this server who have \
+         verified are accepted.",
+        parse_bool, print_display);
+    join_request_auto_decline<bool>(
+        JoinRequestAutoDecline, true, |_| Ok(GuildShowType::OnlyInGuild),
+        "Whether requests to join join_request_group from users who have not verified in this \
+         server are declined.",
+        parse_bool, print_display);
+    group_admin_api_key<Option<String>>(
+        GroupAdminApiKey, false, |_| Ok(GuildShowType::OnlyInTerminal),
+        "A Roblox Open Cloud API key with the group:write scope, used to give verified members \
+         their starter rank and to answer group join requests.",
+        |x|    Ok(Some(x.to_owned())),
+        |_, x| Ok(x.map_or("(not set)", |_| "<secret redacted>").to_owned()));
+    group_admin_cookie<Option<String>>(
+        GroupAdminCookie, false, |_| Ok(GuildShowType::OnlyInTerminal),
+        "The .ROBLOSECURITY cookie of a Roblox account that can manage ranks and join requests \
+         in the configured groups. Only used if group_admin_api_key is not set.",
+        |x|    Ok(Some(x.trim_left_matches(".ROBLOSECURITY=").to_owned())),
+        |_, x| Ok(x.map_or("(not set)", |_| "<secret redacted>").to_owned()));
+    username_refresh_interval<u32>(
+        UsernameRefreshIntervalHours, false, |_| Ok(GuildShowType::OnlyInTerminal),
+        "How many hours to wait between checking verified users for Roblox username changes. 0 \
+         disables checking.",
+        parse_u32, print_display);
+    bloxlink_api_key<Option<String>>(
+        BloxlinkApiKey, true, |_| Ok(GuildShowType::OnlyInGuild),
+        "The Bloxlink server API key used to import links from Bloxlink.",
+        |x|    Ok(Some(x.to_owned())),
+        |_, x| Ok(x.map_or("(not set)", |_| "<key redacted>").to_owned()));
 
     verification_attempt_limit<u32>(
-        VerificationAttemptLimit, false, |_| Ok(GuildShowType::OnlyInTerminal),
-        "How many times a user can verify in a row before they must wait a period of time.",
+        VerificationAttemptLimit, true, |_| Ok(GuildShowType::AlwaysShow),
+        "How many times a user can verify in a row before they must wait a period of time. \
+         Servers can only set a lower limit than the global one.",
         parse_u32, print_display);
     verification_cooldown<u64>(
-        VerificationCooldownSeconds, false, |_| Ok(GuildShowType::OnlyInTerminal),
-        "How many seconds a user must wait to attempt to verify after using up the attempt limit.",
+        VerificationCooldownSeconds, true, |_| Ok(GuildShowType::AlwaysShow),
+        "How many seconds a user must wait to attempt to verify after using up the attempt limit. \
+         Servers can only set a longer cooldown than the global one.",
         parse_u64, |_, x| Ok(util::to_english_time_precise(x)));
+    verification_history_days<u32>(
+        VerificationHistoryDays, false, |_| Ok(GuildShowType::OnlyInTerminal),
+        "How many days verification attempts are kept for the admin history and alts commands. \
+         0 keeps them forever.",
+        parse_u32, print_display);
+    unverify_restore_days<u32>(
+        UnverifyRestoreDays, false, |_| Ok(GuildShowType::OnlyInTerminal),
+        "How many days after a user is unverified their links can be restored with \
+         `admin restore`. 0 disables restoring links.",
+        parse_u32, print_display);
 
     verification_channel_intro<Option<String>>(
         VerificationChannelIntro, true, |_| Ok(GuildShowType::OnlyInGuild),
@@ -246,32 +822,187 @@ config_values! {
         "A sentence that you can add to the bottom of your server's verification message.",
         |x| Ok(Some(x.to_owned())),
         |_, x| Ok(x.map_or_else(|| "*(none set)*".to_owned(), |x| format!("\"{}\"", x))));
+    delete_token_messages<bool>(
+        DeleteTokenMessages, true, |_| Ok(GuildShowType::OnlyInGuild),
+        "Whether verification commands containing a verification code are deleted, so the code \
+         is not visible to others.",
+        parse_bool, print_display);
+
+    embed_colour<Option<u32>>(
+        EmbedColour, true, |_| Ok(GuildShowType::OnlyInGuild),
+        "The colour of the bot's embeds, such as `#43B581`. Warnings in the audit log keep their \
+         own colour.",
+        |x| parse_colour(x).map(Some),
+        |_, x| Ok(x.map_or_else(|| "*(default)*".to_owned(), |x| format!("#{:06X}", x))),
+        export = |x| x.map(|x| format!("#{:06X}", x)));
+    embed_footer<Option<String>>(
+        EmbedFooter, true, |_| Ok(GuildShowType::OnlyInGuild),
+        "Text shown at the bottom of the bot's embeds.",
+        |x| Ok(Some(x.to_owned())),
+        |_, x| Ok(x.map_or_else(|| "*(none set)*".to_owned(), |x| format!("\"{}\"", x))));
+    embed_thumbnails<bool>(
+        EmbedThumbnails, true, |_| Ok(GuildShowType::OnlyInGuild),
+        "Whether Roblox avatars are shown in the bot's embeds.",
+        parse_bool, print_display);
+    verify_success_image<Option<String>>(
+        VerifySuccessImageUrl, true, |_| Ok(GuildShowType::OnlyInGuild),
+        "The URL of an image or GIF shown when a user verifies successfully.",
+        |x| {
+            cmd_ensure!(x.starts_with("https://"), "The image URL must start with https://.");
+            Ok(Some(x.to_owned()))
+        },
+        |_, x| Ok(x.map_or_else(|| "*(none set)*".to_owned(), |x| format!("<{}>", x))));
 
     token_validity<u32>(
         TokenValiditySeconds, false, |_| Ok(GuildShowType::OnlyInTerminal),
         "How many seconds a verification token is valid for.",
         parse_u32, |_, x| Ok(util::to_english_time_precise(x as u64)));
+    token_format_version<u32>(
+        TokenFormatVersion, false, |_| Ok(GuildShowType::OnlyInTerminal),
+        "The format of verification tokens. Version 2 tokens include a checksum so typos can be \
+         detected. Changing this changes the shared key, and tokens in the old format are \
+         accepted for another week.",
+        |x| {
+            let version = parse_u32(x)?;
+            cmd_ensure!(version == 1 || version == 2, "Token format version must be 1 or 2.");
+            Ok(version)
+        },
+        print_display);
+    token_length<u32>(
+        TokenLength, false, |_| Ok(GuildShowType::OnlyInTerminal),
+        "How many characters verification tokens have, not counting the checksum character of \
+         version 2 tokens. Longer tokens are harder to guess. Changing this changes the shared \
+         key, and tokens in the old format are accepted for another week.",
+        |x| {
+            let length = parse_u32(x)?;
+            cmd_ensure!(length >= 6 && length <= 12, "Token length must be between 6 and 12.");
+            Ok(length)
+        },
+        print_display);
+    token_alphabet<Option<String>>(
+        TokenAlphabet, false, |_| Ok(GuildShowType::OnlyInTerminal),
+        "The characters verification tokens are made from. By default, version 1 tokens use \
+         letters, and version 2 tokens use letters and numbers except I, O, 0 and 1. Changing \
+         this changes the shared key, and tokens in the old format are accepted for another \
+         week.",
+        |x| {
+            let alphabet = x.trim().to_uppercase();
+            cmd_ensure!(alphabet.chars().all(|c| (c >= 'A' && c <= 'Z') || (c >= '0' && c <= '9')),
+                        "The token alphabet may only contain letters and numbers.");
+            let mut chars: Vec<char> = alphabet.chars().collect();
+            chars.sort();
+            chars.dedup();
+            cmd_ensure!(chars.len() == alphabet.len(),
+                        "The token alphabet may not contain the same character twice.");
+            cmd_ensure!(chars.len() >= 16, "The token alphabet must have at least 16 characters.");
+            Ok(Some(alphabet))
+        },
+        |_, x| Ok(x.map_or_else(|| "*(default)*".to_owned(), |x| format!("\"{}\"", x))));
+    auto_rekey_interval<u32>(
+        AutoRekeyIntervalDays, false, |_| Ok(GuildShowType::OnlyInTerminal),
+        "How many days the shared key is used before it is automatically changed. The place file \
+         must be reuploaded each time. 0 disables automatic rekeying.",
+        parse_u32, print_display);
 
     allow_reverify_discord_account<bool>(
-        AllowReverifyDiscord, false, |_| Ok(GuildShowType::OnlyInTerminal),
-        "Whether a user can reverify a Discord account that is already verified.",
+        AllowReverifyDiscord, true, |_| Ok(GuildShowType::AlwaysShow),
+        "Whether a user can reverify a Discord account that is already verified. Servers cannot \
+         turn it on when it is off globally.",
         parse_bool, print_display);
     allow_reverify_roblox_account<bool>(
-        AllowReverifyRoblox, false, |_| Ok(GuildShowType::OnlyInTerminal),
-        "Whether a user can reverify a Roblox account that is already verified.",
+        AllowReverifyRoblox, true, |_| Ok(GuildShowType::AlwaysShow),
+        "Whether a user can reverify a Roblox account that is already verified. Servers cannot \
+         turn it on when it is off globally.",
         parse_bool, print_display);
     reverification_cooldown<u64>(
-        ReverificationCooldownSeconds, false, |_| Ok(GuildShowType::OnlyInTerminal),
-        "How many seconds a user must wait after verifying before they can reverify.",
+        ReverificationCooldownSeconds, true, |_| Ok(GuildShowType::AlwaysShow),
+        "How many seconds a user must wait after verifying before they can reverify. Servers can \
+         only set a longer cooldown than the global one.",
         parse_u64, |_, x| Ok(util::to_english_time_precise(x)));
+    max_linked_accounts<u32>(
+        MaxLinkedRobloxAccounts, false, |_| Ok(GuildShowType::AlwaysShow),
+        "How many Roblox accounts a user can link to their Discord account at once. Links are \
+         shared between servers, so this can only be set globally.",
+        parse_u32, print_display);
+}
+
+fn set_message(ctx: &CommandContext, guild_id: GuildId) -> Result<()> {
+    let templates = ctx.core.templates();
+    let key = match ctx.arg_opt(1) {
+        Some(key) => MessageKey::from_name(key)?,
+        None => {
+            let mut buffer = String::new();
+            writeln!(buffer, "Custom messages: (use `{}set message <message> [template]`)",
+                     ctx.prefix())?;
+            for &key in MessageKey::ALL {
+                writeln!(buffer, "• {} - {} (placeholders: {})", key.name(),
+                         templates.get(guild_id, key)?
+                             .map_or_else(|| "*(default)*".to_owned(), |x| format!("\"{}\"", x)),
+                         key.placeholders().iter().map(|x| format!("`{{{}}}`", x))
+                             .collect::<Vec<_>>().join(", "))?;
+            }
+            return ctx.respond(buffer)
+        }
+    };
+    let template = ctx.rest(2)?;
+    if template.is_empty() {
+        templates.set(guild_id, key, None)?;
+        ctx.respond("Message reset to default.")?;
+    } else {
+        templates.set(guild_id, key, Some(template))?;
+        ctx.respond("Message set.")?;
+    }
+    if key == MessageKey::Instructions {
+        ctx.core.verify_channel().update(Some(guild_id))?;
+    }
+    Ok(())
 }
 
 fn set(ctx: &CommandContext, guild: Option<GuildId>) -> Result<()> {
-    if ctx.argc() == 0 {
+    if let Some(guild_id) = guild {
+        if ctx.arg_opt(0) == Some("message") {
+            return set_message(ctx, guild_id)
+        }
+    }
+    if guild.is_some() && ctx.arg_opt(0) == Some("global") {
+        cmd_ensure!(ctx.privilege_level >= PrivilegeLevel::BotOwner,
+                    "Only the bot owner can change the global configuration.");
+        return set_args(ctx, None, 1)
+    }
+    set_args(ctx, guild, 0)
+}
+// Settings that give out roles are checked the same way as binding a role to a rule, so that
+// users cannot have the bot give out roles above their own. Channels must be in the server, so
+// its logs and approval requests are not sent elsewhere.
+fn check_guild_setting(
+    ctx: &CommandContext, guild_id: GuildId, key: &str, value: &str,
+) -> Result<()> {
+    match key {
+        "verified_role" | "unverified_role" =>
+            check_role_access(guild_id, ctx.author()?.id, parse_role(value)?,
+                              ctx.privilege_level >= PrivilegeLevel::BotOwner),
+        "audit_channel" | "approval_channel" => {
+            let channel_guild = match parse_channel(value)?.find() {
+                Some(Channel::Guild(ref channel)) => Some(channel.read().guild_id),
+                _ => None,
+            };
+            cmd_ensure!(channel_guild == Some(guild_id), "That channel is not in this server.");
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+fn set_args(ctx: &CommandContext, guild: Option<GuildId>, start: usize) -> Result<()> {
+    if ctx.argc() == start {
         ctx.respond(print_config(&ctx.core, guild)?)
     } else {
-        let key = ctx.arg(0)?;
-        let value = ctx.rest(1)?;
+        let key = ctx.arg(start)?;
+        let value = ctx.rest(start + 1)?;
+        if let Some(guild_id) = guild {
+            if !value.trim().is_empty() {
+                check_guild_setting(ctx, guild_id, key, value)?;
+            }
+        }
         if value.trim().is_empty() {
             set_config(&ctx.core, guild, key, None)?;
             ctx.respond("Configuration option reset to default.")?;
@@ -285,10 +1016,18 @@ fn set(ctx: &CommandContext, guild: Option<GuildId>) -> Result<()> {
 
 pub const COMMANDS: &[Command] = &[
     Command::new("set")
-        .help(Some("<key> [new value]"), "Sets a configuration value for this guild.")
+        .help(Some("[global] <key> [new value]"),
+              "Sets a configuration value for this guild, or globally for the bot owner. Use \
+               `set message <message> [template]` to customize messages shown while verifying.")
         .required_permissions(enum_set!(DiscordPermission::ManageGuild))
+        .permission("config.set")
         .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
-        .exec(|ctx| set(ctx, Some(ctx.get_guild()?.unwrap()))),
+        .exec(|ctx| set(ctx, Some(ctx.require_guild()?))),
+    Command::new("get")
+        .help(Some("<key>"), "Shows a configuration value and where it is set.")
+        .required_permissions(enum_set!(DiscordPermission::ManageGuild))
+        .permission("config.get")
+        .exec(|ctx| ctx.respond(print_config_value(&ctx.core, ctx.get_guild()?, ctx.arg(0)?)?)),
     Command::new("set_global")
         .help(Some("<key> [new value]"), "Sets a global configuration value.")
         .terminal_only()
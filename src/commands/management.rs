@@ -1,9 +1,632 @@
 use super::*;
 
+use log::LevelFilter;
+use logger;
+use roblox;
+use std::cmp::min;
+use std::fs::File;
+use std::io::{Read, Write as IoWrite};
 use std::process::exit;
 use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
+use super::config::guild_settings;
+use super::paginate::paginate;
+use super::verifier::{check_role_access, find_role, parse_discord_user, update_all_members};
+use util;
+use util::get_discord_username;
+
+const PLACE_EXPORT_NAME: &str = "Sylph-Verifier.rbxlx";
+const CONFIG_EXPORT_NAME: &str = "sylphie-config.toml";
+
+fn place_export(ctx: &CommandContext) -> Result<()> {
+    let place = ctx.core.export_place()?;
+    if let Some((_, msg)) = ctx.discord_context() {
+        msg.channel_id.send_files(vec![(place.as_slice(), PLACE_EXPORT_NAME)], |m| m.content(
+            "Here is the verification place file. It contains the shared key, so do not share it \
+             with anyone."
+        ))?;
+        Ok(())
+    } else {
+        let path = ctx.arg_opt(1).unwrap_or(PLACE_EXPORT_NAME);
+        File::create(path)?.write_all(&place)?;
+        ctx.respond(format!("The verification place file has been written to '{}'.", path))
+    }
+}
+
+fn admin_config(ctx: &CommandContext) -> Result<()> {
+    let guild_id = ctx.get_guild()?.to_cmd_err(|| "This can only be used in a server.")?;
+    let settings = guild_settings();
+    match ctx.arg(1)? {
+        "export" => {
+            let data = export_config(&ctx.core, guild_id, &settings)?;
+            let (_, msg) = ctx.discord_context()?;
+            msg.channel_id.send_files(vec![(data.as_bytes(), CONFIG_EXPORT_NAME)], |m| m.content(
+                "Here is this server's configuration. Use `admin config import` with it attached \
+                 to copy it to another server."
+            ))?;
+            Ok(())
+        }
+        "import" => {
+            let (_, msg) = ctx.discord_context()?;
+            let attachment = msg.attachments.first()
+                .to_cmd_err(|| "Please attach the configuration file to your message.")?;
+            let data = String::from_utf8(attachment.download()?)
+                .to_cmd_err(|| "The configuration file is not valid UTF-8.")?;
+            let author = ctx.author()?.id;
+            let is_bot_owner = ctx.privilege_level >= PrivilegeLevel::BotOwner;
+            let summary = import_config(&ctx.core, guild_id, &settings, &data, &|role_id| {
+                check_role_access(guild_id, author, role_id, is_bot_owner)
+            })?;
+            let mut message = format!("Imported {} settings, {} role bindings and {} message \
+                                       templates.", summary.settings, summary.roles,
+                                      summary.templates);
+            if !summary.missing_roles.is_empty() {
+                message.push_str(&format!(" The roles of {} do not exist on this server, and \
+                                           must be set with `set_role`.",
+                                          summary.missing_roles.join(", ")));
+            }
+            ctx.respond(message)
+        }
+        _ => cmd_error!("Unknown subcommand. Usage: {}admin config <export|import>",
+                        ctx.prefix()),
+    }
+}
+
+fn import_summary(source: ImportSource, imported: usize, skipped: usize) -> String {
+    format!("Imported {} links from {}. {} links were skipped, as the Discord or Roblox account \
+             was already linked, or the Roblox account is not allowed to verify.",
+            imported, source.name(), skipped)
+}
+fn admin_import(ctx: &CommandContext) -> Result<()> {
+    let source = ImportSource::from_name(ctx.arg(1)?)?;
+    if ctx.arg_opt(2) == Some("--api") {
+        // This makes one request per member, so it runs in the background and reports back to
+        // the channel it was started from, or the log on the terminal.
+        let guild_id = GuildId(ctx.parse_arg(3, "server ID")?);
+        let channel_id = ctx.discord_context().map(|(_, msg)| msg.channel_id);
+        ctx.respond(format!("Looking up server members using the {} API in the background. \
+                             This may take a while.", source.name()))?;
+        ctx.core.import_guild_links(source, guild_id, move |result| {
+            let message = match result {
+                Ok((imported, skipped)) => import_summary(source, imported, skipped),
+                Err(e) => format!("Could not import links from {}: {}", source.name(), e),
+            };
+            match channel_id {
+                Some(channel_id) => { channel_id.say(&message)?; }
+                None => info!("{}", message),
+            }
+            Ok(())
+        });
+        return Ok(())
+    }
+
+    let data = match ctx.discord_context() {
+        Some((_, msg)) if ctx.arg_opt(2).is_none() => {
+            let attachment = msg.attachments.first()
+                .to_cmd_err(|| "Please attach the export file to your message.")?;
+            String::from_utf8(attachment.download()?)
+                .to_cmd_err(|| "The export file is not valid UTF-8.")?
+        }
+        _ => {
+            let mut data = String::new();
+            File::open(ctx.arg(2)?)?.read_to_string(&mut data)?;
+            data
+        }
+    };
+    let links = source.parse_export(&data)?;
+    let (imported, skipped) = ctx.core.verifier().import_links(None, &links)?;
+    ctx.respond(import_summary(source, imported, skipped))
+}
+
+fn admin_cooldown(ctx: &CommandContext) -> Result<()> {
+    let user_id = parse_discord_user(ctx.arg(2)?)?
+        .to_cmd_err(|| "Please mention the user or give their user ID.")?;
+    let username = get_discord_username(user_id);
+    // Server moderators can only see and reset the attempts of their own members.
+    let guild_id = lookup_scope(ctx, user_id)?;
+    match ctx.arg(1)? {
+        "show" => match ctx.core.verifier().get_cooldown(guild_id, user_id)? {
+            Some(info) => {
+                let now = SystemTime::now();
+                ctx.respond(format!(
+                    "{} has made {} of {} verification attempts. Their last attempt was {} \
+                     ago.{}", username, info.attempt_count, info.max_attempts,
+                    util::english_time_diff(info.last_attempt, now),
+                    info.cooldown_ends.map_or(String::new(), |x| format!(
+                        " They are on cooldown for another {}.", util::english_time_diff(now, x),
+                    )),
+                ))
+            }
+            None => ctx.respond(format!("{} has no recent verification attempts.", username)),
+        },
+        "reset" => {
+            if ctx.core.verifier().reset_cooldown(user_id)? {
+                ctx.respond(format!("{}'s verification attempts have been reset.", username))
+            } else {
+                ctx.respond(format!("{} has no recent verification attempts.", username))
+            }
+        }
+        _ => cmd_error!("Unknown subcommand. Usage: {}admin cooldown <show|reset> <user>",
+                        ctx.prefix()),
+    }
+}
+
+fn admin_restore(ctx: &CommandContext) -> Result<()> {
+    let user_id = parse_discord_user(ctx.arg(1)?)?
+        .to_cmd_err(|| "Please mention the user or give their user ID.")?;
+    // Restoring recreates a global link, so server admins can only restore their own.
+    if ctx.data.author().map(|x| x.id) != Some(user_id) {
+        require_bot_owner(ctx)?;
+    }
+    let roblox_id = ctx.core.verifier().restore_unverified(ctx.get_guild()?, user_id)?;
+    info!("{} was reverified as {} by restoring their previous links",
+          get_discord_username(user_id), roblox_id.0);
+    ctx.core.roles().assign_roles_on_switch(user_id, roblox_id);
+    ctx.respond(format!("{} is verified as {} again.", get_discord_username(user_id),
+                        roblox_id.lookup_username()?))
+}
+
+fn admin_unverify(ctx: &CommandContext) -> Result<()> {
+    let user_id = parse_discord_user(ctx.arg(1)?)?
+        .to_cmd_err(|| "Please mention the user or give their user ID.")?;
+    let username = get_discord_username(user_id);
+    // Server admins can only remove links made in their own server. As the link is shared by
+    // every server, the roles it gave are removed everywhere.
+    let roblox_id = match lookup_scope(ctx, user_id)? {
+        Some(guild_id) => {
+            let roblox_id = ctx.core.verifier().unverify_guild_link(guild_id, user_id)?;
+            let roblox_id = match roblox_id {
+                Some(roblox_id) => roblox_id,
+                None if ctx.core.verifier().get_verified_roblox_user(user_id)?.is_some() =>
+                    cmd_error!("{} verified on another server, so they can only be unverified \
+                                there or by the bot owner.", username),
+                None => cmd_error!("{} is not verified with this bot.", username),
+            };
+            ctx.core.roles().remove_roles_on_unverify(user_id);
+            info!("{} was unverified from {} in {} by {}",
+                  username, roblox_id.0, guild_id, get_discord_username(ctx.author()?.id));
+            roblox_id
+        }
+        None => {
+            let roblox_id = ctx.core.verifier().unverify(None, user_id)?
+                .to_cmd_err(|| format!("{} is not verified with this bot.", username))?;
+            ctx.core.roles().remove_roles_on_unverify(user_id);
+            info!("{} was unverified from {} by the bot owner", username, roblox_id.0);
+            roblox_id
+        }
+    };
+    ctx.respond(format!("{} is no longer verified as {}.", username,
+                        roblox_id.lookup_username()?))
+}
+
+fn admin_guest(ctx: &CommandContext) -> Result<()> {
+    let guild_id = ctx.get_guild()?.to_cmd_err(|| "This can only be used in a server.")?;
+    let user_id = parse_discord_user(ctx.arg(1)?)?
+        .to_cmd_err(|| "Please mention the user or give their user ID.")?;
+    let username = get_discord_username(user_id);
+    if ctx.arg(2)? == "revoke" {
+        let pass = ctx.core.guest_passes().revoke(guild_id, user_id)?
+            .to_cmd_err(|| format!("{} does not have a guest pass.", username))?;
+        ctx.core.roles().end_guest_pass(guild_id, user_id, pass.role_id)?;
+        return ctx.respond(format!("{}'s guest pass has been revoked.", username))
+    }
+
+    let duration = util::parse_duration(ctx.arg(2)?)?;
+    cmd_ensure!(ctx.core.verifier().get_verified_roblox_user(user_id)?.is_none(),
+                "{} is already verified.", username);
+    let role_id = match ctx.rest_opt(3) {
+        Some(role) => find_role(guild_id, role)?,
+        None => ctx.core.config().get(Some(guild_id), ConfigKeys::VerifiedRole)?
+            .to_cmd_err(|| "No role was given, and verified_role is not set.")?,
+    };
+    let author = ctx.author()?.id;
+    check_role_access(guild_id, author, role_id, ctx.privilege_level >= PrivilegeLevel::BotOwner)?;
+    let pass = ctx.core.guest_passes().grant(guild_id, user_id, role_id, duration, author)?;
+    ctx.core.roles().assign_roles(guild_id, user_id, None)?;
+    info!("{} was given a guest pass for role {} in {} by {}, lasting {}",
+          username, pass.role_id, guild_id, get_discord_username(author),
+          util::to_english_time(duration.as_secs()));
+    ctx.respond(format!("{} has been given <@&{}> for {}. It will be removed automatically \
+                         unless they verify.", username, pass.role_id.0,
+                        util::to_english_time(duration.as_secs())))
+}
+
+fn describe_admin(user_id: UserId) -> String {
+    format!("{} ({})", get_discord_username(user_id), user_id.0)
+}
+
+// Runs a destructive admin action, and returns a description of what was done.
+fn run_admin_action(
+    ctx: &CommandContext, action: &AdminAction, initiated_by: &str,
+) -> Result<String> {
+    match *action {
+        AdminAction::EmergencyRekey { ref reason } => {
+            let summary =
+                ctx.core.emergency_rekey(initiated_by, reason.as_ref().map(|x| x.as_str()))?;
+            Ok(format!("{}\nAn incident summary has been posted to every server's audit \
+                        channel.", summary))
+        }
+        AdminAction::PurgeUser { discord_id } => {
+            let receipt = ctx.core.purge_user(discord_id)?.to_cmd_err(|| {
+                "That user is verifying at the moment. Please try again once they finish."
+            })?;
+            let mut text = format!("All data stored about user ID {} has been removed.\n\
+                                    Receipt: `{}`\nRemoved at: {}\n",
+                                   discord_id.0, receipt.receipt_id,
+                                   util::time_to_i64(receipt.purged_at));
+            for &(table, rows) in &receipt.removed {
+                if rows != 0 {
+                    writeln!(text, "• {}: {} rows", table, rows)?;
+                }
+            }
+            Ok(text)
+        }
+        AdminAction::ResetVerification { guild_id, strip_roles } => {
+            let count = ctx.core.reset_guild_verification(guild_id, strip_roles, initiated_by)?;
+            let roles =
+                if strip_roles { " Their verification roles are being removed." } else { "" };
+            Ok(format!("Verification has been reset. {} users must verify again.{}",
+                       count, roles))
+        }
+    }
+}
+
+// Runs a destructive admin action right away, or holds it until another admin confirms it if
+// `action_approvers` is set.
+fn request_admin_action(ctx: &CommandContext, action: AdminAction) -> Result<()> {
+    let requested_by = match ctx.data.author() {
+        Some(user) => Some(user.id),
+        None => ctx.core.config().get(None, ConfigKeys::BotOwnerId)?.map(UserId),
+    };
+    if !ctx.core.admin_actions().requires_confirmation()? {
+        let initiated_by = match ctx.data.author() {
+            Some(user) => describe_admin(user.id),
+            None => "the terminal".to_owned(),
+        };
+        return ctx.respond(run_admin_action(ctx, &action, &initiated_by)?)
+    }
+
+    let requested_by = requested_by.to_cmd_err(|| {
+        "`bot_owner_id` must be set to request admin actions from the terminal."
+    })?;
+    let pending = ctx.core.admin_actions().request(action, requested_by)?;
+    let timeout = ctx.core.config().get(None, ConfigKeys::ActionApprovalTimeoutMinutes)?;
+    ctx.respond(format!("Admin action #{} ({}) must be confirmed by another admin within {} \
+                         minutes before it is run. They can confirm it with the buttons in the \
+                         confirmation channel, or with `{}confirm_action {}`.",
+                        pending.id, pending.action.describe(), timeout, ctx.prefix(), pending.id))
+}
+
+fn admin_purge_user(ctx: &CommandContext) -> Result<()> {
+    let discord_id = parse_discord_user(ctx.arg(1)?)?
+        .to_cmd_err(|| "Please mention the user or give their user ID.")?;
+    request_admin_action(ctx, AdminAction::PurgeUser { discord_id })
+}
+
+// Links are shared by every server, so unverifying a server's users also unverifies them
+// everywhere else. Only the bot owner can do that, for a server that asks for it.
+fn admin_reset_verification(ctx: &CommandContext) -> Result<()> {
+    require_bot_owner(ctx)?;
+    let guild_id = GuildId(ctx.parse_arg(1, "server ID")?);
+    let mut confirmed = false;
+    let mut strip_roles = false;
+    for i in 2..ctx.argc() {
+        match ctx.arg(i)? {
+            "confirm" => confirmed = true,
+            "--strip-roles" => strip_roles = true,
+            arg => cmd_error!("Unknown option '{}'. Usage: {}admin resetverification \
+                               <server id> [confirm] [--strip-roles]", arg, ctx.prefix()),
+        }
+    }
+    if !confirmed {
+        let count = ctx.core.verifier().count_guild_links(guild_id)?;
+        return ctx.respond(format!(
+            "This will unverify all {} users who verified on that server, in every server, so \
+             they must verify again. It can be undone for each user with `{0}admin restore`. \
+             To continue, use `{0}admin resetverification {} confirm{}`.",
+            count, ctx.prefix(), guild_id.0, if strip_roles { " --strip-roles" } else { "" },
+        ))
+    }
+    request_admin_action(ctx, AdminAction::ResetVerification { guild_id, strip_roles })
+}
+
+fn admin_emergency_rekey(ctx: &CommandContext) -> Result<()> {
+    let reason = match ctx.rest_opt(1) {
+        Some("") | None => None,
+        Some(reason) => Some(reason.to_owned()),
+    };
+    request_admin_action(ctx, AdminAction::EmergencyRekey { reason })
+}
+
+const MAX_EVENT_LOG_PROBLEMS: usize = 20;
+
+fn admin_verify_log(ctx: &CommandContext) -> Result<()> {
+    let status = ctx.core.event_log().verify()?;
+    if status.problems.is_empty() {
+        return ctx.respond(format!("All {} event log entries are intact.", status.entries))
+    }
+    let mut text = format!("**The event log has been tampered with.** {} problems were found in \
+                            {} entries:\n", status.problems.len(), status.entries);
+    for problem in status.problems.iter().take(MAX_EVENT_LOG_PROBLEMS) {
+        match *problem {
+            EventLogProblem::Missing { after, before } =>
+                writeln!(text, "• Entries between #{} and #{} were removed.", after, before)?,
+            EventLogProblem::InvalidSignature { id } =>
+                writeln!(text, "• Entry #{}, or the entry before it, was changed.", id)?,
+            EventLogProblem::Truncated { after, expected } =>
+                writeln!(text, "• Entries after #{} were removed. The log should reach #{}.",
+                         after, expected)?,
+            EventLogProblem::Replaced { id } =>
+                writeln!(text, "• Entries up to #{} were removed and replaced.", id)?,
+        }
+    }
+    if status.problems.len() > MAX_EVENT_LOG_PROBLEMS {
+        writeln!(text, "...and {} more.", status.problems.len() - MAX_EVENT_LOG_PROBLEMS)?;
+    }
+    ctx.respond(text)
+}
+
+fn admin_cache(ctx: &CommandContext) -> Result<()> {
+    match ctx.arg_opt(1) {
+        Some("stats") | None => {
+            let mut stats = String::new();
+            for (name, metrics) in roblox::api_cache_metrics() {
+                writeln!(stats, "• {}: {} hits, {} stale hits, {} misses, {} background \
+                                 refreshes, {} entries",
+                         name, metrics.hits, metrics.stale_hits, metrics.misses,
+                         metrics.refreshes, metrics.entries)?;
+            }
+            ctx.respond(stats.trim())
+        }
+        Some("flush") => {
+            let count = roblox::flush_api_caches();
+            ctx.respond(format!("Removed {} cached Roblox API results.", count))
+        }
+        _ => cmd_error!("Unknown subcommand. Usage: {}admin cache <stats|flush>",
+                        ctx.prefix()),
+    }
+}
+
+const MAX_ALT_REPORT_ENTRIES: usize = 20;
+
+// Server admins only see attempts made in their own server, and only for its members. The bot
+// owner sees everything from the terminal or direct messages.
+fn lookup_scope(ctx: &CommandContext, user_id: UserId) -> Result<Option<GuildId>> {
+    match ctx.get_guild()? {
+        Some(guild_id) => {
+            cmd_ensure!(guild_id.member(user_id).is_ok(), "{} is not a member of this server.",
+                        get_discord_username(user_id));
+            Ok(Some(guild_id))
+        }
+        None => {
+            require_bot_owner(ctx)?;
+            Ok(None)
+        }
+    }
+}
+
+fn admin_alts(ctx: &CommandContext) -> Result<()> {
+    let user_id = parse_discord_user(ctx.arg(1)?)?
+        .to_cmd_err(|| "Please mention the user or give their user ID.")?;
+    let guild_id = lookup_scope(ctx, user_id)?;
+    let username = get_discord_username(user_id);
+    let mut matches = ctx.core.verifier().find_alt_accounts(guild_id, user_id)?;
+    if let Some(guild_id) = guild_id {
+        // Accounts that are only linked have no attempts here, so show them only if present.
+        matches.retain(|x| x.last_attempt.is_some() || guild_id.member(x.discord_id).is_ok());
+    }
+    if matches.is_empty() {
+        return ctx.respond(format!("No other Discord accounts have tried to verify as {}'s \
+                                    Roblox accounts.", username))
+    }
+
+    let now = SystemTime::now();
+    let mut report = format!("Other Discord accounts that have tried to verify as {}'s Roblox \
+                              accounts:\n", username);
+    for alt in matches.iter().take(MAX_ALT_REPORT_ENTRIES) {
+        let roblox_name = match alt.roblox_id.lookup_username_opt() {
+            Ok(Some(name)) => name,
+            _ => format!("(roblox uid #{})", alt.roblox_id.0),
+        };
+        write!(report, "• {} (<@{}>) as {}: ", get_discord_username(alt.discord_id),
+               alt.discord_id.0, roblox_name)?;
+        if alt.is_linked {
+            write!(report, "currently verified as it")?;
+        } else if alt.proved_control {
+            write!(report, "proved control of it")?;
+        } else {
+            write!(report, "did not prove control of it")?;
+        }
+        if let Some(last_attempt) = alt.last_attempt {
+            write!(report, ", {} attempts, last {} ago", alt.attempts,
+                   util::english_time_diff(last_attempt, now))?;
+        }
+        report.push('\n');
+    }
+    if matches.len() > MAX_ALT_REPORT_ENTRIES {
+        write!(report, "...and {} more.", matches.len() - MAX_ALT_REPORT_ENTRIES)?;
+    }
+    ctx.respond(report.trim())
+}
+
+const HISTORY_PAGE_SIZE: u32 = 10;
+// Each page is looked up separately, so only this many are shown at once.
+const HISTORY_MAX_PAGES: u32 = 10;
+
+fn admin_shards(ctx: &CommandContext) -> Result<()> {
+    let statuses = ctx.core.discord().shard_statuses();
+    cmd_ensure!(!statuses.is_empty(), "The bot is not connected to Discord.");
+    let current_shard = ctx.get_guild()?.map(|x| ctx.core.discord().guild_shard(x));
+    let mut list = String::new();
+    for shard in statuses {
+        let latency = match shard.latency {
+            Some(latency) => format!("{} ms", latency.as_secs() * 1000 +
+                                              latency.subsec_nanos() as u64 / 1000000),
+            None => "unknown".to_owned(),
+        };
+        writeln!(list, "• Shard {}{}: {}, {} servers, {} latency",
+                 shard.id, if current_shard == Some(shard.id) { " *(this server)*" } else { "" },
+                 shard.stage, shard.guilds, latency)?;
+    }
+    if let Some(lease) = ctx.core.leader_lease() {
+        writeln!(list, "\nThis instance, {}, is the leader.", lease.instance_id())?;
+    }
+    ctx.respond(list)
+}
+
+fn admin_keystatus(ctx: &CommandContext) -> Result<()> {
+    let status = ctx.core.verifier().key_status();
+    let previous = if status.previous_keys.is_empty() {
+        "none".to_owned()
+    } else {
+        status.previous_keys.iter().map(|x| format!("#{}", x)).collect::<Vec<_>>().join(", ")
+    };
+    ctx.respond(format!(
+        "• Current key: #{}, created {} ago\n\
+         • Tokens: version {}, {} characters, valid for {}\n\
+         • Earlier keys still recognized: {}",
+        status.key_id, util::english_time_diff(status.created, SystemTime::now()),
+        status.version, status.token_length, util::to_english_time(status.time_increment as u64),
+        previous,
+    ))
+}
+
+fn as_millis_f64(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1000.0 + duration.subsec_nanos() as f64 / 1_000_000.0
+}
+
+fn admin_history(ctx: &CommandContext) -> Result<()> {
+    let user_id = parse_discord_user(ctx.arg(1)?)?
+        .to_cmd_err(|| "Please mention the user or give their user ID.")?;
+    let first_page = ctx.parse_arg_opt::<u32>(2, "page number")?.unwrap_or(1);
+    cmd_ensure!(first_page > 0, "The page must be at least 1.");
+    let guild_id = lookup_scope(ctx, user_id)?;
+    let username = get_discord_username(user_id);
+    let (attempts, total) = ctx.core.verifier().get_attempt_history(
+        guild_id, user_id, first_page - 1, HISTORY_PAGE_SIZE,
+    )?;
+    if total == 0 {
+        return ctx.respond(format!("{} has no recorded verification attempts.", username))
+    }
+    let pages = (total + HISTORY_PAGE_SIZE - 1) / HISTORY_PAGE_SIZE;
+    cmd_ensure!(!attempts.is_empty(), "{} only has {} pages of verification attempts.",
+                username, pages);
+
+    let last_page = min(pages, first_page + HISTORY_MAX_PAGES - 1);
+    let mut embeds = Vec::new();
+    let mut attempts = attempts;
+    for page in first_page..last_page + 1 {
+        if page != first_page {
+            attempts = ctx.core.verifier().get_attempt_history(guild_id, user_id, page - 1,
+                                                               HISTORY_PAGE_SIZE)?.0;
+        }
+        let mut description = history_page(&attempts)?;
+        if page == last_page && page < pages {
+            write!(description, "\nUse `{}admin history {} {}` for the next page.",
+                   ctx.prefix(), user_id.0, page + 1)?;
+        }
+        embeds.push(ResponseEmbed {
+            title: format!("Verification attempts by {} (page {} of {}, {} total)",
+                           username, page, pages, total),
+            description, fields: Vec::new(),
+        });
+    }
+    paginate(ctx, embeds, 0)
+}
+fn history_page(attempts: &[VerificationAttempt]) -> Result<String> {
+    let now = SystemTime::now();
+    let mut history = String::new();
+    for attempt in attempts {
+        let roblox_name = match attempt.roblox_id.lookup_username_opt() {
+            Ok(Some(name)) => name,
+            _ => format!("(roblox uid #{})", attempt.roblox_id.0),
+        };
+        write!(history, "• {} ago: as {}", util::english_time_diff(attempt.attempted_at, now),
+               roblox_name)?;
+        if let Some(guild_id) = attempt.guild_id {
+            let server = guild_id.find()
+                .map_or_else(|| guild_id.to_string(), |x| x.read().name.clone());
+            write!(history, " in {}", server)?;
+        }
+        if let Some(ref method) = attempt.method {
+            write!(history, " by {}", method)?;
+        }
+        write!(history, ": `{}`", attempt.result.as_ref().map_or("unknown", |x| x.as_str()))?;
+        if let Some(ref token_status) = attempt.token_status {
+            write!(history, " (token {})", token_status)?;
+        }
+        history.push('\n');
+    }
+    Ok(history)
+}
+
+const USAGE_DEFAULT_DAYS: u32 = 7;
+const USAGE_MAX_DAYS: u32 = 90;
+const USAGE_PAGE_SIZE: usize = 15;
+
+fn admin_usage(ctx: &CommandContext) -> Result<()> {
+    let days = ctx.parse_arg_opt::<u32>(1, "number of days")?.unwrap_or(USAGE_DEFAULT_DAYS);
+    cmd_ensure!(days >= 1 && days <= USAGE_MAX_DAYS,
+                "The number of days must be between 1 and {}.", USAGE_MAX_DAYS);
+    let usage = ctx.core.stats().command_usage(days)?;
+    if usage.is_empty() {
+        return ctx.respond(format!("No commands have been used in the last {} days.", days))
+    }
+    let total_uses: u64 = usage.iter().map(|x| x.uses).sum();
+
+    let mut categories: HashMap<&str, u64> = HashMap::new();
+    for command in &usage {
+        for &(ref outcome, count) in &command.errors {
+            *categories.entry(outcome.as_str()).or_insert(0) += count;
+        }
+    }
+    let mut categories: Vec<(&str, u64)> = categories.into_iter().collect();
+    categories.sort_by(|a, b| b.1.cmp(&a.1));
+    let categories = if categories.is_empty() {
+        "None".to_owned()
+    } else {
+        categories.iter().map(|x| format!("{} {}", x.1, x.0)).collect::<Vec<_>>().join(", ")
+    };
+    let failures = ctx.core.stats().daily_command_failures(days)?.iter()
+        .map(|x| x.to_string()).collect::<Vec<_>>().join(", ");
+
+    let mut pages = Vec::new();
+    for (i, page) in usage.chunks(USAGE_PAGE_SIZE).enumerate() {
+        let mut description = String::new();
+        for command in page {
+            write!(description, "• `{}`: {} uses, {} ms on average, {} ms at most",
+                   command.command, command.uses, command.total_millis / command.uses,
+                   command.max_millis)?;
+            if !command.errors.is_empty() {
+                let errors = command.errors.iter().map(|x| format!("{} {}", x.1, x.0))
+                    .collect::<Vec<_>>().join(", ");
+                write!(description, " (errors: {})", errors)?;
+            }
+            description.push('\n');
+        }
+        let mut fields = Vec::new();
+        if i == 0 {
+            fields.push(("Errors by category".to_owned(), categories.clone(), false));
+            fields.push(("Unexpected errors per day, oldest first".to_owned(), failures.clone(),
+                         false));
+        }
+        pages.push(ResponseEmbed {
+            title: format!("Command usage in the last {} days ({} uses)", days, total_uses),
+            description, fields,
+        });
+    }
+    paginate(ctx, pages, 0)
+}
+
+// Backups, imports, shutdowns and anything changing global verification state affect every
+// server, so they are restricted further than the rest of the admin command.
+fn require_bot_owner(ctx: &CommandContext) -> Result<()> {
+    cmd_ensure!(ctx.privilege_level >= PrivilegeLevel::BotOwner &&
+                ctx.command_target != CommandTarget::ServerMessage,
+                "Only the bot owner can use that command, in the terminal or direct messages.");
+    Ok(())
+}
 
 pub const COMMANDS: &[Command] = &[
     Command::new("shutdown")
@@ -27,6 +650,312 @@ pub const COMMANDS: &[Command] = &[
             ctx.core.refresh_place()?;
             Ok(())
         }),
+    Command::new("place")
+        .help(Some("<export [path]|publish>"),
+              "Exports the verification place file with the current shared key and settings, or \
+               publishes it with Roblox Open Cloud. In direct messages, exported files are sent \
+               as an attachment.")
+        .required_privilege(PrivilegeLevel::BotOwner)
+        .allowed_contexts(enum_set!(CommandTarget::Terminal | CommandTarget::PrivateMessage))
+        .exec(|ctx| {
+            match ctx.arg(0)? {
+                "export" => place_export(ctx),
+                "publish" => {
+                    let version = ctx.core.publish_place()?;
+                    ctx.respond(format!("Published version {} of the verification place.",
+                                        version))
+                }
+                _ => cmd_error!("Unknown subcommand. Usage: {}place <export [path]|publish>",
+                                ctx.prefix()),
+            }
+        }),
+
+    Command::new("admin")
+        .help(Some("<cooldown <show|reset> <user>|history <user> [page]|alts <user>|\
+                    restore <user>|unverify <user>|guest <user> <duration|revoke> [role]|updateall|\
+                    purge-user <user>|\
+                    emergency-rekey [reason]|\
+                    resetverification <server id> [confirm] [--strip-roles]|\
+                    verify-log|cache <stats|flush>|keystatus|shards|backup|\
+                    import <rover|bloxlink> <file|--api server id>|config <export|import>|\
+                    usage [days]|shutdown>"),
+              "Administrative commands. `cooldown` shows a member's verification attempts, or \
+               resets them so they can try again. `history` pages through every verification \
+               attempt a member has made in this server. `alts` lists other Discord accounts \
+               that have tried to verify as the same Roblox accounts as a member here. \
+               `restore` undoes you being unverified, if it was recent enough, and the bot owner \
+               can restore any user. \
+               `unverify` unlinks a member who verified on this server and removes the roles \
+               it gave them in every server. `guest` gives an unverified user the verified \
+               role, or the given role, for a limited time such as `12h` or `3d`, and \
+               `guest <user> revoke` takes it away early. \
+               `updateall` updates the roles and nicknames of every verified member of this \
+               server in the background. `purge-user` irreversibly removes everything stored \
+               about a user, for data deletion requests. `emergency-rekey` replaces the \
+               verification key if it may have leaked, rejecting codes made with any earlier \
+               key and cancelling verifications in progress. `resetverification` unverifies \
+               everyone who verified on a server, and with `--strip-roles` also removes their \
+               verification roles there. If `action_approvers` is set, `purge-user`, \
+               `emergency-rekey` and `resetverification` only run once another admin confirms \
+               them. `verify-log` checks that no entries in the signed log of verifications, key \
+               changes and configuration changes have been changed or removed. `cache stats` \
+               shows how often cached Roblox API results were used, and `cache flush` forgets \
+               them. `keystatus` shows the current verification key, its token format and age. \
+               `shards` shows the latency and server \
+               count of each shard the bot is connected with. `backup` writes a snapshot of the \
+               database to the backup directory. Use the `restore` command line option while the \
+               bot is stopped to restore a backup. `import` links accounts verified with RoVer or \
+               Bloxlink, from a JSON export or their public APIs. In direct messages, the export \
+               can be attached instead. `config export` sends this server's settings, role \
+               bindings and message templates as a TOML file, and `config import` applies an \
+               attached one, so a configuration can be copied to other servers or kept as a \
+               backup. Settings containing credentials are left out. `usage` shows how often \
+               each command was used over the last week, or the given number of days, how long \
+               they took and how they failed. \
+               `shutdown` stops the bot after waiting for running verifications to finish. \
+               `purge-user`, `emergency-rekey`, `resetverification`, \
+               `verify-log`, `cache`, `backup`, `import`, `usage` and `shutdown` can only be \
+               used by the bot owner. `resetverification` and `config` cannot be granted with \
+               `perm`, and need the Manage Server permission.")
+        .required_permissions(enum_set!(DiscordPermission::ManageGuild))
+        .subcommand_permissions(&[
+            ("cooldown", "management.admin.cooldown"), ("history", "management.admin.history"),
+            ("alts", "management.admin.alts"), ("restore", "management.admin.restore"),
+            ("unverify", "management.admin.unverify"), ("guest", "management.admin.guest"),
+            ("updateall", "management.admin.updateall"),
+            ("keystatus", "management.admin.keystatus"), ("shards", "management.admin.shards"),
+        ])
+        .exec(|ctx| {
+            match ctx.arg(0)? {
+                "cooldown" => admin_cooldown(ctx),
+                "history" => admin_history(ctx),
+                "alts" => admin_alts(ctx),
+                "restore" => admin_restore(ctx),
+                "unverify" => admin_unverify(ctx),
+                "guest" => admin_guest(ctx),
+                "updateall" => {
+                    let guild_id = ctx.get_guild()?
+                        .to_cmd_err(|| "This can only be used in a server.")?;
+                    let (_, msg) = ctx.discord_context()?;
+                    update_all_members(ctx, msg, guild_id)
+                }
+                "purge-user" => {
+                    require_bot_owner(ctx)?;
+                    admin_purge_user(ctx)
+                }
+                "emergency-rekey" => {
+                    require_bot_owner(ctx)?;
+                    admin_emergency_rekey(ctx)
+                }
+                "resetverification" => admin_reset_verification(ctx),
+                "verify-log" => {
+                    require_bot_owner(ctx)?;
+                    admin_verify_log(ctx)
+                }
+                "cache" => {
+                    require_bot_owner(ctx)?;
+                    admin_cache(ctx)
+                }
+                "shards" => admin_shards(ctx),
+                "keystatus" => admin_keystatus(ctx),
+                "backup" => {
+                    require_bot_owner(ctx)?;
+                    let path = ctx.core.backup_database()?;
+                    ctx.respond(format!("The database has been backed up to '{}'.",
+                                        path.display()))
+                }
+                "import" => {
+                    require_bot_owner(ctx)?;
+                    admin_import(ctx)
+                }
+                "config" => admin_config(ctx),
+                "usage" => {
+                    require_bot_owner(ctx)?;
+                    admin_usage(ctx)
+                }
+                "shutdown" => {
+                    require_bot_owner(ctx)?;
+                    ctx.respond("Shutting down. Running commands will be given some time to \
+                                 finish first.")?;
+                    ctx.core.shutdown()
+                }
+                _ => cmd_error!("Unknown subcommand. Usage: {0}admin cooldown <show|reset> \
+                                 <user>, {0}admin history <user> [page], \
+                                 {0}admin alts <user>, {0}admin restore <user>, \
+                                 {0}admin unverify <user>, \
+                                 {0}admin updateall, {0}admin purge-user <user>, \
+                                 {0}admin emergency-rekey [reason], \
+                                 {0}admin resetverification <server id> [confirm] \
+                                 [--strip-roles], \
+                                 {0}admin verify-log, {0}admin cache <stats|flush>, \
+                                 {0}admin keystatus, {0}admin shards, {0}admin backup, \
+                                 {0}admin import <rover|bloxlink> <file|--api server id>, \
+                                 {0}admin config <export|import>, {0}admin usage [days], \
+                                 {0}admin shutdown",
+                                ctx.prefix()),
+            }
+        }),
+
+    Command::new("confirm_action")
+        .help(Some("<action id>"),
+              "Confirms and runs an admin action that is waiting for a second admin. Only the \
+               users in `action_approvers` can confirm actions, and not ones they requested \
+               themselves.")
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage | CommandTarget::PrivateMessage))
+        .exec(|ctx| {
+            let reviewer = ctx.author()?.id;
+            let pending = ctx.core.admin_actions().confirm(ctx.parse_arg(0, "action ID")?,
+                                                           reviewer)?;
+            let initiated_by = format!("{}, confirmed by {}",
+                                       describe_admin(pending.requested_by),
+                                       describe_admin(reviewer));
+            let result = run_admin_action(ctx, &pending.action, &initiated_by)?;
+            ctx.respond(result)
+        }),
+    Command::new("cancel_action")
+        .help(Some("<action id>"),
+              "Cancels an admin action that is waiting for a second admin.")
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage | CommandTarget::PrivateMessage))
+        .exec(|ctx| {
+            let pending = ctx.core.admin_actions().cancel(ctx.parse_arg(0, "action ID")?,
+                                                          ctx.author()?.id)?;
+            ctx.respond(format!("Admin action #{} has been cancelled.", pending.id))
+        }),
+
+    Command::new("api_stats")
+        .help(None, "Shows request, retry and failure counts for each Roblox API, and how \
+                     often verified accounts were found in the lookup cache.")
+        .required_privilege(PrivilegeLevel::BotOwner)
+        .allowed_contexts(enum_set!(CommandTarget::Terminal | CommandTarget::PrivateMessage))
+        .exec(|ctx| {
+            let mut stats = String::new();
+            for (endpoint, metrics) in roblox::api_metrics() {
+                writeln!(stats, "• {}: {} requests, {} retries, {} rate limited, {} failed",
+                         endpoint, metrics.requests, metrics.retries, metrics.rate_limited,
+                         metrics.failures)?;
+            }
+            if stats.is_empty() {
+                writeln!(stats, "No Roblox API requests have been made yet.")?;
+            }
+            let cache = ctx.core.verifier().cache_metrics();
+            write!(stats, "Verified account cache: {} hits, {} misses, {}/{} entries",
+                   cache.hits, cache.misses, cache.entries, cache.capacity)?;
+            ctx.respond(stats.trim())
+        }),
+
+    Command::new("db_stats")
+        .help(None, "Shows how many database connections are open, and how long commands have \
+                     waited for one.")
+        .required_privilege(PrivilegeLevel::BotOwner)
+        .allowed_contexts(enum_set!(CommandTarget::Terminal | CommandTarget::PrivateMessage))
+        .exec(|ctx| {
+            let metrics = ctx.core.database_metrics();
+            let average_ms = if metrics.checkouts == 0 {
+                0.0
+            } else {
+                as_millis_f64(metrics.total_wait) / metrics.checkouts as f64
+            };
+            ctx.respond(format!(
+                "• Connections: {} open, {} idle, {} maximum, {} opened in total\n\
+                 • Read-only connections: {} open, {} idle, {} maximum\n\
+                 • Checkouts: {} total, {} slower than usual\n\
+                 • Wait time: {:.2} ms average, {:.2} ms longest",
+                metrics.connections, metrics.idle_connections, metrics.max_connections,
+                metrics.opened, metrics.read_connections, metrics.read_idle_connections,
+                metrics.max_read_connections, metrics.checkouts, metrics.slow_checkouts,
+                average_ms, as_millis_f64(metrics.max_wait),
+            ))
+        }),
+
+    Command::new("jobs")
+        .help(Some("[run <job>|schedule <job> <schedule|default>]"),
+              "Lists the background jobs run by the bot, runs a job immediately, or changes \
+               when a job runs. Schedules use the cron format `minute hour day month weekday`, \
+               in UTC.")
+        .required_privilege(PrivilegeLevel::BotOwner)
+        .allowed_contexts(enum_set!(CommandTarget::Terminal | CommandTarget::PrivateMessage))
+        .exec(|ctx| {
+            if ctx.argc() == 0 {
+                let now = SystemTime::now();
+                let mut jobs = String::new();
+                for job in ctx.core.jobs().jobs() {
+                    write!(jobs, "• {} (`{}`{}): {} ", job.name, job.schedule,
+                           if job.is_default_schedule { "" } else { ", customized" },
+                           job.description)?;
+                    if job.running {
+                        write!(jobs, "Currently running.")?;
+                    } else {
+                        write!(jobs, "Next run in {}.",
+                               util::english_time_diff(now, job.next_run))?;
+                    }
+                    if let Some(ref error) = job.last_error {
+                        write!(jobs, " Failed {} times in a row, last error: {}",
+                               job.failure_count, error)?;
+                    }
+                    jobs.push('\n');
+                }
+                return ctx.respond(jobs.trim())
+            }
+            match ctx.arg(0)? {
+                "run" => {
+                    let name = ctx.arg(1)?;
+                    ctx.core.jobs().trigger(name)?;
+                    ctx.respond(format!("The job '{}' has been started.", name))
+                }
+                "schedule" => {
+                    let name = ctx.arg(1)?;
+                    match ctx.rest(2)? {
+                        "default" => {
+                            ctx.core.jobs().set_schedule(name, None)?;
+                            ctx.respond(format!("The job '{}' now uses its default schedule.",
+                                                name))
+                        }
+                        schedule => {
+                            ctx.core.jobs().set_schedule(name, Some(schedule))?;
+                            ctx.respond(format!("The schedule of '{}' has been set to `{}`.",
+                                                name, schedule))
+                        }
+                    }
+                }
+                _ => cmd_error!("Unknown subcommand. Usage: {0}jobs, {0}jobs run <job>, \
+                                 {0}jobs schedule <job> <schedule|default>", ctx.prefix()),
+            }
+        }),
+
+    Command::new("log_level")
+        .help(Some("[module] [level|reset]"),
+              "Changes the log level of a module, e.g. `core::verifier` or `roblox`, until the \
+               bot is restarted. Without arguments, shows the levels that have been changed.")
+        .required_privilege(PrivilegeLevel::BotOwner)
+        .allowed_contexts(enum_set!(CommandTarget::Terminal | CommandTarget::PrivateMessage))
+        .exec(|ctx| {
+            if ctx.argc() == 0 {
+                let mut levels = String::new();
+                for (module, level) in logger::level_overrides() {
+                    writeln!(levels, "• {}: {}", module, level)?;
+                }
+                return if levels.is_empty() {
+                    ctx.respond("No log levels have been changed.")
+                } else {
+                    ctx.respond(levels.trim())
+                }
+            }
+            let module = ctx.arg(0)?;
+            match ctx.arg(1)? {
+                "reset" => {
+                    logger::set_level_override(module, None);
+                    ctx.respond(format!("The log level of '{}' has been reset.", module))
+                }
+                level => {
+                    let level = level.parse::<LevelFilter>().to_cmd_err(||
+                        "Unknown log level. Use off, error, warn, info, debug or trace."
+                    )?;
+                    logger::set_level_override(module, Some(level));
+                    ctx.respond(format!("The log level of '{}' has been set to {}.",
+                                        module, level))
+                }
+            }
+        }),
 
     // Discord management
     Command::new("connect")
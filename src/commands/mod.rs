@@ -8,6 +8,8 @@ use serenity::prelude::*;
 use std::collections::HashMap;
 use std::fmt::Write;
 use std::str::FromStr;
+use std::time::Instant;
+use util;
 
 // TODO: Unify parsing of common types better.
 
@@ -99,15 +101,17 @@ discord_permissions! {
 pub struct Command {
     name: &'static str, help_args: Option<&'static str>, help_desc: Option<&'static str>,
     required_privilege: PrivilegeLevel, allowed_contexts: EnumSet<CommandTarget>,
-    discord_permissions: EnumSet<DiscordPermission>, pub no_threading: bool,
-    hidden: bool, command_fn: Option<CommandFn>,
+    discord_permissions: EnumSet<DiscordPermission>, permission_node: Option<&'static str>,
+    subcommand_nodes: &'static [(&'static str, &'static str)], cooldown_secs: u64,
+    pub no_threading: bool, hidden: bool, command_fn: Option<CommandFn>,
 }
 impl Command {
     pub(self) const fn new(name: &'static str) -> Command {
         Command {
             name, help_args: None, help_desc: None,
             required_privilege: PrivilegeLevel::NormalUser,
-            discord_permissions: EnumSet::new(),
+            discord_permissions: EnumSet::new(), permission_node: None, subcommand_nodes: &[],
+            cooldown_secs: 0,
             allowed_contexts: enum_set!(CommandTarget::Terminal |
                                         CommandTarget::ServerMessage |
                                         CommandTarget::PrivateMessage),
@@ -142,6 +146,24 @@ impl Command {
     ) -> Command {
         Command { discord_permissions, ..self }
     }
+    // The node that can be granted with the `perm` command to let users without the required
+    // Discord permissions use this command.
+    pub(self) const fn permission(self, node: &'static str) -> Command {
+        Command { permission_node: Some(node), ..self }
+    }
+    // Gives subcommands, chosen by the first argument, their own permission nodes. Subcommands
+    // that are not listed cannot be granted, so only users with the Discord permissions can use
+    // them.
+    pub(self) const fn subcommand_permissions(
+        self, nodes: &'static [(&'static str, &'static str)],
+    ) -> Command {
+        Command { subcommand_nodes: nodes, ..self }
+    }
+    // How long each user must wait between uses of this command in a server, on top of the
+    // rate limit shared by all commands.
+    pub(self) const fn cooldown(self, secs: u64) -> Command {
+        Command { cooldown_secs: secs, ..self }
+    }
     pub(self) const fn no_threading(self) -> Command {
         Command { no_threading: true, ..self }
     }
@@ -156,13 +178,32 @@ impl Command {
     }
 
     pub fn run(&self, ctx: &CommandContextData, core: &VerifierCore) {
-        let args = Args::new(ctx.message_content());
+        let args = match ctx.arg_positions() {
+            Some(positions) => Args { str: ctx.message_content(), matches: positions.to_vec() },
+            None => Args::new(ctx.message_content()),
+        };
 
         let ctx = CommandContext::new(core, ctx, args, self);
-        ctx.catch_error(|| {
-            cmd_ensure!(ctx.privilege_level >= self.required_privilege &&
-                        ctx.has_discord_permissions(self.discord_permissions),
+        let mut context = vec![("command", self.name.to_owned())];
+        if let Ok(Some(guild_id)) = ctx.get_guild() {
+            context.push(("guild", guild_id.to_string()));
+            context.push(("shard", core.discord().guild_shard(guild_id).to_string()));
+        }
+        let started = Instant::now();
+        let result = error_report::with_context(context, || ctx.catch_error(|| {
+            match ratelimit::check(&ctx)? {
+                RateLimitStatus::Allowed => { }
+                RateLimitStatus::Limited { retry_after, first: true } =>
+                    cmd_error!("You are using commands too quickly. Please try again in {}.",
+                               util::to_english_time(retry_after.as_secs())),
+                RateLimitStatus::Limited { first: false, .. } => return Ok(()),
+            }
+            cmd_ensure!(ctx.has_command_permissions(self, ctx.arg_opt(0))?,
                         "You do not have the necessary permissions to use that command.");
+            if let Some(retry_after) = ratelimit::check_cooldown(&ctx, self)? {
+                cmd_error!("Please wait {} before using `{}{}` again.",
+                           util::to_english_time(retry_after.as_secs()), ctx.prefix(), self.name)
+            }
             if !self.allowed_contexts.contains(ctx.command_target) {
                 match ctx.command_target {
                     CommandTarget::Terminal =>
@@ -174,19 +215,28 @@ impl Command {
                 };
             }
             self.command_fn.as_ref().unwrap().call(&ctx)
-        }).ok();
+        }));
+        let outcome = CommandOutcome::from_result(&result);
+        if let Err(e) = core.stats().record_command(self.name, outcome, started.elapsed()) {
+            warn!("Could not record the use of command '{}': {}", self.name, e);
+        }
     }
 }
 
 struct CommandList {
     sorted_commands: Vec<&'static Command>,
+    categories: Vec<(&'static str, Vec<&'static Command>)>,
     commands: HashMap<&'static str, &'static Command>,
 }
 impl CommandList {
-    fn new(lists: &[&'static [Command]]) -> CommandList {
+    fn new(lists: &[(&'static str, &'static [Command])]) -> CommandList {
         let mut commands = HashMap::new();
         let mut sorted_command_names = Vec::new();
-        for &list in lists {
+        let mut categories = Vec::new();
+        for &(category, list) in lists {
+            let mut category_commands: Vec<&'static Command> = list.iter().collect();
+            category_commands.sort_by_key(|x| x.name);
+            categories.push((category, category_commands));
             for command in list {
                 if commands.contains_key(&command.name) {
                     panic!("Duplicate command '{}'", command.name)
@@ -201,12 +251,15 @@ impl CommandList {
         sorted_command_names.sort();
         let sorted_commands =
             sorted_command_names.into_iter().map(|x| commands[&x]).collect();
-        CommandList { commands, sorted_commands }
+        CommandList { commands, sorted_commands, categories }
     }
 
     fn command_list(&self) -> &[&'static Command] {
         &self.sorted_commands
     }
+    fn categories(&self) -> &[(&'static str, Vec<&'static Command>)] {
+        &self.categories
+    }
     fn get(&self, command: &str) -> Option<&'static Command> {
         self.commands.get(&command).cloned()
     }
@@ -229,6 +282,21 @@ impl <'a> Args<'a> {
     }
 }
 
+// A response shown as an embed in contexts that can show one, and as plain text elsewhere.
+#[derive(Clone, Default, Debug)]
+pub struct ResponseEmbed {
+    pub title: String, pub description: String, pub fields: Vec<(String, String, bool)>,
+}
+impl ResponseEmbed {
+    pub fn to_text(&self) -> String {
+        let mut text = format!("**{}**\n{}", self.title, self.description);
+        for &(ref name, ref value, _) in &self.fields {
+            text.push_str(&format!("\n**{}:** {}", name, value));
+        }
+        text
+    }
+}
+
 struct CommandContext<'a> {
     pub core: &'a VerifierCore,
     pub privilege_level: PrivilegeLevel,
@@ -252,9 +320,28 @@ impl <'a> CommandContext<'a> {
     pub fn respond<S: AsRef<str>>(&self, message: S) -> Result<()> {
         self.data.respond(message.as_ref().trim())
     }
+    pub fn respond_with_thumbnail<S: AsRef<str>>(
+        &self, message: S, thumbnail_url: &str,
+    ) -> Result<()> {
+        self.data.respond_with_thumbnail(message.as_ref().trim(), thumbnail_url, None)
+    }
+    // Responds to a successful verification, with the server's success image if it has one.
+    pub fn respond_verified<S: AsRef<str>>(
+        &self, message: S, thumbnail_url: &str,
+    ) -> Result<()> {
+        let image = self.core.config().get(self.get_guild()?, ConfigKeys::VerifySuccessImageUrl)?;
+        self.data.respond_with_thumbnail(message.as_ref().trim(), thumbnail_url,
+                                         image.as_ref().map(|x| x.as_str()))
+    }
+    pub fn respond_embed(&self, embed: &ResponseEmbed) -> Result<()> {
+        self.data.respond_embed(embed)
+    }
     pub fn discord_context(&self) -> Option<(&Context, &Message)> {
         self.data.discord_context()
     }
+    pub fn author(&self) -> Result<&User> {
+        self.data.author().to_cmd_err(|| "This command can only be used on Discord.")
+    }
 
     fn catch_error<F, T>(&self, f: F) -> Result<T> where F: FnOnce() -> Result<T> {
         match error_report::catch_error(|| match f() {
@@ -306,9 +393,13 @@ impl <'a> CommandContext<'a> {
                     Channel::Guild(ch) => Ok(Some(ch.read().guild_id)),
                     Channel::Group(_) | Channel::Private(_) | Channel::Category(_) => Ok(None),
                 },
-            None => Ok(None),
+            None => Ok(self.data.guild_member().map(|x| x.0)),
         }
     }
+    // Returns the server the command was used in, for commands that can only be used in one.
+    pub fn require_guild(&self) -> Result<GuildId> {
+        self.get_guild()?.to_cmd_err(|| "This command can only be used in a server.")
+    }
     pub fn user_guild_permissions(&self) -> Result<Permissions> {
         match self.data.discord_context() {
             Some((_, message)) =>
@@ -318,7 +409,10 @@ impl <'a> CommandContext<'a> {
                     Channel::Group(_) | Channel::Private(_) | Channel::Category(_) =>
                         Ok(Permissions::empty()),
                 },
-            None => bail!("This command can only be used on Discord."),
+            None => match self.data.guild_member() {
+                Some((_, permissions)) => Ok(permissions),
+                None => bail!("This command can only be used on Discord."),
+            },
         }
     }
     pub fn has_discord_permissions(&self, perms: EnumSet<DiscordPermission>) -> bool {
@@ -331,11 +425,36 @@ impl <'a> CommandContext<'a> {
                 x.contains(perms) || x.contains(Permissions::ADMINISTRATOR))
     }
 
-    fn not_enough_arguments(&self) -> String {
-        format!("Not enough arguments for command. Usage: {}{}{}",
-                self.prefix(), self.command.name,
+    // Checks the privilege level and Discord permissions a command requires. Users without the
+    // Discord permissions can still use it if they were granted its permission node, or the node
+    // of the subcommand being used. If `subcommand` is `None`, any subcommand's node will do.
+    pub fn has_command_permissions(
+        &self, command: &Command, subcommand: Option<&str>,
+    ) -> Result<bool> {
+        if self.privilege_level < command.required_privilege {
+            return Ok(false)
+        }
+        if self.has_discord_permissions(command.discord_permissions) {
+            return Ok(true)
+        }
+        for &(name, node) in command.subcommand_nodes {
+            if subcommand.map_or(true, |x| x == name) && permissions::has_grant(self, node)? {
+                return Ok(true)
+            }
+        }
+        match command.permission_node {
+            Some(node) => permissions::has_grant(self, node),
+            None => Ok(false),
+        }
+    }
+
+    pub fn usage(&self) -> String {
+        format!("{}{}{}", self.prefix(), self.command.name,
                 self.command.help_args.map_or("".to_owned(), |x| format!(" {}", x)))
     }
+    fn not_enough_arguments(&self) -> String {
+        format!("Not enough arguments for command. Usage: {}", self.usage())
+    }
 
     pub fn argc(&self) -> usize {
         self.args.matches.len()
@@ -351,6 +470,18 @@ impl <'a> CommandContext<'a> {
     pub fn arg(&self, i: usize) -> Result<&str> {
         self.arg_opt(i).to_cmd_err(|| self.not_enough_arguments())
     }
+    // Parses an argument, describing it as `what` if it is invalid.
+    pub fn parse_arg_opt<T: FromStr>(&self, i: usize, what: &str) -> Result<Option<T>> {
+        match self.arg_opt(i) {
+            Some(arg) => Ok(Some(arg.parse().to_cmd_err(|| format!(
+                "'{}' is not a valid {}. Usage: {}", arg, what, self.usage(),
+            ))?)),
+            None => Ok(None),
+        }
+    }
+    pub fn parse_arg<T: FromStr>(&self, i: usize, what: &str) -> Result<T> {
+        self.parse_arg_opt(i, what)?.to_cmd_err(|| self.not_enough_arguments())
+    }
 
     pub fn rest_opt(&self, i: usize) -> Option<&str> {
         if i < self.argc() {
@@ -372,30 +503,95 @@ pub trait CommandContextData {
 
     fn prefix(&self) -> &str;
     fn message_content(&self) -> &str;
+    // Where each argument is in `message_content`, for contexts whose arguments are given
+    // separately and may contain spaces, such as slash commands. Otherwise, arguments are split
+    // at whitespace.
+    fn arg_positions(&self) -> Option<&[(usize, usize)]> { None }
     fn respond(&self, message: &str) -> Result<()>;
+    // Responds with an image shown alongside the message, such as a Roblox avatar, and optionally
+    // a larger one below it, in contexts that can show them.
+    fn respond_with_thumbnail(
+        &self, message: &str, _thumbnail_url: &str, _image_url: Option<&str>,
+    ) -> Result<()> {
+        self.respond(message)
+    }
+    fn respond_embed(&self, embed: &ResponseEmbed) -> Result<()> {
+        self.respond(&embed.to_text())
+    }
 
     fn discord_context(&self) -> Option<(&Context, &Message)> { None }
+
+    // These are used by contexts that are on Discord but have no message, such as slash commands.
+    fn author(&self) -> Option<&User> {
+        self.discord_context().map(|x| &x.1.author)
+    }
+    fn guild_member(&self) -> Option<(GuildId, Permissions)> { None }
 }
 
 mod config;
 mod management;
+mod paginate;
+mod permissions;
+mod ratelimit;
+mod setup;
+mod slash;
 mod verifier;
+mod wizard;
+
+use self::ratelimit::RateLimitStatus;
+
+pub use self::paginate::{handle_page_reaction, is_page_reaction, prune_paginators};
+pub use self::ratelimit::prune_rate_limits;
+pub use self::setup::handle_setup_message;
+pub use self::slash::{handle_interaction, register_slash_commands};
+pub use self::verifier::verify_in_guild;
+pub use self::wizard::{cancel_wizards, handle_wizard_message, start_wizard_dm};
+
+fn is_listed(ctx: &CommandContext, command: &Command) -> Result<bool> {
+    Ok(!command.hidden && command.allowed_contexts.contains(ctx.command_target) &&
+       ctx.has_command_permissions(command, None)?)
+}
+fn help_line(ctx: &CommandContext, command: &Command) -> String {
+    format!("{}{}{}{}", ctx.prefix(), command.name,
+            command.help_args.map_or("".to_owned(), |x| format!(" {}", x)),
+            command.help_desc.map_or("".to_owned(), |x| format!(" - {}", x)))
+}
 
 static CORE_COMMANDS: &'static [Command] = &[
     Command::new("help")
-        .help(None, "Lists all available commands.")
+        .help(Some("[command]"), "Lists all available commands, or explains one command.")
         .exec(|ctx| {
             let mut buffer = String::new();
+            if let Some(name) = ctx.arg_opt(0) {
+                let name = name.trim_left_matches(ctx.prefix()).to_lowercase();
+                let command = match COMMANDS.get(&name) {
+                    Some(command) if is_listed(ctx, command)? => command,
+                    _ => cmd_error!("There is no command named '{}'.", name),
+                };
+                writeln!(buffer, "{}", help_line(ctx, command))?;
+                if command.cooldown_secs != 0 {
+                    writeln!(buffer, "This command can be used once every {}.",
+                             util::to_english_time(command.cooldown_secs))?;
+                }
+                if let Some(node) = command.permission_node {
+                    writeln!(buffer, "Permission node: `{}`", node)?;
+                }
+                for &(subcommand, node) in command.subcommand_nodes {
+                    writeln!(buffer, "Permission node for `{}`: `{}`", subcommand, node)?;
+                }
+                return ctx.respond(&buffer)
+            }
+
             writeln!(buffer, "Command list: ([optional parameter], <required parameter>)")?;
-            for command in COMMANDS.command_list() {
-                if !command.hidden &&
-                   ctx.privilege_level >= command.required_privilege &&
-                   command.allowed_contexts.contains(ctx.command_target) &&
-                   ctx.has_discord_permissions(command.discord_permissions) {
-                    writeln!(buffer, "• {}{}{}{}",
-                             ctx.prefix(), command.name,
-                             command.help_args.map_or("".to_owned(), |x| format!(" {}", x)),
-                             command.help_desc.map_or("".to_owned(), |x| format!(" - {}", x)))?;
+            for &(category, ref commands) in COMMANDS.categories() {
+                let mut lines = String::new();
+                for &command in commands {
+                    if is_listed(ctx, command)? {
+                        writeln!(lines, "• {}", help_line(ctx, command))?;
+                    }
+                }
+                if !lines.is_empty() {
+                    write!(buffer, "\n**{}**\n{}", category, lines)?;
                 }
             }
             ctx.respond(&buffer)?;
@@ -404,7 +600,9 @@ static CORE_COMMANDS: &'static [Command] = &[
 ];
 lazy_static! {
     static ref COMMANDS: CommandList = CommandList::new(&[
-        CORE_COMMANDS, config::COMMANDS, management::COMMANDS, verifier::COMMANDS,
+        ("General", CORE_COMMANDS), ("Verification", verifier::COMMANDS),
+        ("Configuration", config::COMMANDS), ("Permissions", permissions::COMMANDS),
+        ("Management", management::COMMANDS),
     ]);
 }
 pub fn get_command(msg: &str) -> Option<&'static Command> {
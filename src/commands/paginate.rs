@@ -0,0 +1,133 @@
+use super::*;
+
+use parking_lot::Mutex;
+use serenity::builder::CreateEmbed;
+use std::cmp::min;
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+const PAGE_TIMEOUT_SECS: u64 = 60 * 5;
+const PREVIOUS_PAGE_EMOJI: &str = "◀";
+const NEXT_PAGE_EMOJI: &str = "▶";
+
+struct Paginator {
+    channel_id: ChannelId, user_id: UserId, pages: Vec<ResponseEmbed>, page: usize,
+    last_activity: SystemTime,
+}
+impl Paginator {
+    fn is_expired(&self, now: SystemTime) -> bool {
+        match now.duration_since(self.last_activity) {
+            Ok(age) => age >= Duration::from_secs(PAGE_TIMEOUT_SECS),
+            Err(_) => false,
+        }
+    }
+}
+
+lazy_static! {
+    static ref PAGINATORS: Mutex<HashMap<MessageId, Paginator>> = Mutex::new(HashMap::new());
+}
+
+fn page_embed(e: CreateEmbed, page: &ResponseEmbed, number: usize, count: usize) -> CreateEmbed {
+    let mut e = e.title(&page.title).description(&page.description)
+        .footer(|f| f.text(format!("Page {} of {}", number + 1, count)));
+    for &(ref name, ref value, inline) in &page.fields {
+        e = e.field(name, value, inline);
+    }
+    e
+}
+
+// Shows a list of pages one at a time, starting from `start`. The user who ran the command can
+// turn the pages by reacting to the message, until it has not been used for a while. Where
+// reactions cannot be used, such as in the terminal, every page is shown at once.
+pub(super) fn paginate(
+    ctx: &CommandContext, pages: Vec<ResponseEmbed>, start: usize,
+) -> Result<()> {
+    let msg = match ctx.discord_context() {
+        Some((_, msg)) if pages.len() > 1 => msg,
+        _ => {
+            for page in &pages {
+                ctx.respond_embed(page)?;
+            }
+            return Ok(())
+        }
+    };
+    let start = min(start, pages.len() - 1);
+    let author = msg.author.id;
+    let message = msg.channel_id.send_message(|m|
+        m.content(format_args!("<@{}>", author))
+            .embed(|e| page_embed(e, &pages[start], start, pages.len()))
+    )?;
+    PAGINATORS.lock().insert(message.id, Paginator {
+        channel_id: msg.channel_id, user_id: author, pages, page: start,
+        last_activity: SystemTime::now(),
+    });
+    for &emoji in &[PREVIOUS_PAGE_EMOJI, NEXT_PAGE_EMOJI] {
+        message.react(ReactionType::Unicode(emoji.to_owned()))?;
+    }
+    Ok(())
+}
+
+pub fn is_page_reaction(emoji: &ReactionType) -> bool {
+    match *emoji {
+        ReactionType::Unicode(ref emoji) =>
+            emoji == PREVIOUS_PAGE_EMOJI || emoji == NEXT_PAGE_EMOJI,
+        _ => false,
+    }
+}
+
+// Turns the page of a paginated message when the user it was shown to reacts to it, and returns
+// whether the reaction was on one.
+pub fn handle_page_reaction(reaction: &Reaction) -> Result<bool> {
+    let forward = match reaction.emoji {
+        ReactionType::Unicode(ref emoji) if emoji == NEXT_PAGE_EMOJI => true,
+        ReactionType::Unicode(ref emoji) if emoji == PREVIOUS_PAGE_EMOJI => false,
+        _ => return Ok(false),
+    };
+    let now = SystemTime::now();
+    let (page, number, count) = {
+        let mut paginators = PAGINATORS.lock();
+        let paginator = match paginators.get_mut(&reaction.message_id) {
+            Some(paginator) => paginator,
+            None => return Ok(false),
+        };
+        if paginator.is_expired(now) {
+            return Ok(false)
+        }
+        if reaction.user_id != paginator.user_id {
+            return Ok(true)
+        }
+        let number = if forward {
+            min(paginator.page + 1, paginator.pages.len() - 1)
+        } else {
+            paginator.page.saturating_sub(1)
+        };
+        paginator.last_activity = now;
+        if number == paginator.page {
+            return Ok(true)
+        }
+        paginator.page = number;
+        (paginator.pages[number].clone(), number, paginator.pages.len())
+    };
+    reaction.channel_id.edit_message(reaction.message_id, |m|
+        m.embed(|e| page_embed(e, &page, number, count))
+    )?;
+    Ok(true)
+}
+
+// Forgets paginated messages that have not been used for a while, and removes their reactions.
+pub fn prune_paginators() {
+    let now = SystemTime::now();
+    let expired: Vec<(ChannelId, MessageId)> = {
+        let mut paginators = PAGINATORS.lock();
+        let expired = paginators.iter().filter(|x| x.1.is_expired(now))
+            .map(|(&message_id, x)| (x.channel_id, message_id)).collect();
+        paginators.retain(|_, x| !x.is_expired(now));
+        paginators.shrink_to_fit();
+        expired
+    };
+    for (channel_id, message_id) in expired {
+        if let Err(e) = channel_id.delete_reactions(message_id) {
+            debug!("Could not remove page reactions from message #{}: {}", message_id, e);
+        }
+    }
+}
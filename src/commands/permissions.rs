@@ -0,0 +1,141 @@
+use super::*;
+
+use super::verifier::{find_role, parse_discord_user};
+use util::get_discord_username;
+
+// Checks whether the user running a command was granted a node in the current server.
+pub(super) fn has_grant(ctx: &CommandContext, node: &str) -> Result<bool> {
+    let guild_id = match ctx.get_guild()? {
+        Some(guild_id) => guild_id,
+        None => return Ok(false),
+    };
+    let user_id = match ctx.data.author() {
+        Some(user) => user.id,
+        None => return Ok(false),
+    };
+    let roles = match guild_id.find() {
+        Some(guild) => guild.read().members.get(&user_id)
+            .map_or(Vec::new(), |member| member.roles.clone()),
+        None => Vec::new(),
+    };
+    ctx.core.permissions().is_granted(guild_id, user_id, &roles, node)
+}
+
+fn parse_target(guild_id: GuildId, target: &str) -> Result<PermissionTarget> {
+    if !target.starts_with("<@&") {
+        if let Some(user_id) = parse_discord_user(target)? {
+            let is_role = guild_id.find()
+                .map_or(false, |guild| guild.read().roles.contains_key(&RoleId(user_id.0)));
+            if !is_role {
+                return Ok(PermissionTarget::User(user_id))
+            }
+        }
+    }
+    Ok(PermissionTarget::Role(find_role(guild_id, target)?))
+}
+
+fn show_target(guild_id: GuildId, target: PermissionTarget) -> String {
+    match target {
+        PermissionTarget::Role(role_id) => {
+            let name = guild_id.find()
+                .and_then(|guild| guild.read().roles.get(&role_id).map(|x| x.name.clone()));
+            match name {
+                Some(name) => format!("role '{}'", name),
+                None => format!("deleted role {}", role_id.0),
+            }
+        }
+        PermissionTarget::User(user_id) => get_discord_username(user_id),
+    }
+}
+
+// Returns every permission node, with the commands and subcommands that use it.
+fn all_nodes() -> Vec<(&'static str, Vec<String>)> {
+    let mut nodes: Vec<(&'static str, Vec<String>)> = Vec::new();
+    {
+        let mut add = |node: &'static str, command: String| {
+            match nodes.iter().position(|x| x.0 == node) {
+                Some(i) => nodes[i].1.push(command),
+                None => nodes.push((node, vec![command])),
+            }
+        };
+        for command in super::COMMANDS.command_list() {
+            if let Some(node) = command.permission_node {
+                add(node, command.name.to_owned());
+            }
+            for &(subcommand, node) in command.subcommand_nodes {
+                add(node, format!("{} {}", command.name, subcommand));
+            }
+        }
+    }
+    nodes.sort_by_key(|x| x.0);
+    nodes
+}
+
+fn perm_grant(ctx: &CommandContext, guild_id: GuildId, grant: bool) -> Result<()> {
+    // The node comes last, so that role names containing spaces do not need to be quoted.
+    let args = ctx.rest(1)?;
+    let split = args.rfind(char::is_whitespace)
+        .to_cmd_err(|| format!("Usage: {}perm {} <@role|@user|role name> <node>",
+                               ctx.prefix(), ctx.arg(0).unwrap_or("grant")))?;
+    let node = args[split..].trim();
+    let target = parse_target(guild_id, args[..split].trim())?;
+    cmd_ensure!(!grant || node == "*" || all_nodes().iter().any(|x| node_matches(node, x.0)),
+                "No commands use the permission node '{}'. Use `{}perm nodes` to list the \
+                 available nodes.", node, ctx.prefix());
+    let target_name = show_target(guild_id, target);
+    if grant {
+        cmd_ensure!(ctx.core.permissions().grant(guild_id, target, node)?,
+                    "{} has already been granted '{}'.", target_name, node);
+        ctx.respond(format!("{} has been granted '{}'.", target_name, node))
+    } else {
+        cmd_ensure!(ctx.core.permissions().revoke(guild_id, target, node)?,
+                    "{} has not been granted '{}'.", target_name, node);
+        ctx.respond(format!("'{}' has been revoked from {}.", node, target_name))
+    }
+}
+
+fn perm_list(ctx: &CommandContext, guild_id: GuildId) -> Result<()> {
+    let grants = ctx.core.permissions().get_grants(guild_id)?;
+    let mut list = String::new();
+    for grant in grants.iter() {
+        writeln!(list, "• `{}` to {}", grant.node, show_target(guild_id, grant.target))?;
+    }
+    if list.is_empty() {
+        ctx.respond("No permission nodes have been granted on this server.")
+    } else {
+        ctx.respond(list)
+    }
+}
+
+fn perm_nodes(ctx: &CommandContext) -> Result<()> {
+    let mut list = String::new();
+    for (node, commands) in all_nodes() {
+        writeln!(list, "• `{}`: {}", node, commands.join(", "))?;
+    }
+    ctx.respond(list)
+}
+
+pub const COMMANDS: &[Command] = &[
+    Command::new("perm")
+        .help(Some("<grant|revoke <@role|@user|role name> <node>|list|nodes>"),
+              "Lets roles or users without the required Discord permissions use commands. \
+               `grant` and `revoke` give or take away a permission node, which covers every \
+               node under it, such as `verifier.roles` for `verifier.roles.bind`. `*` covers \
+               every node. `list` shows the nodes granted on this server, and `nodes` lists the \
+               nodes commands use.")
+        .required_permissions(enum_set!(DiscordPermission::ManageGuild))
+        .permission("permissions.manage")
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
+        .exec_discord(|ctx, _, msg| {
+            let guild_id = msg.guild_id()?;
+            match ctx.arg(0)? {
+                "grant" => perm_grant(ctx, guild_id, true),
+                "revoke" => perm_grant(ctx, guild_id, false),
+                "list" => perm_list(ctx, guild_id),
+                "nodes" => perm_nodes(ctx),
+                _ => cmd_error!("Unknown subcommand. Usage: {0}perm grant <target> <node>, \
+                                 {0}perm revoke <target> <node>, {0}perm list, {0}perm nodes",
+                                ctx.prefix()),
+            }
+        }),
+];
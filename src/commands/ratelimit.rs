@@ -0,0 +1,125 @@
+use super::*;
+
+use parking_lot::Mutex;
+use std::time::{Duration, Instant};
+
+// Buckets that have not been used for this long are forgotten, as they will have refilled.
+const BUCKET_IDLE_SECS: u64 = 60 * 60 * 24;
+
+struct TokenBucket {
+    tokens: f64, last_update: Instant, warned: bool,
+}
+
+lazy_static! {
+    static ref BUCKETS: Mutex<HashMap<(Option<GuildId>, UserId), TokenBucket>> =
+        Mutex::new(HashMap::new());
+    static ref COOLDOWNS: Mutex<HashMap<(&'static str, Option<GuildId>, UserId), Instant>> =
+        Mutex::new(HashMap::new());
+}
+
+fn as_secs_f64(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1_000_000_000.0
+}
+
+pub(super) enum RateLimitStatus {
+    Allowed,
+    // `first` is set only for the first command rejected since the user was last allowed one,
+    // so that spamming commands does not also make the bot spam replies.
+    Limited { retry_after: Duration, first: bool },
+}
+
+fn is_exempt(ctx: &CommandContext, guild_id: GuildId, user_id: UserId) -> Result<bool> {
+    let exempt_roles: Vec<RoleId> =
+        match ctx.core.config().get(Some(guild_id), ConfigKeys::CommandRateLimitExemptRoles)? {
+            Some(roles) => roles.split(',').filter_map(|x| x.trim().parse().ok())
+                .map(RoleId).collect(),
+            None => return Ok(false),
+        };
+    Ok(match guild_id.find() {
+        Some(guild) => guild.read().members.get(&user_id)
+            .map_or(false, |member| member.roles.iter().any(|x| exempt_roles.contains(x))),
+        None => false,
+    })
+}
+
+// Takes a token from the bucket of the user running a command. Each user has a separate bucket
+// in each server, holding up to the server's burst size, and refilled at its per-minute rate.
+pub(super) fn check(ctx: &CommandContext) -> Result<RateLimitStatus> {
+    if ctx.privilege_level >= PrivilegeLevel::GuildOwner {
+        return Ok(RateLimitStatus::Allowed)
+    }
+    let user_id = match ctx.data.author() {
+        Some(user) => user.id,
+        None => return Ok(RateLimitStatus::Allowed),
+    };
+    let guild_id = ctx.get_guild()?;
+    let per_minute = ctx.core.config().get(guild_id, ConfigKeys::CommandRateLimitPerMinute)?;
+    let burst = ctx.core.config().get(guild_id, ConfigKeys::CommandRateLimitBurst)?;
+    if per_minute == 0 || burst == 0 {
+        return Ok(RateLimitStatus::Allowed)
+    }
+    if let Some(guild_id) = guild_id {
+        if is_exempt(ctx, guild_id, user_id)? {
+            return Ok(RateLimitStatus::Allowed)
+        }
+    }
+
+    let now = Instant::now();
+    let per_second = per_minute as f64 / 60.0;
+    let mut buckets = BUCKETS.lock();
+    let bucket = buckets.entry((guild_id, user_id)).or_insert(TokenBucket {
+        tokens: burst as f64, last_update: now, warned: false,
+    });
+    let refilled = as_secs_f64(now - bucket.last_update) * per_second;
+    bucket.tokens = (bucket.tokens + refilled).min(burst as f64);
+    bucket.last_update = now;
+    if bucket.tokens >= 1.0 {
+        bucket.tokens -= 1.0;
+        bucket.warned = false;
+        Ok(RateLimitStatus::Allowed)
+    } else {
+        let wait_secs = ((1.0 - bucket.tokens) / per_second).ceil() as u64;
+        let first = !bucket.warned;
+        bucket.warned = true;
+        Ok(RateLimitStatus::Limited { retry_after: Duration::from_secs(wait_secs), first })
+    }
+}
+
+// Returns how long the user running a command must wait before using it again, if its cooldown
+// has not ended yet.
+pub(super) fn check_cooldown(ctx: &CommandContext, command: &Command) -> Result<Option<Duration>> {
+    if command.cooldown_secs == 0 || ctx.privilege_level >= PrivilegeLevel::BotOwner {
+        return Ok(None)
+    }
+    let user_id = match ctx.data.author() {
+        Some(user) => user.id,
+        None => return Ok(None),
+    };
+    let key = (command.name, ctx.get_guild()?, user_id);
+
+    let now = Instant::now();
+    let cooldown = Duration::from_secs(command.cooldown_secs);
+    let mut cooldowns = COOLDOWNS.lock();
+    if let Some(&last_used) = cooldowns.get(&key) {
+        if now - last_used < cooldown {
+            return Ok(Some(cooldown - (now - last_used)))
+        }
+    }
+    cooldowns.insert(key, now);
+    Ok(None)
+}
+
+pub fn prune_rate_limits() {
+    let now = Instant::now();
+    let mut buckets = BUCKETS.lock();
+    buckets.retain(|_, bucket| now - bucket.last_update < Duration::from_secs(BUCKET_IDLE_SECS));
+    buckets.shrink_to_fit();
+    drop(buckets);
+
+    let mut cooldowns = COOLDOWNS.lock();
+    cooldowns.retain(|&(name, _, _), last_used| match COMMANDS.get(name) {
+        Some(command) => now - *last_used < Duration::from_secs(command.cooldown_secs),
+        None => false,
+    });
+    cooldowns.shrink_to_fit();
+}
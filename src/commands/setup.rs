@@ -0,0 +1,254 @@
+use super::*;
+use super::verifier::{check_role_access, find_role, parse_group_binding};
+
+use core::NicknameSource;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+const SETUP_TIMEOUT_SECS: u64 = 60 * 10;
+const SETUP_STEPS: u32 = 4;
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+enum SetupStep {
+    VerifiedRole, AuditChannel, Nickname, GroupBindings,
+}
+impl SetupStep {
+    fn number(self) -> u32 {
+        match self {
+            SetupStep::VerifiedRole => 1,
+            SetupStep::AuditChannel => 2,
+            SetupStep::Nickname => 3,
+            SetupStep::GroupBindings => 4,
+        }
+    }
+    fn prompt(self) -> &'static str {
+        match self {
+            SetupStep::VerifiedRole =>
+                "**Verified role.** Which role should every verified member get? Reply with the \
+                 role's name or mention, or `skip`.",
+            SetupStep::AuditChannel =>
+                "**Audit channel.** Which channel should verifications and failed attempts be \
+                 logged to? Reply with a channel mention, or `skip`.",
+            SetupStep::Nickname =>
+                "**Nicknames.** How should the nicknames of verified members be set? Reply \
+                 with a number:\n\
+                 `1` - Do not change nicknames\n\
+                 `2` - Roblox username\n\
+                 `3` - Roblox display name\n\
+                 `4` - Both, e.g. `Builder (@builderman)`",
+            SetupStep::GroupBindings =>
+                "**Group roles.** To give a role to the members of a Roblox group, reply with \
+                 `<group id>[:<ranks>] <role>`, e.g. `12345:100-254 @Officers`. Send one group \
+                 per message, and `done` when you are finished.",
+        }
+    }
+    fn next(self) -> Option<SetupStep> {
+        match self {
+            SetupStep::VerifiedRole => Some(SetupStep::AuditChannel),
+            SetupStep::AuditChannel => Some(SetupStep::Nickname),
+            SetupStep::Nickname => Some(SetupStep::GroupBindings),
+            SetupStep::GroupBindings => None,
+        }
+    }
+}
+
+fn step_message(step: SetupStep) -> String {
+    format!("Step {}/{}: {}\n*Reply with 'cancel' at any time to stop.*",
+            step.number(), SETUP_STEPS, step.prompt())
+}
+
+struct SetupSession {
+    channel_id: ChannelId, step: SetupStep, changes: Vec<String>, last_activity: SystemTime,
+}
+impl SetupSession {
+    fn is_expired(&self, now: SystemTime) -> bool {
+        match now.duration_since(self.last_activity) {
+            Ok(age) => age >= Duration::from_secs(SETUP_TIMEOUT_SECS),
+            Err(_) => false,
+        }
+    }
+}
+
+lazy_static! {
+    static ref SESSIONS: Mutex<HashMap<(GuildId, UserId), SetupSession>> =
+        Mutex::new(HashMap::new());
+}
+
+// Starts walking an admin through the settings most servers need, in the channel the command
+// was used in.
+pub(super) fn start_setup(ctx: &CommandContext, msg: &Message) -> Result<()> {
+    let guild_id = msg.guild_id()?;
+    SESSIONS.lock().insert((guild_id, msg.author.id), SetupSession {
+        channel_id: msg.channel_id, step: SetupStep::VerifiedRole, changes: Vec::new(),
+        last_activity: SystemTime::now(),
+    });
+    ctx.respond(format!("Let's set up verification on this server. Your replies in this channel \
+                         will be used to answer each question.\n\n{}",
+                        step_message(SetupStep::VerifiedRole)))
+}
+
+fn is_skip(content: &str) -> bool {
+    content.eq_ignore_ascii_case("skip") || content.eq_ignore_ascii_case("none")
+}
+
+// Applies one answer, and returns a description of what was changed, if anything.
+fn setup_step(
+    core: &VerifierCore, guild_id: GuildId, user_id: UserId, step: SetupStep, content: &str,
+) -> Result<Option<String>> {
+    let guild = Some(guild_id);
+    match step {
+        SetupStep::VerifiedRole => {
+            if is_skip(content) {
+                return Ok(None)
+            }
+            let role_id = find_role(guild_id, content)?;
+            check_role_access(guild_id, user_id, role_id, false)?;
+            core.config().set(core, guild, ConfigKeys::VerifiedRole, Some(role_id))?;
+            Ok(Some(format!("Verified members are given <@&{}>.", role_id.0)))
+        }
+        SetupStep::AuditChannel => {
+            if is_skip(content) {
+                return Ok(None)
+            }
+            let id = content.trim_left_matches("<#").trim_right_matches('>');
+            let channel_id = ChannelId(id.parse().ok()
+                .to_cmd_err(|| "Please reply with a channel mention, such as #audit-log.")?);
+            let channel_guild = match channel_id.find() {
+                Some(Channel::Guild(ref channel)) => Some(channel.read().guild_id),
+                _ => None,
+            };
+            cmd_ensure!(channel_guild == guild, "That channel is not in this server.");
+            core.config().set(core, guild, ConfigKeys::AuditChannel, Some(channel_id))?;
+            Ok(Some(format!("Verifications are logged to <#{}>.", channel_id.0)))
+        }
+        SetupStep::Nickname => {
+            let source = match content {
+                "1" => None,
+                "2" => Some(NicknameSource::Username),
+                "3" => Some(NicknameSource::DisplayName),
+                "4" => Some(NicknameSource::Combined),
+                _ => cmd_error!("Please reply with a number from 1 to 4."),
+            };
+            core.config().set(core, guild, ConfigKeys::SetNickname, source.is_some())?;
+            Ok(Some(match source {
+                Some(source) => {
+                    core.config().set(core, guild, ConfigKeys::NicknameSource,
+                                      source.name().to_owned())?;
+                    format!("Nicknames are set using the `{}` of verified members.",
+                            source.name())
+                }
+                None => "Nicknames are not changed.".to_owned(),
+            }))
+        }
+        SetupStep::GroupBindings => {
+            let mut parts = content.splitn(2, char::is_whitespace);
+            let binding = parts.next()?;
+            let role_name = parts.next().map_or("", |x| x.trim());
+            cmd_ensure!(!role_name.is_empty(),
+                        "Please reply with `<group id>[:<ranks>] <role>`, or `done`.");
+            let (rule_name, definition) = parse_group_binding(binding)?;
+            let role_id = find_role(guild_id, role_name)?;
+            check_role_access(guild_id, user_id, role_id, false)?;
+            core.roles().set_custom_rule(guild_id, &rule_name, Some(&definition))?;
+            core.roles().set_active_role(guild_id, &rule_name, Some(role_id))?;
+            Ok(Some(format!("Members of group {} are given <@&{}>.", binding, role_id.0)))
+        }
+    }
+}
+
+fn finish_message(core: &VerifierCore, guild_id: GuildId, changes: &[String]) -> Result<String> {
+    let prefix = core.config().get(Some(guild_id), ConfigKeys::CommandPrefix)?;
+    let mut message = "Setup is complete!".to_owned();
+    if !changes.is_empty() {
+        message.push_str(" The following was configured:");
+        for change in changes {
+            message.push_str(&format!("\n• {}", change));
+        }
+    }
+    if let Some(err) = core.roles().check_error(guild_id)? {
+        message.push_str(&format!("\n\nThere are errors in the role configuration: {}", err));
+    }
+    message.push_str(&format!("\n\nUse `{0}set` to change any other settings, and \
+                               `{0}setup verifypanel` to post a verification panel.", prefix));
+    Ok(message)
+}
+
+// Handles messages in servers that are not commands, continuing any setup the author started
+// in that channel.
+pub fn handle_setup_message(
+    core: &VerifierCore, guild_id: GuildId, message: &Message,
+) -> Result<()> {
+    let content = message.content.trim();
+    let now = SystemTime::now();
+    let key = (guild_id, message.author.id);
+    let mut session = {
+        let mut sessions = SESSIONS.lock();
+        sessions.retain(|_, session| !session.is_expired(now));
+        match sessions.get(&key) {
+            Some(session) if session.channel_id == message.channel_id => { }
+            _ => return Ok(()),
+        }
+        sessions.remove(&key)?
+    };
+    let prefix = core.config().get(Some(guild_id), ConfigKeys::CommandPrefix)?;
+    if content.is_empty() || content.starts_with(&prefix) {
+        SESSIONS.lock().insert(key, session);
+        return Ok(())
+    }
+
+    if content.eq_ignore_ascii_case("cancel") {
+        message.channel_id.say("Setup canceled. Settings already answered have been kept.")?;
+        return Ok(())
+    }
+
+    let is_done = session.step == SetupStep::GroupBindings &&
+        content.eq_ignore_ascii_case("done");
+    let mut change = None;
+    if !is_done {
+        match setup_step(core, guild_id, message.author.id, session.step, content) {
+            Ok(result) => change = result,
+            Err(Error::CommandError(err)) => {
+                session.last_activity = now;
+                SESSIONS.lock().insert(key, session);
+                message.channel_id.say(&err)?;
+                return Ok(())
+            }
+            Err(e) => {
+                message.channel_id.say("Setup encountered an unexpected error. Settings already \
+                                        answered have been kept.")?;
+                return Err(e)
+            }
+        }
+    }
+
+    // Group bindings are asked for until the admin is done with them.
+    let reply = match change {
+        Some(change) => {
+            session.changes.push(change.clone());
+            if session.step == SetupStep::GroupBindings {
+                Some(format!("{} Send another group, or `done`.", change))
+            } else {
+                None
+            }
+        }
+        None => None,
+    };
+    let reply = match reply {
+        Some(reply) => reply,
+        None => match session.step.next() {
+            Some(step) => {
+                session.step = step;
+                step_message(step)
+            }
+            None => {
+                message.channel_id.say(&finish_message(core, guild_id, &session.changes)?)?;
+                return Ok(())
+            }
+        },
+    };
+    session.last_activity = now;
+    SESSIONS.lock().insert(key, session);
+    message.channel_id.say(&reply)?;
+    Ok(())
+}
@@ -0,0 +1,303 @@
+use super::*;
+
+use reqwest::Method;
+use serde_json;
+use serde_json::Value;
+use util;
+use util::{DISCORD_API_BASE as API_BASE, discord_get, discord_request};
+
+// TODO: Serenity does not support interactions yet. Replace this with its implementation once it
+//       does.
+
+const INTERACTION_APPLICATION_COMMAND: u8 = 2;
+const INTERACTION_MESSAGE_COMPONENT: u8 = 3;
+const CALLBACK_DEFERRED_MESSAGE: u8 = 5;
+const CALLBACK_DEFERRED_UPDATE: u8 = 6;
+const FLAG_EPHEMERAL: u64 = 1 << 6;
+
+const OPTION_STRING: u8 = 3;
+const OPTION_USER: u8 = 6;
+
+struct SlashOption {
+    name: &'static str, description: &'static str, kind: u8, required: bool,
+}
+const fn option(
+    name: &'static str, description: &'static str, kind: u8, required: bool,
+) -> SlashOption {
+    SlashOption { name, description, kind, required }
+}
+
+// A slash command. Each slash command is run as the text command of the same name, with its
+// options passed as arguments in the order they are declared here. Options are passed whole, so
+// they may contain spaces.
+struct SlashCommand {
+    name: &'static str, description: &'static str, options: &'static [SlashOption],
+    default_permissions: Option<Permissions>,
+}
+
+static SLASH_COMMANDS: &'static [SlashCommand] = &[
+    SlashCommand {
+        name: "verify", description: "Verifies a Roblox account to your Discord account.",
+        options: &[
+            option("username", "Your Roblox username.", OPTION_STRING, true),
+            option("code", "The code shown in the verification place.", OPTION_STRING, true),
+        ],
+        default_permissions: None,
+    },
+    SlashCommand {
+        name: "update",
+        description: "Updates your roles and nickname according to your Roblox account.",
+        options: &[
+            option("user", "The user to update. Requires Manage Roles.", OPTION_USER, false),
+        ],
+        default_permissions: None,
+    },
+    SlashCommand {
+        name: "whois",
+        description: "Retrieves the Roblox account a Discord account is verified with.",
+        options: &[
+            option("target", "A Discord mention, user id, or roblox:<Roblox username>.",
+                   OPTION_STRING, true),
+        ],
+        default_permissions: None,
+    },
+    SlashCommand {
+        name: "get", description: "Retrieves a configuration option for this server.",
+        options: &[
+            option("option", "The name of the configuration option.", OPTION_STRING, true),
+        ],
+        default_permissions: Some(Permissions::MANAGE_GUILD),
+    },
+    SlashCommand {
+        name: "set", description: "Sets a configuration option for this server.",
+        options: &[
+            option("option", "The name of the configuration option.", OPTION_STRING, true),
+            option("value", "The new value of the configuration option.", OPTION_STRING, true),
+        ],
+        default_permissions: Some(Permissions::MANAGE_GUILD),
+    },
+    SlashCommand {
+        name: "stats", description: "Shows verification statistics for this server.",
+        options: &[],
+        default_permissions: Some(Permissions::MANAGE_GUILD),
+    },
+];
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct CommandOptionPayload {
+    name: String, description: String,
+    #[serde(rename = "type")] kind: u8, #[serde(default)] required: bool,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct CommandPayload {
+    name: String, description: String, #[serde(default)] options: Vec<CommandOptionPayload>,
+    default_member_permissions: Option<String>, #[serde(default)] dm_permission: bool,
+}
+
+#[derive(Serialize)]
+struct MessagePayload<'a> {
+    content: Option<&'a str>, flags: u64,
+}
+
+#[derive(Serialize)]
+struct CallbackPayload<'a> {
+    #[serde(rename = "type")] kind: u8, data: MessagePayload<'a>,
+}
+
+// Registers the slash commands globally, replacing any previously registered commands. This is
+// skipped if they are already registered as they are here, as Discord limits how often commands
+// can be registered.
+pub fn register_slash_commands(token: &str, application_id: UserId) -> Result<()> {
+    let mut commands: Vec<_> = SLASH_COMMANDS.iter().map(|command| CommandPayload {
+        name: command.name.to_owned(), description: command.description.to_owned(),
+        options: command.options.iter().map(|x| CommandOptionPayload {
+            name: x.name.to_owned(), description: x.description.to_owned(), kind: x.kind,
+            required: x.required,
+        }).collect(),
+        default_member_permissions: command.default_permissions.map(|x| x.bits().to_string()),
+        dm_permission: false,
+    }).collect();
+    commands.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let uri = format!("{}/applications/{}/commands", API_BASE, application_id.0);
+    let mut registered =
+        serde_json::from_str::<Vec<CommandPayload>>(&discord_get(token, &uri)?.text()?)?;
+    registered.sort_by(|a, b| a.name.cmp(&b.name));
+    if registered == commands {
+        debug!("Slash commands are already registered.");
+        return Ok(())
+    }
+    discord_request(token, Method::Put, &uri, &commands)?;
+    info!("Registered {} slash commands.", SLASH_COMMANDS.len());
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct InteractionMember {
+    user: User, permissions: String,
+}
+
+#[derive(Deserialize)]
+struct InteractionOption {
+    name: String, value: Value,
+}
+
+#[derive(Deserialize)]
+struct InteractionData {
+    #[serde(default)] name: String, #[serde(default)] options: Vec<InteractionOption>,
+    #[serde(default)] custom_id: String,
+}
+
+#[derive(Deserialize)]
+struct Interaction {
+    id: String, application_id: String, token: String,
+    #[serde(rename = "type")] kind: u8,
+    guild_id: Option<GuildId>, member: Option<InteractionMember>,
+    data: Option<InteractionData>,
+}
+
+struct SlashContext {
+    bot_token: String, application_id: String, interaction_token: String,
+    user: User, guild_id: GuildId, permissions: Permissions,
+    privilege_level: PrivilegeLevel, prefix: String, content: String,
+    arg_positions: Vec<(usize, usize)>, command_no: usize,
+}
+impl CommandContextData for SlashContext {
+    fn privilege_level(&self) -> PrivilegeLevel {
+        self.privilege_level
+    }
+    fn command_target(&self) -> CommandTarget {
+        CommandTarget::ServerMessage
+    }
+    fn prefix(&self) -> &str {
+        &self.prefix
+    }
+    fn message_content(&self) -> &str {
+        &self.content
+    }
+    fn arg_positions(&self) -> Option<&[(usize, usize)]> {
+        Some(&self.arg_positions)
+    }
+    // Responses are sent as ephemeral followup messages, so only the user that used the command
+    // can see them, and any verification codes in it are never shown in the channel.
+    fn respond(&self, message: &str) -> Result<()> {
+        for line in message.split('\n') {
+            debug!(target: "$raw", "[Command #{}] {}", self.command_no, line);
+        }
+        discord_request(&self.bot_token, Method::Post,
+                        &format!("{}/webhooks/{}/{}", API_BASE,
+                                 self.application_id, self.interaction_token),
+                        &MessagePayload { content: Some(message), flags: FLAG_EPHEMERAL })?;
+        Ok(())
+    }
+    fn author(&self) -> Option<&User> {
+        Some(&self.user)
+    }
+    fn guild_member(&self) -> Option<(GuildId, Permissions)> {
+        Some((self.guild_id, self.permissions))
+    }
+}
+
+fn option_to_arg(value: &Value) -> String {
+    match *value {
+        Value::String(ref s) => s.clone(),
+        ref value => value.to_string(),
+    }
+}
+
+// Returns the text command a slash command is run as, along with where each of its arguments
+// is in it.
+fn slash_command_line(data: &InteractionData) -> Option<(String, Vec<(usize, usize)>)> {
+    let command = SLASH_COMMANDS.iter().find(|x| x.name == data.name)?;
+    let mut content = data.name.clone();
+    let mut positions = Vec::new();
+    for declared in command.options {
+        // Only trailing options are optional, so the arguments stop at the first one missing.
+        let value = match data.options.iter().find(|x| x.name == declared.name) {
+            Some(option) => option_to_arg(&option.value),
+            None => break,
+        };
+        content.push(' ');
+        positions.push((content.len(), content.len() + value.len()));
+        content.push_str(&value);
+    }
+    Some((content, positions))
+}
+
+// Returns the text command a button is run as. Only the buttons on approval requests and admin
+// action confirmation requests are currently handled here.
+fn button_command_line(data: &InteractionData) -> Option<String> {
+    if data.custom_id.starts_with(APPROVE_BUTTON_PREFIX) {
+        Some(format!("approve {}", &data.custom_id[APPROVE_BUTTON_PREFIX.len()..]))
+    } else if data.custom_id.starts_with(REJECT_BUTTON_PREFIX) {
+        Some(format!("reject {}", &data.custom_id[REJECT_BUTTON_PREFIX.len()..]))
+    } else if data.custom_id.starts_with(CONFIRM_ACTION_BUTTON_PREFIX) {
+        Some(format!("confirm_action {}",
+                     &data.custom_id[CONFIRM_ACTION_BUTTON_PREFIX.len()..]))
+    } else if data.custom_id.starts_with(CANCEL_ACTION_BUTTON_PREFIX) {
+        Some(format!("cancel_action {}", &data.custom_id[CANCEL_ACTION_BUTTON_PREFIX.len()..]))
+    } else {
+        None
+    }
+}
+
+// Handles a raw INTERACTION_CREATE event from the gateway.
+pub fn handle_interaction(core: &VerifierCore, raw: Value) -> Result<()> {
+    let interaction = serde_json::from_value::<Interaction>(raw)?;
+    let data = interaction.data?;
+    let (command_line, callback) = match interaction.kind {
+        INTERACTION_APPLICATION_COMMAND => (slash_command_line(&data), CALLBACK_DEFERRED_MESSAGE),
+        INTERACTION_MESSAGE_COMPONENT =>
+            (button_command_line(&data).map(|x| (x, Vec::new())), CALLBACK_DEFERRED_UPDATE),
+        _ => return Ok(()),
+    };
+    let token = core.config().get(None, ConfigKeys::DiscordToken)?
+        .to_cmd_err(|| "No Discord token is configured.")?;
+
+    // Discord requires a response within 3 seconds, so the response is deferred and the actual
+    // result of the command is sent as a followup.
+    discord_request(&token, Method::Post,
+                    &format!("{}/interactions/{}/{}/callback",
+                             API_BASE, interaction.id, interaction.token),
+                    &CallbackPayload {
+                        kind: callback,
+                        data: MessagePayload { content: None, flags: FLAG_EPHEMERAL },
+                    })?;
+
+    let (guild_id, member) = match (interaction.guild_id, interaction.member) {
+        (Some(guild_id), Some(member)) => (guild_id, member),
+        _ => return Ok(()),
+    };
+    let permissions = Permissions::from_bits_truncate(member.permissions.parse()?);
+    let bot_owner_id = core.config().get(None, ConfigKeys::BotOwnerId)?.map(UserId);
+    let privilege_level =
+        if Some(member.user.id) == bot_owner_id {
+            PrivilegeLevel::BotOwner
+        } else if guild_id.find().map_or(false, |x| x.read().owner_id == member.user.id) {
+            PrivilegeLevel::GuildOwner
+        } else {
+            PrivilegeLevel::NormalUser
+        };
+
+    let (content, arg_positions) = command_line.unwrap_or_default();
+    let command_no = util::command_id();
+    info!("{} in guild #{} (slash command #{}): /{}",
+          member.user.tag(), guild_id, command_no, content);
+    let ctx = SlashContext {
+        bot_token: token, application_id: interaction.application_id,
+        interaction_token: interaction.token,
+        user: member.user, guild_id, permissions, privilege_level,
+        prefix: core.config().get(Some(guild_id), ConfigKeys::CommandPrefix)?, content,
+        arg_positions, command_no,
+    };
+    if !core.is_running() {
+        return ctx.respond(SHUTDOWN_MESSAGE)
+    }
+    match get_command(&ctx.content) {
+        Some(command) => command.run(&ctx, core),
+        None => ctx.respond("Unknown command.")?,
+    }
+    debug!("Command #{} completed.", command_no);
+    Ok(())
+}
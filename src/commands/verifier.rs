@@ -1,121 +1,273 @@
 use super::*;
 
 use chrono::{DateTime, Utc};
+use i18n;
 use regex::Regex;
 use roblox::*;
 use serenity;
 use std::cmp::max;
 use std::time::SystemTime;
 use util;
+use super::paginate::paginate;
+use util::get_discord_username;
+use web;
 
 // TODO: Check role existence.
 // TODO: Consider moving error messages back into roles.rs
-// TODO: Support force updating an user.
 
 lazy_static! {
     static ref MENTION_REGEX: Regex = Regex::new("^<@!?([0-9]+)>$").unwrap();
+    static ref ROLE_MENTION_REGEX: Regex = Regex::new("^<@&?([0-9]+)>$").unwrap();
     static ref SNOWFLAKE_REGEX: Regex = Regex::new("^([0-9]+)$").unwrap();
 }
 
-fn get_discord_username(discord_id: UserId) -> String {
-    match discord_id.find() {
-        Some(x) => x.read().tag(),
-        None => match discord_id.get() {
-            Ok(x) => x.tag(),
-            Err(_) => format!("(discord uid #{})", discord_id.0),
-        }
-    }
-}
 fn reverify_help(
-    ctx: &CommandContext, discord_id: UserId, roblox_id: RobloxUserID
+    core: &VerifierCore, guild_id: GuildId, prefix: &str, discord_id: UserId,
+    roblox_id: RobloxUserID,
 ) -> Result<String> {
-    if ctx.core.verifier().get_verified_roblox_user(discord_id)? == Some(roblox_id) {
-        Ok(format!(" If you only want to update your roles, use the '{}update' command.",
-                   ctx.prefix()))
+    let accounts = core.verifier().get_linked_roblox_users(discord_id)?;
+    let key = if accounts.first() == Some(&roblox_id) {
+        "verify-hint-update"
+    } else if accounts.contains(&roblox_id) {
+        "verify-hint-switch"
     } else {
-        Ok(String::new())
-    }
+        return Ok(String::new())
+    };
+    Ok(format!(" {}", i18n::tr(core.config(), Some(guild_id), key, &[("prefix", prefix)])?))
 }
-fn do_verify(ctx: &CommandContext, _: &Context, msg: &Message) -> Result<()> {
-    cmd_ensure!(ctx.argc() >= 2, ctx.core.verify_channel().verify_instructions()?);
-
-    let roblox_username = ctx.arg(0)?;
-    let token = ctx.arg(1)?;
+// Approves or rejects a user's pending verification, updates their roles to match, and returns
+// the message to show the moderator.
+pub(super) fn review_approval(
+    core: &VerifierCore, guild_id: GuildId, discord_id: UserId, moderator: UserId, approve: bool,
+) -> Result<String> {
+    let (roblox_id, key) = if approve {
+        (core.approvals().approve(guild_id, discord_id, moderator)?, "verify-approved-notice")
+    } else {
+        (core.approvals().reject(guild_id, discord_id, moderator)?, "verify-rejected-notice")
+    };
+    info!("{}'s verification as {} was {} by {} in {}.",
+          get_discord_username(discord_id), roblox_id.0,
+          if approve { "approved" } else { "rejected" }, get_discord_username(moderator),
+          guild_id);
+    core.roles().update_user(guild_id, discord_id, true).drop_nonfatal()?;
+    if approve {
+        core.group_ranks().promote_on_verify(guild_id, discord_id, roblox_id);
+    }
 
-    let roblox_id = RobloxUserID::for_username(roblox_username)?;
-    let discord_username = msg.author.tag();
-    let discord_id = msg.author.id;
+    let server = guild_id.find().map_or_else(|| guild_id.to_string(), |x| x.read().name.clone());
+    let notice = i18n::tr(core.config(), Some(guild_id), key, &[("server", &server)])?;
+    if let Err(e) = discord_id.create_dm_channel().and_then(|x| x.say(&notice)) {
+        debug!("Could not notify {} of their approval status: {}", discord_id, e);
+    }
+    Ok(format!("{}'s verification as {} has been {}.", get_discord_username(discord_id),
+               roblox_id.lookup_username()?, if approve { "approved" } else { "rejected" }))
+}
 
-    let guild_id = msg.guild_id()?;
+// Attempts to verify a user in a guild, and returns the message to show them on success.
+pub fn verify_in_guild<F>(
+    core: &VerifierCore, prefix: &str, guild_id: GuildId, user: &User, roblox_username: &str,
+    method: &VerificationMethod, data: &str, invalid_message: F,
+) -> Result<String> where F: FnOnce(RobloxUserID) -> Result<String> {
+    let roblox_id = core.usernames().resolve_current(roblox_username)?;
+    let discord_username = user.tag();
+    let discord_id = user.id;
+    let tr = |key: &str, args: &[(&str, &str)]| i18n::tr(core.config(), Some(guild_id), key, args);
+    let time_diff = |to: SystemTime|
+        i18n::tr_time_diff(core.config(), Some(guild_id), SystemTime::now(), to);
+    let duration = |secs: u64| i18n::tr_duration(core.config(), Some(guild_id), secs);
 
     debug!("Beginning verification attempt: {} -> {}", discord_username, roblox_username);
 
-    match ctx.core.verifier().try_verify(discord_id, roblox_id, token)? {
+    match core.verifier().try_verify(Some(guild_id), discord_id, roblox_id, method, data)? {
         VerifyResult::VerificationOk => {
             info!("{} successfully verified as {}",
                   discord_username, roblox_username);
-            match ctx.core.roles().assign_roles(guild_id, msg.author.id, Some(roblox_id))? {
-                SetRolesStatus::Success =>
-                    ctx.respond("Your roles have been set.")?,
+            let status = core.roles().assign_roles_on_verify(guild_id, discord_id, roblox_id)?;
+            match status {
+                SetRolesStatus::Success | SetRolesStatus::IsAdmin =>
+                    core.group_ranks().promote_on_verify(guild_id, discord_id, roblox_id),
+                _ => { }
+            }
+            let message = core.templates().render(Some(guild_id), MessageKey::VerifySuccess, &[
+                ("discord_name", &discord_username), ("roblox_name", roblox_username),
+            ], || tr("verify-success", &[]))?;
+            Ok(match status {
+                SetRolesStatus::Success => message,
                 SetRolesStatus::IsAdmin =>
-                    ctx.respond("Your roles have been set. Note that your nickname has not been \
-                                 set as this bot does not have permission to edit it.")?,
+                    format!("{} {}", message, tr("verify-success-no-nickname", &[])?),
                 SetRolesStatus::NotSet  =>
                     // This case shouldn't actually happen.
-                    ctx.respond("Your roles were not set. Please contact a server administrator.")?,
-            }
-            Ok(())
+                    tr("verify-roles-not-set", &[])?,
+                SetRolesStatus::PendingApproval => tr("verify-pending-approval", &[])?,
+                SetRolesStatus::ApprovalRejected => tr("verify-approval-rejected", &[])?,
+            })
         }
         VerifyResult::TokenAlreadyUsed => {
             info!("{} failed to verify as {}: Token already used.",
                   discord_username, roblox_username);
-            cmd_error!("Someone has already used that verification code. Please wait for a \
-                        new code to be generated, then try again.")
+            cmd_error!("{}", tr("verify-token-used", &[])?)
         }
         VerifyResult::VerificationPlaceOutdated => {
             info!("{} failed to verify as {}: Outdated verification place.",
                   discord_username, roblox_username);
-            cmd_error!("The verification place is outdated, and has not been updated with the \
-                        verification bot. Please contact the bot owner.")
+            cmd_error!("{}", tr("verify-place-outdated", &[])?)
         }
         VerifyResult::InvalidToken => {
             info!("{} failed to verify as {}: Invalid token.",
                   discord_username, roblox_username);
-            cmd_error!("The verification code you used is not valid. Please check the code \
-                        you entered and try again.")
+            cmd_error!("{}", core.templates().render(
+                Some(guild_id), MessageKey::VerifyFailed, &[("roblox_name", roblox_username)],
+                || invalid_message(roblox_id),
+            )?)
         }
         VerifyResult::TooManyAttempts { max_attempts, cooldown, cooldown_ends } => {
             info!("{} failed to verify as {}: Too many attempts.",
                   discord_username, roblox_username);
-            cmd_error!("You can only try to verify {} times every {}. \
-                        Please try again in {}.{}",
-                       max_attempts, util::to_english_time(cooldown),
-                       util::english_time_diff(SystemTime::now(), cooldown_ends),
-                       reverify_help(ctx, discord_id, roblox_id)?)
+            let time_left = time_diff(cooldown_ends)?;
+            let max_attempts = max_attempts.to_string();
+            cmd_error!("{}", core.templates().render(Some(guild_id), MessageKey::Cooldown, &[
+                ("roblox_name", roblox_username), ("max_attempts", &max_attempts),
+                ("time_left", &time_left),
+            ], || Ok(format!("{}{}", tr("verify-too-many-attempts", &[
+                ("max_attempts", &max_attempts), ("cooldown", &duration(cooldown)?),
+                ("time_left", &time_left),
+            ])?, reverify_help(core, guild_id, prefix, discord_id, roblox_id)?)))?)
         }
         VerifyResult::SenderVerifiedAs { other_roblox_id } => {
             let other_roblox_username = other_roblox_id.lookup_username()?;
             info!("{} failed to verify as {}: Already verified as {}.",
                   discord_username, roblox_username, other_roblox_username);
-            cmd_error!("You are already verified as {}.{}",
-                       other_roblox_username, reverify_help(ctx, discord_id, roblox_id)?)
+            cmd_error!("{}{}",
+                       tr("verify-already-verified", &[("roblox_name", &other_roblox_username)])?,
+                       reverify_help(core, guild_id, prefix, discord_id, roblox_id)?)
         }
         VerifyResult::RobloxAccountVerifiedTo { other_discord_id } => {
             let other_discord_username = get_discord_username(other_discord_id);
             info!("{} failed to verify as {}: Roblox account already verified to {}.",
                   discord_username, roblox_username, other_discord_username);
-            cmd_error!("{} has already verified as {}.",
-                       other_discord_username, roblox_username)
+            cmd_error!("{}", tr("verify-roblox-taken", &[
+                ("discord_name", &other_discord_username), ("roblox_name", roblox_username),
+            ])?)
         }
         VerifyResult::ReverifyOnCooldown { cooldown, cooldown_ends } => {
             info!("{} failed to verify as {}: Reverified too soon.",
                   discord_username, roblox_username);
-            cmd_error!("You can only reverify once every {}. Please try again in {}.{}",
-                       util::to_english_time(cooldown),
-                       util::english_time_diff(SystemTime::now(), cooldown_ends),
-                       reverify_help(ctx, discord_id, roblox_id)?)
+            cmd_error!("{}{}", tr("verify-reverify-cooldown", &[
+                ("cooldown", &duration(cooldown)?), ("time_left", &time_diff(cooldown_ends)?),
+            ])?, reverify_help(core, guild_id, prefix, discord_id, roblox_id)?)
+        }
+        VerifyResult::Blacklisted { message } => {
+            info!("{} failed to verify as {}: Blacklisted.",
+                  discord_username, roblox_username);
+            cmd_error!("{}", message)
         }
+        VerifyResult::NameNotAllowed { name } => {
+            info!("{} failed to verify as {}: Name '{}' is not allowed.",
+                  discord_username, roblox_username, name);
+            cmd_error!("{}", tr("verify-name-not-allowed", &[("roblox_name", &name)])?)
+        }
+        VerifyResult::TooManyLinkedAccounts { max_accounts } => {
+            info!("{} failed to verify as {}: Too many linked accounts.",
+                  discord_username, roblox_username);
+            cmd_error!("{}", tr("verify-too-many-accounts", &[
+                ("max_accounts", &max_accounts.to_string()), ("prefix", prefix),
+            ])?)
+        }
+        VerifyResult::AccountTooNew { minimum_days, allowed_at } => {
+            info!("{} failed to verify as {}: Roblox account too new.",
+                  discord_username, roblox_username);
+            cmd_error!("{}", tr("verify-account-too-new", &[
+                ("minimum_days", &minimum_days.to_string()),
+                ("time_left", &time_diff(allowed_at)?),
+            ])?)
+        }
+        VerifyResult::NotInRequiredGroup { group_id, minimum_rank } => {
+            info!("{} failed to verify as {}: Not in required group {}.",
+                  discord_username, roblox_username, group_id);
+            let group_url = format!("https://www.roblox.com/groups/{}", group_id);
+            match minimum_rank {
+                Some(minimum_rank) if minimum_rank > 1 =>
+                    cmd_error!("{}", tr("verify-group-rank-required", &[
+                        ("minimum_rank", &minimum_rank.to_string()), ("group_url", &group_url),
+                    ])?),
+                _ => cmd_error!("{}", tr("verify-group-required", &[("group_url", &group_url)])?),
+            }
+        }
+    }
+}
+fn verify_with<F>(
+    ctx: &CommandContext, roblox_username: &str,
+    method: &VerificationMethod, data: &str, invalid_message: F,
+) -> Result<()> where F: FnOnce(RobloxUserID) -> Result<String> {
+    let message = verify_in_guild(ctx.core, ctx.prefix(), ctx.require_guild()?,
+                                  ctx.author()?, roblox_username, method, data, invalid_message)?;
+    let roblox_id = ctx.core.usernames().resolve_current(roblox_username)?;
+    ctx.respond_verified(message, &roblox_id.avatar_headshot_url())
+}
+fn do_verify(ctx: &CommandContext) -> Result<()> {
+    if ctx.argc() == 0 {
+        return super::wizard::start_wizard(ctx)
+    }
+    if ctx.argc() == 1 && ctx.core.config().get(None, ConfigKeys::PlaceID)?.is_some() {
+        let roblox_id = ctx.core.usernames().resolve_current(ctx.arg(0)?)?;
+        let roblox_username = roblox_id.lookup_username()?;
+        let (link, link_secs) = ctx.core.verifier().deep_link(roblox_id)?;
+        let guild_id = ctx.get_guild()?;
+        return ctx.respond(i18n::tr(ctx.core.config(), guild_id, "verify-deep-link", &[
+            ("link", &link), ("roblox_name", &roblox_username), ("prefix", ctx.prefix()),
+            ("duration", &i18n::tr_duration(ctx.core.config(), guild_id, link_secs)?),
+        ])?)
+    }
+    cmd_ensure!(ctx.argc() >= 2, ctx.core.verify_channel().verify_instructions(ctx.get_guild()?)?);
+    verify_with(ctx, ctx.arg(0)?, &TokenVerification, ctx.arg(1)?, |_| {
+        i18n::tr(ctx.core.config(), ctx.get_guild()?, "verify-invalid-token", &[])
+    })
+}
+fn do_verify_profile(ctx: &CommandContext) -> Result<()> {
+    verify_with(ctx, ctx.arg(0)?, &ProfileVerification, "", |roblox_id| {
+        let phrase = ctx.core.verifier().profile_phrase(ctx.author()?.id, roblox_id)?;
+        i18n::tr(ctx.core.config(), ctx.get_guild()?, "verify-profile-phrase",
+                 &[("phrase", &phrase)])
+    })
+}
+
+fn do_verify_oauth(ctx: &CommandContext) -> Result<()> {
+    let guild_id = ctx.get_guild()?;
+    if ctx.argc() < 2 {
+        let key = ConfigKeys::VerificationMethodRobloxOAuth;
+        cmd_ensure!(ctx.core.verifier().is_method_enabled(guild_id, key)?,
+                    "{}", i18n::tr(ctx.core.config(), guild_id, "verify-method-disabled", &[])?);
+        let url = ctx.core.verifier().roblox_oauth_url(ctx.author()?.id)?;
+        return ctx.respond(i18n::tr(ctx.core.config(), guild_id, "verify-oauth-link", &[
+            ("url", &url), ("prefix", ctx.prefix()),
+        ])?)
     }
+    verify_with(ctx, ctx.arg(0)?, &RobloxOAuthVerification, ctx.arg(1)?, |_| {
+        i18n::tr(ctx.core.config(), guild_id, "verify-oauth-wrong-account", &[])
+    })
+}
+
+fn do_verify_friend(ctx: &CommandContext, msg: &Message) -> Result<()> {
+    let guild_id = msg.guild_id()?;
+    let roblox_id = ctx.core.usernames().resolve_current(ctx.arg(0)?)?;
+    let secs = ctx.core.verifier().claim_friend_request(guild_id, msg.channel_id,
+                                                        msg.author.id, roblox_id)?;
+    let cookie = ctx.core.verifier().friend_verification_cookie()?;
+    let (_, bot_account) = RobloxSession { cookie: &cookie }.authenticated_user()?;
+    ctx.respond(i18n::tr(ctx.core.config(), Some(guild_id), "verify-friend-instructions", &[
+        ("bot_account", &bot_account),
+        ("duration", &i18n::tr_duration(ctx.core.config(), Some(guild_id), secs)?),
+    ])?)
+}
+
+fn do_verify_place_code(ctx: &CommandContext, msg: &Message) -> Result<()> {
+    let guild_id = msg.guild_id()?;
+    let (code, secs) = ctx.core.verifier().new_place_code(guild_id, msg.channel_id,
+                                                          msg.author.id)?;
+    ctx.respond(i18n::tr(ctx.core.config(), Some(guild_id), "verify-place-code-instructions", &[
+        ("code", &code),
+        ("duration", &i18n::tr_duration(ctx.core.config(), Some(guild_id), secs)?),
+    ])?)
 }
 
 fn check_configuration(ctx: &CommandContext, guild_id: GuildId) -> Result<()> {
@@ -127,11 +279,11 @@ fn check_configuration(ctx: &CommandContext, guild_id: GuildId) -> Result<()> {
     }
 }
 
-fn find_role(guild_id: GuildId, role_name: &str) -> Result<RoleId> {
+pub(super) fn find_role(guild_id: GuildId, role_name: &str) -> Result<RoleId> {
     let guild = guild_id.find()?;
     let guild = guild.read();
 
-    if let Some(captures) = MENTION_REGEX.captures(role_name) {
+    if let Some(captures) = ROLE_MENTION_REGEX.captures(role_name) {
         let role_id_str = captures.get(1)?.as_str();
         let role_id = RoleId(role_id_str.parse().to_cmd_err(|| "Role ID too large.")?);
         cmd_ensure!(guild.roles.contains_key(&role_id),
@@ -155,11 +307,223 @@ fn find_role(guild_id: GuildId, role_name: &str) -> Result<RoleId> {
     }
 }
 
+const SHOW_CONFIG_PAGE_SIZE: usize = 10;
+
+fn show_config(ctx: &CommandContext, guild_id: GuildId) -> Result<()> {
+    let config_map = ctx.core.roles().get_configuration(guild_id)?;
+    let mut role_names: Vec<&str> = config_map.keys().map(|x| x.as_str()).collect();
+    if role_names.is_empty() {
+        return ctx.respond("No roles are configured.")
+    }
+    role_names.sort();
+    let mut pages = Vec::new();
+    for page in role_names.chunks(SHOW_CONFIG_PAGE_SIZE) {
+        pages.push(ResponseEmbed {
+            title: "Role configuration".to_owned(),
+            description: show_config_page(guild_id, &config_map, page)?, fields: Vec::new(),
+        });
+    }
+    paginate(ctx, pages, 0)
+}
+fn show_config_page(
+    guild_id: GuildId, config_map: &HashMap<String, ConfiguredRole>, role_names: &[&str],
+) -> Result<String> {
+    let mut config = String::new();
+    for &role in role_names {
+        let role_data = &config_map[role];
+        let definition = role_data.custom_rule.as_ref()
+            .map(|x| format!("`{}`", x))
+            .unwrap_or_else(|| if VerificationRule::has_builtin(role) {
+                "*(builtin)*".to_string()
+            } else {
+                "**(does not exist)**".to_string()
+            });
+        writeln!(config, "• {} = {}", role, definition)?;
+        if let Some(role_id) = role_data.role_id {
+            let guild = guild_id.find()?;
+            let guild = guild.read();
+            match guild.roles.get(&role_id) {
+                Some(role) =>
+                    writeln!(config, "   Users matching this rule will be assigned **{}**.",
+                             role.name)?,
+                None =>
+                    writeln!(config, "   **A role with ID #{} was assigned to this rule, \
+                                         but it no longer exists!**", role_id)?,
+            };
+        }
+        let date: DateTime<Utc> = role_data.last_updated.into();
+        writeln!(config, "   *Last updated at {} UTC*", date.format("%Y-%m-%d %H:%M:%S"))?;
+    }
+    Ok(config)
+}
+// Checks that both the user and the bot are above a role. Bot owners may use any role the bot
+// can manage.
+pub(super) fn check_role_access(
+    guild_id: GuildId, user_id: UserId, role_id: RoleId, is_bot_owner: bool,
+) -> Result<()> {
+    cmd_ensure!(guild_id.find()?.read().roles.contains_key(&role_id),
+                "That role does not exist in this server.");
+    let me_member = guild_id.member(serenity::CACHE.read().user.id)?;
+    let sender_member = guild_id.member(user_id)?;
+    if !is_bot_owner {
+        if !util::can_member_access_role(&sender_member, role_id)? {
+            cmd_error!("You do not have permission to modify that role.")
+        }
+    }
+    if !util::can_member_access_role(&me_member, role_id)? {
+        cmd_error!("This bot does not have permission to modify that role.")
+    }
+    Ok(())
+}
+fn set_active_role(
+    ctx: &CommandContext, msg: &Message, guild_id: GuildId, rule_name: &str, role_id: RoleId,
+) -> Result<()> {
+    check_role_access(guild_id, msg.author.id, role_id,
+                      ctx.privilege_level >= PrivilegeLevel::BotOwner)?;
+    ctx.core.roles().set_active_role(guild_id, rule_name, Some(role_id))
+}
+fn test_roles(
+    ctx: &CommandContext, guild_id: GuildId, name: &str, roblox_id: RobloxUserID,
+) -> Result<()> {
+    let mut roles = String::new();
+    for role in ctx.core.roles().get_assigned_roles(guild_id, roblox_id)? {
+        writeln!(roles, "• {} {} **{}**",
+                 name,
+                 if role.is_assigned { "matches the rule" } else { "does not match the rule" },
+                 role.rule)?
+    }
+    if roles.is_empty() {
+        ctx.respond("No roles are configured.")
+    } else {
+        ctx.respond(roles.trim())
+    }
+}
+fn do_rule(ctx: &CommandContext, msg: &Message) -> Result<()> {
+    let guild_id = msg.guild_id()?;
+    match ctx.arg(0)? {
+        "set" => {
+            let bind = ctx.arg_opt(1) == Some("--bind");
+            let start = if bind { 2 } else { 1 };
+            let rule_name = ctx.arg(start)?;
+            let definition = ctx.rest(start + 1)?.trim();
+            cmd_ensure!(!definition.is_empty(),
+                        "Usage: {}rule set [--bind] <role name> <rule expression>", ctx.prefix());
+            ctx.core.roles().set_custom_rule(guild_id, rule_name, Some(definition))?;
+            if bind {
+                let role_id = find_role(guild_id, rule_name)?;
+                set_active_role(ctx, msg, guild_id, rule_name, role_id)?;
+            }
+            check_configuration(ctx, guild_id)
+        }
+        "test" => {
+            let target = ctx.arg(1)?;
+            match parse_discord_user(target)? {
+                Some(discord_id) => {
+                    let roblox_id = ctx.core.verifier().get_verified_roblox_user(discord_id)?
+                        .to_cmd_err(|| "That user has not verified a Roblox account.")?;
+                    let name = roblox_id.lookup_username()?;
+                    test_roles(ctx, guild_id, &name, roblox_id)
+                }
+                None => {
+                    let roblox_id = ctx.core.usernames().resolve(target)?;
+                    test_roles(ctx, guild_id, target, roblox_id)
+                }
+            }
+        }
+        "list" => show_config(ctx, guild_id),
+        _ => cmd_error!("Unknown subcommand. Usage: {0}rule set [--bind] <role name> \
+                         <rule expression>, {0}rule test <user>, {0}rule list", ctx.prefix()),
+    }
+}
+
+fn parse_rank(rank: &str) -> Result<u32> {
+    let rank = rank.parse::<u32>().ok().to_cmd_err(|| format!("Invalid group rank: {}", rank))?;
+    cmd_ensure!(rank <= 255, "Group ranks must be between 0 and 255.");
+    Ok(rank)
+}
+// Parses a group binding in the format used by other verification bots, i.e. `<group id>`,
+// `<group id>:<rank>`, `<group id>:<min rank>-<max rank>` or `<group id>:<min rank>+`, and
+// returns the name and expression of the equivalent rule.
+pub(super) fn parse_group_binding(binding: &str) -> Result<(String, String)> {
+    let (group, ranks) = match binding.find(':') {
+        Some(i) => (&binding[..i], Some(&binding[i + 1..])),
+        None => (binding, None),
+    };
+    let group = group.parse::<u64>().ok()
+        .to_cmd_err(|| format!("Group ID is not a number: {}", group))?;
+    Ok(match ranks {
+        None => (format!("group_{}", group), format!("group({})", group)),
+        Some(ranks) if ranks.ends_with('+') => {
+            let min = parse_rank(&ranks[..ranks.len() - 1])?;
+            (format!("group_{}_{}_up", group, min), format!("group({}, {}+)", group, min))
+        }
+        Some(ranks) => match ranks.find('-') {
+            Some(i) => {
+                let (min, max) = (parse_rank(&ranks[..i])?, parse_rank(&ranks[i + 1..])?);
+                cmd_ensure!(min <= max, "The minimum rank cannot be higher than the maximum rank.");
+                (format!("group_{}_{}_{}", group, min, max),
+                 format!("group({0}, {1}+) and group({0}, {2}-)", group, min, max))
+            }
+            None => {
+                let rank = parse_rank(ranks)?;
+                (format!("group_{}_{}", group, rank), format!("group({}, {})", group, rank))
+            }
+        },
+    })
+}
+
+const WHOIS_MAX_GROUPS: usize = 10;
+fn format_date(time: SystemTime) -> String {
+    let date: DateTime<Utc> = time.into();
+    date.format("%Y-%m-%d").to_string()
+}
 fn whois_msg(
     ctx: &CommandContext, user: User, roblox_id: RobloxUserID, roblox_name: &str
 ) -> Result<()> {
-    ctx.respond(format!("{} is verified as {} (https://www.roblox.com/users/{}/profile)",
-                        user.tag(), roblox_name, roblox_id.0))
+    let profile = format!("https://www.roblox.com/users/{}/profile", roblox_id.0);
+    match ctx.discord_context() {
+        Some((_, msg)) => {
+            let verified_at = ctx.core.verifier().get_verification_time(user.id)?;
+            let past_names: Vec<String> = ctx.core.usernames().get_history(roblox_id)?
+                .into_iter().map(|x| x.0).collect();
+            let roblox_created = roblox_id.creation_time()?;
+            let groups = roblox_id.get_group_ranks()?;
+            let mut group_list = String::new();
+            for group in groups.iter().take(WHOIS_MAX_GROUPS) {
+                writeln!(group_list, "{}: {} ({})", group.group_name, group.rank_name, group.rank)?;
+            }
+            if groups.len() > WHOIS_MAX_GROUPS {
+                writeln!(group_list, "*...and {} more.*", groups.len() - WHOIS_MAX_GROUPS)?;
+            }
+            if group_list.is_empty() {
+                group_list.push_str("*(none)*");
+            }
+
+            let headshot = roblox_id.avatar_headshot_url();
+            let theme = EmbedTheme::load(ctx.core.config(), ctx.get_guild()?)?;
+
+            msg.channel_id.send_message(|m| m.embed(|e| {
+                let e = e.title(format!("{} is verified as {}", user.tag(), roblox_name))
+                    .url(&profile)
+                    .author(|a| a.name(&user.tag()).icon_url(&user.face()))
+                    .field("Discord account created", user.id.created_at().format("%Y-%m-%d"),
+                           true)
+                    .field("Roblox account created", format_date(roblox_created), true);
+                let e = match verified_at {
+                    Some(verified_at) => e.field("Verified on", format_date(verified_at), true),
+                    None => e,
+                };
+                let e = if past_names.is_empty() {
+                    e
+                } else {
+                    e.field("Previous usernames", past_names.join(", "), false)
+                };
+                theme.apply(e.field("Groups", group_list, false), Some(&headshot))
+            }))?;
+            Ok(())
+        }
+        None => ctx.respond(format!("{} is verified as {} ({})", user.tag(), roblox_name, profile)),
+    }
 }
 fn whois_discord(ctx: &CommandContext, discord_user_id: UserId) -> Result<()> {
     let user = discord_user_id.get().map_err(Error::from)
@@ -179,7 +543,7 @@ fn whois_discord(ctx: &CommandContext, discord_user_id: UserId) -> Result<()> {
     }
 }
 fn whois_roblox(ctx: &CommandContext, roblox_name: &str) -> Result<()> {
-    let roblox_user_id = RobloxUserID::for_username(roblox_name)
+    let roblox_user_id = ctx.core.usernames().resolve(roblox_name)
         .to_cmd_err(|| format!("No such Roblox user '{}' exists.", roblox_name))?;
     let discord_user_id = ctx.core.verifier().get_verified_discord_user(roblox_user_id)?;
     if let Some(discord_user_id) = discord_user_id {
@@ -192,19 +556,269 @@ fn whois_roblox(ctx: &CommandContext, roblox_name: &str) -> Result<()> {
                    roblox_name, roblox_user_id.0)
     }
 }
+const COVERAGE_TREND_DAYS: u32 = 30;
+// Embeds can have at most 25 fields, so only the highest roles are shown.
+const COVERAGE_MAX_ROLES: usize = 20;
+const SPARKLINE_BLOCKS: &[char] = &['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+fn percent(part: usize, total: usize) -> usize {
+    if total == 0 { 0 } else { part * 100 / total }
+}
+// Draws a small bar chart of some values with block characters.
+fn sparkline(values: &[u64]) -> String {
+    let max = values.iter().cloned().max().unwrap_or(0);
+    values.iter().map(|&x| if max == 0 {
+        SPARKLINE_BLOCKS[0]
+    } else {
+        SPARKLINE_BLOCKS[(x * (SPARKLINE_BLOCKS.len() as u64 - 1) / max) as usize]
+    }).collect()
+}
+
+fn coverage_report(core: &VerifierCore, guild_id: GuildId) -> Result<ResponseEmbed> {
+    let (members, mut roles) = {
+        let guild = guild_id.find()?;
+        let guild = guild.read();
+        let members: Vec<(UserId, Vec<RoleId>)> = guild.members.values()
+            .filter(|x| !x.user.read().bot)
+            .map(|x| (x.user.read().id, x.roles.clone())).collect();
+        // The @everyone role has the same ID as the server.
+        let roles: Vec<_> = guild.roles.values().filter(|x| x.id.0 != guild_id.0)
+            .map(|x| (x.position, x.id, x.name.clone())).collect();
+        (members, roles)
+    };
+    roles.sort_by(|a, b| b.0.cmp(&a.0));
+
+    let mut verified_members = 0;
+    let mut role_counts = HashMap::new();
+    for &(user_id, ref member_roles) in &members {
+        let is_verified = core.verifier().get_verified_roblox_user(user_id)?.is_some();
+        if is_verified {
+            verified_members += 1;
+        }
+        for &role_id in member_roles {
+            let counts = role_counts.entry(role_id).or_insert((0, 0));
+            counts.1 += 1;
+            if is_verified {
+                counts.0 += 1;
+            }
+        }
+    }
+
+    let mut embed = ResponseEmbed {
+        title: "Verification coverage".to_owned(),
+        description: format!("**{}** of **{}** members are verified ({}%).",
+                             verified_members, members.len(),
+                             percent(verified_members, members.len())),
+        fields: Vec::new(),
+    };
+    let roles: Vec<_> = roles.into_iter()
+        .filter_map(|(_, id, name)| role_counts.get(&id).map(|&counts| (name, counts)))
+        .collect();
+    for &(ref name, (verified, total)) in roles.iter().take(COVERAGE_MAX_ROLES) {
+        embed.fields.push((name.clone(), format!("{}/{} verified ({}%)",
+                                                 verified, total, percent(verified, total)),
+                           true));
+    }
+    if roles.len() > COVERAGE_MAX_ROLES {
+        embed.description.push_str(&format!("\nOnly the {} highest roles are shown.",
+                                            COVERAGE_MAX_ROLES));
+    }
+
+    let daily = core.stats().daily_totals(Some(guild_id), COVERAGE_TREND_DAYS)?;
+    let verifications: Vec<u64> = daily.iter().map(|x| x.verifications).collect();
+    let days = verifications.len();
+    let this_week: u64 = verifications[days - 7..].iter().sum();
+    let last_week: u64 = verifications[days - 14..days - 7].iter().sum();
+    let total: u64 = verifications.iter().sum();
+    let failed: u64 = daily.iter().map(|x| x.failed_attempts).sum();
+    embed.fields.push((
+        format!("Verifications on this server, last {} days", COVERAGE_TREND_DAYS),
+        format!("`{}`\n{} in total, {} in the last 7 days and {} in the 7 days before. \
+                 {} attempts failed.",
+                sparkline(&verifications), total, this_week, last_week, failed),
+        false,
+    ));
+    Ok(embed)
+}
+
+fn parse_blacklist_target(target: &str) -> Result<BlacklistTarget> {
+    if target.starts_with("group:") {
+        let group_id = &target["group:".len()..];
+        Ok(BlacklistTarget::Group(
+            group_id.parse().to_cmd_err(|| format!("Group ID is not a number: {}", group_id))?
+        ))
+    } else {
+        Ok(BlacklistTarget::User(RobloxUserID::for_username(target)?))
+    }
+}
+fn show_blacklist_target(target: BlacklistTarget) -> Result<String> {
+    Ok(match target {
+        BlacklistTarget::User(roblox_id) => match roblox_id.lookup_username_opt()? {
+            Some(name) => format!("Roblox user {} (ID #{})", name, roblox_id.0),
+            None => format!("Roblox user ID #{}", roblox_id.0),
+        },
+        BlacklistTarget::Group(group_id) => format!("Roblox group #{}", group_id),
+    })
+}
+pub(super) fn parse_discord_user(target: &str) -> Result<Option<UserId>> {
+    if let Some(captures) = MENTION_REGEX.captures(target) {
+        let user_id_str = captures.get(1)?.as_str();
+        Ok(Some(UserId(user_id_str.parse().to_cmd_err(|| "User ID too large.")?)))
+    } else if SNOWFLAKE_REGEX.is_match(target) {
+        Ok(Some(UserId(target.parse().to_cmd_err(|| "User ID too large.")?)))
+    } else {
+        Ok(None)
+    }
+}
 fn do_whois(ctx: &CommandContext) -> Result<()> {
     let target_name = ctx.arg(0)?;
-    if let Some(captures) = MENTION_REGEX.captures(target_name) {
-        let user_id_str = captures.get(1)?.as_str();
-        let user_id = UserId(user_id_str.parse().to_cmd_err(|| "User ID too large.")?);
-        whois_discord(ctx, user_id)
-    } else if SNOWFLAKE_REGEX.is_match(target_name) {
-        let user_id = UserId(target_name.parse().to_cmd_err(|| "User ID too large.")?);
-        whois_discord(ctx, user_id)
+    if target_name.starts_with("roblox:") {
+        return whois_roblox(ctx, &target_name["roblox:".len()..])
+    }
+    match parse_discord_user(target_name)? {
+        Some(user_id) => whois_discord(ctx, user_id),
+        None => whois_roblox(ctx, target_name),
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+enum DryRun {
+    No, Report, Csv,
+}
+
+// Removes a trailing `--dry-run` or `--dry-run csv` from a command's arguments.
+fn strip_dry_run(args: &str) -> (&str, DryRun) {
+    let args = args.trim();
+    for &(flag, dry_run) in &[("--dry-run csv", DryRun::Csv), ("--dry-run", DryRun::Report)] {
+        if args == flag {
+            return ("", dry_run)
+        } else if args.ends_with(flag) && args[..args.len() - flag.len()].ends_with(' ') {
+            return (args[..args.len() - flag.len()].trim(), dry_run)
+        }
+    }
+    (args, DryRun::No)
+}
+
+const DRY_RUN_PAGE_SIZE: usize = 15;
+const DRY_RUN_CSV_NAME: &str = "dry-run.csv";
+
+fn role_names(guild_id: GuildId, roles: &[RoleId]) -> Vec<String> {
+    let guild = guild_id.find();
+    roles.iter().map(|id| {
+        guild.as_ref().and_then(|x| x.read().roles.get(id).map(|x| x.name.clone()))
+            .unwrap_or_else(|| id.0.to_string())
+    }).collect()
+}
+fn describe_change(guild_id: GuildId, change: &PlannedRoleChange) -> String {
+    let mut parts = Vec::new();
+    if !change.added.is_empty() {
+        parts.push(format!("gains {}", role_names(guild_id, &change.added).join(", ")));
+    }
+    if !change.removed.is_empty() {
+        parts.push(format!("loses {}", role_names(guild_id, &change.removed).join(", ")));
+    }
+    match change.nickname {
+        Some(ref nickname) if nickname.is_empty() => parts.push("nickname reset".to_owned()),
+        Some(ref nickname) => parts.push(format!("nickname set to {}", nickname)),
+        None => { }
+    }
+    format!("• {}: {}", get_discord_username(change.user_id), parts.join("; "))
+}
+fn csv_field(field: &str) -> String {
+    if field.contains(|c: char| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", field.replace('"', "\"\""))
     } else {
-        whois_roblox(ctx, target_name)
+        field.to_owned()
     }
 }
+fn dry_run_csv(guild_id: GuildId, changes: &[PlannedRoleChange]) -> Result<String> {
+    let mut csv = "discord_user_id,username,added_roles,removed_roles,nickname\n".to_owned();
+    for change in changes {
+        writeln!(csv, "{},{},{},{},{}",
+                 change.user_id.0, csv_field(&get_discord_username(change.user_id)),
+                 csv_field(&role_names(guild_id, &change.added).join(";")),
+                 csv_field(&role_names(guild_id, &change.removed).join(";")),
+                 csv_field(change.nickname.as_ref().map_or("", |x| x.as_str())))?;
+    }
+    Ok(csv)
+}
+
+// Shows which members' roles and nicknames would change after making `changes` to the role
+// configuration and updating everyone, without changing anything.
+fn dry_run_report(
+    ctx: &CommandContext, msg: &Message, guild_id: GuildId, changes: &[RuleChange],
+    dry_run: DryRun,
+) -> Result<()> {
+    let mut status_msg = msg.channel_id.send_message(|m|
+        m.content("Checking which roles would change...")
+    )?;
+    let preview = ctx.core.roles().preview_mass_update(guild_id, changes, |done, total| {
+        status_msg.edit(|m| m.content(format!(
+            "Checking which roles would change... ({}/{})", done, total
+        )))?;
+        Ok(())
+    })?;
+    status_msg.edit(|m| m.content(format!(
+        "**Dry run:** {} of {} verified members would have their roles or nickname changed. \
+         Nothing has been changed.{}", preview.changes.len(), preview.checked,
+        if preview.failed != 0 {
+            format!(" {} members could not be checked.", preview.failed)
+        } else {
+            String::new()
+        }
+    )))?;
+    if preview.changes.is_empty() {
+        return Ok(())
+    }
+
+    if dry_run == DryRun::Csv {
+        let csv = dry_run_csv(guild_id, &preview.changes)?;
+        msg.channel_id.send_files(vec![(csv.as_bytes(), DRY_RUN_CSV_NAME)], |m| m)?;
+        return Ok(())
+    }
+    let mut pages = Vec::new();
+    for page in preview.changes.chunks(DRY_RUN_PAGE_SIZE) {
+        let mut report = String::new();
+        for change in page {
+            writeln!(report, "{}", describe_change(guild_id, change))?;
+        }
+        pages.push(ResponseEmbed {
+            title: "Changes from a dry run".to_owned(), description: report, fields: Vec::new(),
+        });
+    }
+    paginate(ctx, pages, 0)
+}
+
+// Starts updating the roles of every verified member of a server, reporting progress by editing
+// a status message as each batch finishes.
+pub fn update_all_members(ctx: &CommandContext, msg: &Message, guild_id: GuildId) -> Result<()> {
+    let status_msg = msg.channel_id.send_message(|m|
+        m.content("Updating the roles of all verified members...")
+    )?;
+    let mut progress_msg = status_msg.clone();
+    let mut finished_msg = status_msg.clone();
+    let started = ctx.core.roles().mass_update(guild_id, move |done, total| {
+        progress_msg.edit(|m| m.content(format!(
+            "Updating the roles of all verified members... ({}/{})", done, total
+        )))?;
+        Ok(())
+    }, move |status| {
+        finished_msg.edit(|m| m.content(format!(
+            "Updated the roles of {} verified members.{}", status.updated,
+            if status.failed != 0 {
+                format!(" {} members could not be updated.", status.failed)
+            } else {
+                String::new()
+            }
+        )))?;
+        Ok(())
+    });
+    if started.is_err() {
+        status_msg.delete().ok();
+    }
+    started?;
+    Ok(())
+}
 
 fn maybe_sprunge(ctx: &CommandContext, text: &str) -> Result<()> {
     if text.chars().count() < 1900 {
@@ -219,81 +833,59 @@ pub const COMMANDS: &[Command] = &[
     Command::new("show_config")
         .help(None, "Shows the role configuration for the current channel.")
         .required_permissions(enum_set!(DiscordPermission::ManageRoles))
+        .permission("verifier.roles.show_config")
         .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
         .exec_discord(|ctx, _, msg| {
-            let guild_id = msg.guild_id()?;
-
-            let mut config = String::new();
-            let config_map = ctx.core.roles().get_configuration(guild_id)?;
-            let mut role_names: Vec<&str> = config_map.keys().map(|x| x.as_str()).collect();
-            role_names.sort();
-            for role in role_names {
-                let role_data = &config_map[role];
-                let definition = role_data.custom_rule.as_ref()
-                    .map(|x| format!("`{}`", x))
-                    .unwrap_or_else(|| if VerificationRule::has_builtin(role) {
-                        "*(builtin)*".to_string()
-                    } else {
-                        "**(does not exist)**".to_string()
-                    });
-                writeln!(config, "• {} = {}", role, definition)?;
-                if let Some(role_id) = role_data.role_id {
-                    let guild = guild_id.find()?;
-                    let guild = guild.read();
-                    match guild.roles.get(&role_id) {
-                        Some(role) =>
-                            writeln!(config, "   Users matching this rule will be assigned **{}**.",
-                                     role.name)?,
-                        None =>
-                            writeln!(config, "   **A role with ID #{} was assigned to this rule, \
-                                                 but it no longer exists!**", role_id)?,
-                    };
-                }
-                let date: DateTime<Utc> = role_data.last_updated.into();
-                writeln!(config, "   *Last updated at {} UTC*", date.format("%Y-%m-%d %H:%M:%S"))?;
-            }
-            if config.is_empty() {
-                ctx.respond("No roles are configured.")
-            } else {
-                ctx.respond(config.trim())
-            }
+            show_config(ctx, msg.guild_id()?)
         }),
     Command::new("set_role")
-        .help(Some("<rule name> [discord role name]"),
-              "Sets the Discord role the bot will set when a rule is matched.")
+        .help(Some("<rule name> [discord role name] [--dry-run [csv]]"),
+              "Sets the Discord role the bot will set when a rule is matched. With `--dry-run`, \
+               shows which members would gain or lose roles instead.")
         .required_permissions(enum_set!(DiscordPermission::ManageRoles))
+        .permission("verifier.roles.set_role")
         .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
         .exec_discord(|ctx, _, msg| {
             let rule_name = ctx.arg(0)?;
-            let role_name = ctx.rest(1)?.trim();
+            let (role_name, dry_run) = strip_dry_run(ctx.rest(1)?);
             let guild_id = msg.guild_id()?;
-            let me_member = guild_id.member(serenity::CACHE.read().user.id)?;
-            let sender_member = guild_id.member(msg.author.id)?;
+            if dry_run != DryRun::No {
+                let role_id = if !role_name.is_empty() {
+                    let role_id = find_role(guild_id, role_name)?;
+                    check_role_access(guild_id, msg.author.id, role_id,
+                                      ctx.privilege_level >= PrivilegeLevel::BotOwner)?;
+                    Some(role_id)
+                } else {
+                    None
+                };
+                let changes = [RuleChange::SetRole(rule_name.to_owned(), role_id)];
+                return dry_run_report(ctx, msg, guild_id, &changes, dry_run)
+            }
             if !role_name.is_empty() {
                 let role_id = find_role(guild_id, role_name)?;
-                if ctx.privilege_level < PrivilegeLevel::BotOwner {
-                    if !util::can_member_access_role(&sender_member, role_id)? {
-                        cmd_error!("You do not have permission to modify that role.")
-                    }
-                }
-                if !util::can_member_access_role(&me_member, role_id)? {
-                    cmd_error!("This bot does not have permission to modify that role.")
-                }
-                ctx.core.roles().set_active_role(guild_id, rule_name, Some(role_id))?;
+                set_active_role(ctx, msg, guild_id, rule_name, role_id)?;
             } else {
                 ctx.core.roles().set_active_role(guild_id, rule_name, None)?;
             }
             check_configuration(ctx, guild_id)
         }),
     Command::new("set_custom_rule")
-        .help(Some("<rule name> [rule definition]"),
-              "Defines a custom rule for setting roles.")
+        .help(Some("<rule name> [rule definition] [--dry-run [csv]]"),
+              "Defines a custom rule for setting roles. With `--dry-run`, shows which members \
+               would gain or lose roles instead.")
         .required_permissions(enum_set!(DiscordPermission::ManageRoles))
+        .permission("verifier.roles.set_custom_rule")
         .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
         .exec_discord(|ctx, _, msg| {
             let rule_name = ctx.arg(0)?;
-            let definition = ctx.rest(1)?.trim();
+            let (definition, dry_run) = strip_dry_run(ctx.rest(1)?);
             let guild_id = msg.guild_id()?;
+            if dry_run != DryRun::No {
+                let definition = if definition.is_empty() { None } else { Some(definition) };
+                let changes = [RuleChange::SetCustomRule(rule_name.to_owned(),
+                                                         definition.map(|x| x.to_owned()))];
+                return dry_run_report(ctx, msg, guild_id, &changes, dry_run)
+            }
             if !definition.is_empty() {
                 ctx.core.roles().set_custom_rule(guild_id, rule_name, Some(definition))?;
             } else {
@@ -301,62 +893,516 @@ pub const COMMANDS: &[Command] = &[
             }
             check_configuration(ctx, guild_id)
         }),
-    Command::new("test_verify")
-        .help(Some("<roblox username>"), "Tests the results of your role configuration.")
+    Command::new("bind")
+        .help(Some("group <group id>[:<rank>|:<min rank>-<max rank>|:<min rank>+] \
+                    <discord role> [--dry-run [csv]]"),
+              "Gives a role to the members of a Roblox group, optionally only those with \
+               certain ranks, e.g. `bind group 12345:100-254 @Officers`. This creates a rule \
+               that can be viewed with `rule list`. With `--dry-run`, shows which members would \
+               gain or lose roles instead.")
         .required_permissions(enum_set!(DiscordPermission::ManageRoles))
+        .permission("verifier.roles.bind")
         .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
         .exec_discord(|ctx, _, msg| {
-            let roblox_username = ctx.arg(0)?;
-            let roblox_id = RobloxUserID::for_username(roblox_username)?;
             let guild_id = msg.guild_id()?;
-
-            let mut roles = String::new();
-            for role in ctx.core.roles().get_assigned_roles(guild_id, roblox_id)? {
-                writeln!(roles, "• {} {} **{}**",
-                         roblox_username,
-                         if role.is_assigned { "matches the rule" }
-                             else { "does not match the rule" },
-                         role.rule)?
+            let (role_name, dry_run) = strip_dry_run(ctx.rest(2)?);
+            cmd_ensure!(ctx.arg(0)? == "group" && !role_name.is_empty(),
+                        "Usage: {}bind group <group id>[:<ranks>] <discord role>", ctx.prefix());
+            let (rule_name, definition) = parse_group_binding(ctx.arg(1)?)?;
+            let role_id = find_role(guild_id, role_name)?;
+            if dry_run != DryRun::No {
+                check_role_access(guild_id, msg.author.id, role_id,
+                                  ctx.privilege_level >= PrivilegeLevel::BotOwner)?;
+                let changes = [RuleChange::SetCustomRule(rule_name.clone(), Some(definition)),
+                               RuleChange::SetRole(rule_name, Some(role_id))];
+                return dry_run_report(ctx, msg, guild_id, &changes, dry_run)
             }
-            if roles.is_empty() {
-                ctx.respond("No roles are configured.")
-            } else {
-                ctx.respond(roles.trim())
+            ctx.core.roles().set_custom_rule(guild_id, &rule_name, Some(&definition))?;
+            set_active_role(ctx, msg, guild_id, &rule_name, role_id)?;
+            check_configuration(ctx, guild_id)
+        }),
+    Command::new("unbind")
+        .help(Some("group <group id>[:<rank>|:<min rank>-<max rank>|:<min rank>+] \
+                    [--dry-run [csv]]"),
+              "Removes a group binding created with `bind`. With `--dry-run`, shows which \
+               members would lose roles instead.")
+        .required_permissions(enum_set!(DiscordPermission::ManageRoles))
+        .permission("verifier.roles.unbind")
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
+        .exec_discord(|ctx, _, msg| {
+            let guild_id = msg.guild_id()?;
+            let (binding, dry_run) = strip_dry_run(ctx.rest(1)?);
+            cmd_ensure!(ctx.arg(0)? == "group" && !binding.is_empty(),
+                        "Usage: {}unbind group <group id>[:<ranks>]", ctx.prefix());
+            let (rule_name, _) = parse_group_binding(binding)?;
+            if dry_run != DryRun::No {
+                let changes = [RuleChange::SetRole(rule_name.clone(), None),
+                               RuleChange::SetCustomRule(rule_name, None)];
+                return dry_run_report(ctx, msg, guild_id, &changes, dry_run)
             }
+            ctx.core.roles().set_active_role(guild_id, &rule_name, None)?;
+            ctx.core.roles().set_custom_rule(guild_id, &rule_name, None)?;
+            check_configuration(ctx, guild_id)
         }),
-    Command::new("update")
-        .help(None, "Updates your roles according to your Roblox account.")
+    Command::new("test_verify")
+        .help(Some("<roblox username>"), "Tests the results of your role configuration.")
+        .required_permissions(enum_set!(DiscordPermission::ManageRoles))
+        .permission("verifier.roles.test_verify")
+        .cooldown(5)
         .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
         .exec_discord(|ctx, _, msg| {
-            cmd_ensure!(ctx.core.verifier().get_verified_roblox_user(msg.author.id)?.is_some(),
-                        "You are not verified with this bot. {}",
-                        ctx.core.verify_channel().verify_instructions()?);
+            let roblox_username = ctx.arg(0)?;
+            let roblox_id = ctx.core.usernames().resolve(roblox_username)?;
+            test_roles(ctx, msg.guild_id()?, roblox_username, roblox_id)
+        }),
+    Command::new("rule")
+        .help(Some("<set|test|list> ..."),
+              "Manages role rules. `rule set <role name> <rule expression>` defines a rule, \
+               such as `group(12345) >= 100 and player_badge(678)`. With `--bind` before the \
+               name, the role with the same name is also given to users matching the rule. \
+               `rule test <user>` shows which rules a Discord user or Roblox username matches. \
+               `rule list` shows all rules.")
+        .required_permissions(enum_set!(DiscordPermission::ManageRoles))
+        .permission("verifier.roles.rule")
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
+        .exec_discord(|ctx, _, msg| do_rule(ctx, msg)),
+    Command::new("update")
+        .help(Some("[discord mention or user id]"),
+              "Updates your roles and nickname according to your Roblox account. Users with the \
+               Manage Roles permission may update other users.")
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
+        .exec(|ctx| {
+            if let Some(target) = ctx.arg_opt(0) {
+                cmd_ensure!(ctx.has_discord_permissions(enum_set!(DiscordPermission::ManageRoles)),
+                            "You do not have permission to update other users.");
+                let user_id = parse_discord_user(target)?
+                    .to_cmd_err(|| "Please mention the user you want to update.")?;
+                let guild_id = ctx.require_guild()?;
+                let username = get_discord_username(user_id);
+                match ctx.core.roles().update_user(guild_id, user_id, true)? {
+                    SetRolesStatus::Success =>
+                        ctx.respond(format!("{}'s roles have been updated.", username))?,
+                    SetRolesStatus::IsAdmin =>
+                        ctx.respond(format!("{}'s roles have been updated. Their nickname was not \
+                                             changed as this bot does not have permission to \
+                                             edit it.", username))?,
+                    SetRolesStatus::NotSet =>
+                        ctx.respond(format!("{} has not verified a Roblox account, so their roles \
+                                             were not changed.", username))?,
+                    SetRolesStatus::PendingApproval =>
+                        ctx.respond(format!("{}'s verification is waiting for approval by a \
+                                             moderator, so they were not given verified roles \
+                                             yet.", username))?,
+                    SetRolesStatus::ApprovalRejected =>
+                        ctx.respond(format!("{}'s verification was rejected by a moderator, so \
+                                             they were not given verified roles.", username))?,
+                }
+                return Ok(())
+            }
+
+            let user_id = ctx.author()?.id;
+            let guild_id = ctx.require_guild()?;
+            cmd_ensure!(ctx.core.verifier().get_verified_roblox_user(user_id)?.is_some(),
+                        "{} {}",
+                        i18n::tr(ctx.core.config(), Some(guild_id), "verify-not-verified", &[])?,
+                        ctx.core.verify_channel().verify_instructions(Some(guild_id))?);
 
-            let guild_id = msg.guild_id()?;
             let cooldown = max(
                 ctx.core.config().get(None, ConfigKeys::MinimumUpdateCooldownSeconds)?,
                 ctx.core.config().get(Some(guild_id), ConfigKeys::UpdateCooldownSeconds)?,
             );
-            ctx.core.roles().update_user_with_cooldown(
-                guild_id, msg.author.id, cooldown, true, false,
-            )?;
-            ctx.respond("Your roles have been updated.")?;
+            let key = match ctx.core.roles().update_user_with_cooldown(
+                guild_id, user_id, cooldown, true, false,
+            )? {
+                SetRolesStatus::PendingApproval => "verify-pending-approval",
+                SetRolesStatus::ApprovalRejected => "verify-approval-rejected",
+                _ => "update-success",
+            };
+            ctx.respond(i18n::tr(ctx.core.config(), Some(guild_id), key, &[])?)?;
             Ok(())
         }),
+    Command::new("update_all")
+        .help(Some("[--dry-run [csv]]"),
+              "Updates the roles and nicknames of every verified member of this server. With \
+               `--dry-run`, shows which members would be changed instead, or with \
+               `--dry-run csv`, sends the changes as a spreadsheet.")
+        .required_permissions(enum_set!(DiscordPermission::ManageGuild |
+                                        DiscordPermission::ManageRoles))
+        .permission("verifier.admin.update_all")
+        .cooldown(60 * 5)
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
+        .exec_discord(|ctx, _, msg| {
+            let guild_id = msg.guild_id()?;
+            match strip_dry_run(ctx.rest(0)?) {
+                ("", DryRun::No) => { }
+                ("", dry_run) => return dry_run_report(ctx, msg, guild_id, &[], dry_run),
+                _ => cmd_error!("Usage: {}update_all [--dry-run [csv]]", ctx.prefix()),
+            }
+            update_all_members(ctx, msg, guild_id)
+        }),
+    Command::new("stats")
+        .help(None, "Shows verification statistics for this server and how many of its members \
+                     are verified. On the terminal, shows statistics for every server.")
+        .required_permissions(enum_set!(DiscordPermission::ManageGuild))
+        .permission("verifier.admin.stats")
+        .allowed_contexts(enum_set!(CommandTarget::Terminal | CommandTarget::ServerMessage))
+        .exec(|ctx| {
+            let stats = ctx.core.stats();
+            let guild_id = ctx.get_guild()?;
+            let periods = [
+                ("Today", stats.totals(guild_id, Some(1))?),
+                ("Last 7 days", stats.totals(guild_id, Some(7))?),
+                ("Last 30 days", stats.totals(guild_id, Some(30))?),
+                ("All time", stats.totals(guild_id, None)?),
+            ];
+            let mut text = String::new();
+            for &(name, totals) in &periods {
+                writeln!(text, "**{}:** {} verifications, {} failed attempts, {} cooldown hits",
+                         name, totals.verifications, totals.failed_attempts,
+                         totals.cooldown_hits)?;
+            }
+            if let Some(guild_id) = guild_id {
+                let (verified, unverified) = ctx.core.verifier().count_verified_members(guild_id)?;
+                writeln!(text, "This server has {} verified and {} unverified members.",
+                         verified, unverified)?;
+            }
+            ctx.respond(text)
+        }),
+    Command::new("coverage")
+        .help(None, "Shows how many members of this server are verified, in total and for each \
+                     role, and how verifications have trended over the last 30 days.")
+        .required_permissions(enum_set!(DiscordPermission::ManageGuild))
+        .permission("verifier.admin.stats")
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
+        .cooldown(10)
+        .exec(|ctx| {
+            let guild_id = ctx.require_guild()?;
+            ctx.respond_embed(&coverage_report(ctx.core, guild_id)?)
+        }),
+    Command::new("blacklist")
+        .help(Some("<roblox username or group:<group id>> [reason]"),
+              "Prevents a Roblox account or the members of a Roblox group from verifying on \
+               this server.")
+        .required_permissions(enum_set!(DiscordPermission::ManageRoles))
+        .permission("verifier.blacklist.add")
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
+        .exec_discord(|ctx, _, msg| {
+            let target = parse_blacklist_target(ctx.arg(0)?)?;
+            let reason = match ctx.rest_opt(1) {
+                Some(reason) if !reason.is_empty() => Some(reason),
+                _ => None,
+            };
+            ctx.core.blacklist().add(msg.guild_id()?, target, reason)?;
+            ctx.respond(format!("{} has been blacklisted.", show_blacklist_target(target)?))
+        }),
+    Command::new("unblacklist")
+        .help(Some("<roblox username or group:<group id>>"),
+              "Removes a Roblox account or group from this server's blacklist.")
+        .required_permissions(enum_set!(DiscordPermission::ManageRoles))
+        .permission("verifier.blacklist.remove")
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
+        .exec_discord(|ctx, _, msg| {
+            let target = parse_blacklist_target(ctx.arg(0)?)?;
+            cmd_ensure!(ctx.core.blacklist().remove(msg.guild_id()?, target)?,
+                        "{} is not blacklisted.", show_blacklist_target(target)?);
+            ctx.respond(format!("{} is no longer blacklisted.", show_blacklist_target(target)?))
+        }),
+    Command::new("show_blacklist")
+        .help(None, "Lists the Roblox accounts and groups blacklisted on this server.")
+        .required_permissions(enum_set!(DiscordPermission::ManageRoles))
+        .permission("verifier.blacklist.show")
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
+        .exec_discord(|ctx, _, msg| {
+            let entries = ctx.core.blacklist().get_entries(msg.guild_id()?)?;
+            let mut list = String::new();
+            for entry in entries.iter() {
+                match entry.reason {
+                    Some(ref reason) =>
+                        writeln!(list, "• {} *({})*", show_blacklist_target(entry.target)?,
+                                 reason)?,
+                    None => writeln!(list, "• {}", show_blacklist_target(entry.target)?)?,
+                }
+            }
+            if list.is_empty() {
+                ctx.respond("No Roblox accounts or groups are blacklisted.")
+            } else {
+                ctx.respond(list)
+            }
+        }),
+    Command::new("trust")
+        .help(Some("[add|remove <server id>]"),
+              "Manages the servers this server trusts. Users who verified in a trusted server \
+               are given roles when they join this one, as long as that server trusts this one \
+               too. Without arguments, lists the trusted servers.")
+        .required_permissions(enum_set!(DiscordPermission::ManageGuild))
+        .permission("verifier.admin.trust")
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
+        .exec(|ctx| {
+            let guild_id = ctx.require_guild()?;
+            let server_name = |id: GuildId|
+                id.find().map_or_else(|| format!("(server #{})", id.0), |x| x.read().name.clone());
+            if ctx.argc() == 0 {
+                let mut list = String::new();
+                for (trusted_id, is_mutual) in ctx.core.verifier().get_trusted_guilds(guild_id)? {
+                    writeln!(list, "• {} ({}){}", server_name(trusted_id), trusted_id.0,
+                             if is_mutual { "" } else { " *(does not trust this server yet)*" })?;
+                }
+                return if list.is_empty() {
+                    ctx.respond("This server does not trust any other servers.")
+                } else {
+                    ctx.respond(list)
+                }
+            }
+            let trusted_id = || -> Result<GuildId> {
+                Ok(GuildId(ctx.parse_arg(1, "server ID")?))
+            };
+            match ctx.arg(0)? {
+                "add" => {
+                    let trusted_id = trusted_id()?;
+                    cmd_ensure!(trusted_id.find().is_some(),
+                                "This bot is not in a server with that ID.");
+                    cmd_ensure!(ctx.core.verifier().set_guild_trust(guild_id, trusted_id, true)?,
+                                "This server already trusts {}.", server_name(trusted_id));
+                    let is_mutual = ctx.core.verifier().get_trusted_guilds(trusted_id)?
+                        .iter().any(|&(id, _)| id == guild_id);
+                    ctx.respond(format!("This server now trusts {}.{}", server_name(trusted_id),
+                                        if is_mutual { "" } else {
+                                            " It must also trust this server before users \
+                                             verified there are given roles here."
+                                        }))
+                }
+                "remove" => {
+                    let trusted_id = trusted_id()?;
+                    cmd_ensure!(ctx.core.verifier().set_guild_trust(guild_id, trusted_id, false)?,
+                                "This server does not trust {}.", server_name(trusted_id));
+                    ctx.respond(format!("This server no longer trusts {}.",
+                                        server_name(trusted_id)))
+                }
+                _ => cmd_error!("Unknown subcommand. Usage: {0}trust, {0}trust add <server id>, \
+                                 {0}trust remove <server id>", ctx.prefix()),
+            }
+        }),
+    Command::new("badge")
+        .help(Some("<roblox username>"),
+              "Creates links to a badge showing whether a Roblox account is verified in this \
+               server, which can be embedded in websites.")
+        .required_permissions(enum_set!(DiscordPermission::ManageGuild))
+        .permission("verifier.admin.badge")
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
+        .exec(|ctx| {
+            let guild_id = ctx.require_guild()?;
+            let secret = ctx.core.config().get(None, ConfigKeys::WebBadgeSecret)?
+                .to_cmd_err(|| "Verification badges are not enabled on this bot.")?;
+            let roblox_id = RobloxUserID::for_username(ctx.arg(0)?)?;
+            let base_url = ctx.core.config().get(None, ConfigKeys::WebPublicUrl)?
+                .unwrap_or_else(String::new);
+            let signature = web::badge_signature(&secret, guild_id, roblox_id);
+            let mut links = String::new();
+            for extension in &["svg", "json"] {
+                writeln!(links, "<{}/api/v1/badge/{}.{}?guild={}&sig={}>",
+                         base_url, roblox_id.0, extension, guild_id.0, signature)?;
+            }
+            ctx.respond(links)
+        }),
+    Command::new("approvals")
+        .help(None, "Lists the verifications waiting to be approved on this server.")
+        .required_permissions(enum_set!(DiscordPermission::ManageRoles))
+        .permission("verifier.approvals.show")
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
+        .exec(|ctx| {
+            let guild_id = ctx.require_guild()?;
+            let now = SystemTime::now();
+            let mut list = String::new();
+            for pending in ctx.core.approvals().list_pending(guild_id)? {
+                writeln!(list, "• {} as {} *(requested {} ago)*",
+                         get_discord_username(pending.discord_id),
+                         pending.roblox_id.lookup_username()?,
+                         util::english_time_diff(pending.requested_at, now))?;
+            }
+            if list.is_empty() {
+                ctx.respond("No verifications are waiting to be approved.")
+            } else {
+                ctx.respond(list)
+            }
+        }),
+    Command::new("approve")
+        .help(Some("<discord mention or user id>"),
+              "Approves a user's verification, and gives them their roles.")
+        .required_permissions(enum_set!(DiscordPermission::ManageRoles))
+        .permission("verifier.approvals.approve")
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
+        .exec(|ctx| {
+            let user_id = parse_discord_user(ctx.arg(0)?)?
+                .to_cmd_err(|| "Please mention the user you want to approve.")?;
+            let message = review_approval(ctx.core, ctx.require_guild()?, user_id,
+                                          ctx.author()?.id, true)?;
+            ctx.respond(message)
+        }),
+    Command::new("reject")
+        .help(Some("<discord mention or user id>"),
+              "Rejects a user's verification. They will not be given roles on this server \
+               unless they are approved later, or verify a different Roblox account.")
+        .required_permissions(enum_set!(DiscordPermission::ManageRoles))
+        .permission("verifier.approvals.reject")
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
+        .exec(|ctx| {
+            let user_id = parse_discord_user(ctx.arg(0)?)?
+                .to_cmd_err(|| "Please mention the user you want to reject.")?;
+            let message = review_approval(ctx.core, ctx.require_guild()?, user_id,
+                                          ctx.author()?.id, false)?;
+            ctx.respond(message)
+        }),
+    Command::new("unverify")
+        .help(None, "Unlinks your Roblox accounts and removes the roles they granted you.")
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage | CommandTarget::PrivateMessage))
+        .exec_discord(|ctx, _, msg| {
+            let guild_id = ctx.get_guild()?;
+            let roblox_id = match ctx.core.verifier().unverify(guild_id, msg.author.id)? {
+                Some(roblox_id) => roblox_id,
+                None => cmd_error!("{}", i18n::tr(ctx.core.config(), guild_id,
+                                                  "verify-not-verified", &[])?),
+            };
+            info!("{} unverified from {}", msg.author.tag(), roblox_id.0);
+            ctx.core.roles().remove_roles_on_unverify(msg.author.id);
+            ctx.respond(i18n::tr(ctx.core.config(), guild_id, "unverify-success", &[
+                ("roblox_name", &roblox_id.lookup_username()?),
+            ])?)
+        }),
+    Command::new("accounts")
+        .help(None, "Lists the Roblox accounts linked to your Discord account.")
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage | CommandTarget::PrivateMessage))
+        .exec_discord(|ctx, _, msg| {
+            let guild_id = ctx.get_guild()?;
+            let tr = |key: &str| i18n::tr(ctx.core.config(), guild_id, key, &[]);
+            let accounts = ctx.core.verifier().get_linked_roblox_users(msg.author.id)?;
+            cmd_ensure!(!accounts.is_empty(), "{}", tr("verify-not-verified")?);
+            let mut list = String::new();
+            for (i, roblox_id) in accounts.into_iter().enumerate() {
+                if i == 0 {
+                    writeln!(list, "• {} {}", roblox_id.lookup_username()?,
+                             tr("accounts-primary")?)?;
+                } else {
+                    writeln!(list, "• {}", roblox_id.lookup_username()?)?;
+                }
+            }
+            ctx.respond(format!("{}\n{}", tr("accounts-header")?, list.trim_right()))
+        }),
+    Command::new("reminders")
+        .help(Some("<on|off>"),
+              "Turns reminders to verify sent in direct messages by servers you have joined on \
+               or off.")
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage | CommandTarget::PrivateMessage))
+        .exec_discord(|ctx, _, msg| {
+            let guild_id = ctx.get_guild()?;
+            let enabled = match ctx.arg(0)?.to_lowercase().as_str() {
+                "on" => true,
+                "off" => false,
+                _ => cmd_error!("{}", i18n::tr(ctx.core.config(), guild_id, "reminders-usage",
+                                               &[("prefix", ctx.prefix())])?),
+            };
+            ctx.core.enforcement().set_reminders_opt_out(msg.author.id, !enabled)?;
+            let key = if enabled { "reminders-on" } else { "reminders-off" };
+            ctx.respond(i18n::tr(ctx.core.config(), guild_id, key, &[])?)
+        }),
+    Command::new("switch")
+        .help(Some("<roblox username>"),
+              "Makes another linked Roblox account your primary account, and updates your roles \
+               to match it.")
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage | CommandTarget::PrivateMessage))
+        .exec_discord(|ctx, _, msg| {
+            let roblox_id = ctx.core.usernames().resolve(ctx.arg(0)?)?;
+            let previous_id = ctx.core.verifier().switch_primary(ctx.get_guild()?, msg.author.id,
+                                                                 roblox_id)?;
+            info!("{} switched their primary account from {} to {}",
+                  msg.author.tag(), previous_id.0, roblox_id.0);
+            ctx.core.roles().assign_roles_on_switch(msg.author.id, roblox_id);
+            ctx.respond(i18n::tr(ctx.core.config(), ctx.get_guild()?, "switch-success", &[
+                ("roblox_name", &roblox_id.lookup_username()?),
+            ])?)
+        }),
+    Command::new("force_unverify")
+        .help(Some("<discord mention or user id>"),
+              "Unlinks a user's Roblox account and removes the roles it granted them.")
+        .required_privilege(PrivilegeLevel::BotOwner)
+        .exec(|ctx| {
+            let user_id = parse_discord_user(ctx.arg(0)?)?
+                .to_cmd_err(|| "Please mention the user you want to unverify.")?;
+            let roblox_id = ctx.core.verifier().unverify(ctx.get_guild()?, user_id)?
+                .to_cmd_err(|| "That user is not verified with this bot.")?;
+            info!("{} was unverified from {} by a bot owner",
+                  get_discord_username(user_id), roblox_id.0);
+            ctx.core.roles().remove_roles_on_unverify(user_id);
+            ctx.respond(format!("{} is no longer verified as {}.",
+                                get_discord_username(user_id), roblox_id.lookup_username()?))
+        }),
     Command::new("whois")
-        .help(Some("<discord mention, user id, or roblox username>"),
+        .help(Some("<discord mention, user id, or [roblox:]roblox username>"),
               "Retrieves the Roblox account a Discord account is verified with or vice versa.")
         .exec(do_whois),
     Command::new("verify")
-        .help(Some("<roblox username> <verification code>"),
-              "Verifies a Roblox account to your Discord account.")
+        .help(Some("[roblox username] [verification code]"),
+              "Verifies a Roblox account to your Discord account. Without arguments, guides you \
+               through verifying in direct messages. With only a username, gives you a link that \
+               opens the verification place for that account.")
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
+        .exec(do_verify),
+    Command::new("token_time")
+        .help(None, "Shows how long until the code shown in the verification place changes, \
+                     and how long a code shown now can be used for.")
+        .exec(|ctx| {
+            let (changes_in, valid_for) = ctx.core.verifier().token_time_left()?;
+            let guild_id = ctx.get_guild()?;
+            let duration = |secs| i18n::tr_duration(ctx.core.config(), guild_id, secs);
+            ctx.respond(i18n::tr(ctx.core.config(), guild_id, "token-time", &[
+                ("changes_in", &duration(changes_in.as_secs())?),
+                ("valid_for", &duration(valid_for.as_secs())?),
+            ])?)
+        }),
+    Command::new("verify_profile")
+        .help(Some("<roblox username>"),
+              "Verifies a Roblox account using a phrase placed on its profile.")
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
+        .exec(do_verify_profile),
+    Command::new("verify_oauth")
+        .help(Some("[roblox username] [code]"),
+              "Verifies a Roblox account by signing in to it. Without arguments, gives you a \
+               link to sign in at.")
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
+        .exec(do_verify_oauth),
+    Command::new("verify_friend")
+        .help(Some("<roblox username>"),
+              "Verifies a Roblox account by sending a friend request to the bot's Roblox account.")
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
+        .exec_discord(|ctx, _, msg| do_verify_friend(ctx, msg)),
+    Command::new("verify_place_code")
+        .help(None, "Gives you a code to enter in the verification place to verify your Roblox \
+                     account.")
         .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
-        .exec_discord(do_verify),
+        .exec_discord(|ctx, _, msg| do_verify_place_code(ctx, msg)),
+    Command::new("verify_website")
+        .help(None, "Links to the bot's website, where you can verify by signing in to your \
+                     Discord and Roblox accounts.")
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
+        .exec(|ctx| {
+            let guild_id = ctx.require_guild()?;
+            let tr = |key: &str, args: &[(&str, &str)]|
+                i18n::tr(ctx.core.config(), Some(guild_id), key, args);
+            cmd_ensure!(ctx.core.verifier().is_method_enabled(Some(guild_id),
+                                                              ConfigKeys::VerificationMethodOAuth)?,
+                        "{}", tr("verify-method-disabled", &[])?);
+            let base_url = match ctx.core.config().get(None, ConfigKeys::WebPublicUrl)? {
+                Some(base_url) => base_url,
+                None => cmd_error!("{}", tr("verify-website-not-set-up", &[])?),
+            };
+            let url = format!("{}/verify/{}", base_url, guild_id.0);
+            ctx.respond(tr("verify-website-link", &[("url", &url)])?)
+        }),
     Command::new("set_verification_channel")
         .help(None, "Makes the current channel a verification channel.")
         .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
         .required_permissions(enum_set!(DiscordPermission::ManageGuild |
                                         DiscordPermission::ManageMessages))
+        .permission("verifier.channel.set")
         .exec_discord(|ctx, _, msg| {
             let guild_id = msg.guild_id()?;
             if let Some("confirm") = ctx.arg_opt(0) {
@@ -378,11 +1424,29 @@ pub const COMMANDS: &[Command] = &[
             }
             Ok(())
         }),
+    Command::new("setup")
+        .help(Some("[verifypanel]"),
+              "Walks you through setting up verification on this server. With `verifypanel`, \
+               posts a message in the current channel that users can react to in order to \
+               verify in direct messages instead. This replaces any existing panel.")
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
+        .required_permissions(enum_set!(DiscordPermission::ManageGuild))
+        .permission("verifier.admin.setup")
+        .exec_discord(|ctx, _, msg| {
+            match ctx.arg_opt(0) {
+                None => super::setup::start_setup(ctx, msg),
+                Some("verifypanel") => {
+                    ctx.core.verify_channel().setup_verify_panel(msg.guild_id()?, msg.channel_id)
+                }
+                _ => cmd_error!("Unknown subcommand. Usage: {}setup [verifypanel]", ctx.prefix()),
+            }
+        }),
     Command::new("remove_verification_channel")
         .help(None, "Unsets the server's current verification channel, if one exists.")
         .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
         .required_permissions(enum_set!(DiscordPermission::ManageGuild |
                                         DiscordPermission::ManageMessages))
+        .permission("verifier.channel.remove")
         .exec_discord(|ctx, _, msg| {
             let guild_id = msg.guild_id()?;
             ctx.core.verify_channel().remove(guild_id)?;
@@ -392,6 +1456,7 @@ pub const COMMANDS: &[Command] = &[
         .help(Some("[rule to explain]"),
               "Explains the compilation of your ruleset or a role. You probably don't need this.")
         .required_permissions(enum_set!(DiscordPermission::ManageRoles))
+        .permission("verifier.roles.explain")
         .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
         .exec_discord(|ctx, _, msg| {
             let rule = ctx.rest(0)?;
@@ -0,0 +1,133 @@
+use super::*;
+use super::verifier::verify_in_guild;
+
+use i18n;
+use parking_lot::Mutex;
+use roblox::*;
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+const WIZARD_TIMEOUT_SECS: u64 = 60 * 10;
+
+#[derive(Clone)]
+enum WizardState {
+    AwaitingUsername, AwaitingToken { roblox_username: String },
+}
+
+struct WizardSession {
+    guild_id: GuildId, state: WizardState, last_activity: SystemTime,
+}
+impl WizardSession {
+    fn is_expired(&self, now: SystemTime) -> bool {
+        match now.duration_since(self.last_activity) {
+            Ok(age) => age >= Duration::from_secs(WIZARD_TIMEOUT_SECS),
+            Err(_) => false,
+        }
+    }
+}
+
+lazy_static! {
+    static ref SESSIONS: Mutex<HashMap<UserId, WizardSession>> = Mutex::new(HashMap::new());
+}
+
+// Starts a verification wizard in a user's direct messages, and returns whether the message
+// could be sent.
+pub fn start_wizard_dm(core: &VerifierCore, user_id: UserId, guild_id: GuildId) -> Result<bool> {
+    let guild_name = guild_id.find()?.read().name.clone();
+    let message = i18n::tr(core.config(), Some(guild_id), "wizard-start",
+                           &[("server", &guild_name)])?;
+    let sent = user_id.create_dm_channel().and_then(|channel| channel.say(&message));
+    if sent.is_err() {
+        return Ok(false)
+    }
+
+    SESSIONS.lock().insert(user_id, WizardSession {
+        guild_id, state: WizardState::AwaitingUsername, last_activity: SystemTime::now(),
+    });
+    Ok(true)
+}
+pub(super) fn start_wizard(ctx: &CommandContext) -> Result<()> {
+    let guild_id = ctx.require_guild()?;
+    let tr = |key: &str, args: &[(&str, &str)]|
+        i18n::tr(ctx.core.config(), Some(guild_id), key, args);
+    cmd_ensure!(start_wizard_dm(ctx.core, ctx.author()?.id, guild_id)?,
+                "{}", tr("wizard-dm-failed", &[("prefix", ctx.prefix())])?);
+    ctx.respond(tr("wizard-dm-sent", &[])?)
+}
+
+fn wizard_step(
+    core: &VerifierCore, user: &User, guild_id: GuildId, state: WizardState, content: &str,
+) -> Result<(Option<WizardState>, String)> {
+    match state {
+        WizardState::AwaitingUsername => {
+            let roblox_id = core.usernames().resolve_current(content)?;
+            let roblox_username = roblox_id.lookup_username()?;
+            let (link, link_secs) = core.verifier().deep_link(roblox_id)?;
+            let message = i18n::tr(core.config(), Some(guild_id), "wizard-deep-link", &[
+                ("link", &link), ("roblox_name", &roblox_username),
+                ("duration", &i18n::tr_duration(core.config(), Some(guild_id), link_secs)?),
+            ])?;
+            Ok((Some(WizardState::AwaitingToken { roblox_username }), message))
+        }
+        WizardState::AwaitingToken { roblox_username } => {
+            // The place shows a complete command, so only the last word is used as the code.
+            let token = content.split_whitespace().last()?;
+            let prefix = core.config().get(Some(guild_id), ConfigKeys::CommandPrefix)?;
+            let message = verify_in_guild(
+                core, &prefix, guild_id, user, &roblox_username, &TokenVerification, token, |_| {
+                    i18n::tr(core.config(), Some(guild_id), "verify-invalid-token", &[])
+                },
+            )?;
+            Ok((None, message))
+        }
+    }
+}
+
+// Ends every verification wizard in progress, and returns how many there were.
+pub fn cancel_wizards() -> usize {
+    SESSIONS.lock().drain().count()
+}
+
+// Handles direct messages that are not commands, continuing any verification wizard the user
+// has started.
+pub fn handle_wizard_message(core: &VerifierCore, message: &Message) -> Result<()> {
+    let content = message.content.trim();
+    let now = SystemTime::now();
+    let session = {
+        let mut sessions = SESSIONS.lock();
+        sessions.retain(|_, session| !session.is_expired(now));
+        match sessions.remove(&message.author.id) {
+            Some(session) => session,
+            None => return Ok(()),
+        }
+    };
+
+    let guild_id = session.guild_id;
+    let tr = |key: &str| i18n::tr(core.config(), Some(guild_id), key, &[]);
+    if content.eq_ignore_ascii_case("cancel") {
+        message.channel_id.say(&tr("wizard-canceled")?)?;
+        return Ok(())
+    }
+
+    let (next_state, reply) = if content.is_empty() {
+        (Some(session.state), None)
+    } else {
+        match wizard_step(core, &message.author, guild_id, session.state.clone(), content) {
+            Ok((next_state, reply)) => (next_state, Some(reply)),
+            Err(Error::CommandError(err)) => (Some(session.state), Some(err.into_owned())),
+            Err(e) => {
+                message.channel_id.say(&tr("wizard-error")?)?;
+                return Err(e)
+            }
+        }
+    };
+    if let Some(state) = next_state {
+        SESSIONS.lock().insert(message.author.id, WizardSession {
+            guild_id, state, last_activity: now,
+        });
+    }
+    if let Some(reply) = reply {
+        message.channel_id.say(&reply)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,310 @@
+use core::config::*;
+use database::*;
+use errors::*;
+use reqwest::Method;
+use serde_json;
+use serenity::model::prelude::*;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use util::{DISCORD_API_BASE, discord_request, ActionRowPayload, ButtonPayload, CreatedMessage};
+use util::{EmbedPayload, MessagePayload};
+use util::{COMPONENT_ACTION_ROW, COMPONENT_BUTTON, BUTTON_STYLE_DANGER, BUTTON_STYLE_SECONDARY};
+
+const COLOUR_PENDING  : u32 = 0xFAA61A;
+const COLOUR_CONFIRMED: u32 = 0x43B581;
+const COLOUR_CLOSED   : u32 = 0x747F8D;
+
+// The custom IDs of the buttons on confirmation requests are these prefixes followed by the ID of
+// the pending action.
+pub const CONFIRM_ACTION_BUTTON_PREFIX: &str = "admin_action_confirm:";
+pub const CANCEL_ACTION_BUTTON_PREFIX: &str = "admin_action_cancel:";
+
+// Destructive admin actions that can be required to be confirmed by a second admin.
+#[derive(Clone, Debug)]
+pub enum AdminAction {
+    EmergencyRekey { reason: Option<String> },
+    PurgeUser { discord_id: UserId },
+    ResetVerification { guild_id: GuildId, strip_roles: bool },
+}
+impl AdminAction {
+    fn to_db(&self) -> (&'static str, Option<String>) {
+        match *self {
+            AdminAction::EmergencyRekey { ref reason } => ("emergency_rekey", reason.clone()),
+            AdminAction::PurgeUser { discord_id } =>
+                ("purge_user", Some(discord_id.0.to_string())),
+            AdminAction::ResetVerification { guild_id, strip_roles } =>
+                ("reset_verification", Some(format!("{},{}", guild_id.0, strip_roles))),
+        }
+    }
+    fn from_db(name: &str, argument: Option<String>) -> Result<AdminAction> {
+        Ok(match name {
+            "emergency_rekey" => AdminAction::EmergencyRekey { reason: argument },
+            "purge_user" => AdminAction::PurgeUser {
+                discord_id: UserId(argument.unwrap_or_default().parse()?),
+            },
+            "reset_verification" => {
+                let argument = argument.unwrap_or_default();
+                let mut parts = argument.split(',');
+                AdminAction::ResetVerification {
+                    guild_id: GuildId(parts.next().unwrap_or("").parse()?),
+                    strip_roles: parts.next() == Some("true"),
+                }
+            }
+            _ => bail!("Unknown admin action '{}'.", name),
+        })
+    }
+
+    pub fn describe(&self) -> String {
+        match *self {
+            AdminAction::EmergencyRekey { reason: Some(ref reason) } =>
+                format!("Emergency rekey (reason: {})", reason),
+            AdminAction::EmergencyRekey { reason: None } => "Emergency rekey".to_owned(),
+            AdminAction::PurgeUser { discord_id } =>
+                format!("Purge all data stored about <@{}> (user ID {})",
+                        discord_id.0, discord_id.0),
+            AdminAction::ResetVerification { guild_id, strip_roles } => {
+                let name = guild_id.find().map_or_else(|| format!("server {}", guild_id.0),
+                                                       |x| x.read().name.clone());
+                format!("Reset verification on {}{}", name,
+                        if strip_roles { ", removing verification roles" } else { "" })
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum ActionStatus {
+    Pending, Confirmed, Cancelled, Expired,
+}
+impl ActionStatus {
+    fn name(self) -> &'static str {
+        match self {
+            ActionStatus::Pending => "pending",
+            ActionStatus::Confirmed => "confirmed",
+            ActionStatus::Cancelled => "cancelled",
+            ActionStatus::Expired => "expired",
+        }
+    }
+    fn from_name(name: &str) -> Result<ActionStatus> {
+        match name {
+            "pending" => Ok(ActionStatus::Pending),
+            "confirmed" => Ok(ActionStatus::Confirmed),
+            "cancelled" => Ok(ActionStatus::Cancelled),
+            "expired" => Ok(ActionStatus::Expired),
+            _ => bail!("Unknown admin action status '{}'.", name),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PendingAction {
+    pub id: u64, pub action: AdminAction, pub requested_by: UserId, pub expires_at: SystemTime,
+}
+
+fn describe_request(pending: &PendingAction) -> String {
+    format!("**#{}**: {}\nRequested by <@{}>.",
+            pending.id, pending.action.describe(), pending.requested_by.0)
+}
+
+struct AdminActionManagerData {
+    config: ConfigManager, database: Database,
+}
+
+// Holds destructive admin actions until a second admin confirms them, when the bot is configured
+// to require that.
+#[derive(Clone)]
+pub struct AdminActionManager(Arc<AdminActionManagerData>);
+impl AdminActionManager {
+    pub fn new(config: ConfigManager, database: Database) -> AdminActionManager {
+        AdminActionManager(Arc::new(AdminActionManagerData { config, database }))
+    }
+
+    fn approvers(&self) -> Result<Vec<UserId>> {
+        Ok(match self.0.config.get(None, ConfigKeys::ActionApprovers)? {
+            Some(approvers) => approvers.split(',').filter_map(|x| x.trim().parse().ok())
+                .map(UserId).collect(),
+            None => Vec::new(),
+        })
+    }
+    // Returns whether destructive actions must be confirmed before they are run.
+    pub fn requires_confirmation(&self) -> Result<bool> {
+        Ok(!self.approvers()?.is_empty())
+    }
+
+    pub fn request(&self, action: AdminAction, requested_by: UserId) -> Result<PendingAction> {
+        let timeout = self.0.config.get(None, ConfigKeys::ActionApprovalTimeoutMinutes)?;
+        let now = SystemTime::now();
+        let expires_at = now + Duration::from_secs(timeout as u64 * 60);
+        let (name, argument) = action.to_db();
+        let conn = self.0.database.connect()?;
+        let id = conn.transaction_immediate(|| {
+            conn.execute(
+                "INSERT INTO pending_admin_actions \
+                     (action, argument, requested_by, requested_at, expires_at, status) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                (name, &argument, requested_by, now, expires_at, ActionStatus::Pending.name()),
+            )?;
+            conn.query("SELECT last_insert_rowid()", ()).get::<u64>()
+        })?;
+        let pending = PendingAction { id, action, requested_by, expires_at };
+        if let Err(e) = self.post_request(&conn, &pending) {
+            warn!("Could not post confirmation request for admin action #{}: {}", id, e);
+        }
+        Ok(pending)
+    }
+
+    fn post_request(&self, conn: &DatabaseConnection, pending: &PendingAction) -> Result<()> {
+        let channel_id = match self.0.config.get(None, ConfigKeys::ActionApprovalChannel)? {
+            Some(channel_id) => channel_id,
+            None => return Ok(()),
+        };
+        let token = self.0.config.get(None, ConfigKeys::DiscordToken)?
+            .to_cmd_err(|| "No Discord token is configured.")?;
+        let payload = MessagePayload {
+            embeds: vec![EmbedPayload {
+                title: "Admin action awaiting confirmation",
+                description: describe_request(pending),
+                color: COLOUR_PENDING, thumbnail: None,
+            }],
+            components: vec![ActionRowPayload {
+                kind: COMPONENT_ACTION_ROW,
+                components: vec![
+                    ButtonPayload {
+                        kind: COMPONENT_BUTTON, style: BUTTON_STYLE_DANGER, label: "Confirm",
+                        custom_id: format!("{}{}", CONFIRM_ACTION_BUTTON_PREFIX, pending.id),
+                    },
+                    ButtonPayload {
+                        kind: COMPONENT_BUTTON, style: BUTTON_STYLE_SECONDARY, label: "Cancel",
+                        custom_id: format!("{}{}", CANCEL_ACTION_BUTTON_PREFIX, pending.id),
+                    },
+                ],
+            }],
+        };
+        let mut response = discord_request(
+            &token, Method::Post,
+            &format!("{}/channels/{}/messages", DISCORD_API_BASE, channel_id.0), &payload,
+        )?;
+        let message = serde_json::from_str::<CreatedMessage>(&response.text()?)?;
+        conn.execute(
+            "UPDATE pending_admin_actions SET channel_id = ?2, message_id = ?3 WHERE id = ?1",
+            (pending.id, channel_id, message.id),
+        )?;
+        Ok(())
+    }
+
+    // Replaces the buttons on a confirmation request with what happened to it.
+    fn close_request(
+        &self, channel_id: ChannelId, message_id: MessageId, pending: &PendingAction,
+        status: ActionStatus, reviewer: Option<UserId>,
+    ) -> Result<()> {
+        let token = self.0.config.get(None, ConfigKeys::DiscordToken)?
+            .to_cmd_err(|| "No Discord token is configured.")?;
+        let (title, color) = match status {
+            ActionStatus::Confirmed => ("Admin action confirmed", COLOUR_CONFIRMED),
+            ActionStatus::Cancelled => ("Admin action cancelled", COLOUR_CLOSED),
+            _ => ("Admin action expired", COLOUR_CLOSED),
+        };
+        let description = match (status, reviewer) {
+            (ActionStatus::Confirmed, Some(reviewer)) =>
+                format!("{}\nConfirmed by <@{}>.", describe_request(pending), reviewer.0),
+            (_, Some(reviewer)) =>
+                format!("{}\nCancelled by <@{}>.", describe_request(pending), reviewer.0),
+            (_, None) => describe_request(pending),
+        };
+        let payload = MessagePayload {
+            embeds: vec![EmbedPayload { title, description, color, thumbnail: None }],
+            components: Vec::new(),
+        };
+        discord_request(&token, Method::Patch,
+                        &format!("{}/channels/{}/messages/{}",
+                                 DISCORD_API_BASE, channel_id.0, message_id.0),
+                        &payload)?;
+        Ok(())
+    }
+
+    fn review(
+        &self, id: u64, reviewer: UserId, status: ActionStatus,
+    ) -> Result<PendingAction> {
+        let conn = self.0.database.connect()?;
+        let approvers = self.approvers()?;
+        let (pending, message) = conn.transaction_immediate(|| {
+            let (name, argument, requested_by, expires_at, current, channel_id, message_id) =
+                conn.query(
+                    "SELECT action, argument, requested_by, expires_at, status, channel_id, \
+                            message_id \
+                     FROM pending_admin_actions WHERE id = ?1", id,
+                ).get_opt::<(String, Option<String>, UserId, SystemTime, String,
+                              Option<ChannelId>, Option<MessageId>)>()?
+                    .to_cmd_err(|| format!("There is no admin action #{}.", id))?;
+            let current = ActionStatus::from_name(&current)?;
+            cmd_ensure!(current == ActionStatus::Pending, "Admin action #{} was already {}.",
+                        id, current.name());
+            cmd_ensure!(SystemTime::now() < expires_at,
+                        "Admin action #{} has expired, and must be requested again.", id);
+            if status == ActionStatus::Confirmed {
+                cmd_ensure!(reviewer != requested_by,
+                            "Admin actions must be confirmed by someone other than the admin \
+                             that requested them.");
+                cmd_ensure!(approvers.contains(&reviewer),
+                            "Only the users listed in `action_approvers` can confirm admin \
+                             actions.");
+            } else {
+                cmd_ensure!(reviewer == requested_by || approvers.contains(&reviewer),
+                            "Only the admin that requested an action or the users listed in \
+                             `action_approvers` can cancel it.");
+            }
+            conn.execute(
+                "UPDATE pending_admin_actions SET status = ?2, reviewed_by = ?3 WHERE id = ?1",
+                (id, status.name(), reviewer),
+            )?;
+            let pending = PendingAction {
+                id, action: AdminAction::from_db(&name, argument)?, requested_by, expires_at,
+            };
+            Ok((pending, (channel_id, message_id)))
+        })?;
+        if let (Some(channel_id), Some(message_id)) = message {
+            if let Err(e) = self.close_request(channel_id, message_id, &pending, status,
+                                               Some(reviewer)) {
+                warn!("Could not update confirmation request for admin action #{}: {}", id, e);
+            }
+        }
+        Ok(pending)
+    }
+    // Marks an action as confirmed, and returns it so that it can be run.
+    pub fn confirm(&self, id: u64, reviewer: UserId) -> Result<PendingAction> {
+        self.review(id, reviewer, ActionStatus::Confirmed)
+    }
+    pub fn cancel(&self, id: u64, reviewer: UserId) -> Result<PendingAction> {
+        self.review(id, reviewer, ActionStatus::Cancelled)
+    }
+
+    // Marks actions that were not confirmed in time as expired, and removes the buttons from
+    // their confirmation requests.
+    pub fn expire_pending(&self) -> Result<()> {
+        let conn = self.0.database.connect()?;
+        let now = SystemTime::now();
+        let expired = conn.query(
+            "SELECT id, action, argument, requested_by, expires_at, channel_id, message_id \
+             FROM pending_admin_actions WHERE status = ?1 AND expires_at <= ?2",
+            (ActionStatus::Pending.name(), now),
+        ).get_all::<(u64, String, Option<String>, UserId, SystemTime,
+                     Option<ChannelId>, Option<MessageId>)>()?;
+        for (id, name, argument, requested_by, expires_at, channel_id, message_id) in expired {
+            conn.execute(
+                "UPDATE pending_admin_actions SET status = ?2 WHERE id = ?1",
+                (id, ActionStatus::Expired.name()),
+            )?;
+            if let (Some(channel_id), Some(message_id)) = (channel_id, message_id) {
+                let pending = PendingAction {
+                    id, action: AdminAction::from_db(&name, argument)?, requested_by, expires_at,
+                };
+                if let Err(e) = self.close_request(channel_id, message_id, &pending,
+                                                   ActionStatus::Expired, None) {
+                    warn!("Could not update confirmation request for admin action #{}: {}",
+                          id, e);
+                }
+            }
+        }
+        Ok(())
+    }
+}
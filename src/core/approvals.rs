@@ -0,0 +1,285 @@
+use core::config::*;
+use core::name_policy::*;
+use database::*;
+use errors::*;
+use reqwest::Method;
+use roblox::RobloxUserID;
+use serde_json;
+use serenity::model::prelude::*;
+use std::sync::Arc;
+use std::time::SystemTime;
+use util::{DISCORD_API_BASE, discord_request, ActionRowPayload, ButtonPayload, CreatedMessage};
+use util::{EmbedPayload, EmbedThumbnailPayload, MessagePayload};
+use util::{COMPONENT_ACTION_ROW, COMPONENT_BUTTON, BUTTON_STYLE_DANGER, BUTTON_STYLE_SUCCESS};
+
+const COLOUR_PENDING : u32 = 0xFAA61A;
+const COLOUR_APPROVED: u32 = 0x43B581;
+const COLOUR_REJECTED: u32 = 0xF04747;
+
+// The custom IDs of the buttons on approval requests are these prefixes followed by the Discord
+// ID of the user that requested approval.
+pub const APPROVE_BUTTON_PREFIX: &str = "approval_approve:";
+pub const REJECT_BUTTON_PREFIX: &str = "approval_reject:";
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ApprovalStatus {
+    Pending, Approved, Rejected,
+}
+impl ApprovalStatus {
+    fn name(self) -> &'static str {
+        match self {
+            ApprovalStatus::Pending => "pending",
+            ApprovalStatus::Approved => "approved",
+            ApprovalStatus::Rejected => "rejected",
+        }
+    }
+    fn from_name(name: &str) -> Result<ApprovalStatus> {
+        match name {
+            "pending" => Ok(ApprovalStatus::Pending),
+            "approved" => Ok(ApprovalStatus::Approved),
+            "rejected" => Ok(ApprovalStatus::Rejected),
+            _ => bail!("Unknown approval status '{}'.", name),
+        }
+    }
+}
+
+pub struct PendingVerification {
+    pub discord_id: UserId, pub roblox_id: RobloxUserID, pub requested_at: SystemTime,
+}
+
+fn describe_request(discord_id: UserId, roblox_id: RobloxUserID) -> String {
+    let roblox_name = match roblox_id.lookup_username_opt() {
+        Ok(Some(name)) => format!("{} (ID #{})", name, roblox_id.0),
+        _ => format!("(roblox uid #{})", roblox_id.0),
+    };
+    format!("<@{}> wants to verify as {}.", discord_id.0, roblox_name)
+}
+
+struct ApprovalManagerData {
+    config: ConfigManager, database: Database,
+}
+
+// Tracks which verified users have been approved by the staff of servers that require manual
+// approval before giving roles to new members.
+#[derive(Clone)]
+pub struct ApprovalManager(Arc<ApprovalManagerData>);
+impl ApprovalManager {
+    pub fn new(config: ConfigManager, database: Database) -> ApprovalManager {
+        ApprovalManager(Arc::new(ApprovalManagerData { config, database }))
+    }
+
+    fn get_status(
+        conn: &DatabaseConnection, guild_id: GuildId, discord_id: UserId,
+    ) -> Result<Option<(RobloxUserID, ApprovalStatus)>> {
+        match conn.query(
+            "SELECT roblox_user_id, status FROM pending_verifications \
+             WHERE discord_guild_id = ?1 AND discord_user_id = ?2", (guild_id, discord_id),
+        ).get_opt::<(RobloxUserID, String)>()? {
+            Some((roblox_id, status)) => Ok(Some((roblox_id, ApprovalStatus::from_name(&status)?))),
+            None => Ok(None),
+        }
+    }
+
+    fn requires_approval(&self, guild_id: GuildId, roblox_id: RobloxUserID) -> Result<bool> {
+        if self.0.config.get(Some(guild_id), ConfigKeys::RequireApproval)? {
+            return Ok(true)
+        }
+        Ok(match check_name_policy(&self.0.config, guild_id, roblox_id)? {
+            NamePolicyResult::Denied { require_approval, .. } => require_approval,
+            NamePolicyResult::Allowed => false,
+        })
+    }
+
+    // Returns whether a verified user may be given roles in a server. If the server requires
+    // approval, or the user's Roblox name must be approved, and the user has not requested it for
+    // their current Roblox account yet, a new request is posted to the server's approval channel.
+    pub fn check(
+        &self, guild_id: GuildId, discord_id: UserId, roblox_id: RobloxUserID,
+    ) -> Result<ApprovalStatus> {
+        if !self.requires_approval(guild_id, roblox_id)? {
+            return Ok(ApprovalStatus::Approved)
+        }
+        let conn = self.0.database.connect()?;
+        match Self::get_status(&conn, guild_id, discord_id)? {
+            Some((id, status)) if id == roblox_id => return Ok(status),
+            _ => { }
+        }
+        conn.execute(
+            "REPLACE INTO pending_verifications \
+                 (discord_guild_id, discord_user_id, roblox_user_id, status, requested_at) \
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            (guild_id, discord_id, roblox_id, ApprovalStatus::Pending.name(), SystemTime::now()),
+        )?;
+        if let Err(e) = self.post_request(&conn, guild_id, discord_id, roblox_id) {
+            warn!("Could not post approval request for {} in {}: {}", discord_id, guild_id, e);
+        }
+        Ok(ApprovalStatus::Pending)
+    }
+
+    // Returns what `check` would, without posting a new request.
+    pub fn peek(
+        &self, guild_id: GuildId, discord_id: UserId, roblox_id: RobloxUserID,
+    ) -> Result<ApprovalStatus> {
+        if !self.requires_approval(guild_id, roblox_id)? {
+            return Ok(ApprovalStatus::Approved)
+        }
+        match Self::get_status(&self.0.database.connect()?, guild_id, discord_id)? {
+            Some((id, status)) if id == roblox_id => Ok(status),
+            _ => Ok(ApprovalStatus::Pending),
+        }
+    }
+
+    fn post_request(
+        &self, conn: &DatabaseConnection, guild_id: GuildId, discord_id: UserId,
+        roblox_id: RobloxUserID,
+    ) -> Result<()> {
+        let channel_id = match self.0.config.get(Some(guild_id), ConfigKeys::ApprovalChannel)? {
+            Some(channel_id) => channel_id,
+            None => return Ok(()),
+        };
+        let token = self.0.config.get(None, ConfigKeys::DiscordToken)?
+            .to_cmd_err(|| "No Discord token is configured.")?;
+        let payload = MessagePayload {
+            embeds: vec![EmbedPayload {
+                title: "Verification awaiting approval",
+                description: describe_request(discord_id, roblox_id),
+                color: COLOUR_PENDING,
+                thumbnail: Some(EmbedThumbnailPayload { url: roblox_id.avatar_headshot_url() }),
+            }],
+            components: vec![ActionRowPayload {
+                kind: COMPONENT_ACTION_ROW,
+                components: vec![
+                    ButtonPayload {
+                        kind: COMPONENT_BUTTON, style: BUTTON_STYLE_SUCCESS, label: "Approve",
+                        custom_id: format!("{}{}", APPROVE_BUTTON_PREFIX, discord_id.0),
+                    },
+                    ButtonPayload {
+                        kind: COMPONENT_BUTTON, style: BUTTON_STYLE_DANGER, label: "Reject",
+                        custom_id: format!("{}{}", REJECT_BUTTON_PREFIX, discord_id.0),
+                    },
+                ],
+            }],
+        };
+        let mut response = discord_request(
+            &token, Method::Post,
+            &format!("{}/channels/{}/messages", DISCORD_API_BASE, channel_id.0), &payload,
+        )?;
+        let message = serde_json::from_str::<CreatedMessage>(&response.text()?)?;
+        conn.execute(
+            "UPDATE pending_verifications SET staff_channel_id = ?3, staff_message_id = ?4 \
+             WHERE discord_guild_id = ?1 AND discord_user_id = ?2",
+            (guild_id, discord_id, channel_id, message.id),
+        )?;
+        Ok(())
+    }
+
+    // Replaces the buttons on an approval request with the decision made on it.
+    fn close_request(
+        &self, channel_id: ChannelId, message_id: MessageId, discord_id: UserId,
+        roblox_id: RobloxUserID, status: ApprovalStatus, moderator: UserId,
+    ) -> Result<()> {
+        let token = self.0.config.get(None, ConfigKeys::DiscordToken)?
+            .to_cmd_err(|| "No Discord token is configured.")?;
+        let (title, color) = match status {
+            ApprovalStatus::Approved => ("Verification approved", COLOUR_APPROVED),
+            _ => ("Verification rejected", COLOUR_REJECTED),
+        };
+        let payload = MessagePayload {
+            embeds: vec![EmbedPayload {
+                title, color,
+                description: format!("{}\nReviewed by <@{}>.",
+                                     describe_request(discord_id, roblox_id), moderator.0),
+                thumbnail: Some(EmbedThumbnailPayload { url: roblox_id.avatar_headshot_url() }),
+            }],
+            components: Vec::new(),
+        };
+        discord_request(&token, Method::Patch,
+                        &format!("{}/channels/{}/messages/{}",
+                                 DISCORD_API_BASE, channel_id.0, message_id.0),
+                        &payload)?;
+        Ok(())
+    }
+
+    fn review(
+        &self, guild_id: GuildId, discord_id: UserId, status: ApprovalStatus, moderator: UserId,
+    ) -> Result<RobloxUserID> {
+        let conn = self.0.database.connect()?;
+        let (roblox_id, channel_id, message_id) = conn.query(
+            "SELECT roblox_user_id, staff_channel_id, staff_message_id \
+             FROM pending_verifications WHERE discord_guild_id = ?1 AND discord_user_id = ?2",
+            (guild_id, discord_id),
+        ).get_opt::<(RobloxUserID, Option<ChannelId>, Option<MessageId>)>()?
+            .to_cmd_err(|| "That user has not requested approval in this server.")?;
+        conn.execute(
+            "UPDATE pending_verifications \
+             SET status = ?3, staff_channel_id = NULL, staff_message_id = NULL \
+             WHERE discord_guild_id = ?1 AND discord_user_id = ?2",
+            (guild_id, discord_id, status.name()),
+        )?;
+        if let (Some(channel_id), Some(message_id)) = (channel_id, message_id) {
+            if let Err(e) = self.close_request(channel_id, message_id, discord_id, roblox_id,
+                                               status, moderator) {
+                warn!("Could not update approval request for {} in {}: {}",
+                      discord_id, guild_id, e);
+            }
+        }
+        Ok(roblox_id)
+    }
+    pub fn approve(
+        &self, guild_id: GuildId, discord_id: UserId, moderator: UserId,
+    ) -> Result<RobloxUserID> {
+        self.review(guild_id, discord_id, ApprovalStatus::Approved, moderator)
+    }
+    pub fn reject(
+        &self, guild_id: GuildId, discord_id: UserId, moderator: UserId,
+    ) -> Result<RobloxUserID> {
+        self.review(guild_id, discord_id, ApprovalStatus::Rejected, moderator)
+    }
+
+    pub fn list_pending(&self, guild_id: GuildId) -> Result<Vec<PendingVerification>> {
+        Ok(self.0.database.connect()?.query(
+            "SELECT discord_user_id, roblox_user_id, requested_at FROM pending_verifications \
+             WHERE discord_guild_id = ?1 AND status = ?2 ORDER BY requested_at",
+            (guild_id, ApprovalStatus::Pending.name()),
+        ).get_all::<(UserId, RobloxUserID, SystemTime)>()?.into_iter().map(
+            |(discord_id, roblox_id, requested_at)|
+                PendingVerification { discord_id, roblox_id, requested_at }
+        ).collect())
+    }
+
+    // Members who are already verified when a server starts requiring approval are approved
+    // automatically, so turning it on does not take their roles away.
+    pub fn approve_existing(&self, guild_id: Option<GuildId>) -> Result<()> {
+        let guild_id = match guild_id {
+            Some(guild_id) => guild_id,
+            None => return Ok(()),
+        };
+        if !self.0.config.get(Some(guild_id), ConfigKeys::RequireApproval)? {
+            return Ok(())
+        }
+        let members: Vec<UserId> = match guild_id.find() {
+            Some(guild) => guild.read().members.keys().cloned().collect(),
+            None => return Ok(()),
+        };
+        let conn = self.0.database.connect()?;
+        conn.transaction_immediate(|| {
+            for discord_id in members {
+                let roblox_id = conn.query(
+                    "SELECT roblox_user_id FROM discord_user_info WHERE discord_user_id = ?1",
+                    discord_id,
+                ).get_opt::<Option<RobloxUserID>>()?.and_then(|x| x);
+                if let Some(roblox_id) = roblox_id {
+                    conn.execute(
+                        "INSERT OR IGNORE INTO pending_verifications \
+                             (discord_guild_id, discord_user_id, roblox_user_id, status, \
+                              requested_at) \
+                         VALUES (?1, ?2, ?3, ?4, ?5)",
+                        (guild_id, discord_id, roblox_id, ApprovalStatus::Approved.name(),
+                         SystemTime::now()),
+                    )?;
+                }
+            }
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,305 @@
+use core::config::*;
+use core::embeds::EmbedTheme;
+use core::tasks::*;
+use errors::*;
+use roblox::RobloxUserID;
+use serenity;
+use serenity::model::prelude::*;
+use std::sync::Arc;
+use util;
+
+const COLOUR_VERIFIED  : u32 = 0x43B581;
+const COLOUR_UNVERIFIED: u32 = 0x747F8D;
+const COLOUR_WARNING   : u32 = 0xFAA61A;
+
+#[derive(Copy, Clone)]
+pub enum AuditEvent {
+    Verified {
+        roblox_id: RobloxUserID, previous_id: Option<RobloxUserID>,
+        method: &'static str, key_id: u64, epoch: i64,
+    },
+    Unverified { roblox_id: RobloxUserID },
+    LinkRestored { roblox_id: RobloxUserID },
+    PrimaryAccountChanged { roblox_id: RobloxUserID, previous_id: RobloxUserID },
+    AttemptLimitReached { roblox_id: RobloxUserID, max_attempts: u32 },
+    PossibleAltAccount {
+        roblox_id: RobloxUserID, other_discord_id: UserId, other_accounts: u32,
+    },
+}
+impl AuditEvent {
+    fn title(&self) -> &'static str {
+        match *self {
+            AuditEvent::Verified { previous_id: None, .. } => "User verified",
+            AuditEvent::Verified { .. } => "User reverified",
+            AuditEvent::Unverified { .. } => "User unverified",
+            AuditEvent::LinkRestored { .. } => "Verification restored",
+            AuditEvent::PrimaryAccountChanged { .. } => "Primary account changed",
+            AuditEvent::AttemptLimitReached { .. } => "Verification attempt limit reached",
+            AuditEvent::PossibleAltAccount { .. } => "Possible alt account",
+        }
+    }
+    // Warnings keep their colour when a server sets its own.
+    fn is_warning(&self) -> bool {
+        match *self {
+            AuditEvent::AttemptLimitReached { .. } |
+            AuditEvent::PossibleAltAccount { .. } => true,
+            _ => false,
+        }
+    }
+    fn colour(&self) -> u32 {
+        match *self {
+            AuditEvent::Verified { .. } |
+            AuditEvent::LinkRestored { .. } |
+            AuditEvent::PrimaryAccountChanged { .. } => COLOUR_VERIFIED,
+            AuditEvent::Unverified { .. } => COLOUR_UNVERIFIED,
+            AuditEvent::AttemptLimitReached { .. } |
+            AuditEvent::PossibleAltAccount { .. } => COLOUR_WARNING,
+        }
+    }
+    fn roblox_id(&self) -> RobloxUserID {
+        match *self {
+            AuditEvent::Verified { roblox_id, .. } |
+            AuditEvent::Unverified { roblox_id } |
+            AuditEvent::LinkRestored { roblox_id } |
+            AuditEvent::PrimaryAccountChanged { roblox_id, .. } |
+            AuditEvent::AttemptLimitReached { roblox_id, .. } |
+            AuditEvent::PossibleAltAccount { roblox_id, .. } => roblox_id,
+        }
+    }
+}
+
+fn roblox_account_name(roblox_id: RobloxUserID) -> String {
+    match roblox_id.lookup_username_opt() {
+        Ok(Some(name)) => format!("{} (ID #{})", name, roblox_id.0),
+        _ => format!("(roblox uid #{})", roblox_id.0),
+    }
+}
+
+struct AuditLogData {
+    config: ConfigManager, tasks: TaskManager,
+}
+
+#[derive(Clone)]
+pub struct AuditLog(Arc<AuditLogData>);
+impl AuditLog {
+    pub fn new(config: ConfigManager, tasks: TaskManager) -> AuditLog {
+        AuditLog(Arc::new(AuditLogData { config, tasks }))
+    }
+
+    fn theme(&self, guild: GuildId) -> Result<EmbedTheme> {
+        EmbedTheme::load(&self.0.config, Some(guild))
+    }
+
+    fn protected_names(&self, guild: GuildId) -> Result<Vec<String>> {
+        Ok(match self.0.config.get(Some(guild), ConfigKeys::ProtectedRobloxNames)? {
+            Some(names) => names.split(',').map(|x| x.trim().to_owned())
+                .filter(|x| !x.is_empty()).collect(),
+            None => Vec::new(),
+        })
+    }
+
+    fn post_similar_name_warning(
+        &self, guild: GuildId, channel: ChannelId, discord_id: UserId, roblox_id: RobloxUserID,
+    ) -> Result<()> {
+        let protected = self.protected_names(guild)?;
+        if protected.is_empty() {
+            return Ok(())
+        }
+        let roblox_name = roblox_id.lookup_username()?;
+        let max_distance = self.0.config.get(Some(guild), ConfigKeys::ProtectedNameMaxDistance)?;
+        let similar = util::find_similar_name(&roblox_name, protected.iter().map(|x| x.as_str()),
+                                              max_distance as usize);
+        if let Some(similar) = similar {
+            let theme = self.theme(guild)?;
+            channel.send_message(|m| m.embed(|e| {
+                let e = e.title("Possible impersonation")
+                    .colour(COLOUR_WARNING)
+                    .description(format!("<@{}> verified as **{}**, which is similar to the \
+                                          protected name **{}**.",
+                                         discord_id.0, roblox_name, similar))
+                    .field("Roblox account", roblox_account_name(roblox_id), false);
+                theme.decorate(e, None)
+            })).map_err(Error::from).drop_nonfatal()?;
+        }
+        Ok(())
+    }
+
+    fn post_rename(
+        &self, discord_id: UserId, roblox_id: RobloxUserID, old_name: &str, new_name: &str,
+    ) -> Result<()> {
+        for guild in util::member_guilds(discord_id) {
+            if !self.0.config.get(Some(guild), ConfigKeys::AuditRenames)? {
+                continue
+            }
+            if let Some(channel) = self.0.config.get(Some(guild), ConfigKeys::AuditChannel)? {
+                let theme = self.theme(guild)?;
+                channel.send_message(|m| m.embed(|e| {
+                    let e = e.title("Roblox username changed")
+                        .colour(COLOUR_UNVERIFIED)
+                        .field("Discord account", format!("{} (<@{}>)",
+                                                          util::get_discord_username(discord_id),
+                                                          discord_id.0), false)
+                        .field("Roblox account", format!("{} (ID #{})", new_name, roblox_id.0),
+                               false)
+                        .field("Previous username", old_name, false);
+                    theme.apply(e, None)
+                })).map_err(Error::from).drop_nonfatal()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn post_group_rank_failed(
+        &self, guild_id: GuildId, discord_id: UserId, roblox_id: RobloxUserID, group_id: u64,
+        rank: u32, error: &str,
+    ) -> Result<()> {
+        if let Some(channel) = self.0.config.get(Some(guild_id), ConfigKeys::AuditChannel)? {
+            let theme = self.theme(guild_id)?;
+            channel.send_message(|m| m.embed(|e| {
+                let e = e.title("Could not set group rank")
+                    .colour(COLOUR_WARNING)
+                    .field("Discord account", format!("{} (<@{}>)",
+                                                      util::get_discord_username(discord_id),
+                                                      discord_id.0), false)
+                    .field("Roblox account", roblox_account_name(roblox_id), false)
+                    .field("Group", group_id, true)
+                    .field("Rank", rank, true)
+                    .field("Error", error, false);
+                theme.decorate(e, None)
+            })).map_err(Error::from).drop_nonfatal()?;
+        }
+        Ok(())
+    }
+
+    // Events are only posted in the audit channel of the server they happened in, so other
+    // servers the user is in do not see what they do elsewhere.
+    fn post(&self, guild: GuildId, discord_id: UserId, event: AuditEvent) -> Result<()> {
+        let channel = match self.0.config.get(Some(guild), ConfigKeys::AuditChannel)? {
+            Some(channel) => channel,
+            None => return Ok(()),
+        };
+        // The warning is less important than the entry itself, so failing to post it does not
+        // stop the entry from being posted.
+        if let AuditEvent::Verified { roblox_id, .. } = event {
+            if let Err(e) = self.post_similar_name_warning(guild, channel, discord_id, roblox_id) {
+                warn!("Could not post similar name warning in {}: {}", guild, e);
+            }
+        }
+
+        let discord_name = util::get_discord_username(discord_id);
+        let roblox_name = roblox_account_name(event.roblox_id());
+        let headshot = event.roblox_id().avatar_headshot_url();
+        let theme = self.theme(guild)?;
+        channel.send_message(|m| m.embed(|e| {
+            let e = e.title(event.title())
+                .colour(event.colour())
+                .field("Discord account", format!("{} (<@{}>)", discord_name, discord_id.0),
+                       false)
+                .field("Roblox account", &roblox_name, false);
+            let e = match event {
+                AuditEvent::Verified { previous_id, method, key_id, epoch, .. } => {
+                    let e = e.field("Method", method, true)
+                        .field("Token key", format!("#{}, epoch {}", key_id, epoch), true);
+                    match previous_id {
+                        Some(previous_id) =>
+                            e.field("Previous Roblox account",
+                                    roblox_account_name(previous_id), false),
+                        None => e,
+                    }
+                }
+                AuditEvent::Unverified { .. } | AuditEvent::LinkRestored { .. } => e,
+                AuditEvent::PrimaryAccountChanged { previous_id, .. } =>
+                    e.field("Previous primary account", roblox_account_name(previous_id),
+                            false),
+                AuditEvent::AttemptLimitReached { max_attempts, .. } =>
+                    e.field("Attempts", max_attempts, true),
+                AuditEvent::PossibleAltAccount { other_discord_id, other_accounts, .. } =>
+                    e.description("Another Discord account has also proven control of \
+                                   this Roblox account.")
+                        .field("Other Discord account",
+                               format!("{} (<@{}>)",
+                                       util::get_discord_username(other_discord_id),
+                                       other_discord_id.0), false)
+                        .field("Other accounts", other_accounts, true),
+            };
+            if event.is_warning() {
+                theme.decorate(e, Some(&headshot))
+            } else {
+                theme.apply(e, Some(&headshot))
+            }
+        })).map_err(Error::from).drop_nonfatal()?;
+        Ok(())
+    }
+
+    // Posts a summary of an incident or admin action to the audit channels of some servers.
+    fn post_incident(
+        &self, guilds: &[GuildId], title: &str, description: &str, fields: &[(String, String)],
+    ) -> Result<()> {
+        for &guild in guilds {
+            if let Some(channel) = self.0.config.get(Some(guild), ConfigKeys::AuditChannel)? {
+                let theme = self.theme(guild)?;
+                channel.send_message(|m| m.embed(|e| {
+                    let mut e = e.title(title).colour(COLOUR_WARNING).description(description);
+                    for &(ref name, ref value) in fields {
+                        e = e.field(name, value, false);
+                    }
+                    theme.decorate(e, None)
+                })).map_err(Error::from).drop_nonfatal()?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn log(&self, guild_id: Option<GuildId>, discord_id: UserId, event: AuditEvent) {
+        if let Some(guild_id) = guild_id {
+            let audit = self.clone();
+            self.0.tasks.dispatch_task(move |_| audit.post(guild_id, discord_id, event))
+        }
+    }
+    pub fn log_rename(
+        &self, discord_id: UserId, roblox_id: RobloxUserID, old_name: String, new_name: String,
+    ) {
+        let audit = self.clone();
+        self.0.tasks.dispatch_task(move |_| {
+            audit.post_rename(discord_id, roblox_id, &old_name, &new_name)
+        })
+    }
+    pub fn log_group_rank_failed(
+        &self, guild_id: GuildId, discord_id: UserId, roblox_id: RobloxUserID, group_id: u64,
+        rank: u32, error: String,
+    ) {
+        let audit = self.clone();
+        self.0.tasks.dispatch_task(move |_| {
+            audit.post_group_rank_failed(guild_id, discord_id, roblox_id, group_id, rank, &error)
+        })
+    }
+    // Logs a security incident to the audit channel of every server.
+    pub fn log_incident(&self, title: String, description: String, fields: Vec<(String, String)>) {
+        let audit = self.clone();
+        self.0.tasks.dispatch_task(move |_| {
+            let guilds: Vec<GuildId> = serenity::CACHE.read().guilds.keys().cloned().collect();
+            audit.post_incident(&guilds, &title, &description, &fields)
+        })
+    }
+    pub fn log_guild_incident(
+        &self, guild_id: GuildId, title: String, description: String,
+        fields: Vec<(String, String)>,
+    ) {
+        let audit = self.clone();
+        self.0.tasks.dispatch_task(move |_| {
+            audit.post_incident(&[guild_id], &title, &description, &fields)
+        })
+    }
+    // Sends the bot owner a direct message, for problems only they can fix.
+    pub fn alert_owner(&self, message: String) {
+        let audit = self.clone();
+        self.0.tasks.dispatch_task(move |_| {
+            if let Some(owner) = audit.0.config.get(None, ConfigKeys::BotOwnerId)? {
+                if let Err(e) = UserId(owner).create_dm_channel().and_then(|x| x.say(&message)) {
+                    warn!("Could not send an alert to the bot owner: {}", e);
+                }
+            }
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,162 @@
+use database::*;
+use errors::*;
+use roblox::RobloxUserID;
+use serenity::model::prelude::*;
+use std::sync::Arc;
+use std::time::SystemTime;
+use util::ConcurrentCache;
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum BlacklistTarget {
+    User(RobloxUserID), Group(u64),
+}
+impl BlacklistTarget {
+    fn to_db(self) -> (bool, u64) {
+        match self {
+            BlacklistTarget::User(id) => (false, id.0),
+            BlacklistTarget::Group(id) => (true, id),
+        }
+    }
+    fn from_db(is_group: bool, id: u64) -> BlacklistTarget {
+        if is_group {
+            BlacklistTarget::Group(id)
+        } else {
+            BlacklistTarget::User(RobloxUserID(id))
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct BlacklistEntry {
+    pub target: BlacklistTarget, pub reason: Option<String>,
+}
+
+struct BlacklistManagerData {
+    database: Database, entries: ConcurrentCache<GuildId, Arc<Vec<BlacklistEntry>>>,
+}
+
+#[derive(Clone)]
+pub struct BlacklistManager(Arc<BlacklistManagerData>);
+impl BlacklistManager {
+    pub fn new(database: Database) -> BlacklistManager {
+        let db_ref_update = database.clone();
+        BlacklistManager(Arc::new(BlacklistManagerData {
+            database,
+            entries: ConcurrentCache::new(move |&guild_id| {
+                Ok(Arc::new(Self::get_entries_db(&db_ref_update, guild_id)?))
+            }),
+        }))
+    }
+
+    fn get_entries_db(database: &Database, guild_id: GuildId) -> Result<Vec<BlacklistEntry>> {
+        let entries = database.connect()?.query(
+            "SELECT is_group, roblox_id, reason FROM guild_blacklist WHERE discord_guild_id = ?1",
+            guild_id,
+        ).get_all::<(bool, u64, Option<String>)>()?;
+        Ok(entries.into_iter().map(|(is_group, id, reason)| BlacklistEntry {
+            target: BlacklistTarget::from_db(is_group, id), reason,
+        }).collect())
+    }
+    pub fn get_entries(&self, guild_id: GuildId) -> Result<Arc<Vec<BlacklistEntry>>> {
+        Ok(self.0.entries.read(&guild_id)?.clone())
+    }
+    fn refresh(&self, guild_id: GuildId) -> Result<()> {
+        *self.0.entries.write(&guild_id)? =
+            Arc::new(Self::get_entries_db(&self.0.database, guild_id)?);
+        Ok(())
+    }
+
+    pub fn add(
+        &self, guild_id: GuildId, target: BlacklistTarget, reason: Option<&str>,
+    ) -> Result<()> {
+        let (is_group, id) = target.to_db();
+        self.0.database.connect()?.execute(
+            "REPLACE INTO guild_blacklist (discord_guild_id, is_group, roblox_id, reason) \
+             VALUES (?1, ?2, ?3, ?4)", (guild_id, is_group, id, reason),
+        )?;
+        self.refresh(guild_id)
+    }
+    pub fn remove(&self, guild_id: GuildId, target: BlacklistTarget) -> Result<bool> {
+        let (is_group, id) = target.to_db();
+        let removed = self.0.database.connect()?.execute(
+            "DELETE FROM guild_blacklist \
+             WHERE discord_guild_id = ?1 AND is_group = ?2 AND roblox_id = ?3",
+            (guild_id, is_group, id),
+        )? != 0;
+        self.refresh(guild_id)?;
+        Ok(removed)
+    }
+
+    // Returns the matching blacklist entry, if the Roblox account is blacklisted in the guild.
+    pub fn check(
+        &self, guild_id: GuildId, roblox_id: RobloxUserID,
+    ) -> Result<Option<BlacklistEntry>> {
+        let entries = self.get_entries(guild_id)?;
+        let user_target = BlacklistTarget::User(roblox_id);
+        if let Some(entry) = entries.iter().find(|x| x.target == user_target) {
+            return Ok(Some(entry.clone()))
+        }
+        let has_groups = entries.iter().any(|x| match x.target {
+            BlacklistTarget::Group(_) => true,
+            _ => false,
+        });
+        if has_groups {
+            let groups = roblox_id.get_group_ranks()?;
+            for entry in entries.iter() {
+                if let BlacklistTarget::Group(group_id) = entry.target {
+                    if groups.iter().any(|x| x.group_id == group_id) {
+                        return Ok(Some(entry.clone()))
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    // Records the Roblox accounts linked to a Discord user who was banned from a guild, so they
+    // cannot be verified there again by another Discord account.
+    pub fn record_ban(&self, guild_id: GuildId, discord_id: UserId) -> Result<usize> {
+        let conn = self.0.database.connect()?;
+        let roblox_ids = conn.query(
+            "SELECT roblox_user_id FROM discord_user_info \
+             WHERE discord_user_id = ?1 AND roblox_user_id IS NOT NULL \
+             UNION \
+             SELECT roblox_user_id FROM discord_user_alt_accounts WHERE discord_user_id = ?1",
+            discord_id,
+        ).get_all::<RobloxUserID>()?;
+        let now = SystemTime::now();
+        conn.transaction_immediate(|| {
+            for &roblox_id in &roblox_ids {
+                conn.execute(
+                    "REPLACE INTO banned_roblox_ids \
+                         (discord_guild_id, roblox_user_id, discord_user_id, banned_at) \
+                     VALUES (?1, ?2, ?3, ?4)", (guild_id, roblox_id, discord_id, now),
+                )?;
+            }
+            Ok(())
+        })?;
+        Ok(roblox_ids.len())
+    }
+    pub fn remove_ban(&self, guild_id: GuildId, discord_id: UserId) -> Result<usize> {
+        Ok(self.0.database.connect()?.execute(
+            "DELETE FROM banned_roblox_ids WHERE discord_guild_id = ?1 AND discord_user_id = ?2",
+            (guild_id, discord_id),
+        )? as usize)
+    }
+    // Returns the banned Discord user the Roblox account was linked to, if any.
+    pub fn check_banned(
+        &self, guild_id: GuildId, roblox_id: RobloxUserID,
+    ) -> Result<Option<UserId>> {
+        self.0.database.connect()?.query(
+            "SELECT discord_user_id FROM banned_roblox_ids \
+             WHERE discord_guild_id = ?1 AND roblox_user_id = ?2", (guild_id, roblox_id),
+        ).get_opt()
+    }
+
+    pub fn on_cleanup_tick(&self) {
+        self.0.entries.shrink_to_fit();
+    }
+    pub fn on_guild_remove(&self, guild: GuildId) {
+        self.0.entries.remove(&guild);
+    }
+}
@@ -1,12 +1,18 @@
 use core::VerifierCore;
+use core::event_log::EVENT_CONFIG_CHANGE;
 use database::*;
 use errors::*;
+use i18n;
+use log::LevelFilter;
 use parking_lot::RwLock;
-use serenity::model::prelude::GuildId;
+use serenity::model::prelude::{GuildId, ChannelId, RoleId};
 use std::any::{Any, TypeId};
+use std::env;
+use std::env::VarError;
 use std::marker::PhantomData;
 use std::mem;
 use std::ops::{Deref, DerefMut};
+use std::path::PathBuf;
 use std::sync::Arc;
 use util::ConcurrentCache;
 
@@ -73,6 +79,45 @@ fn reset_db(
     Ok(())
 }
 
+// A setting as it is stored in the database, used to copy settings without knowing their types.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RawConfigValue {
+    Null, Integer(i64), Text(String),
+}
+impl FromSql for RawConfigValue {
+    fn from_sql(value: ValueRef) -> Result<Self> {
+        Ok(match value {
+            ValueRef::Null => RawConfigValue::Null,
+            ValueRef::Integer(i) => RawConfigValue::Integer(i),
+            ValueRef::Text(s) => RawConfigValue::Text(s.to_owned()),
+            _ => bail!("Unexpected setting type in the database."),
+        })
+    }
+}
+impl RawConfigValue {
+    // Returns a value as it would be stored in the database.
+    pub fn from_value<T: ToSql>(value: &T) -> Result<RawConfigValue> {
+        Ok(match value.to_sql()? {
+            ToSqlOutput::Borrowed(ValueRef::Null) | ToSqlOutput::Owned(Value::Null) =>
+                RawConfigValue::Null,
+            ToSqlOutput::Borrowed(ValueRef::Integer(i)) | ToSqlOutput::Owned(Value::Integer(i)) =>
+                RawConfigValue::Integer(i),
+            ToSqlOutput::Borrowed(ValueRef::Text(s)) => RawConfigValue::Text(s.to_owned()),
+            ToSqlOutput::Owned(Value::Text(s)) => RawConfigValue::Text(s),
+            _ => bail!("Unexpected setting type."),
+        })
+    }
+}
+impl ToSql for RawConfigValue {
+    fn to_sql(&self) -> Result<ToSqlOutput> {
+        match *self {
+            RawConfigValue::Null => Ok(ToSqlOutput::Owned(Value::Null)),
+            RawConfigValue::Integer(i) => i.to_sql(),
+            RawConfigValue::Text(ref s) => s.to_sql(),
+        }
+    }
+}
+
 #[inline(never)]
 fn get_db_type_panic() -> ! {
     panic!("Incorrect types in get_db!")
@@ -84,6 +129,19 @@ macro_rules! config_keys {
         enum ConfigKeyName {
             $($name,)*
         }
+        impl ConfigKeyName {
+            fn name(self) -> &'static str {
+                match self {
+                    $(ConfigKeyName::$name => stringify!($name),)*
+                }
+            }
+            fn from_name(name: &str) -> Option<ConfigKeyName> {
+                match name {
+                    $(stringify!($name) => Some(ConfigKeyName::$name),)*
+                    _ => None,
+                }
+            }
+        }
 
         #[allow(non_snake_case)]
         struct ConfigCache {
@@ -115,6 +173,12 @@ macro_rules! config_keys {
                 }
                 Ok(())
             }
+            // Makes the next read of a field load it from the database again.
+            fn forget_field(&self, name: ConfigKeyName) {
+                match name {
+                    $(ConfigKeyName::$name => *self.$name.write() = None,)*
+                }
+            }
             fn after_update(
                 &self, core: &VerifierCore, name: ConfigKeyName, guild: Option<GuildId>,
             ) -> Result<()> {
@@ -140,20 +204,29 @@ macro_rules! config_keys {
                             get_db_type_panic()
                         } else {
                             set_db(conn, guild, stringify!($name), &value)?;
-
-                            // This will only execute if transmute is an noop, so this is safe.
-                            {
-                                let mut write_ptr = self.$name.write();
-                                let mut transmute: &mut Option<Option<T>> = unsafe {
-                                    mem::transmute(write_ptr.deref_mut())
-                                };
-                                *transmute = Some(Some(value));
-                            }
                         }
                     })*
                 }
+                self.set_cached(key, value);
                 self.after_update(core, key.enum_name, guild)
             }
+            // Changes the value of a field without storing it in the database.
+            fn set_cached<T: 'static>(&self, key: ConfigKey<T>, value: T) {
+                match key.enum_name {
+                    $(ConfigKeyName::$name => {
+                        if TypeId::of::<T>() != TypeId::of::<$tp>() {
+                            get_db_type_panic()
+                        } else {
+                            // This will only execute if transmute is an noop, so this is safe.
+                            let mut write_ptr = self.$name.write();
+                            let mut transmute: &mut Option<Option<T>> = unsafe {
+                                mem::transmute(write_ptr.deref_mut())
+                            };
+                            *transmute = Some(Some(value));
+                        }
+                    })*
+                }
+            }
             fn get<T: FromSql + Clone + 'static>(
                 &self, conn: &DatabaseConnection, guild: Option<GuildId>, key: ConfigKey<T>,
             ) -> Result<Option<T>> {
@@ -204,9 +277,46 @@ macro_rules! config_keys {
 
 config_keys! {
     // Discord settings
-    CommandPrefix<String>("!".to_owned(), |_, core| core.refresh_place());
+    CommandPrefix<String>("!".to_owned(), |guild, core| match guild {
+        Some(_) => core.verify_channel().update(guild),
+        None => core.refresh_place(),
+    });
+    Language<String>(i18n::DEFAULT_LANGUAGE.to_owned(),
+        |guild, core| core.verify_channel().update(guild));
     DiscordToken<Option<String>>(None, |_, core| core.discord().reconnect());
+    DiscordShardCount<u64>(0, |_, core| core.discord().reconnect());
     BotOwnerId<Option<u64>>(None);
+    ActionApprovers<Option<String>>(None);
+    ActionApprovalChannel<Option<ChannelId>>(None);
+    ActionApprovalTimeoutMinutes<u32>(15);
+    CommandRateLimitPerMinute<u32>(10);
+    CommandRateLimitBurst<u32>(5);
+    CommandRateLimitExemptRoles<Option<String>>(None);
+
+    // Web API settings
+    WebServerAddress<Option<String>>(None, |_, core| core.web().restart());
+    WebApiKey<Option<String>>(None);
+    WebBadgeSecret<Option<String>>(None);
+    WebBadgeRateLimit<u32>(60);
+    WebApiRateLimit<u32>(120);
+    WebPublicUrl<Option<String>>(None, |_, core| core.refresh_place());
+    DiscordOAuthClientId<Option<String>>(None);
+    DiscordOAuthClientSecret<Option<String>>(None);
+    RobloxOAuthClientId<Option<String>>(None);
+    RobloxOAuthClientSecret<Option<String>>(None);
+    RobloxOAuthRedirectUri<Option<String>>(None);
+
+    // Database settings
+    BackupDirectory<Option<String>>(None);
+
+    // Error reporting settings
+    ErrorReportUrl<Option<String>>(None, |_, core| core.update_error_reporting());
+    ErrorReportFormat<String>("sentry".to_owned(), |_, core| core.update_error_reporting());
+
+    // Roblox API settings
+    RobloxCacheSeconds<u32>(60 * 5, |_, core| core.refresh_api_caches());
+    RobloxHeadshotCacheSeconds<u32>(60 * 60, |_, core| core.refresh_api_caches());
+    RobloxCacheStaleSeconds<u32>(60 * 5, |_, core| core.refresh_api_caches());
 
     // Limits for verification rules
     RolesEnableLimits<bool>(false, |_, core| Ok(core.roles().clear_rule_cache()));
@@ -217,9 +327,23 @@ config_keys! {
 
     // Role management settings
     SetNickname<bool>(true);
+    NicknameFormat<String>("{roblox_name}".to_owned());
+    NicknameSource<String>("username".to_owned());
+
+    SetRolesOnVerify<bool>(true);
+    VerifiedRole<Option<RoleId>>(None);
+    UnverifiedRole<Option<RoleId>>(None);
 
     AllowSetRolesOnJoin<bool>(true);
     SetRolesOnJoin<bool>(false);
+    JoinGate<bool>(false);
+    UnverifiedGracePeriodHours<u32>(0);
+    UnverifiedWarningHours<u32>(24);
+    UnverifiedAction<String>("kick".to_owned());
+    UnverifiedExemptRoles<Option<String>>(None);
+    VerificationReminders<bool>(false);
+    VerificationReminderDays<String>("1,3,7".to_owned());
+    RoleDecayDays<u32>(0);
 
     AllowEnableAutoUpdate<bool>(true);
     EnableAutoUpdate<bool>(false);
@@ -237,27 +361,158 @@ config_keys! {
          command on it.".to_owned(),
          |_, core| core.refresh_place());
     PlaceUIBackground<Option<String>>(None, |_, core| core.refresh_place());
+    PlaceUIShowTokenCountdown<bool>(true, |_, core| core.refresh_place());
     PlaceID<Option<u64>>(None, |guild, core| core.verify_channel().update(guild));
+    PlaceUniverseID<Option<u64>>(None);
+    OpenCloudApiKey<Option<String>>(None);
+    PlacePushSecret<Option<String>>(None, |_, core| core.refresh_place());
+    RequireIssuedTokens<bool>(false, |_, core| core.refresh_place());
 
     // Verification settings
+    VerificationMethodToken<bool>(true);
+    VerificationMethodProfile<bool>(false);
+    VerificationMethodOAuth<bool>(false);
+    VerificationMethodRobloxOAuth<bool>(false);
+    VerificationMethodFriend<bool>(false);
+    FriendVerificationCookie<Option<String>>(None);
+    // Replaces verifying by joining the verification place, which could not tell which Discord
+    // user joined it.
+    VerificationMethodPlaceCode<bool>(false);
+
+    MinimumRobloxAccountAgeDays<u32>(0);
+    RequiredGroupID<Option<u64>>(None);
+    RequiredGroupMinimumRank<Option<u32>>(None);
+    BlacklistMessage<String>("That Roblox account is not allowed to verify on this server."
+                                 .to_owned());
+    SyncBans<bool>(false);
+    AuditChannel<Option<ChannelId>>(None);
+    RequireApproval<bool>(false, |guild, core| core.approvals().approve_existing(guild));
+    ApprovalChannel<Option<ChannelId>>(None);
+    WebhookUrl<Option<String>>(None);
+    WebhookSecret<Option<String>>(None);
+    ProtectedRobloxNames<Option<String>>(None);
+    ProtectedNameMaxDistance<u32>(2);
+    NamePolicyPatterns<Option<String>>(None);
+    NamePolicyRequireApproval<bool>(false);
+    BloxlinkApiKey<Option<String>>(None);
+    AuditRenames<bool>(false);
+    StarterRankGroupID<Option<u64>>(None);
+    StarterRank<Option<u32>>(None);
+    JoinRequestGroupID<Option<u64>>(None);
+    JoinRequestAutoAccept<bool>(false);
+    JoinRequestAutoDecline<bool>(false);
+    GroupAdminApiKey<Option<String>>(None);
+    GroupAdminCookie<Option<String>>(None);
+    UsernameRefreshIntervalHours<u32>(24);
+
     VerificationAttemptLimit<u32>(10);
     VerificationCooldownSeconds<u64>(60 * 60 * 24);
+    VerificationHistoryDays<u32>(90);
+    UnverifyRestoreDays<u32>(7);
 
     VerificationChannelIntro<Option<String>>(None,
         |guild, core| core.verify_channel().update(guild));
     VerificationChannelDeleteSeconds<u32>(30);
     VerificationChannelFooter<Option<String>>(None,
         |guild, core| core.verify_channel().update(guild));
+    DeleteTokenMessages<bool>(true);
+
+    EmbedColour<Option<u32>>(None);
+    EmbedFooter<Option<String>>(None);
+    EmbedThumbnails<bool>(true);
+    VerifySuccessImageUrl<Option<String>>(None);
 
     TokenValiditySeconds<u32>(60 * 5, |_, core| {
         core.verifier().rekey(false)?;
         core.refresh_place()?;
         Ok(())
     });
+    TokenFormatVersion<u32>(1, |_, core| {
+        core.verifier().rekey(false)?;
+        core.refresh_place()?;
+        Ok(())
+    });
+    TokenLength<u32>(6, |_, core| {
+        core.verifier().rekey(false)?;
+        core.refresh_place()?;
+        Ok(())
+    });
+    TokenAlphabet<Option<String>>(None, |_, core| {
+        core.verifier().rekey(false)?;
+        core.refresh_place()?;
+        Ok(())
+    });
+    AutoRekeyIntervalDays<u32>(0);
 
     AllowReverifyDiscord<bool>(false);
     AllowReverifyRoblox<bool>(false);
     ReverificationCooldownSeconds<u64>(60 * 60 * 24);
+    MaxLinkedRobloxAccounts<u32>(1);
+}
+
+// Settings that can be given in environment variables, for deployments where the terminal and
+// database are inconvenient to reach, such as containers. They are applied at startup, and take
+// priority over the settings stored in the database.
+#[derive(Clone, Debug, Default)]
+pub struct EnvConfig {
+    pub discord_token: Option<String>,
+    pub database_path: Option<PathBuf>,
+    pub web_server_address: Option<String>,
+    pub log_level: Option<LevelFilter>,
+    pub high_availability: bool,
+    pub leader_lease_seconds: Option<u64>,
+}
+
+const ENV_DISCORD_TOKEN: &str = "SYLPH_DISCORD_TOKEN";
+const ENV_DATABASE_PATH: &str = "SYLPH_DATABASE_PATH";
+const ENV_WEB_SERVER_ADDRESS: &str = "SYLPH_WEB_SERVER_ADDRESS";
+const ENV_LOG_LEVEL: &str = "SYLPH_LOG_LEVEL";
+const ENV_HIGH_AVAILABILITY: &str = "SYLPH_HIGH_AVAILABILITY";
+const ENV_LEADER_LEASE_SECONDS: &str = "SYLPH_LEADER_LEASE_SECONDS";
+const MIN_LEADER_LEASE_SECONDS: u64 = 10;
+
+// Empty variables are treated as unset, as `docker run -e NAME=` is easy to write by mistake.
+fn env_var(name: &str) -> Result<Option<String>> {
+    match env::var(name) {
+        Ok(value) => Ok(if value.trim().is_empty() { None } else { Some(value.trim().to_owned()) }),
+        Err(VarError::NotPresent) => Ok(None),
+        Err(VarError::NotUnicode(_)) => bail!("{} is not valid Unicode.", name),
+    }
+}
+
+impl EnvConfig {
+    pub fn load() -> Result<EnvConfig> {
+        let log_level = match env_var(ENV_LOG_LEVEL)? {
+            Some(level) => match level.parse::<LevelFilter>() {
+                Ok(level) => Some(level),
+                Err(_) => bail!("{} must be one of off, error, warn, info, debug or trace.",
+                                ENV_LOG_LEVEL),
+            },
+            None => None,
+        };
+        let high_availability = match env_var(ENV_HIGH_AVAILABILITY)? {
+            Some(value) => match value.to_lowercase().as_str() {
+                "1" | "true" | "yes" | "on" => true,
+                "0" | "false" | "no" | "off" => false,
+                _ => bail!("{} must be true or false.", ENV_HIGH_AVAILABILITY),
+            },
+            None => false,
+        };
+        let leader_lease_seconds = match env_var(ENV_LEADER_LEASE_SECONDS)? {
+            Some(value) => match value.parse::<u64>() {
+                Ok(secs) if secs >= MIN_LEADER_LEASE_SECONDS => Some(secs),
+                _ => bail!("{} must be a number of seconds, at least {}.",
+                           ENV_LEADER_LEASE_SECONDS, MIN_LEADER_LEASE_SECONDS),
+            },
+            None => None,
+        };
+        Ok(EnvConfig {
+            discord_token: env_var(ENV_DISCORD_TOKEN)?,
+            database_path: env_var(ENV_DATABASE_PATH)?.map(PathBuf::from),
+            web_server_address: env_var(ENV_WEB_SERVER_ADDRESS)?,
+            log_level, high_availability, leader_lease_seconds,
+        })
+    }
 }
 
 struct ConfigManagerData {
@@ -289,12 +544,25 @@ impl ConfigManager {
     pub fn set<T : ToSql + Clone + Any + Send + Sync>(
         &self, core: &VerifierCore, guild: Option<GuildId>, key: ConfigKey<T>, val: T,
     ) -> Result<()> {
-        self.get_cache(guild)?.set(core, &self.0.database.connect()?, guild, key, val)
+        self.get_cache(guild)?.set(core, &self.0.database.connect()?, guild, key, val)?;
+        Self::record_change(core, guild, key.enum_name, "set");
+        Ok(())
     }
     pub fn reset<T: Clone + Any + Send + Sync>(
         &self, core: &VerifierCore, guild: Option<GuildId>, key: ConfigKey<T>
     ) -> Result<()> {
-        self.get_cache(guild)?.reset(core, &self.0.database.connect()?, guild, key.enum_name)
+        self.get_cache(guild)?.reset(core, &self.0.database.connect()?, guild, key.enum_name)?;
+        Self::record_change(core, guild, key.enum_name, "reset");
+        Ok(())
+    }
+    fn record_change(
+        core: &VerifierCore, guild: Option<GuildId>, name: ConfigKeyName, what: &str,
+    ) {
+        let details = match guild {
+            Some(guild) => format!("{} {} on server {}", name.name(), what, guild.0),
+            None => format!("{} {} globally", name.name(), what),
+        };
+        core.event_log().record(EVENT_CONFIG_CHANGE, details);
     }
 
     fn get_internal<T : ToSql + FromSql + Clone + Any + Send + Sync>(
@@ -315,6 +583,72 @@ impl ConfigManager {
     ) -> Result<T> {
         self.get_internal(&self.0.database.connect()?, guild, key)
     }
+    pub fn is_overridden<T : ToSql + FromSql + Clone + Any + Send + Sync>(
+        &self, guild: GuildId, key: ConfigKey<T>
+    ) -> Result<bool> {
+        let conn = self.0.database.connect()?;
+        Ok(self.get_cache(Some(guild))?.get(&conn, Some(guild), key)?.is_some())
+    }
+
+    // Sets many of a guild's settings at once. Either every setting is changed, or, if any of
+    // them has an unknown name or a value of the wrong type, none are.
+    pub fn import_guild(
+        &self, core: &VerifierCore, guild: GuildId, values: &[(String, RawConfigValue)],
+    ) -> Result<()> {
+        let mut names = Vec::new();
+        for &(ref name, _) in values {
+            names.push(ConfigKeyName::from_name(name)
+                .to_cmd_err(|| format!("Unknown setting '{}'.", name))?);
+        }
+        let conn = self.0.database.connect()?;
+        let cache = self.get_cache(Some(guild))?;
+        let result = conn.transaction_immediate(|| {
+            for (&(ref name, ref value), &key) in values.iter().zip(&names) {
+                set_db(&conn, Some(guild), name, value)?;
+                cache.forget_field(key);
+                if cache.init_field(&conn, Some(guild), key).is_err() {
+                    cmd_error!("The value given for '{}' is not the right type.", name)
+                }
+            }
+            Ok(())
+        });
+        if result.is_err() {
+            for &key in &names {
+                cache.forget_field(key);
+            }
+        }
+        result?;
+        for &key in &names {
+            if let Err(e) = cache.after_update(core, key, Some(guild)) {
+                warn!("Could not apply imported setting {} on {}: {}", key.name(), guild, e);
+            }
+            Self::record_change(core, Some(guild), key, "imported");
+        }
+        Ok(())
+    }
+
+    // Uses the settings given in environment variables instead of those in the database. This
+    // should be called before the bot is started, as no settings are reapplied.
+    pub fn apply_env(&self, env: &EnvConfig) {
+        if let Some(ref token) = env.discord_token {
+            info!("Using the Discord token from {}.", ENV_DISCORD_TOKEN);
+            self.0.global_cache.set_cached(ConfigKeys::DiscordToken, Some(token.clone()));
+        }
+        if let Some(ref address) = env.web_server_address {
+            info!("Using the web server address from {}.", ENV_WEB_SERVER_ADDRESS);
+            self.0.global_cache.set_cached(ConfigKeys::WebServerAddress, Some(address.clone()));
+        }
+    }
+
+    // Changes a setting without storing it or running what normally happens after it changes,
+    // for tests that do not have a running core.
+    #[cfg(test)]
+    pub fn set_for_test<T: 'static>(
+        &self, guild: Option<GuildId>, key: ConfigKey<T>, val: T,
+    ) -> Result<()> {
+        self.get_cache(guild)?.set_cached(key, val);
+        Ok(())
+    }
 
     pub fn on_cleanup_tick(&self) {
         self.0.guild_cache.shrink_to_fit();
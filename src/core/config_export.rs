@@ -0,0 +1,309 @@
+use core::VerifierCore;
+use core::config::RawConfigValue;
+use core::templates::MessageKey;
+use errors::*;
+use serenity::model::prelude::*;
+use std::char;
+use std::fmt::Write;
+
+// Exports are versioned, so that the format can be changed without misreading older ones.
+const EXPORT_FORMAT: i64 = 1;
+
+// Settings holding credentials are never exported, as exports are posted in server channels.
+fn is_secret(key_name: &str) -> bool {
+    ["Secret", "ApiKey", "Cookie", "Token"].iter().any(|x| key_name.ends_with(x))
+}
+
+// Exports only use a small part of TOML: integers, booleans and basic strings, in tables whose
+// names are made of bare or quoted keys. Imports are limited to the same subset.
+#[derive(Clone, Debug)]
+enum TomlValue {
+    Integer(i64), Boolean(bool), String(String),
+}
+
+fn is_bare_key(key: &str) -> bool {
+    !key.is_empty() && key.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+}
+fn toml_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04X}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+fn toml_key(key: &str) -> String {
+    if is_bare_key(key) { key.to_owned() } else { toml_string(key) }
+}
+
+// Parses a basic string at the start of `s`, and returns it with the rest of `s`.
+fn parse_string(s: &str) -> Result<(String, &str)> {
+    let mut out = String::new();
+    let mut chars = s.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((out, &s[i + 1..])),
+            '\\' => match chars.next().map(|x| x.1) {
+                Some('n') => out.push('\n'),
+                Some('r') => out.push('\r'),
+                Some('t') => out.push('\t'),
+                Some('"') => out.push('"'),
+                Some('\\') => out.push('\\'),
+                Some('u') => {
+                    let hex: String = (0..4).filter_map(|_| chars.next().map(|x| x.1)).collect();
+                    out.push(u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32)
+                        .to_cmd_err(|| format!("Invalid escape `\\u{}`.", hex))?);
+                }
+                _ => cmd_error!("Invalid escape sequence in string."),
+            },
+            c => out.push(c),
+        }
+    }
+    cmd_error!("Unclosed string.")
+}
+fn parse_key(s: &str) -> Result<(String, &str)> {
+    let s = s.trim_left();
+    if s.starts_with('"') {
+        parse_string(s)
+    } else {
+        let end = s.find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'))
+            .unwrap_or(s.len());
+        cmd_ensure!(end != 0, "Expected a key.");
+        Ok((s[..end].to_owned(), &s[end..]))
+    }
+}
+fn check_line_end(rest: &str) -> Result<()> {
+    let rest = rest.trim();
+    cmd_ensure!(rest.is_empty() || rest.starts_with('#'), "Unexpected text: {}", rest);
+    Ok(())
+}
+fn parse_value(s: &str) -> Result<TomlValue> {
+    let s = s.trim();
+    if s.starts_with('"') {
+        cmd_ensure!(!s.starts_with("\"\"\""), "Multi-line strings are not supported.");
+        let (value, rest) = parse_string(s)?;
+        check_line_end(rest)?;
+        return Ok(TomlValue::String(value))
+    }
+    let end = s.find(|c: char| c.is_whitespace() || c == '#').unwrap_or(s.len());
+    check_line_end(&s[end..])?;
+    Ok(match &s[..end] {
+        "true" => TomlValue::Boolean(true),
+        "false" => TomlValue::Boolean(false),
+        value => TomlValue::Integer(value.replace('_', "").parse().ok()
+            .to_cmd_err(|| format!("Unsupported value: {}", value))?),
+    })
+}
+
+fn parse_table_header(line: &str) -> Result<Vec<String>> {
+    cmd_ensure!(!line.starts_with("[["), "Arrays of tables are not supported.");
+    let mut path = Vec::new();
+    let mut rest = &line[1..];
+    loop {
+        let (key, after) = parse_key(rest)?;
+        path.push(key);
+        let after = after.trim_left();
+        if after.starts_with('.') {
+            rest = &after[1..];
+        } else {
+            cmd_ensure!(after.starts_with(']'), "Expected `]`.");
+            check_line_end(&after[1..])?;
+            return Ok(path)
+        }
+    }
+}
+fn parse_entry(line: &str) -> Result<(String, TomlValue)> {
+    let (key, rest) = parse_key(line)?;
+    let rest = rest.trim_left();
+    cmd_ensure!(rest.starts_with('='), "Expected `=` after `{}`.", key);
+    Ok((key, parse_value(&rest[1..])?))
+}
+
+// Returns every key in a document along with the table it is in.
+fn parse_toml(data: &str) -> Result<Vec<(Vec<String>, String, TomlValue)>> {
+    let mut entries = Vec::new();
+    let mut table = Vec::new();
+    for (i, line) in data.lines().enumerate() {
+        let line = line.trim();
+        let result = if line.is_empty() || line.starts_with('#') {
+            Ok(())
+        } else if line.starts_with('[') {
+            parse_table_header(line).map(|path| table = path)
+        } else {
+            parse_entry(line).map(|(key, value)| entries.push((table.clone(), key, value)))
+        };
+        match result {
+            Err(Error::CommandError(err)) => cmd_error!("Line {}: {}", i + 1, err),
+            result => result?,
+        }
+    }
+    Ok(entries)
+}
+
+// Settings are read back with the same parser as `set`, so they are checked the same way.
+fn to_setting_text(value: TomlValue) -> String {
+    match value {
+        TomlValue::Integer(i) => i.to_string(),
+        TomlValue::Boolean(b) => b.to_string(),
+        TomlValue::String(s) => s,
+    }
+}
+
+// A setting that can be set per-server, by the name used in commands and the name of the key it
+// is stored under. `parse` reads the text given to `set`, and `export` returns the text of a
+// server's value if it has changed it.
+pub struct GuildSetting {
+    pub name: &'static str, pub key_name: &'static str,
+    pub parse: fn(&str) -> Result<RawConfigValue>,
+    pub export: fn(&VerifierCore, GuildId) -> Result<Option<String>>,
+}
+
+// Writes a guild's settings, role bindings and message templates as a TOML document.
+pub fn export_config(
+    core: &VerifierCore, guild_id: GuildId, settings: &[GuildSetting],
+) -> Result<String> {
+    let mut out = String::new();
+    let server = guild_id.find().map_or_else(|| guild_id.to_string(), |x| x.read().name.clone());
+    writeln!(out, "# Configuration of {} ({}).", server, guild_id.0)?;
+    writeln!(out, "# Settings containing credentials are not included.")?;
+    writeln!(out, "format = {}", EXPORT_FORMAT)?;
+
+    writeln!(out, "\n[settings]")?;
+    for setting in settings.iter().filter(|x| !is_secret(x.key_name)) {
+        if let Some(value) = (setting.export)(core, guild_id)? {
+            writeln!(out, "{} = {}", setting.name, toml_string(&value))?;
+        }
+    }
+
+    let mut roles: Vec<_> = core.roles().get_configuration(guild_id)?.into_iter().collect();
+    roles.sort_by(|a, b| a.0.cmp(&b.0));
+    for (rule_name, role) in roles {
+        writeln!(out, "\n[roles.{}]", toml_key(&rule_name))?;
+        if let Some(rule) = role.custom_rule {
+            writeln!(out, "rule = {}", toml_string(&rule))?;
+        }
+        if let Some(role_id) = role.role_id {
+            writeln!(out, "role = {}", role_id.0)?;
+        }
+    }
+
+    writeln!(out, "\n[templates]")?;
+    for &key in MessageKey::ALL {
+        if let Some(template) = core.templates().get(guild_id, key)? {
+            writeln!(out, "{} = {}", key.name(), toml_string(&template))?;
+        }
+    }
+    Ok(out)
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ConfigImportSummary {
+    pub settings: usize, pub roles: usize, pub templates: usize, pub missing_roles: Vec<String>,
+}
+
+struct ImportedRole {
+    name: String, rule: Option<String>, role: Option<RoleId>,
+}
+
+// Applies a document written by `export_config` to a guild. Settings, role bindings and templates
+// not in the document are left alone. Role bindings for roles that do not exist in the guild are
+// imported without their role, and listed in the summary.
+// `check_role` is called with every role the file would bind before anything is changed, and
+// the import is rejected if it fails for any of them.
+pub fn import_config(
+    core: &VerifierCore, guild_id: GuildId, settings: &[GuildSetting], data: &str,
+    check_role: &Fn(RoleId) -> Result<()>,
+) -> Result<ConfigImportSummary> {
+    let mut format = None;
+    let mut values = Vec::new();
+    let mut setting_roles = Vec::new();
+    let mut roles: Vec<ImportedRole> = Vec::new();
+    let mut templates = Vec::new();
+    for (table, key, value) in parse_toml(data)? {
+        let section = table.first().map(|x| x.as_str());
+        match (section, table.len(), value) {
+            (None, _, TomlValue::Integer(i)) if key == "format" => format = Some(i),
+            (Some("settings"), 1, value) => {
+                let setting = settings.iter().find(|x| x.name == key)
+                    .to_cmd_err(|| format!("'{}' cannot be set per-server.", key))?;
+                cmd_ensure!(!is_secret(setting.key_name), "'{}' cannot be imported.", key);
+                let text = to_setting_text(value);
+                let value = match (setting.parse)(&text) {
+                    Err(Error::CommandError(err)) => cmd_error!("'{}': {}", key, err),
+                    value => value?,
+                };
+                if setting.key_name == "VerifiedRole" || setting.key_name == "UnverifiedRole" {
+                    setting_roles.push(RoleId(text.trim().trim_left_matches("<@&")
+                                                  .trim_right_matches('>').parse()?));
+                }
+                values.push((setting.key_name.to_owned(), value));
+            }
+            (Some("roles"), 2, value) => {
+                let rule_name = &table[1];
+                if !roles.iter().any(|x| &x.name == rule_name) {
+                    roles.push(ImportedRole { name: rule_name.clone(), rule: None, role: None });
+                }
+                let role = roles.iter_mut().find(|x| &x.name == rule_name)?;
+                match (key.as_str(), value) {
+                    ("rule", TomlValue::String(rule)) => role.rule = Some(rule),
+                    ("role", TomlValue::Integer(id)) => role.role = Some(RoleId(id as u64)),
+                    (key, _) => cmd_error!("Unknown or invalid key '{}' in role '{}'.",
+                                           key, rule_name),
+                }
+            }
+            (Some("templates"), 1, TomlValue::String(template)) =>
+                templates.push((MessageKey::from_name(&key)?, template)),
+            _ => cmd_error!("Unexpected key '{}' in {}.", key,
+                            if table.is_empty() { "the document".to_owned() }
+                            else { format!("[{}]", table.join(".")) }),
+        }
+    }
+    cmd_ensure!(format == Some(EXPORT_FORMAT),
+                "This is not a configuration export, or it was made by a newer version of the \
+                 bot.");
+
+    let guild_roles: Vec<RoleId> = match guild_id.find() {
+        Some(guild) => guild.read().roles.keys().cloned().collect(),
+        None => Vec::new(),
+    };
+    let bound_roles = roles.iter().filter_map(|x| x.role).filter(|x| guild_roles.contains(x));
+    for role_id in setting_roles.iter().cloned().chain(bound_roles) {
+        match check_role(role_id) {
+            Err(Error::CommandError(err)) =>
+                cmd_error!("Nothing was imported. <@&{}>: {}", role_id.0, err),
+            result => result?,
+        }
+    }
+
+    let mut summary = ConfigImportSummary::default();
+    core.config().import_guild(core, guild_id, &values)?;
+    summary.settings = values.len();
+
+    for role in roles {
+        if role.rule.is_some() {
+            core.roles().set_custom_rule(guild_id, &role.name,
+                                         role.rule.as_ref().map(|x| x.as_str()))?;
+        }
+        match role.role {
+            Some(role_id) if guild_roles.contains(&role_id) =>
+                core.roles().set_active_role(guild_id, &role.name, Some(role_id))?,
+            Some(_) => summary.missing_roles.push(role.name.clone()),
+            None => { }
+        }
+        summary.roles += 1;
+    }
+
+    for (key, template) in templates {
+        core.templates().set(guild_id, key, Some(&template))?;
+        summary.templates += 1;
+    }
+    Ok(summary)
+}
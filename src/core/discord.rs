@@ -1,18 +1,23 @@
 use commands::*;
 use core::CoreRef;
+use core::blacklist::*;
 use core::config::*;
+use core::embeds::EmbedTheme;
 use core::roles::*;
 use core::tasks::*;
 use core::verification_channel::*;
 use errors::*;
 use error_report;
 use parking_lot::{Mutex, RwLock};
+use serde_json::Value;
 use serenity;
 use serenity::Client;
 use serenity::client::bridge::gateway::ShardManager;
 use serenity::model::prelude::*;
 use serenity::prelude::*;
 use std::borrow::Cow;
+use std::cmp::max;
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
 use std::thread;
@@ -26,22 +31,13 @@ use util::MultiMutex;
 struct DiscordContext<'a> {
     ctx: Context, message: &'a Message, content: &'a str, prefix: String,
     privilege_level: PrivilegeLevel, command_target: CommandTarget, command_no: usize,
-    is_verification_channel: bool, delete_in: u32, tasks: TaskManager,
+    is_verification_channel: bool, delete_in: u32, tasks: TaskManager, theme: EmbedTheme,
 }
-impl <'a> CommandContextData for DiscordContext<'a> {
-    fn privilege_level(&self) -> PrivilegeLevel {
-        self.privilege_level
-    }
-    fn command_target(&self) -> CommandTarget {
-        self.command_target
-    }
-    fn prefix(&self) -> &str {
-        &self.prefix
-    }
-    fn message_content(&self) -> &str {
-        self.content
-    }
-    fn respond(&self, message: &str) -> Result<()> {
+impl <'a> DiscordContext<'a> {
+    fn send_response(
+        &self, message: &str, thumbnail_url: Option<&str>, image_url: Option<&str>,
+        embed: Option<&ResponseEmbed>,
+    ) -> Result<()> {
         for line in message.split('\n') {
             debug!(target: "$raw", "[Command #{}] {}", self.command_no, line);
         }
@@ -51,13 +47,29 @@ impl <'a> CommandContextData for DiscordContext<'a> {
         } else {
             String::new()
         };
-        let message = self.message.channel_id.send_message(|m|
-            if message.contains('\n') {
-                m.content(format_args!("<@{}>\n{}{}", self.message.author.id, message, verify_tail))
-            } else {
-                m.content(format_args!("<@{}> {}{}", self.message.author.id, message, verify_tail))
-            }
-        )?;
+        let author = self.message.author.id;
+        let message = self.message.channel_id.send_message(|m| match (thumbnail_url, embed) {
+            // The message is moved into an embed, so the image is shown next to it.
+            (Some(url), _) => m.content(format_args!("<@{}>{}", author, verify_tail))
+                .embed(|e| {
+                    let e = self.theme.apply(e.description(message), Some(url));
+                    match image_url {
+                        Some(image_url) => e.image(image_url),
+                        None => e,
+                    }
+                }),
+            (None, Some(embed)) => m.content(format_args!("<@{}>{}", author, verify_tail))
+                .embed(|e| {
+                    let mut e = e.title(&embed.title).description(&embed.description);
+                    for &(ref name, ref value, inline) in &embed.fields {
+                        e = e.field(name, value, inline);
+                    }
+                    self.theme.apply(e, None)
+                }),
+            (None, None) if message.contains('\n') =>
+                m.content(format_args!("<@{}>\n{}{}", author, message, verify_tail)),
+            (None, None) => m.content(format_args!("<@{}> {}{}", author, message, verify_tail)),
+        })?;
         if self.is_verification_channel {
             self.tasks.dispatch_delayed_task(Duration::from_secs(self.delete_in as u64), move |_| {
                 message.delete().map_err(Error::from).drop_nonfatal()
@@ -65,6 +77,31 @@ impl <'a> CommandContextData for DiscordContext<'a> {
         }
         Ok(())
     }
+}
+impl <'a> CommandContextData for DiscordContext<'a> {
+    fn privilege_level(&self) -> PrivilegeLevel {
+        self.privilege_level
+    }
+    fn command_target(&self) -> CommandTarget {
+        self.command_target
+    }
+    fn prefix(&self) -> &str {
+        &self.prefix
+    }
+    fn message_content(&self) -> &str {
+        self.content
+    }
+    fn respond(&self, message: &str) -> Result<()> {
+        self.send_response(message, None, None, None)
+    }
+    fn respond_with_thumbnail(
+        &self, message: &str, thumbnail_url: &str, image_url: Option<&str>,
+    ) -> Result<()> {
+        self.send_response(message, Some(thumbnail_url), image_url, None)
+    }
+    fn respond_embed(&self, embed: &ResponseEmbed) -> Result<()> {
+        self.send_response(&embed.to_text(), None, None, Some(embed))
+    }
     fn discord_context(&self) -> Option<(&Context, &Message)> {
         Some((&self.ctx, self.message))
     }
@@ -78,7 +115,8 @@ const STATUS_DROPPED : u8 = 4;
 
 struct DiscordBotSharedData {
     config: ConfigManager, core_ref: CoreRef, roles: RoleManager, tasks: TaskManager,
-    verify_channel: VerificationChannelManager, is_in_command: MultiMutex<UserId>,
+    verify_channel: VerificationChannelManager, blacklist: BlacklistManager,
+    is_in_command: MultiMutex<UserId>,
 }
 
 struct Handler {
@@ -155,6 +193,7 @@ impl Handler {
             None => (false, 0),
         };
         let tasks = self.shared.tasks.clone();
+        let theme = EmbedTheme::load(&self.shared.config, guild_id)?;
 
         thread::Builder::new().name(format!("command #{}", command_no)).spawn(move || {
             error_report::catch_error(move || {
@@ -162,7 +201,7 @@ impl Handler {
                 let ctx = DiscordContext {
                     ctx, message: &message, prefix, content: &content,
                     privilege_level, command_target, command_no,
-                    is_verification_channel, delete_in, tasks,
+                    is_verification_channel, delete_in, tasks, theme,
                 };
                 if let Some(_lock) = is_in_command.lock(message.author.id) {
                     core_ref.run_command(command, &ctx);
@@ -183,6 +222,7 @@ impl Handler {
         self.shared.roles.on_guild_remove(guild_id);
         self.shared.config.on_guild_remove(guild_id);
         self.shared.verify_channel.on_guild_remove(guild_id);
+        self.shared.blacklist.on_guild_remove(guild_id);
     }
 }
 impl Drop for Handler {
@@ -202,9 +242,20 @@ impl EventHandler for Handler {
                   ready.user.id, permissions.bits());
         }
 
-        error_report::catch_error(||
-            self.shared.verify_channel.check_verification_channels_ready(&ready)
-        ).ok();
+        let application_id = ready.user.id;
+        // Cleaning up verification channels makes requests to Discord, so it must not hold up
+        // the event thread.
+        self.shared.tasks.dispatch_io_task(move |core| {
+            core.verify_channel().check_verification_channels_ready(&ready)
+        });
+        self.shared.tasks.dispatch_task(move |core| {
+            if let Some(token) = core.config().get(None, ConfigKeys::DiscordToken)? {
+                if let Err(e) = register_slash_commands(&token, application_id) {
+                    warn!("Could not register slash commands: {}", e);
+                }
+            }
+            Ok(())
+        });
     }
 
     fn message(&self, ctx: Context, message: Message) {
@@ -224,14 +275,16 @@ impl EventHandler for Handler {
                 core.roles().check_roles_update_msg(guild_id, message.author.id)?;
                 if message.author.id != user_id {
                     core.verify_channel().check_verification_channel_msg(guild_id, &message)?;
+                    handle_setup_message(core, guild_id, &message)?;
                 }
                 Ok(())
             });
         }
 
-        // Process commands.
+        // Process commands. Servers can set their own prefix, and mentioning the bot works as a
+        // prefix everywhere.
         let prefix = match error_report::catch_error(||
-            self.shared.config.get(None, ConfigKeys::CommandPrefix)
+            self.shared.config.get(guild_id, ConfigKeys::CommandPrefix)
         ) {
             Ok(prefix) => prefix,
             Err(_) => return,
@@ -240,12 +293,7 @@ impl EventHandler for Handler {
         let content = if message.content.starts_with(&prefix) {
             Some(message.content[prefix.len()..].to_owned())
         } else {
-            let user_prefix = format!("<@{}> ", user_id);
-            if message.content.starts_with(&user_prefix) {
-                Some(message.content[user_prefix.len()..].to_owned())
-            } else {
-                None
-            }
+            strip_mention(&message.content, user_id).map(|x| x.to_owned())
         };
 
         if let Some(content) = content {
@@ -255,19 +303,96 @@ impl EventHandler for Handler {
                                               content, command, prefix)
                 ).ok();
             }
+        } else if guild_id.is_none() && message.author.id != user_id {
+            self.shared.tasks.dispatch_task(move |core| handle_wizard_message(core, &message));
+        }
+    }
+
+    fn reaction_add(&self, _: Context, reaction: Reaction) {
+        if reaction.user_id == serenity::CACHE.read().user.id {
+            return
+        }
+        if is_page_reaction(&reaction.emoji) {
+            let is_private = match reaction.channel_id.find() {
+                Some(Channel::Private(_)) => true,
+                _ => false,
+            };
+            self.shared.tasks.dispatch_task(move |_| {
+                // The reaction is removed so the same page can be turned to again.
+                if handle_page_reaction(&reaction)? && !is_private {
+                    reaction.delete().map_err(Error::from).drop_nonfatal()?;
+                }
+                Ok(())
+            });
+            return
+        }
+        if reaction.emoji != ReactionType::Unicode(VERIFY_PANEL_EMOJI.to_owned()) {
+            return
+        }
+        let guild_id = match reaction.channel_id.find() {
+            Some(Channel::Guild(ref channel)) => channel.read().guild_id,
+            _ => return,
+        };
+        self.shared.tasks.dispatch_task(move |core| {
+            if core.verify_channel().is_verify_panel(guild_id, reaction.message_id)? {
+                reaction.delete().map_err(Error::from).drop_nonfatal()?;
+                if !start_wizard_dm(core, reaction.user_id, guild_id)? {
+                    debug!("Could not send verification wizard to {}.", reaction.user_id);
+                }
+            }
+            Ok(())
+        });
+    }
+
+    // Other users' reactions cannot be removed in direct messages, so removing a page reaction
+    // there turns the page as well.
+    fn reaction_remove(&self, _: Context, reaction: Reaction) {
+        if reaction.user_id == serenity::CACHE.read().user.id ||
+           !is_page_reaction(&reaction.emoji) {
+            return
+        }
+        if let Some(Channel::Private(_)) = reaction.channel_id.find() {
+            self.shared.tasks.dispatch_task(move |_| {
+                handle_page_reaction(&reaction)?;
+                Ok(())
+            });
         }
     }
 
     fn guild_member_addition(&self, _: Context, guild_id: GuildId, member: Member) {
-        self.shared.tasks.dispatch_task(move |core|
-            core.roles().check_roles_update_join(guild_id, member)
-        );
+        self.shared.tasks.dispatch_task(move |core| {
+            let user_id = member.user.read().id;
+            if core.roles().check_roles_update_join(guild_id, member)? {
+                core.verify_channel().send_join_welcome(guild_id, user_id)?;
+            }
+            Ok(())
+        });
+    }
+
+    fn guild_ban_addition(&self, _: Context, guild_id: GuildId, user: User) {
+        self.shared.tasks.dispatch_task(move |core| {
+            if core.config().get(Some(guild_id), ConfigKeys::SyncBans)? {
+                let count = core.blacklist().record_ban(guild_id, user.id)?;
+                if count != 0 {
+                    info!("Recorded {} Roblox accounts linked to {}, who was banned from {}.",
+                          count, user.tag(), guild_id);
+                }
+            }
+            Ok(())
+        });
+    }
+    fn guild_ban_removal(&self, _: Context, guild_id: GuildId, user: User) {
+        self.shared.tasks.dispatch_task(move |core| {
+            core.blacklist().remove_ban(guild_id, user.id)?;
+            Ok(())
+        });
     }
 
     fn guild_create(&self, _: Context, guild: Guild, _: bool) {
-        error_report::catch_error(||
-            self.shared.verify_channel.check_guild_create(guild.id)
-        ).ok();
+        let guild_id = guild.id;
+        self.shared.tasks.dispatch_io_task(move |core| {
+            core.verify_channel().check_guild_create(guild_id)
+        });
     }
     fn guild_delete(&self, _: Context, guild: PartialGuild, _: Option<Arc<RwLock<Guild>>>) {
         self.on_guild_remove(guild.id);
@@ -275,6 +400,34 @@ impl EventHandler for Handler {
     fn guild_unavailable(&self, _: Context, guild_id: GuildId) {
         self.on_guild_remove(guild_id);
     }
+
+    fn unknown(&self, _: Context, name: String, raw: Value) {
+        if name == "INTERACTION_CREATE" {
+            self.shared.tasks.dispatch_task(move |core| handle_interaction(core, raw));
+        }
+    }
+}
+
+// Returns the rest of a message that starts by mentioning the bot, in either mention format.
+fn strip_mention(content: &str, user_id: UserId) -> Option<&str> {
+    for mention in &[format!("<@{}>", user_id.0), format!("<@!{}>", user_id.0)] {
+        if content.starts_with(mention.as_str()) {
+            let rest = &content[mention.len()..];
+            if rest.starts_with(char::is_whitespace) {
+                return Some(rest.trim_left())
+            }
+        }
+    }
+    None
+}
+
+pub struct ShardStatus {
+    pub id: u64, pub stage: String, pub latency: Option<Duration>, pub guilds: usize,
+}
+
+// Discord assigns each server to a shard based on its ID.
+fn guild_shard(guild_id: GuildId, shard_count: u64) -> u64 {
+    (guild_id.0 >> 22) % max(shard_count, 1)
 }
 
 struct DiscordBot {
@@ -292,6 +445,7 @@ impl DiscordBot {
         ensure!(self.status.compare_and_swap(STATUS_NOT_INIT, STATUS_STARTING,
                                              Ordering::Relaxed) == STATUS_NOT_INIT,
                 "Discord component already started!");
+        let shard_count = self.shared.config.get(None, ConfigKeys::DiscordShardCount)?;
         let mut client = Client::new(&self.token, Handler {
             shared: self.shared.clone(), status: self.status.clone(),
             printed_url: AtomicBool::new(false),
@@ -302,7 +456,12 @@ impl DiscordBot {
                 "Internal error: DiscordBot not in STATUS_STARTING!");
         thread::Builder::new().name("discord thread".to_string()).spawn(move || {
             error_report::catch_error(|| {
-                match client.start_autosharded() {
+                let result = if shard_count == 0 {
+                    client.start_autosharded()
+                } else {
+                    client.start_shards(shard_count)
+                };
+                match result {
                     Ok(_) | Err(SerenityError::Client(ClientError::Shutdown)) => Ok(()),
                     Err(err) => Err(err.into()),
                 }
@@ -329,6 +488,28 @@ impl DiscordBot {
     fn is_alive(&self) -> bool {
         self.status.load(Ordering::Relaxed) == STATUS_RUNNING
     }
+
+    fn shard_statuses(&self) -> Vec<ShardStatus> {
+        let shard_manager = match *self.shard_manager.lock() {
+            Some(ref shard_manager) => shard_manager.clone(),
+            None => return Vec::new(),
+        };
+        let mut guilds = HashMap::new();
+        {
+            let cache = serenity::CACHE.read();
+            for &guild_id in cache.guilds.keys() {
+                *guilds.entry(guild_shard(guild_id, cache.shard_count)).or_insert(0) += 1;
+            }
+        }
+        let shard_manager = shard_manager.lock();
+        let runners = shard_manager.runners.lock();
+        let mut statuses: Vec<ShardStatus> = runners.iter().map(|(id, info)| ShardStatus {
+            id: id.0, stage: format!("{:?}", info.stage), latency: info.latency,
+            guilds: guilds.get(&id.0).cloned().unwrap_or(0),
+        }).collect();
+        statuses.sort_by_key(|x| x.id);
+        statuses
+    }
 }
 
 enum BotStatus {
@@ -374,12 +555,13 @@ pub struct DiscordManager {
 impl DiscordManager {
     pub(in ::core) fn new(
         config: ConfigManager, core_ref: CoreRef, roles: RoleManager, tasks: TaskManager,
-        verify_channel: VerificationChannelManager,
+        verify_channel: VerificationChannelManager, blacklist: BlacklistManager,
     ) -> DiscordManager {
         DiscordManager {
             bot: Mutex::new(BotStatus::NotConnected), shutdown: AtomicBool::new(false),
             shared: Arc::new(DiscordBotSharedData {
-                config, core_ref, roles, tasks, verify_channel, is_in_command: MultiMutex::new(),
+                config, core_ref, roles, tasks, verify_channel, blacklist,
+                is_in_command: MultiMutex::new(),
             }),
         }
     }
@@ -414,6 +596,18 @@ impl DiscordManager {
         Ok(())
     }
 
+    // Returns the status of each shard the bot is connected with, or nothing if it is not
+    // connected to Discord.
+    pub fn shard_statuses(&self) -> Vec<ShardStatus> {
+        match *self.bot.lock() {
+            BotStatus::Connected(ref bot) => bot.shard_statuses(),
+            BotStatus::NotConnected => Vec::new(),
+        }
+    }
+    pub fn guild_shard(&self, guild_id: GuildId) -> u64 {
+        guild_shard(guild_id, serenity::CACHE.read().shard_count)
+    }
+
     pub fn on_cleanup_tick(&self) {
         self.shared.is_in_command.shrink_to_fit()
     }
@@ -0,0 +1,48 @@
+use core::config::*;
+use errors::*;
+use serenity::builder::CreateEmbed;
+use serenity::model::prelude::*;
+
+// How a server's embeds look, shared by verification responses, whois and the audit log.
+#[derive(Clone, Debug)]
+pub struct EmbedTheme {
+    pub colour: Option<u32>, pub footer: Option<String>, pub show_thumbnails: bool,
+}
+impl EmbedTheme {
+    pub fn load(config: &ConfigManager, guild: Option<GuildId>) -> Result<EmbedTheme> {
+        Ok(EmbedTheme {
+            colour: config.get(guild, ConfigKeys::EmbedColour)?,
+            footer: config.get(guild, ConfigKeys::EmbedFooter)?,
+            show_thumbnails: config.get(guild, ConfigKeys::EmbedThumbnails)?,
+        })
+    }
+
+    // Adds the footer and, unless they are turned off, a thumbnail. The embed's own colour is
+    // kept, for embeds whose colour means something, such as warnings.
+    pub fn decorate(&self, e: CreateEmbed, thumbnail_url: Option<&str>) -> CreateEmbed {
+        let e = match self.footer {
+            Some(ref footer) => e.footer(|f| f.text(footer)),
+            None => e,
+        };
+        match thumbnail_url {
+            Some(url) if self.show_thumbnails => e.thumbnail(url),
+            _ => e,
+        }
+    }
+    // Like `decorate`, but also replaces the embed's colour with the server's, if one is set.
+    pub fn apply(&self, e: CreateEmbed, thumbnail_url: Option<&str>) -> CreateEmbed {
+        let e = self.decorate(e, thumbnail_url);
+        match self.colour {
+            Some(colour) => e.colour(colour),
+            None => e,
+        }
+    }
+}
+
+// Parses a colour written as `#RRGGBB` or `RRGGBB`.
+pub fn parse_colour(s: &str) -> Result<u32> {
+    let hex = s.trim().trim_left_matches('#');
+    cmd_ensure!(hex.len() == 6, "Please give a colour such as `#43B581`.");
+    u32::from_str_radix(hex, 16).ok()
+        .to_cmd_err(|| "Please give a colour such as `#43B581`.")
+}
@@ -0,0 +1,315 @@
+use chrono::{Duration as ChronoDuration, Utc};
+use core::config::*;
+use core::guest_passes::GuestPassManager;
+use core::verifier::Verifier;
+use database::*;
+use errors::*;
+use i18n;
+use reqwest::Method;
+use serenity;
+use serenity::model::prelude::*;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use util;
+use util::{DISCORD_API_BASE, discord_request};
+
+// How long timeouts last. Members still unverified when it runs out are timed out again.
+const TIMEOUT_SECS: u64 = 60 * 60 * 24 * 7;
+const MAX_REMINDERS: usize = 5;
+
+// Parses a comma separated list of how many days after joining reminders are sent, in order.
+pub fn parse_reminder_days(s: &str) -> Result<Vec<u32>> {
+    let mut days = s.split(',').filter(|x| !x.trim().is_empty())
+        .map(|x| x.trim().parse::<u32>().ok())
+        .collect::<Option<Vec<u32>>>()
+        .to_cmd_err(|| "Please give a comma separated list of days, such as `1,3,7`.")?;
+    days.sort();
+    days.dedup();
+    cmd_ensure!(!days.is_empty(), "At least one day must be given.");
+    cmd_ensure!(days[0] != 0, "Reminders cannot be sent sooner than a day after joining.");
+    cmd_ensure!(days.len() <= MAX_REMINDERS, "At most {} reminders can be sent.", MAX_REMINDERS);
+    Ok(days)
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum EnforcementAction {
+    Kick, Timeout,
+}
+impl EnforcementAction {
+    pub fn name(self) -> &'static str {
+        match self {
+            EnforcementAction::Kick => "kick",
+            EnforcementAction::Timeout => "timeout",
+        }
+    }
+    pub fn from_name(name: &str) -> Result<EnforcementAction> {
+        match name {
+            "kick" => Ok(EnforcementAction::Kick),
+            "timeout" => Ok(EnforcementAction::Timeout),
+            _ => cmd_error!("Unknown action '{}'. Valid actions are: kick, timeout", name),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct TimeoutPayload {
+    communication_disabled_until: String,
+}
+
+// A member of a server who has not verified yet.
+struct UnverifiedMember {
+    user_id: UserId, joined_at: SystemTime,
+}
+
+struct EnforcementManagerData {
+    config: ConfigManager, database: Database, verifier: Verifier, guest_passes: GuestPassManager,
+}
+
+// Warns, then kicks or times out members that have not verified within a server's grace period.
+#[derive(Clone)]
+pub struct EnforcementManager(Arc<EnforcementManagerData>);
+impl EnforcementManager {
+    pub fn new(
+        config: ConfigManager, database: Database, verifier: Verifier,
+        guest_passes: GuestPassManager,
+    ) -> EnforcementManager {
+        EnforcementManager(Arc::new(EnforcementManagerData {
+            config, database, verifier, guest_passes,
+        }))
+    }
+
+    fn exempt_roles(&self, guild_id: GuildId) -> Result<Vec<RoleId>> {
+        Ok(match self.0.config.get(Some(guild_id), ConfigKeys::UnverifiedExemptRoles)? {
+            Some(roles) => roles.split(',').filter_map(|x| x.trim().parse().ok())
+                .map(RoleId).collect(),
+            None => Vec::new(),
+        })
+    }
+
+    fn unverified_members(&self, guild_id: GuildId) -> Result<Vec<UnverifiedMember>> {
+        let exempt_roles = self.exempt_roles(guild_id)?;
+        let candidates: Vec<UnverifiedMember> = match guild_id.find() {
+            Some(guild) => {
+                let guild = guild.read();
+                guild.members.values().filter(|member| {
+                    let user = member.user.read();
+                    !user.bot && user.id != guild.owner_id &&
+                        !member.roles.iter().any(|x| exempt_roles.contains(x))
+                }).filter_map(|member| member.joined_at.map(|joined_at| UnverifiedMember {
+                    user_id: member.user.read().id,
+                    joined_at: util::time_from_i64(joined_at.timestamp()),
+                })).collect()
+            }
+            None => return Ok(Vec::new()),
+        };
+        let mut members = Vec::new();
+        for member in candidates {
+            if self.0.verifier.get_verified_roblox_user(member.user_id)?.is_none() &&
+                self.0.guest_passes.get(guild_id, member.user_id)?.is_none() {
+                members.push(member);
+            }
+        }
+        Ok(members)
+    }
+
+    fn warn_member(
+        &self, guild_id: GuildId, user_id: UserId, action: EnforcementAction,
+        action_at: SystemTime,
+    ) -> Result<()> {
+        let server = guild_id.find()
+            .map_or_else(|| guild_id.to_string(), |x| x.read().name.clone());
+        let time_left = i18n::tr_time_diff(&self.0.config, Some(guild_id), SystemTime::now(),
+                                           action_at)?;
+        let key = match action {
+            EnforcementAction::Kick => "enforce-warning-kick",
+            EnforcementAction::Timeout => "enforce-warning-timeout",
+        };
+        let message = i18n::tr(&self.0.config, Some(guild_id), key, &[
+            ("server", &server), ("time_left", &time_left),
+        ])?;
+        if let Err(e) = user_id.create_dm_channel().and_then(|x| x.say(&message)) {
+            debug!("Could not warn {} about verification in {}: {}", user_id, guild_id, e);
+        }
+        Ok(())
+    }
+
+    fn take_action(
+        &self, guild_id: GuildId, user_id: UserId, action: EnforcementAction,
+    ) -> Result<()> {
+        match action {
+            EnforcementAction::Kick => guild_id.kick(user_id)?,
+            EnforcementAction::Timeout => {
+                let token = self.0.config.get(None, ConfigKeys::DiscordToken)?
+                    .to_cmd_err(|| "No Discord token is configured.")?;
+                let until = Utc::now() + ChronoDuration::seconds(TIMEOUT_SECS as i64);
+                discord_request(&token, Method::Patch,
+                                &format!("{}/guilds/{}/members/{}",
+                                         DISCORD_API_BASE, guild_id.0, user_id.0),
+                                &TimeoutPayload {
+                                    communication_disabled_until: until.to_rfc3339(),
+                                })?;
+            }
+        }
+        Ok(())
+    }
+
+    fn enforce_guild(&self, conn: &DatabaseConnection, guild_id: GuildId) -> Result<()> {
+        let grace_hours =
+            self.0.config.get(Some(guild_id), ConfigKeys::UnverifiedGracePeriodHours)?;
+        if grace_hours == 0 {
+            return Ok(())
+        }
+        let grace = Duration::from_secs(grace_hours as u64 * 60 * 60);
+        let warning_hours =
+            self.0.config.get(Some(guild_id), ConfigKeys::UnverifiedWarningHours)?;
+        let warning = Duration::from_secs(warning_hours.min(grace_hours) as u64 * 60 * 60);
+        let action = EnforcementAction::from_name(
+            &self.0.config.get(Some(guild_id), ConfigKeys::UnverifiedAction)?
+        )?;
+
+        let now = SystemTime::now();
+        for member in self.unverified_members(guild_id)? {
+            let action_at = member.joined_at + grace;
+            let (warned_at, actioned_at) = conn.query(
+                "SELECT warned_at, actioned_at FROM unverified_enforcement \
+                 WHERE discord_guild_id = ?1 AND discord_user_id = ?2",
+                (guild_id, member.user_id),
+            ).get_opt::<(Option<SystemTime>, Option<SystemTime>)>()?.unwrap_or((None, None));
+
+            if now >= action_at {
+                let due = match actioned_at {
+                    Some(actioned_at) => action == EnforcementAction::Timeout &&
+                        now >= actioned_at + Duration::from_secs(TIMEOUT_SECS),
+                    None => true,
+                };
+                if !due {
+                    continue
+                }
+                info!("{} has not verified in {} after {} hours. Taking action: {}",
+                      member.user_id, guild_id, grace_hours, action.name());
+                if let Err(e) = self.take_action(guild_id, member.user_id, action) {
+                    warn!("Could not {} {} in {}: {}", action.name(), member.user_id, guild_id, e);
+                    continue
+                }
+                conn.execute(
+                    "REPLACE INTO unverified_enforcement \
+                         (discord_guild_id, discord_user_id, warned_at, actioned_at) \
+                     VALUES (?1, ?2, ?3, ?4)", (guild_id, member.user_id, warned_at, now),
+                )?;
+            } else if warning_hours != 0 && warned_at.is_none() && now + warning >= action_at {
+                self.warn_member(guild_id, member.user_id, action, action_at)?;
+                conn.execute(
+                    "REPLACE INTO unverified_enforcement \
+                         (discord_guild_id, discord_user_id, warned_at, actioned_at) \
+                     VALUES (?1, ?2, ?3, NULL)", (guild_id, member.user_id, now),
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    // Checks every server's unverified members against its grace period.
+    pub fn run(&self) -> Result<()> {
+        let conn = self.0.database.connect()?;
+        conn.execute(
+            "DELETE FROM unverified_enforcement WHERE discord_user_id IN (\
+                 SELECT discord_user_id FROM discord_user_info WHERE roblox_user_id IS NOT NULL\
+             )", (),
+        )?;
+        let guilds: Vec<GuildId> = serenity::CACHE.read().guilds.keys().cloned().collect();
+        for guild_id in guilds {
+            self.enforce_guild(&conn, guild_id).drop_nonfatal()?;
+        }
+        Ok(())
+    }
+
+    fn remind_member(&self, guild_id: GuildId, user_id: UserId, days: u32) -> Result<()> {
+        let server = guild_id.find()
+            .map_or_else(|| guild_id.to_string(), |x| x.read().name.clone());
+        let prefix = self.0.config.get(Some(guild_id), ConfigKeys::CommandPrefix)?;
+        let message = i18n::tr(&self.0.config, Some(guild_id), "remind-unverified", &[
+            ("server", &server), ("days", &days.to_string()), ("prefix", &prefix),
+        ])?;
+        if let Err(e) = user_id.create_dm_channel().and_then(|x| x.say(&message)) {
+            debug!("Could not remind {} to verify in {}: {}", user_id, guild_id, e);
+        }
+        Ok(())
+    }
+
+    fn remind_guild(&self, conn: &DatabaseConnection, guild_id: GuildId) -> Result<()> {
+        if !self.0.config.get(Some(guild_id), ConfigKeys::VerificationReminders)? {
+            return Ok(())
+        }
+        let days = parse_reminder_days(
+            &self.0.config.get(Some(guild_id), ConfigKeys::VerificationReminderDays)?
+        )?;
+
+        let now = SystemTime::now();
+        for member in self.unverified_members(guild_id)? {
+            let joined_for = now.duration_since(member.joined_at).unwrap_or(Duration::from_secs(0));
+            let due = days.iter()
+                .filter(|&&x| joined_for >= Duration::from_secs(x as u64 * 60 * 60 * 24)).count();
+            if due == 0 {
+                continue
+            }
+            // Members who joined long before reminders were turned on are not sent reminders
+            // for stages they are well past.
+            let stage_ends = Duration::from_secs((days[due - 1] as u64 + 1) * 60 * 60 * 24);
+            if joined_for >= stage_ends {
+                continue
+            }
+            let stage = conn.query(
+                "SELECT stage FROM verification_reminders \
+                 WHERE discord_guild_id = ?1 AND discord_user_id = ?2",
+                (guild_id, member.user_id),
+            ).get_opt::<u32>()?.unwrap_or(0);
+            if stage as usize >= due || self.is_opted_out(conn, member.user_id)? {
+                continue
+            }
+            self.remind_member(guild_id, member.user_id, days[due - 1])?;
+            conn.execute(
+                "REPLACE INTO verification_reminders \
+                     (discord_guild_id, discord_user_id, stage, reminded_at) \
+                 VALUES (?1, ?2, ?3, ?4)", (guild_id, member.user_id, due as u32, now),
+            )?;
+        }
+        Ok(())
+    }
+
+    // Sends reminders to verify to the unverified members of every server that has them turned
+    // on. Each reminder is only sent once to a member.
+    pub fn send_reminders(&self) -> Result<()> {
+        let conn = self.0.database.connect()?;
+        conn.execute(
+            "DELETE FROM verification_reminders WHERE discord_user_id IN (\
+                 SELECT discord_user_id FROM discord_user_info WHERE roblox_user_id IS NOT NULL\
+             )", (),
+        )?;
+        let guilds: Vec<GuildId> = serenity::CACHE.read().guilds.keys().cloned().collect();
+        for guild_id in guilds {
+            self.remind_guild(&conn, guild_id).drop_nonfatal()?;
+        }
+        Ok(())
+    }
+
+    fn is_opted_out(&self, conn: &DatabaseConnection, user_id: UserId) -> Result<bool> {
+        Ok(conn.query(
+            "SELECT COUNT(*) FROM verification_reminder_opt_outs WHERE discord_user_id = ?1",
+            user_id,
+        ).get::<u32>()? != 0)
+    }
+    // Stops or resumes reminders to verify for a user, in every server.
+    pub fn set_reminders_opt_out(&self, user_id: UserId, opt_out: bool) -> Result<()> {
+        let conn = self.0.database.connect()?;
+        if opt_out {
+            conn.execute(
+                "REPLACE INTO verification_reminder_opt_outs (discord_user_id, opted_out_at) \
+                 VALUES (?1, ?2)", (user_id, SystemTime::now()),
+            )?;
+        } else {
+            conn.execute("DELETE FROM verification_reminder_opt_outs WHERE discord_user_id = ?1",
+                         user_id)?;
+        }
+        Ok(())
+    }
+}
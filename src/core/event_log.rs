@@ -0,0 +1,259 @@
+use core::key_encryption::hmac;
+use database::*;
+use errors::*;
+use parking_lot::Mutex;
+use rand::{Rng, OsRng};
+use roblox::RobloxUserID;
+use serenity::model::prelude::*;
+use std::fs;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+use util;
+
+const SIGNING_KEY_LENGTH: usize = 32;
+// Users are referred to by this many bytes of a keyed hash of their ID.
+const USER_REF_LENGTH: usize = 8;
+// Entries are checked this many at a time, so large logs do not have to be loaded at once.
+const VERIFY_PAGE_SIZE: u32 = 1000;
+
+// The kinds of events recorded in the event log.
+pub const EVENT_REKEY: &str = "rekey";
+pub const EVENT_VERIFY: &str = "verify";
+pub const EVENT_UNVERIFY: &str = "unverify";
+pub const EVENT_CONFIG_CHANGE: &str = "config_change";
+
+// The signing key is kept in a file beside the database rather than in it, so that someone who
+// can only modify the database cannot sign entries of their own.
+fn load_signing_key(path: &Path) -> Result<Vec<u8>> {
+    if path.exists() {
+        let mut key = Vec::new();
+        File::open(path)?.read_to_end(&mut key)?;
+        ensure!(key.len() == SIGNING_KEY_LENGTH,
+                "The event log signing key in '{}' is corrupt.", path.display());
+        Ok(key)
+    } else {
+        info!("Creating a new event log signing key in '{}'.", path.display());
+        let mut key = vec![0u8; SIGNING_KEY_LENGTH];
+        OsRng::new()?.fill_bytes(&mut key);
+        File::create(path)?.write_all(&key)?;
+        Ok(key)
+    }
+}
+
+// The id and signature of the newest entry are also kept in a file beside the signing key, so
+// that entries removed from the end of the log, which leave no gap, can still be noticed.
+fn head_path(key_path: &Path) -> PathBuf {
+    key_path.with_extension("head")
+}
+fn load_head(path: &Path) -> Result<Option<(u64, Vec<u8>)>> {
+    if !path.exists() {
+        return Ok(None)
+    }
+    let mut head = String::new();
+    File::open(path)?.read_to_string(&mut head)?;
+    let mut parts = head.trim().split(' ');
+    let id = parts.next().unwrap_or("").parse::<u64>();
+    let signature = parts.next().unwrap_or("");
+    ensure!(id.is_ok() && signature.len() % 2 == 0 && parts.next().is_none(),
+            "The event log head in '{}' is corrupt.", path.display());
+    let mut bytes = Vec::new();
+    for i in 0..signature.len() / 2 {
+        match u8::from_str_radix(&signature[i * 2..i * 2 + 2], 16) {
+            Ok(byte) => bytes.push(byte),
+            Err(_) => bail!("The event log head in '{}' is corrupt.", path.display()),
+        }
+    }
+    Ok(Some((id?, bytes)))
+}
+fn store_head(path: &Path, id: u64, signature: &[u8]) -> Result<()> {
+    let signature: String = signature.iter().map(|x| format!("{:02x}", x)).collect();
+    let temp_path = path.with_extension("head.tmp");
+    File::create(&temp_path)?.write_all(format!("{} {}\n", id, signature).as_bytes())?;
+    fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
+// Signs an entry together with the signature of the entry before it. Each part is preceded by
+// its length, so that text cannot be moved from one field to another without being noticed.
+fn sign(
+    key: &[u8], previous: &[u8], id: u64, recorded_at: i64, event_type: &str, details: &str,
+) -> Vec<u8> {
+    let id = id.to_string();
+    let recorded_at = recorded_at.to_string();
+    let mut message = Vec::new();
+    for part in &[previous, id.as_bytes(), recorded_at.as_bytes(), event_type.as_bytes(),
+                  details.as_bytes()] {
+        let len = part.len() as u32;
+        message.extend_from_slice(&[(len >> 24) as u8, (len >> 16) as u8, (len >> 8) as u8,
+                                    len as u8]);
+        message.extend_from_slice(part);
+    }
+    hmac(key, &[&message[..]])
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum EventLogProblem {
+    // Entries between two ids are missing.
+    Missing { after: u64, before: u64 },
+    // An entry does not match its signature, so it or the entry before it was changed.
+    InvalidSignature { id: u64 },
+    // The log ends before the newest entry recorded outside the database.
+    Truncated { after: u64, expected: u64 },
+    // An entry does not match the newest entry recorded outside the database, so the entries up
+    // to it were removed and replaced by newer ones.
+    Replaced { id: u64 },
+}
+
+#[derive(Clone, Debug)]
+pub struct EventLogStatus {
+    pub entries: u64, pub problems: Vec<EventLogProblem>,
+}
+
+struct EventLogData {
+    database: Database, signing_key: Vec<u8>, head_path: PathBuf, head_lock: Mutex<()>,
+}
+
+// An append-only record of security relevant events. Each entry is signed together with the one
+// before it, so changing or removing entries breaks the chain from that point on.
+//
+// Entries refer to users only by `discord_ref` and `roblox_ref`, so they do not have to be
+// removed when a user's data is purged.
+#[derive(Clone)]
+pub struct EventLog(Arc<EventLogData>);
+impl EventLog {
+    pub fn new(database: Database, key_path: &Path) -> Result<EventLog> {
+        let log = EventLog(Arc::new(EventLogData {
+            database, signing_key: load_signing_key(key_path)?, head_path: head_path(key_path),
+            head_lock: Mutex::new(()),
+        }));
+        // Logs from before the head was kept outside the database start from their current end.
+        if !log.0.head_path.exists() {
+            let last = log.0.database.connect()?.query(
+                "SELECT id, signature FROM event_log ORDER BY id DESC LIMIT 1", (),
+            ).get_opt::<(u64, Vec<u8>)>()?;
+            if let Some((id, signature)) = last {
+                store_head(&log.0.head_path, id, &signature)?;
+            }
+        }
+        Ok(log)
+    }
+
+    // Forgets the head, for when the database is replaced by a backup. The restored log rightly
+    // ends before the head, so the head is taken from its end on the next start instead.
+    pub fn reset_head(key_path: &Path) -> Result<()> {
+        let path = head_path(key_path);
+        if path.exists() {
+            fs::remove_file(&path)?;
+        }
+        Ok(())
+    }
+
+    fn user_ref(&self, kind: &str, id: u64) -> String {
+        let hash = hmac(&self.0.signing_key, &[format!("{}:{}", kind, id).as_bytes()]);
+        hash[..USER_REF_LENGTH].iter().map(|x| format!("{:02x}", x)).collect()
+    }
+    // An opaque reference to a Discord user, which can only be matched to them with the signing
+    // key.
+    pub fn discord_ref(&self, discord_id: UserId) -> String {
+        format!("discord:{}", self.user_ref("discord", discord_id.0))
+    }
+    pub fn roblox_ref(&self, roblox_id: RobloxUserID) -> String {
+        format!("roblox:{}", self.user_ref("roblox", roblox_id.0))
+    }
+
+    // Moves the head forward to a new entry, unless it shows that entries were removed from the
+    // end of the log, so that `verify` can still report that.
+    fn update_head(&self, id: u64, previous: &[u8], signature: &[u8]) -> Result<()> {
+        match load_head(&self.0.head_path)? {
+            Some((head_id, _)) if head_id >= id =>
+                bail!("The event log ends before entry #{}, recorded outside the database.",
+                      head_id),
+            Some((head_id, ref head_signature))
+                if head_id + 1 == id && &head_signature[..] != previous =>
+                bail!("Event log entry #{} does not match the one recorded outside the database.",
+                      head_id),
+            _ => store_head(&self.0.head_path, id, signature),
+        }
+    }
+
+    fn append(&self, event_type: &str, details: &str) -> Result<()> {
+        // Held until the head is updated, so that it is moved forward in order.
+        let _guard = self.0.head_lock.lock();
+        let conn = self.0.database.connect()?;
+        conn.transaction_immediate(|| {
+            let last = conn.query(
+                "SELECT id, signature FROM event_log ORDER BY id DESC LIMIT 1", (),
+            ).get_opt::<(u64, Vec<u8>)>()?;
+            let (id, previous) = match last {
+                Some((id, signature)) => (id + 1, signature),
+                None => (1, Vec::new()),
+            };
+            let recorded_at = util::time_to_i64(SystemTime::now());
+            let signature =
+                sign(&self.0.signing_key, &previous, id, recorded_at, event_type, details);
+            conn.execute(
+                "INSERT INTO event_log (id, recorded_at, event_type, details, signature) \
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                (id, recorded_at, event_type, details, &signature),
+            )?;
+            Ok((id, previous, signature))
+        }).and_then(|(id, previous, signature)| self.update_head(id, &previous, &signature))
+    }
+    // Failing to record an event does not stop the action it describes.
+    pub fn record(&self, event_type: &str, details: String) {
+        if let Err(e) = self.append(event_type, &details) {
+            error!("Could not record '{}' in the event log: {}", event_type, e);
+        }
+    }
+
+    // Checks every entry against its signature and the entry before it, and the end of the log
+    // against the head kept outside the database.
+    pub fn verify(&self) -> Result<EventLogStatus> {
+        let head = load_head(&self.0.head_path)?;
+        let conn = self.0.database.connect()?;
+        let mut status = EventLogStatus { entries: 0, problems: Vec::new() };
+        let mut last_id = 0;
+        let mut previous = Vec::new();
+        loop {
+            let page = conn.query(
+                "SELECT id, recorded_at, event_type, details, signature FROM event_log \
+                 WHERE id > ?1 ORDER BY id LIMIT ?2", (last_id, VERIFY_PAGE_SIZE),
+            ).get_all::<(u64, i64, String, String, Vec<u8>)>()?;
+            if page.is_empty() {
+                break
+            }
+            for (id, recorded_at, event_type, details, signature) in page {
+                status.entries += 1;
+                if id != last_id + 1 {
+                    // The signature of an entry after a gap cannot be checked, as the signature
+                    // it was chained with is gone.
+                    status.problems.push(EventLogProblem::Missing { after: last_id, before: id });
+                } else {
+                    let expected = sign(&self.0.signing_key, &previous, id, recorded_at,
+                                        &event_type, &details);
+                    if expected != signature {
+                        status.problems.push(EventLogProblem::InvalidSignature { id });
+                    }
+                }
+                if let Some((head_id, ref head_signature)) = head {
+                    if id == head_id && signature != *head_signature {
+                        status.problems.push(EventLogProblem::Replaced { id });
+                    }
+                }
+                last_id = id;
+                previous = signature;
+            }
+        }
+        if let Some((head_id, _)) = head {
+            if head_id > last_id {
+                status.problems.push(EventLogProblem::Truncated {
+                    after: last_id, expected: head_id,
+                });
+            }
+        }
+        Ok(status)
+    }
+}
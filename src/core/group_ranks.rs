@@ -0,0 +1,164 @@
+use core::audit::*;
+use core::config::*;
+use core::tasks::*;
+use core::verifier::Verifier;
+use errors::*;
+use roblox;
+use roblox::{RobloxUserID, GroupCredentials};
+use serenity;
+use serenity::model::prelude::*;
+use std::sync::Arc;
+
+struct GroupRankManagerData {
+    config: ConfigManager, audit: AuditLog, tasks: TaskManager, verifier: Verifier,
+}
+
+// Picks the credentials used to manage Roblox groups, preferring the Open Cloud API key.
+fn group_credentials<'a>(
+    api_key: &'a Option<String>, cookie: &'a Option<String>,
+) -> Result<GroupCredentials<'a>> {
+    Ok(match (api_key, cookie) {
+        (&Some(ref api_key), _) => GroupCredentials::OpenCloud(api_key),
+        (_, &Some(ref cookie)) => GroupCredentials::Cookie(cookie),
+        _ => cmd_error!("Neither group_admin_api_key nor group_admin_cookie is set."),
+    })
+}
+
+// Gives newly verified users a starting rank in a server's Roblox group, and answers requests to
+// join it.
+#[derive(Clone)]
+pub struct GroupRankManager(Arc<GroupRankManagerData>);
+impl GroupRankManager {
+    pub fn new(
+        config: ConfigManager, audit: AuditLog, tasks: TaskManager, verifier: Verifier,
+    ) -> GroupRankManager {
+        GroupRankManager(Arc::new(GroupRankManagerData { config, audit, tasks, verifier }))
+    }
+
+    fn set_starter_rank(
+        &self, guild_id: GuildId, roblox_id: RobloxUserID, group_id: u64, rank: u32,
+    ) -> Result<()> {
+        let api_key = self.0.config.get(None, ConfigKeys::GroupAdminApiKey)?;
+        let cookie = self.0.config.get(None, ConfigKeys::GroupAdminCookie)?;
+        let credentials = group_credentials(&api_key, &cookie)?;
+        match roblox_id.get_group_rank(group_id)? {
+            Some(ref current) if current.rank >= rank => {
+                debug!("{} already has rank {} in group {}, not changing it for {}.",
+                       roblox_id.0, current.rank, group_id, guild_id);
+                Ok(())
+            }
+            Some(_) => roblox_id.set_group_rank(credentials, group_id, rank),
+            None => cmd_error!("The user is not a member of group {}.", group_id),
+        }
+    }
+
+    // Promotes a user that has just been verified in a server to its configured starter rank.
+    // This happens in the background, and failures are reported to the server's audit channel
+    // rather than to the user.
+    pub fn promote_on_verify(
+        &self, guild_id: GuildId, discord_id: UserId, roblox_id: RobloxUserID,
+    ) {
+        let manager = self.clone();
+        self.0.tasks.dispatch_io_task(move |_| {
+            let config = &manager.0.config;
+            let group_id = config.get(Some(guild_id), ConfigKeys::StarterRankGroupID)?;
+            let rank = config.get(Some(guild_id), ConfigKeys::StarterRank)?;
+            let (group_id, rank) = match (group_id, rank) {
+                (Some(group_id), Some(rank)) => (group_id, rank),
+                _ => return Ok(()),
+            };
+            match manager.set_starter_rank(guild_id, roblox_id, group_id, rank) {
+                Ok(()) => Ok(()),
+                Err(e) => {
+                    warn!("Could not give {} rank {} in group {} for {}: {}",
+                          roblox_id.0, rank, group_id, guild_id, e);
+                    manager.0.audit.log_group_rank_failed(guild_id, discord_id, roblox_id,
+                                                          group_id, rank, e.to_string());
+                    Ok(())
+                }
+            }
+        })
+    }
+
+    // Whether a Roblox user is verified as a member of a server.
+    fn is_verified_member(&self, guild_id: GuildId, roblox_id: RobloxUserID) -> Result<bool> {
+        Ok(match self.0.verifier.get_verified_discord_user(roblox_id)? {
+            Some(discord_id) => match guild_id.find() {
+                Some(guild) => guild.read().members.contains_key(&discord_id),
+                None => false,
+            },
+            None => false,
+        })
+    }
+
+    fn answer_join_requests(
+        &self, guild_id: GuildId, credentials: GroupCredentials, group_id: u64,
+    ) -> Result<()> {
+        let config = &self.0.config;
+        let auto_accept = config.get(Some(guild_id), ConfigKeys::JoinRequestAutoAccept)?;
+        let auto_decline = config.get(Some(guild_id), ConfigKeys::JoinRequestAutoDecline)?;
+        if !auto_accept && !auto_decline {
+            return Ok(())
+        }
+
+        let mut accepted = Vec::new();
+        let mut declined = Vec::new();
+        let mut failed = Vec::new();
+        for roblox_id in roblox::list_join_requests(credentials, group_id)? {
+            let accept = self.is_verified_member(guild_id, roblox_id)?;
+            if (accept && !auto_accept) || (!accept && !auto_decline) {
+                continue
+            }
+            match roblox_id.answer_join_request(credentials, group_id, accept) {
+                Ok(()) if accept => accepted.push(roblox_id.0.to_string()),
+                Ok(()) => declined.push(roblox_id.0.to_string()),
+                Err(e) => {
+                    warn!("Could not {} the request of {} to join group {} for {}: {}",
+                          if accept { "accept" } else { "decline" }, roblox_id.0, group_id,
+                          guild_id, e);
+                    failed.push(format!("{}: {}", roblox_id.0, e));
+                }
+            }
+        }
+
+        if !accepted.is_empty() || !declined.is_empty() || !failed.is_empty() {
+            let mut fields = Vec::new();
+            for &(name, ref list) in &[("Accepted", &accepted), ("Declined", &declined),
+                                       ("Failed", &failed)] {
+                if !list.is_empty() {
+                    fields.push((name.to_owned(), list.join("\n")));
+                }
+            }
+            self.0.audit.log_guild_incident(
+                guild_id, "Answered group join requests".to_owned(),
+                format!("Requests to join Roblox group {} were answered automatically.",
+                        group_id),
+                fields,
+            );
+        }
+        Ok(())
+    }
+
+    // Accepts requests to join each server's Roblox group from its verified members, and
+    // declines the rest where configured to.
+    pub fn process_join_requests(&self) -> Result<()> {
+        let api_key = self.0.config.get(None, ConfigKeys::GroupAdminApiKey)?;
+        let cookie = self.0.config.get(None, ConfigKeys::GroupAdminCookie)?;
+        let guilds: Vec<GuildId> = serenity::CACHE.read().guilds.keys().cloned().collect();
+        for guild_id in guilds {
+            let group_id = self.0.config.get(Some(guild_id), ConfigKeys::JoinRequestGroupID)?;
+            let group_id = match group_id {
+                Some(group_id) => group_id,
+                None => continue,
+            };
+            let result = group_credentials(&api_key, &cookie).and_then(|credentials| {
+                self.answer_join_requests(guild_id, credentials, group_id)
+            });
+            if let Err(e) = result {
+                warn!("Could not answer join requests for group {} in {}: {}",
+                      group_id, guild_id, e);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,80 @@
+use database::*;
+use errors::*;
+use serenity::model::prelude::*;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+#[derive(Copy, Clone, Debug)]
+pub struct GuestPass {
+    pub guild_id: GuildId, pub discord_id: UserId, pub role_id: RoleId,
+    pub expires_at: SystemTime,
+}
+
+struct GuestPassManagerData {
+    database: Database,
+}
+
+// Tracks roles given to members for a limited time without verifying, such as to visitors of an
+// event. Members with a pass are treated as verified when their roles are set.
+#[derive(Clone)]
+pub struct GuestPassManager(Arc<GuestPassManagerData>);
+impl GuestPassManager {
+    pub fn new(database: Database) -> GuestPassManager {
+        GuestPassManager(Arc::new(GuestPassManagerData { database }))
+    }
+
+    // Gives a member a pass for a role. Any pass they already had is replaced.
+    pub fn grant(
+        &self, guild_id: GuildId, discord_id: UserId, role_id: RoleId, duration: Duration,
+        granted_by: UserId,
+    ) -> Result<GuestPass> {
+        let now = SystemTime::now();
+        let expires_at = now + duration;
+        self.0.database.connect()?.execute(
+            "REPLACE INTO guest_passes \
+                 (discord_guild_id, discord_user_id, discord_role_id, granted_by, granted_at, \
+                  expires_at) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            (guild_id, discord_id, role_id, granted_by, now, expires_at),
+        )?;
+        Ok(GuestPass { guild_id, discord_id, role_id, expires_at })
+    }
+
+    // Returns the member's pass, unless it has expired.
+    pub fn get(&self, guild_id: GuildId, discord_id: UserId) -> Result<Option<GuestPass>> {
+        let pass = self.0.database.connect_read()?.query(
+            "SELECT discord_role_id, expires_at FROM guest_passes \
+             WHERE discord_guild_id = ?1 AND discord_user_id = ?2 AND expires_at > ?3",
+            (guild_id, discord_id, SystemTime::now()),
+        ).get_opt::<(RoleId, SystemTime)>()?;
+        Ok(pass.map(|(role_id, expires_at)| {
+            GuestPass { guild_id, discord_id, role_id, expires_at }
+        }))
+    }
+
+    // Removes a member's pass, and returns it if they had one.
+    pub fn revoke(&self, guild_id: GuildId, discord_id: UserId) -> Result<Option<GuestPass>> {
+        let pass = self.get(guild_id, discord_id)?;
+        self.0.database.connect()?.execute(
+            "DELETE FROM guest_passes WHERE discord_guild_id = ?1 AND discord_user_id = ?2",
+            (guild_id, discord_id),
+        )?;
+        Ok(pass)
+    }
+
+    // Removes every pass that has expired, and returns them.
+    pub fn take_expired(&self) -> Result<Vec<GuestPass>> {
+        let now = SystemTime::now();
+        let conn = self.0.database.connect()?;
+        conn.transaction_immediate(|| {
+            let expired = conn.query(
+                "SELECT discord_guild_id, discord_user_id, discord_role_id, expires_at \
+                 FROM guest_passes WHERE expires_at <= ?1", now,
+            ).get_all::<(GuildId, UserId, RoleId, SystemTime)>()?;
+            conn.execute("DELETE FROM guest_passes WHERE expires_at <= ?1", now)?;
+            Ok(expired.into_iter().map(|(guild_id, discord_id, role_id, expires_at)| GuestPass {
+                guild_id, discord_id, role_id, expires_at,
+            }).collect())
+        })
+    }
+}
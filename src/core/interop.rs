@@ -0,0 +1,162 @@
+use core::config::*;
+use core::verifier::Verifier;
+use errors::*;
+use reqwest;
+use reqwest::StatusCode;
+use reqwest::header::Headers;
+use roblox::RobloxUserID;
+use serde_json;
+use serde_json::Value;
+use serenity::model::prelude::*;
+use std::thread;
+use std::time::Duration;
+
+const API_LOOKUP_DELAY_MS: u64 = 500;
+
+#[derive(Deserialize)]
+struct RoVerLookup {
+    #[serde(rename = "robloxId")] roblox_id: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct BloxlinkLookup {
+    #[serde(rename = "robloxID")] roblox_id: Option<String>,
+}
+
+fn parse_id(value: &Value) -> Option<u64> {
+    match *value {
+        Value::Number(ref n) => n.as_u64(),
+        Value::String(ref s) => s.parse().ok(),
+        _ => None,
+    }
+}
+fn find_id(entry: &Value, fields: &[&str]) -> Option<u64> {
+    fields.iter().filter_map(|x| entry.get(x)).filter_map(parse_id).next()
+}
+
+// Other verification bots that links can be imported from, so servers can migrate without
+// everyone needing to reverify.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ImportSource {
+    RoVer, Bloxlink,
+}
+impl ImportSource {
+    pub fn from_name(name: &str) -> Result<ImportSource> {
+        match name.to_lowercase().as_str() {
+            "rover" => Ok(ImportSource::RoVer),
+            "bloxlink" => Ok(ImportSource::Bloxlink),
+            _ => cmd_error!("Unknown import source '{}'. Use `rover` or `bloxlink`.", name),
+        }
+    }
+    pub fn name(self) -> &'static str {
+        match self {
+            ImportSource::RoVer => "RoVer",
+            ImportSource::Bloxlink => "Bloxlink",
+        }
+    }
+
+    fn discord_fields(self) -> &'static [&'static str] {
+        match self {
+            ImportSource::RoVer => &["discordId", "discord_id"],
+            ImportSource::Bloxlink => &["discordID", "discordId", "discord_id"],
+        }
+    }
+    fn roblox_fields(self) -> &'static [&'static str] {
+        match self {
+            ImportSource::RoVer => &["robloxId", "roblox_id"],
+            ImportSource::Bloxlink => &["robloxID", "robloxId", "primaryAccount", "roblox_id"],
+        }
+    }
+
+    // Parses a JSON data export. This accepts either a list of objects containing a Discord ID
+    // and a Roblox ID, or an object mapping Discord IDs to a Roblox ID or an object containing
+    // one.
+    pub fn parse_export(self, data: &str) -> Result<Vec<(UserId, RobloxUserID)>> {
+        let json = serde_json::from_str::<Value>(data)
+            .to_cmd_err(|| format!("The {} export is not valid JSON.", self.name()))?;
+        let mut links = Vec::new();
+        match json {
+            Value::Array(ref entries) => for entry in entries {
+                let discord_id = find_id(entry, self.discord_fields());
+                let roblox_id = find_id(entry, self.roblox_fields());
+                match (discord_id, roblox_id) {
+                    (Some(discord_id), Some(roblox_id)) =>
+                        links.push((UserId(discord_id), RobloxUserID(roblox_id))),
+                    _ => cmd_error!("Invalid entry in {} export: {}", self.name(), entry),
+                }
+            },
+            Value::Object(ref entries) => for (discord_id, entry) in entries {
+                let discord_id = discord_id.parse().to_cmd_err(|| format!(
+                    "Invalid Discord ID in {} export: {}", self.name(), discord_id,
+                ))?;
+                let roblox_id = parse_id(entry).or_else(|| find_id(entry, self.roblox_fields()))
+                    .to_cmd_err(|| format!("Invalid entry in {} export: {}", self.name(), entry))?;
+                links.push((UserId(discord_id), RobloxUserID(roblox_id)));
+            },
+            _ => cmd_error!("The {} export is not in a recognized format.", self.name()),
+        }
+        Ok(links)
+    }
+
+    fn lookup_api(
+        self, config: &ConfigManager, guild_id: GuildId, discord_id: UserId,
+    ) -> Result<Option<RobloxUserID>> {
+        match self {
+            ImportSource::RoVer => {
+                let uri = format!("https://verify.eryn.io/api/user/{}", discord_id.0);
+                let response = reqwest::get(&uri)?;
+                if response.status() == StatusCode::NotFound {
+                    return Ok(None)
+                }
+                let json = response.error_for_status()?.text()?;
+                Ok(serde_json::from_str::<RoVerLookup>(&json)?.roblox_id.map(RobloxUserID))
+            }
+            ImportSource::Bloxlink => {
+                let api_key = config.get(Some(guild_id), ConfigKeys::BloxlinkApiKey)?
+                    .to_cmd_err(|| "A Bloxlink API key must be set for this server with \
+                                    `bloxlink_api_key` to import using the Bloxlink API.")?;
+                let uri = format!("https://api.blox.link/v4/public/guilds/{}/discord-to-roblox/{}",
+                                  guild_id.0, discord_id.0);
+                let mut headers = Headers::new();
+                headers.set_raw("Authorization", api_key);
+                let response = reqwest::Client::new().get(&uri).headers(headers).send()?;
+                if response.status() == StatusCode::NotFound {
+                    return Ok(None)
+                }
+                let json = response.error_for_status()?.text()?;
+                let info = serde_json::from_str::<BloxlinkLookup>(&json)?;
+                Ok(info.roblox_id.and_then(|x| x.parse().ok()).map(RobloxUserID))
+            }
+        }
+    }
+
+    // Looks up every member of a guild that is not yet verified with this bot using the other
+    // bot's public API. This makes one request per member, so it can take a long time.
+    pub fn lookup_guild(
+        self, config: &ConfigManager, verifier: &Verifier, guild_id: GuildId,
+    ) -> Result<Vec<(UserId, RobloxUserID)>> {
+        let members: Vec<UserId> = {
+            let guild = guild_id.find().to_cmd_err(|| "That server is not in the cache.")?;
+            let guild = guild.read();
+            guild.members.values()
+                .filter(|x| !x.user.read().bot)
+                .map(|x| x.user.read().id)
+                .collect()
+        };
+
+        let mut links = Vec::new();
+        for (i, discord_id) in members.into_iter().enumerate() {
+            if verifier.get_verified_roblox_user(discord_id)?.is_some() {
+                continue
+            }
+            if i % 100 == 0 {
+                debug!("Looking up {} links in {}: {} members done", self.name(), guild_id, i);
+            }
+            if let Some(roblox_id) = self.lookup_api(config, guild_id, discord_id)? {
+                links.push((discord_id, roblox_id));
+            }
+            thread::sleep(Duration::from_millis(API_LOOKUP_DELAY_MS));
+        }
+        Ok(links)
+    }
+}
@@ -0,0 +1,190 @@
+use errors::*;
+use hmac::{Hmac, Mac};
+use rand::{Rng, OsRng};
+use ring::aead::{CHACHA20_POLY1305, OpeningKey, SealingKey, open_in_place, seal_in_place};
+use ring::{digest, pbkdf2};
+use sha2::Sha256;
+use std::env;
+use std::fs::File;
+use std::io::Read;
+
+// The key used to encrypt verification keys at rest is read from one of these, if either is set.
+// The file variant is meant for secret stores that provide secrets as files, such as systemd
+// credentials or Docker secrets.
+const KEY_ENV_VAR: &str = "SYLPHIE_KEY_ENCRYPTION_KEY";
+const KEY_FILE_ENV_VAR: &str = "SYLPHIE_KEY_ENCRYPTION_KEY_FILE";
+const MIN_KEY_LENGTH: usize = 16;
+
+// Encrypted keys are stored as the magic bytes, a random salt and nonce, and the ciphertext
+// followed by its authentication tag. The magic bytes are authenticated along with it.
+const MAGIC: &[u8] = b"SYE2";
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 12;
+const TAG_LENGTH: usize = 16;
+// The encryption key is derived from the configured key and the salt with PBKDF2, as the
+// configured key may be a passphrase.
+const KDF_ITERATIONS: u32 = 100_000;
+const DERIVED_KEY_LENGTH: usize = 32;
+
+pub(super) fn hmac(key: &[u8], parts: &[&[u8]]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new(key).unwrap();
+    for part in parts {
+        mac.input(part);
+    }
+    mac.result().code().to_vec()
+}
+
+// Returns whether a stored key was encrypted with `KeyEncryption::seal`. Unencrypted keys are
+// always 64 bytes, so they cannot be mistaken for encrypted ones.
+pub fn is_sealed(data: &[u8]) -> bool {
+    data.len() >= MAGIC.len() + SALT_LENGTH + NONCE_LENGTH + TAG_LENGTH && data.starts_with(MAGIC)
+}
+
+// Encrypts verification keys before they are stored in the database, so that a copy of the
+// database alone cannot be used to create valid tokens. This uses ChaCha20-Poly1305 from `ring`,
+// with a key derived by PBKDF2-HMAC-SHA256.
+pub struct KeyEncryption {
+    master_key: Vec<u8>,
+}
+impl KeyEncryption {
+    fn new(master_key: &[u8]) -> KeyEncryption {
+        KeyEncryption { master_key: master_key.to_vec() }
+    }
+
+    // Reads the encryption key from the environment. Returns `None` if keys should be stored
+    // without encryption.
+    pub fn from_env() -> Result<Option<KeyEncryption>> {
+        let master_key = if let Some(key) = env::var_os(KEY_ENV_VAR) {
+            match key.into_string() {
+                Ok(key) => key,
+                Err(_) => bail!("{} is not valid UTF-8.", KEY_ENV_VAR),
+            }
+        } else if let Some(path) = env::var_os(KEY_FILE_ENV_VAR) {
+            let mut key = String::new();
+            File::open(&path)?.read_to_string(&mut key)?;
+            key
+        } else {
+            return Ok(None)
+        };
+        let master_key = master_key.trim();
+        ensure!(master_key.len() >= MIN_KEY_LENGTH,
+                "The verification key encryption key must be at least {} characters.",
+                MIN_KEY_LENGTH);
+        Ok(Some(KeyEncryption::new(master_key.as_bytes())))
+    }
+
+    fn derive_key(&self, salt: &[u8]) -> [u8; DERIVED_KEY_LENGTH] {
+        let mut key = [0u8; DERIVED_KEY_LENGTH];
+        pbkdf2::derive(&digest::SHA256, KDF_ITERATIONS, salt, &self.master_key, &mut key);
+        key
+    }
+
+    pub fn seal(&self, key: &[u8]) -> Result<Vec<u8>> {
+        let mut rng = OsRng::new()?;
+        let mut salt = [0u8; SALT_LENGTH];
+        rng.fill_bytes(&mut salt);
+        let mut nonce = [0u8; NONCE_LENGTH];
+        rng.fill_bytes(&mut nonce);
+
+        let sealing_key = match SealingKey::new(&CHACHA20_POLY1305, &self.derive_key(&salt)) {
+            Ok(sealing_key) => sealing_key,
+            Err(_) => bail!("Could not create the verification key encryption key."),
+        };
+        let mut in_out = key.to_vec();
+        in_out.extend_from_slice(&[0u8; TAG_LENGTH]);
+        let len = match seal_in_place(&sealing_key, &nonce, MAGIC, &mut in_out, TAG_LENGTH) {
+            Ok(len) => len,
+            Err(_) => bail!("Could not encrypt a verification key."),
+        };
+
+        let mut sealed = MAGIC.to_vec();
+        sealed.extend_from_slice(&salt);
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&in_out[..len]);
+        Ok(sealed)
+    }
+
+    pub fn open(&self, sealed: &[u8]) -> Result<Vec<u8>> {
+        ensure!(is_sealed(sealed), "Stored verification key is not encrypted.");
+        let salt = &sealed[MAGIC.len()..MAGIC.len() + SALT_LENGTH];
+        let nonce = &sealed[MAGIC.len() + SALT_LENGTH..MAGIC.len() + SALT_LENGTH + NONCE_LENGTH];
+        let mut in_out = sealed[MAGIC.len() + SALT_LENGTH + NONCE_LENGTH..].to_vec();
+
+        let opening_key = match OpeningKey::new(&CHACHA20_POLY1305, &self.derive_key(salt)) {
+            Ok(opening_key) => opening_key,
+            Err(_) => bail!("Could not create the verification key encryption key."),
+        };
+        match open_in_place(&opening_key, nonce, MAGIC, 0, &mut in_out) {
+            Ok(key) => Ok(key.to_vec()),
+            Err(_) => bail!("Could not decrypt a stored verification key. Is {} set to the key \
+                             it was encrypted with?", KEY_ENV_VAR),
+        }
+    }
+
+}
+
+// Returns a key loaded from the database in its unencrypted form.
+pub fn open_stored_key(encryption: Option<&KeyEncryption>, stored: &[u8]) -> Result<Vec<u8>> {
+    match encryption {
+        Some(encryption) if is_sealed(stored) => encryption.open(stored),
+        None if is_sealed(stored) =>
+            bail!("The verification keys in the database are encrypted, but neither {} nor {} \
+                   is set.", KEY_ENV_VAR, KEY_FILE_ENV_VAR),
+        _ => Ok(stored.to_vec()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MASTER_KEY: &[u8] = b"correct horse battery staple";
+
+    fn test_key() -> Vec<u8> {
+        (0..64).map(|x| x as u8).collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let encryption = KeyEncryption::new(MASTER_KEY);
+        let sealed = encryption.seal(&test_key()).unwrap();
+        assert!(is_sealed(&sealed));
+        assert_eq!(encryption.open(&sealed).unwrap(), test_key());
+        assert_eq!(open_stored_key(Some(&encryption), &sealed).unwrap(), test_key());
+    }
+
+    #[test]
+    fn unique_nonces() {
+        let encryption = KeyEncryption::new(MASTER_KEY);
+        assert_ne!(encryption.seal(&test_key()).unwrap(), encryption.seal(&test_key()).unwrap());
+    }
+
+    #[test]
+    fn tampering_detected() {
+        let encryption = KeyEncryption::new(MASTER_KEY);
+        let sealed = encryption.seal(&test_key()).unwrap();
+        // One byte each of the salt, nonce, ciphertext and tag.
+        let salt = MAGIC.len();
+        let nonce = salt + SALT_LENGTH;
+        let ciphertext = nonce + NONCE_LENGTH;
+        for &i in &[salt, nonce, ciphertext, sealed.len() - 1] {
+            let mut tampered = sealed.clone();
+            tampered[i] ^= 1;
+            assert!(encryption.open(&tampered).is_err(), "byte {} was not authenticated", i);
+        }
+        assert!(encryption.open(&sealed[..sealed.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn wrong_key_rejected() {
+        let sealed = KeyEncryption::new(MASTER_KEY).seal(&test_key()).unwrap();
+        assert!(KeyEncryption::new(b"another sixteen+ byte key").open(&sealed).is_err());
+        assert!(open_stored_key(None, &sealed).is_err());
+    }
+
+    #[test]
+    fn unsealed_keys_pass_through() {
+        assert!(!is_sealed(&test_key()));
+        assert_eq!(open_stored_key(None, &test_key()).unwrap(), test_key());
+    }
+}
@@ -0,0 +1,135 @@
+use database::Database;
+use errors::*;
+use parking_lot::Mutex;
+use rand::{Rng, thread_rng};
+use std::env;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+const LEASE_NAME: &str = "bot";
+
+struct LeaderLeaseData {
+    database: Database, instance_id: String, lease_time: Duration,
+    last_renewed: Mutex<Option<Instant>>, lost: AtomicBool,
+}
+
+// Lets several instances share a database, with only one of them running the bot at a time. The
+// leader holds a lease in the database, which it renews regularly. If it stops renewing it, such
+// as when it crashes, another instance takes over once it expires.
+//
+// Leases are compared against the local time of each instance, so their clocks must be kept in
+// sync.
+#[derive(Clone)]
+pub struct LeaderLease(Arc<LeaderLeaseData>);
+impl LeaderLease {
+    pub fn new(database: Database, lease_time: Duration) -> LeaderLease {
+        let host = env::var("HOSTNAME").unwrap_or_else(|_| "instance".to_owned());
+        let instance_id = format!("{}-{:08x}", host, thread_rng().gen::<u32>());
+        LeaderLease(Arc::new(LeaderLeaseData {
+            database, instance_id, lease_time,
+            last_renewed: Mutex::new(None), lost: AtomicBool::new(false),
+        }))
+    }
+
+    pub fn instance_id(&self) -> &str {
+        &self.0.instance_id
+    }
+    // The lease is renewed several times per lease period, so one slow renewal does not lose it.
+    pub fn renew_interval(&self) -> Duration {
+        Duration::from_secs((self.0.lease_time.as_secs() / 3).max(1))
+    }
+
+    // Takes or renews the lease, returning whether this instance holds it.
+    fn try_acquire(&self) -> Result<bool> {
+        let now = SystemTime::now();
+        let expires_at = now + self.0.lease_time;
+        let conn = self.0.database.connect()?;
+        conn.transaction_immediate(|| {
+            let current = conn.query(
+                "SELECT instance_id, expires_at FROM leader_lease WHERE lease_name = ?1",
+                LEASE_NAME,
+            ).get_opt::<(String, SystemTime)>()?;
+            match current {
+                Some((ref instance_id, _)) if *instance_id == self.0.instance_id => {
+                    conn.execute(
+                        "UPDATE leader_lease SET expires_at = ?2 WHERE lease_name = ?1",
+                        (LEASE_NAME, expires_at),
+                    )?;
+                }
+                Some((_, current_expires_at)) if current_expires_at > now => return Ok(false),
+                _ => {
+                    conn.execute(
+                        "REPLACE INTO leader_lease \
+                             (lease_name, instance_id, acquired_at, expires_at) \
+                         VALUES (?1, ?2, ?3, ?4)",
+                        (LEASE_NAME, &self.0.instance_id, now, expires_at),
+                    )?;
+                }
+            }
+            Ok(true)
+        })
+    }
+
+    // Blocks until this instance becomes the leader.
+    pub fn wait_for_leadership(&self) -> Result<()> {
+        let mut printed_waiting = false;
+        loop {
+            if self.try_acquire()? {
+                *self.0.last_renewed.lock() = Some(Instant::now());
+                self.0.lost.store(false, Ordering::Relaxed);
+                info!("Instance {} is now the leader.", self.0.instance_id);
+                return Ok(())
+            }
+            if !printed_waiting {
+                info!("Another instance is the leader. Instance {} is standing by.",
+                      self.0.instance_id);
+                printed_waiting = true;
+            }
+            thread::sleep(self.renew_interval());
+        }
+    }
+
+    // Renews the lease, returning false if it was lost. Errors are tolerated until the lease
+    // would expire before the next renewal.
+    pub fn renew(&self) -> bool {
+        if self.is_lost() {
+            return false
+        }
+        match self.try_acquire() {
+            Ok(true) => {
+                *self.0.last_renewed.lock() = Some(Instant::now());
+                return true
+            }
+            Ok(false) => warn!("Another instance took over the leader lease."),
+            Err(e) => {
+                let last_renewed = *self.0.last_renewed.lock();
+                let expiring = last_renewed.map_or(true, |x| {
+                    x.elapsed() + self.renew_interval() >= self.0.lease_time
+                });
+                if !expiring {
+                    warn!("Could not renew the leader lease: {}", e);
+                    return true
+                }
+                error!("Could not renew the leader lease before it expires: {}", e);
+            }
+        }
+        self.0.lost.store(true, Ordering::Relaxed);
+        false
+    }
+    pub fn is_lost(&self) -> bool {
+        self.0.lost.load(Ordering::Relaxed)
+    }
+
+    // Gives up the lease, so another instance can take over without waiting for it to expire.
+    pub fn release(&self) -> Result<()> {
+        if !self.is_lost() {
+            self.0.database.connect()?.execute(
+                "DELETE FROM leader_lease WHERE lease_name = ?1 AND instance_id = ?2",
+                (LEASE_NAME, &self.0.instance_id),
+            )?;
+        }
+        Ok(())
+    }
+}
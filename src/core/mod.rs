@@ -1,32 +1,90 @@
 use commands::*;
-use database::Database;
+use database::{Database, DatabaseMetrics};
+use error_report;
+use error_report::{ReportSink, ReportSinkKind};
 use errors::*;
 use parking_lot::RwLock;
+use roblox;
+use roblox::RobloxUserID;
+use serenity::model::id::{GuildId, UserId};
+use std::fs;
 use std::mem::drop;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::sync::atomic::{AtomicU8, Ordering};
 use std::thread;
 use std::time::{Duration, Instant};
+use util::SystemClock;
+use web::WebServer;
 
+mod admin_actions;
+mod approvals;
+mod audit;
+mod blacklist;
 mod config;
+mod config_export;
 mod discord;
+mod embeds;
+mod enforcement;
+mod event_log;
+mod group_ranks;
+mod guest_passes;
+mod interop;
+mod key_encryption;
+mod leader;
+mod name_policy;
+mod nickname;
+mod permissions;
 mod place;
 mod roles;
+mod signal;
+mod stats;
 mod tasks;
+mod templates;
 mod terminal;
+#[cfg(test)] mod test_support;
+mod usernames;
 mod verification_channel;
 mod verifier;
+mod webhooks;
 
-pub use self::config::{ConfigManager, ConfigKey, ConfigKeys};
-pub use self::roles::{RoleManager, AssignedRole, ConfiguredRole, SetRolesStatus};
+pub use self::admin_actions::{AdminActionManager, AdminAction, PendingAction,
+                              CONFIRM_ACTION_BUTTON_PREFIX, CANCEL_ACTION_BUTTON_PREFIX};
+pub use self::approvals::{ApprovalManager, ApprovalStatus, PendingVerification,
+                          APPROVE_BUTTON_PREFIX, REJECT_BUTTON_PREFIX};
+pub use self::audit::{AuditLog, AuditEvent};
+pub use self::blacklist::{BlacklistManager, BlacklistTarget, BlacklistEntry};
+pub use self::config::{ConfigManager, ConfigKey, ConfigKeys, EnvConfig, RawConfigValue};
+pub use self::config_export::{ConfigImportSummary, GuildSetting, export_config, import_config};
+pub use self::embeds::{EmbedTheme, parse_colour};
+pub use self::enforcement::{EnforcementManager, EnforcementAction, parse_reminder_days};
+pub use self::event_log::{EventLog, EventLogProblem, EventLogStatus};
+pub use self::group_ranks::GroupRankManager;
+pub use self::guest_passes::{GuestPassManager, GuestPass};
+pub use self::interop::ImportSource;
+pub use self::leader::LeaderLease;
+pub use self::name_policy::check_name_patterns;
+pub use self::nickname::{NicknameSource, check_nickname_format};
+pub use self::permissions::{PermissionManager, PermissionTarget, PermissionGrant,
+                            node_matches};
+pub use self::roles::{RoleManager, AssignedRole, ConfiguredRole, SetRolesStatus,
+                      MassUpdateStatus, MassUpdatePreview, PlannedRoleChange, RuleChange};
+pub use self::stats::{StatsManager, CommandOutcome, CommandUsage};
+pub use self::tasks::{JobScheduler, JobStatus, Schedule};
+pub use self::templates::{MessageTemplateManager, MessageKey};
+pub use self::usernames::UsernameManager;
 pub use self::verification_channel::VerificationChannelManager;
-pub use self::verifier::{Verifier, VerifyResult, TokenStatus};
+pub use self::verifier::{Verifier, VerifyResult, CooldownInfo, TokenStatus, VerificationMethod,
+                         MethodContext, TokenVerification, ProfileVerification,
+                         OAuthVerification, RobloxOAuthVerification,
+                         FriendVerification, FriendClaim, PlaceCodeVerification, PlaceCodeClaim,
+                         AltAccountMatch, VerificationAttempt, DeletionReceipt};
+pub use self::webhooks::{WebhookManager, check_webhook_url};
 
 use self::discord::DiscordManager;
 use self::place::PlaceManager;
 use self::terminal::Terminal;
-use self::tasks::TaskManager;
+use self::tasks::{TaskManager, ScheduledJob};
 
 const STATUS_STOPPED : u8 = 0;
 const STATUS_RUNNING : u8 = 1;
@@ -34,10 +92,15 @@ const STATUS_STOPPING: u8 = 2;
 
 struct VerifierCoreData {
     status: AtomicU8,
-    _database: Database, config: ConfigManager, core_ref: CoreRef,
+    root_path: PathBuf, database: Database, config: ConfigManager, core_ref: CoreRef,
     terminal: Terminal, verifier: Verifier, discord: DiscordManager,
-    place: PlaceManager, roles: RoleManager, _tasks: TaskManager,
-    verify_channel: VerificationChannelManager,
+    place: PlaceManager, roles: RoleManager, tasks: TaskManager,
+    verify_channel: VerificationChannelManager, audit: AuditLog, _webhooks: WebhookManager,
+    web: WebServer, blacklist: BlacklistManager, stats: StatsManager, usernames: UsernameManager,
+    templates: MessageTemplateManager, jobs: JobScheduler, approvals: ApprovalManager,
+    group_ranks: GroupRankManager, enforcement: EnforcementManager,
+    permissions: PermissionManager, event_log: EventLog, admin_actions: AdminActionManager,
+    guest_passes: GuestPassManager, lease: Option<LeaderLease>,
 }
 
 struct CoreRefActiveGuard<'a>(&'a CoreRef);
@@ -48,7 +111,7 @@ impl <'a> Drop for CoreRefActiveGuard<'a> {
 }
 
 #[derive(Clone)]
-struct CoreRef(Arc<RwLock<Option<Arc<VerifierCoreData>>>>);
+pub struct CoreRef(Arc<RwLock<Option<Arc<VerifierCoreData>>>>);
 impl CoreRef {
     fn new() -> CoreRef {
         CoreRef(Arc::new(RwLock::new(None)))
@@ -69,44 +132,156 @@ impl CoreRef {
         self.0.read().as_ref().map(|x| VerifierCore(x.clone()))
     }
     pub fn run_command(&self, command: &Command, ctx: &CommandContextData) {
-        if let Some(core) = self.get_core() {
-            command.run(ctx, &core);
-        } else  {
-            ctx.respond("The bot is currently shutting down. Please wait until it is \
-                         restarted.").ok();
+        match self.get_core() {
+            Some(ref core) if core.is_running() => command.run(ctx, core),
+            _ => { ctx.respond(SHUTDOWN_MESSAGE).ok(); }
         }
     }
 }
 
+static SCHEDULED_JOBS: &'static [ScheduledJob] = &[
+    ScheduledJob {
+        name: "cleanup", description: "Frees unused cached data.",
+        default_schedule: "*/10 * * * *", run: VerifierCore::cleanup,
+    },
+    ScheduledJob {
+        name: "auto_rekey", description: "Changes the shared key if it is too old.",
+        default_schedule: "0 * * * *", run: VerifierCore::check_auto_rekey,
+    },
+    ScheduledJob {
+        name: "enforce_verification",
+        description: "Warns, then kicks or times out members who have not verified in time.",
+        default_schedule: "*/15 * * * *", run: VerifierCore::enforce_verification,
+    },
+    ScheduledJob {
+        name: "decay_roles",
+        description: "Removes the roles of members who have not updated them recently.",
+        default_schedule: "45 * * * *", run: VerifierCore::decay_roles,
+    },
+    ScheduledJob {
+        name: "guest_passes", description: "Removes the roles of guest passes that have expired.",
+        default_schedule: "*/5 * * * *", run: VerifierCore::expire_guest_passes,
+    },
+    ScheduledJob {
+        name: "verification_reminders",
+        description: "Reminds members who have not verified yet in direct messages.",
+        default_schedule: "20 * * * *", run: VerifierCore::send_verification_reminders,
+    },
+    ScheduledJob {
+        name: "refresh_usernames", description: "Checks verified Roblox accounts for renames.",
+        default_schedule: "30 * * * *", run: VerifierCore::refresh_usernames,
+    },
+    ScheduledJob {
+        name: "join_requests",
+        description: "Answers requests to join Roblox groups based on verification.",
+        default_schedule: "*/5 * * * *", run: VerifierCore::process_join_requests,
+    },
+];
+
+// How often the bot's Roblox account is checked for friend requests while users are waiting to
+// verify with one.
+const FRIEND_REQUEST_POLL_SECS: u64 = 15;
+
+// Turns the result of a verification made outside of a command into whether it succeeded and the
+// message to show the user.
+fn background_result_message(discord_id: UserId, result: Result<String>) -> (bool, String) {
+    match result {
+        Ok(message) => (true, message),
+        Err(Error::CommandError(message)) => (false, message.into_owned()),
+        Err(e) => {
+            error!("Could not complete a verification for {}: {}", discord_id, e);
+            (false, "An error occurred while verifying. Please try again.".to_owned())
+        }
+    }
+}
+
+pub const SHUTDOWN_MESSAGE: &str =
+    "The bot is currently shutting down. Please wait until it is restarted.";
+
+// How long to wait for verification attempts and Roblox API requests to finish when shutting down.
+const SHUTDOWN_DRAIN_TIMEOUT_SECS: u64 = 30;
+
 const PLACE_TARGET_NAME: &str = "Sylph-Verifier.rbxl";
+const EVENT_LOG_KEY_NAME: &str = "Sylph-Verifier.eventlog.key";
+pub const BACKUP_DIR_NAME: &str = "backups";
+
+// The event log signing key is kept beside the database, so instances sharing it also share the
+// key.
+pub fn event_log_key_path(db_path: &Path) -> PathBuf {
+    db_path.with_file_name(EVENT_LOG_KEY_NAME)
+}
 
 #[derive(Clone)]
 pub struct VerifierCore(Arc<VerifierCoreData>);
 impl VerifierCore {
-    pub fn new(root_path: PathBuf, database: Database) -> Result<VerifierCore> {
+    // `lease` is the leader lease this instance holds, when several instances share a database.
+    pub fn new(
+        root_path: PathBuf, db_path: &Path, database: Database, lease: Option<LeaderLease>,
+    ) -> Result<VerifierCore> {
         let mut place_target = root_path.clone();
         place_target.push(PLACE_TARGET_NAME);
+        let event_log_key = event_log_key_path(db_path);
+        let old_event_log_key = root_path.join(EVENT_LOG_KEY_NAME);
+        if !event_log_key.exists() && old_event_log_key.exists() {
+            info!("Moving the event log signing key to '{}'.", event_log_key.display());
+            fs::copy(&old_event_log_key, &event_log_key)?;
+            fs::remove_file(&old_event_log_key)?;
+        }
 
         let config = ConfigManager::new(database.clone());
         let core_ref = CoreRef::new();
 
         let tasks = TaskManager::new(core_ref.clone())?;
         let terminal = Terminal::new(core_ref.clone())?;
-        let verify_channel = VerificationChannelManager::new(config.clone(), database.clone());
-        let verifier = Verifier::new(config.clone(), database.clone())?;
+        let templates = MessageTemplateManager::new(database.clone());
+        let verify_channel = VerificationChannelManager::new(config.clone(), database.clone(),
+                                                             templates.clone());
+        let audit = AuditLog::new(config.clone(), tasks.clone());
+        let webhooks = WebhookManager::new(config.clone(), tasks.clone());
+        let blacklist = BlacklistManager::new(database.clone());
+        let permissions = PermissionManager::new(database.clone());
+        let event_log = EventLog::new(database.clone(), &event_log_key)?;
+        let stats = StatsManager::new(database.clone());
+        let usernames = UsernameManager::new(database.clone());
+        let verifier = Verifier::new(config.clone(), database.clone(), audit.clone(),
+                                     webhooks.clone(), blacklist.clone(), stats.clone(),
+                                     usernames.clone(), event_log.clone(),
+                                     Arc::new(SystemClock))?;
+        let web = WebServer::new(config.clone(), core_ref.clone(), verifier.clone());
         let place = PlaceManager::new(place_target)?;
+        let approvals = ApprovalManager::new(config.clone(), database.clone());
+        let admin_actions = AdminActionManager::new(config.clone(), database.clone());
+        let group_ranks = GroupRankManager::new(config.clone(), audit.clone(), tasks.clone(),
+                                                verifier.clone());
+        let guest_passes = GuestPassManager::new(database.clone());
+        let enforcement = EnforcementManager::new(config.clone(), database.clone(),
+                                                  verifier.clone(), guest_passes.clone());
         let roles = RoleManager::new(config.clone(), database.clone(), verifier.clone(),
-                                     tasks.clone());
+                                     tasks.clone(), blacklist.clone(), approvals.clone(),
+                                     guest_passes.clone());
         let discord = DiscordManager::new(config.clone(), core_ref.clone(), roles.clone(),
-                                          tasks.clone(), verify_channel.clone());
+                                          tasks.clone(), verify_channel.clone(),
+                                          blacklist.clone());
 
-        tasks.dispatch_repeating_task(Duration::from_secs(60 * 10), |core| core.cleanup());
+        let jobs = JobScheduler::new(database.clone(), tasks.clone(), SCHEDULED_JOBS)?;
+        tasks.dispatch_repeating_task(Duration::from_secs(FRIEND_REQUEST_POLL_SECS),
+                                      |core| core.poll_friend_requests());
+        if let Some(ref lease) = lease {
+            tasks.dispatch_repeating_task(lease.renew_interval(), |core| core.renew_lease());
+        }
 
-        Ok(VerifierCore(Arc::new(VerifierCoreData {
+        let core = VerifierCore(Arc::new(VerifierCoreData {
             status: AtomicU8::new(STATUS_STOPPED),
-            _database: database, _tasks: tasks,
-            config, core_ref, terminal, verifier, discord, place, roles, verify_channel,
-        })))
+            root_path, database, tasks, audit, _webhooks: webhooks,
+            config, core_ref, terminal, verifier, discord, place, roles, verify_channel, web,
+            blacklist, stats, usernames, templates, jobs, approvals, group_ranks, enforcement,
+            permissions, event_log, admin_actions, guest_passes, lease,
+        }));
+        core.refresh_api_caches()?;
+        if let Err(e) = core.update_error_reporting() {
+            warn!("Error reporting is not configured correctly: {}", e);
+        }
+        Ok(core)
     }
 
     fn cleanup(&self) -> Result<()> {
@@ -116,8 +291,260 @@ impl VerifierCore {
         self.0.roles.on_cleanup_tick();
         self.0.verify_channel.on_cleanup_tick();
         self.0.verifier.on_cleanup_tick();
+        self.0.verifier.prune_attempts()?;
+        self.0.blacklist.on_cleanup_tick();
+        self.0.permissions.on_cleanup_tick();
+        self.0.templates.on_cleanup_tick();
+        self.0.web.on_cleanup_tick();
+        roblox::prune_api_caches();
+        self.0.admin_actions.expire_pending()?;
+        prune_rate_limits();
+        prune_paginators();
+        error_report::set_secrets(self.report_secrets()?);
         Ok(())
     }
+    // Stops the bot if another instance has taken over as the leader, so that only one of them
+    // is connected to Discord.
+    fn renew_lease(&self) -> Result<()> {
+        if let Some(ref lease) = self.0.lease {
+            if !lease.renew() && self.is_running() {
+                error!("This instance is no longer the leader. Stopping the bot.");
+                self.shutdown()?;
+            }
+        }
+        Ok(())
+    }
+    fn check_auto_rekey(&self) -> Result<()> {
+        let interval_days = self.0.config.get(None, ConfigKeys::AutoRekeyIntervalDays)?;
+        if interval_days == 0 ||
+           self.0.verifier.key_age() < Duration::from_secs(interval_days as u64 * 60 * 60 * 24) {
+            return Ok(())
+        }
+
+        info!("Shared key is more than {} days old. Automatically rekeying.", interval_days);
+        self.0.verifier.rekey(true)?;
+        // The owner is still reminded if publishing the new place failed.
+        if self.0.place.update_place(self)? {
+            return Ok(())
+        }
+
+        let reminder = format!("The verification shared key was automatically changed. Please \
+                                upload the new {} to the verification place, or users will not \
+                                be able to verify.", PLACE_TARGET_NAME);
+        warn!("{}", reminder);
+        if let Some(owner) = self.0.config.get(None, ConfigKeys::BotOwnerId)? {
+            if let Err(e) = UserId(owner).create_dm_channel().and_then(|x| x.say(&reminder)) {
+                warn!("Could not send rekey reminder to the bot owner: {}", e);
+            }
+        }
+        Ok(())
+    }
+    // Rotates the shared key after it may have been leaked, rejecting every token made with an
+    // earlier key and cancelling verifications in progress, then reports the incident to the
+    // audit channel of every server.
+    pub fn emergency_rekey(&self, initiated_by: &str, reason: Option<&str>) -> Result<String> {
+        let (revoked_keys, cancelled_claims) = self.0.verifier.emergency_rekey()?;
+        let cancelled_wizards = cancel_wizards();
+        // Restarting the web server drops the sign ins in progress on the website.
+        self.0.web.restart()?;
+        let place_status = match self.0.place.update_place(self) {
+            Ok(true) =>
+                "The verification place was published with the new key.".to_owned(),
+            Ok(false) => format!("The new {} must be uploaded to the verification place before \
+                               users can verify again.", PLACE_TARGET_NAME),
+            Err(e) => format!("The verification place could not be updated: {}", e),
+        };
+        warn!("Emergency rekey by {}: {} keys revoked, {} pending verifications cancelled.",
+              initiated_by, revoked_keys, cancelled_claims + cancelled_wizards);
+
+        let summary = format!(
+            "The verification key was replaced after it may have been compromised. Codes made \
+             with the {} previous keys are no longer accepted, and {} verifications in \
+             progress were cancelled. {}",
+            revoked_keys, cancelled_claims + cancelled_wizards, place_status,
+        );
+        let mut fields = vec![("Initiated by".to_owned(), initiated_by.to_owned())];
+        if let Some(reason) = reason {
+            fields.push(("Reason".to_owned(), reason.to_owned()));
+        }
+        // The event log only refers to users by opaque references, so the admin is left out.
+        self.0.event_log.record(event_log::EVENT_REKEY, format!(
+            "Emergency rekey, revoking {} keys{}", revoked_keys,
+            reason.map_or(String::new(), |x| format!(": {}", x)),
+        ));
+        self.0.audit.log_incident("Emergency rekey".to_owned(), summary.clone(), fields);
+        Ok(summary)
+    }
+
+    fn enforce_verification(&self) -> Result<()> {
+        self.0.enforcement.run()
+    }
+    fn expire_guest_passes(&self) -> Result<()> {
+        for pass in self.0.guest_passes.take_expired()? {
+            info!("The guest pass of {} in {} has expired.", pass.discord_id, pass.guild_id);
+            if let Err(e) = self.0.roles.end_guest_pass(pass.guild_id, pass.discord_id,
+                                                        pass.role_id) {
+                debug!("Could not remove the guest role of {} in {}: {}",
+                       pass.discord_id, pass.guild_id, e);
+            }
+        }
+        Ok(())
+    }
+    fn send_verification_reminders(&self) -> Result<()> {
+        self.0.enforcement.send_reminders()
+    }
+    fn decay_roles(&self) -> Result<()> {
+        self.0.roles.suspend_inactive()
+    }
+    fn refresh_usernames(&self) -> Result<()> {
+        let interval_hours = self.0.config.get(None, ConfigKeys::UsernameRefreshIntervalHours)?;
+        if interval_hours == 0 {
+            return Ok(())
+        }
+        let interval = Duration::from_secs(interval_hours as u64 * 60 * 60);
+        for rename in self.0.usernames.refresh_verified(interval)? {
+            debug!("Roblox user #{} was renamed from {} to {}.",
+                   rename.roblox_id.0, rename.old_username, rename.new_username);
+            self.0.audit.log_rename(rename.discord_id, rename.roblox_id,
+                                    rename.old_username, rename.new_username);
+        }
+        Ok(())
+    }
+    fn process_join_requests(&self) -> Result<()> {
+        self.0.group_ranks.process_join_requests()
+    }
+    fn poll_friend_requests(&self) -> Result<()> {
+        if !self.0.verifier.has_friend_claims() {
+            return Ok(())
+        }
+        let cookie = match self.0.config.get(None, ConfigKeys::FriendVerificationCookie)? {
+            Some(cookie) => cookie,
+            None => return Ok(()),
+        };
+        let session = roblox::RobloxSession { cookie: &cookie };
+        let requests = session.friend_requests()?;
+        for (discord_id, claim) in self.0.verifier.match_friend_requests(&requests) {
+            // The request is only used to prove control of the account, so it is declined
+            // rather than accepted.
+            session.decline_friend_request(claim.roblox_id).drop_nonfatal()?;
+            let result = self.verify_in_background(discord_id, claim.guild_id, claim.roblox_id,
+                                                   &FriendVerification, "");
+            let (_, message) = background_result_message(discord_id, result);
+            claim.channel_id.say(format!("<@{}> {}", discord_id.0, message))
+                .map_err(Error::from).drop_nonfatal()?;
+        }
+        Ok(())
+    }
+
+    // Called by the web server when a code is entered in the verification place. Returns whether
+    // the code was accepted. The verification itself is finished in the background, and its result
+    // is sent to both Discord and the verification place.
+    pub fn enter_place_code(&self, code: &str, roblox_id: RobloxUserID) -> bool {
+        match self.0.verifier.enter_place_code(code, roblox_id) {
+            Some((code, claim)) => {
+                self.0.tasks.dispatch_task(move |core| {
+                    core.complete_place_code(&code, roblox_id, claim)
+                });
+                true
+            }
+            None => false,
+        }
+    }
+    fn complete_place_code(
+        &self, code: &str, roblox_id: RobloxUserID, claim: PlaceCodeClaim,
+    ) -> Result<()> {
+        let result = self.verify_in_background(claim.discord_id, claim.guild_id, roblox_id,
+                                               &PlaceCodeVerification, code);
+        let (verified, message) = background_result_message(claim.discord_id, result);
+        if let Err(e) = self.0.place.publish_verification_result(self, roblox_id, verified,
+                                                                  &message) {
+            warn!("Could not send a verification result to the verification place: {}", e);
+        }
+        claim.channel_id.say(format!("<@{}> {}", claim.discord_id.0, message))
+            .map_err(Error::from).drop_nonfatal()?;
+        Ok(())
+    }
+
+    // Removes everything stored about a Discord user, along with the roles they were given for
+    // being verified. Returns `None` if the user is verifying at the moment.
+    pub fn purge_user(&self, discord_id: UserId) -> Result<Option<DeletionReceipt>> {
+        let receipt = match self.0.verifier.purge_user(discord_id)? {
+            Some(receipt) => receipt,
+            None => return Ok(None),
+        };
+        self.0.roles.forget_user(discord_id);
+        self.0.permissions.clear_cache();
+        self.0.roles.remove_roles_on_unverify(discord_id);
+        info!("Purged all data stored about a user. Receipt {}: {} rows removed.",
+              receipt.receipt_id, receipt.total_removed());
+        Ok(Some(receipt))
+    }
+
+    // Links the members of a server that are verified with another bot, looking each of them up
+    // with its public API in the background. `report` is given the number of links imported and
+    // skipped once the lookup finishes.
+    pub fn import_guild_links<F>(
+        &self, source: ImportSource, guild_id: GuildId, report: F,
+    ) where F: FnOnce(Result<(usize, usize)>) -> Result<()> + Send + 'static {
+        self.0.tasks.dispatch_io_task(move |core| {
+            report(source.lookup_guild(&core.0.config, &core.0.verifier, guild_id)
+                       .and_then(|links| core.0.verifier.import_links(Some(guild_id), &links)))
+        })
+    }
+
+    // Unverifies every user who verified in a server, for servers that want a fresh start, such
+    // as after changing owners. Returns how many users were unverified.
+    pub fn reset_guild_verification(
+        &self, guild_id: GuildId, strip_roles: bool, initiated_by: &str,
+    ) -> Result<usize> {
+        let users = self.0.verifier.reset_guild_links(guild_id)?;
+        let count = users.len();
+        if strip_roles {
+            self.0.roles.remove_roles_in_guild(guild_id, users);
+        }
+        info!("Verification was reset in {} by {}: {} users unverified.",
+              guild_id, initiated_by, count);
+
+        let description = format!(
+            "Every link made on this server was removed, so {} users must verify again.{}",
+            count, if strip_roles { " Their verification roles are being removed." } else { "" },
+        );
+        self.0.audit.log_guild_incident(guild_id, "Verification reset".to_owned(), description,
+                                        vec![("Initiated by".to_owned(),
+                                              initiated_by.to_owned())]);
+        Ok(count)
+    }
+
+    fn verify_in_background(
+        &self, discord_id: UserId, guild_id: GuildId, roblox_id: RobloxUserID,
+        method: &VerificationMethod, data: &str,
+    ) -> Result<String> {
+        let user = discord_id.get()?;
+        let roblox_name = roblox_id.lookup_username()?;
+        let prefix = self.0.config.get(Some(guild_id), ConfigKeys::CommandPrefix)?;
+        verify_in_guild(self, &prefix, guild_id, &user, &roblox_name, method, data,
+                        |_| Ok(String::new()))
+    }
+    fn drain_in_flight(&self) {
+        let deadline = Instant::now() + Duration::from_secs(SHUTDOWN_DRAIN_TIMEOUT_SECS);
+        let mut next_message = Instant::now() + Duration::from_secs(1);
+        loop {
+            let verifications = self.0.verifier.in_flight();
+            let requests = roblox::in_flight_requests();
+            if verifications == 0 && requests == 0 { break }
+            if Instant::now() > deadline {
+                warn!("Timed out waiting on {} verification attempts and {} Roblox API \
+                       requests. Shutting down anyway.", verifications, requests);
+                break
+            }
+            if Instant::now() > next_message {
+                info!("Waiting on {} verification attempts and {} Roblox API requests to \
+                       finish.", verifications, requests);
+                next_message = Instant::now() + Duration::from_secs(5);
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
     fn wait_on_instances(&self) {
         let mut next_message = Instant::now() + Duration::from_secs(1);
         let mut printed_waiting = false;
@@ -143,12 +570,27 @@ impl VerifierCore {
         let core_ref_guard = self.0.core_ref.activate(&self.0);
         self.refresh_place()?;
         self.0.discord.connect()?;
+        self.0.web.start()?;
+        signal::watch_sigterm(self.0.core_ref.clone())?;
         self.0.terminal.open()?;
         ensure!(self.0.status.load(Ordering::Relaxed) == STATUS_STOPPING,
                 "Terminal interrupted without initializing shutdown!");
+
+        // New commands are rejected from this point, but commands that are already running are
+        // given some time to finish before the bot disconnects from Discord.
+        self.0.web.stop()?;
+        self.drain_in_flight();
         drop(core_ref_guard);
         self.0.discord.shutdown()?;
+        if let Some(ref lease) = self.0.lease {
+            if let Err(e) = lease.release() {
+                warn!("Could not release the leader lease: {}", e);
+            }
+        }
         self.wait_on_instances();
+        if let Err(e) = self.0.database.connect().and_then(|conn| conn.checkpoint()) {
+            warn!("Could not checkpoint the database: {}", e);
+        }
         ensure!(self.0.status.compare_and_swap(STATUS_STOPPING, STATUS_STOPPED,
                                                Ordering::Relaxed) == STATUS_STOPPING,
                 "VerifierCore not currently stopping?");
@@ -166,6 +608,10 @@ impl VerifierCore {
         }
     }
 
+    pub fn is_running(&self) -> bool {
+        self.0.status.load(Ordering::Relaxed) == STATUS_RUNNING
+    }
+
     pub fn config(&self) -> &ConfigManager {
         &self.0.config
     }
@@ -181,9 +627,112 @@ impl VerifierCore {
     pub fn verify_channel(&self) -> &VerificationChannelManager {
         &self.0.verify_channel
     }
+    pub fn blacklist(&self) -> &BlacklistManager {
+        &self.0.blacklist
+    }
+    pub fn permissions(&self) -> &PermissionManager {
+        &self.0.permissions
+    }
+    pub fn event_log(&self) -> &EventLog {
+        &self.0.event_log
+    }
+    pub fn admin_actions(&self) -> &AdminActionManager {
+        &self.0.admin_actions
+    }
+    pub fn stats(&self) -> &StatsManager {
+        &self.0.stats
+    }
+    pub fn usernames(&self) -> &UsernameManager {
+        &self.0.usernames
+    }
+    pub fn templates(&self) -> &MessageTemplateManager {
+        &self.0.templates
+    }
+    pub fn approvals(&self) -> &ApprovalManager {
+        &self.0.approvals
+    }
+    pub fn guest_passes(&self) -> &GuestPassManager {
+        &self.0.guest_passes
+    }
+    pub fn enforcement(&self) -> &EnforcementManager {
+        &self.0.enforcement
+    }
+    pub fn group_ranks(&self) -> &GroupRankManager {
+        &self.0.group_ranks
+    }
+    pub fn jobs(&self) -> &JobScheduler {
+        &self.0.jobs
+    }
+    pub fn web(&self) -> &WebServer {
+        &self.0.web
+    }
+    pub fn leader_lease(&self) -> Option<&LeaderLease> {
+        self.0.lease.as_ref()
+    }
 
+    pub fn refresh_api_caches(&self) -> Result<()> {
+        let seconds = |key: ConfigKey<u32>| -> Result<Duration> {
+            Ok(Duration::from_secs(self.0.config.get(None, key)? as u64))
+        };
+        roblox::set_api_cache_lifetimes(seconds(ConfigKeys::RobloxCacheSeconds)?,
+                                        seconds(ConfigKeys::RobloxHeadshotCacheSeconds)?,
+                                        seconds(ConfigKeys::RobloxCacheStaleSeconds)?);
+        Ok(())
+    }
+    // Credentials in the global configuration, which are removed from error reports.
+    fn report_secrets(&self) -> Result<Vec<String>> {
+        let keys = [
+            ConfigKeys::DiscordToken, ConfigKeys::WebApiKey, ConfigKeys::WebBadgeSecret,
+            ConfigKeys::DiscordOAuthClientSecret, ConfigKeys::RobloxOAuthClientSecret,
+            ConfigKeys::OpenCloudApiKey, ConfigKeys::PlacePushSecret,
+            ConfigKeys::FriendVerificationCookie, ConfigKeys::GroupAdminApiKey,
+            ConfigKeys::GroupAdminCookie, ConfigKeys::ErrorReportUrl,
+        ];
+        let mut secrets = Vec::new();
+        for &key in &keys {
+            if let Some(secret) = self.0.config.get(None, key)? {
+                secrets.push(secret);
+            }
+        }
+        Ok(secrets)
+    }
+    pub fn update_error_reporting(&self) -> Result<()> {
+        error_report::set_secrets(self.report_secrets()?);
+        let sink = match self.0.config.get(None, ConfigKeys::ErrorReportUrl)? {
+            Some(url) => {
+                let format = self.0.config.get(None, ConfigKeys::ErrorReportFormat)?;
+                Some(ReportSink::new(ReportSinkKind::from_name(&format)?, &url)?)
+            }
+            None => None,
+        };
+        error_report::set_sink(sink);
+        Ok(())
+    }
     pub fn refresh_place(&self) -> Result<()> {
-        self.0.place.update_place(self)
+        self.0.place.update_place(self)?;
+        Ok(())
+    }
+    pub fn export_place(&self) -> Result<Vec<u8>> {
+        self.0.place.export_place(self)
+    }
+    pub fn publish_place(&self) -> Result<u64> {
+        self.0.place.publish_place(self)
+    }
+
+    pub fn backup_database(&self) -> Result<PathBuf> {
+        let dir = match self.0.config.get(None, ConfigKeys::BackupDirectory)? {
+            Some(dir) => self.0.root_path.join(dir),
+            None => self.0.root_path.join(BACKUP_DIR_NAME),
+        };
+        let path = self.0.database.backup(&dir)?;
+        info!("Database backed up to {}.", path.display());
+        Ok(path)
+    }
+    pub fn database_metrics(&self) -> DatabaseMetrics {
+        self.0.database.metrics()
+    }
+    pub fn check_database(&self) -> Result<()> {
+        self.0.database.check_health()
     }
 }
 
@@ -0,0 +1,63 @@
+use core::config::*;
+use errors::*;
+use regex::{Regex, RegexBuilder, escape};
+use roblox::RobloxUserID;
+use serenity::model::prelude::*;
+
+// Splits a comma separated list of name patterns, as stored in `NamePolicyPatterns`.
+pub fn parse_name_patterns(patterns: &str) -> Vec<String> {
+    patterns.split(',').map(|x| x.trim().to_lowercase()).filter(|x| !x.is_empty()).collect()
+}
+
+pub fn check_name_patterns(patterns: &str) -> Result<()> {
+    for pattern in parse_name_patterns(patterns) {
+        cmd_ensure!(pattern.chars().any(|x| x != '*'),
+                    "Name pattern `{}` would match every name.", pattern);
+    }
+    Ok(())
+}
+
+// A pattern matches anywhere in a name, ignoring case, and `*` in it matches any characters.
+fn compile_pattern(pattern: &str) -> Result<Regex> {
+    let source = pattern.split('*').map(escape).collect::<Vec<_>>().join(".*");
+    Ok(RegexBuilder::new(&source).case_insensitive(true).build()?)
+}
+
+// Underscores are also ignored, so they cannot be used to split up a denied word.
+fn name_matches(patterns: &[Regex], name: &str) -> bool {
+    let squashed = name.replace('_', "");
+    patterns.iter().any(|x| x.is_match(name) || x.is_match(&squashed))
+}
+
+#[derive(Clone, Debug)]
+pub enum NamePolicyResult {
+    Allowed,
+    Denied { name: String, require_approval: bool },
+}
+
+// Checks a Roblox account's username and display name against a server's denied name patterns.
+pub fn check_name_policy(
+    config: &ConfigManager, guild: GuildId, roblox_id: RobloxUserID,
+) -> Result<NamePolicyResult> {
+    let patterns = match config.get(Some(guild), ConfigKeys::NamePolicyPatterns)? {
+        Some(patterns) => parse_name_patterns(&patterns),
+        None => return Ok(NamePolicyResult::Allowed),
+    };
+    if patterns.is_empty() {
+        return Ok(NamePolicyResult::Allowed)
+    }
+    let patterns = patterns.iter().map(|x| compile_pattern(x)).collect::<Result<Vec<_>>>()?;
+
+    let mut names = vec![roblox_id.lookup_username()?];
+    if let Some(display_name) = roblox_id.display_name()? {
+        names.push(display_name);
+    }
+    for name in names {
+        if name_matches(&patterns, &name) {
+            let require_approval =
+                config.get(Some(guild), ConfigKeys::NamePolicyRequireApproval)?;
+            return Ok(NamePolicyResult::Denied { name, require_approval })
+        }
+    }
+    Ok(NamePolicyResult::Allowed)
+}
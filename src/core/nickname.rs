@@ -0,0 +1,85 @@
+use core::config::*;
+use errors::*;
+use roblox::RobloxUserID;
+use serenity::model::prelude::*;
+
+const MAX_NICKNAME_LENGTH: usize = 32;
+
+// Appended to nicknames set by the bot, so they are never identical to the Discord username.
+const NICKNAME_MARKER: char = '\u{17B5}';
+
+// Which of a user's Roblox names is used for `{roblox_name}` in nicknames.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum NicknameSource {
+    Username, DisplayName, Combined,
+}
+impl NicknameSource {
+    pub fn name(self) -> &'static str {
+        match self {
+            NicknameSource::Username => "username",
+            NicknameSource::DisplayName => "display_name",
+            NicknameSource::Combined => "combined",
+        }
+    }
+    pub fn from_name(name: &str) -> Result<NicknameSource> {
+        match name {
+            "username" => Ok(NicknameSource::Username),
+            "display_name" => Ok(NicknameSource::DisplayName),
+            "combined" => Ok(NicknameSource::Combined),
+            _ => cmd_error!("Unknown nickname source '{}'. Valid sources are: username, \
+                             display_name, combined", name),
+        }
+    }
+
+    // Accounts without a display name, or whose display name is their username, are always
+    // shown by their username.
+    pub fn roblox_name(self, username: &str, display_name: Option<&str>) -> String {
+        match display_name {
+            Some(display_name) if display_name != username => match self {
+                NicknameSource::Username => username.to_string(),
+                NicknameSource::DisplayName => display_name.to_string(),
+                NicknameSource::Combined => format!("{} (@{})", display_name, username),
+            },
+            _ => username.to_string(),
+        }
+    }
+}
+
+pub fn check_nickname_format(format: &str) -> Result<()> {
+    cmd_ensure!(!format.trim().is_empty(), "Nickname format cannot be empty.");
+    cmd_ensure!(format.contains("{roblox_name}"),
+                "Nickname format must contain `{roblox_name}`.");
+    Ok(())
+}
+
+pub fn format_nickname(format: &str, roblox_name: &str, discord_name: &str) -> String {
+    let nickname = format
+        .replace("{roblox_name}", roblox_name)
+        .replace("{discord_name}", discord_name);
+    let mut nickname: String = nickname.trim().chars().take(MAX_NICKNAME_LENGTH - 1).collect();
+    nickname.push(NICKNAME_MARKER);
+    nickname
+}
+
+pub fn target_nickname(
+    config: &ConfigManager, guild: GuildId, member: &Member, roblox_id: Option<RobloxUserID>,
+) -> Result<Option<String>> {
+    match roblox_id {
+        Some(roblox_id) => {
+            let format = config.get(Some(guild), ConfigKeys::NicknameFormat)?;
+            let source = config.get(Some(guild), ConfigKeys::NicknameSource)?;
+            let source = NicknameSource::from_name(&source)?;
+            let username = roblox_id.lookup_username()?;
+            let roblox_name = match source {
+                NicknameSource::Username => username,
+                _ => {
+                    let display_name = roblox_id.display_name()?;
+                    source.roblox_name(&username, display_name.as_ref().map(|x| x.as_str()))
+                }
+            };
+            let discord_name = member.user.read().name.clone();
+            Ok(Some(format_nickname(&format, &roblox_name, &discord_name)))
+        }
+        None => Ok(None),
+    }
+}
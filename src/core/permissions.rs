@@ -0,0 +1,118 @@
+use database::*;
+use errors::*;
+use serenity::model::prelude::*;
+use std::sync::Arc;
+use util::ConcurrentCache;
+
+// Who a permission node is granted to in a guild.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum PermissionTarget {
+    Role(RoleId), User(UserId),
+}
+impl PermissionTarget {
+    fn to_db(self) -> (bool, u64) {
+        match self {
+            PermissionTarget::Role(id) => (true, id.0),
+            PermissionTarget::User(id) => (false, id.0),
+        }
+    }
+    fn from_db(is_role: bool, id: u64) -> PermissionTarget {
+        if is_role {
+            PermissionTarget::Role(RoleId(id))
+        } else {
+            PermissionTarget::User(UserId(id))
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct PermissionGrant {
+    pub target: PermissionTarget, pub node: String,
+}
+
+// Checks whether a granted node covers a command's node. Nodes are dot separated, so granting
+// `verifier.roles` also grants `verifier.roles.bind`, and `*` grants every node.
+pub fn node_matches(granted: &str, node: &str) -> bool {
+    granted == "*" || granted == node ||
+        (node.starts_with(granted) && node[granted.len()..].starts_with('.'))
+}
+
+struct PermissionManagerData {
+    database: Database, grants: ConcurrentCache<GuildId, Arc<Vec<PermissionGrant>>>,
+}
+
+#[derive(Clone)]
+pub struct PermissionManager(Arc<PermissionManagerData>);
+impl PermissionManager {
+    pub fn new(database: Database) -> PermissionManager {
+        let db_ref_update = database.clone();
+        PermissionManager(Arc::new(PermissionManagerData {
+            database,
+            grants: ConcurrentCache::new(move |&guild_id| {
+                Ok(Arc::new(Self::get_grants_db(&db_ref_update, guild_id)?))
+            }),
+        }))
+    }
+
+    fn get_grants_db(database: &Database, guild_id: GuildId) -> Result<Vec<PermissionGrant>> {
+        let grants = database.connect()?.query(
+            "SELECT is_role, target_id, node FROM command_permission_grants \
+             WHERE discord_guild_id = ?1 ORDER BY node",
+            guild_id,
+        ).get_all::<(bool, u64, String)>()?;
+        Ok(grants.into_iter().map(|(is_role, id, node)| PermissionGrant {
+            target: PermissionTarget::from_db(is_role, id), node,
+        }).collect())
+    }
+    pub fn get_grants(&self, guild_id: GuildId) -> Result<Arc<Vec<PermissionGrant>>> {
+        Ok(self.0.grants.read(&guild_id)?.clone())
+    }
+    fn refresh(&self, guild_id: GuildId) -> Result<()> {
+        *self.0.grants.write(&guild_id)? =
+            Arc::new(Self::get_grants_db(&self.0.database, guild_id)?);
+        Ok(())
+    }
+
+    // Returns whether the node was not already granted to the target.
+    pub fn grant(&self, guild_id: GuildId, target: PermissionTarget, node: &str) -> Result<bool> {
+        let (is_role, id) = target.to_db();
+        let added = self.0.database.connect()?.execute(
+            "INSERT OR IGNORE INTO command_permission_grants \
+                 (discord_guild_id, is_role, target_id, node) \
+             VALUES (?1, ?2, ?3, ?4)", (guild_id, is_role, id, node),
+        )? != 0;
+        self.refresh(guild_id)?;
+        Ok(added)
+    }
+    pub fn revoke(&self, guild_id: GuildId, target: PermissionTarget, node: &str) -> Result<bool> {
+        let (is_role, id) = target.to_db();
+        let removed = self.0.database.connect()?.execute(
+            "DELETE FROM command_permission_grants \
+             WHERE discord_guild_id = ?1 AND is_role = ?2 AND target_id = ?3 AND node = ?4",
+            (guild_id, is_role, id, node),
+        )? != 0;
+        self.refresh(guild_id)?;
+        Ok(removed)
+    }
+
+    // Checks whether a member of a guild was granted a node, either directly or through one of
+    // their roles.
+    pub fn is_granted(
+        &self, guild_id: GuildId, user_id: UserId, roles: &[RoleId], node: &str,
+    ) -> Result<bool> {
+        Ok(self.get_grants(guild_id)?.iter().any(|grant| {
+            let applies = match grant.target {
+                PermissionTarget::Role(role_id) => roles.contains(&role_id),
+                PermissionTarget::User(id) => id == user_id,
+            };
+            applies && node_matches(&grant.node, node)
+        }))
+    }
+
+    pub fn clear_cache(&self) {
+        self.0.grants.clear_cache();
+    }
+    pub fn on_cleanup_tick(&self) {
+        self.0.grants.shrink_to_fit();
+    }
+}
@@ -3,10 +3,21 @@ use core::config::*;
 use errors::*;
 use parking_lot::Mutex;
 use roblox::*;
+use serde_json;
 use sha2::*;
 use std::fs::File;
 use std::io::{Read, Write};
 use std::path::PathBuf;
+use web;
+
+// The MessagingService topic the results of codes entered in the verification place are
+// published to.
+const VERIFICATION_RESULT_TOPIC: &str = "SylphieVerification";
+
+#[derive(Serialize)]
+struct VerificationResultMessage<'a> {
+    roblox_id: u64, verified: bool, message: &'a str,
+}
 
 struct PlaceManagerState {
     place_target: PathBuf, current_hash: [u8; 32],
@@ -38,14 +49,26 @@ impl PlaceManager {
                                         core.config().get(None, ConfigKeys::CommandPrefix)?));
         config.push(LuaConfigEntry::new("background_image", false,
                                         core.config().get(None, ConfigKeys::PlaceUIBackground)?));
-        core.verifier().add_config(&mut config);
+        config.push(LuaConfigEntry::new("show_token_countdown", false,
+                                        core.config().get(None,
+                                                          ConfigKeys::PlaceUIShowTokenCountdown)?));
+        let push_url = core.config().get(None, ConfigKeys::WebPublicUrl)?
+            .map(|x| format!("{}{}", x, web::PLACE_PUSH_PATH));
+        let push_secret = core.config().get(None, ConfigKeys::PlacePushSecret)?;
+        let place_codes_enabled = push_url.is_some() && push_secret.is_some();
+        config.push(LuaConfigEntry::new("push_url", true, push_url));
+        config.push(LuaConfigEntry::new("push_secret", true, push_secret));
+        config.push(LuaConfigEntry::new("place_codes_enabled", false, place_codes_enabled));
+        config.push(LuaConfigEntry::new("result_topic", false, VERIFICATION_RESULT_TOPIC));
+        core.verifier().add_config(&mut config)?;
         Ok(config)
     }
-    fn check_write_place(&self, data: &[u8]) -> Result<()> {
+    fn check_write_place(&self, data: &[u8]) -> Result<bool> {
         let state = self.0.lock();
 
         let hash = Sha256::digest(data);
-        if hash.as_slice() != state.current_hash {
+        let changed = hash.as_slice() != state.current_hash;
+        if changed {
             info!("An updated place file has been written to '{}'.", state.place_target.display());
             // TODO: Online documentation!
             info!("Please follow the instructions at [url] to update the place. If you do not, \
@@ -54,11 +77,63 @@ impl PlaceManager {
             let mut handle = File::create(&state.place_target)?;
             handle.write_all(data)?;
         }
-        Ok(())
+        Ok(changed)
     }
-    pub fn update_place(&self, core: &VerifierCore) -> Result<()> {
-        let place_data = create_place_file(None, &self.place_config(core)?)?;
-        self.check_write_place(&place_data)?;
+    // Writes the place file if it changed, and publishes it if Open Cloud is set up. Returns
+    // whether the changed place was published.
+    pub fn update_place(&self, core: &VerifierCore) -> Result<bool> {
+        let place_data = self.binary_place(core)?;
+        if self.check_write_place(&place_data)? && Self::open_cloud_config(core)?.is_some() {
+            match self.publish_data(core, place_data) {
+                Ok(_) => return Ok(true),
+                Err(e) => error!("Could not publish the updated place file: {}", e),
+            }
+        }
+        Ok(false)
+    }
+
+    fn open_cloud_config(core: &VerifierCore) -> Result<Option<(String, u64, u64)>> {
+        let api_key = core.config().get(None, ConfigKeys::OpenCloudApiKey)?;
+        let universe_id = core.config().get(None, ConfigKeys::PlaceUniverseID)?;
+        let place_id = core.config().get(None, ConfigKeys::PlaceID)?;
+        Ok(match (api_key, universe_id, place_id) {
+            (Some(api_key), Some(universe_id), Some(place_id)) =>
+                Some((api_key, universe_id, place_id)),
+            _ => None,
+        })
+    }
+    // Tells the servers of the verification place how a code entered in one of them was handled,
+    // so the player who entered it can be shown the result. Does nothing if Open Cloud is not
+    // set up.
+    pub fn publish_verification_result(
+        &self, core: &VerifierCore, roblox_id: RobloxUserID, verified: bool, message: &str,
+    ) -> Result<()> {
+        if let Some((api_key, universe_id, _)) = Self::open_cloud_config(core)? {
+            let message = serde_json::to_string(&VerificationResultMessage {
+                roblox_id: roblox_id.0, verified, message,
+            })?;
+            publish_message(&api_key, universe_id, VERIFICATION_RESULT_TOPIC, &message)?;
+        }
         Ok(())
     }
+    fn publish_data(&self, core: &VerifierCore, data: Vec<u8>) -> Result<u64> {
+        let (api_key, universe_id, place_id) = Self::open_cloud_config(core)?
+            .to_cmd_err(|| "The open_cloud_api_key, universe_id and place_id settings must be \
+                            set to publish the place.")?;
+        let version = publish_place(&api_key, universe_id, place_id, data)?;
+        info!("Published version {} of the verification place.", version);
+        Ok(version)
+    }
+    pub fn publish_place(&self, core: &VerifierCore) -> Result<u64> {
+        let place_data = self.binary_place(core)?;
+        self.publish_data(core, place_data)
+    }
+    fn binary_place(&self, core: &VerifierCore) -> Result<Vec<u8>> {
+        create_place_file(None, &self.place_config(core)?)
+    }
+    // Exports the place in the XML format (.rbxlx), which can be opened in Roblox Studio and
+    // uploaded as is.
+    pub fn export_place(&self, core: &VerifierCore) -> Result<Vec<u8>> {
+        create_xml_place_file(None, &self.place_config(core)?)
+    }
 }
\ No newline at end of file
@@ -1,20 +1,28 @@
+use core::approvals::*;
+use core::blacklist::*;
 use core::config::*;
+use core::guest_passes::*;
+use core::name_policy::*;
+use core::nickname;
 use core::tasks::*;
 use core::verifier::*;
 use database::*;
 use errors::*;
+use i18n;
 use parking_lot::RwLock;
 use serenity;
 use serenity::model::prelude::*;
 use std::borrow::Cow;
+use std::boxed::FnBox;
 use std::cmp::max;
 use std::collections::{HashMap, HashSet};
 use std::mem::drop;
 use std::sync::Arc;
+use std::thread;
 use std::time::{SystemTime, Duration};
 use roblox::{VerificationSet, VerificationRule, RobloxUserID};
 use util;
-use util::ConcurrentCache;
+use util::{ConcurrentCache, MultiMutex, MultiMutexGuard};
 
 // TODO: Prevent assigning the same role id to two rules.
 
@@ -39,23 +47,72 @@ pub struct AssignedRole {
     pub rule: String, pub role_id: RoleId, pub is_assigned: bool,
 }
 pub enum SetRolesStatus {
-    Success, IsAdmin, NotSet,
+    Success, IsAdmin, NotSet, PendingApproval, ApprovalRejected,
+}
+pub struct MassUpdateStatus {
+    pub updated: usize, pub failed: usize,
+}
+struct MassUpdate {
+    guild_id: GuildId, members: Vec<UserId>, total: usize, status: MassUpdateStatus,
+    progress: Box<FnMut(usize, usize) -> Result<()> + Send>,
+    finished: Box<FnBox(MassUpdateStatus) -> Result<()> + Send>,
+    _lock: MultiMutexGuard<GuildId>,
+}
+
+// A change to a server's role configuration, used to preview what it would do before making it.
+pub enum RuleChange {
+    SetRole(String, Option<RoleId>), SetCustomRule(String, Option<String>),
+}
+pub struct PlannedRoleChange {
+    pub user_id: UserId, pub added: Vec<RoleId>, pub removed: Vec<RoleId>,
+    pub nickname: Option<String>,
+}
+pub struct MassUpdatePreview {
+    pub changes: Vec<PlannedRoleChange>, pub checked: usize, pub failed: usize,
 }
 
+// A role configuration that has not been saved, used to preview changes to it.
+struct SimulatedRules {
+    status: VerificationRulesStatus, role_ids: Vec<RoleId>,
+}
+
+// The roles and nickname a member should have, as worked out by `plan_roles`.
+struct RolePlan {
+    member: Member, approval: ApprovalStatus, can_access_user: bool, do_set_nickname: bool,
+    set_nickname: Option<String>, orig_roles: HashSet<RoleId>, roles: HashSet<RoleId>,
+}
+impl RolePlan {
+    fn to_change(&self) -> PlannedRoleChange {
+        PlannedRoleChange {
+            user_id: self.member.user.read().id,
+            added: self.roles.difference(&self.orig_roles).cloned().collect(),
+            removed: self.orig_roles.difference(&self.roles).cloned().collect(),
+            nickname: self.set_nickname.clone(),
+        }
+    }
+}
+
+// Members are updated in small batches to stay clear of Discord and Roblox rate limits.
+const MASS_UPDATE_BATCH_SIZE: usize = 10;
+const MASS_UPDATE_BATCH_DELAY_SECS: u64 = 5;
+
 struct RoleManagerData {
     config: ConfigManager, database: Database, verifier: Verifier, tasks: TaskManager,
+    blacklist: BlacklistManager, approvals: ApprovalManager, guest_passes: GuestPassManager,
     rule_cache: ConcurrentCache<GuildId, Arc<RwLock<VerificationRulesStatus>>>,
     update_cache: ConcurrentCache<GuildId, Arc<ConcurrentCache<(UserId, bool), Option<SystemTime>>>>,
+    mass_update_lock: MultiMutex<GuildId>,
 }
 #[derive(Clone)]
 pub struct RoleManager(Arc<RoleManagerData>);
 impl RoleManager {
     pub fn new(
         config: ConfigManager, database: Database, verifier: Verifier, tasks: TaskManager,
+        blacklist: BlacklistManager, approvals: ApprovalManager, guest_passes: GuestPassManager,
     ) -> RoleManager {
         let db_ref_update = database.clone();
         RoleManager(Arc::new(RoleManagerData {
-            config, database, verifier, tasks,
+            config, database, verifier, tasks, blacklist, approvals, guest_passes,
             rule_cache: ConcurrentCache::new(|_|
                 Ok(Arc::new(RwLock::new(VerificationRulesStatus::NotCompiled)))
             ),
@@ -65,6 +122,7 @@ impl RoleManager {
                     Self::get_cooldown_cache(&db_ref_update, guild_id, user_id, is_manual)
                 )))
             }),
+            mass_update_lock: MultiMutex::new(),
         }))
     }
 
@@ -114,7 +172,12 @@ impl RoleManager {
     ) -> Result<VerificationRulesStatus> {
         let (configuration, active_count, custom_count) =
             self.get_configuration_internal(conn, guild)?;
-
+        self.compile_configuration(configuration, active_count, custom_count)
+    }
+    fn compile_configuration(
+        &self, configuration: HashMap<String, ConfiguredRole>, active_count: usize,
+        custom_count: usize,
+    ) -> Result<VerificationRulesStatus> {
         let limits_enabled = self.0.config.get(None, ConfigKeys::RolesEnableLimits)?;
         if limits_enabled {
             let max_assigned = self.0.config.get(None, ConfigKeys::RolesMaxAssigned)?;
@@ -301,13 +364,10 @@ impl RoleManager {
         })
     }
 
-    pub fn get_assigned_roles(
-        &self, guild: GuildId, roblox_id: RobloxUserID
+    fn assigned_roles_from(
+        status: &VerificationRulesStatus, roblox_id: RobloxUserID,
     ) -> Result<Vec<AssignedRole>> {
-        let lock = self.0.rule_cache.read(&guild)?;
-        self.update_rules(&lock, guild, false)?;
-        let read = lock.read();
-        Ok(match *read {
+        Ok(match *status {
             VerificationRulesStatus::Compiled(ref rule_set, ref role_info) => {
                 let mut vec = Vec::new();
                 for (rule_name, is_assigned) in rule_set.verify(roblox_id)? {
@@ -324,21 +384,54 @@ impl RoleManager {
             VerificationRulesStatus::NotCompiled => unreachable!(),
         })
     }
+    pub fn get_assigned_roles(
+        &self, guild: GuildId, roblox_id: RobloxUserID
+    ) -> Result<Vec<AssignedRole>> {
+        let lock = self.0.rule_cache.read(&guild)?;
+        self.update_rules(&lock, guild, false)?;
+        let read = lock.read();
+        Self::assigned_roles_from(&read, roblox_id)
+    }
+
+    // Works out a member's roles and nickname. With `simulated` rules, nothing is changed, and
+    // approval requests are not posted.
+    fn plan_roles(
+        &self, guild: GuildId, discord_id: UserId, roblox_id: Option<RobloxUserID>,
+        simulated: Option<&SimulatedRules>,
+    ) -> Result<RolePlan> {
+        let roblox_id = match roblox_id {
+            Some(roblox_id) if self.0.blacklist.check(guild, roblox_id)?.is_some() => None,
+            // Links are global, so accounts of banned users may have been verified elsewhere.
+            Some(roblox_id) if self.0.blacklist.check_banned(guild, roblox_id)?.is_some() => None,
+            Some(_) if self.is_suspended(guild, discord_id)? => None,
+            roblox_id => roblox_id,
+        };
+        let approval = match roblox_id {
+            Some(roblox_id) if simulated.is_some() =>
+                self.0.approvals.peek(guild, discord_id, roblox_id)?,
+            Some(roblox_id) => self.0.approvals.check(guild, discord_id, roblox_id)?,
+            None => ApprovalStatus::Approved,
+        };
+        let roblox_id = if approval == ApprovalStatus::Approved { roblox_id } else { None };
 
-    pub fn assign_roles(
-        &self, guild: GuildId, discord_id: UserId, roblox_id: Option<RobloxUserID>
-    ) -> Result<SetRolesStatus> {
         let member = guild.member(discord_id)?;
         let me_member = guild.member(serenity::CACHE.read().user.id)?;
         let can_access_user = util::can_member_access_member(&me_member, &member)?;
-        let do_set_nickname = self.0.config.get(None, ConfigKeys::SetNickname)?;
+        // Members who renamed themselves to a denied name after verifying keep their roles,
+        // but their new name is not copied into their nickname. Names that were approved by
+        // moderators are.
+        let do_set_nickname = self.0.config.get(Some(guild), ConfigKeys::SetNickname)? &&
+            match roblox_id {
+                Some(roblox_id) => match check_name_policy(&self.0.config, guild, roblox_id)? {
+                    NamePolicyResult::Denied { require_approval, .. } => require_approval,
+                    NamePolicyResult::Allowed => true,
+                },
+                None => true,
+            };
 
         let set_nickname = if can_access_user && do_set_nickname {
-            let target_nickname = if let Some(roblox_id) = roblox_id {
-                Some(format!("{}\u{17B5}", roblox_id.lookup_username()?))
-            } else {
-                None
-            };
+            let target_nickname = nickname::target_nickname(&self.0.config, guild, &member,
+                                                            roblox_id)?;
             if target_nickname != member.nick {
                 Some(target_nickname.unwrap_or_else(|| "".to_string()))
             } else {
@@ -351,7 +444,10 @@ impl RoleManager {
         let orig_roles: HashSet<RoleId> = member.roles.iter().map(|x| *x).collect();
         let mut roles = orig_roles.clone();
         if let Some(roblox_id) = roblox_id {
-            let assigned_roles = self.get_assigned_roles(guild, roblox_id)?;
+            let assigned_roles = match simulated {
+                Some(rules) => Self::assigned_roles_from(&rules.status, roblox_id)?,
+                None => self.get_assigned_roles(guild, roblox_id)?,
+            };
             for role in assigned_roles {
                 if role.is_assigned {
                     roles.insert(role.role_id);
@@ -360,13 +456,54 @@ impl RoleManager {
                 }
             }
         } else {
-            let config = self.get_configuration(guild)?;
-            for (_, role) in config {
-                if let Some(id) = role.role_id {
-                    roles.remove(&id);
-                }
+            let role_ids = match simulated {
+                Some(rules) => rules.role_ids.clone(),
+                None => self.get_configuration(guild)?.values().filter_map(|x| x.role_id).collect(),
+            };
+            for id in role_ids {
+                roles.remove(&id);
+            }
+        }
+        let verified_role = self.0.config.get(Some(guild), ConfigKeys::VerifiedRole)?;
+        let unverified_role = self.0.config.get(Some(guild), ConfigKeys::UnverifiedRole)?;
+        // Members with a guest pass are given its role instead of being treated as unverified.
+        let guest_pass = match roblox_id {
+            Some(_) => None,
+            None => self.0.guest_passes.get(guild, discord_id)?,
+        };
+        let (add_role, remove_role) = match (roblox_id, guest_pass) {
+            (Some(_), _) => (verified_role, unverified_role),
+            (None, Some(pass)) => (Some(pass.role_id), unverified_role),
+            (None, None) => (unverified_role, verified_role),
+        };
+        if let Some(add_role) = add_role {
+            if util::can_member_access_role(&me_member, add_role)? {
+                roles.insert(add_role);
+            }
+        }
+        if let Some(remove_role) = remove_role {
+            if util::can_member_access_role(&me_member, remove_role)? {
+                roles.remove(&remove_role);
             }
         }
+
+        Ok(RolePlan {
+            member, approval, can_access_user, do_set_nickname, set_nickname, orig_roles, roles,
+        })
+    }
+
+    // Sets a member's roles and nickname, also removing `revoked_role`.
+    fn apply_roles(
+        &self, guild: GuildId, discord_id: UserId, roblox_id: Option<RobloxUserID>,
+        revoked_role: Option<RoleId>,
+    ) -> Result<SetRolesStatus> {
+        let RolePlan {
+            member, approval, can_access_user, do_set_nickname, set_nickname, orig_roles,
+            mut roles,
+        } = self.plan_roles(guild, discord_id, roblox_id, None)?;
+        if let Some(revoked_role) = revoked_role {
+            roles.remove(&revoked_role);
+        }
         let set_roles: Option<Vec<RoleId>> = if orig_roles != roles {
             Some(roles.drain().collect())
         } else {
@@ -387,17 +524,82 @@ impl RoleManager {
                 edit
             })?;
         }
-        Ok(if !can_access_user && do_set_nickname {
+        Ok(if approval == ApprovalStatus::Pending {
+            SetRolesStatus::PendingApproval
+        } else if approval == ApprovalStatus::Rejected {
+            SetRolesStatus::ApprovalRejected
+        } else if !can_access_user && do_set_nickname {
             SetRolesStatus::IsAdmin
         } else {
             SetRolesStatus::Success
         })
     }
+    pub fn assign_roles(
+        &self, guild: GuildId, discord_id: UserId, roblox_id: Option<RobloxUserID>
+    ) -> Result<SetRolesStatus> {
+        self.apply_roles(guild, discord_id, roblox_id, None)
+    }
+
+    // Removes the role of a guest pass that has ended. Members who have since verified keep it
+    // if it is one verifying gives them.
+    pub fn end_guest_pass(
+        &self, guild: GuildId, discord_id: UserId, role_id: RoleId,
+    ) -> Result<SetRolesStatus> {
+        let roblox_id = self.0.verifier.get_verified_roblox_user(discord_id)?;
+        let is_managed = self.0.config.get(Some(guild), ConfigKeys::VerifiedRole)? ==
+            Some(role_id) ||
+            self.get_configuration(guild)?.values().any(|x| x.role_id == Some(role_id));
+        let revoked_role = if roblox_id.is_some() && is_managed { None } else { Some(role_id) };
+        self.apply_roles(guild, discord_id, roblox_id, revoked_role)
+    }
+
+    pub fn assign_roles_on_verify(
+        &self, guild: GuildId, discord_id: UserId, roblox_id: RobloxUserID,
+    ) -> Result<SetRolesStatus> {
+        let status = self.assign_roles(guild, discord_id, Some(roblox_id))?;
+        for other_guild in util::member_guilds(discord_id) {
+            if other_guild != guild &&
+               self.0.config.get(Some(other_guild), ConfigKeys::SetRolesOnVerify)? {
+                let roles = self.clone();
+                self.0.tasks.dispatch_io_task(move |_| {
+                    roles.update_user_with_cooldown(
+                        other_guild, discord_id, 0, false, false
+                    ).drop_nonfatal()
+                })
+            }
+        }
+        Ok(status)
+    }
+
+    fn assign_roles_everywhere(&self, discord_id: UserId, roblox_id: Option<RobloxUserID>) {
+        for guild in util::member_guilds(discord_id) {
+            let roles = self.clone();
+            self.0.tasks.dispatch_io_task(move |_| {
+                roles.assign_roles(guild, discord_id, roblox_id).drop_nonfatal()
+            })
+        }
+    }
+    pub fn remove_roles_on_unverify(&self, discord_id: UserId) {
+        self.assign_roles_everywhere(discord_id, None)
+    }
+    pub fn assign_roles_on_switch(&self, discord_id: UserId, roblox_id: RobloxUserID) {
+        self.assign_roles_everywhere(discord_id, Some(roblox_id))
+    }
+    // Removes the roles given for verifying from many users in one server, in the background.
+    pub fn remove_roles_in_guild(&self, guild: GuildId, discord_ids: Vec<UserId>) {
+        let roles = self.clone();
+        self.0.tasks.dispatch_io_task(move |_| {
+            for discord_id in discord_ids {
+                roles.assign_roles(guild, discord_id, None).drop_nonfatal()?;
+            }
+            Ok(())
+        })
+    }
 
     pub fn update_user(
         &self, guild: GuildId, discord_id: UserId, update_unverified: bool,
     ) -> Result<SetRolesStatus> {
-        if let Some(roblox_id) = self.0.verifier.get_verified_roblox_user(discord_id)? {
+        if let Some(roblox_id) = self.0.verifier.update_user(discord_id)? {
             self.assign_roles(guild, discord_id, Some(roblox_id))
         } else {
             if update_unverified {
@@ -437,6 +639,10 @@ impl RoleManager {
             }
         }
 
+        if is_manual {
+            self.lift_suspension(guild_id, user_id)?;
+        }
+
         // TODO: Resolve this stuff.
         if !is_manual {
             debug!("Automatically updating roles for <@{}> in {}.", user_id, guild_id);
@@ -445,13 +651,257 @@ impl RoleManager {
         }
 
         let result = self.update_user(guild_id, user_id, update_unverified)?;
+        drop(guild_cache);
+        self.record_updates(guild_id, &[user_id], is_manual, now)?;
+        Ok(result)
+    }
+    fn record_updates(
+        &self, guild_id: GuildId, users: &[UserId], is_manual: bool, now: SystemTime,
+    ) -> Result<()> {
+        let rows: Vec<_> = users.iter().map(|&user_id| (guild_id, user_id, is_manual, now))
+            .collect();
+        self.0.database.connect()?.insert_batch(
+            "REPLACE INTO roles_last_updated \
+                 (discord_guild_id, discord_user_id, is_manual, last_updated)", &rows,
+        )?;
+        let guild_cache = self.0.update_cache.read(&guild_id)?;
+        for &user_id in users {
+            *guild_cache.write(&(user_id, is_manual))? = Some(now);
+        }
+        Ok(())
+    }
+
+    // Suspensions made before the member last verified no longer apply.
+    fn is_suspended(&self, guild_id: GuildId, user_id: UserId) -> Result<bool> {
+        Ok(self.0.database.connect()?.query(
+            "SELECT 1 FROM suspended_verifications AS s \
+             JOIN discord_user_info AS i ON i.discord_user_id = s.discord_user_id \
+             WHERE s.discord_guild_id = ?1 AND s.discord_user_id = ?2 \
+                 AND s.suspended_at >= i.last_updated", (guild_id, user_id),
+        ).get_opt::<u32>()?.is_some())
+    }
+    fn lift_suspension(&self, guild_id: GuildId, user_id: UserId) -> Result<()> {
         self.0.database.connect()?.execute(
-            "REPLACE INTO roles_last_updated (\
-                discord_guild_id, discord_user_id, is_manual, last_updated\
-            ) VALUES (?1, ?2, ?3, ?4)", (guild_id, user_id, is_manual, now),
+            "DELETE FROM suspended_verifications \
+             WHERE discord_guild_id = ?1 AND discord_user_id = ?2", (guild_id, user_id),
         )?;
-        *guild_cache.write(&(user_id, is_manual))? = Some(now);
-        Ok(result)
+        Ok(())
+    }
+
+    // Returns when a member last verified or manually updated their roles in a server, or
+    // `None` if they are not verified.
+    fn last_checked(
+        &self, conn: &DatabaseConnection, guild_id: GuildId, user_id: UserId,
+    ) -> Result<Option<SystemTime>> {
+        let verified_at = conn.query(
+            "SELECT last_updated FROM discord_user_info \
+             WHERE discord_user_id = ?1 AND roblox_user_id IS NOT NULL", user_id,
+        ).get_opt::<SystemTime>()?;
+        Ok(match verified_at {
+            Some(verified_at) => match Self::get_cooldown_cache(&self.0.database, guild_id,
+                                                                user_id, true)? {
+                Some(updated_at) => Some(max(verified_at, updated_at)),
+                None => Some(verified_at),
+            },
+            None => None,
+        })
+    }
+
+    fn suspend_inactive_in_guild(
+        &self, conn: &DatabaseConnection, guild_id: GuildId,
+    ) -> Result<()> {
+        let days = self.0.config.get(Some(guild_id), ConfigKeys::RoleDecayDays)?;
+        if days == 0 {
+            return Ok(())
+        }
+        let now = SystemTime::now();
+        let cutoff = now - Duration::from_secs(days as u64 * 60 * 60 * 24);
+        let (server, members) = match guild_id.find() {
+            Some(guild) => {
+                let guild = guild.read();
+                (guild.name.clone(), guild.members.values()
+                    .filter(|x| !x.user.read().bot).map(|x| x.user.read().id)
+                    .collect::<Vec<_>>())
+            }
+            None => return Ok(()),
+        };
+        let prefix = self.0.config.get(Some(guild_id), ConfigKeys::CommandPrefix)?;
+        for user_id in members {
+            match self.last_checked(conn, guild_id, user_id)? {
+                Some(last_checked) if last_checked < cutoff => { }
+                _ => continue,
+            }
+            if self.is_suspended(guild_id, user_id)? {
+                continue
+            }
+            info!("{} has not updated their roles in {} for {} days. Suspending their roles.",
+                  user_id, guild_id, days);
+            conn.execute(
+                "REPLACE INTO suspended_verifications \
+                     (discord_guild_id, discord_user_id, suspended_at) \
+                 VALUES (?1, ?2, ?3)", (guild_id, user_id, now),
+            )?;
+            if let Err(e) = self.assign_roles(guild_id, user_id, None) {
+                warn!("Could not remove the roles of {} in {}: {}", user_id, guild_id, e);
+                continue
+            }
+            let message = i18n::tr(&self.0.config, Some(guild_id), "update-decay-notice", &[
+                ("server", &server), ("days", &days.to_string()), ("prefix", &prefix),
+            ])?;
+            if let Err(e) = user_id.create_dm_channel().and_then(|x| x.say(&message)) {
+                debug!("Could not tell {} their roles in {} were removed: {}",
+                       user_id, guild_id, e);
+            }
+        }
+        Ok(())
+    }
+    // Removes the verification roles of members who have not verified or updated their roles
+    // within their server's role_decay_days.
+    pub fn suspend_inactive(&self) -> Result<()> {
+        let conn = self.0.database.connect()?;
+        let guilds: Vec<GuildId> = serenity::CACHE.read().guilds.keys().cloned().collect();
+        for guild_id in guilds {
+            self.suspend_inactive_in_guild(&conn, guild_id).drop_nonfatal()?;
+        }
+        Ok(())
+    }
+
+    // Starts updating the roles of every verified member of a server in the background, in
+    // batches spaced out by `MASS_UPDATE_BATCH_DELAY_SECS`. Returns how many members will be
+    // updated.
+    pub fn mass_update<F, G>(
+        &self, guild_id: GuildId, progress: F, finished: G,
+    ) -> Result<usize> where F: FnMut(usize, usize) -> Result<()> + Send + 'static,
+                             G: FnOnce(MassUpdateStatus) -> Result<()> + Send + 'static {
+        let lock = self.0.mass_update_lock.lock(guild_id)
+            .to_cmd_err(|| "This server's roles are already being updated.")?;
+
+        let members: Vec<UserId> =
+            self.verified_members(guild_id)?.into_iter().map(|x| x.0).collect();
+        let total = members.len();
+        let update = MassUpdate {
+            guild_id, members, total, status: MassUpdateStatus { updated: 0, failed: 0 },
+            progress: Box::new(progress), finished: Box::new(finished), _lock: lock,
+        };
+        self.0.tasks.dispatch_io_task(move |core| core.roles().run_mass_update_batch(update));
+        Ok(total)
+    }
+    fn run_mass_update_batch(&self, mut update: MassUpdate) -> Result<()> {
+        let guild_id = update.guild_id;
+        let split = update.members.len().saturating_sub(MASS_UPDATE_BATCH_SIZE);
+        let batch = update.members.split_off(split);
+
+        // The update times of each batch are recorded together, rather than with a separate
+        // write for each member.
+        let now = SystemTime::now();
+        let mut updated = Vec::new();
+        for member in batch {
+            debug!("Automatically updating roles for <@{}> in {}.", member, guild_id);
+            match self.update_user(guild_id, member, false) {
+                Ok(_) => updated.push(member),
+                Err(e) => {
+                    debug!("Could not update roles for <@{}> in {}: {}", member, guild_id, e);
+                    update.status.failed += 1;
+                }
+            }
+        }
+        if let Err(e) = self.record_updates(guild_id, &updated, false, now) {
+            warn!("Could not record role updates in {}: {}", guild_id, e);
+        }
+        update.status.updated += updated.len();
+
+        if update.members.is_empty() {
+            let MassUpdate { status, finished, .. } = update;
+            FnBox::call_box(finished, (status,))
+        } else {
+            let done = update.status.updated + update.status.failed;
+            if let Err(e) = (update.progress)(done, update.total) {
+                debug!("Could not report mass update progress in {}: {}", guild_id, e);
+            }
+            let roles = self.clone();
+            self.0.tasks.dispatch_delayed_task(
+                Duration::from_secs(MASS_UPDATE_BATCH_DELAY_SECS), move |_| {
+                    let next = roles.clone();
+                    roles.0.tasks.dispatch_io_task(move |_| next.run_mass_update_batch(update));
+                    Ok(())
+                }
+            );
+            Ok(())
+        }
+    }
+    // Returns the verified members of a server with their Roblox accounts.
+    fn verified_members(&self, guild_id: GuildId) -> Result<Vec<(UserId, RobloxUserID)>> {
+        let members: Vec<UserId> = guild_id.find()?.read().members.keys().cloned().collect();
+        self.0.verifier.get_verified_roblox_users(&members)
+    }
+
+    fn simulate_rules(&self, guild: GuildId, changes: &[RuleChange]) -> Result<SimulatedRules> {
+        let (mut configuration, _, _) =
+            self.get_configuration_internal(&self.0.database.connect()?, guild)?;
+        let now = SystemTime::now();
+        for change in changes {
+            let (rule_name, is_role) = match *change {
+                RuleChange::SetRole(ref rule_name, _) => (rule_name, true),
+                RuleChange::SetCustomRule(ref rule_name, _) => (rule_name, false),
+            };
+            if is_role {
+                cmd_ensure!(VerificationRule::has_builtin(rule_name) ||
+                            configuration.get(rule_name).map_or(false, |x| x.custom_rule.is_some()),
+                            "No rule name '{}' found.", rule_name);
+            }
+            let role = configuration.entry(rule_name.clone()).or_insert_with(|| ConfiguredRole {
+                role_id: None, custom_rule: None, last_updated: now,
+            });
+            match *change {
+                RuleChange::SetRole(_, role_id) => role.role_id = role_id,
+                RuleChange::SetCustomRule(_, ref condition) => role.custom_rule = condition.clone(),
+            }
+        }
+        configuration.retain(|_, x| x.role_id.is_some() || x.custom_rule.is_some());
+
+        let active_count = configuration.values().filter(|x| x.role_id.is_some()).count();
+        let custom_count = configuration.values().filter(|x| x.custom_rule.is_some()).count();
+        let role_ids = configuration.values().filter_map(|x| x.role_id).collect();
+        match self.compile_configuration(configuration, active_count, custom_count)? {
+            VerificationRulesStatus::Error(err) =>
+                cmd_error!("The role configuration would have errors: {}", err),
+            status => Ok(SimulatedRules { status, role_ids }),
+        }
+    }
+    // Works out which roles and nicknames `mass_update` would change, after making `changes` to
+    // the role configuration, without changing anything.
+    pub fn preview_mass_update<F>(
+        &self, guild_id: GuildId, changes: &[RuleChange], mut progress: F,
+    ) -> Result<MassUpdatePreview> where F: FnMut(usize, usize) -> Result<()> {
+        let lock = self.0.mass_update_lock.lock(guild_id);
+        cmd_ensure!(lock.is_some(), "This server's roles are already being updated.");
+
+        let rules = self.simulate_rules(guild_id, changes)?;
+        let verified = self.verified_members(guild_id)?;
+        let mut preview = MassUpdatePreview { changes: Vec::new(), checked: 0, failed: 0 };
+        for (i, batch) in verified.chunks(MASS_UPDATE_BATCH_SIZE).enumerate() {
+            if i != 0 {
+                thread::sleep(Duration::from_secs(MASS_UPDATE_BATCH_DELAY_SECS));
+            }
+            for &(member, roblox_id) in batch {
+                match self.plan_roles(guild_id, member, Some(roblox_id), Some(&rules)) {
+                    Ok(plan) => {
+                        let change = plan.to_change();
+                        if !change.added.is_empty() || !change.removed.is_empty() ||
+                           change.nickname.is_some() {
+                            preview.changes.push(change);
+                        }
+                        preview.checked += 1;
+                    }
+                    Err(e) => {
+                        debug!("Could not preview roles for <@{}> in {}: {}", member, guild_id, e);
+                        preview.failed += 1;
+                    }
+                }
+            }
+            progress(preview.checked + preview.failed, verified.len())?;
+        }
+        Ok(preview)
     }
 
     pub fn explain_rule_set(&self, guild: GuildId) -> Result<String> {
@@ -481,7 +931,7 @@ impl RoleManager {
                 let update_unverified =
                     self.0.config.get(Some(guild_id), ConfigKeys::EnableAutoUpdateUnverified)?;
                 let roles = self.clone();
-                self.0.tasks.dispatch_task(move |_| {
+                self.0.tasks.dispatch_io_task(move |_| {
                     roles.update_user_with_cooldown(
                         guild_id, user_id, auto_update_cooldown, false, update_unverified,
                     ).drop_nonfatal()
@@ -490,22 +940,42 @@ impl RoleManager {
         }
         Ok(())
     }
-    pub fn check_roles_update_join(&self, guild_id: GuildId, member: Member) -> Result<()> {
+
+    // Returns whether the user is an unverified newcomer that should be sent verification
+    // instructions by the join gate.
+    pub fn check_roles_update_join(&self, guild_id: GuildId, member: Member) -> Result<bool> {
         if member.user.read().id != serenity::CACHE.read().user.id {
+            let user_id = member.user.read().id;
+            if self.0.config.get(Some(guild_id), ConfigKeys::JoinGate)? &&
+               self.0.verifier.get_verified_roblox_user(user_id)?.is_none() {
+                let roles = self.clone();
+                self.0.tasks.dispatch_io_task(move |_| {
+                    roles.assign_roles(guild_id, user_id, None).drop_nonfatal()
+                });
+                return Ok(true)
+            }
+            if let Some(roblox_id) = self.0.verifier.get_trusted_link(guild_id, user_id)? {
+                debug!("{} verified in a trusted server. Setting roles in {}.", user_id, guild_id);
+                let roles = self.clone();
+                self.0.tasks.dispatch_io_task(move |_| {
+                    roles.assign_roles(guild_id, user_id, Some(roblox_id)).drop_nonfatal()
+                });
+                return Ok(false)
+            }
+
             let set_roles_on_join =
                 self.0.config.get(None, ConfigKeys::AllowSetRolesOnJoin)? &&
                 self.0.config.get(Some(guild_id), ConfigKeys::SetRolesOnJoin)?;
             if set_roles_on_join {
                 let roles = self.clone();
-                let user_id = member.user.read().id;
-                self.0.tasks.dispatch_task(move |_| {
+                self.0.tasks.dispatch_io_task(move |_| {
                     roles.update_user_with_cooldown(
                         guild_id, user_id, 0, false, false
                     ).drop_nonfatal()
                 })
             }
         }
-        Ok(())
+        Ok(false)
     }
 
     pub fn on_cleanup_tick(&self) {
@@ -517,6 +987,11 @@ impl RoleManager {
         self.0.rule_cache.shrink_to_fit();
         self.0.update_cache.shrink_to_fit();
     }
+    pub fn forget_user(&self, discord_id: UserId) {
+        self.0.update_cache.for_each(|cache| {
+            cache.retain(|&(user_id, _), _| user_id != discord_id)
+        });
+    }
     pub fn on_guild_remove(&self, guild: GuildId) {
         self.0.rule_cache.remove(&guild);
         self.0.update_cache.remove(&guild);
@@ -0,0 +1,50 @@
+use core::CoreRef;
+use errors::*;
+
+#[cfg(unix)]
+mod unix {
+    extern crate libc;
+
+    use core::CoreRef;
+    use errors::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    static SIGTERM_RECEIVED: AtomicBool = AtomicBool::new(false);
+
+    extern "C" fn on_sigterm(_: libc::c_int) {
+        SIGTERM_RECEIVED.store(true, Ordering::SeqCst);
+    }
+
+    // Signal handlers cannot safely do much of anything, so the handler only sets a flag, and a
+    // separate thread starts the shutdown when it sees it.
+    pub fn watch_sigterm(core_ref: CoreRef) -> Result<()> {
+        unsafe {
+            libc::signal(libc::SIGTERM, on_sigterm as libc::sighandler_t);
+        }
+        thread::Builder::new().name("signal thread".to_string()).spawn(move || {
+            while core_ref.is_alive() {
+                if SIGTERM_RECEIVED.load(Ordering::SeqCst) {
+                    info!("Received SIGTERM. Shutting down.");
+                    if let Some(core) = core_ref.get_core() {
+                        core.shutdown().ok();
+                    }
+                    break
+                }
+                thread::sleep(Duration::from_millis(100));
+            }
+        })?;
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+pub(in ::core) fn watch_sigterm(core_ref: CoreRef) -> Result<()> {
+    unix::watch_sigterm(core_ref)
+}
+
+#[cfg(not(unix))]
+pub(in ::core) fn watch_sigterm(_: CoreRef) -> Result<()> {
+    Ok(())
+}
@@ -0,0 +1,239 @@
+use database::*;
+use errors::*;
+use serenity::model::prelude::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use util;
+
+const SECONDS_PER_DAY: i64 = 60 * 60 * 24;
+// The server ID statistics are recorded under for verifications made outside of a server.
+const NO_GUILD_ID: u64 = 0;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum StatKind {
+    Verification, FailedAttempt, CooldownHit,
+}
+impl StatKind {
+    fn name(self) -> &'static str {
+        match self {
+            StatKind::Verification => "verification",
+            StatKind::FailedAttempt => "failed_attempt",
+            StatKind::CooldownHit => "cooldown_hit",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Default, Debug)]
+pub struct StatTotals {
+    pub verifications: u64, pub failed_attempts: u64, pub cooldown_hits: u64,
+}
+impl StatTotals {
+    fn add(&mut self, stat: &str, count: u64) {
+        match stat {
+            "verification" => self.verifications += count,
+            "failed_attempt" => self.failed_attempts += count,
+            "cooldown_hit" => self.cooldown_hits += count,
+            _ => warn!("Unknown statistic in database: {}", stat),
+        }
+    }
+}
+
+// How a command ended. Errors are grouped by their cause, so that problems with the bot stand out
+// from users giving invalid arguments.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum CommandOutcome {
+    Success, CommandError, PermissionError, NotFound, HttpError, InternalError, Panicked,
+}
+impl CommandOutcome {
+    pub fn from_result<T>(result: &Result<T>) -> CommandOutcome {
+        match *result {
+            Ok(_) => CommandOutcome::Success,
+            Err(Error::CommandError(_)) => CommandOutcome::CommandError,
+            Err(Error::SerenityPermissionError(_)) => CommandOutcome::PermissionError,
+            Err(Error::SerenityNotFoundError(_)) => CommandOutcome::NotFound,
+            Err(Error::SerenityHttpError(..)) => CommandOutcome::HttpError,
+            Err(Error::Panicked) => CommandOutcome::Panicked,
+            Err(_) => CommandOutcome::InternalError,
+        }
+    }
+    pub fn name(self) -> &'static str {
+        match self {
+            CommandOutcome::Success => "success",
+            CommandOutcome::CommandError => "command_error",
+            CommandOutcome::PermissionError => "permission_error",
+            CommandOutcome::NotFound => "not_found",
+            CommandOutcome::HttpError => "http_error",
+            CommandOutcome::InternalError => "internal_error",
+            CommandOutcome::Panicked => "panic",
+        }
+    }
+}
+
+// Whether an outcome points to a problem with the bot or its permissions, rather than with how
+// the command was used.
+fn is_failure(outcome: &str) -> bool {
+    outcome != CommandOutcome::Success.name() && outcome != CommandOutcome::CommandError.name()
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct CommandUsage {
+    pub command: String, pub uses: u64, pub total_millis: u64, pub max_millis: u64,
+    pub errors: Vec<(String, u64)>,
+}
+
+fn duration_millis(duration: Duration) -> i64 {
+    duration.as_secs() as i64 * 1000 + duration.subsec_nanos() as i64 / 1_000_000
+}
+
+fn current_day() -> i64 {
+    util::time_to_i64(SystemTime::now()) / SECONDS_PER_DAY
+}
+
+struct StatsManagerData {
+    database: Database,
+}
+
+#[derive(Clone)]
+pub struct StatsManager(Arc<StatsManagerData>);
+impl StatsManager {
+    pub fn new(database: Database) -> StatsManager {
+        StatsManager(Arc::new(StatsManagerData { database }))
+    }
+
+    pub fn record(&self, guild_id: Option<GuildId>, kind: StatKind) -> Result<()> {
+        let conn = self.0.database.connect()?;
+        let day = current_day();
+        let guild_id = guild_id.map_or(NO_GUILD_ID, |x| x.0);
+        conn.transaction_immediate(|| {
+            conn.execute(
+                "INSERT OR IGNORE INTO verification_stats (day, discord_guild_id, stat, count) \
+                 VALUES (?1, ?2, ?3, 0)", (day, guild_id, kind.name()),
+            )?;
+            conn.execute(
+                "UPDATE verification_stats SET count = count + 1 \
+                 WHERE day = ?1 AND discord_guild_id = ?2 AND stat = ?3",
+                (day, guild_id, kind.name()),
+            )?;
+            Ok(())
+        })
+    }
+
+    pub fn record_command(
+        &self, command: &str, outcome: CommandOutcome, duration: Duration,
+    ) -> Result<()> {
+        let conn = self.0.database.connect()?;
+        let day = current_day();
+        let millis = duration_millis(duration);
+        conn.transaction_immediate(|| {
+            conn.execute(
+                "INSERT OR IGNORE INTO command_stats \
+                     (day, command, outcome, count, total_millis, max_millis) \
+                 VALUES (?1, ?2, ?3, 0, 0, 0)",
+                (day, command, outcome.name()),
+            )?;
+            conn.execute(
+                "UPDATE command_stats \
+                 SET count = count + 1, total_millis = total_millis + ?4, \
+                     max_millis = MAX(max_millis, ?4) \
+                 WHERE day = ?1 AND command = ?2 AND outcome = ?3",
+                (day, command, outcome.name(), millis),
+            )?;
+            Ok(())
+        })
+    }
+
+    // Returns how each command was used in the last `days` days, including today, with the most
+    // used commands first.
+    pub fn command_usage(&self, days: u32) -> Result<Vec<CommandUsage>> {
+        let first_day = current_day() - days as i64 + 1;
+        let counts = self.0.database.connect_read()?.query(
+            "SELECT command, outcome, SUM(count), SUM(total_millis), MAX(max_millis) \
+             FROM command_stats WHERE day >= ?1 GROUP BY command, outcome",
+            first_day,
+        ).get_all::<(String, String, u64, u64, u64)>()?;
+
+        let mut usage: HashMap<String, CommandUsage> = HashMap::new();
+        for (command, outcome, count, total_millis, max_millis) in counts {
+            let entry = usage.entry(command.clone()).or_insert_with(|| CommandUsage {
+                command, ..CommandUsage::default()
+            });
+            entry.uses += count;
+            entry.total_millis += total_millis;
+            if max_millis > entry.max_millis {
+                entry.max_millis = max_millis;
+            }
+            if outcome != CommandOutcome::Success.name() {
+                entry.errors.push((outcome, count));
+            }
+        }
+        let mut usage: Vec<CommandUsage> = usage.into_iter().map(|x| x.1).collect();
+        for command in &mut usage {
+            command.errors.sort_by(|a, b| b.1.cmp(&a.1));
+        }
+        usage.sort_by(|a, b| b.uses.cmp(&a.uses).then_with(|| a.command.cmp(&b.command)));
+        Ok(usage)
+    }
+
+    // Returns how many commands failed because of a problem with the bot on each of the last
+    // `days` days, oldest first and ending with today.
+    pub fn daily_command_failures(&self, days: u32) -> Result<Vec<u64>> {
+        let first_day = current_day() - days as i64 + 1;
+        let counts = self.0.database.connect_read()?.query(
+            "SELECT day, outcome, SUM(count) FROM command_stats WHERE day >= ?1 \
+             GROUP BY day, outcome",
+            first_day,
+        ).get_all::<(i64, String, u64)>()?;
+
+        let mut failures = vec![0; days as usize];
+        for (day, outcome, count) in counts {
+            if is_failure(&outcome) {
+                if let Some(failures) = failures.get_mut((day - first_day) as usize) {
+                    *failures += count;
+                }
+            }
+        }
+        Ok(failures)
+    }
+
+    // Returns the totals for the last `days` days, including today, or for all time if `days`
+    // is `None`. The totals are for one server, or for every server if `guild_id` is `None`.
+    pub fn totals(&self, guild_id: Option<GuildId>, days: Option<u32>) -> Result<StatTotals> {
+        let first_day = match days {
+            Some(days) => current_day() - days as i64 + 1,
+            None => i64::min_value(),
+        };
+        let counts = self.0.database.connect_read()?.query(
+            "SELECT stat, SUM(count) FROM verification_stats \
+             WHERE day >= ?1 AND (?2 IS NULL OR discord_guild_id = ?2) GROUP BY stat",
+            (first_day, guild_id),
+        ).get_all::<(String, u64)>()?;
+
+        let mut totals = StatTotals::default();
+        for (stat, count) in counts {
+            totals.add(&stat, count);
+        }
+        Ok(totals)
+    }
+
+    // Returns the totals for each of the last `days` days, oldest first and ending with today,
+    // for one server or for every server.
+    pub fn daily_totals(
+        &self, guild_id: Option<GuildId>, days: u32,
+    ) -> Result<Vec<StatTotals>> {
+        let first_day = current_day() - days as i64 + 1;
+        let counts = self.0.database.connect_read()?.query(
+            "SELECT day, stat, SUM(count) FROM verification_stats \
+             WHERE day >= ?1 AND (?2 IS NULL OR discord_guild_id = ?2) GROUP BY day, stat",
+            (first_day, guild_id),
+        ).get_all::<(i64, String, u64)>()?;
+
+        let mut totals = vec![StatTotals::default(); days as usize];
+        for (day, stat, count) in counts {
+            if let Some(totals) = totals.get_mut((day - first_day) as usize) {
+                totals.add(&stat, count);
+            }
+        }
+        Ok(totals)
+    }
+}
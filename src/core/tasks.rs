@@ -1,18 +1,26 @@
+use chrono::{DateTime, Utc, Datelike, Timelike, Duration as ChronoDuration};
 use core::{VerifierCore, CoreRef};
+use database::Database;
 use errors::*;
 use error_report;
 use num_cpus;
 use parking_lot::Mutex;
+use rand::{Rng, thread_rng};
 use std::boxed::FnBox;
-use std::mem::{uninitialized, drop};
+use std::collections::HashMap;
+use std::fmt;
+use std::mem::{uninitialized, drop, replace};
 use std::ptr;
 use std::sync::Arc;
 use std::thread;
 use std::thread::Builder;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 use threadpool::ThreadPool;
 
 const MAX_SECS: usize = 4096; // 68 minutes
+// Tasks that mostly wait on Roblox or Discord run on their own, larger pool, so that a burst of
+// them cannot hold up event handling on the main pool.
+const IO_THREADS_PER_CPU: usize = 4;
 
 enum Task {
     NormalTask(Box<FnBox(&VerifierCore) -> Result<()> + Send + 'static>),
@@ -25,7 +33,8 @@ struct TimerRing {
     slots: [Option<Box<TaskList>>; MAX_SECS], cur_pos: usize,
 }
 struct TaskManagerData {
-    core_ref: CoreRef, pool: Mutex<ThreadPool>, timer_ring: Mutex<TimerRing>,
+    core_ref: CoreRef, pool: Mutex<ThreadPool>, io_pool: Mutex<ThreadPool>,
+    timer_ring: Mutex<TimerRing>,
 }
 
 #[derive(Clone)]
@@ -42,6 +51,8 @@ impl TaskManager {
         let tasks = TaskManager(Arc::new(TaskManagerData {
             core_ref,
             pool: Mutex::new(ThreadPool::with_name("task thread".to_string(), num_cpus::get())),
+            io_pool: Mutex::new(ThreadPool::with_name("io task thread".to_string(),
+                                                      num_cpus::get() * IO_THREADS_PER_CPU)),
             timer_ring: Mutex::new(TimerRing { slots, cur_pos: 0, }),
         }));
         {
@@ -81,11 +92,11 @@ impl TaskManager {
         Ok(tasks)
     }
 
-    pub fn dispatch_task<F>(
-        &self, f: F
+    fn execute<F>(
+        &self, pool: &Mutex<ThreadPool>, f: F
     ) where F: FnOnce(&VerifierCore) -> Result<()> + Send + 'static {
         let core_ref = self.0.core_ref.clone();
-        self.0.pool.lock().execute(move || {
+        pool.lock().execute(move || {
             error_report::catch_error(|| {
                 if let Some(core) = core_ref.get_core() {
                     f(&core)
@@ -95,6 +106,17 @@ impl TaskManager {
             }).ok();
         })
     }
+    pub fn dispatch_task<F>(
+        &self, f: F
+    ) where F: FnOnce(&VerifierCore) -> Result<()> + Send + 'static {
+        self.execute(&self.0.pool, f)
+    }
+    // Runs a task that spends most of its time on HTTP requests, such as updating roles.
+    pub fn dispatch_io_task<F>(
+        &self, f: F
+    ) where F: FnOnce(&VerifierCore) -> Result<()> + Send + 'static {
+        self.execute(&self.0.io_pool, f)
+    }
 
     fn push_to_ring(&self, task: Task, duration_secs: usize) {
         let mut ring = self.0.timer_ring.lock();
@@ -122,4 +144,285 @@ impl TaskManager {
         self.push_to_ring(Task::RepeatingTask(Arc::new(f), period_secs as usize),
                           period_secs as usize);
     }
-}
\ No newline at end of file
+}
+// A cron-like schedule, in the format `minute hour day-of-month month day-of-week`. Each field
+// is `*`, a number, a range `a-b`, or a comma separated list of these, optionally followed by a
+// step `/n`. Times are in UTC.
+#[derive(Clone, Debug)]
+pub struct Schedule {
+    source: String,
+    minutes: u64, hours: u64, days: u64, months: u64, weekdays: u64,
+    any_day: bool, any_weekday: bool,
+}
+impl Schedule {
+    pub fn parse(source: &str) -> Result<Schedule> {
+        let fields: Vec<&str> = source.split_whitespace().collect();
+        cmd_ensure!(fields.len() == 5,
+                    "Schedules must have 5 fields: minute, hour, day of month, month, and day \
+                     of week.");
+        let weekdays = parse_field(fields[4], 0, 7, "day of week")?;
+        let schedule = Schedule {
+            source: fields.join(" "),
+            minutes: parse_field(fields[0], 0, 59, "minute")?,
+            hours: parse_field(fields[1], 0, 23, "hour")?,
+            days: parse_field(fields[2], 1, 31, "day of month")?,
+            months: parse_field(fields[3], 1, 12, "month")?,
+            // Both 0 and 7 are Sunday.
+            weekdays: (weekdays | (weekdays >> 7)) & 0x7F,
+            any_day: fields[2] == "*", any_weekday: fields[4] == "*",
+        };
+        cmd_ensure!(schedule.next_after(SystemTime::now()).is_some(),
+                    "The schedule '{}' never runs.", schedule.source);
+        Ok(schedule)
+    }
+
+    fn matches_day(&self, time: &DateTime<Utc>) -> bool {
+        let day = self.days & (1 << time.day()) != 0;
+        let weekday = self.weekdays & (1 << time.weekday().num_days_from_sunday()) != 0;
+        // Like cron, if both the day of month and day of week are restricted, either can match.
+        let day_matches =
+            if self.any_day || self.any_weekday { day && weekday } else { day || weekday };
+        self.months & (1 << time.month()) != 0 && day_matches
+    }
+
+    // Returns the first time after the given time that matches this schedule.
+    pub fn next_after(&self, time: SystemTime) -> Option<SystemTime> {
+        let time: DateTime<Utc> = time.into();
+        let mut time = time.with_second(0)?.with_nanosecond(0)? + ChronoDuration::minutes(1);
+        let limit = time + ChronoDuration::days(366 * 8);
+        while time < limit {
+            if !self.matches_day(&time) {
+                time = time.date().succ().and_hms(0, 0, 0);
+            } else if self.hours & (1 << time.hour()) == 0 {
+                time = time.with_minute(0)? + ChronoDuration::hours(1);
+            } else if self.minutes & (1 << time.minute()) == 0 {
+                time = time + ChronoDuration::minutes(1);
+            } else {
+                return Some(time.into())
+            }
+        }
+        None
+    }
+}
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+fn parse_number(value: &str, name: &str) -> Result<u32> {
+    value.parse().ok().to_cmd_err(|| format!("'{}' is not a valid {}.", value, name))
+}
+fn parse_field(field: &str, min: u32, max: u32, name: &str) -> Result<u64> {
+    let mut mask = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.find('/') {
+            Some(i) => (&part[..i], parse_number(&part[i + 1..], "step")?),
+            None => (part, 1),
+        };
+        cmd_ensure!(step > 0, "The step of a schedule field cannot be 0.");
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some(i) = range.find('-') {
+            (parse_number(&range[..i], name)?, parse_number(&range[i + 1..], name)?)
+        } else {
+            let value = parse_number(range, name)?;
+            (value, if step == 1 { value } else { max })
+        };
+        cmd_ensure!(min <= start && start <= end && end <= max,
+                    "'{}' is out of range for {}. It must be between {} and {}.",
+                    range, name, min, max);
+        let mut i = start;
+        while i <= end {
+            mask |= 1 << i;
+            i += step;
+        }
+    }
+    Ok(mask)
+}
+
+// The maximum random delay added to each scheduled run, so jobs with the same schedule do not
+// all start at once.
+const MAX_JITTER_SECS: u64 = 60;
+const MAX_RETRIES: u32 = 3;
+const RETRY_DELAY_SECS: u64 = 60;
+const SCHEDULER_TICK_SECS: u64 = 30;
+
+pub struct ScheduledJob {
+    pub name: &'static str, pub description: &'static str, pub default_schedule: &'static str,
+    pub run: fn(&VerifierCore) -> Result<()>,
+}
+
+#[derive(Clone, Debug)]
+pub struct JobStatus {
+    pub name: &'static str, pub description: &'static str, pub schedule: Schedule,
+    pub is_default_schedule: bool, pub last_run: Option<SystemTime>, pub next_run: SystemTime,
+    pub failure_count: u32, pub last_error: Option<String>, pub running: bool,
+}
+
+fn jitter() -> Duration {
+    Duration::from_secs(thread_rng().gen_range(0, MAX_JITTER_SECS + 1))
+}
+
+struct JobSchedulerData {
+    database: Database, tasks: TaskManager, jobs: &'static [ScheduledJob],
+    status: Mutex<HashMap<&'static str, JobStatus>>,
+}
+
+// Runs background jobs according to schedules stored in the database. Failed jobs are retried
+// with an increasing delay before falling back to their normal schedule.
+#[derive(Clone)]
+pub struct JobScheduler(Arc<JobSchedulerData>);
+impl JobScheduler {
+    pub(in ::core) fn new(
+        database: Database, tasks: TaskManager, jobs: &'static [ScheduledJob],
+    ) -> Result<JobScheduler> {
+        let conn = database.connect()?;
+        let mut status = HashMap::new();
+        for job in jobs {
+            let row = conn.query(
+                "SELECT schedule, last_run, next_run, failure_count, last_error \
+                 FROM scheduled_jobs WHERE job_name = ?1", job.name,
+            ).get_opt::<(Option<String>, Option<SystemTime>, SystemTime, u32, Option<String>)>()?;
+            let default_schedule = Schedule::parse(job.default_schedule)?;
+            let job_status = match row {
+                Some((schedule, last_run, next_run, failure_count, last_error)) => {
+                    let schedule = match schedule.map(|x| Schedule::parse(&x)) {
+                        Some(Ok(schedule)) => Some(schedule),
+                        Some(Err(e)) => {
+                            warn!("Invalid schedule for job '{}', using the default: {}",
+                                  job.name, e);
+                            None
+                        }
+                        None => None,
+                    };
+                    JobStatus {
+                        name: job.name, description: job.description,
+                        is_default_schedule: schedule.is_none(),
+                        schedule: schedule.unwrap_or(default_schedule),
+                        last_run, next_run, failure_count, last_error, running: false,
+                    }
+                }
+                None => JobStatus {
+                    name: job.name, description: job.description,
+                    next_run: default_schedule.next_after(SystemTime::now())? + jitter(),
+                    schedule: default_schedule, is_default_schedule: true,
+                    last_run: None, failure_count: 0, last_error: None, running: false,
+                },
+            };
+            status.insert(job.name, job_status);
+        }
+        let scheduler = JobScheduler(Arc::new(JobSchedulerData {
+            database, tasks: tasks.clone(), jobs, status: Mutex::new(status),
+        }));
+        for job in jobs {
+            scheduler.save_status(job.name)?;
+        }
+        tasks.dispatch_repeating_task(Duration::from_secs(SCHEDULER_TICK_SECS),
+                                      |core| core.jobs().run_due_jobs());
+        Ok(scheduler)
+    }
+
+    fn find_job(&self, name: &str) -> Result<&'static ScheduledJob> {
+        self.0.jobs.iter().find(|x| x.name == name)
+            .to_cmd_err(|| format!("Unknown job '{}'.", name))
+    }
+
+    fn save_status(&self, name: &str) -> Result<()> {
+        let status = self.0.status.lock().get(name).cloned()?;
+        let schedule = if status.is_default_schedule {
+            None
+        } else {
+            Some(status.schedule.to_string())
+        };
+        self.0.database.connect()?.execute(
+            "REPLACE INTO scheduled_jobs \
+                 (job_name, schedule, last_run, next_run, failure_count, last_error) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            (status.name, schedule, status.last_run, status.next_run,
+             status.failure_count, status.last_error),
+        )?;
+        Ok(())
+    }
+
+    // Marks a job as running, returning false if it is already running.
+    fn start_job(&self, name: &str) -> bool {
+        match self.0.status.lock().get_mut(name) {
+            Some(status) => !replace(&mut status.running, true),
+            None => false,
+        }
+    }
+    fn run_job(&self, core: &VerifierCore, job: &'static ScheduledJob) -> Result<()> {
+        debug!("Running scheduled job '{}'.", job.name);
+        let result = (job.run)(core);
+        let now = SystemTime::now();
+        {
+            let mut status = self.0.status.lock();
+            let status = status.get_mut(job.name)?;
+            let next_run = status.schedule.next_after(now)? + jitter();
+            status.running = false;
+            status.last_run = Some(now);
+            match result {
+                Ok(()) => {
+                    status.failure_count = 0;
+                    status.last_error = None;
+                    status.next_run = next_run;
+                }
+                Err(ref e) => {
+                    status.failure_count += 1;
+                    status.last_error = Some(e.to_string());
+                    status.next_run = if status.failure_count <= MAX_RETRIES {
+                        let delay = RETRY_DELAY_SECS << (status.failure_count - 1);
+                        (now + Duration::from_secs(delay)).min(next_run)
+                    } else {
+                        next_run
+                    };
+                }
+            }
+        }
+        self.save_status(job.name)?;
+        if let Err(e) = result {
+            error!("Scheduled job '{}' failed: {}", job.name, e);
+        }
+        Ok(())
+    }
+    fn dispatch_job(&self, job: &'static ScheduledJob) {
+        let scheduler = self.clone();
+        self.0.tasks.dispatch_task(move |core| scheduler.run_job(core, job));
+    }
+
+    pub(in ::core) fn run_due_jobs(&self) -> Result<()> {
+        let now = SystemTime::now();
+        for job in self.0.jobs {
+            let is_due = self.0.status.lock().get(job.name).map_or(false, |x| x.next_run <= now);
+            if is_due && self.start_job(job.name) {
+                self.dispatch_job(job);
+            }
+        }
+        Ok(())
+    }
+
+    // Runs a job immediately, without changing when it is next scheduled to run.
+    pub fn trigger(&self, name: &str) -> Result<()> {
+        let job = self.find_job(name)?;
+        cmd_ensure!(self.start_job(job.name), "The job '{}' is already running.", job.name);
+        self.dispatch_job(job);
+        Ok(())
+    }
+    pub fn set_schedule(&self, name: &str, schedule: Option<&str>) -> Result<()> {
+        let job = self.find_job(name)?;
+        let schedule = Schedule::parse(schedule.unwrap_or(job.default_schedule))?;
+        {
+            let mut status = self.0.status.lock();
+            let status = status.get_mut(job.name)?;
+            status.next_run = schedule.next_after(SystemTime::now())? + jitter();
+            status.is_default_schedule = schedule.source == job.default_schedule;
+            status.schedule = schedule;
+        }
+        self.save_status(job.name)
+    }
+    pub fn jobs(&self) -> Vec<JobStatus> {
+        let status = self.0.status.lock();
+        self.0.jobs.iter().filter_map(|x| status.get(x.name).cloned()).collect()
+    }
+}
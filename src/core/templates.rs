@@ -0,0 +1,164 @@
+use database::*;
+use errors::*;
+use serenity::model::prelude::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+use util::ConcurrentCache;
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum MessageKey {
+    VerifySuccess, VerifyFailed, Cooldown, Instructions, JoinWelcome,
+}
+impl MessageKey {
+    pub const ALL: &'static [MessageKey] = &[
+        MessageKey::VerifySuccess, MessageKey::VerifyFailed,
+        MessageKey::Cooldown, MessageKey::Instructions, MessageKey::JoinWelcome,
+    ];
+
+    pub fn from_name(name: &str) -> Result<MessageKey> {
+        match MessageKey::ALL.iter().find(|x| x.name() == name.to_lowercase()) {
+            Some(&key) => Ok(key),
+            None => cmd_error!("Unknown message '{}'. Valid messages are: {}", name,
+                               MessageKey::ALL.iter().map(|x| x.name())
+                                   .collect::<Vec<_>>().join(", ")),
+        }
+    }
+    pub fn name(self) -> &'static str {
+        match self {
+            MessageKey::VerifySuccess => "verify_success",
+            MessageKey::VerifyFailed => "verify_failed",
+            MessageKey::Cooldown => "cooldown",
+            MessageKey::Instructions => "instructions",
+            MessageKey::JoinWelcome => "join_welcome",
+        }
+    }
+    pub fn placeholders(self) -> &'static [&'static str] {
+        match self {
+            MessageKey::VerifySuccess => &["discord_name", "roblox_name"],
+            MessageKey::VerifyFailed => &["roblox_name"],
+            MessageKey::Cooldown => &["roblox_name", "max_attempts", "time_left"],
+            MessageKey::Instructions => &["place_url", "prefix"],
+            MessageKey::JoinWelcome => &["server", "place_url", "prefix", "channel"],
+        }
+    }
+
+    fn check_template(self, template: &str) -> Result<()> {
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..].find('}')
+                .to_cmd_err(|| "Unclosed `{` in message template.")? + start;
+            let name = &rest[start + 1..end];
+            cmd_ensure!(self.placeholders().contains(&name),
+                        "Unknown placeholder `{{{}}}`. This message can use: {}", name,
+                        self.placeholders().iter().map(|x| format!("`{{{}}}`", x))
+                            .collect::<Vec<_>>().join(", "));
+            rest = &rest[end + 1..];
+        }
+        Ok(())
+    }
+}
+
+// Replaces `{name}` with the value of each variable. The template is only read once, so values
+// containing something like `{name}` themselves, such as usernames, are left as they are.
+fn fill_template(template: &str, vars: &[(&str, &str)]) -> String {
+    let mut message = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        message.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let value = rest.find('}').and_then(|end| {
+            let name = &rest[1..end];
+            vars.iter().find(|x| x.0 == name).map(|x| (x.1, end))
+        });
+        match value {
+            Some((value, end)) => {
+                message.push_str(value);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                message.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    message.push_str(rest);
+    message
+}
+
+struct MessageTemplateManagerData {
+    database: Database, cache: ConcurrentCache<GuildId, HashMap<MessageKey, String>>,
+}
+
+// Stores per-guild replacements for messages shown to users while verifying.
+#[derive(Clone)]
+pub struct MessageTemplateManager(Arc<MessageTemplateManagerData>);
+impl MessageTemplateManager {
+    pub fn new(database: Database) -> MessageTemplateManager {
+        let db_ref = database.clone();
+        MessageTemplateManager(Arc::new(MessageTemplateManagerData {
+            database, cache: ConcurrentCache::new(move |&guild_id| {
+                let templates = db_ref.connect()?.query(
+                    "SELECT message_key, template FROM guild_message_templates \
+                     WHERE discord_guild_id = ?1", guild_id,
+                ).get_all::<(String, String)>()?;
+                let mut map = HashMap::new();
+                for (key, template) in templates {
+                    match MessageKey::from_name(&key) {
+                        Ok(key) => { map.insert(key, template); }
+                        Err(_) => warn!("Unknown message template '{}' in {}.", key, guild_id),
+                    }
+                }
+                Ok(map)
+            }),
+        }))
+    }
+
+    pub fn get(&self, guild_id: GuildId, key: MessageKey) -> Result<Option<String>> {
+        Ok(self.0.cache.read(&guild_id)?.get(&key).cloned())
+    }
+    pub fn set(&self, guild_id: GuildId, key: MessageKey, template: Option<&str>) -> Result<()> {
+        let conn = self.0.database.connect()?;
+        let mut cache = self.0.cache.write(&guild_id)?;
+        match template {
+            Some(template) => {
+                key.check_template(template)?;
+                conn.execute(
+                    "REPLACE INTO guild_message_templates \
+                         (discord_guild_id, message_key, template) \
+                     VALUES (?1, ?2, ?3)", (guild_id, key.name(), template),
+                )?;
+                cache.insert(key, template.to_owned());
+            }
+            None => {
+                conn.execute(
+                    "DELETE FROM guild_message_templates \
+                     WHERE discord_guild_id = ?1 AND message_key = ?2", (guild_id, key.name()),
+                )?;
+                cache.remove(&key);
+            }
+        }
+        Ok(())
+    }
+
+    // Returns the guild's template for a message with its placeholders filled in, or the default
+    // message if the guild has not customized it.
+    pub fn render<F>(
+        &self, guild_id: Option<GuildId>, key: MessageKey, vars: &[(&str, &str)], default: F,
+    ) -> Result<String> where F: FnOnce() -> Result<String> {
+        let template = match guild_id {
+            Some(guild_id) => self.get(guild_id, key)?,
+            None => None,
+        };
+        match template {
+            Some(template) => Ok(fill_template(&template, vars)),
+            None => default(),
+        }
+    }
+
+    pub fn on_cleanup_tick(&self) {
+        self.0.cache.shrink_to_fit();
+    }
+    pub fn on_guild_remove(&self, guild_id: GuildId) {
+        self.0.cache.remove(&guild_id);
+    }
+}
@@ -5,8 +5,9 @@ use errors::*;
 use linefeed::*;
 use linefeed::reader::LogSender;
 use logger;
-use parking_lot::Mutex;
+use parking_lot::{Mutex, Condvar};
 use std::io;
+use std::io::BufRead;
 use std::thread;
 use util;
 
@@ -37,14 +38,95 @@ impl CommandContextData for TerminalContext {
     }
 }
 
+#[cfg(unix)]
+fn stdin_is_terminal() -> bool {
+    extern crate libc;
+    unsafe { libc::isatty(libc::STDIN_FILENO) != 0 }
+}
+
+#[cfg(not(unix))]
+fn stdin_is_terminal() -> bool {
+    true
+}
+
+fn run_line(core_ref: &CoreRef, line: &str) -> Result<()> {
+    let command_no = util::command_id();
+    if let Some(command) = get_command(line) {
+        let ctx = TerminalContext {
+            line: line.to_owned(), command_no,
+        };
+
+        if command.no_threading {
+            core_ref.run_command(command, &ctx)
+        } else {
+            let core_ref = core_ref.clone();
+            thread::Builder::new()
+                .name(format!("command #{}", ctx.command_no + 1))
+                .spawn(move || core_ref.run_command(command, &ctx))?;
+            thread::yield_now();
+        }
+    } else {
+        info!(target: "$raw", "[Command #{}] Unknown command.", command_no);
+    }
+    Ok(())
+}
+
 pub struct Terminal {
     core_ref: CoreRef, sender: Mutex<Option<LogSender>>,
+    interrupted: Mutex<bool>, interrupt_signal: Condvar,
 }
 impl Terminal {
     pub(in ::core) fn new(core_ref: CoreRef) -> Result<Terminal> {
-        Ok(Terminal { core_ref, sender: Mutex::new(None) })
+        Ok(Terminal {
+            core_ref, sender: Mutex::new(None),
+            interrupted: Mutex::new(false), interrupt_signal: Condvar::new(),
+        })
     }
     pub fn open(&self) -> Result<()> {
+        *self.interrupted.lock() = false;
+        if stdin_is_terminal() {
+            self.open_interactive()
+        } else {
+            self.open_plain()
+        }
+    }
+
+    // When stdin is not a terminal, e.g. when the bot is run by a service manager with commands
+    // piped to it, commands are read from it one per line without line editing. Reading stdin
+    // cannot be interrupted, so this is done on a separate thread.
+    fn open_plain(&self) -> Result<()> {
+        info!("Standard input is not a terminal. Reading commands from it line by line.");
+        let core_ref = self.core_ref.clone();
+        thread::Builder::new().name("stdin thread".to_string()).spawn(move || {
+            let stdin = io::stdin();
+            for line in stdin.lock().lines() {
+                if !core_ref.is_alive() {
+                    break
+                }
+                match line {
+                    Ok(line) => {
+                        let line = line.trim();
+                        info!(target: "$command_input", "{}", line);
+                        if !line.is_empty() {
+                            error_report::catch_error(|| run_line(&core_ref, line)).ok();
+                        }
+                    }
+                    Err(err) => {
+                        error!("Could not read from standard input: {}", err);
+                        break
+                    }
+                }
+            }
+        })?;
+
+        let mut interrupted = self.interrupted.lock();
+        while !*interrupted {
+            self.interrupt_signal.wait(&mut interrupted);
+        }
+        Ok(())
+    }
+
+    fn open_interactive(&self) -> Result<()> {
         let mut reader = Reader::new("sylph-verifier")?;
         reader.set_prompt("sylph-verifier> ");
         reader.set_history_size(1000);
@@ -70,25 +152,7 @@ impl Terminal {
                             last_line = line.to_owned();
                         }
 
-                        let command_no = util::command_id();
-                        if let Some(command) = get_command(line) {
-                            let ctx = TerminalContext {
-                                line: line.to_owned(), command_no,
-                            };
-
-                            if command.no_threading {
-                                self.core_ref.run_command(command, &ctx)
-                            } else {
-                                let core_ref = self.core_ref.clone();
-                                thread::Builder::new()
-                                    .name(format!("command #{}", ctx.command_no + 1))
-                                    .spawn(move || core_ref.run_command(command, &ctx))?;
-                                thread::yield_now();
-                            }
-                        } else {
-                            info!(target: "$raw", "[Command #{}] Unknown command.", command_no);
-                        }
-                        Ok(())
+                        run_line(&self.core_ref, line)
                     }).ok();
                 }
                 Ok(ReadResult::Eof) =>
@@ -114,5 +178,7 @@ impl Terminal {
     }
     pub fn interrupt(&self) {
         self.sender.lock().as_ref().map(|x| x.interrupt().ok());
+        *self.interrupted.lock() = true;
+        self.interrupt_signal.notify_all();
     }
 }
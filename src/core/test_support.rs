@@ -0,0 +1,64 @@
+// Fixtures for tests that need a verifier, without connecting to Discord or starting the bot.
+
+use core::*;
+use core::tasks::TaskManager;
+use database::Database;
+use parking_lot::Mutex;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, ATOMIC_USIZE_INIT, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use util::Clock;
+
+// A clock that only moves when told to.
+pub struct TestClock(Mutex<SystemTime>);
+impl TestClock {
+    pub fn new() -> Arc<TestClock> {
+        Arc::new(TestClock(Mutex::new(UNIX_EPOCH + Duration::from_secs(1_500_000_000))))
+    }
+    pub fn advance(&self, duration: Duration) {
+        *self.0.lock() += duration;
+    }
+}
+impl Clock for TestClock {
+    fn now(&self) -> SystemTime {
+        *self.0.lock()
+    }
+}
+
+// A verifier with its own in-memory database and clock. Background tasks, such as posting to
+// audit channels, are dropped, as there is no running core for them to use.
+pub struct TestVerifier {
+    pub verifier: Verifier, pub config: ConfigManager, pub database: Database,
+    pub clock: Arc<TestClock>, dir: PathBuf,
+}
+impl TestVerifier {
+    pub fn new() -> TestVerifier {
+        static NEXT_ID: AtomicUsize = ATOMIC_USIZE_INIT;
+        let dir = ::std::env::temp_dir().join(format!(
+            "sylph_test_{}_{}", process::id(), NEXT_ID.fetch_add(1, Ordering::Relaxed),
+        ));
+        fs::create_dir_all(&dir).unwrap();
+
+        let database = Database::in_memory().unwrap();
+        let config = ConfigManager::new(database.clone());
+        let tasks = TaskManager::new(CoreRef::new()).unwrap();
+        let audit = AuditLog::new(config.clone(), tasks.clone());
+        let webhooks = WebhookManager::new(config.clone(), tasks);
+        let event_log = EventLog::new(database.clone(), &dir.join("eventlog.key")).unwrap();
+        let clock = TestClock::new();
+        let verifier = Verifier::new(config.clone(), database.clone(), audit, webhooks,
+                                     BlacklistManager::new(database.clone()),
+                                     StatsManager::new(database.clone()),
+                                     UsernameManager::new(database.clone()), event_log,
+                                     clock.clone()).unwrap();
+        TestVerifier { verifier, config, database, clock, dir }
+    }
+}
+impl Drop for TestVerifier {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.dir).ok();
+    }
+}
@@ -0,0 +1,173 @@
+use database::*;
+use errors::*;
+use roblox::RobloxUserID;
+use serenity::model::prelude::*;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+const REFRESH_BATCH_SIZE: u32 = 500;
+
+pub struct Rename {
+    pub discord_id: UserId, pub roblox_id: RobloxUserID,
+    pub old_username: String, pub new_username: String,
+}
+
+struct UsernameManagerData {
+    database: Database,
+}
+
+// Remembers the current and past usernames of Roblox users, so a username can still be resolved
+// after the user changes it, and so renames can be detected.
+#[derive(Clone)]
+pub struct UsernameManager(Arc<UsernameManagerData>);
+impl UsernameManager {
+    pub fn new(database: Database) -> UsernameManager {
+        UsernameManager(Arc::new(UsernameManagerData { database }))
+    }
+
+    pub fn get_stored_username(&self, roblox_id: RobloxUserID) -> Result<Option<String>> {
+        self.0.database.connect_read()?.query(
+            "SELECT username FROM roblox_usernames WHERE roblox_user_id = ?1 AND is_current = 1",
+            roblox_id,
+        ).get_opt()
+    }
+
+    // Records the current username of a Roblox user. Returns the user's previous username if
+    // they were renamed since they were last seen.
+    pub fn record(&self, roblox_id: RobloxUserID, username: &str) -> Result<Option<String>> {
+        let conn = self.0.database.connect()?;
+        let is_known = conn.query(
+            "SELECT COUNT(*) FROM roblox_usernames WHERE roblox_user_id = ?1", roblox_id,
+        ).get::<u32>()? != 0;
+        let past_usernames = if is_known {
+            Vec::new()
+        } else {
+            match roblox_id.past_usernames() {
+                Ok(past_usernames) => past_usernames,
+                Err(e) => {
+                    warn!("Could not look up past usernames of Roblox user #{}: {}",
+                          roblox_id.0, e);
+                    Vec::new()
+                }
+            }
+        };
+
+        let now = SystemTime::now();
+        conn.transaction_immediate(|| {
+            let previous = conn.query(
+                "SELECT username FROM roblox_usernames \
+                 WHERE roblox_user_id = ?1 AND is_current = 1", roblox_id,
+            ).get_opt::<String>()?;
+            conn.execute(
+                "UPDATE roblox_usernames SET is_current = 0 WHERE roblox_user_id = ?1", roblox_id,
+            )?;
+            for past_username in &past_usernames {
+                conn.execute(
+                    "INSERT OR IGNORE INTO roblox_usernames \
+                         (username, roblox_user_id, is_current, last_updated) \
+                     VALUES (?1, ?2, 0, ?3)", (past_username, roblox_id, now),
+                )?;
+            }
+            conn.execute(
+                "REPLACE INTO roblox_usernames \
+                     (username, roblox_user_id, is_current, last_updated) \
+                 VALUES (?1, ?2, 1, ?3)", (username, roblox_id, now),
+            )?;
+            Ok(match previous {
+                Some(ref previous) if previous != username => {
+                    conn.execute(
+                        "INSERT INTO roblox_username_history \
+                             (roblox_user_id, old_username, new_username, changed_at) \
+                         VALUES (?1, ?2, ?3, ?4)", (roblox_id, previous, username, now),
+                    )?;
+                    Some(previous.clone())
+                }
+                _ => None,
+            })
+        })
+    }
+
+    // Returns the detected username changes of a Roblox user, oldest first.
+    pub fn get_history(
+        &self, roblox_id: RobloxUserID,
+    ) -> Result<Vec<(String, String, SystemTime)>> {
+        self.0.database.connect_read()?.query(
+            "SELECT old_username, new_username, changed_at FROM roblox_username_history \
+             WHERE roblox_user_id = ?1 ORDER BY changed_at", roblox_id,
+        ).get_all()
+    }
+
+    // Refreshes the usernames of verified users that have not been checked within `interval`.
+    // Only a limited number of users are checked per call, so this should be called regularly.
+    pub fn refresh_verified(&self, interval: Duration) -> Result<Vec<Rename>> {
+        let stale = self.0.database.connect()?.query(
+            "SELECT discord_user_info.discord_user_id, discord_user_info.roblox_user_id \
+             FROM discord_user_info LEFT JOIN roblox_usernames \
+                 ON roblox_usernames.roblox_user_id = discord_user_info.roblox_user_id \
+                 AND roblox_usernames.is_current = 1 \
+             WHERE discord_user_info.roblox_user_id IS NOT NULL AND \
+                 (roblox_usernames.last_updated IS NULL OR roblox_usernames.last_updated < ?1) \
+             ORDER BY roblox_usernames.last_updated LIMIT ?2",
+            (SystemTime::now() - interval, REFRESH_BATCH_SIZE),
+        ).get_all::<(UserId, RobloxUserID)>()?;
+
+        let mut renames = Vec::new();
+        for (discord_id, roblox_id) in stale {
+            let new_username = match roblox_id.lookup_username_opt()? {
+                Some(new_username) => new_username,
+                None => continue,
+            };
+            if let Some(old_username) = self.record(roblox_id, &new_username)? {
+                renames.push(Rename { discord_id, roblox_id, old_username, new_username });
+            }
+        }
+        Ok(renames)
+    }
+
+    // Finds the Roblox user that currently has a username. Verification uses this rather than
+    // `resolve`, so that an account cannot be claimed by a name it no longer has.
+    pub fn resolve_current(&self, username: &str) -> Result<RobloxUserID> {
+        match self.find_current(username)? {
+            Some(roblox_id) => Ok(roblox_id),
+            None => cmd_error!("No Roblox user named '{}' found.", username),
+        }
+    }
+    fn find_current(&self, username: &str) -> Result<Option<RobloxUserID>> {
+        Ok(match RobloxUserID::find_by_username(username)? {
+            Some((roblox_id, current_name)) => {
+                // Only changes are recorded, so looking up the same user does not write to the
+                // database every time.
+                if self.get_stored_username(roblox_id)?.as_ref() != Some(&current_name) {
+                    self.record(roblox_id, &current_name)?;
+                }
+                Some(roblox_id)
+            }
+            None => None,
+        })
+    }
+
+    // Finds the Roblox user with a username, falling back to usernames users have had in the
+    // past if no user currently has it. This is only for looking users up, as the name may have
+    // been taken by someone else since.
+    pub fn resolve(&self, username: &str) -> Result<RobloxUserID> {
+        if let Some(roblox_id) = self.find_current(username)? {
+            return Ok(roblox_id)
+        }
+        let past_owner = self.0.database.connect_read()?.query(
+            "SELECT roblox_user_id FROM roblox_usernames WHERE username = ?1", username.trim(),
+        ).get_opt::<RobloxUserID>()?;
+        match past_owner {
+            Some(roblox_id) => Ok(roblox_id),
+            None => cmd_error!("No Roblox user named '{}' found.", username),
+        }
+    }
+
+    // Looks up the current username of a Roblox user, recording it and returning the previous
+    // username if they were renamed.
+    pub fn refresh(&self, roblox_id: RobloxUserID) -> Result<Option<String>> {
+        match roblox_id.lookup_username_opt()? {
+            Some(username) => self.record(roblox_id, &username),
+            None => Ok(None),
+        }
+    }
+}
@@ -1,6 +1,10 @@
 use database::*;
 use core::config::*;
+use core::embeds::EmbedTheme;
+use core::templates::*;
+use core::verifier::is_token;
 use errors::*;
+use i18n;
 use serenity::model::prelude::*;
 use std::sync::Arc;
 use std::time::{SystemTime, Duration};
@@ -8,21 +12,49 @@ use util::ConcurrentCache;
 
 // TODO: Handle the verification channel being deleted.
 
+pub const VERIFY_PANEL_EMOJI: &str = "\u{2705}";
+const VERIFY_PANEL_COLOUR: u32 = 0x7289DA;
+
+// Checks if a message is a verify command with a verification code, e.g. `!verify Name ABCDEF`.
+fn contains_token(config: &ConfigManager, content: &str, prefix: &str) -> Result<bool> {
+    let content = content.trim_left();
+    let content = if content.starts_with(prefix) {
+        &content[prefix.len()..]
+    } else if content.starts_with("<@") {
+        match content.find('>') {
+            Some(end) => &content[end + 1..],
+            None => return Ok(false),
+        }
+    } else {
+        return Ok(false)
+    };
+    let words: Vec<&str> = content.split_whitespace().collect();
+    Ok(words.len() >= 3 && words[0].eq_ignore_ascii_case("verify") &&
+       is_token(config, words[2])?)
+}
+
 struct VerificationChannelManagerData {
-    config: ConfigManager, database: Database,
+    config: ConfigManager, database: Database, templates: MessageTemplateManager,
     channel_cache: ConcurrentCache<GuildId, Option<(ChannelId, MessageId)>>,
+    panel_cache: ConcurrentCache<GuildId, Option<(ChannelId, MessageId)>>,
 }
 
 #[derive(Clone)]
 pub struct VerificationChannelManager(Arc<VerificationChannelManagerData>);
 impl VerificationChannelManager {
-    pub fn new(config: ConfigManager, database: Database) -> VerificationChannelManager {
+    pub fn new(
+        config: ConfigManager, database: Database, templates: MessageTemplateManager,
+    ) -> VerificationChannelManager {
         let db_ref_update = database.clone();
+        let db_ref_panel = database.clone();
         VerificationChannelManager(Arc::new(VerificationChannelManagerData {
-            config, database,
+            config, database, templates,
             channel_cache: ConcurrentCache::new(move |&guild_id| {
                 Self::get_verification_channel(&db_ref_update, guild_id)
             }),
+            panel_cache: ConcurrentCache::new(move |&guild_id| {
+                Self::get_verify_panel(&db_ref_panel, guild_id)
+            }),
         }))
     }
 
@@ -56,10 +88,33 @@ impl VerificationChannelManager {
     ) -> Result<()> {
         if self.is_verification_channel(guild_id, message.channel_id)? {
             message.delete().map_err(Error::from).drop_nonfatal()?;
+        } else if self.0.config.get(Some(guild_id), ConfigKeys::DeleteTokenMessages)? {
+            self.check_token_msg(guild_id, message)?;
         }
         Ok(())
     }
 
+    // Deletes verify commands that contain a verification code, so other users in the channel
+    // cannot see it. The command itself still runs normally.
+    fn check_token_msg(&self, guild_id: GuildId, message: &Message) -> Result<()> {
+        let prefix = self.0.config.get(Some(guild_id), ConfigKeys::CommandPrefix)?;
+        if !contains_token(&self.0.config, &message.content, &prefix)? {
+            return Ok(())
+        }
+        match message.delete() {
+            Ok(()) => {
+                message.channel_id.say(format_args!(
+                    "<@{}> Your message was deleted because it contained your verification \
+                     code. Next time, please use `/verify`, which only you can see, or use \
+                     `{}verify` without a code to verify in direct messages.",
+                    message.author.id, prefix,
+                ))?;
+                Ok(())
+            }
+            Err(e) => Error::from(e).drop_nonfatal(),
+        }
+    }
+
     fn remove_messages(&self, channel_id: ChannelId, ignore: Option<MessageId>) -> Result<()> {
         let mut tries = 0;
         loop {
@@ -99,20 +154,53 @@ impl VerificationChannelManager {
         Ok(())
     }
 
-    pub fn verify_instructions(&self) -> Result<String> {
+    pub fn verify_instructions(&self, guild_id: Option<GuildId>) -> Result<String> {
         if let Some(place_id) = self.0.config.get(None, ConfigKeys::PlaceID)? {
-            Ok(format!("To verify your Roblox account with your Discord account, please \
-                        follow these instructions:\n\
-                        • Visit <https://roblox.com/--place?id={}> as the account you want to \
-                          verify as.\n\
-                        • Type the command it shows in the oval box into this channel.",
-                       place_id))
+            let place_url = format!("https://roblox.com/--place?id={}", place_id);
+            let prefix = self.0.config.get(guild_id, ConfigKeys::CommandPrefix)?;
+            self.0.templates.render(guild_id, MessageKey::Instructions, &[
+                ("place_url", &place_url), ("prefix", &prefix),
+            ], || i18n::tr(&self.0.config, guild_id, "verify-instructions", &[
+                ("place_url", &place_url),
+            ]))
         } else {
             error!("No place ID set! Please upload the place file to Roblox, and use \
                     \"set_global place_id [your place id]\".");
             cmd_error!("No place ID set. Please ask the bot owner to fix this.")
         }
     }
+    // Sends a new member of a server that uses the join gate a direct message explaining how to
+    // verify there.
+    pub fn send_join_welcome(&self, guild_id: GuildId, user_id: UserId) -> Result<()> {
+        let place_id = match self.0.config.get(None, ConfigKeys::PlaceID)? {
+            Some(place_id) => place_id,
+            None => {
+                warn!("Not sending a welcome message in {}, as no place ID is set.", guild_id);
+                return Ok(())
+            }
+        };
+        let place_url = format!("https://roblox.com/--place?id={}", place_id);
+        let prefix = self.0.config.get(Some(guild_id), ConfigKeys::CommandPrefix)?;
+        let server = guild_id.find()
+            .map_or_else(|| guild_id.to_string(), |x| x.read().name.clone());
+        let channel = (*self.0.channel_cache.read(&guild_id)?)
+            .map(|(channel_id, _)| format!("<#{}>", channel_id.0));
+        let message = self.0.templates.render(Some(guild_id), MessageKey::JoinWelcome, &[
+            ("server", &server), ("place_url", &place_url), ("prefix", &prefix),
+            ("channel", channel.as_ref().map_or("", |x| x.as_str())),
+        ], || match channel {
+            Some(ref channel) => i18n::tr(&self.0.config, Some(guild_id), "join-welcome", &[
+                ("server", &server), ("place_url", &place_url), ("channel", channel),
+            ]),
+            None => i18n::tr(&self.0.config, Some(guild_id), "join-welcome-any-channel", &[
+                ("server", &server), ("place_url", &place_url),
+            ]),
+        })?;
+        if let Err(e) = user_id.create_dm_channel().and_then(|x| x.say(&message)) {
+            debug!("Could not send a welcome message to {}: {}", user_id, e);
+        }
+        Ok(())
+    }
     fn intro_message(&self, guild_id: GuildId) -> Result<String> {
         let verify_intro =
             self.0.config.get(Some(guild_id), ConfigKeys::VerificationChannelIntro)?;
@@ -130,7 +218,7 @@ impl VerificationChannelManager {
 
         Ok(format!("{}{}{}{}{}",
                    verify_intro.unwrap_or_else(|| String::new()), intro_space,
-                   self.verify_instructions()?,
+                   self.verify_instructions(Some(guild_id))?,
                    footer_space, verify_footer.unwrap_or_else(|| String::new())))
     }
     pub fn setup_check(&self, _: GuildId, channel_id: ChannelId) -> Result<()> {
@@ -189,10 +277,47 @@ impl VerificationChannelManager {
         Ok(())
     }
 
+    fn get_verify_panel(
+        database: &Database, guild_id: GuildId
+    ) -> Result<Option<(ChannelId, MessageId)>> {
+        database.connect()?.query(
+            "SELECT discord_channel_id, panel_message_id FROM verify_panel_info \
+             WHERE discord_guild_id = ?1", guild_id
+        ).get_opt::<(ChannelId, MessageId)>()
+    }
+    pub fn is_verify_panel(&self, guild_id: GuildId, message_id: MessageId) -> Result<bool> {
+        Ok(self.0.panel_cache.read(&guild_id)?.map(|x| x.1) == Some(message_id))
+    }
+    pub fn setup_verify_panel(&self, guild_id: GuildId, channel_id: ChannelId) -> Result<()> {
+        if let Some((old_channel, old_message)) = *self.0.panel_cache.read(&guild_id)? {
+            old_channel.delete_message(old_message).map_err(Error::from).drop_nonfatal()?;
+        }
+
+        let theme = EmbedTheme::load(&self.0.config, Some(guild_id))?;
+        let message = channel_id.send_message(|m| m.embed(|e| theme.apply(e
+            .title("Verify your Roblox account")
+            .description(format!("React with {} below to verify your Roblox account. The bot \
+                                  will send you instructions in direct messages.",
+                                 VERIFY_PANEL_EMOJI))
+            .colour(VERIFY_PANEL_COLOUR), None)
+        ))?;
+        message.react(ReactionType::Unicode(VERIFY_PANEL_EMOJI.to_owned()))?;
+
+        self.0.database.connect()?.execute(
+            "REPLACE INTO verify_panel_info (\
+                 discord_guild_id, discord_channel_id, panel_message_id\
+             ) VALUES (?1, ?2, ?3)", (guild_id, channel_id, message.id),
+        )?;
+        *self.0.panel_cache.write(&guild_id)? = Some((channel_id, message.id));
+        Ok(())
+    }
+
     pub fn on_cleanup_tick(&self) {
         self.0.channel_cache.shrink_to_fit();
+        self.0.panel_cache.shrink_to_fit();
     }
     pub fn on_guild_remove(&self, guild: GuildId) {
         self.0.channel_cache.remove(&guild);
+        self.0.panel_cache.remove(&guild);
     }
 }
\ No newline at end of file
@@ -1,50 +1,91 @@
 use constant_time_eq::constant_time_eq;
+use core::audit::*;
+use core::blacklist::BlacklistManager;
 use core::config::*;
+use core::event_log::*;
+use core::key_encryption::{KeyEncryption, is_sealed, open_stored_key};
+use core::name_policy::*;
+use core::stats::*;
+use core::usernames::UsernameManager;
+use core::webhooks::WebhookManager;
 use database::*;
 use errors::*;
 use hmac::{Hmac, Mac};
-use parking_lot::RwLock;
+use logger;
+use parking_lot::{Mutex, RwLock};
 use rand::{Rng, OsRng};
 use roblox::*;
+use serde_json;
 use serenity::model::prelude::*;
 use sha2::Sha256;
+use std::cmp::{max, min};
+use std::collections::{HashMap, HashSet};
 use std::fmt::{Display, Formatter, Write, Result as FmtResult};
 use std::sync::Arc;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
-use util::MultiMutex;
+use util;
+use util::{Clock, MultiMutex, InFlightCounter, LruCache, LruCacheMetrics};
+use web;
 
 const TOKEN_CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ";
-const TOKEN_VERSION: u32 = 1;
+const TOKEN_CHARS_V2: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const MIN_TOKEN_LENGTH: usize = 6;
+const MAX_TOKEN_LENGTH: usize = 12;
 const HISTORY_COUNT: u32 = 5;
 
+// How long tokens using an older format are still accepted after the token format is changed, so
+// the verification place can be updated without interrupting verification.
+const TOKEN_MIGRATION_WINDOW_SECS: u64 = 60 * 60 * 24 * 7;
+
+const PROFILE_PHRASE_INCREMENT: u64 = 60 * 60 * 24;
+const PROFILE_PHRASE_LENGTH: usize = 5;
+const PROFILE_PHRASE_WORDS: &[&str] = &[
+    "apple", "badger", "candle", "desert", "engine", "forest", "garden", "harbor",
+    "island", "jacket", "kettle", "lantern", "meadow", "needle", "orange", "pepper",
+    "quartz", "rabbit", "saddle", "tunnel", "umbrella", "valley", "walnut", "yellow",
+    "anchor", "bridge", "castle", "dragon", "ember", "falcon", "glacier", "hammer",
+    "igloo", "jungle", "koala", "lemon", "marble", "nectar", "otter", "pillow",
+    "quiver", "river", "silver", "tiger", "velvet", "window", "zebra", "acorn",
+    "breeze", "cactus", "dolphin", "feather", "ginger", "honey", "iris", "jelly",
+    "kitten", "ladder", "mango", "nutmeg", "olive", "pebble", "rocket", "sunset",
+];
+
+const FRIEND_CLAIM_SECONDS: u64 = 60 * 10;
+const PLACE_CODE_SECONDS: u64 = 60 * 10;
+// When `require_issued_tokens` is set, the verification place records the tokens it shows each
+// player in this data store, as a JSON array stored under their user ID.
+const ISSUED_TOKENS_DATASTORE: &str = "SylphieIssuedTokens";
+const PLACE_CODE_LENGTH: usize = 6;
+// How long a link that opens the verification place for one Roblox account can be used for.
+const DEEP_LINK_SECONDS: u64 = 60 * 10;
+const DEEP_LINK_VERSION: u32 = 1;
+const MAX_OAUTH_CODE_LENGTH: usize = 512;
+
 // TODO: Add caching to this module. Extensive caching.
 
-#[derive(Clone, Hash, Debug, PartialOrd, Ord)]
-struct Token([u8; 6]);
-impl Token {
-    fn from_arr(arr: [u8; 6]) -> Token {
-        Token(arr)
+fn token_checksum(alphabet: &[u8], chars: &[u8]) -> u8 {
+    let mut accum = 0;
+    for (i, &c) in chars.iter().enumerate() {
+        let value = alphabet.iter().position(|&x| x == c).unwrap_or(0);
+        accum += (2 * i + 1) * value;
     }
+    alphabet[accum % alphabet.len()]
+}
 
+// Tokens are parsed without knowing which format they are in, so only their characters are
+// checked here. `TokenFormat` checks the rest.
+#[derive(Clone, Hash, Debug, PartialOrd, Ord)]
+struct Token(Vec<u8>);
+impl Token {
     fn from_str(token: &str) -> Result<Token> {
-        let token = token.as_bytes();
-        cmd_ensure!(token.len() == 6,
-                    "Verification token must be exactly 6 characters. Please check the code \
-                     you entered and try again");
-
-        let mut chars = [0u8; 6];
-        for i in 0..6 {
-            let byte = token[i];
-            if byte >= b'A' && byte <= b'Z' {
-                chars[i] = byte
-            } else if byte >= b'a' && byte <= b'z' {
-                chars[i] = byte - b'a' + b'A'
-            } else {
-                cmd_error!("Verification tokens may only contain letters. Please check the code \
-                            you entered and try again.")
-            }
-        }
-        Ok(Token(chars))
+        let token = token.trim().to_uppercase().into_bytes();
+        cmd_ensure!(token.iter().all(|&x| (x >= b'A' && x <= b'Z') || (x >= b'0' && x <= b'9')),
+                    "Verification codes may only contain letters and numbers. Please check the \
+                     code you entered and try again.");
+        cmd_ensure!(token.len() >= MIN_TOKEN_LENGTH && token.len() <= MAX_TOKEN_LENGTH + 1,
+                    "That is not a valid verification code. Please check the code you entered \
+                     and try again.");
+        Ok(Token(token))
     }
 }
 impl PartialEq for Token {
@@ -62,46 +103,139 @@ impl Display for Token {
     }
 }
 
+// Checks whether a word is a verification code in the current format, so messages containing
+// one can be found without trying to verify with it.
+pub(in ::core) fn is_token(config: &ConfigManager, word: &str) -> Result<bool> {
+    Ok(match Token::from_str(word) {
+        Ok(token) => TokenFormat::from_config(config)?.matches(&token),
+        Err(_) => false,
+    })
+}
+
+// The format of the tokens made with a key. Version 1 tokens are letters by default. Version 2
+// tokens use an alphabet without easily confused characters by default, and are followed by a
+// checksum character. The length and alphabet can be changed for deployments that want tokens
+// that are harder to guess.
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct TokenFormat {
+    version: u32, length: usize, alphabet: Vec<u8>,
+}
+impl TokenFormat {
+    fn new(version: u32, length: usize, alphabet: Option<&str>) -> TokenFormat {
+        let alphabet = match alphabet {
+            Some(alphabet) => alphabet.as_bytes().to_vec(),
+            None if version == 1 => TOKEN_CHARS.to_vec(),
+            None => TOKEN_CHARS_V2.to_vec(),
+        };
+        TokenFormat { version, length, alphabet }
+    }
+    fn from_config(config: &ConfigManager) -> Result<TokenFormat> {
+        Ok(TokenFormat::new(config.get(None, ConfigKeys::TokenFormatVersion)?,
+                            config.get(None, ConfigKeys::TokenLength)? as usize,
+                            config.get(None, ConfigKeys::TokenAlphabet)?.as_ref()
+                                .map(|x| x.as_str())))
+    }
+
+    fn alphabet_str(&self) -> &str {
+        ::std::str::from_utf8(&self.alphabet).unwrap_or("")
+    }
+    fn token_length(&self) -> usize {
+        if self.version == 1 { self.length } else { self.length + 1 }
+    }
+    fn matches(&self, token: &Token) -> bool {
+        token.0.len() == self.token_length() &&
+            token.0.iter().all(|x| self.alphabet.contains(x)) &&
+            (self.version == 1 ||
+             token_checksum(&self.alphabet, &token.0[..self.length]) == token.0[self.length])
+    }
+    // Explains why a token of the right length is not valid in this format.
+    fn check(&self, token: &Token) -> Result<()> {
+        for &c in &token.0 {
+            if !self.alphabet.contains(&c) {
+                if self.alphabet == TOKEN_CHARS_V2 {
+                    cmd_ensure!(!b"IO01".contains(&c),
+                                "Verification codes never contain the letters I or O, or the \
+                                 numbers 0 or 1. Did you mistype it? Please check the code you \
+                                 entered and try again.");
+                }
+                cmd_error!("That verification code contains a character it never contains. Did \
+                            you mistype it? Please check the code you entered and try again.")
+            }
+        }
+        cmd_ensure!(self.matches(token),
+                    "That verification code is not valid. Did you mistype it? Please check the \
+                     code you entered and try again.");
+        Ok(())
+    }
+}
+
 struct TokenParameters {
-    id: u64, key: Vec<u8>, time_increment: u32, version: u32,
+    id: u64, key: Vec<u8>, time_increment: u32, format: TokenFormat, created: SystemTime,
 }
 impl TokenParameters {
     fn add_config<'a>(&self, config: &mut Vec<LuaConfigEntry<'a>>) {
         config.push(LuaConfigEntry::new("shared_key", true, self.key.clone()));
         config.push(LuaConfigEntry::new("time_increment", false, self.time_increment));
+        config.push(LuaConfigEntry::new("token_version", false, self.format.version));
+        config.push(LuaConfigEntry::new("token_length", false, self.format.length as u32));
+        config.push(LuaConfigEntry::new("token_alphabet", false,
+                                        self.format.alphabet_str().to_owned()));
     }
 
-    fn sha256_token(&self, data: &str) -> Token {
+    fn hmac(&self, data: &str) -> Vec<u8> {
         let mut mac = Hmac::<Sha256>::new(&self.key).unwrap();
         mac.input(data.as_bytes());
-        let result = mac.result();
-        let code = result.code();
+        mac.result().code().to_vec()
+    }
 
-        let mut accum = 0;
-        for i in 0..6 {
-            accum *= 256;
-            accum += code[i] as u64;
-        }
+    // Each block of up to 6 characters is made from the next 6 bytes of the HMAC, so tokens of
+    // the default length are the same as before the length could be changed.
+    fn sha256_token(&self, data: &str) -> Token {
+        let code = self.hmac(data);
+        let alphabet = &self.format.alphabet;
 
-        let mut chars = [0u8; 6];
-        for i in 0..6 {
-            chars[i] = TOKEN_CHARS[(accum % TOKEN_CHARS.len() as u64) as usize];
-            accum /= TOKEN_CHARS.len() as u64;
+        let mut chars = Vec::new();
+        for block in code.chunks(6).take((self.format.length + 5) / 6) {
+            let mut accum = 0;
+            for &b in block {
+                accum *= 256;
+                accum += b as u64;
+            }
+            for _ in 0..min(6, self.format.length - chars.len()) {
+                chars.push(alphabet[(accum % alphabet.len() as u64) as usize]);
+                accum /= alphabet.len() as u64;
+            }
+        }
+        if self.format.version != 1 {
+            let checksum = token_checksum(alphabet, &chars);
+            chars.push(checksum);
         }
-        Token::from_arr(chars)
+        Token(chars)
     }
 
-    fn current_epoch(&self) -> Result<i64> {
-        let unix_time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    fn current_epoch(&self, now: SystemTime) -> Result<i64> {
+        let unix_time = now.duration_since(UNIX_EPOCH)?.as_secs();
         Ok((unix_time / self.time_increment as u64) as i64)
     }
 
+    // Returns how long until the current epoch ends, and tokens shown in the place change.
+    fn epoch_time_left(&self, now: SystemTime) -> Result<Duration> {
+        let unix_time = now.duration_since(UNIX_EPOCH)?.as_secs();
+        let increment = self.time_increment as u64;
+        Ok(Duration::from_secs(increment - unix_time % increment))
+    }
+
     fn make_token(&self, user_id: u64, epoch: i64) -> Result<Token> {
-        Ok(self.sha256_token(&format!("{}|{}|{}", TOKEN_VERSION, user_id, epoch)))
+        Ok(self.sha256_token(&format!("{}|{}|{}", self.format.version, user_id, epoch)))
     }
 
-    fn check_token(&self, user: RobloxUserID, token: &Token) -> Result<Option<i64>> {
-        let epoch = self.current_epoch()?;
+    fn check_token(
+        &self, user: RobloxUserID, token: &Token, now: SystemTime,
+    ) -> Result<Option<i64>> {
+        if !self.format.matches(token) {
+            return Ok(None)
+        }
+        let epoch = self.current_epoch(now)?;
         for i in &[1, 0, -1] {
             if token == &self.make_token(user.0, epoch + i)? {
                 return Ok(Some(epoch + i))
@@ -109,31 +243,97 @@ impl TokenParameters {
         }
         Ok(None)
     }
+
+    fn profile_epoch(&self, now: SystemTime) -> Result<i64> {
+        let unix_time = now.duration_since(UNIX_EPOCH)?.as_secs();
+        Ok((unix_time / PROFILE_PHRASE_INCREMENT) as i64)
+    }
+    // Makes the launch data for a link that opens the verification place for one Roblox account.
+    // The place checks the signature with the shared key, so it can show the token flow to that
+    // account only.
+    fn make_launch_data(&self, roblox_id: RobloxUserID, expires: i64, nonce: &str) -> String {
+        let signature: String = self.hmac(&format!("{}|launch|{}|{}|{}", DEEP_LINK_VERSION,
+                                                   roblox_id.0, expires, nonce))
+            .iter().map(|x| format!("{:02x}", x)).collect();
+        format!("{}.{}.{}.{}.{}", DEEP_LINK_VERSION, roblox_id.0, expires, nonce, signature)
+    }
+    fn make_profile_phrase(
+        &self, discord_id: UserId, roblox_id: RobloxUserID, epoch: i64,
+    ) -> String {
+        let code = self.hmac(&format!("{}|profile|{}|{}|{}",
+                                      self.format.version, discord_id.0, roblox_id.0, epoch));
+        let words: Vec<&str> = code[..PROFILE_PHRASE_LENGTH].iter()
+            .map(|&b| PROFILE_PHRASE_WORDS[b as usize % PROFILE_PHRASE_WORDS.len()])
+            .collect();
+        words.join(" ")
+    }
 }
 impl FromSqlRow for TokenParameters {
     fn from_sql_row(row: Row) -> Result<Self> {
         let (
-            id, key, time_increment, version
-        ): (u64, Vec<u8>, u32, u32) = FromSqlRow::from_sql_row(row)?;
-        Ok(TokenParameters { id, key, time_increment, version })
+            id, key, time_increment, version, length, alphabet, created
+        ): (u64, Vec<u8>, u32, u32, u32, Option<String>, SystemTime) =
+            FromSqlRow::from_sql_row(row)?;
+        let format = TokenFormat::new(version, length as usize,
+                                      alphabet.as_ref().map(|x| x.as_str()));
+        Ok(TokenParameters { id, key, time_increment, format, created })
+    }
+}
+
+// Why the key a token was made with is no longer the current key.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
+pub enum RekeyReason {
+    TimeIncrementChanged, FormatChanged, KeyReplaced,
+}
+impl RekeyReason {
+    fn between(old: &TokenParameters, new: &TokenParameters) -> RekeyReason {
+        if old.time_increment != new.time_increment {
+            RekeyReason::TimeIncrementChanged
+        } else if old.format != new.format {
+            RekeyReason::FormatChanged
+        } else {
+            RekeyReason::KeyReplaced
+        }
+    }
+    pub fn describe(self) -> &'static str {
+        match self {
+            RekeyReason::TimeIncrementChanged => "the token validity time was changed",
+            RekeyReason::FormatChanged => "the token format was changed",
+            RekeyReason::KeyReplaced => "the shared key was replaced",
+        }
     }
 }
 
 #[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
 pub enum TokenStatus {
-    Verified { key_id: u64, epoch: i64 }, Outdated, NotVerified,
+    Verified { key_id: u64, epoch: i64 }, Outdated { key_id: u64, reason: RekeyReason },
+    NotVerified,
+}
+impl TokenStatus {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            TokenStatus::Verified { .. } => "verified",
+            TokenStatus::Outdated { .. } => "outdated",
+            TokenStatus::NotVerified => "not_verified",
+        }
+    }
 }
 
 struct TokenContext {
     current: TokenParameters, history: Vec<TokenParameters>
 }
 impl TokenContext {
-    fn from_db_internal(conn: &DatabaseConnection) -> Result<Option<TokenContext>> {
+    fn from_db_internal(
+        conn: &DatabaseConnection, encryption: Option<&KeyEncryption>,
+    ) -> Result<Option<TokenContext>> {
         let mut results = conn.query(
-            "SELECT id, key, time_increment, version FROM verification_keys \
-             ORDER BY id DESC LIMIT ?1",
+            "SELECT id, key, time_increment, version, token_length, token_alphabet, created \
+             FROM verification_keys WHERE revoked_at IS NULL ORDER BY id DESC LIMIT ?1",
             1 + HISTORY_COUNT,
         ).get_all::<TokenParameters>()?;
+        for params in &mut results {
+            params.key = open_stored_key(encryption, &params.key)?;
+        }
         if results.is_empty() {
             Ok(None)
         } else {
@@ -141,7 +341,29 @@ impl TokenContext {
             Ok(Some(TokenContext { current: results.pop().unwrap(), history }))
         }
     }
-    fn new_in_db(conn: &DatabaseConnection, time_increment: u32) -> Result<TokenContext> {
+    // Encrypts any keys that were stored before encryption was enabled.
+    fn seal_stored_keys(conn: &DatabaseConnection, encryption: &KeyEncryption) -> Result<()> {
+        let keys = conn.query(
+            "SELECT id, key FROM verification_keys", (),
+        ).get_all::<(u64, Vec<u8>)>()?;
+        let mut sealed = 0;
+        for (id, key) in keys {
+            if !is_sealed(&key) {
+                let key = open_stored_key(Some(encryption), &key)?;
+                conn.execute("UPDATE verification_keys SET key = ?2 WHERE id = ?1",
+                             (id, encryption.seal(&key)?))?;
+                sealed += 1;
+            }
+        }
+        if sealed != 0 {
+            info!("Encrypted {} verification keys stored in the database.", sealed);
+        }
+        Ok(())
+    }
+    fn new_in_db(
+        conn: &DatabaseConnection, time_increment: u32, format: &TokenFormat,
+        encryption: Option<&KeyEncryption>, clock: &Clock,
+    ) -> Result<TokenContext> {
         let mut rng = OsRng::new()?;
         let mut key = Vec::new();
         for _ in 0..16 {
@@ -152,114 +374,1471 @@ impl TokenContext {
             key.push((r >> 24) as u8);
         }
 
+        let key = match encryption {
+            Some(encryption) => encryption.seal(&key)?,
+            None => key,
+        };
         conn.execute(
-            "INSERT INTO verification_keys (key, time_increment, version) VALUES (?1, ?2, ?3)",
-            (key, time_increment, TOKEN_VERSION)
+            "INSERT INTO verification_keys \
+                 (key, time_increment, version, token_length, token_alphabet, created) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            (key, time_increment, format.version, format.length as u32,
+             format.alphabet_str().to_owned(), clock.now())
         )?;
-        Ok(TokenContext::from_db_internal(conn)??)
+        Ok(TokenContext::from_db_internal(conn, encryption)??)
+    }
+    fn rekey(
+        conn: &DatabaseConnection, time_increment: u32, format: &TokenFormat,
+        encryption: Option<&KeyEncryption>, clock: &Clock,
+    ) -> Result<TokenContext> {
+        info!("Regenerating token key.");
+        conn.transaction_immediate(|| {
+            TokenContext::new_in_db(conn, time_increment, format, encryption, clock)
+        })
     }
-    fn rekey(conn: &DatabaseConnection, time_increment: u32) -> Result<TokenContext> {
-        info!("Regenerating token key due to user request.");
+    // Replaces the key, and revokes every earlier key so tokens made with them are not accepted
+    // at all. Returns the new context and how many keys were revoked.
+    fn revoke_all(
+        conn: &DatabaseConnection, time_increment: u32, format: &TokenFormat,
+        encryption: Option<&KeyEncryption>, clock: &Clock,
+    ) -> Result<(TokenContext, usize)> {
+        warn!("Revoking all token keys.");
         conn.transaction_immediate(|| {
-            TokenContext::new_in_db(conn, time_increment)
+            let revoked = conn.execute(
+                "UPDATE verification_keys SET revoked_at = ?1 WHERE revoked_at IS NULL",
+                clock.now(),
+            )?;
+            Ok((TokenContext::new_in_db(conn, time_increment, format, encryption, clock)?,
+                revoked as usize))
         })
     }
-    fn from_db(conn: &DatabaseConnection, time_increment: u32) -> Result<TokenContext> {
+    fn from_db(
+        conn: &DatabaseConnection, time_increment: u32, format: &TokenFormat,
+        encryption: Option<&KeyEncryption>, clock: &Clock,
+    ) -> Result<TokenContext> {
         conn.transaction_immediate(|| {
-            match TokenContext::from_db_internal(conn)? {
+            if let Some(encryption) = encryption {
+                TokenContext::seal_stored_keys(conn, encryption)?;
+            }
+            match TokenContext::from_db_internal(conn, encryption)? {
                 Some(x) => {
                     if x.current.time_increment != time_increment {
                         info!("Token key in database has a different time increment, \
                                regenerating...");
-                        TokenContext::new_in_db(conn, time_increment)
-                    } else if x.current.version != TOKEN_VERSION {
-                        info!("Token key in database is for an older version, \
+                        TokenContext::new_in_db(conn, time_increment, format, encryption, clock)
+                    } else if &x.current.format != format {
+                        info!("Token key in database is for a different token format, \
                                regenerating...");
-                        TokenContext::new_in_db(conn, time_increment)
+                        TokenContext::new_in_db(conn, time_increment, format, encryption, clock)
                     } else {
                         Ok(x)
                     }
                 },
                 None => {
                     info!("No token keys in database, generating new key...");
-                    TokenContext::new_in_db(conn, time_increment)
+                    TokenContext::new_in_db(conn, time_increment, format, encryption, clock)
                 },
             }
         })
     }
 
-    fn check_token(&self, user: RobloxUserID, token: &str) -> Result<TokenStatus> {
+    fn check_token(
+        &self, user: RobloxUserID, token: &str, now: SystemTime,
+    ) -> Result<TokenStatus> {
         let token = Token::from_str(token)?;
-        if let Some(epoch) = self.current.check_token(user, &token)? {
+        let format = &self.current.format;
+        if token.0.len() == format.token_length() {
+            format.check(&token)?;
+        } else if !self.history.iter().any(|x| x.format.matches(&token)) {
+            cmd_error!("Verification codes must be exactly {} characters. Please check the code \
+                        you entered and try again.", format.token_length())
+        }
+        if let Some(epoch) = self.current.check_token(user, &token, now)? {
             return Ok(TokenStatus::Verified { key_id: self.current.id, epoch })
         }
+        let migration_ends =
+            self.current.created + Duration::from_secs(TOKEN_MIGRATION_WINDOW_SECS);
         for param in &self.history {
-            if param.check_token(user, &token)?.is_some() {
-                return Ok(TokenStatus::Outdated)
+            if let Some(epoch) = param.check_token(user, &token, now)? {
+                if param.format != self.current.format && now < migration_ends {
+                    return Ok(TokenStatus::Verified { key_id: param.id, epoch })
+                }
+                return Ok(TokenStatus::Outdated {
+                    key_id: param.id, reason: RekeyReason::between(param, &self.current),
+                })
+            }
+        }
+        Ok(TokenStatus::NotVerified)
+    }
+}
+
+pub struct MethodContext<'a> {
+    data: &'a VerifierData, token_ctx: &'a TokenContext, now: SystemTime,
+    pub discord_id: UserId, pub roblox_id: RobloxUserID,
+}
+impl <'a> MethodContext<'a> {
+    fn current_status(&self) -> Result<TokenStatus> {
+        let current = &self.token_ctx.current;
+        Ok(TokenStatus::Verified { key_id: current.id, epoch: current.current_epoch(self.now)? })
+    }
+}
+
+pub trait VerificationMethod: Sync {
+    fn name(&self) -> &'static str;
+    fn enabled_key(&self) -> ConfigKey<bool>;
+    // Checks that the data is well formed before a verification attempt is counted.
+    fn validate(&self, _: &str) -> Result<()> {
+        Ok(())
+    }
+    fn check(&self, ctx: &MethodContext, data: &str) -> Result<TokenStatus>;
+}
+
+// Verifies users with the time-based token displayed in the verification place.
+pub struct TokenVerification;
+impl VerificationMethod for TokenVerification {
+    fn name(&self) -> &'static str {
+        "token"
+    }
+    fn enabled_key(&self) -> ConfigKey<bool> {
+        ConfigKeys::VerificationMethodToken
+    }
+    fn validate(&self, data: &str) -> Result<()> {
+        Token::from_str(data)?;
+        Ok(())
+    }
+    fn check(&self, ctx: &MethodContext, data: &str) -> Result<TokenStatus> {
+        let status = ctx.token_ctx.check_token(ctx.roblox_id, data, ctx.now)?;
+        if let TokenStatus::Verified { .. } = status {
+            if ctx.data.config.get(None, ConfigKeys::RequireIssuedTokens)? &&
+               !was_token_issued(ctx, data)? {
+                return Ok(TokenStatus::NotVerified)
+            }
+        }
+        Ok(status)
+    }
+}
+
+// Checks that the verification place actually showed a token to the account it is for, so a
+// token computed outside of the place is not accepted.
+fn was_token_issued(ctx: &MethodContext, data: &str) -> Result<bool> {
+    let api_key = ctx.data.config.get(None, ConfigKeys::OpenCloudApiKey)?;
+    let universe_id = ctx.data.config.get(None, ConfigKeys::PlaceUniverseID)?;
+    let (api_key, universe_id) = match (api_key, universe_id) {
+        (Some(api_key), Some(universe_id)) => (api_key, universe_id),
+        _ => cmd_error!("The open_cloud_api_key and universe_id settings must be set to check \
+                         tokens issued by the verification place. Please ask the bot owner to \
+                         fix this."),
+    };
+    let json = match get_datastore_entry(&api_key, universe_id, ISSUED_TOKENS_DATASTORE,
+                                         &ctx.roblox_id.0.to_string())? {
+        Some(json) => json,
+        None => return Ok(false),
+    };
+    let token = Token::from_str(data)?;
+    let issued = serde_json::from_str::<Vec<String>>(&json)?;
+    Ok(issued.iter().any(|x| Token::from_str(x).ok().as_ref() == Some(&token)))
+}
+
+// Verifies users with a phrase they place in the About section of their Roblox profile.
+pub struct ProfileVerification;
+impl VerificationMethod for ProfileVerification {
+    fn name(&self) -> &'static str {
+        "profile"
+    }
+    fn enabled_key(&self) -> ConfigKey<bool> {
+        ConfigKeys::VerificationMethodProfile
+    }
+    fn check(&self, ctx: &MethodContext, _: &str) -> Result<TokenStatus> {
+        let description = match ctx.roblox_id.profile_description()? {
+            Some(description) => description,
+            None => return Ok(TokenStatus::NotVerified),
+        };
+        let current = &ctx.token_ctx.current;
+        let epoch = current.profile_epoch(ctx.now)?;
+        for i in &[0, -1] {
+            let phrase = current.make_profile_phrase(ctx.discord_id, ctx.roblox_id, epoch + i);
+            if description.contains(&phrase) {
+                return ctx.current_status()
             }
         }
         Ok(TokenStatus::NotVerified)
     }
 }
 
+// Verifies users who signed in to both their Discord and Roblox accounts through OAuth on the
+// web server. The web server confirms both accounts before the attempt is made, and passes the
+// ID of the Roblox account that signed in as the data, so this method must never be used from
+// commands. The name the attempt was made with may have changed owners since, so the account it
+// resolves to must be the one that signed in.
+pub struct OAuthVerification;
+impl VerificationMethod for OAuthVerification {
+    fn name(&self) -> &'static str {
+        "oauth"
+    }
+    fn enabled_key(&self) -> ConfigKey<bool> {
+        ConfigKeys::VerificationMethodOAuth
+    }
+    fn validate(&self, data: &str) -> Result<()> {
+        data.parse::<u64>().ok().to_cmd_err(|| "The Roblox account that signed in is unknown.")?;
+        Ok(())
+    }
+    fn check(&self, ctx: &MethodContext, data: &str) -> Result<TokenStatus> {
+        if RobloxUserID(data.parse()?) == ctx.roblox_id {
+            ctx.current_status()
+        } else {
+            Ok(TokenStatus::NotVerified)
+        }
+    }
+}
+
+fn roblox_oauth_app(config: &ConfigManager) -> Result<(String, String)> {
+    let client_id = config.get(None, ConfigKeys::RobloxOAuthClientId)?;
+    let client_secret = config.get(None, ConfigKeys::RobloxOAuthClientSecret)?;
+    match (client_id, client_secret) {
+        (Some(client_id), Some(client_secret)) => Ok((client_id, client_secret)),
+        _ => cmd_error!("Signing in with Roblox is not set up. Please ask the bot owner to fix \
+                         this."),
+    }
+}
+fn roblox_oauth_redirect_uri(config: &ConfigManager) -> Result<String> {
+    if let Some(uri) = config.get(None, ConfigKeys::RobloxOAuthRedirectUri)? {
+        return Ok(uri)
+    }
+    match config.get(None, ConfigKeys::WebPublicUrl)? {
+        Some(url) => Ok(format!("{}{}", url, web::CODE_PAGE_PATH)),
+        None => cmd_error!("No Roblox sign in redirect URI is set. Please ask the bot owner to \
+                            fix this."),
+    }
+}
+
+// Verifies users with the code from signing in to their Roblox account through Roblox's OAuth
+// flow. The code is exchanged for the account that signed in, which must be the one claimed.
+pub struct RobloxOAuthVerification;
+impl VerificationMethod for RobloxOAuthVerification {
+    fn name(&self) -> &'static str {
+        "roblox_oauth"
+    }
+    fn enabled_key(&self) -> ConfigKey<bool> {
+        ConfigKeys::VerificationMethodRobloxOAuth
+    }
+    fn validate(&self, data: &str) -> Result<()> {
+        cmd_ensure!(!data.is_empty() && data.len() <= MAX_OAUTH_CODE_LENGTH &&
+                    data.chars().all(|c| c.is_ascii_alphanumeric() || "-._~".contains(c)),
+                    "That is not a valid sign in code.");
+        Ok(())
+    }
+    fn check(&self, ctx: &MethodContext, data: &str) -> Result<TokenStatus> {
+        let (client_id, client_secret) = roblox_oauth_app(&ctx.data.config)?;
+        let app = RobloxOAuthApp { client_id: &client_id, client_secret: &client_secret };
+        let redirect_uri = roblox_oauth_redirect_uri(&ctx.data.config)?;
+        let (roblox_id, _) = app.sign_in(data, &redirect_uri)?;
+        if roblox_id == ctx.roblox_id {
+            ctx.current_status()
+        } else {
+            Ok(TokenStatus::NotVerified)
+        }
+    }
+}
+
+// A Discord user waiting for a friend request from the Roblox account they claimed. Claims are
+// confirmed by `Verifier::match_friend_requests` when the request arrives.
+#[derive(Copy, Clone, Debug)]
+pub struct FriendClaim {
+    pub roblox_id: RobloxUserID, pub guild_id: GuildId, pub channel_id: ChannelId,
+    pub claimed_at: SystemTime, confirmed: bool,
+}
+impl FriendClaim {
+    fn is_live(&self, now: SystemTime) -> bool {
+        now < self.claimed_at + Duration::from_secs(FRIEND_CLAIM_SECONDS)
+    }
+}
+
+// Verifies users by checking that the bot's Roblox account received a friend request from the
+// claimed account after the claim was made. Friend requests are checked by a background task,
+// which then makes the verification attempt, so this method is not used by commands directly.
+pub struct FriendVerification;
+impl VerificationMethod for FriendVerification {
+    fn name(&self) -> &'static str {
+        "friend"
+    }
+    fn enabled_key(&self) -> ConfigKey<bool> {
+        ConfigKeys::VerificationMethodFriend
+    }
+    fn check(&self, ctx: &MethodContext, _: &str) -> Result<TokenStatus> {
+        let mut claims = ctx.data.friend_claims.lock();
+        let confirmed = match claims.get(&ctx.discord_id) {
+            Some(claim) => claim.confirmed && claim.roblox_id == ctx.roblox_id,
+            None => false,
+        };
+        if confirmed {
+            claims.remove(&ctx.discord_id);
+            ctx.current_status()
+        } else {
+            Ok(TokenStatus::NotVerified)
+        }
+    }
+}
+
+// A code given to a Discord user to enter in the verification place. The place sends the code to
+// the web server along with the Roblox account that entered it, which records it here.
+#[derive(Copy, Clone, Debug)]
+pub struct PlaceCodeClaim {
+    pub discord_id: UserId, pub guild_id: GuildId, pub channel_id: ChannelId,
+    pub claimed_at: SystemTime, pub roblox_id: Option<RobloxUserID>,
+}
+impl PlaceCodeClaim {
+    fn is_live(&self, now: SystemTime) -> bool {
+        now < self.claimed_at + Duration::from_secs(PLACE_CODE_SECONDS)
+    }
+}
+
+// Verifies users by checking that the claimed account entered the user's code in the
+// verification place. Like `FriendVerification`, the attempt is made by a background task once
+// the place reports the code, and the code itself is passed as the method data.
+pub struct PlaceCodeVerification;
+impl VerificationMethod for PlaceCodeVerification {
+    fn name(&self) -> &'static str {
+        "place_code"
+    }
+    fn enabled_key(&self) -> ConfigKey<bool> {
+        ConfigKeys::VerificationMethodPlaceCode
+    }
+    fn check(&self, ctx: &MethodContext, code: &str) -> Result<TokenStatus> {
+        let mut codes = ctx.data.place_codes.lock();
+        let entered = match codes.get(code) {
+            Some(claim) => claim.discord_id == ctx.discord_id &&
+                           claim.roblox_id == Some(ctx.roblox_id) &&
+                           claim.is_live(ctx.now),
+            None => false,
+        };
+        if entered {
+            codes.remove(code);
+            ctx.current_status()
+        } else {
+            Ok(TokenStatus::NotVerified)
+        }
+    }
+}
+
+// A record of the data removed for a user by `purge_user`. It does not contain any of the
+// removed data, only how much of it was removed.
+#[derive(Clone, Debug)]
+pub struct DeletionReceipt {
+    pub receipt_id: String, pub discord_id: UserId, pub purged_at: SystemTime,
+    pub removed: Vec<(&'static str, usize)>,
+}
+impl DeletionReceipt {
+    pub fn total_removed(&self) -> usize {
+        self.removed.iter().map(|x| x.1).sum()
+    }
+}
+
+// Every table containing data about a Discord user, with the condition selecting that data.
+const PURGED_TABLES: &[(&str, &str)] = &[
+    ("discord_user_info", "discord_user_id = ?1"),
+    ("discord_user_alt_accounts", "discord_user_id = ?1"),
+    ("discord_user_unlinked_accounts", "discord_user_id = ?1"),
+    ("verification_cooldown", "discord_user_id = ?1"),
+    ("verification_attempts", "discord_user_id = ?1"),
+    ("roles_last_updated", "discord_user_id = ?1"),
+    ("pending_verifications", "discord_user_id = ?1"),
+    ("unverified_enforcement", "discord_user_id = ?1"),
+    ("verification_reminders", "discord_user_id = ?1"),
+    ("verification_reminder_opt_outs", "discord_user_id = ?1"),
+    ("guest_passes", "discord_user_id = ?1"),
+    ("suspended_verifications", "discord_user_id = ?1"),
+    ("command_permission_grants", "NOT is_role AND target_id = ?1"),
+    // Requests to purge the user name them in their argument.
+    ("pending_admin_actions", "action = 'purge_user' AND argument = CAST(?1 AS TEXT)"),
+];
+// Bans stay on the Roblox accounts a banned user was linked to, so purging them does not let
+// those accounts verify in the server again. Only the Discord ID is removed from them.
+const PURGED_BAN_DISCORD_ID: u64 = 0;
+
 #[derive(Copy, Clone, Debug)]
+pub struct CooldownInfo {
+    pub attempt_count: u32, pub max_attempts: u32, pub last_attempt: SystemTime,
+    pub cooldown_ends: Option<SystemTime>,
+}
+
+#[derive(Clone, Debug)]
+pub struct VerificationAttempt {
+    pub roblox_id: RobloxUserID, pub guild_id: Option<GuildId>, pub method: Option<String>,
+    pub result: Option<String>, pub token_status: Option<String>, pub attempted_at: SystemTime,
+}
+
+// A Discord account that has tried to verify as a Roblox account another Discord account is
+// verified as or has tried to verify as.
+#[derive(Copy, Clone, Debug)]
+pub struct AltAccountMatch {
+    pub discord_id: UserId, pub roblox_id: RobloxUserID, pub attempts: u32,
+    pub proved_control: bool, pub last_attempt: Option<SystemTime>, pub is_linked: bool,
+}
+
+#[derive(Clone, Debug)]
 pub enum VerifyResult {
     VerificationOk, TokenAlreadyUsed, VerificationPlaceOutdated, InvalidToken,
     TooManyAttempts { max_attempts: u32, cooldown: u64, cooldown_ends: SystemTime },
     SenderVerifiedAs { other_roblox_id: RobloxUserID },
     RobloxAccountVerifiedTo { other_discord_id: UserId },
-    ReverifyOnCooldown { cooldown: u64, cooldown_ends: SystemTime }
+    ReverifyOnCooldown { cooldown: u64, cooldown_ends: SystemTime },
+    AccountTooNew { minimum_days: u32, allowed_at: SystemTime },
+    NotInRequiredGroup { group_id: u64, minimum_rank: Option<u32> },
+    Blacklisted { message: String },
+    NameNotAllowed { name: String },
+    TooManyLinkedAccounts { max_accounts: u32 },
+}
+impl VerifyResult {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            VerifyResult::VerificationOk => "ok",
+            VerifyResult::TokenAlreadyUsed => "token_already_used",
+            VerifyResult::VerificationPlaceOutdated => "place_outdated",
+            VerifyResult::InvalidToken => "invalid_token",
+            VerifyResult::TooManyAttempts { .. } => "too_many_attempts",
+            VerifyResult::SenderVerifiedAs { .. } => "already_verified",
+            VerifyResult::RobloxAccountVerifiedTo { .. } => "roblox_account_taken",
+            VerifyResult::ReverifyOnCooldown { .. } => "reverify_cooldown",
+            VerifyResult::AccountTooNew { .. } => "account_too_new",
+            VerifyResult::NotInRequiredGroup { .. } => "not_in_required_group",
+            VerifyResult::Blacklisted { .. } => "blacklisted",
+            VerifyResult::NameNotAllowed { .. } => "name_not_allowed",
+            VerifyResult::TooManyLinkedAccounts { .. } => "too_many_linked_accounts",
+        }
+    }
+}
+
+// The number of Discord users whose verified Roblox account is kept in memory.
+const VERIFIED_CACHE_SIZE: usize = 10000;
+// How often the bot owner and each server are told that someone used an outdated place.
+const OUTDATED_ALERT_INTERVAL_SECS: u64 = 60 * 60;
+
+#[derive(Clone, Debug)]
+pub struct KeyStatus {
+    pub key_id: u64, pub version: u32, pub token_length: usize, pub time_increment: u32,
+    pub created: SystemTime, pub previous_keys: Vec<u64>,
 }
 
 struct VerifierData {
-    config: ConfigManager, database: Database, token_ctx: RwLock<TokenContext>,
+    config: ConfigManager, database: Database, audit: AuditLog, webhooks: WebhookManager,
+    blacklist: BlacklistManager, stats: StatsManager, usernames: UsernameManager,
+    event_log: EventLog, clock: Arc<Clock>, token_ctx: RwLock<TokenContext>,
+    key_encryption: Option<KeyEncryption>,
     discord_lock: MultiMutex<UserId>, roblox_lock: MultiMutex<RobloxUserID>,
+    friend_claims: Mutex<HashMap<UserId, FriendClaim>>,
+    place_codes: Mutex<HashMap<String, PlaceCodeClaim>>,
+    in_flight: InFlightCounter, verified_cache: LruCache<UserId, Option<RobloxUserID>>,
+    drift_warned_epoch: Mutex<i64>,
+    outdated_alerts: Mutex<HashMap<Option<GuildId>, SystemTime>>,
 }
 #[derive(Clone)]
 pub struct Verifier(Arc<VerifierData>);
 impl Verifier {
-    pub fn new(config: ConfigManager, database: Database) -> Result<Verifier> {
+    pub fn new(
+        config: ConfigManager, database: Database, audit: AuditLog, webhooks: WebhookManager,
+        blacklist: BlacklistManager, stats: StatsManager, usernames: UsernameManager,
+        event_log: EventLog, clock: Arc<Clock>,
+    ) -> Result<Verifier> {
+        let key_encryption = KeyEncryption::from_env()?;
         let ctx = TokenContext::from_db(&database.connect()?,
-                                        config.get(None, ConfigKeys::TokenValiditySeconds)?)?;
+                                        config.get(None, ConfigKeys::TokenValiditySeconds)?,
+                                        &TokenFormat::from_config(&config)?,
+                                        key_encryption.as_ref(), &*clock)?;
         Ok(Verifier(Arc::new(VerifierData {
-            config, database, token_ctx: RwLock::new(ctx),
+            config, database, audit, webhooks, blacklist, stats, usernames, event_log, clock,
+            token_ctx: RwLock::new(ctx), key_encryption,
             discord_lock: MultiMutex::new(), roblox_lock: MultiMutex::new(),
+            friend_claims: Mutex::new(HashMap::new()),
+            place_codes: Mutex::new(HashMap::new()),
+            in_flight: InFlightCounter::default(),
+            verified_cache: LruCache::new(VERIFIED_CACHE_SIZE),
+            drift_warned_epoch: Mutex::new(i64::min_value()),
+            outdated_alerts: Mutex::new(HashMap::new()),
         })))
     }
 
     pub fn rekey(&self, force: bool) -> Result<bool> {
         let mut lock = self.0.token_ctx.write();
         let cur_id = lock.current.id;
+        let time_increment = self.0.config.get(None, ConfigKeys::TokenValiditySeconds)?;
+        let format = TokenFormat::from_config(&self.0.config)?;
+        let conn = self.0.database.connect()?;
+        let encryption = self.0.key_encryption.as_ref();
         *lock = if force {
-            TokenContext::rekey(&self.0.database.connect()?,
-                                self.0.config.get(None, ConfigKeys::TokenValiditySeconds)?)?
+            TokenContext::rekey(&conn, time_increment, &format, encryption, &*self.0.clock)?
         } else {
-            TokenContext::from_db(&self.0.database.connect()?,
-                                  self.0.config.get(None, ConfigKeys::TokenValiditySeconds)?)?
+            TokenContext::from_db(&conn, time_increment, &format, encryption, &*self.0.clock)?
         };
-        Ok(cur_id != lock.current.id)
+        let changed = cur_id != lock.current.id;
+        if changed {
+            self.0.event_log.record(EVENT_REKEY, format!("Key #{} replaced key #{}",
+                                                         lock.current.id, cur_id));
+        }
+        Ok(changed)
     }
+    // Responds to the shared key being leaked. The key is replaced, tokens made with any earlier
+    // key are rejected, and verifications waiting on a game visit, friend request or place code
+    // are cancelled. Returns how many keys were revoked and how many verifications cancelled.
+    pub fn emergency_rekey(&self) -> Result<(usize, usize)> {
+        let mut lock = self.0.token_ctx.write();
+        let time_increment = self.0.config.get(None, ConfigKeys::TokenValiditySeconds)?;
+        let format = TokenFormat::from_config(&self.0.config)?;
+        let (ctx, revoked) = TokenContext::revoke_all(&self.0.database.connect()?, time_increment,
+                                                      &format, self.0.key_encryption.as_ref(),
+                                                      &*self.0.clock)?;
+        *lock = ctx;
+        drop(lock);
 
-    pub fn get_verified_roblox_user(&self, user: UserId) -> Result<Option<RobloxUserID>> {
+        let mut cancelled = 0;
+        cancelled += self.0.friend_claims.lock().drain().count();
+        cancelled += self.0.place_codes.lock().drain().count();
+        Ok((revoked, cancelled))
+    }
+    // Warns if a token accepted earlier was from further ahead than the current epoch allows,
+    // which means the local clock has fallen behind the one it was checked against before.
+    // Each epoch is only warned about once.
+    pub fn check_clock_drift(&self) -> Result<()> {
+        let (key_id, time_increment, epoch) = {
+            let ctx = self.0.token_ctx.read();
+            (ctx.current.id, ctx.current.time_increment,
+             ctx.current.current_epoch(self.0.clock.now())?)
+        };
+        let last_epoch = self.0.database.connect()?.query(
+            "SELECT MAX(last_key_epoch) FROM roblox_user_info WHERE last_key_id = ?1", key_id,
+        ).get::<Option<i64>>()?;
+        if let Some(last_epoch) = last_epoch {
+            let mut warned = self.0.drift_warned_epoch.lock();
+            // Tokens from the next epoch are accepted, so only anything past that is drift.
+            if last_epoch > epoch + 1 && last_epoch > *warned {
+                warn!("The local clock is at least {} behind the time a token was last \
+                       accepted. Please check that the system clock is synchronized.",
+                      util::to_english_time((last_epoch - epoch - 1) as u64 *
+                                            time_increment as u64));
+                *warned = last_epoch;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn key_status(&self) -> KeyStatus {
+        let ctx = self.0.token_ctx.read();
+        KeyStatus {
+            key_id: ctx.current.id, version: ctx.current.format.version,
+            token_length: ctx.current.format.token_length(),
+            time_increment: ctx.current.time_increment, created: ctx.current.created,
+            previous_keys: ctx.history.iter().map(|x| x.id).collect(),
+        }
+    }
+    pub fn key_age(&self) -> Duration {
+        let created = self.0.token_ctx.read().current.created;
+        self.0.clock.now().duration_since(created).unwrap_or(Duration::from_secs(0))
+    }
+    // Returns how long until the token shown in the verification place changes, and how long a
+    // token shown now can still be used for. Tokens from the previous epoch are still accepted,
+    // so the second is one epoch longer than the first.
+    pub fn token_time_left(&self) -> Result<(Duration, Duration)> {
+        let ctx = self.0.token_ctx.read();
+        let time_left = ctx.current.epoch_time_left(self.0.clock.now())?;
+        Ok((time_left, time_left + Duration::from_secs(ctx.current.time_increment as u64)))
+    }
+
+    // Returns the user's verified Roblox account if they verified in the guild itself, or in a
+    // guild that it trusts and that trusts it back.
+    pub fn get_trusted_link(
+        &self, guild_id: GuildId, discord_id: UserId,
+    ) -> Result<Option<RobloxUserID>> {
+        self.0.database.connect()?.query(
+            "SELECT roblox_user_id FROM discord_user_info \
+             WHERE discord_user_id = ?2 AND roblox_user_id IS NOT NULL AND (\
+                 verified_guild_id = ?1 OR (\
+                     EXISTS (SELECT * FROM guild_trust \
+                             WHERE discord_guild_id = ?1 AND trusted_guild_id = verified_guild_id) \
+                     AND \
+                     EXISTS (SELECT * FROM guild_trust \
+                             WHERE discord_guild_id = verified_guild_id AND trusted_guild_id = ?1)\
+                 )\
+             )", (guild_id, discord_id),
+        ).get_opt()
+    }
+
+    // Returns the guilds a guild trusts, and whether each of them trusts it back.
+    pub fn get_trusted_guilds(&self, guild_id: GuildId) -> Result<Vec<(GuildId, bool)>> {
+        self.0.database.connect()?.query(
+            "SELECT trusted_guild_id, EXISTS (\
+                 SELECT * FROM guild_trust AS other \
+                 WHERE other.discord_guild_id = guild_trust.trusted_guild_id AND \
+                       other.trusted_guild_id = guild_trust.discord_guild_id\
+             ) FROM guild_trust WHERE discord_guild_id = ?1 ORDER BY added_at", guild_id,
+        ).get_all()
+    }
+    pub fn set_guild_trust(
+        &self, guild_id: GuildId, trusted_guild_id: GuildId, trusted: bool,
+    ) -> Result<bool> {
         let conn = self.0.database.connect()?;
-        Ok(conn.query(
-            "SELECT roblox_user_id FROM discord_user_info WHERE discord_user_id = ?1", user
-        ).get_opt::<Option<RobloxUserID>>()?.and_then(|x| x))
+        Ok(if trusted {
+            cmd_ensure!(guild_id != trusted_guild_id, "A server cannot trust itself.");
+            conn.execute(
+                "INSERT OR IGNORE INTO guild_trust (discord_guild_id, trusted_guild_id, added_at) \
+                 VALUES (?1, ?2, ?3)", (guild_id, trusted_guild_id, SystemTime::now()),
+            )? != 0
+        } else {
+            conn.execute(
+                "DELETE FROM guild_trust WHERE discord_guild_id = ?1 AND trusted_guild_id = ?2",
+                (guild_id, trusted_guild_id),
+            )? != 0
+        })
+    }
+
+    // Returns the number of verification attempts that are currently being processed.
+    pub fn in_flight(&self) -> usize {
+        self.0.in_flight.count()
+    }
+
+    pub fn get_verified_roblox_user(&self, user: UserId) -> Result<Option<RobloxUserID>> {
+        self.0.verified_cache.get(&user, || {
+            let conn = self.0.database.connect_read()?;
+            Ok(conn.query(
+                "SELECT roblox_user_id FROM discord_user_info WHERE discord_user_id = ?1", user
+            ).get_opt::<Option<RobloxUserID>>()?.and_then(|x| x))
+        })
+    }
+    // Returns the verified users among the given Discord users, with their Roblox accounts. The
+    // links are loaded with one query, rather than one for each user.
+    pub fn get_verified_roblox_users(
+        &self, users: &[UserId],
+    ) -> Result<Vec<(UserId, RobloxUserID)>> {
+        let users: HashSet<UserId> = users.iter().cloned().collect();
+        let links = self.0.database.connect_read()?.query(
+            "SELECT discord_user_id, roblox_user_id FROM discord_user_info \
+             WHERE roblox_user_id IS NOT NULL", (),
+        ).get_all::<(UserId, RobloxUserID)>()?;
+        Ok(links.into_iter().filter(|x| users.contains(&x.0)).collect())
+    }
+    pub fn cache_metrics(&self) -> LruCacheMetrics {
+        self.0.verified_cache.metrics()
+    }
+    fn get_alt_accounts(
+        conn: &DatabaseConnection, user: UserId,
+    ) -> Result<Vec<(RobloxUserID, SystemTime)>> {
+        conn.query(
+            "SELECT roblox_user_id, last_updated FROM discord_user_alt_accounts \
+             WHERE discord_user_id = ?1 ORDER BY last_updated", user
+        ).get_all()
+    }
+    // Returns every Roblox account linked to a Discord user, with the primary account first.
+    pub fn get_linked_roblox_users(&self, user: UserId) -> Result<Vec<RobloxUserID>> {
+        let conn = self.0.database.connect_read()?;
+        let mut accounts: Vec<_> = self.get_verified_roblox_user(user)?.into_iter().collect();
+        accounts.extend(Self::get_alt_accounts(&conn, user)?.into_iter().map(|x| x.0));
+        Ok(accounts)
+    }
+    pub fn get_verification_time(&self, user: UserId) -> Result<Option<SystemTime>> {
+        let conn = self.0.database.connect_read()?;
+        conn.query(
+            "SELECT last_updated FROM discord_user_info \
+             WHERE discord_user_id = ?1 AND roblox_user_id IS NOT NULL", user
+        ).get_opt()
+    }
+    // Returns the number of verified and unverified members in a guild, not counting bots.
+    pub fn count_verified_members(&self, guild_id: GuildId) -> Result<(usize, usize)> {
+        let members: Vec<UserId> = guild_id.find()?.read().members.values()
+            .filter(|x| !x.user.read().bot).map(|x| x.user.read().id).collect();
+        let mut verified = 0;
+        for &member in &members {
+            if self.get_verified_roblox_user(member)?.is_some() {
+                verified += 1;
+            }
+        }
+        Ok((verified, members.len() - verified))
     }
     pub fn get_verified_discord_user(&self, user: RobloxUserID) -> Result<Option<UserId>> {
+        let conn = self.0.database.connect_read()?;
+        conn.query(
+            "SELECT discord_user_id FROM discord_user_info WHERE roblox_user_id = ?1 \
+             UNION ALL \
+             SELECT discord_user_id FROM discord_user_alt_accounts WHERE roblox_user_id = ?1", user
+        ).get_opt()
+    }
+
+    // Returns the other Discord accounts that have tried to verify as, or are verified as, any
+    // Roblox account the user has tried to verify as or is verified as. Given a server, only
+    // attempts made there are counted.
+    pub fn find_alt_accounts(
+        &self, guild_id: Option<GuildId>, discord_id: UserId,
+    ) -> Result<Vec<AltAccountMatch>> {
+        let conn = self.0.database.connect_read()?;
+        let roblox_ids: Vec<RobloxUserID> = conn.query(
+            "SELECT roblox_user_id FROM verification_attempts \
+             WHERE discord_user_id = ?1 AND (?2 IS NULL OR discord_guild_id = ?2) \
+             UNION \
+             SELECT roblox_user_id FROM discord_user_info \
+             WHERE discord_user_id = ?1 AND roblox_user_id IS NOT NULL \
+             UNION \
+             SELECT roblox_user_id FROM discord_user_alt_accounts WHERE discord_user_id = ?1",
+            (discord_id, guild_id),
+        ).get_all()?;
+
+        let mut matches: Vec<AltAccountMatch> = Vec::new();
+        for roblox_id in roblox_ids {
+            let attempts = conn.query(
+                "SELECT discord_user_id, COUNT(*), MAX(token_valid), MAX(attempted_at) \
+                 FROM verification_attempts \
+                 WHERE roblox_user_id = ?1 AND discord_user_id != ?2 \
+                   AND (?3 IS NULL OR discord_guild_id = ?3) \
+                 GROUP BY discord_user_id", (roblox_id, discord_id, guild_id),
+            ).get_all::<(UserId, u32, bool, SystemTime)>()?;
+            for (other_id, attempts, proved_control, last_attempt) in attempts {
+                matches.push(AltAccountMatch {
+                    discord_id: other_id, roblox_id, attempts, proved_control,
+                    last_attempt: Some(last_attempt), is_linked: false,
+                });
+            }
+            let linked = conn.query(
+                "SELECT discord_user_id FROM discord_user_info WHERE roblox_user_id = ?1 \
+                 UNION \
+                 SELECT discord_user_id FROM discord_user_alt_accounts WHERE roblox_user_id = ?1",
+                roblox_id,
+            ).get_all::<UserId>()?;
+            for other_id in linked.into_iter().filter(|&x| x != discord_id) {
+                match matches.iter().position(|x| x.discord_id == other_id &&
+                                                  x.roblox_id == roblox_id) {
+                    Some(i) => {
+                        matches[i].is_linked = true;
+                        matches[i].proved_control = true;
+                    }
+                    None => matches.push(AltAccountMatch {
+                        discord_id: other_id, roblox_id, attempts: 0, proved_control: true,
+                        last_attempt: None, is_linked: true,
+                    }),
+                }
+            }
+        }
+        matches.sort_by(|a, b| b.last_attempt.cmp(&a.last_attempt));
+        Ok(matches)
+    }
+
+    // Returns one page of a user's verification attempts, newest first, and the total number of
+    // attempts recorded for them, counting only attempts made in the given server if any.
+    pub fn get_attempt_history(
+        &self, guild_id: Option<GuildId>, discord_id: UserId, page: u32, page_size: u32,
+    ) -> Result<(Vec<VerificationAttempt>, u32)> {
+        let conn = self.0.database.connect_read()?;
+        let total = conn.query(
+            "SELECT COUNT(*) FROM verification_attempts \
+             WHERE discord_user_id = ?1 AND (?2 IS NULL OR discord_guild_id = ?2)",
+            (discord_id, guild_id),
+        ).get::<u32>()?;
+        let attempts = conn.query(
+            "SELECT roblox_user_id, discord_guild_id, method, result, token_status, attempted_at \
+             FROM verification_attempts \
+             WHERE discord_user_id = ?1 AND (?2 IS NULL OR discord_guild_id = ?2) \
+             ORDER BY attempted_at DESC, rowid DESC LIMIT ?3 OFFSET ?4",
+            (discord_id, guild_id, page_size, page * page_size),
+        ).get_all::<(RobloxUserID, Option<GuildId>, Option<String>, Option<String>,
+                     Option<String>, SystemTime)>()?;
+        Ok((attempts.into_iter().map(
+            |(roblox_id, guild_id, method, result, token_status, attempted_at)|
+                VerificationAttempt {
+                    roblox_id, guild_id, method, result, token_status, attempted_at,
+                }
+        ).collect(), total))
+    }
+
+    // Records a verification attempt, and flags the user in the audit log the first time they
+    // prove control of a Roblox account another Discord account has also proven control of.
+    fn record_attempt(
+        &self, guild_id: Option<GuildId>, discord_id: UserId, roblox_id: RobloxUserID,
+        method: &str, result: &str, token_status: Option<&TokenStatus>,
+    ) -> Result<()> {
+        let conn = self.0.database.connect()?;
+        let token_valid = match token_status {
+            Some(&TokenStatus::Verified { .. }) => true,
+            _ => false,
+        };
+        let is_new = token_valid && conn.query(
+            "SELECT COUNT(*) FROM verification_attempts \
+             WHERE discord_user_id = ?1 AND roblox_user_id = ?2 AND token_valid",
+            (discord_id, roblox_id),
+        ).get::<u32>()? == 0;
+        conn.execute(
+            "INSERT INTO verification_attempts \
+                 (discord_user_id, roblox_user_id, discord_guild_id, token_valid, attempted_at, \
+                  method, result, token_status) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            (discord_id, roblox_id, guild_id, token_valid, SystemTime::now(),
+             method, result, token_status.map(|x| x.name())),
+        )?;
+        // Only attempts made in the same server are looked at, so that its audit channel does
+        // not show who tried to verify in other servers.
+        if let (true, Some(guild_id)) = (is_new, guild_id) {
+            let others = conn.query(
+                "SELECT discord_user_id FROM verification_attempts \
+                 WHERE roblox_user_id = ?1 AND discord_user_id != ?2 AND discord_guild_id = ?3 \
+                       AND token_valid \
+                 GROUP BY discord_user_id ORDER BY MAX(attempted_at) DESC",
+                (roblox_id, discord_id, guild_id),
+            ).get_all::<UserId>()?;
+            if let Some(&other_discord_id) = others.first() {
+                self.log_event(Some(guild_id), discord_id, AuditEvent::PossibleAltAccount {
+                    roblox_id, other_discord_id, other_accounts: others.len() as u32,
+                });
+            }
+        }
+        Ok(())
+    }
+    // Returns when someone last verified with a valid code, among the attempts still kept.
+    pub fn last_verification_time(&self) -> Result<Option<SystemTime>> {
+        self.0.database.connect_read()?.query(
+            "SELECT MAX(attempted_at) FROM verification_attempts WHERE token_valid", (),
+        ).get()
+    }
+    pub fn prune_attempts(&self) -> Result<()> {
+        let days = self.0.config.get(None, ConfigKeys::VerificationHistoryDays)?;
+        if days == 0 {
+            return Ok(())
+        }
+        let cutoff = SystemTime::now() - Duration::from_secs(days as u64 * 60 * 60 * 24);
+        self.0.database.connect()?.execute(
+            "DELETE FROM verification_attempts WHERE attempted_at < ?1", cutoff,
+        )?;
+        Ok(())
+    }
+
+    // Returns whether an alert about an outdated place should be sent to a server's audit channel,
+    // or to the bot owner for `None`, and records that one was.
+    fn should_alert_outdated(&self, target: Option<GuildId>, now: SystemTime) -> bool {
+        let mut alerts = self.0.outdated_alerts.lock();
+        let interval = Duration::from_secs(OUTDATED_ALERT_INTERVAL_SECS);
+        if let Some(&last) = alerts.get(&target) {
+            if now < last + interval {
+                return false
+            }
+        }
+        alerts.insert(target, now);
+        true
+    }
+    // Tells the bot owner and the server's admins that someone tried to verify with a code from
+    // an outdated verification place, as otherwise only the user finds out.
+    fn alert_outdated_place(&self, guild_id: Option<GuildId>, key_id: u64, reason: RekeyReason) {
+        let now = self.0.clock.now();
+        let current_id = self.0.token_ctx.read().current.id;
+        let description = format!(
+            "Someone tried to verify with a code from key #{}, but the current key is #{} \
+             because {}. The verification place must be updated before users can verify.",
+            key_id, current_id, reason.describe(),
+        );
+        if let Some(guild_id) = guild_id {
+            if self.should_alert_outdated(Some(guild_id), now) {
+                self.0.audit.log_guild_incident(guild_id, "Verification place outdated".to_owned(),
+                                                description.clone(), Vec::new());
+            }
+        }
+        if self.should_alert_outdated(None, now) {
+            warn!("{}", description);
+            self.0.audit.alert_owner(description);
+        }
+    }
+
+    // Events are only sent to the webhook and audit channel of the server they happened in.
+    fn log_event(&self, guild_id: Option<GuildId>, discord_id: UserId, event: AuditEvent) {
+        match event {
+            AuditEvent::Verified { roblox_id, method, key_id, epoch, .. } =>
+                self.0.event_log.record(EVENT_VERIFY, format!(
+                    "{} verified as {} by {} with key #{}, epoch {}",
+                    self.0.event_log.discord_ref(discord_id),
+                    self.0.event_log.roblox_ref(roblox_id), method, key_id, epoch,
+                )),
+            AuditEvent::LinkRestored { roblox_id } =>
+                self.0.event_log.record(EVENT_VERIFY, format!(
+                    "{} was restored as {}", self.0.event_log.discord_ref(discord_id),
+                    self.0.event_log.roblox_ref(roblox_id),
+                )),
+            AuditEvent::Unverified { roblox_id } =>
+                self.0.event_log.record(EVENT_UNVERIFY, format!(
+                    "{} unverified from {}", self.0.event_log.discord_ref(discord_id),
+                    self.0.event_log.roblox_ref(roblox_id),
+                )),
+            _ => { }
+        }
+        self.0.webhooks.notify(guild_id, discord_id, &event);
+        self.0.audit.log(guild_id, discord_id, event);
+    }
+
+    // Links are shared by every server, so a server can make the attempt limit and cooldown
+    // stricter than the global settings, but not looser.
+    fn attempt_limits(&self, guild_id: Option<GuildId>) -> Result<(u32, u64)> {
+        let max_attempts = min(
+            self.0.config.get(None, ConfigKeys::VerificationAttemptLimit)?,
+            self.0.config.get(guild_id, ConfigKeys::VerificationAttemptLimit)?,
+        );
+        let cooldown = max(
+            self.0.config.get(None, ConfigKeys::VerificationCooldownSeconds)?,
+            self.0.config.get(guild_id, ConfigKeys::VerificationCooldownSeconds)?,
+        );
+        Ok((max_attempts, cooldown))
+    }
+    // Likewise, a server can only turn reverification off, or lengthen its cooldown.
+    fn allows_reverify(&self, guild_id: Option<GuildId>, key: ConfigKey<bool>) -> Result<bool> {
+        Ok(self.0.config.get(None, key)? && self.0.config.get(guild_id, key)?)
+    }
+    // Verification methods must also be enabled globally, so the bot owner can turn one off
+    // everywhere.
+    pub fn is_method_enabled(
+        &self, guild_id: Option<GuildId>, key: ConfigKey<bool>,
+    ) -> Result<bool> {
+        Ok(self.0.config.get(None, key)? && self.0.config.get(guild_id, key)?)
+    }
+
+    // Returns a user's recent verification attempts, and when their cooldown ends if they have
+    // used up the attempt limit.
+    pub fn get_cooldown(
+        &self, guild_id: Option<GuildId>, discord_id: UserId,
+    ) -> Result<Option<CooldownInfo>> {
+        let attempt_info = self.0.database.connect()?.query(
+            "SELECT attempt_count, last_attempt FROM verification_cooldown \
+             WHERE discord_user_id = ?1", discord_id
+        ).get_opt::<(u32, SystemTime)>()?;
+        Ok(match attempt_info {
+            Some((attempt_count, last_attempt)) => {
+                let (max_attempts, cooldown) = self.attempt_limits(guild_id)?;
+                let cooldown_ends = last_attempt + Duration::from_secs(cooldown);
+                Some(CooldownInfo {
+                    attempt_count, max_attempts, last_attempt,
+                    cooldown_ends: if attempt_count >= max_attempts &&
+                                      self.0.clock.now() < cooldown_ends {
+                        Some(cooldown_ends)
+                    } else {
+                        None
+                    },
+                })
+            }
+            None => None,
+        })
+    }
+
+    // Clears a user's verification attempts, so they can try to verify again immediately.
+    // Returns whether the user had any attempts recorded.
+    pub fn reset_cooldown(&self, discord_id: UserId) -> Result<bool> {
+        let discord_lock = self.0.discord_lock.lock(discord_id);
+        cmd_ensure!(discord_lock.is_some(),
+                    "Please wait for the current verification attempt to finish.");
+        Ok(self.0.database.connect()?.execute(
+            "DELETE FROM verification_cooldown WHERE discord_user_id = ?1", discord_id,
+        )? != 0)
+    }
+
+    pub fn unverify(
+        &self, guild_id: Option<GuildId>, discord_id: UserId,
+    ) -> Result<Option<RobloxUserID>> {
+        self.unverify_linked_in(guild_id, discord_id, None)
+    }
+    // Unverifies a user only if their link was made in the given server, so that a server's
+    // admins cannot remove links made elsewhere.
+    pub fn unverify_guild_link(
+        &self, guild_id: GuildId, discord_id: UserId,
+    ) -> Result<Option<RobloxUserID>> {
+        self.unverify_linked_in(Some(guild_id), discord_id, Some(guild_id))
+    }
+    fn unverify_linked_in(
+        &self, guild_id: Option<GuildId>, discord_id: UserId, linked_in: Option<GuildId>,
+    ) -> Result<Option<RobloxUserID>> {
+        let discord_lock = self.0.discord_lock.lock(discord_id);
+        cmd_ensure!(discord_lock.is_some(),
+                    "Please wait for the current verification attempt to finish.");
+
         let conn = self.0.database.connect()?;
+        let roblox_id = conn.query(
+            "SELECT roblox_user_id FROM discord_user_info \
+             WHERE discord_user_id = ?1 AND roblox_user_id IS NOT NULL AND \
+                   (?2 IS NULL OR verified_guild_id = ?2)", (discord_id, linked_in),
+        ).get_opt::<RobloxUserID>()?;
+        let alt_accounts = Self::get_alt_accounts(&conn, discord_id)?;
+        if let Some(roblox_id) = roblox_id {
+            let linked_at = self.get_verification_time(discord_id)?.unwrap_or(UNIX_EPOCH);
+            let now = SystemTime::now();
+            // last_updated is left alone so the reverification cooldown still applies.
+            conn.transaction(|| {
+                Self::record_unlinked(&conn, discord_id, roblox_id, true, linked_at, now)?;
+                for &(alt_id, alt_linked_at) in &alt_accounts {
+                    Self::record_unlinked(&conn, discord_id, alt_id, false, alt_linked_at, now)?;
+                }
+                conn.execute(
+                    "UPDATE discord_user_info SET roblox_user_id = NULL \
+                     WHERE discord_user_id = ?1", discord_id,
+                )?;
+                conn.execute(
+                    "DELETE FROM discord_user_alt_accounts WHERE discord_user_id = ?1",
+                    discord_id,
+                )?;
+                Ok(())
+            })?;
+            self.0.verified_cache.invalidate(&discord_id);
+        }
+        if let Some(roblox_id) = roblox_id {
+            self.log_event(guild_id, discord_id, AuditEvent::Unverified { roblox_id });
+            for (alt_id, _) in alt_accounts {
+                self.log_event(guild_id, discord_id, AuditEvent::Unverified { roblox_id: alt_id });
+            }
+        }
+        Ok(roblox_id)
+    }
+
+    // Returns how many users are verified with a link made in a server.
+    pub fn count_guild_links(&self, guild_id: GuildId) -> Result<u64> {
+        self.0.database.connect()?.query(
+            "SELECT COUNT(*) FROM discord_user_info \
+             WHERE verified_guild_id = ?1 AND roblox_user_id IS NOT NULL", guild_id,
+        ).get()
+    }
+    // Removes every link that was made in a server, so that its members must verify again. Users
+    // who are verifying right now are skipped. Returns the users that were unverified.
+    pub fn reset_guild_links(&self, guild_id: GuildId) -> Result<Vec<UserId>> {
+        let users = self.0.database.connect()?.query(
+            "SELECT discord_user_id FROM discord_user_info \
+             WHERE verified_guild_id = ?1 AND roblox_user_id IS NOT NULL", guild_id,
+        ).get_all::<UserId>()?;
+        let now = SystemTime::now();
+        let mut reset = Vec::new();
+        self.0.database.write_in_chunks(&users, |conn, chunk| {
+            for &discord_id in chunk {
+                let _discord_lock = match self.0.discord_lock.lock(discord_id) {
+                    Some(lock) => lock,
+                    None => continue,
+                };
+                let (roblox_id, linked_at) = match conn.query(
+                    "SELECT roblox_user_id, last_updated FROM discord_user_info \
+                     WHERE discord_user_id = ?1 AND verified_guild_id = ?2 AND \
+                           roblox_user_id IS NOT NULL", (discord_id, guild_id),
+                ).get_opt::<(RobloxUserID, SystemTime)>()? {
+                    Some(link) => link,
+                    None => continue,
+                };
+                Self::record_unlinked(conn, discord_id, roblox_id, true, linked_at, now)?;
+                for (alt_id, alt_linked_at) in Self::get_alt_accounts(conn, discord_id)? {
+                    Self::record_unlinked(conn, discord_id, alt_id, false, alt_linked_at, now)?;
+                }
+                conn.execute(
+                    "UPDATE discord_user_info SET roblox_user_id = NULL \
+                     WHERE discord_user_id = ?1", discord_id,
+                )?;
+                conn.execute(
+                    "DELETE FROM discord_user_alt_accounts WHERE discord_user_id = ?1",
+                    discord_id,
+                )?;
+                self.0.verified_cache.invalidate(&discord_id);
+                reset.push(discord_id);
+            }
+            Ok(())
+        })?;
+        self.0.event_log.record(EVENT_UNVERIFY, format!(
+            "Verification was reset on server {}, unlinking {} users", guild_id.0, reset.len(),
+        ));
+        Ok(reset)
+    }
+
+    // Keeps a record of a link that is being removed, so that it can be restored if it was
+    // removed by mistake.
+    fn record_unlinked(
+        conn: &DatabaseConnection, discord_id: UserId, roblox_id: RobloxUserID, is_primary: bool,
+        linked_at: SystemTime, unlinked_at: SystemTime,
+    ) -> Result<()> {
+        conn.execute(
+            "INSERT INTO discord_user_unlinked_accounts \
+                 (discord_user_id, roblox_user_id, is_primary, linked_at, unlinked_at) \
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            (discord_id, roblox_id, is_primary, linked_at, unlinked_at),
+        )?;
+        Ok(())
+    }
+    fn linked_discord_user(
+        conn: &DatabaseConnection, roblox_id: RobloxUserID,
+    ) -> Result<Option<UserId>> {
         conn.query(
-            "SELECT discord_user_id FROM discord_user_info WHERE roblox_user_id = ?1", user
+            "SELECT discord_user_id FROM discord_user_info WHERE roblox_user_id = ?1 \
+             UNION ALL \
+             SELECT discord_user_id FROM discord_user_alt_accounts WHERE roblox_user_id = ?1",
+            roblox_id,
         ).get_opt()
     }
 
+    // Restores the links removed the last time a user was unverified, as long as that was
+    // within the configured number of days. Alt accounts that have since been linked to another
+    // Discord account are skipped. Returns the restored primary account.
+    pub fn restore_unverified(
+        &self, guild_id: Option<GuildId>, discord_id: UserId,
+    ) -> Result<RobloxUserID> {
+        let discord_lock = self.0.discord_lock.lock(discord_id);
+        cmd_ensure!(discord_lock.is_some(),
+                    "Please wait for the current verification attempt to finish.");
+
+        let days = self.0.config.get(None, ConfigKeys::UnverifyRestoreDays)?;
+        cmd_ensure!(days != 0, "Restoring unverified users is disabled.");
+        cmd_ensure!(self.get_verified_roblox_user(discord_id)?.is_none(),
+                    "That user is currently verified. Their previous links can only be \
+                     restored while they are unverified.");
+
+        let conn = self.0.database.connect()?;
+        let unlinked_at = conn.query(
+            "SELECT unlinked_at FROM discord_user_unlinked_accounts \
+             WHERE discord_user_id = ?1 AND is_primary AND restored_at IS NULL \
+             ORDER BY unlinked_at DESC LIMIT 1", discord_id,
+        ).get_opt::<SystemTime>()?.to_cmd_err(|| "That user has no links that can be restored.")?;
+        let now = SystemTime::now();
+        cmd_ensure!(unlinked_at + Duration::from_secs(days as u64 * 60 * 60 * 24) >= now,
+                    "That user was unverified more than {} days ago, so their links can no \
+                     longer be restored.", days);
+
+        let links = conn.query(
+            "SELECT roblox_user_id, is_primary, linked_at FROM discord_user_unlinked_accounts \
+             WHERE discord_user_id = ?1 AND unlinked_at = ?2 AND restored_at IS NULL",
+            (discord_id, unlinked_at),
+        ).get_all::<(RobloxUserID, bool, SystemTime)>()?;
+        let roblox_id = links.iter().find(|x| x.1).map(|x| x.0)
+            .to_cmd_err(|| "That user has no links that can be restored.")?;
+        cmd_ensure!(Self::linked_discord_user(&conn, roblox_id)?.is_none(),
+                    "That user's Roblox account has since been verified by another Discord \
+                     account.");
+
+        conn.transaction(|| {
+            let updated = conn.execute(
+                "UPDATE discord_user_info SET roblox_user_id = ?2 WHERE discord_user_id = ?1",
+                (discord_id, roblox_id),
+            )?;
+            ensure!(updated == 1, "No discord_user_info row for unverified user.");
+            for &(alt_id, is_primary, linked_at) in &links {
+                if !is_primary && Self::linked_discord_user(&conn, alt_id)?.is_none() {
+                    conn.execute(
+                        "INSERT INTO discord_user_alt_accounts \
+                             (roblox_user_id, discord_user_id, last_updated) \
+                         VALUES (?1, ?2, ?3)", (alt_id, discord_id, linked_at),
+                    )?;
+                }
+            }
+            conn.execute(
+                "UPDATE discord_user_unlinked_accounts SET restored_at = ?3 \
+                 WHERE discord_user_id = ?1 AND unlinked_at = ?2",
+                (discord_id, unlinked_at, now),
+            )?;
+            Ok(())
+        })?;
+        self.0.verified_cache.invalidate(&discord_id);
+        self.log_event(guild_id, discord_id, AuditEvent::LinkRestored { roblox_id });
+        Ok(roblox_id)
+    }
+
+    // Irreversibly removes everything stored about a Discord user, including links that could
+    // otherwise be restored, for data deletion requests. Returns `None` if the user is verifying
+    // at the moment.
+    pub fn purge_user(&self, discord_id: UserId) -> Result<Option<DeletionReceipt>> {
+        let discord_lock = self.0.discord_lock.lock(discord_id);
+        if discord_lock.is_none() {
+            return Ok(None)
+        }
+
+        let conn = self.0.database.connect()?;
+        let removed = conn.transaction_immediate(|| {
+            let mut removed = Vec::new();
+            for &(table, condition) in PURGED_TABLES {
+                let rows = conn.execute(
+                    &format!("DELETE FROM {} WHERE {}", table, condition), discord_id,
+                )?;
+                removed.push((table, rows as usize));
+            }
+            let rows = conn.execute(
+                "UPDATE banned_roblox_ids SET discord_user_id = ?2 WHERE discord_user_id = ?1",
+                (discord_id, PURGED_BAN_DISCORD_ID),
+            )?;
+            removed.push(("banned_roblox_ids", rows as usize));
+            Ok(removed)
+        })?;
+        self.0.verified_cache.invalidate(&discord_id);
+        self.0.friend_claims.lock().remove(&discord_id);
+        self.0.place_codes.lock().retain(|_, claim| claim.discord_id != discord_id);
+
+        let mut rng = OsRng::new()?;
+        let receipt_id = (0..16).map(|_| format!("{:02x}", rng.gen::<u8>())).collect();
+        Ok(Some(DeletionReceipt {
+            receipt_id, discord_id, purged_at: SystemTime::now(), removed,
+        }))
+    }
+
+    // Makes one of the Roblox accounts linked to a Discord user their primary account, and
+    // returns the previous primary account.
+    pub fn switch_primary(
+        &self, guild_id: Option<GuildId>, discord_id: UserId, roblox_id: RobloxUserID,
+    ) -> Result<RobloxUserID> {
+        let discord_lock = self.0.discord_lock.lock(discord_id);
+        cmd_ensure!(discord_lock.is_some(),
+                    "Please wait for the current verification attempt to finish.");
+
+        let conn = self.0.database.connect()?;
+        let previous_id = self.get_verified_roblox_user(discord_id)?
+            .to_cmd_err(|| "You are not verified with this bot.")?;
+        cmd_ensure!(previous_id != roblox_id, "That is already your primary Roblox account.");
+        cmd_ensure!(Self::get_alt_accounts(&conn, discord_id)?.iter().any(|x| x.0 == roblox_id),
+                    "That Roblox account is not linked to your Discord account.");
+
+        // last_updated in discord_user_info is left alone so the reverification cooldown is not
+        // affected by switching accounts.
+        conn.transaction(|| {
+            conn.execute(
+                "DELETE FROM discord_user_alt_accounts WHERE roblox_user_id = ?1", roblox_id,
+            )?;
+            conn.execute(
+                "UPDATE discord_user_info SET roblox_user_id = ?2 WHERE discord_user_id = ?1",
+                (discord_id, roblox_id),
+            )?;
+            conn.execute(
+                "INSERT INTO discord_user_alt_accounts \
+                     (roblox_user_id, discord_user_id, last_updated) \
+                 VALUES (?1, ?2, ?3)", (previous_id, discord_id, SystemTime::now()),
+            )?;
+            Ok(())
+        })?;
+        self.0.verified_cache.invalidate(&discord_id);
+        self.log_event(guild_id, discord_id,
+                       AuditEvent::PrimaryAccountChanged { roblox_id, previous_id });
+        Ok(previous_id)
+    }
+
+    // Links accounts verified with another bot, skipping any Discord or Roblox account that is
+    // already linked. Returns the number of links imported and skipped.
+    //
+    // No audit events are logged, as imports can contain thousands of links. Links are written in
+    // chunks, so if an import fails partway through, the links before the failure are kept.
+    // Whether an imported link could have been made by verifying. The blacklist and bans can only
+    // be checked for links imported from a server.
+    fn import_eligible(&self, guild_id: Option<GuildId>, roblox_id: RobloxUserID) -> Result<bool> {
+        if let Some(guild_id) = guild_id {
+            if self.0.blacklist.check(guild_id, roblox_id)?.is_some() ||
+               self.0.blacklist.check_banned(guild_id, roblox_id)?.is_some() {
+                return Ok(false)
+            }
+        }
+        let minimum_days = self.0.config.get(guild_id, ConfigKeys::MinimumRobloxAccountAgeDays)?;
+        if minimum_days != 0 {
+            let allowed_at = roblox_id.creation_time()? +
+                Duration::from_secs(minimum_days as u64 * 60 * 60 * 24);
+            if self.0.clock.now() < allowed_at {
+                return Ok(false)
+            }
+        }
+        Ok(true)
+    }
+
+    // Imports links from another bot. Links to accounts that could not verify themselves, as
+    // they are blacklisted, banned or too new, are skipped, along with links to accounts that
+    // are already linked.
+    pub fn import_links(
+        &self, guild_id: Option<GuildId>, links: &[(UserId, RobloxUserID)],
+    ) -> Result<(usize, usize)> {
+        // The checks look accounts up on Roblox, so they are done before anything is written.
+        let eligible: Vec<_> = links.iter().cloned().filter(|&(discord_id, roblox_id)| {
+            match self.import_eligible(guild_id, roblox_id) {
+                Ok(eligible) => eligible,
+                Err(e) => {
+                    warn!("Could not check imported link {} -> {}, skipping: {}",
+                          discord_id, roblox_id.0, e);
+                    false
+                }
+            }
+        }).collect();
+        // Imported accounts are recorded as last using epoch 0 of the current key, so any token
+        // generated afterwards is still accepted.
+        let key_id = self.0.token_ctx.read().current.id;
+        let mut seen_discord = HashSet::new();
+        let mut seen_roblox = HashSet::new();
+        let mut imported = 0;
+        let result = self.0.database.write_in_chunks(&eligible, |conn, chunk| {
+            let now = SystemTime::now();
+            let mut roblox_rows = Vec::new();
+            let mut discord_rows = Vec::new();
+            for &(discord_id, roblox_id) in chunk {
+                // Links earlier in the same import are not visible to the queries below until
+                // they are written, so they are checked separately.
+                if seen_discord.contains(&discord_id) || seen_roblox.contains(&roblox_id) {
+                    continue
+                }
+                let discord_linked = conn.query(
+                    "SELECT COUNT(*) FROM discord_user_info \
+                     WHERE discord_user_id = ?1 AND roblox_user_id IS NOT NULL", discord_id,
+                ).get::<u32>()? != 0;
+                let roblox_linked = conn.query(
+                    "SELECT COUNT(*) FROM discord_user_info WHERE roblox_user_id = ?1 \
+                     UNION ALL \
+                     SELECT COUNT(*) FROM discord_user_alt_accounts WHERE roblox_user_id = ?1",
+                    roblox_id,
+                ).get_all::<u32>()?.iter().any(|&x| x != 0);
+                if discord_linked || roblox_linked {
+                    continue
+                }
+                seen_discord.insert(discord_id);
+                seen_roblox.insert(roblox_id);
+                roblox_rows.push((roblox_id, key_id, 0u32, now));
+                discord_rows.push((discord_id, roblox_id, now));
+            }
+            conn.insert_batch(
+                "INSERT OR IGNORE INTO roblox_user_info \
+                     (roblox_user_id, last_key_id, last_key_epoch, last_updated)",
+                &roblox_rows,
+            )?;
+            // Users with an existing row keep its other columns, so only the link is updated.
+            conn.insert_batch(
+                "INSERT OR IGNORE INTO discord_user_info \
+                     (discord_user_id, roblox_user_id, last_updated)", &discord_rows,
+            )?;
+            for &row in &discord_rows {
+                conn.execute(
+                    "UPDATE discord_user_info SET roblox_user_id = ?2, last_updated = ?3 \
+                     WHERE discord_user_id = ?1 AND roblox_user_id IS NULL", row,
+                )?;
+            }
+            imported += discord_rows.len();
+            Ok(())
+        });
+        for discord_id in seen_discord {
+            self.0.verified_cache.invalidate(&discord_id);
+        }
+        result?;
+        Ok((imported, links.len() - imported))
+    }
+
+    pub fn update_user(&self, discord_id: UserId) -> Result<Option<RobloxUserID>> {
+        match self.get_verified_roblox_user(discord_id)? {
+            Some(roblox_id) => {
+                let username = roblox_id.lookup_username_opt()?.to_cmd_err(|| format!(
+                    "The Roblox account verified to this user (ID #{}) no longer exists.",
+                    roblox_id.0,
+                ))?;
+                if let Some(previous) = self.0.usernames.record(roblox_id, &username)? {
+                    debug!("Roblox user #{} was renamed from {} to {}.",
+                           roblox_id.0, previous, username);
+                }
+                Ok(Some(roblox_id))
+            }
+            None => Ok(None),
+        }
+    }
+
+    // Returns the URL users sign in to their Roblox account at for `RobloxOAuthVerification`.
+    pub fn roblox_oauth_url(&self, discord_id: UserId) -> Result<String> {
+        let (client_id, client_secret) = roblox_oauth_app(&self.0.config)?;
+        let app = RobloxOAuthApp { client_id: &client_id, client_secret: &client_secret };
+        app.authorize_url(&roblox_oauth_redirect_uri(&self.0.config)?, &discord_id.0.to_string())
+    }
+
+    // Returns a link that starts the verification place for one Roblox account, with signed
+    // launch data telling the place which account the link was made for, along with how many
+    // seconds it can be used for.
+    pub fn deep_link(&self, roblox_id: RobloxUserID) -> Result<(String, u64)> {
+        let place_id = self.0.config.get(None, ConfigKeys::PlaceID)?
+            .to_cmd_err(|| "No place ID set. Please ask the bot owner to fix this.")?;
+        let expires = util::time_to_i64(self.0.clock.now()) + DEEP_LINK_SECONDS as i64;
+        let mut rng = OsRng::new()?;
+        let nonce: String = (0..8).map(|_| format!("{:02x}", rng.gen::<u8>())).collect();
+        let launch_data =
+            self.0.token_ctx.read().current.make_launch_data(roblox_id, expires, &nonce);
+        Ok((format!("https://www.roblox.com/games/start?placeId={}&launchData={}",
+                    place_id, launch_data), DEEP_LINK_SECONDS))
+    }
+
+    pub fn profile_phrase(&self, discord_id: UserId, roblox_id: RobloxUserID) -> Result<String> {
+        let token_ctx = self.0.token_ctx.read();
+        let epoch = token_ctx.current.profile_epoch(self.0.clock.now())?;
+        Ok(token_ctx.current.make_profile_phrase(discord_id, roblox_id, epoch))
+    }
+
     pub fn try_verify(
-        &self, discord_id: UserId, roblox_id: RobloxUserID, token: &str,
+        &self, guild_id: Option<GuildId>, discord_id: UserId, roblox_id: RobloxUserID,
+        method: &VerificationMethod, data: &str,
+    ) -> Result<VerifyResult> {
+        let _span = logger::span(format!("verify {} -> #{}", discord_id, roblox_id.0));
+        let _in_flight = self.0.in_flight.enter();
+        let mut token_status = None;
+        let result = self.try_verify_inner(guild_id, discord_id, roblox_id, method, data,
+                                           &mut token_status);
+        let result_name = match result {
+            Ok(ref result) => result.name(),
+            Err(_) => "error",
+        };
+        if let Err(e) = self.record_attempt(guild_id, discord_id, roblox_id, method.name(),
+                                            result_name, token_status.as_ref()) {
+            warn!("Could not record verification attempt: {}", e);
+        }
+        let result = result?;
+        debug!("Verification attempt finished: {:?}", result);
+        let stat = match result {
+            VerifyResult::VerificationOk => Some(StatKind::Verification),
+            VerifyResult::TokenAlreadyUsed | VerifyResult::InvalidToken =>
+                Some(StatKind::FailedAttempt),
+            VerifyResult::TooManyAttempts { .. } | VerifyResult::ReverifyOnCooldown { .. } =>
+                Some(StatKind::CooldownHit),
+            _ => None,
+        };
+        if let Some(stat) = stat {
+            if let Err(e) = self.0.stats.record(guild_id, stat) {
+                warn!("Could not record verification statistics: {}", e);
+            }
+        }
+        Ok(result)
+    }
+    fn try_verify_inner(
+        &self, guild_id: Option<GuildId>, discord_id: UserId, roblox_id: RobloxUserID,
+        method: &VerificationMethod, data: &str, token_status: &mut Option<TokenStatus>,
     ) -> Result<VerifyResult> {
+        cmd_ensure!(self.is_method_enabled(guild_id, method.enabled_key())?,
+                    "That verification method is not enabled on this server.");
+        method.validate(data)?;
+        // Every time check in an attempt uses the same time, so they cannot disagree.
+        let now = self.0.clock.now();
+
+        if let Some(guild_id) = guild_id {
+            if let Some(entry) = self.0.blacklist.check(guild_id, roblox_id)? {
+                let message = self.0.config.get(Some(guild_id), ConfigKeys::BlacklistMessage)?;
+                let message = match entry.reason {
+                    Some(reason) => format!("{} (Reason: {})", message, reason),
+                    None => message,
+                };
+                return Ok(VerifyResult::Blacklisted { message })
+            }
+            if let Some(banned_id) = self.0.blacklist.check_banned(guild_id, roblox_id)? {
+                debug!("{} is linked to {}, who is banned from {}.",
+                       roblox_id.0, banned_id, guild_id);
+                let message = self.0.config.get(Some(guild_id), ConfigKeys::BlacklistMessage)?;
+                return Ok(VerifyResult::Blacklisted {
+                    message: format!("{} (Reason: This account is linked to a banned user.)",
+                                     message),
+                })
+            }
+            // Names that need approval are held back when roles are set instead.
+            match check_name_policy(&self.0.config, guild_id, roblox_id)? {
+                NamePolicyResult::Denied { name, require_approval: false } =>
+                    return Ok(VerifyResult::NameNotAllowed { name }),
+                _ => { }
+            }
+        }
+
+        let minimum_days = self.0.config.get(guild_id, ConfigKeys::MinimumRobloxAccountAgeDays)?;
+        if minimum_days != 0 {
+            let allowed_at = roblox_id.creation_time()? +
+                Duration::from_secs(minimum_days as u64 * 60 * 60 * 24);
+            if now < allowed_at {
+                return Ok(VerifyResult::AccountTooNew { minimum_days, allowed_at })
+            }
+        }
+
+        if let Some(group_id) = self.0.config.get(guild_id, ConfigKeys::RequiredGroupID)? {
+            let minimum_rank = self.0.config.get(guild_id, ConfigKeys::RequiredGroupMinimumRank)?;
+            let rank = roblox_id.get_current_group_rank(group_id)?.map_or(0, |x| x.rank);
+            if rank < minimum_rank.unwrap_or(1) {
+                return Ok(VerifyResult::NotInRequiredGroup { group_id, minimum_rank })
+            }
+        }
+
         let conn = self.0.database.connect()?;
 
-        debug!("Starting verification attempt: discord id {} -> roblox id {}",
-               discord_id.0, roblox_id.0);
+        debug!("Starting verification attempt: discord id {} -> roblox id {} (method: {})",
+               discord_id.0, roblox_id.0, method.name());
 
         let discord_lock = self.0.discord_lock.lock(discord_id);
         cmd_ensure!(discord_lock.is_some(),
@@ -275,11 +1854,10 @@ impl Verifier {
             "SELECT attempt_count, last_attempt FROM verification_cooldown \
              WHERE discord_user_id = ?1", discord_id
         ).get_opt::<(u32, SystemTime)>()?;
+        let (max_attempts, cooldown) = self.attempt_limits(guild_id)?;
         let new_attempt_count = if let Some((attempt_count, last_attempt)) = attempt_info {
-            let max_attempts = self.0.config.get(None, ConfigKeys::VerificationAttemptLimit)?;
-            let cooldown = self.0.config.get(None, ConfigKeys::VerificationCooldownSeconds)?;
             let cooldown_ends = last_attempt + Duration::from_secs(cooldown);
-            if attempt_count >= max_attempts && SystemTime::now() < cooldown_ends {
+            if attempt_count >= max_attempts && now < cooldown_ends {
                 return Ok(VerifyResult::TooManyAttempts { max_attempts, cooldown, cooldown_ends })
             }
             attempt_count + 1
@@ -289,12 +1867,21 @@ impl Verifier {
         conn.execute(
             "REPLACE INTO verification_cooldown (\
                 discord_user_id, last_attempt, attempt_count\
-            ) VALUES (?1, ?2, ?3)", (discord_id, SystemTime::now(), new_attempt_count)
+            ) VALUES (?1, ?2, ?3)", (discord_id, now, new_attempt_count)
         )?;
+        if new_attempt_count >= max_attempts {
+            self.log_event(guild_id, discord_id,
+                           AuditEvent::AttemptLimitReached { roblox_id, max_attempts });
+        }
 
         // Check token
         let token_ctx = self.0.token_ctx.read();
-        match token_ctx.check_token(roblox_id, token)? {
+        let method_ctx = MethodContext {
+            data: &self.0, token_ctx: &token_ctx, now, discord_id, roblox_id,
+        };
+        let status = method.check(&method_ctx, data)?;
+        *token_status = Some(status.clone());
+        let (key_id, epoch) = match status {
             TokenStatus::Verified { key_id, epoch } => {
                 let last_key = conn.query(
                     "SELECT last_key_id, last_key_epoch FROM roblox_user_info \
@@ -308,44 +1895,60 @@ impl Verifier {
                 conn.execute(
                     "REPLACE INTO roblox_user_info \
                          (roblox_user_id, last_key_id, last_key_epoch, last_updated) \
-                     VALUES (?1, ?2, ?3, ?4)", (roblox_id, key_id, epoch, SystemTime::now()),
+                     VALUES (?1, ?2, ?3, ?4)", (roblox_id, key_id, epoch, now),
                 )?;
+                (key_id, epoch)
+            }
+            TokenStatus::Outdated { key_id, reason } => {
+                self.alert_outdated_place(guild_id, key_id, reason);
+                return Ok(VerifyResult::VerificationPlaceOutdated)
             }
-            TokenStatus::Outdated =>
-                return Ok(VerifyResult::VerificationPlaceOutdated),
             TokenStatus::NotVerified =>
                 return Ok(VerifyResult::InvalidToken),
-        }
+        };
 
         // Attempt to verify user
-        let allow_reverify_discord = self.0.config.get(None, ConfigKeys::AllowReverifyDiscord)?;
-        let allow_reverify_roblox = self.0.config.get(None, ConfigKeys::AllowReverifyRoblox)?;
+        let allow_reverify_discord =
+            self.allows_reverify(guild_id, ConfigKeys::AllowReverifyDiscord)?;
+        let allow_reverify_roblox =
+            self.allows_reverify(guild_id, ConfigKeys::AllowReverifyRoblox)?;
+        let max_accounts =
+            max(self.0.config.get(None, ConfigKeys::MaxLinkedRobloxAccounts)?, 1);
         let check_discord = conn.query(
             "SELECT roblox_user_id, last_updated FROM discord_user_info \
              WHERE discord_user_id = ?1", discord_id
         ).get_opt::<(Option<RobloxUserID>, SystemTime)>()?;
+        let previous_id = check_discord.and_then(|x| x.0);
+        let alt_accounts = Self::get_alt_accounts(&conn, discord_id)?;
+        // When multiple accounts are allowed, verifying as another account links it as the new
+        // primary account instead of replacing the current one.
+        let is_new_link = max_accounts > 1 && previous_id.is_some();
         if let Some((current_id, last_updated)) = check_discord {
-            if !allow_reverify_discord {
-                if let Some(current_id) = current_id {
+            if let Some(current_id) = current_id {
+                if current_id == roblox_id || alt_accounts.iter().any(|x| x.0 == roblox_id) ||
+                   (!is_new_link && !allow_reverify_discord) {
                     return Ok(VerifyResult::SenderVerifiedAs { other_roblox_id: current_id })
                 }
             }
-            if current_id == Some(roblox_id) {
-                return Ok(VerifyResult::SenderVerifiedAs { other_roblox_id: roblox_id })
-            }
 
-            let cooldown =
-                self.0.config.get(None, ConfigKeys::ReverificationCooldownSeconds)?;
-            let cooldown_ends = last_updated + Duration::from_secs(cooldown);
-            if SystemTime::now() < cooldown_ends {
-                return Ok(VerifyResult::ReverifyOnCooldown { cooldown, cooldown_ends })
+            if is_new_link {
+                if alt_accounts.len() + 1 >= max_accounts as usize {
+                    return Ok(VerifyResult::TooManyLinkedAccounts { max_accounts })
+                }
+            } else {
+                let cooldown = max(
+                    self.0.config.get(None, ConfigKeys::ReverificationCooldownSeconds)?,
+                    self.0.config.get(guild_id, ConfigKeys::ReverificationCooldownSeconds)?,
+                );
+                let cooldown_ends = last_updated + Duration::from_secs(cooldown);
+                if now < cooldown_ends {
+                    return Ok(VerifyResult::ReverifyOnCooldown { cooldown, cooldown_ends })
+                }
             }
         }
 
-        let check_roblox = conn.query(
-            "SELECT discord_user_id FROM discord_user_info \
-             WHERE roblox_user_id = ?1", roblox_id,
-        ).get_opt::<UserId>()?;
+        let check_roblox = Self::linked_discord_user(&conn, roblox_id)?;
+        let mut promoted = None;
         if let Some(current_id) = check_roblox {
             // TODO: Add some locking here in case the current_id is verifying currently.
             if current_id != discord_id {
@@ -356,27 +1959,313 @@ impl Verifier {
                 }
 
                 // TODO: Forcefully update this other person's roles somehow.
-                conn.execute(
-                    "UPDATE discord_user_info SET roblox_user_id = NULL \
+                let link = conn.query(
+                    "SELECT last_updated, 1 FROM discord_user_info WHERE roblox_user_id = ?1 \
+                     UNION ALL \
+                     SELECT last_updated, 0 FROM discord_user_alt_accounts \
                      WHERE roblox_user_id = ?1", roblox_id,
+                ).get_opt::<(SystemTime, bool)>()?;
+                if let Some((linked_at, is_primary)) = link {
+                    Self::record_unlinked(&conn, current_id, roblox_id, is_primary, linked_at,
+                                          now)?;
+                }
+                conn.execute(
+                    "DELETE FROM discord_user_alt_accounts WHERE roblox_user_id = ?1", roblox_id,
                 )?;
+                // The other user keeps their other linked accounts, so if this was their primary
+                // account, the most recently linked of them becomes their primary account.
+                let next_primary = conn.query(
+                    "SELECT roblox_user_id FROM discord_user_alt_accounts \
+                     WHERE discord_user_id = ?1 ORDER BY last_updated DESC LIMIT 1", current_id,
+                ).get_opt::<RobloxUserID>()?;
+                match next_primary {
+                    Some(next_id) if link.map_or(false, |x| x.1) => {
+                        conn.execute(
+                            "DELETE FROM discord_user_alt_accounts WHERE roblox_user_id = ?1",
+                            next_id,
+                        )?;
+                        conn.execute(
+                            "UPDATE discord_user_info SET roblox_user_id = ?2 \
+                             WHERE roblox_user_id = ?1", (roblox_id, next_id),
+                        )?;
+                        promoted = Some((current_id, next_id));
+                    }
+                    _ => {
+                        conn.execute(
+                            "UPDATE discord_user_info SET roblox_user_id = NULL \
+                             WHERE roblox_user_id = ?1", roblox_id,
+                        )?;
+                    }
+                }
+                self.0.verified_cache.invalidate(&current_id);
             }
         }
 
-        conn.execute(
-            "REPLACE INTO discord_user_info (discord_user_id, roblox_user_id, last_updated) \
-             VALUES (?1, ?2, ?3)", (discord_id, roblox_id, SystemTime::now()),
-        )?;
+        conn.transaction(|| {
+            if is_new_link {
+                if let Some((Some(previous_id), last_updated)) = check_discord {
+                    conn.execute(
+                        "INSERT INTO discord_user_alt_accounts \
+                             (roblox_user_id, discord_user_id, last_updated) \
+                         VALUES (?1, ?2, ?3)", (previous_id, discord_id, last_updated),
+                    )?;
+                }
+            }
+            conn.execute(
+                "REPLACE INTO discord_user_info \
+                     (discord_user_id, roblox_user_id, last_updated, verified_guild_id) \
+                 VALUES (?1, ?2, ?3, ?4)", (discord_id, roblox_id, now, guild_id),
+            )?;
+            Ok(())
+        })?;
+        self.0.verified_cache.invalidate(&discord_id);
+        if let Some((other_id, next_id)) = promoted {
+            self.log_event(guild_id, other_id, AuditEvent::PrimaryAccountChanged {
+                roblox_id: next_id, previous_id: roblox_id,
+            });
+        }
+        self.log_event(guild_id, discord_id, AuditEvent::Verified {
+            roblox_id, previous_id, method: method.name(), key_id, epoch,
+        });
 
         Ok(VerifyResult::VerificationOk)
     }
 
-    pub fn add_config<'a>(&self, config: &'a mut Vec<LuaConfigEntry>) {
-        self.0.token_ctx.read().current.add_config(config)
+    pub fn add_config<'a>(&self, config: &'a mut Vec<LuaConfigEntry>) -> Result<()> {
+        self.0.token_ctx.read().current.add_config(config);
+        let datastore = if self.0.config.get(None, ConfigKeys::RequireIssuedTokens)? {
+            Some(ISSUED_TOKENS_DATASTORE)
+        } else {
+            None
+        };
+        config.push(LuaConfigEntry::new("issued_tokens_datastore", false, datastore));
+        config.push(LuaConfigEntry::new("launch_data_version", false, DEEP_LINK_VERSION));
+        config.push(LuaConfigEntry::new("launch_data_max_age", false, DEEP_LINK_SECONDS as u32));
+        Ok(())
     }
 
     pub fn on_cleanup_tick(&self) {
         self.0.discord_lock.shrink_to_fit();
         self.0.roblox_lock.shrink_to_fit();
+        if let Err(e) = self.check_clock_drift() {
+            warn!("Could not check for clock drift: {}", e);
+        }
+
+        let now = self.0.clock.now();
+        let mut claims = self.0.friend_claims.lock();
+        claims.retain(|_, claim| claim.is_live(now));
+        claims.shrink_to_fit();
+        let mut codes = self.0.place_codes.lock();
+        codes.retain(|_, claim| claim.is_live(now));
+        codes.shrink_to_fit();
+    }
+
+    // Creates a code for a Discord user to enter in the verification place, replacing any code
+    // they were given before, and returns it along with how long the user has to enter it.
+    pub fn new_place_code(
+        &self, guild_id: GuildId, channel_id: ChannelId, discord_id: UserId,
+    ) -> Result<(String, u64)> {
+        cmd_ensure!(self.is_method_enabled(Some(guild_id),
+                                           ConfigKeys::VerificationMethodPlaceCode)?,
+                    "That verification method is not enabled on this server.");
+        cmd_ensure!(self.0.config.get(None, ConfigKeys::PlacePushSecret)?.is_some(),
+                    "The verification place is not set up to send codes to the bot. Please ask \
+                     the bot owner to fix this.");
+        let mut rng = OsRng::new()?;
+        let mut codes = self.0.place_codes.lock();
+        codes.retain(|_, claim| claim.discord_id != discord_id);
+        let code = loop {
+            let code: String = (0..PLACE_CODE_LENGTH).map(|_| {
+                TOKEN_CHARS_V2[rng.gen_range(0, TOKEN_CHARS_V2.len())] as char
+            }).collect();
+            if !codes.contains_key(&code) {
+                break code
+            }
+        };
+        codes.insert(code.clone(), PlaceCodeClaim {
+            discord_id, guild_id, channel_id, claimed_at: SystemTime::now(), roblox_id: None,
+        });
+        Ok((code, PLACE_CODE_SECONDS))
+    }
+    // Records that a Roblox account entered a code in the verification place, and returns the
+    // code as it was issued along with the claim it belongs to. Each code can only be entered
+    // once.
+    pub fn enter_place_code(
+        &self, code: &str, roblox_id: RobloxUserID,
+    ) -> Option<(String, PlaceCodeClaim)> {
+        let code = code.trim().to_uppercase();
+        let mut codes = self.0.place_codes.lock();
+        match codes.get_mut(&code) {
+            Some(claim) if claim.roblox_id.is_none() && claim.is_live(SystemTime::now()) => {
+                claim.roblox_id = Some(roblox_id);
+                Some((code.clone(), *claim))
+            }
+            _ => None,
+        }
+    }
+
+    // Starts waiting for a friend request from a Roblox account, and returns how long the user
+    // has to send it.
+    pub fn claim_friend_request(
+        &self, guild_id: GuildId, channel_id: ChannelId, discord_id: UserId,
+        roblox_id: RobloxUserID,
+    ) -> Result<u64> {
+        cmd_ensure!(self.is_method_enabled(Some(guild_id), ConfigKeys::VerificationMethodFriend)?,
+                    "That verification method is not enabled on this server.");
+        self.friend_verification_cookie()?;
+        // A friend request only shows which Roblox account sent it, so only one Discord user can
+        // wait for a request from each account at a time.
+        let now = SystemTime::now();
+        let mut claims = self.0.friend_claims.lock();
+        cmd_ensure!(!claims.iter().any(|(&id, claim)| id != discord_id &&
+                                                      claim.roblox_id == roblox_id &&
+                                                      claim.is_live(now)),
+                    "Someone else is already waiting for a friend request from that Roblox \
+                     account. Please try again in {}.",
+                    util::to_english_time(FRIEND_CLAIM_SECONDS));
+        claims.insert(discord_id, FriendClaim {
+            roblox_id, guild_id, channel_id, claimed_at: now, confirmed: false,
+        });
+        Ok(FRIEND_CLAIM_SECONDS)
+    }
+    pub fn friend_verification_cookie(&self) -> Result<String> {
+        match self.0.config.get(None, ConfigKeys::FriendVerificationCookie)? {
+            Some(cookie) => Ok(cookie),
+            None => cmd_error!("No Roblox account is set up to receive friend requests. Please \
+                                ask the bot owner to fix this."),
+        }
+    }
+    pub fn has_friend_claims(&self) -> bool {
+        !self.0.friend_claims.lock().is_empty()
+    }
+    // Confirms the claims that a friend request was sent for after they were made, and returns
+    // them. Expired claims are removed. If more than one user claimed the same account, none of
+    // their claims are confirmed, as the request cannot tell which of them sent it.
+    pub fn match_friend_requests(
+        &self, requests: &[RobloxFriendRequest],
+    ) -> Vec<(UserId, FriendClaim)> {
+        let now = SystemTime::now();
+        let mut claims = self.0.friend_claims.lock();
+        claims.retain(|_, claim| claim.is_live(now));
+        let mut claim_counts = HashMap::new();
+        for claim in claims.values() {
+            *claim_counts.entry(claim.roblox_id).or_insert(0) += 1;
+        }
+        let mut matched = Vec::new();
+        for (&discord_id, claim) in claims.iter_mut() {
+            if !claim.confirmed && claim_counts[&claim.roblox_id] == 1 &&
+               requests.iter().any(|x| x.sender == claim.roblox_id &&
+                                       x.sent_at >= claim.claimed_at) {
+                claim.confirmed = true;
+                matched.push((discord_id, *claim));
+            }
+        }
+        matched
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::test_support::TestVerifier;
+    use roblox::mock;
+
+    macro_rules! assert_result {
+        ($result:expr, $pattern:pat) => {
+            match $result {
+                $pattern => { }
+                result => panic!("unexpected result: {:?}", result),
+            }
+        }
     }
-}
\ No newline at end of file
+
+    fn make_token(test: &TestVerifier, roblox_id: RobloxUserID) -> String {
+        let ctx = test.verifier.0.token_ctx.read();
+        let epoch = ctx.current.current_epoch(test.clock.now()).unwrap();
+        ctx.current.make_token(roblox_id.0, epoch).unwrap().to_string()
+    }
+
+    fn verify(
+        test: &TestVerifier, discord_id: u64, roblox_id: RobloxUserID, token: &str,
+    ) -> VerifyResult {
+        test.verifier.try_verify(None, UserId(discord_id), roblox_id, &TokenVerification, token)
+            .unwrap()
+    }
+
+    #[test]
+    fn valid_token_links_account() {
+        let test = TestVerifier::new();
+        let roblox_id = RobloxUserID(1001);
+        let token = make_token(&test, roblox_id);
+        assert_result!(verify(&test, 1, roblox_id, &token), VerifyResult::VerificationOk);
+        assert_eq!(test.verifier.get_verified_roblox_user(UserId(1)).unwrap(), Some(roblox_id));
+        assert_eq!(test.verifier.get_verified_discord_user(roblox_id).unwrap(), Some(UserId(1)));
+    }
+
+    #[test]
+    fn token_for_another_account_is_rejected() {
+        let test = TestVerifier::new();
+        let token = make_token(&test, RobloxUserID(1012));
+        assert_result!(verify(&test, 1, RobloxUserID(1011), &token), VerifyResult::InvalidToken);
+        assert_eq!(test.verifier.get_verified_roblox_user(UserId(1)).unwrap(), None);
+    }
+
+    #[test]
+    fn token_cannot_be_reused() {
+        let test = TestVerifier::new();
+        let roblox_id = RobloxUserID(1021);
+        let token = make_token(&test, roblox_id);
+        assert_result!(verify(&test, 1, roblox_id, &token), VerifyResult::VerificationOk);
+        assert_result!(verify(&test, 2, roblox_id, &token), VerifyResult::TokenAlreadyUsed);
+        assert_eq!(test.verifier.get_verified_roblox_user(UserId(2)).unwrap(), None);
+    }
+
+    #[test]
+    fn rekey_outdates_old_tokens() {
+        let test = TestVerifier::new();
+        let roblox_id = RobloxUserID(1031);
+        let old_token = make_token(&test, roblox_id);
+        assert!(test.verifier.rekey(true).unwrap());
+        assert_result!(verify(&test, 1, roblox_id, &old_token),
+                       VerifyResult::VerificationPlaceOutdated);
+        let new_token = make_token(&test, roblox_id);
+        assert_result!(verify(&test, 1, roblox_id, &new_token), VerifyResult::VerificationOk);
+    }
+
+    #[test]
+    fn cooldown_after_attempt_limit() {
+        let test = TestVerifier::new();
+        test.config.set_for_test(None, ConfigKeys::VerificationAttemptLimit, 2).unwrap();
+        test.config.set_for_test(None, ConfigKeys::VerificationCooldownSeconds, 600).unwrap();
+        let roblox_id = RobloxUserID(1041);
+        let wrong_token = make_token(&test, RobloxUserID(1042));
+        for _ in 0..2 {
+            assert_result!(verify(&test, 1, roblox_id, &wrong_token), VerifyResult::InvalidToken);
+        }
+        assert_result!(verify(&test, 1, roblox_id, &make_token(&test, roblox_id)),
+                       VerifyResult::TooManyAttempts { max_attempts: 2, cooldown: 600, .. });
+        let info = test.verifier.get_cooldown(None, UserId(1)).unwrap().unwrap();
+        assert!(info.cooldown_ends.is_some());
+
+        test.clock.advance(Duration::from_secs(601));
+        assert_result!(verify(&test, 1, roblox_id, &make_token(&test, roblox_id)),
+                       VerifyResult::VerificationOk);
+    }
+
+    #[test]
+    fn new_accounts_are_held_back() {
+        let test = TestVerifier::new();
+        test.config.set_for_test(None, ConfigKeys::MinimumRobloxAccountAgeDays, 30).unwrap();
+        // The test clock starts on 2017-07-14.
+        mock::respond("https://users.roblox.com/v1/users/1051", 200,
+                      r#"{"id":1051,"name":"New","created":"2017-07-01T00:00:00Z"}"#);
+        mock::respond("https://users.roblox.com/v1/users/1052", 200,
+                      r#"{"id":1052,"name":"Old","created":"2010-01-01T00:00:00Z"}"#);
+
+        let new_id = RobloxUserID(1051);
+        assert_result!(verify(&test, 1, new_id, &make_token(&test, new_id)),
+                       VerifyResult::AccountTooNew { minimum_days: 30, .. });
+        let old_id = RobloxUserID(1052);
+        assert_result!(verify(&test, 2, old_id, &make_token(&test, old_id)),
+                       VerifyResult::VerificationOk);
+    }
+}
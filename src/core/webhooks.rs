@@ -0,0 +1,201 @@
+use core::audit::AuditEvent;
+use core::config::*;
+use core::tasks::*;
+use errors::*;
+use hmac::{Hmac, Mac};
+use native_tls::TlsConnector;
+use reqwest::Url;
+use serde_json;
+use serenity::model::prelude::*;
+use sha2::Sha256;
+use std::fmt::Write as FmtWrite;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use util;
+
+const SIGNATURE_HEADER: &str = "X-Sylph-Signature";
+const WEBHOOK_TIMEOUT_SECS: u64 = 30;
+
+#[derive(Serialize)]
+struct WebhookPayload {
+    event: &'static str,
+    discord_id: String,
+    roblox_id: u64,
+    previous_roblox_id: Option<u64>,
+    timestamp: i64,
+}
+impl WebhookPayload {
+    fn from_event(discord_id: UserId, event: &AuditEvent) -> Option<WebhookPayload> {
+        let (event, roblox_id, previous_id) = match *event {
+            AuditEvent::Verified { roblox_id, previous_id: None, .. } =>
+                ("verified", roblox_id, None),
+            AuditEvent::Verified { roblox_id, previous_id, .. } =>
+                ("reverified", roblox_id, previous_id),
+            AuditEvent::Unverified { roblox_id } =>
+                ("unverified", roblox_id, None),
+            AuditEvent::LinkRestored { roblox_id } =>
+                ("restored", roblox_id, None),
+            AuditEvent::PrimaryAccountChanged { roblox_id, previous_id } =>
+                ("switched", roblox_id, Some(previous_id)),
+            AuditEvent::AttemptLimitReached { .. } |
+            AuditEvent::PossibleAltAccount { .. } => return None,
+        };
+        Some(WebhookPayload {
+            event,
+            discord_id: discord_id.0.to_string(),
+            roblox_id: roblox_id.0,
+            previous_roblox_id: previous_id.map(|x| x.0),
+            timestamp: util::time_to_i64(SystemTime::now()),
+        })
+    }
+}
+
+// Webhook URLs are set by server admins, so they may not point at the network the bot runs in.
+fn is_public_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let octets = ip.octets();
+            !(ip.is_private() || ip.is_loopback() || ip.is_link_local() || ip.is_broadcast() ||
+              ip.is_unspecified() || ip.is_multicast() || ip.is_documentation() ||
+              octets[0] == 0 || (octets[0] == 100 && octets[1] & 0xC0 == 64))
+        }
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+            match ip.to_ipv4() {
+                Some(ipv4) => is_public_address(IpAddr::V4(ipv4)),
+                None => !(ip.is_loopback() || ip.is_unspecified() || ip.is_multicast() ||
+                          segments[0] & 0xFE00 == 0xFC00 || segments[0] & 0xFFC0 == 0xFE80),
+            }
+        }
+    }
+}
+// Checks that a webhook URL uses HTTPS, and that its host only resolves to public addresses.
+// Returns the parsed URL, along with the addresses that were checked.
+fn resolve_webhook_url(url: &str) -> Result<(Url, Vec<SocketAddr>)> {
+    let url = Url::parse(url).to_cmd_err(|| "The webhook URL is not a valid URL.")?;
+    cmd_ensure!(url.scheme() == "https", "Webhook URL must start with `https://`.");
+    let addresses: Vec<_> = {
+        let host = webhook_host(&url)?;
+        let port = url.port_or_known_default().unwrap_or(443);
+        let addresses: Vec<_> = (host, port).to_socket_addrs()
+            .to_cmd_err(|| format!("Could not look up the webhook host '{}'.", host))?.collect();
+        cmd_ensure!(!addresses.is_empty(), "Could not look up the webhook host '{}'.", host);
+        addresses
+    };
+    cmd_ensure!(addresses.iter().all(|x| is_public_address(x.ip())),
+                "Webhooks cannot be sent to private or local network addresses.");
+    Ok((url, addresses))
+}
+fn webhook_host(url: &Url) -> Result<&str> {
+    Ok(url.host_str().to_cmd_err(|| "The webhook URL has no host name.")?
+        .trim_matches(|c| c == '[' || c == ']'))
+}
+pub fn check_webhook_url(url: &str) -> Result<()> {
+    resolve_webhook_url(url)?;
+    Ok(())
+}
+
+fn connect_any(addresses: &[SocketAddr]) -> Result<TcpStream> {
+    let timeout = Duration::from_secs(WEBHOOK_TIMEOUT_SECS);
+    let mut last_err = None;
+    for address in addresses {
+        match TcpStream::connect_timeout(address, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_err = Some(e),
+        }
+    }
+    Err(last_err?.into())
+}
+
+fn sign(secret: &str, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new(secret.as_bytes()).unwrap();
+    mac.input(body.as_bytes());
+    let mut signature = "sha256=".to_owned();
+    for byte in mac.result().code() {
+        write!(signature, "{:02x}", byte).unwrap();
+    }
+    signature
+}
+
+struct WebhookManagerData {
+    config: ConfigManager, tasks: TaskManager,
+}
+
+#[derive(Clone)]
+pub struct WebhookManager(Arc<WebhookManagerData>);
+impl WebhookManager {
+    pub fn new(config: ConfigManager, tasks: TaskManager) -> WebhookManager {
+        WebhookManager(Arc::new(WebhookManagerData { config, tasks }))
+    }
+
+    fn post(&self, url: &str, secret: Option<&str>, body: &str) -> Result<()> {
+        // The host is checked again when sending, as what it resolves to can change. The request
+        // is sent to the addresses that were checked, rather than looking the host up again, and
+        // redirects are not followed, so it cannot be sent somewhere that was not checked.
+        let (url, addresses) = resolve_webhook_url(url)?;
+        let host = webhook_host(&url)?;
+        let stream = connect_any(&addresses)?;
+        stream.set_read_timeout(Some(Duration::from_secs(WEBHOOK_TIMEOUT_SECS)))?;
+        stream.set_write_timeout(Some(Duration::from_secs(WEBHOOK_TIMEOUT_SECS)))?;
+        let mut stream = match TlsConnector::builder()?.build()?.connect(host, stream) {
+            Ok(stream) => stream,
+            Err(e) => bail!("Could not connect securely to {}: {}", host, e),
+        };
+
+        let mut target = url.path().to_owned();
+        if let Some(query) = url.query() {
+            target.push('?');
+            target.push_str(query);
+        }
+        let authority = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str()?, port),
+            None => url.host_str()?.to_owned(),
+        };
+        let mut request = format!("POST {} HTTP/1.1\r\nHost: {}\r\n", target, authority);
+        request.push_str("Content-Type: application/json\r\n");
+        if let Some(secret) = secret {
+            write!(request, "{}: {}\r\n", SIGNATURE_HEADER, sign(secret, body))?;
+        }
+        write!(request, "Content-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body)?;
+        stream.write_all(request.as_bytes())?;
+        stream.flush()?;
+
+        let mut status_line = String::new();
+        BufReader::new(stream).read_line(&mut status_line)?;
+        let status = status_line.split(' ').nth(1).and_then(|x| x.parse::<u16>().ok());
+        match status {
+            Some(status) if status >= 200 && status < 300 => Ok(()),
+            Some(status) => bail!("The webhook returned status {}.", status),
+            None => bail!("The webhook returned an invalid response."),
+        }
+    }
+
+    // Sends an event to the webhook of the server it happened in, if that server has one. Events
+    // from outside of a server, such as direct messages, are not sent anywhere.
+    pub fn notify(&self, guild_id: Option<GuildId>, discord_id: UserId, event: &AuditEvent) {
+        let guild_id = match guild_id {
+            Some(guild_id) => guild_id,
+            None => return,
+        };
+        let payload = match WebhookPayload::from_event(discord_id, event) {
+            Some(payload) => payload,
+            None => return,
+        };
+        let webhooks = self.clone();
+        self.0.tasks.dispatch_io_task(move |_| {
+            let url = match webhooks.0.config.get(Some(guild_id), ConfigKeys::WebhookUrl)? {
+                Some(url) => url,
+                None => return Ok(()),
+            };
+            let secret = webhooks.0.config.get(Some(guild_id), ConfigKeys::WebhookSecret)?;
+            let body = serde_json::to_string(&payload)?;
+            debug!("Sending {} webhook for <@{}> to {}", payload.event, discord_id, url);
+            if let Err(e) = webhooks.post(&url, secret.as_ref().map(|x| x.as_str()), &body) {
+                warn!("Could not send webhook to {}: {}", url, e);
+            }
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,87 @@
+use chrono::Utc;
+use database::{Database, CURRENT_VERSION, FUTURE_VERSION_ERR};
+use errors::*;
+use rusqlite::{Connection, DatabaseName, OpenFlags};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn timestamp() -> String {
+    Utc::now().format("%Y%m%d_%H%M%S").to_string()
+}
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+impl Database {
+    // Writes a consistent snapshot of the database to a new file in `dir`. This uses SQLite's
+    // online backup API, so the bot can continue running while the backup is made.
+    pub fn backup(&self, dir: &Path) -> Result<PathBuf> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(format!("Sylph-Verifier_{}.db", timestamp()));
+        ensure!(!path.exists(), "Backup file '{}' already exists.", path.display());
+
+        let conn = self.connect()?;
+        conn.conn.backup(DatabaseName::Main, &path, None)?;
+        Ok(path)
+    }
+}
+
+fn check_backup(path: &Path) -> Result<u32> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let get_meta = |key: &str| -> Result<Option<i64>> {
+        let mut stat = conn.prepare("SELECT value FROM sylph_verifier_meta WHERE key = ?1")?;
+        let mut rows = stat.query(&[&key])?;
+        match rows.next() {
+            Some(row) => Ok(Some(row?.get_checked(0)?)),
+            None => Ok(None),
+        }
+    };
+
+    let meta_table_exists = conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type='table' AND name='sylph_verifier_meta';",
+        &[], |row| row.get::<_, i64>(0),
+    )? != 0;
+    ensure!(meta_table_exists, "'{}' is not a Sylph-Verifier database.", path.display());
+
+    let meta_version = get_meta("meta_version")?;
+    ensure!(meta_version == Some(1), FUTURE_VERSION_ERR);
+    let schema_version = match get_meta("schema_version")? {
+        Some(version) if version >= 0 => version as u32,
+        _ => bail!("'{}' does not have a valid schema version.", path.display()),
+    };
+    ensure!(schema_version <= CURRENT_VERSION, FUTURE_VERSION_ERR);
+
+    let integrity = conn.query_row("PRAGMA integrity_check", &[], |row| row.get::<_, String>(0))?;
+    ensure!(integrity == "ok", "'{}' failed the integrity check: {}", path.display(), integrity);
+
+    Ok(schema_version)
+}
+
+// Replaces the database at `db_path` with a backup. This must only be called while the bot is not
+// running. The replaced database is renamed rather than deleted, and its path is returned.
+//
+// Older schema versions are accepted, as they are migrated as usual on the next startup.
+pub fn restore(db_path: &Path, backup_path: &Path) -> Result<(u32, Option<PathBuf>)> {
+    ensure!(backup_path.is_file(), "Backup file '{}' does not exist.", backup_path.display());
+    let schema_version = check_backup(backup_path)?;
+
+    let old_path = if db_path.exists() {
+        let old_path = with_suffix(db_path, &format!(".before_restore_{}", timestamp()));
+        fs::rename(db_path, &old_path)?;
+        for suffix in &["-wal", "-shm"] {
+            let journal = with_suffix(db_path, suffix);
+            if journal.exists() {
+                fs::rename(&journal, with_suffix(&old_path, suffix))?;
+            }
+        }
+        Some(old_path)
+    } else {
+        None
+    };
+    fs::copy(backup_path, db_path)?;
+
+    Ok((schema_version, old_path))
+}
@@ -0,0 +1,71 @@
+use database::{Database, DatabaseConnection, ToSqlArgs};
+use errors::*;
+use rusqlite::types::{ToSql as RusqliteToSql, ToSqlOutput, Value};
+
+// SQLite does not allow more parameters than this in one statement.
+const MAX_PARAMETERS: usize = 999;
+// Large writes are committed in chunks of this many items, so other writers such as running
+// verifications do not have to wait for the entire write to finish.
+const ITEMS_PER_TRANSACTION: usize = 2000;
+
+fn to_values<T: ToSqlArgs>(row: &T) -> Result<Vec<Value>> {
+    row.to_sql_args(|args| {
+        let mut values = Vec::new();
+        for arg in args {
+            values.push(match arg.to_sql()? {
+                ToSqlOutput::Borrowed(value) => value.into(),
+                ToSqlOutput::Owned(value) => value,
+            });
+        }
+        Ok(values)
+    })
+}
+
+impl DatabaseConnection {
+    // Inserts many rows with as few statements as possible. `sql` is the start of an INSERT or
+    // REPLACE statement without its VALUES clause, e.g. `INSERT INTO table (a, b)`.
+    pub fn insert_batch<T: ToSqlArgs>(&self, sql: &str, rows: &[T]) -> Result<usize> {
+        if rows.is_empty() {
+            return Ok(0)
+        }
+        let mut values = Vec::new();
+        for row in rows {
+            values.push(to_values(row)?);
+        }
+        let columns = values[0].len();
+        ensure!(columns != 0 && columns <= MAX_PARAMETERS, "Invalid row size for a batch insert.");
+        ensure!(values.iter().all(|x| x.len() == columns),
+                "Rows in a batch insert differ in size.");
+
+        let row_sql = format!("({})", vec!["?"; columns].join(", "));
+        self.transaction(|| {
+            let mut inserted = 0;
+            for chunk in values.chunks(MAX_PARAMETERS / columns) {
+                let chunk_sql =
+                    format!("{} VALUES {}", sql, vec![row_sql.as_str(); chunk.len()].join(", "));
+                let params: Vec<&RusqliteToSql> =
+                    chunk.iter().flat_map(|x| x.iter()).map(|x| x as &RusqliteToSql).collect();
+                inserted += self.conn.prepare_cached(&chunk_sql)?.execute(&params)? as usize;
+            }
+            Ok(inserted)
+        })
+    }
+}
+
+impl Database {
+    // Runs `f` on chunks of a large number of items, each in its own transaction. This keeps
+    // mass writes from holding the write lock for long, and checkpoints the log afterwards so it
+    // does not grow too large.
+    pub fn write_in_chunks<T, F>(
+        &self, items: &[T], mut f: F,
+    ) -> Result<()> where F: FnMut(&DatabaseConnection, &[T]) -> Result<()> {
+        let conn = self.connect()?;
+        for chunk in items.chunks(ITEMS_PER_TRANSACTION) {
+            conn.transaction_immediate(|| f(&conn, chunk))?;
+        }
+        if items.len() > ITEMS_PER_TRANSACTION {
+            conn.checkpoint_passive()?;
+        }
+        Ok(())
+    }
+}
@@ -1,4 +1,6 @@
 use errors::*;
+use logger;
+use parking_lot::Mutex;
 use r2d2::{Pool, ManageConnection, PooledConnection};
 use rusqlite::{Connection, OpenFlags, Rows, TransactionBehavior,
                Row as RusqliteRow, Result as RusqliteResult, Error as RusqliteError};
@@ -8,11 +10,16 @@ use std::cell::Cell;
 use std::ops::{Deref, DerefMut};
 use std::path::{Path, PathBuf};
 use std::thread::panicking;
-use std::time;
+use std::time::{Duration, Instant, SystemTime};
 use std::sync::Arc;
+#[cfg(test)] use std::sync::atomic::{AtomicUsize, ATOMIC_USIZE_INIT, Ordering};
 
+mod backup;
+mod batch;
 mod impls;
 
+pub use self::backup::restore;
+
 pub use rusqlite::types::{ToSqlOutput, Value, ValueRef};
 
 pub trait FromSql: Sized {
@@ -75,11 +82,11 @@ pub struct QueryDSL<'a, 'b, T> {
 impl <'a, 'b, T: ToSqlArgs> QueryDSL<'a, 'b, T> {
     fn do_op<F, R>(&self, f: F) -> Result<R> where F: FnOnce(RowsWrapper) -> Result<R> {
         self.args.to_sql_args(|args| if self.cache {
-            let mut stat = self.conn.conn.prepare(self.sql)?;
+            let mut stat = self.conn.conn.prepare_cached(self.sql)?;
             let row = RowsWrapper(stat.query(args)?);
             f(row)
         } else {
-            let mut stat = self.conn.conn.prepare_cached(self.sql)?;
+            let mut stat = self.conn.conn.prepare(self.sql)?;
             let row = RowsWrapper(stat.query(args)?);
             f(row)
         })
@@ -123,13 +130,33 @@ impl DerefMut for SqliteConnection {
     }
 }
 
+// The most connections kept open at once, and the fewest kept open while the bot is idle.
+const MAX_CONNECTIONS: u32 = 15;
+const MIN_IDLE_CONNECTIONS: u32 = 2;
+// The same, for the read-only connections used by lookups.
+const MAX_READ_CONNECTIONS: u32 = 10;
+const MIN_IDLE_READ_CONNECTIONS: u32 = 1;
+const STATEMENT_CACHE_SIZE: usize = 128;
+// Waiting longer than this for a connection is counted as a slow checkout.
+const SLOW_CHECKOUT_MS: u64 = 100;
+
+#[derive(Copy, Clone, Default, Debug)]
+pub struct DatabaseMetrics {
+    pub connections: u32, pub idle_connections: u32, pub max_connections: u32,
+    pub read_connections: u32, pub read_idle_connections: u32, pub max_read_connections: u32,
+    pub opened: u64, pub checkouts: u64, pub slow_checkouts: u64,
+    pub total_wait: Duration, pub max_wait: Duration,
+}
+
 struct ConnectionManager {
-    db_file: PathBuf,
+    db_file: PathBuf, metrics: Arc<Mutex<DatabaseMetrics>>, read_only: bool,
 }
 impl ConnectionManager {
-    fn new(path: &Path) -> Result<ConnectionManager> {
+    fn new(
+        path: &Path, metrics: Arc<Mutex<DatabaseMetrics>>, read_only: bool,
+    ) -> Result<ConnectionManager> {
         Ok(ConnectionManager {
-            db_file: path.to_owned(),
+            db_file: path.to_owned(), metrics, read_only,
         })
     }
 }
@@ -138,11 +165,22 @@ impl ManageConnection for ConnectionManager {
     type Error = RusqliteError;
 
     fn connect(&self) -> RusqliteResult<SqliteConnection> {
-        let conn = Connection::open_with_flags(&self.db_file,
-            OpenFlags::SQLITE_OPEN_READ_WRITE |
-            OpenFlags::SQLITE_OPEN_CREATE)?;
-        conn.set_prepared_statement_cache_capacity(64);
-        conn.execute_batch(include_str!("setup_connection.sql"))?;
+        // Tests open databases that only exist in memory by URI.
+        let uri = if cfg!(test) { OpenFlags::SQLITE_OPEN_URI } else { OpenFlags::empty() };
+        let conn = if self.read_only {
+            let conn = Connection::open_with_flags(&self.db_file,
+                OpenFlags::SQLITE_OPEN_READ_ONLY | uri)?;
+            conn.execute_batch(include_str!("setup_read_connection.sql"))?;
+            conn
+        } else {
+            let conn = Connection::open_with_flags(&self.db_file,
+                OpenFlags::SQLITE_OPEN_READ_WRITE |
+                OpenFlags::SQLITE_OPEN_CREATE | uri)?;
+            conn.execute_batch(include_str!("setup_connection.sql"))?;
+            conn
+        };
+        conn.set_prepared_statement_cache_capacity(STATEMENT_CACHE_SIZE);
+        self.metrics.lock().opened += 1;
         Ok(SqliteConnection { conn, is_poisoned: Cell::new(false) })
     }
     fn is_valid(&self, conn: &mut SqliteConnection) -> RusqliteResult<()> {
@@ -154,7 +192,7 @@ impl ManageConnection for ConnectionManager {
         Ok(())
     }
     fn has_broken(&self, conn: &mut SqliteConnection) -> bool {
-        !conn.is_poisoned.get()
+        conn.is_poisoned.get()
     }
 }
 
@@ -191,6 +229,7 @@ impl DatabaseConnection {
     ) -> Result<T> where F: FnOnce() -> Result<T> {
         let cur_depth = self.transaction_depth.get();
         if cur_depth == 0 {
+            let _span = logger::span("transaction");
             let sql = match behavior {
                 TransactionBehavior::Deferred  => "BEGIN DEFERRED",
                 TransactionBehavior::Immediate => "BEGIN IMMEDIATE",
@@ -255,6 +294,11 @@ impl DatabaseConnection {
         self.conn.query_row("PRAGMA wal_checkpoint(RESTART)", &[], |_| ())?;
         Ok(())
     }
+    // Checkpoints as much of the log as possible without waiting for readers or writers.
+    pub fn checkpoint_passive(&self) -> Result<()> {
+        self.conn.query_row("PRAGMA wal_checkpoint(PASSIVE)", &[], |_| ())?;
+        Ok(())
+    }
 }
 
 struct Migration {
@@ -267,33 +311,126 @@ macro_rules! migration {
 }
 static MIGRATIONS: &'static [Migration] = &[
     migration!(0, 2, "version_0_to_2.sql"),
+    migration!(2, 3, "version_2_to_3.sql"),
+    migration!(3, 4, "version_3_to_4.sql"),
+    migration!(4, 5, "version_4_to_5.sql"),
+    migration!(5, 6, "version_5_to_6.sql"),
+    migration!(6, 7, "version_6_to_7.sql"),
+    migration!(7, 8, "version_7_to_8.sql"),
+    migration!(8, 9, "version_8_to_9.sql"),
+    migration!(9, 10, "version_9_to_10.sql"),
+    migration!(10, 11, "version_10_to_11.sql"),
+    migration!(11, 12, "version_11_to_12.sql"),
+    migration!(12, 13, "version_12_to_13.sql"),
+    migration!(13, 14, "version_13_to_14.sql"),
+    migration!(14, 15, "version_14_to_15.sql"),
+    migration!(15, 16, "version_15_to_16.sql"),
+    migration!(16, 17, "version_16_to_17.sql"),
+    migration!(17, 18, "version_17_to_18.sql"),
+    migration!(18, 19, "version_18_to_19.sql"),
+    migration!(19, 20, "version_19_to_20.sql"),
+    migration!(20, 21, "version_20_to_21.sql"),
+    migration!(21, 22, "version_21_to_22.sql"),
+    migration!(22, 23, "version_22_to_23.sql"),
+    migration!(23, 24, "version_23_to_24.sql"),
+    migration!(24, 25, "version_24_to_25.sql"),
+    migration!(25, 26, "version_25_to_26.sql"),
+    migration!(26, 27, "version_26_to_27.sql"),
+    migration!(27, 28, "version_27_to_28.sql"),
+    migration!(28, 29, "version_28_to_29.sql"),
 ];
-const CURRENT_VERSION: u32 = 2;
+const CURRENT_VERSION: u32 = 29;
 const FUTURE_VERSION_ERR: &str = "This database was created for a future version of this bot. \
                                   Please restore an older version of the database from a backup.";
 
+// A pool of connections shared by everything using the database. Connections are kept open
+// between uses, so each one only runs the setup in `setup_connection.sql` and prepares its
+// statements once.
+//
+// Lookups that do not change anything can use a separate pool of read-only connections, so
+// that they do not hold up connections needed for verifications and other changes.
 #[derive(Clone)]
 pub struct Database {
-    pool: Arc<Pool<ConnectionManager>>,
+    pool: Arc<Pool<ConnectionManager>>, read_pool: Arc<Pool<ConnectionManager>>,
+    metrics: Arc<Mutex<DatabaseMetrics>>,
 }
 impl Database {
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Database> {
+        let metrics = Arc::new(Mutex::new(DatabaseMetrics::default()));
         let pool = Arc::new(Pool::builder()
-            .max_size(15)
-            .idle_timeout(Some(time::Duration::from_secs(60 * 5)))
-            .build(ConnectionManager::new(path.as_ref())?)?);
-        let database = Database { pool };
-        database.init_db()?;
-        Ok(database)
+            .max_size(MAX_CONNECTIONS)
+            .min_idle(Some(MIN_IDLE_CONNECTIONS))
+            .idle_timeout(Some(Duration::from_secs(60 * 5)))
+            .build(ConnectionManager::new(path.as_ref(), metrics.clone(), false)?)?);
+        // Read-only connections cannot create the database, so it is set up first.
+        Self::init_db(&DatabaseConnection::new(pool.get()?))?;
+        let read_pool = Arc::new(Pool::builder()
+            .max_size(MAX_READ_CONNECTIONS)
+            .min_idle(Some(MIN_IDLE_READ_CONNECTIONS))
+            .idle_timeout(Some(Duration::from_secs(60 * 5)))
+            .build(ConnectionManager::new(path.as_ref(), metrics.clone(), true)?)?);
+        Ok(Database { pool, read_pool, metrics })
+    }
+
+    // Opens a new database that only exists in memory, for tests. It is shared by every
+    // connection to it, and removed once the last one is closed.
+    #[cfg(test)]
+    pub fn in_memory() -> Result<Database> {
+        static NEXT_ID: AtomicUsize = ATOMIC_USIZE_INIT;
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        Database::new(format!("file:sylph_test_{}?mode=memory&cache=shared", id))
     }
 
     pub fn connect(&self) -> Result<DatabaseConnection> {
-        Ok(DatabaseConnection::new(self.pool.get()?))
+        self.checkout(&self.pool)
+    }
+    // Returns a connection that can only be used to read from the database.
+    pub fn connect_read(&self) -> Result<DatabaseConnection> {
+        self.checkout(&self.read_pool)
+    }
+    fn checkout(&self, pool: &Pool<ConnectionManager>) -> Result<DatabaseConnection> {
+        let start = Instant::now();
+        let conn = pool.get()?;
+        let wait = start.elapsed();
+
+        let mut metrics = self.metrics.lock();
+        metrics.checkouts += 1;
+        metrics.total_wait += wait;
+        if wait > metrics.max_wait {
+            metrics.max_wait = wait;
+        }
+        if wait >= Duration::from_millis(SLOW_CHECKOUT_MS) {
+            metrics.slow_checkouts += 1;
+        }
+        Ok(DatabaseConnection::new(conn))
+    }
+
+    pub fn metrics(&self) -> DatabaseMetrics {
+        let state = self.pool.state();
+        let read_state = self.read_pool.state();
+        DatabaseMetrics {
+            connections: state.connections, idle_connections: state.idle_connections,
+            max_connections: self.pool.max_size(),
+            read_connections: read_state.connections,
+            read_idle_connections: read_state.idle_connections,
+            max_read_connections: self.read_pool.max_size(),
+            ..*self.metrics.lock()
+        }
     }
 
-    fn init_db(&self) -> Result<()> {
-        let conn = self.connect()?;
+    // Checks that the database can be read from and written to, for health checks.
+    pub fn check_health(&self) -> Result<()> {
+        self.connect_read()?.query(
+            "SELECT value FROM sylph_verifier_meta WHERE key = 'schema_version';", ()
+        ).get::<u32>()?;
+        self.connect()?.execute(
+            "REPLACE INTO sylph_verifier_meta (key, value) VALUES ('last_health_check', ?1);",
+            SystemTime::now(),
+        )?;
+        Ok(())
+    }
 
+    fn init_db(conn: &DatabaseConnection) -> Result<()> {
         conn.transaction_exclusive(|| {
             let meta_table_exists = conn.query(
                 "SELECT COUNT(*) FROM sqlite_master \
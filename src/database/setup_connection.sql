@@ -1,4 +1,5 @@
 PRAGMA busy_timeout = 5000;
 PRAGMA wal_autocheckpoint = 250;
 PRAGMA foreign_keys = true;
-PRAGMA journal_mode = wal;
\ No newline at end of file
+PRAGMA journal_mode = wal;
+PRAGMA synchronous = normal;
\ No newline at end of file
@@ -0,0 +1,2 @@
+PRAGMA busy_timeout = 5000;
+PRAGMA query_only = true;
\ No newline at end of file
@@ -0,0 +1,8 @@
+BEGIN EXCLUSIVE;
+  -- Stores the state of background jobs run by the scheduler. A NULL schedule means the job uses
+  -- its default schedule.
+  CREATE TABLE scheduled_jobs (
+    job_name TEXT PRIMARY KEY, schedule TEXT, last_run TIMESTAMP, next_run TIMESTAMP NOT NULL,
+    failure_count INTEGER NOT NULL DEFAULT 0, last_error TEXT
+  );
+COMMIT;
@@ -0,0 +1,12 @@
+BEGIN EXCLUSIVE;
+  -- Stores approval requests in servers that require staff approval before verified users are
+  -- given roles. Reviewed requests are kept, so approved users keep their roles, and rejected
+  -- users do not get them back by updating.
+  CREATE TABLE pending_verifications (
+    discord_guild_id BIGINT NOT NULL, discord_user_id BIGINT NOT NULL,
+    roblox_user_id BIGINT NOT NULL, status TEXT NOT NULL, requested_at TIMESTAMP NOT NULL,
+    staff_channel_id BIGINT, staff_message_id BIGINT,
+    PRIMARY KEY (discord_guild_id, discord_user_id)
+  );
+  CREATE INDEX pending_verifications_status_idx ON pending_verifications (discord_guild_id, status);
+COMMIT;
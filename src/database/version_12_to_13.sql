@@ -0,0 +1,11 @@
+BEGIN EXCLUSIVE;
+  -- Records verification attempts, so Discord accounts that try to verify as the same Roblox
+  -- account can be found. token_valid is set when the attempt proved control of the account.
+  CREATE TABLE verification_attempts (
+    discord_user_id BIGINT NOT NULL, roblox_user_id BIGINT NOT NULL, discord_guild_id BIGINT,
+    token_valid BOOLEAN NOT NULL, attempted_at TIMESTAMP NOT NULL
+  );
+  CREATE INDEX verification_attempts_roblox_idx ON verification_attempts (roblox_user_id);
+  CREATE INDEX verification_attempts_discord_idx ON verification_attempts (discord_user_id);
+  CREATE INDEX verification_attempts_time_idx ON verification_attempts (attempted_at);
+COMMIT;
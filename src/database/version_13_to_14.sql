@@ -0,0 +1,7 @@
+BEGIN EXCLUSIVE;
+  -- Records the outcome of each verification attempt, so moderators can review a user's history.
+  -- Attempts recorded before this version do not have this information.
+  ALTER TABLE verification_attempts ADD COLUMN method TEXT;
+  ALTER TABLE verification_attempts ADD COLUMN result TEXT;
+  ALTER TABLE verification_attempts ADD COLUMN token_status TEXT;
+COMMIT;
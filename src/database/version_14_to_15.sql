@@ -0,0 +1,9 @@
+BEGIN EXCLUSIVE;
+  -- Tracks the warnings and kicks or timeouts given to members who have not verified within a
+  -- server's grace period. Rows are removed once the member verifies.
+  CREATE TABLE unverified_enforcement (
+    discord_guild_id BIGINT NOT NULL, discord_user_id BIGINT NOT NULL,
+    warned_at TIMESTAMP, actioned_at TIMESTAMP,
+    PRIMARY KEY (discord_guild_id, discord_user_id)
+  );
+COMMIT;
@@ -0,0 +1,11 @@
+BEGIN EXCLUSIVE;
+  -- Stores the Roblox accounts linked to Discord users when they were banned from a server, so
+  -- the same Roblox accounts cannot be verified there again from another Discord account.
+  CREATE TABLE banned_roblox_ids (
+    discord_guild_id BIGINT NOT NULL, roblox_user_id BIGINT NOT NULL,
+    discord_user_id BIGINT NOT NULL, banned_at TIMESTAMP NOT NULL,
+    PRIMARY KEY (discord_guild_id, roblox_user_id)
+  );
+  CREATE INDEX banned_roblox_ids_discord_idx
+    ON banned_roblox_ids (discord_guild_id, discord_user_id);
+COMMIT;
@@ -0,0 +1,13 @@
+BEGIN EXCLUSIVE;
+  -- Stores which servers a server trusts. Users who verified in a server are given roles on
+  -- joining another without updating when both servers trust each other.
+  CREATE TABLE guild_trust (
+    discord_guild_id BIGINT NOT NULL, trusted_guild_id BIGINT NOT NULL,
+    added_at TIMESTAMP NOT NULL,
+    PRIMARY KEY (discord_guild_id, trusted_guild_id)
+  ) WITHOUT ROWID;
+
+  -- The server a user last verified in. NULL for verifications made in direct messages, or
+  -- before this version.
+  ALTER TABLE discord_user_info ADD COLUMN verified_guild_id BIGINT;
+COMMIT;
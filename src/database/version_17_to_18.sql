@@ -0,0 +1,6 @@
+BEGIN EXCLUSIVE;
+  -- The length and alphabet of the tokens made with a key. A NULL alphabet means the default
+  -- alphabet for the key's token format version.
+  ALTER TABLE verification_keys ADD COLUMN token_length INTEGER NOT NULL DEFAULT 6;
+  ALTER TABLE verification_keys ADD COLUMN token_alphabet TEXT;
+COMMIT;
@@ -0,0 +1,9 @@
+BEGIN EXCLUSIVE;
+  -- Permission nodes granted to roles or users in a server, letting them use commands that
+  -- otherwise require Discord permissions they do not have.
+  CREATE TABLE command_permission_grants (
+    discord_guild_id BIGINT NOT NULL, is_role BOOLEAN NOT NULL, target_id BIGINT NOT NULL,
+    node TEXT NOT NULL,
+    PRIMARY KEY (discord_guild_id, is_role, target_id, node)
+  ) WITHOUT ROWID;
+COMMIT;
@@ -0,0 +1,11 @@
+BEGIN EXCLUSIVE;
+  -- Links that have been removed, kept so accidental unverifications can be undone and as an
+  -- audit trail. Rows removed together share the same unlinked_at.
+  CREATE TABLE discord_user_unlinked_accounts (
+    id INTEGER PRIMARY KEY,
+    discord_user_id BIGINT NOT NULL, roblox_user_id BIGINT NOT NULL, is_primary BOOLEAN NOT NULL,
+    linked_at TIMESTAMP NOT NULL, unlinked_at TIMESTAMP NOT NULL, restored_at TIMESTAMP
+  );
+  CREATE INDEX discord_user_unlinked_accounts_discord_user_id_idx
+    ON discord_user_unlinked_accounts (discord_user_id, unlinked_at);
+COMMIT;
@@ -0,0 +1,5 @@
+BEGIN EXCLUSIVE;
+  -- Keys revoked by an emergency rekey. Tokens made with them are rejected outright, rather than
+  -- being reported as outdated.
+  ALTER TABLE verification_keys ADD COLUMN revoked_at TIMESTAMP;
+COMMIT;
@@ -0,0 +1,13 @@
+BEGIN EXCLUSIVE;
+  -- Security relevant events, each signed together with the signature of the entry before it,
+  -- so that entries being changed or removed can be detected.
+  CREATE TABLE event_log (
+    id INTEGER PRIMARY KEY,
+    recorded_at TIMESTAMP NOT NULL, event_type TEXT NOT NULL, details TEXT NOT NULL,
+    signature BLOB NOT NULL
+  );
+  CREATE TRIGGER event_log_no_update BEFORE UPDATE ON event_log
+    BEGIN SELECT RAISE(ABORT, 'event_log is append-only'); END;
+  CREATE TRIGGER event_log_no_delete BEFORE DELETE ON event_log
+    BEGIN SELECT RAISE(ABORT, 'event_log is append-only'); END;
+COMMIT;
@@ -0,0 +1,11 @@
+BEGIN EXCLUSIVE;
+  -- Destructive admin actions waiting to be confirmed by a second admin.
+  CREATE TABLE pending_admin_actions (
+    id INTEGER PRIMARY KEY,
+    action TEXT NOT NULL, argument TEXT,
+    requested_by BIGINT NOT NULL, requested_at TIMESTAMP NOT NULL, expires_at TIMESTAMP NOT NULL,
+    status TEXT NOT NULL, reviewed_by BIGINT,
+    channel_id BIGINT, message_id BIGINT
+  );
+  CREATE INDEX pending_admin_actions_status_idx ON pending_admin_actions (status, expires_at);
+COMMIT;
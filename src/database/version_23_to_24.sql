@@ -0,0 +1,8 @@
+BEGIN EXCLUSIVE;
+  -- Members whose verification roles were taken away for not updating them recently. A
+  -- suspension ends when the member updates their roles or verifies again.
+  CREATE TABLE suspended_verifications (
+    discord_guild_id BIGINT, discord_user_id BIGINT, suspended_at TIMESTAMP NOT NULL,
+    PRIMARY KEY (discord_guild_id, discord_user_id)
+  ) WITHOUT ROWID;
+COMMIT;
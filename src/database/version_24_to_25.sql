@@ -0,0 +1,9 @@
+BEGIN EXCLUSIVE;
+  -- Stores daily counters of how often each command was used, how long it took to run and how
+  -- it ended.
+  CREATE TABLE command_stats (
+    day BIGINT, command TEXT, outcome TEXT,
+    count BIGINT NOT NULL, total_millis BIGINT NOT NULL, max_millis BIGINT NOT NULL,
+    PRIMARY KEY (day, command, outcome)
+  ) WITHOUT ROWID;
+COMMIT;
@@ -0,0 +1,8 @@
+BEGIN EXCLUSIVE;
+  -- Which instance is the leader when several share the database. The leader renews its lease
+  -- before it expires, and another instance takes over if it does not.
+  CREATE TABLE leader_lease (
+    lease_name TEXT PRIMARY KEY, instance_id TEXT NOT NULL,
+    acquired_at TIMESTAMP NOT NULL, expires_at TIMESTAMP NOT NULL
+  ) WITHOUT ROWID;
+COMMIT;
@@ -0,0 +1,14 @@
+BEGIN EXCLUSIVE;
+  -- Tracks how many reminders to verify have been sent to unverified members of each server.
+  -- Rows are removed once the member verifies.
+  CREATE TABLE verification_reminders (
+    discord_guild_id BIGINT NOT NULL, discord_user_id BIGINT NOT NULL,
+    stage INTEGER NOT NULL, reminded_at TIMESTAMP NOT NULL,
+    PRIMARY KEY (discord_guild_id, discord_user_id)
+  );
+
+  -- Users who asked not to be sent reminders to verify.
+  CREATE TABLE verification_reminder_opt_outs (
+    discord_user_id BIGINT PRIMARY KEY, opted_out_at TIMESTAMP NOT NULL
+  );
+COMMIT;
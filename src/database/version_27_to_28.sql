@@ -0,0 +1,11 @@
+BEGIN EXCLUSIVE;
+  -- Roles given to members for a limited time without verifying, such as to visitors of an
+  -- event. Rows are removed when the pass expires or is revoked.
+  CREATE TABLE guest_passes (
+    discord_guild_id BIGINT NOT NULL, discord_user_id BIGINT NOT NULL,
+    discord_role_id BIGINT NOT NULL, granted_by BIGINT NOT NULL,
+    granted_at TIMESTAMP NOT NULL, expires_at TIMESTAMP NOT NULL,
+    PRIMARY KEY (discord_guild_id, discord_user_id)
+  );
+  CREATE INDEX guest_passes_expires_at_idx ON guest_passes (expires_at);
+COMMIT;
@@ -0,0 +1,13 @@
+BEGIN EXCLUSIVE;
+  -- Verification statistics are counted separately for each server, with a server ID of 0 for
+  -- verifications made outside of a server. Counts from before this are kept with a server ID
+  -- of 0, so they only appear in the totals for every server.
+  ALTER TABLE verification_stats RENAME TO verification_stats_old;
+  CREATE TABLE verification_stats (
+    day BIGINT, discord_guild_id BIGINT, stat TEXT, count BIGINT NOT NULL,
+    PRIMARY KEY (day, discord_guild_id, stat)
+  ) WITHOUT ROWID;
+  INSERT INTO verification_stats (day, discord_guild_id, stat, count)
+    SELECT day, 0, stat, count FROM verification_stats_old;
+  DROP TABLE verification_stats_old;
+COMMIT;
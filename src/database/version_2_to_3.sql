@@ -0,0 +1,7 @@
+BEGIN EXCLUSIVE;
+  -- Roblox accounts and groups that are not allowed to verify in a Discord guild.
+  CREATE TABLE guild_blacklist (
+    discord_guild_id BIGINT, is_group BOOL, roblox_id BIGINT, reason TEXT,
+    PRIMARY KEY (discord_guild_id, is_group, roblox_id)
+  ) WITHOUT ROWID;
+COMMIT;
@@ -0,0 +1,11 @@
+BEGIN EXCLUSIVE;
+  -- Stores additional Roblox accounts linked to a Discord user. The primary account is still
+  -- stored in discord_user_info.
+  CREATE TABLE discord_user_alt_accounts (
+    roblox_user_id BIGINT PRIMARY KEY, discord_user_id BIGINT NOT NULL,
+    last_updated TIMESTAMP NOT NULL,
+    FOREIGN KEY (roblox_user_id) REFERENCES roblox_user_info (roblox_user_id)
+  ) WITHOUT ROWID;
+  CREATE INDEX discord_user_alt_accounts_discord_user_id_idx
+    ON discord_user_alt_accounts (discord_user_id);
+COMMIT;
@@ -0,0 +1,7 @@
+BEGIN EXCLUSIVE;
+  -- Stores daily counters for verification statistics.
+  CREATE TABLE verification_stats (
+    day BIGINT, stat TEXT, count BIGINT NOT NULL,
+    PRIMARY KEY (day, stat)
+  ) WITHOUT ROWID;
+COMMIT;
@@ -0,0 +1,5 @@
+BEGIN EXCLUSIVE;
+  -- Records when each verification key was created, for scheduled key rotation.
+  ALTER TABLE verification_keys ADD COLUMN created TIMESTAMP;
+  UPDATE verification_keys SET created = CAST(strftime('%s', 'now') AS INTEGER);
+COMMIT;
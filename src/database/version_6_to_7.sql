@@ -0,0 +1,7 @@
+BEGIN EXCLUSIVE;
+  -- Stores the message users can react to in order to start verifying in direct messages.
+  CREATE TABLE verify_panel_info (
+    discord_guild_id BIGINT PRIMARY KEY,
+    discord_channel_id BIGINT NOT NULL, panel_message_id BIGINT NOT NULL
+  ) WITHOUT ROWID;
+COMMIT;
@@ -0,0 +1,9 @@
+BEGIN EXCLUSIVE;
+  -- Caches the usernames of Roblox users, including past usernames, so commands can accept a
+  -- username a user has since changed.
+  CREATE TABLE roblox_usernames (
+    username TEXT PRIMARY KEY COLLATE NOCASE, roblox_user_id BIGINT NOT NULL,
+    is_current BOOL NOT NULL, last_updated TIMESTAMP NOT NULL
+  ) WITHOUT ROWID;
+  CREATE INDEX roblox_usernames_roblox_user_id_idx ON roblox_usernames (roblox_user_id);
+COMMIT;
@@ -0,0 +1,9 @@
+BEGIN EXCLUSIVE;
+  -- Stores username changes of Roblox users that were detected by the bot.
+  CREATE TABLE roblox_username_history (
+    roblox_user_id BIGINT NOT NULL, old_username TEXT NOT NULL, new_username TEXT NOT NULL,
+    changed_at TIMESTAMP NOT NULL
+  );
+  CREATE INDEX roblox_username_history_roblox_user_id_idx
+    ON roblox_username_history (roblox_user_id);
+COMMIT;
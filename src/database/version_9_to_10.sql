@@ -0,0 +1,7 @@
+BEGIN EXCLUSIVE;
+  -- Stores per-guild replacements for messages shown to users while verifying.
+  CREATE TABLE guild_message_templates (
+    discord_guild_id BIGINT NOT NULL, message_key TEXT NOT NULL, template TEXT NOT NULL,
+    PRIMARY KEY (discord_guild_id, message_key)
+  );
+COMMIT;
@@ -5,13 +5,21 @@ use failure::Fail;
 use logger;
 use parking_lot::RwLock;
 use parking_lot::deadlock::check_deadlock;
+use rand::{Rng, thread_rng};
+use regex::Regex;
+use reqwest;
+use reqwest::Url;
+use reqwest::header::{ContentType, Headers};
+use serde_json;
 use std::any::Any;
 use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::fmt::{Write as FmtWrite};
 use std::fs;
 use std::fs::File;
 use std::io::{Write as IoWrite};
+use std::mem;
 use std::panic::*;
 use std::path::{Path, PathBuf};
 use std::process::abort;
@@ -41,6 +49,207 @@ impl ReportType {
     }
 }
 
+thread_local! {
+    static CONTEXT: RefCell<Vec<(&'static str, String)>> = RefCell::new(Vec::new());
+    static IS_SENDING: Cell<bool> = Cell::new(false);
+}
+
+// Runs a function with information attached to any error or panic reported while it runs, such
+// as the command being used.
+pub fn with_context<F, T>(context: Vec<(&'static str, String)>, f: F) -> T
+    where F: FnOnce() -> T {
+    let old = CONTEXT.with(|x| mem::replace(&mut *x.borrow_mut(), context));
+    let result = f();
+    CONTEXT.with(|x| *x.borrow_mut() = old);
+    result
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ReportSinkKind {
+    Sentry, Webhook,
+}
+impl ReportSinkKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            ReportSinkKind::Sentry => "sentry",
+            ReportSinkKind::Webhook => "webhook",
+        }
+    }
+    pub fn from_name(name: &str) -> Result<ReportSinkKind> {
+        match name {
+            "sentry" => Ok(ReportSinkKind::Sentry),
+            "webhook" => Ok(ReportSinkKind::Webhook),
+            _ => cmd_error!("Unknown error report format '{}'. It must be `sentry` or `webhook`.",
+                            name),
+        }
+    }
+}
+
+// A service reports are sent to, as well as being written to the logs directory.
+#[derive(Clone)]
+pub struct ReportSink {
+    kind: ReportSinkKind, url: String, sentry_key: Option<String>,
+}
+impl ReportSink {
+    pub fn new(kind: ReportSinkKind, url: &str) -> Result<ReportSink> {
+        let url = Url::parse(url).to_cmd_err(|| "The error report URL is not a valid URL.")?;
+        cmd_ensure!(url.scheme() == "https" || url.scheme() == "http",
+                    "The error report URL must be an HTTP or HTTPS URL.");
+        match kind {
+            ReportSinkKind::Sentry => {
+                // DSNs look like `https://<key>@<host>/<project>`, and events are sent to
+                // `https://<host>/api/<project>/store/`.
+                let key = url.username().to_owned();
+                let path = url.path().trim_matches('/').to_owned();
+                let (prefix, project) = match path.rfind('/') {
+                    Some(i) => (format!("/{}", &path[..i]), &path[i + 1..]),
+                    None => (String::new(), &path[..]),
+                };
+                cmd_ensure!(!key.is_empty() && !project.is_empty() &&
+                            project.chars().all(|c| c.is_digit(10)),
+                            "Sentry DSNs look like `https://<key>@sentry.io/<project id>`.");
+                let mut store = url.clone();
+                store.set_username("").ok();
+                store.set_password(None).ok();
+                store.set_query(None);
+                store.set_path(&format!("{}/api/{}/store/", prefix, project));
+                Ok(ReportSink { kind, url: store.into_string(), sentry_key: Some(key) })
+            }
+            ReportSinkKind::Webhook =>
+                Ok(ReportSink { kind, url: url.into_string(), sentry_key: None }),
+        }
+    }
+
+    fn payload(
+        &self, kind: ReportType, cause: &str, backtrace: &str, context: &[(&'static str, String)],
+    ) -> Result<String> {
+        let mut tags: HashMap<&str, String> = context.iter().cloned().collect();
+        tags.insert("kind", kind.lc_name().to_owned());
+        tags.insert("thread", thread_name());
+        let message = cause.trim().split('\n').next().unwrap_or("").to_owned();
+        Ok(match self.kind {
+            ReportSinkKind::Sentry => {
+                let mut extra = HashMap::new();
+                extra.insert("cause", cause.to_owned());
+                extra.insert("backtrace", backtrace.to_owned());
+                serde_json::to_string(&SentryEvent {
+                    event_id: format!("{:016x}{:016x}",
+                                      thread_rng().gen::<u64>(), thread_rng().gen::<u64>()),
+                    timestamp: Utc::now().format("%Y-%m-%dT%H:%M:%S").to_string(),
+                    level: match kind {
+                        ReportType::Error => "error",
+                        ReportType::Panic | ReportType::Deadlock => "fatal",
+                    },
+                    platform: "other", logger: env!("CARGO_PKG_NAME"),
+                    release: concat!(env!("CARGO_PKG_NAME"), "@", env!("CARGO_PKG_VERSION")),
+                    message, tags, extra,
+                })?
+            }
+            ReportSinkKind::Webhook => serde_json::to_string(&WebhookReport {
+                kind: kind.lc_name(), version: env!("CARGO_PKG_VERSION"),
+                commit: env!("GIT_COMMIT"), timestamp: Utc::now().timestamp(),
+                message, cause: cause.to_owned(), backtrace: backtrace.to_owned(), context: tags,
+            })?,
+        })
+    }
+    fn send(&self, body: String) -> Result<()> {
+        let mut headers = Headers::new();
+        headers.set(ContentType::json());
+        if let Some(ref key) = self.sentry_key {
+            headers.set_raw("X-Sentry-Auth", format!(
+                "Sentry sentry_version=7, sentry_client={}/{}, sentry_key={}",
+                env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), key,
+            ));
+        }
+        reqwest::Client::new().post(&self.url).headers(headers).body(body)
+            .send()?.error_for_status()?;
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+struct SentryEvent {
+    event_id: String, timestamp: String, level: &'static str, platform: &'static str,
+    logger: &'static str, release: &'static str, message: String,
+    tags: HashMap<&'static str, String>, extra: HashMap<&'static str, String>,
+}
+#[derive(Serialize)]
+struct WebhookReport {
+    kind: &'static str, version: &'static str, commit: &'static str, timestamp: i64,
+    message: String, cause: String, backtrace: String, context: HashMap<&'static str, String>,
+}
+
+lazy_static! {
+    static ref SINK: RwLock<Option<ReportSink>> = RwLock::new(None);
+    static ref SECRETS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+    // Credentials that may appear in error messages, such as in URLs or request headers.
+    static ref SECRET_PATTERNS: Vec<(Regex, &'static str)> = vec![
+        (Regex::new(r"[A-Za-z0-9_-]{23,28}\.[A-Za-z0-9_-]{6,7}\.[A-Za-z0-9_-]{27,}").unwrap(),
+         "<token redacted>"),
+        (Regex::new(r#"_\|WARNING:-DO-NOT-SHARE-THIS\.[^\s"']*"#).unwrap(), "<cookie redacted>"),
+        (Regex::new(r"(?i)\b(bearer|bot|basic)\s+[A-Za-z0-9._~+/=-]{20,}").unwrap(),
+         "$1 <redacted>"),
+        (Regex::new(r"(?i)([a-z][a-z0-9+.-]*://)[^/\s@]+@").unwrap(), "$1<redacted>@"),
+        (Regex::new(r#"(?i)([?&][a-z_]*(key|token|secret|code|sig|signature)=)[^&\s"']+"#)
+             .unwrap(), "$1<redacted>"),
+    ];
+}
+
+pub fn set_sink(sink: Option<ReportSink>) {
+    *SINK.write() = sink;
+}
+// Sets the configured credentials, which are removed from reports before they are sent.
+pub fn set_secrets(secrets: Vec<String>) {
+    *SECRETS.write() = secrets;
+}
+
+fn scrub(text: &str) -> String {
+    let mut text = text.to_owned();
+    for secret in SECRETS.read().iter() {
+        if !secret.is_empty() {
+            text = text.replace(secret.as_str(), "<secret redacted>");
+        }
+    }
+    for &(ref pattern, replacement) in SECRET_PATTERNS.iter() {
+        text = pattern.replace_all(&text, replacement).into_owned();
+    }
+    text
+}
+
+// Sends a report to the configured sink, if any. Recent logs are not included, as they may
+// contain user information. Deadlock reports are sent before returning, since the process is
+// aborted right after.
+fn send_report(kind: ReportType, cause: &str, backtrace: &str) {
+    if IS_SENDING.with(|x| x.get()) {
+        return
+    }
+    let sink = match *SINK.read() {
+        Some(ref sink) => sink.clone(),
+        None => return,
+    };
+    let context = CONTEXT.with(|x| x.borrow().clone());
+    let body = match sink.payload(kind, &scrub(cause), &scrub(backtrace), &context) {
+        Ok(body) => body,
+        Err(e) => {
+            warn!("Could not create {} report for {}: {}", kind.lc_name(), sink.kind.name(), e);
+            return
+        }
+    };
+    let send = move || {
+        IS_SENDING.with(|x| x.set(true));
+        if let Err(e) = sink.send(body) {
+            warn!("Could not send {} report to {}: {}", kind.lc_name(), sink.kind.name(), e);
+        }
+        IS_SENDING.with(|x| x.set(false));
+    };
+    match kind {
+        ReportType::Deadlock => send(),
+        _ => if let Err(e) = thread::Builder::new().name("error report".to_owned()).spawn(send) {
+            warn!("Could not start sending {} report: {}", kind.lc_name(), e);
+        },
+    }
+}
+
 fn make_error_report(kind: ReportType, cause: &str, backtrace: &str) -> Result<String> {
     let mut buf = String::new();
     writeln!(buf, "--- Sylph-Verifier {} Report ---", kind.name())?;
@@ -95,6 +304,7 @@ fn write_report(kind: ReportType, cause: &str, backtrace: &str) -> Result<()> {
     error!("This is probably a bug. Please report it at \
             https://github.com/Lymia/sylph-verifier/issues and include the {} report.",
            kind.lc_name());
+    send_report(kind, cause, backtrace);
     Ok(())
 }
 
@@ -61,9 +61,10 @@ macro_rules! from_err {
 mod impls {
     use *;
     from_err! {
-        std::fmt::Error, std::io::Error, std::num::ParseIntError, std::str::Utf8Error,
-        std::string::FromUtf8Error, std::time::SystemTimeError, r2d2::Error, reqwest::Error,
-        rusqlite::Error, rusqlite::types::FromSqlError, serde_json::Error,
+        chrono::ParseError, std::fmt::Error, std::io::Error, std::num::ParseIntError,
+        std::str::Utf8Error, std::string::FromUtf8Error, std::time::SystemTimeError,
+        hyper::Error, native_tls::Error, r2d2::Error, regex::Error, reqwest::Error,
+        reqwest::UrlError, rusqlite::Error, rusqlite::types::FromSqlError, serde_json::Error,
     }
 }
 
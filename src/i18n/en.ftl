@@ -0,0 +1,79 @@
+# Verification
+verify-instructions = To verify your Roblox account with your Discord account, please follow these instructions:
+    • Visit <{ $place_url }> as the account you want to verify as.
+    • Type the command it shows in the oval box into this channel.
+verify-success = Your roles have been set.
+verify-success-no-nickname = Note that your nickname has not been set as this bot does not have permission to edit it.
+verify-roles-not-set = Your roles were not set. Please contact a server administrator.
+verify-invalid-token = The verification code you used is not valid. Please check the code you entered and try again.
+verify-token-used = Someone has already used that verification code. Please wait for a new code to be generated, then try again.
+verify-place-outdated = The verification place is outdated, and has not been updated with the verification bot. Please contact the bot owner.
+verify-too-many-attempts = You can only try to verify { $max_attempts } times every { $cooldown }. Please try again in { $time_left }.
+verify-already-verified = You are already verified as { $roblox_name }.
+verify-roblox-taken = { $discord_name } has already verified as { $roblox_name }.
+verify-reverify-cooldown = You can only reverify once every { $cooldown }. Please try again in { $time_left }.
+verify-too-many-accounts = You can only link { $max_accounts } Roblox accounts to your Discord account. Use the '{ $prefix }unverify' command to unlink them first.
+verify-account-too-new = Roblox accounts must be at least { $minimum_days } days old to verify on this server. Please try again in { $time_left }.
+verify-group-required = You must join the Roblox group at <{ $group_url }> before you can verify on this server. Please join it and try again.
+verify-group-rank-required = You must have rank { $minimum_rank } or higher in the Roblox group at <{ $group_url }> to verify on this server.
+verify-name-not-allowed = The name { $roblox_name } is not allowed on this server. Please change it on Roblox and try again.
+verify-hint-update = If you only want to update your roles, use the '{ $prefix }update' command.
+verify-hint-switch = To make it your primary account, use the '{ $prefix }switch' command.
+verify-not-verified = You are not verified with this bot.
+verify-pending-approval = Your verification is waiting to be approved by this server's moderators. Your roles will be set once it is approved.
+verify-approval-rejected = Your verification was not approved by this server's moderators.
+verify-approved-notice = Your verification in { $server } has been approved, and your roles have been set.
+verify-rejected-notice = Your verification in { $server } was not approved by its moderators.
+
+# Join gate
+join-welcome = Welcome to { $server }! This server requires you to verify your Roblox account before you can take part:
+    • Visit <{ $place_url }> as the account you want to verify as.
+    • Type the command it shows in the oval box into { $channel }.
+join-welcome-any-channel = Welcome to { $server }! This server requires you to verify your Roblox account before you can take part:
+    • Visit <{ $place_url }> as the account you want to verify as.
+    • Type the command it shows in the oval box into any channel on the server.
+enforce-warning-kick = You have not verified your Roblox account in { $server } yet. If you do not verify within { $time_left }, you will be kicked from the server.
+enforce-warning-timeout = You have not verified your Roblox account in { $server } yet. If you do not verify within { $time_left }, you will be timed out on the server.
+remind-unverified = You joined { $server } { $days } days ago, but have not verified your Roblox account there yet. Use the '{ $prefix }verify' command in the server to get started. To stop these reminders, use the '{ $prefix }reminders off' command.
+
+# Updating roles
+update-success = Your roles have been updated.
+update-decay-notice = Your verification roles in { $server } have been removed, as you have not updated them in { $days } days. Use the '{ $prefix }update' command there to get them back.
+
+# Verification methods
+verify-deep-link = Please open <{ $link }> as **{ $roblox_name }**, then use `{ $prefix }verify { $roblox_name } <verification code>` with the code it shows you. The link can only be used for the next { $duration }.
+verify-profile-phrase = Please add the following phrase to the About section of your Roblox profile, then use this command again: `{ $phrase }`
+verify-oauth-link = Sign in to the Roblox account you want to verify as at <{ $url }>, then use `{ $prefix }verify_oauth <roblox username> <code>` with the code it gives you.
+verify-oauth-wrong-account = You signed in to a different Roblox account than the one you are verifying as. Please sign in again as the right account.
+verify-friend-instructions = Please send a friend request to the Roblox account **{ $bot_account }** from the account you want to verify as within { $duration }. You will be verified automatically once it arrives.
+verify-place-code-instructions = Please join the verification place as the account you want to verify as, and enter the code `{ $code }` within { $duration }. You will be verified automatically once it is entered.
+verify-website-link = To verify, sign in to your Discord and Roblox accounts at <{ $url }>.
+verify-website-not-set-up = The bot's website is not set up. Please contact the bot owner.
+verify-method-disabled = That verification method is not enabled on this server.
+token-time = The code shown in the verification place changes in { $changes_in }. A code shown now can be used for another { $valid_for }.
+
+# Verification wizard
+wizard-start = Let's verify your Roblox account on **{ $server }**. What is your Roblox username?
+    *Reply with 'cancel' at any time to stop.*
+wizard-dm-failed = I could not send you a direct message. Please check your privacy settings, or use '{ $prefix }verify <roblox username> <verification code>' instead.
+wizard-dm-sent = I have sent you a direct message with instructions.
+wizard-deep-link = Please open <{ $link }> as **{ $roblox_name }**, then reply with the verification code it shows you. The link can only be used for the next { $duration }.
+wizard-canceled = Verification canceled.
+wizard-error = The verification wizard encountered an unexpected error. Please contact the bot owner.
+
+# Linked accounts
+unverify-success = You are no longer verified as { $roblox_name }.
+accounts-header = Roblox accounts linked to your Discord account:
+accounts-primary = (primary)
+switch-success = Your primary account is now { $roblox_name }. Your roles will be updated shortly.
+reminders-usage = Please use `{ $prefix }reminders on` or `{ $prefix }reminders off`.
+reminders-on = You will be reminded to verify by servers that send reminders.
+reminders-off = You will no longer be sent reminders to verify.
+
+# Durations
+duration-seconds-one = 1 second
+duration-seconds = { $count } seconds
+duration-minutes-one = 1 minute
+duration-minutes = { $count } minutes
+duration-hours-one = 1 hour
+duration-hours = { $count } hours
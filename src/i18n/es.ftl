@@ -0,0 +1,79 @@
+# Verificación
+verify-instructions = Para verificar tu cuenta de Roblox con tu cuenta de Discord, sigue estas instrucciones:
+    • Visita <{ $place_url }> con la cuenta que quieres verificar.
+    • Escribe en este canal el comando que aparece en el recuadro ovalado.
+verify-success = Tus roles han sido asignados.
+verify-success-no-nickname = Ten en cuenta que tu apodo no ha sido cambiado porque este bot no tiene permiso para editarlo.
+verify-roles-not-set = Tus roles no fueron asignados. Por favor, contacta a un administrador del servidor.
+verify-invalid-token = El código de verificación que usaste no es válido. Por favor, revisa el código que escribiste e inténtalo de nuevo.
+verify-token-used = Alguien ya usó ese código de verificación. Por favor, espera a que se genere un código nuevo e inténtalo de nuevo.
+verify-place-outdated = El lugar de verificación está desactualizado y no coincide con el bot de verificación. Por favor, contacta al dueño del bot.
+verify-too-many-attempts = Solo puedes intentar verificarte { $max_attempts } veces cada { $cooldown }. Por favor, inténtalo de nuevo en { $time_left }.
+verify-already-verified = Ya estás verificado como { $roblox_name }.
+verify-roblox-taken = { $discord_name } ya se verificó como { $roblox_name }.
+verify-reverify-cooldown = Solo puedes volver a verificarte una vez cada { $cooldown }. Por favor, inténtalo de nuevo en { $time_left }.
+verify-too-many-accounts = Solo puedes vincular { $max_accounts } cuentas de Roblox a tu cuenta de Discord. Usa el comando '{ $prefix }unverify' para desvincularlas primero.
+verify-account-too-new = Las cuentas de Roblox deben tener al menos { $minimum_days } días de antigüedad para verificarse en este servidor. Por favor, inténtalo de nuevo en { $time_left }.
+verify-group-required = Debes unirte al grupo de Roblox en <{ $group_url }> antes de poder verificarte en este servidor. Por favor, únete e inténtalo de nuevo.
+verify-group-rank-required = Debes tener el rango { $minimum_rank } o superior en el grupo de Roblox en <{ $group_url }> para verificarte en este servidor.
+verify-name-not-allowed = El nombre { $roblox_name } no está permitido en este servidor. Por favor, cámbialo en Roblox e inténtalo de nuevo.
+verify-hint-update = Si solo quieres actualizar tus roles, usa el comando '{ $prefix }update'.
+verify-hint-switch = Para convertirla en tu cuenta principal, usa el comando '{ $prefix }switch'.
+verify-not-verified = No estás verificado con este bot.
+verify-pending-approval = Tu verificación está esperando la aprobación de los moderadores de este servidor. Tus roles serán asignados cuando sea aprobada.
+verify-approval-rejected = Tu verificación no fue aprobada por los moderadores de este servidor.
+verify-approved-notice = Tu verificación en { $server } ha sido aprobada y tus roles han sido asignados.
+verify-rejected-notice = Tu verificación en { $server } no fue aprobada por sus moderadores.
+
+# Control de entrada
+join-welcome = ¡Bienvenido a { $server }! Este servidor requiere que verifiques tu cuenta de Roblox antes de participar:
+    • Visita <{ $place_url }> con la cuenta que quieres verificar.
+    • Escribe en { $channel } el comando que aparece en el recuadro ovalado.
+join-welcome-any-channel = ¡Bienvenido a { $server }! Este servidor requiere que verifiques tu cuenta de Roblox antes de participar:
+    • Visita <{ $place_url }> con la cuenta que quieres verificar.
+    • Escribe en cualquier canal del servidor el comando que aparece en el recuadro ovalado.
+enforce-warning-kick = Todavía no has verificado tu cuenta de Roblox en { $server }. Si no te verificas en { $time_left }, serás expulsado del servidor.
+enforce-warning-timeout = Todavía no has verificado tu cuenta de Roblox en { $server }. Si no te verificas en { $time_left }, serás silenciado en el servidor.
+remind-unverified = Te uniste a { $server } hace { $days } días, pero todavía no has verificado tu cuenta de Roblox allí. Usa el comando '{ $prefix }verify' en el servidor para empezar. Para dejar de recibir estos recordatorios, usa el comando '{ $prefix }reminders off'.
+
+# Actualización de roles
+update-success = Tus roles han sido actualizados.
+update-decay-notice = Tus roles de verificación en { $server } han sido retirados, ya que no los has actualizado en { $days } días. Usa el comando '{ $prefix }update' allí para recuperarlos.
+
+# Métodos de verificación
+verify-deep-link = Por favor, abre <{ $link }> con la cuenta **{ $roblox_name }** y luego usa `{ $prefix }verify { $roblox_name } <código de verificación>` con el código que te muestre. El enlace solo se puede usar durante { $duration }.
+verify-profile-phrase = Por favor, añade la siguiente frase a la sección "Acerca de" de tu perfil de Roblox y luego usa este comando de nuevo: `{ $phrase }`
+verify-oauth-link = Inicia sesión en la cuenta de Roblox que quieres verificar en <{ $url }> y luego usa `{ $prefix }verify_oauth <nombre de usuario de Roblox> <código>` con el código que te dé.
+verify-oauth-wrong-account = Iniciaste sesión en una cuenta de Roblox distinta de la que estás verificando. Por favor, inicia sesión de nuevo con la cuenta correcta.
+verify-friend-instructions = Por favor, envía una solicitud de amistad a la cuenta de Roblox **{ $bot_account }** desde la cuenta que quieres verificar en menos de { $duration }. Serás verificado automáticamente cuando llegue.
+verify-place-code-instructions = Por favor, únete al lugar de verificación con la cuenta que quieres verificar e introduce el código `{ $code }` en menos de { $duration }. Serás verificado automáticamente cuando lo introduzcas.
+verify-website-link = Para verificarte, inicia sesión con tus cuentas de Discord y Roblox en <{ $url }>.
+verify-website-not-set-up = El sitio web del bot no está configurado. Por favor, contacta al dueño del bot.
+verify-method-disabled = Ese método de verificación no está activado en este servidor.
+token-time = El código que aparece en el lugar de verificación cambia en { $changes_in }. Un código que aparece ahora se puede usar durante { $valid_for } más.
+
+# Asistente de verificación
+wizard-start = Vamos a verificar tu cuenta de Roblox en **{ $server }**. ¿Cuál es tu nombre de usuario de Roblox?
+    *Responde 'cancel' en cualquier momento para detenerte.*
+wizard-dm-failed = No pude enviarte un mensaje directo. Por favor, revisa tu configuración de privacidad o usa '{ $prefix }verify <nombre de usuario de Roblox> <código de verificación>' en su lugar.
+wizard-dm-sent = Te he enviado un mensaje directo con las instrucciones.
+wizard-deep-link = Por favor, abre <{ $link }> con la cuenta **{ $roblox_name }** y luego responde con el código de verificación que te muestre. El enlace solo se puede usar durante { $duration }.
+wizard-canceled = Verificación cancelada.
+wizard-error = El asistente de verificación encontró un error inesperado. Por favor, contacta al dueño del bot.
+
+# Cuentas vinculadas
+unverify-success = Ya no estás verificado como { $roblox_name }.
+accounts-header = Cuentas de Roblox vinculadas a tu cuenta de Discord:
+accounts-primary = (principal)
+switch-success = Tu cuenta principal ahora es { $roblox_name }. Tus roles se actualizarán en breve.
+reminders-usage = Por favor, usa `{ $prefix }reminders on` o `{ $prefix }reminders off`.
+reminders-on = Los servidores que envían recordatorios te recordarán que te verifiques.
+reminders-off = Ya no recibirás recordatorios para verificarte.
+
+# Duraciones
+duration-seconds-one = 1 segundo
+duration-seconds = { $count } segundos
+duration-minutes-one = 1 minuto
+duration-minutes = { $count } minutos
+duration-hours-one = 1 hora
+duration-hours = { $count } horas
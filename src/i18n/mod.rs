@@ -0,0 +1,112 @@
+use core::{ConfigManager, ConfigKeys};
+use errors::*;
+use serenity::model::prelude::*;
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+// Messages shown to members while they verify or manage their own accounts are translated, along
+// with the durations in them. Commands for server staff and the bot owner, and errors from deeper
+// within the bot, are only available in English.
+
+pub const DEFAULT_LANGUAGE: &str = "en";
+
+// Message catalogs use a subset of the Fluent syntax: `key = value` messages with `{ $name }`
+// placeholders. Indented lines continue the previous message on a new line.
+static CATALOG_SOURCES: &'static [(&'static str, &'static str, &'static str)] = &[
+    ("en", "English", include_str!("en.ftl")),
+    ("es", "Español", include_str!("es.ftl")),
+];
+
+fn parse_catalog(code: &str, source: &'static str) -> HashMap<&'static str, String> {
+    let mut messages = HashMap::new();
+    let mut current: Option<(&'static str, String)> = None;
+    for line in source.lines() {
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue
+        }
+        if line.starts_with(' ') {
+            match current {
+                Some((_, ref mut value)) => {
+                    value.push('\n');
+                    value.push_str(line.trim());
+                }
+                None => warn!("Continuation line without a message in catalog '{}'.", code),
+            }
+            continue
+        }
+        if let Some((key, value)) = current.take() {
+            messages.insert(key, value);
+        }
+        match line.find('=') {
+            Some(i) => current = Some((line[..i].trim(), line[i + 1..].trim().to_owned())),
+            None => warn!("Invalid line in catalog '{}': {}", code, line),
+        }
+    }
+    if let Some((key, value)) = current {
+        messages.insert(key, value);
+    }
+    messages
+}
+
+lazy_static! {
+    static ref CATALOGS: HashMap<&'static str, HashMap<&'static str, String>> =
+        CATALOG_SOURCES.iter().map(|&(code, _, source)| (code, parse_catalog(code, source)))
+            .collect();
+}
+
+pub fn is_language(code: &str) -> bool {
+    CATALOGS.contains_key(code)
+}
+pub fn languages() -> Vec<(&'static str, &'static str)> {
+    CATALOG_SOURCES.iter().map(|&(code, name, _)| (code, name)).collect()
+}
+
+// Looks up a message in a language, falling back to English if it has not been translated.
+pub fn translate(language: &str, key: &str, args: &[(&str, &str)]) -> String {
+    let message = CATALOGS.get(language).and_then(|x| x.get(key))
+        .or_else(|| CATALOGS[DEFAULT_LANGUAGE].get(key));
+    let mut message = match message {
+        Some(message) => message.clone(),
+        None => {
+            error!("Missing message '{}' in catalog '{}'.", key, DEFAULT_LANGUAGE);
+            return key.to_owned()
+        }
+    };
+    for &(name, value) in args {
+        message = message.replace(&format!("{{ ${} }}", name), value);
+    }
+    message
+}
+
+// Looks up a message in the language configured for a guild.
+pub fn tr(
+    config: &ConfigManager, guild: Option<GuildId>, key: &str, args: &[(&str, &str)],
+) -> Result<String> {
+    Ok(translate(&config.get(guild, ConfigKeys::Language)?, key, args))
+}
+
+// Formats a duration in a language, rounded up to whole seconds, minutes or hours in the same way
+// as `util::to_english_time`.
+pub fn duration(language: &str, secs: u64) -> String {
+    let (count, unit) = if secs < 60 {
+        (secs, "seconds")
+    } else if secs < 60 * 60 {
+        ((secs + 59) / 60, "minutes")
+    } else {
+        ((secs + 60 * 60 - 1) / (60 * 60), "hours")
+    };
+    let key = if count == 1 {
+        format!("duration-{}-one", unit)
+    } else {
+        format!("duration-{}", unit)
+    };
+    translate(language, &key, &[("count", &count.to_string())])
+}
+pub fn tr_duration(config: &ConfigManager, guild: Option<GuildId>, secs: u64) -> Result<String> {
+    Ok(duration(&config.get(guild, ConfigKeys::Language)?, secs))
+}
+pub fn tr_time_diff(
+    config: &ConfigManager, guild: Option<GuildId>, from: SystemTime, to: SystemTime,
+) -> Result<String> {
+    tr_duration(config, guild, to.duration_since(from).map(|x| x.as_secs()).unwrap_or(0))
+}
@@ -3,6 +3,7 @@ use errors::*;
 use linefeed::reader::LogSender;
 use log::*;
 use parking_lot::Mutex;
+use std::cell::RefCell;
 use std::collections::VecDeque;
 use std::fs;
 use std::fs::{File, OpenOptions};
@@ -10,6 +11,7 @@ use std::fmt::{Write as FmtWrite};
 use std::io::{BufWriter, Write as IoWrite};
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime};
 
 // TODO: Use log4rs, which provides much of the functionality of this module.
 
@@ -43,6 +45,60 @@ fn source_info(source: &str) -> &'static LogPolicy {
     }
     unreachable!() // due to the "*" entry
 }
+
+// Levels set at runtime with the `log_level` command. These apply to both the console and log
+// files, and take priority over LOG_POLICY. Later entries take priority over earlier ones.
+lazy_static! {
+    static ref LEVEL_OVERRIDES: Mutex<Vec<(String, LevelFilter)>> = Mutex::new(Vec::new());
+}
+fn source_levels(source: &str) -> (LevelFilter, LevelFilter) {
+    let munged = munge_target(source);
+    for &(ref module, level) in LEVEL_OVERRIDES.lock().iter().rev() {
+        if is_in_module(module, munged) || is_in_module(module, source) {
+            return (level, level)
+        }
+    }
+    let info = source_info(source);
+    (info.console, info.log)
+}
+
+pub fn set_level_override(module: &str, level: Option<LevelFilter>) {
+    let mut overrides = LEVEL_OVERRIDES.lock();
+    overrides.retain(|x| x.0 != module);
+    if let Some(level) = level {
+        overrides.push((module.to_owned(), level));
+    }
+}
+pub fn level_overrides() -> Vec<(String, LevelFilter)> {
+    LEVEL_OVERRIDES.lock().clone()
+}
+
+thread_local! {
+    static SPANS: RefCell<Vec<String>> = RefCell::new(Vec::new());
+}
+
+// A unit of work that is included in every log line made on the current thread while it is
+// active, so the lines of interleaved operations can be told apart.
+pub struct Span(());
+impl Drop for Span {
+    fn drop(&mut self) {
+        SPANS.with(|x| { x.borrow_mut().pop(); })
+    }
+}
+pub fn span<S: Into<String>>(name: S) -> Span {
+    SPANS.with(|x| x.borrow_mut().push(name.into()));
+    Span(())
+}
+fn current_spans() -> String {
+    SPANS.with(|x| {
+        let spans = x.borrow();
+        if spans.is_empty() {
+            String::new()
+        } else {
+            format!("({}) ", spans.join(" > "))
+        }
+    })
+}
 fn logs(filter: LevelFilter, level: Level) -> bool {
     match filter.to_level() {
         None => false,
@@ -83,40 +139,71 @@ const NEW_LINE: &str = "\r\n"; // Because Notepad exists.
 #[cfg(not(windows))]
 const NEW_LINE: &str = "\n";
 
+const MAX_LOG_FILE_SIZE: u64 = 1024 * 1024 * 20;
+const LOG_RETENTION_DAYS: u64 = 30;
+
+fn delete_old_logs(log_dir: &PathBuf) -> Result<()> {
+    let cutoff = SystemTime::now() - Duration::from_secs(LOG_RETENTION_DAYS * 60 * 60 * 24);
+    for entry in fs::read_dir(log_dir)? {
+        let entry = entry?;
+        let is_log = entry.path().extension().map_or(false, |x| x == "log");
+        if is_log && entry.metadata()?.modified()? < cutoff {
+            fs::remove_file(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+// Log files are rotated daily, and when they grow larger than MAX_LOG_FILE_SIZE.
 enum LogFileOutput {
     NotInitialized,
-    Initialized { out: BufWriter<File>, date: Date<Local> }
+    Initialized { out: BufWriter<File>, date: Date<Local>, index: u32, size: u64 }
 }
 impl LogFileOutput {
-    fn refresh(&mut self, log_dir: &PathBuf) -> Result<()> {
+    fn refresh(&mut self, log_dir: &PathBuf, index: u32) -> Result<()> {
         let mut out_path = log_dir.clone();
         let today = Local::today();
-        out_path.push(format!("{}.log", today.format("%Y-%m-%d")));
+        if index == 0 {
+            out_path.push(format!("{}.log", today.format("%Y-%m-%d")));
+        } else {
+            out_path.push(format!("{}.{}.log", today.format("%Y-%m-%d"), index));
+        }
 
+        let file = OpenOptions::new()
+            .write(true).read(true).append(true).truncate(false).create(true)
+            .open(out_path)?;
+        let size = file.metadata()?.len();
         *self = LogFileOutput::Initialized {
-            out: BufWriter::new(OpenOptions::new()
-                .write(true).read(true).append(true).truncate(false).create(true)
-                .open(out_path)?),
-            date: Local::today(),
+            out: BufWriter::new(file), date: today, index, size,
         };
+        if size >= MAX_LOG_FILE_SIZE {
+            return self.refresh(log_dir, index + 1)
+        }
 
         Ok(())
     }
     fn check_open_new(&mut self, log_dir: &PathBuf) -> Result<()> {
-        let needs_refresh = match *self {
-            LogFileOutput::NotInitialized => true,
-            LogFileOutput::Initialized { ref date, .. } => date != &Local::today(),
+        let next_index = match *self {
+            LogFileOutput::NotInitialized => Some(0),
+            LogFileOutput::Initialized { ref date, .. } if date != &Local::today() => {
+                delete_old_logs(log_dir)?;
+                Some(0)
+            }
+            LogFileOutput::Initialized { index, size, .. } if size >= MAX_LOG_FILE_SIZE =>
+                Some(index + 1),
+            LogFileOutput::Initialized { .. } => None,
         };
-        if needs_refresh {
-            self.refresh(log_dir)?
+        if let Some(index) = next_index {
+            self.refresh(log_dir, index)?
         }
         Ok(())
     }
     fn log(&mut self, log_dir: &PathBuf, line: &str) -> Result<()> {
         self.check_open_new(log_dir)?;
-        if let &mut LogFileOutput::Initialized { ref mut out, .. } = self {
+        if let &mut LogFileOutput::Initialized { ref mut out, ref mut size, .. } = self {
             write!(out, "{}{}", line, NEW_LINE)?;
             out.flush()?;
+            *size += (line.len() + NEW_LINE.len()) as u64;
             Ok(())
         } else {
             unreachable!()
@@ -172,17 +259,17 @@ fn log_raw(line: &str) {
 }
 impl Log for Logger {
     fn enabled(&self, metadata: &Metadata) -> bool {
-        let info = source_info(metadata.target());
+        let (console, log) = source_levels(metadata.target());
         let level = metadata.level();
 
-        logs(info.console, level) || logs(info.log, level)
+        logs(console, level) || logs(log, level)
     }
 
     fn log(&self, record: &Record) {
-        let info = source_info(record.target());
+        let (console, log) = source_levels(record.target());
         let level = record.level();
-        let log_console = logs(info.console, level) && record.target() != "$command_input";
-        let log_file = logs(info.log, level);
+        let log_console = logs(console, level) && record.target() != "$command_input";
+        let log_file = logs(log, level);
 
         if log_console || log_file {
             let now = Local::now().format("%Y-%m-%d %H:%M:%S");
@@ -191,8 +278,9 @@ impl Log for Logger {
             } else if record.target() == "$command_input" {
                 format!("sylph-verifier> {}", record.args())
             } else {
-                format!("[{}] [{}/{}] {}",
-                        now, munge_target(record.target()), record.level(), record.args())
+                format!("[{}] [{}/{}] {}{}",
+                        now, munge_target(record.target()), record.level(), current_spans(),
+                        record.args())
             };
 
             if log_console {
@@ -221,6 +309,7 @@ pub fn init<P: AsRef<Path>>(root_path: P) -> Result<()> {
     let mut log_dir = PathBuf::from(root_path.as_ref());
     log_dir.push("logs");
     fs::create_dir_all(&log_dir)?;
+    delete_old_logs(&log_dir)?;
 
     LOG_FILE.lock().log(&log_dir, &format!("===== Starting logging at {} =====",
                                            Local::now().format("%Y-%m-%d %H:%M:%S")))?;
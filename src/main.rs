@@ -9,7 +9,6 @@
 // TODO: Clean up program thread usage
 // TODO: Pass around IDs less to touch Serenity's cache less.
 // TODO: Add statistics tracking to better understand current bot load.
-// TODO: Add logging for verifications to a log channel.
 // TODO: Rewrite to be async.
 
 extern crate backtrace;
@@ -20,6 +19,7 @@ extern crate fs2;
 extern crate hmac;
 extern crate hyper;
 extern crate linefeed;
+extern crate native_tls;
 extern crate num_cpus;
 extern crate parking_lot;
 extern crate percent_encoding;
@@ -27,6 +27,7 @@ extern crate r2d2;
 extern crate rand;
 extern crate regex;
 extern crate reqwest;
+extern crate ring;
 extern crate rusqlite;
 extern crate serde_json;
 extern crate serenity;
@@ -48,10 +49,12 @@ mod commands;
 mod core;
 mod database;
 mod error_report;
+mod i18n;
 mod logger;
 mod roblox;
 mod startup;
 mod util;
+mod web;
 
 fn main() {
     println!("Sylph-Verifier v{} by LymeeFairy", env!("CARGO_PKG_VERSION"));
@@ -1,17 +1,67 @@
+use chrono::DateTime;
 use errors::*;
 use percent_encoding::{percent_encode, QUERY_ENCODE_SET};
 use reqwest;
 use reqwest::StatusCode;
+use reqwest::header::{ContentType, Headers};
 use roblox::*;
+use roblox::client::{ApiClient, EndpointMetrics, route};
 use serde_json;
-use std::collections::{HashSet, HashMap};
+use std::collections::HashSet;
+use std::time::SystemTime;
+
+lazy_static! {
+    pub(super) static ref CLIENT: ApiClient = ApiClient::new();
+}
+
+pub fn api_metrics() -> Vec<(&'static str, EndpointMetrics)> {
+    CLIENT.metrics()
+}
+// Returns the number of Roblox API requests that are currently being made.
+pub fn in_flight_requests() -> usize {
+    CLIENT.in_flight()
+}
 
 #[derive(Deserialize)]
 struct RobloxIDLookup {
-    #[serde(rename = "Id")] id: Option<u64>,
     #[serde(rename = "Username")] name: Option<String>,
 }
 
+#[derive(Serialize)]
+struct RobloxUsernamesRequest<'a> {
+    usernames: Vec<&'a str>,
+    #[serde(rename = "excludeBannedUsers")] exclude_banned_users: bool,
+}
+
+#[derive(Deserialize)]
+struct RobloxUsernameLookup {
+    id: u64,
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct RobloxUsernamesLookup {
+    data: Vec<RobloxUsernameLookup>,
+}
+
+#[derive(Deserialize)]
+struct RobloxUsernameHistoryEntry {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct RobloxUsernameHistoryLookup {
+    data: Vec<RobloxUsernameHistoryEntry>,
+}
+
+#[derive(Deserialize)]
+struct RobloxUserInfoLookup {
+    description: Option<String>,
+    #[serde(rename = "displayName")] display_name: Option<String>,
+    created: Option<String>,
+    #[serde(rename = "hasVerifiedBadge", default)] has_verified_badge: bool,
+}
+
 #[derive(Deserialize)]
 struct RobloxDevForumUserLookup {
     trust_level: u32,
@@ -32,15 +82,69 @@ struct RobloxBadgesLookup {
     #[serde(rename = "RobloxBadges")] badges: Vec<RobloxBadgeLookup>,
 }
 
+#[derive(Deserialize)]
+struct RobloxBadgeAwardLookup {
+    #[serde(rename = "badgeId")] badge_id: u64,
+}
+
+#[derive(Deserialize)]
+struct RobloxBadgeAwardsLookup {
+    data: Vec<RobloxBadgeAwardLookup>,
+}
+
+#[derive(Deserialize)]
+struct RobloxGroupInfoLookup {
+    id: u64,
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct RobloxGroupRoleLookup {
+    name: String,
+    rank: u32,
+}
+
 #[derive(Deserialize)]
 struct RobloxGroupLookup {
-    #[serde(rename = "Id")] id: u64,
-    #[serde(rename = "Rank")] rank: u32,
+    group: RobloxGroupInfoLookup,
+    role: RobloxGroupRoleLookup,
+}
+
+#[derive(Deserialize)]
+struct RobloxGroupsLookup {
+    data: Vec<RobloxGroupLookup>,
+}
+
+#[derive(Deserialize)]
+struct RobloxThumbnailLookup {
+    state: String,
+    #[serde(rename = "imageUrl")] image_url: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RobloxThumbnailsLookup {
+    data: Vec<RobloxThumbnailLookup>,
+}
+
+#[derive(Deserialize)]
+struct OpenCloudPublishResult {
+    #[serde(rename = "versionNumber")] version_number: u64,
+}
+
+#[derive(Serialize)]
+struct OpenCloudMessageRequest<'a> {
+    message: &'a str,
+}
+
+// Makes a single request to the Roblox API, without retrying, to check that it can be reached.
+pub fn check_api_reachable() -> Result<()> {
+    reqwest::get("https://users.roblox.com/v1/users/1")?.error_for_status()?;
+    Ok(())
 }
 
 pub fn web_profile_exists(id: RobloxUserID) -> Result<bool> {
     let uri = format!("https://www.roblox.com/users/{}/profile", id.0);
-    let response = reqwest::get(&uri)?;
+    let response = CLIENT.get("web", &uri)?;
     let response = if response.status() != StatusCode::NotFound {
         response.error_for_status()?
     } else {
@@ -49,25 +153,68 @@ pub fn web_profile_exists(id: RobloxUserID) -> Result<bool> {
     Ok(response.url().as_str() == "https://www.roblox.com/request-error?code=404")
 }
 
-pub fn for_username(name: &str) -> Result<Option<RobloxUserID>> {
-    let uri = format!("https://api.roblox.com/users/get-by-username?username={}",
-                      percent_encode(name.as_bytes(), QUERY_ENCODE_SET));
-    let json = reqwest::get(&uri)?.error_for_status()?.text()?;
-    let info = serde_json::from_str::<RobloxIDLookup>(&json)?;
-    Ok(info.id.map(RobloxUserID))
+pub fn find_by_username(name: &str) -> Result<Option<(RobloxUserID, String)>> {
+    let request = RobloxUsernamesRequest { usernames: vec![name], exclude_banned_users: false };
+    let uri = route("https://users.roblox.com/v1/usernames/users");
+    let json = CLIENT.send("users", |client| client.post(&uri).json(&request).send())?
+        .error_for_status()?.text()?;
+    let users = serde_json::from_str::<RobloxUsernamesLookup>(&json)?;
+    Ok(users.data.into_iter().next().map(|x| (RobloxUserID(x.id), x.name)))
+}
+
+pub fn get_username_history(id: RobloxUserID) -> Result<Vec<String>> {
+    let uri = format!("https://users.roblox.com/v1/users/{}/username-history?limit=100", id.0);
+    let json = CLIENT.get("users", &uri)?.error_for_status()?.text()?;
+    let history = serde_json::from_str::<RobloxUsernameHistoryLookup>(&json)?;
+    Ok(history.data.into_iter().map(|x| x.name).collect())
 }
 
 pub fn lookup_username(id: RobloxUserID) -> Result<Option<String>> {
     let uri = format!("https://api.roblox.com/users/{}", id.0);
-    let json = reqwest::get(&uri)?.error_for_status()?.text()?;
+    let json = CLIENT.get("users", &uri)?.error_for_status()?.text()?;
     let info = serde_json::from_str::<RobloxIDLookup>(&json)?;
     Ok(info.name)
 }
 
+fn get_user_info(id: RobloxUserID) -> Result<Option<RobloxUserInfoLookup>> {
+    let uri = format!("https://users.roblox.com/v1/users/{}", id.0);
+    let response = CLIENT.get("users", &uri)?;
+    if response.status() == StatusCode::NotFound {
+        return Ok(None)
+    }
+    let json = response.error_for_status()?.text()?;
+    Ok(Some(serde_json::from_str::<RobloxUserInfoLookup>(&json)?))
+}
+
+pub fn get_profile_description(id: RobloxUserID) -> Result<Option<String>> {
+    Ok(get_user_info(id)?.map(|info| info.description.unwrap_or_else(|| String::new())))
+}
+
+pub fn get_display_name(id: RobloxUserID) -> Result<Option<String>> {
+    Ok(get_user_info(id)?.and_then(|info| info.display_name))
+}
+
+pub fn get_creation_time(id: RobloxUserID) -> Result<Option<SystemTime>> {
+    match get_user_info(id)?.and_then(|info| info.created) {
+        Some(created) => Ok(Some(DateTime::parse_from_rfc3339(&created)?.into())),
+        None => Ok(None),
+    }
+}
+
+pub fn has_verified_badge(id: RobloxUserID) -> Result<bool> {
+    Ok(get_user_info(id)?.map_or(false, |info| info.has_verified_badge))
+}
+
+pub fn has_premium(id: RobloxUserID) -> Result<bool> {
+    let uri = format!("https://premiumfeatures.roblox.com/v1/users/{}/validate-membership", id.0);
+    let text = CLIENT.get("premium", &uri)?.error_for_status()?.text()?;
+    Ok(text.trim() == "true")
+}
+
 pub fn get_dev_trust_level(name: &str) -> Result<Option<u32>> {
     let uri = format!("https://devforum.roblox.com/users/{}.json",
                       percent_encode(name.as_bytes(), QUERY_ENCODE_SET));
-    let mut request = reqwest::get(&uri)?;
+    let mut request = CLIENT.get("devforum", &uri)?;
     if request.status().is_success() {
         let lookup = serde_json::from_str::<RobloxDevForumLookup>(&request.text()?)?;
         Ok(Some(lookup.user.trust_level))
@@ -76,33 +223,91 @@ pub fn get_dev_trust_level(name: &str) -> Result<Option<u32>> {
     }
 }
 
-pub fn owns_asset(id: RobloxUserID, asset: u64) -> Result<bool> {
-    let uri = format!("https://api.roblox.com/Ownership/HasAsset?userId={}&assetId={}",
-                      id.0, asset);
-    let text = reqwest::get(&uri)?.error_for_status()?.text()?;
-    Ok(text == "true")
+pub const ITEM_TYPE_ASSET: u32 = 0;
+pub const ITEM_TYPE_GAMEPASS: u32 = 1;
+pub fn owns_item(id: RobloxUserID, item_type: u32, item: u64) -> Result<bool> {
+    let uri = format!("https://inventory.roblox.com/v1/users/{}/items/{}/{}/is-owned",
+                      id.0, item_type, item);
+    let text = CLIENT.get("inventory", &uri)?.error_for_status()?.text()?;
+    Ok(text.trim() == "true")
 }
 
 pub fn get_roblox_badges(id: RobloxUserID) -> Result<HashSet<String>> {
     let uri = format!("https://www.roblox.com/badges/roblox?userId={}", id.0);
-    let json = reqwest::get(&uri)?.error_for_status()?.text()?;
+    let json = CLIENT.get("web", &uri)?.error_for_status()?.text()?;
     let badges = serde_json::from_str::<RobloxBadgesLookup>(&json)?;
     Ok(badges.badges.into_iter().map(|x| x.name).collect())
 }
 
-pub fn has_player_badge(id: RobloxUserID, asset: u64) -> Result<bool> {
-    let uri = format!("https://assetgame.roblox.com/Game/Badge/HasBadge.ashx?UserID={}&BadgeID={}",
-                      id.0, asset);
-    Ok(reqwest::get(&uri)?.error_for_status()?.text()? == "Success")
+pub fn has_player_badge(id: RobloxUserID, badge: u64) -> Result<bool> {
+    let uri = format!("https://badges.roblox.com/v1/users/{}/badges/awarded-dates?badgeIds={}",
+                      id.0, badge);
+    let json = CLIENT.get("badges", &uri)?.error_for_status()?.text()?;
+    let awarded = serde_json::from_str::<RobloxBadgeAwardsLookup>(&json)?;
+    Ok(awarded.data.iter().any(|x| x.badge_id == badge))
+}
+
+pub fn get_group_ranks(id: RobloxUserID) -> Result<Vec<RobloxGroupRank>> {
+    let uri = format!("https://groups.roblox.com/v2/users/{}/groups/roles", id.0);
+    let json = CLIENT.get("groups", &uri)?.error_for_status()?.text()?;
+    let groups = serde_json::from_str::<RobloxGroupsLookup>(&json)?;
+    Ok(groups.data.into_iter().map(|x| RobloxGroupRank {
+        group_id: x.group.id, group_name: x.group.name, rank: x.role.rank, rank_name: x.role.name,
+    }).collect())
+}
+
+// Returns None if the thumbnail has not been generated yet, or cannot be shown.
+pub fn get_avatar_headshot(id: RobloxUserID) -> Result<Option<String>> {
+    let uri = format!("https://thumbnails.roblox.com/v1/users/avatar-headshot\
+                       ?userIds={}&size=150x150&format=Png&isCircular=false", id.0);
+    let json = CLIENT.get("thumbnails", &uri)?.error_for_status()?.text()?;
+    let thumbnails = serde_json::from_str::<RobloxThumbnailsLookup>(&json)?;
+    Ok(thumbnails.data.into_iter().next()
+        .and_then(|x| if x.state == "Completed" { x.image_url } else { None }))
 }
 
-pub fn get_player_groups(id: RobloxUserID) -> Result<HashMap<u64, u32>> {
-    let uri = format!("https://api.roblox.com/users/{}/groups", id.0);
-    let json = reqwest::get(&uri)?.error_for_status()?.text()?;
-    let groups = serde_json::from_str::<Vec<RobloxGroupLookup>>(&json)?;
-    let mut map = HashMap::new();
-    for RobloxGroupLookup { id, rank } in groups {
-        map.insert(id, rank);
+pub fn publish_place(api_key: &str, universe_id: u64, place_id: u64, data: Vec<u8>) -> Result<u64> {
+    let uri = format!("https://apis.roblox.com/universes/v1/{}/places/{}/versions\
+                       ?versionType=Published", universe_id, place_id);
+    let json = CLIENT.send_write("opencloud", |client| {
+        let mut headers = Headers::new();
+        headers.set(ContentType::octet_stream());
+        headers.set_raw("x-api-key", api_key.to_owned());
+        client.post(&uri).headers(headers).body(data.clone()).send()
+    })?.error_for_status()?.text()?;
+    Ok(serde_json::from_str::<OpenCloudPublishResult>(&json)?.version_number)
+}
+
+// Reads an entry of a standard data store in a universe, returning its JSON value, or `None` if
+// the entry does not exist.
+pub fn get_datastore_entry(
+    api_key: &str, universe_id: u64, datastore: &str, key: &str,
+) -> Result<Option<String>> {
+    let uri = format!("https://apis.roblox.com/datastores/v1/universes/{}/standard-datastores/\
+                       datastore/entries/entry?datastoreName={}&entryKey={}",
+                      universe_id, percent_encode(datastore.as_bytes(), QUERY_ENCODE_SET),
+                      percent_encode(key.as_bytes(), QUERY_ENCODE_SET));
+    let response = CLIENT.send("opencloud", |client| {
+        let mut headers = Headers::new();
+        headers.set_raw("x-api-key", api_key.to_owned());
+        client.get(&uri).headers(headers).send()
+    })?;
+    if response.status() == StatusCode::NotFound {
+        return Ok(None)
     }
-    Ok(map)
+    Ok(Some(response.error_for_status()?.text()?))
+}
+
+// Publishes a message to every running server of a universe through MessagingService.
+pub fn publish_message(api_key: &str, universe_id: u64, topic: &str, message: &str) -> Result<()> {
+    let uri = format!("https://apis.roblox.com/messaging-service/v1/universes/{}/topics/{}",
+                      universe_id, percent_encode(topic.as_bytes(), QUERY_ENCODE_SET));
+    let body = serde_json::to_string(&OpenCloudMessageRequest { message })?;
+    CLIENT.send_write("opencloud", |client| {
+        let mut headers = Headers::new();
+        headers.set(ContentType::json());
+        headers.set_raw("x-api-key", api_key.to_owned());
+        client.post(&uri).headers(headers).body(body.clone()).send()
+    })?.error_for_status()?;
+    Ok(())
 }
@@ -0,0 +1,155 @@
+use errors::*;
+use parking_lot::Mutex;
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{SystemTime, Duration};
+
+// The most stale entries refreshed in the background at once. Past this, stale entries are
+// refreshed before returning instead, so a sweep over many users cannot start a thread for each.
+const MAX_BACKGROUND_REFRESHES: usize = 8;
+static BACKGROUND_REFRESHES: AtomicUsize = AtomicUsize::new(0);
+
+fn reserve_background_refresh() -> bool {
+    if BACKGROUND_REFRESHES.fetch_add(1, Ordering::SeqCst) >= MAX_BACKGROUND_REFRESHES {
+        BACKGROUND_REFRESHES.fetch_sub(1, Ordering::SeqCst);
+        false
+    } else {
+        true
+    }
+}
+
+#[derive(Copy, Clone, Default, Debug)]
+pub struct ApiCacheMetrics {
+    pub hits: u64, pub stale_hits: u64, pub misses: u64, pub refreshes: u64, pub entries: usize,
+}
+
+struct ApiCacheData<K: Clone + Eq + Hash, V: Clone> {
+    entries: HashMap<K, (SystemTime, V)>, refreshing: HashSet<K>,
+    lifetime: Duration, stale_lifetime: Duration, metrics: ApiCacheMetrics,
+}
+impl <K: Clone + Eq + Hash, V: Clone> ApiCacheData<K, V> {
+    fn age(time: SystemTime, now: SystemTime) -> Option<Duration> {
+        now.duration_since(time).ok()
+    }
+    fn is_live(&self, time: SystemTime, now: SystemTime) -> bool {
+        Self::age(time, now).map_or(false, |age| age < self.lifetime)
+    }
+    // Entries past their lifetime are still returned for a while, and refreshed in the
+    // background when they are.
+    fn is_usable(&self, time: SystemTime, now: SystemTime) -> bool {
+        Self::age(time, now).map_or(false, |age| age < self.lifetime + self.stale_lifetime)
+    }
+    fn insert(&mut self, k: K, v: V, now: SystemTime) {
+        self.entries.insert(k, (now, v));
+    }
+}
+
+// Results of Roblox API calls that are reused between commands, e.g. when `!update` is run
+// repeatedly or when a user is updated in many guilds at once.
+pub struct ApiCache<K: Clone + Eq + Hash, V: Clone>(Arc<Mutex<ApiCacheData<K, V>>>);
+impl <K: Clone + Eq + Hash + Send + 'static, V: Clone + Send + 'static> ApiCache<K, V> {
+    pub fn new(lifetime: Duration) -> Self {
+        ApiCache(Arc::new(Mutex::new(ApiCacheData {
+            entries: HashMap::new(), refreshing: HashSet::new(),
+            lifetime, stale_lifetime: Duration::from_secs(0),
+            metrics: ApiCacheMetrics::default(),
+        })))
+    }
+
+    // Must only be called after `reserve_background_refresh` returns true.
+    fn refresh_in_background<F>(&self, k: K, f: F) where F: FnOnce() -> Result<V> + Send + 'static {
+        let data = self.0.clone();
+        data.lock().refreshing.insert(k.clone());
+        let spawned = thread::Builder::new().name("api cache refresh".to_string()).spawn({
+            let data = data.clone();
+            let k = k.clone();
+            move || {
+                let result = f();
+                let mut data = data.lock();
+                data.refreshing.remove(&k);
+                match result {
+                    Ok(value) => {
+                        data.metrics.refreshes += 1;
+                        data.insert(k, value, SystemTime::now());
+                    }
+                    Err(e) => debug!("Could not refresh a cached Roblox API result: {}", e),
+                }
+                BACKGROUND_REFRESHES.fetch_sub(1, Ordering::SeqCst);
+            }
+        });
+        if let Err(e) = spawned {
+            debug!("Could not start refreshing a cached Roblox API result: {}", e);
+            data.lock().refreshing.remove(&k);
+            BACKGROUND_REFRESHES.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    pub fn get<F>(&self, k: &K, f: F) -> Result<V>
+        where F: FnOnce() -> Result<V> + Send + 'static {
+        let now = SystemTime::now();
+        let stale = {
+            let mut data = self.0.lock();
+            let cached = data.entries.get(k).map(|&(time, ref value)| (time, value.clone()));
+            match cached {
+                Some((time, value)) => if data.is_live(time, now) {
+                    data.metrics.hits += 1;
+                    return Ok(value)
+                } else if data.is_usable(time, now) {
+                    data.metrics.stale_hits += 1;
+                    if data.refreshing.contains(k) {
+                        return Ok(value)
+                    }
+                    Some(value)
+                } else {
+                    None
+                },
+                None => None,
+            }
+        };
+        match stale {
+            Some(value) => if reserve_background_refresh() {
+                self.refresh_in_background(k.clone(), f);
+                return Ok(value)
+            },
+            None => self.0.lock().metrics.misses += 1,
+        }
+
+        let value = f()?;
+        self.0.lock().insert(k.clone(), value.clone(), now);
+        Ok(value)
+    }
+    pub fn invalidate(&self, k: &K) {
+        self.0.lock().entries.remove(k);
+    }
+
+    pub fn set_lifetimes(&self, lifetime: Duration, stale_lifetime: Duration) {
+        let mut data = self.0.lock();
+        data.lifetime = lifetime;
+        data.stale_lifetime = stale_lifetime;
+    }
+    // Removes entries that are too old to be used, which are otherwise kept until replaced.
+    pub fn prune(&self) {
+        let now = SystemTime::now();
+        let mut data = self.0.lock();
+        let (lifetime, stale_lifetime) = (data.lifetime, data.stale_lifetime);
+        data.entries.retain(|_, &mut (time, _)| {
+            ApiCacheData::<K, V>::age(time, now)
+                .map_or(false, |age| age < lifetime + stale_lifetime)
+        });
+        data.entries.shrink_to_fit();
+    }
+    pub fn flush(&self) -> usize {
+        let mut data = self.0.lock();
+        let count = data.entries.len();
+        data.entries.clear();
+        data.entries.shrink_to_fit();
+        count
+    }
+    pub fn metrics(&self) -> ApiCacheMetrics {
+        let data = self.0.lock();
+        ApiCacheMetrics { entries: data.entries.len(), ..data.metrics }
+    }
+}
@@ -0,0 +1,150 @@
+use errors::*;
+use logger;
+use parking_lot::Mutex;
+use reqwest;
+use reqwest::{Client, Response, StatusCode};
+use std::cmp::min;
+use std::collections::HashMap;
+use std::str::from_utf8;
+use std::thread;
+use std::time::{Duration, Instant};
+use util::InFlightCounter;
+
+const MAX_RETRIES: u32 = 4;
+const BASE_BACKOFF_MS: u64 = 500;
+const MAX_BACKOFF_MS: u64 = 30 * 1000;
+
+#[derive(Copy, Clone, Default, Debug)]
+pub struct EndpointMetrics {
+    pub requests: u64, pub retries: u64, pub rate_limited: u64, pub failures: u64,
+}
+
+#[derive(Default)]
+struct EndpointState {
+    blocked_until: Option<Instant>, metrics: EndpointMetrics,
+}
+
+// Returns where a request to a Roblox API URL is sent. In tests, this is the mock server.
+#[cfg(not(test))]
+pub(super) fn route(uri: &str) -> String {
+    uri.to_owned()
+}
+#[cfg(test)]
+pub(super) fn route(uri: &str) -> String {
+    super::mock::route(uri)
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    status == StatusCode::TooManyRequests || status.is_server_error()
+}
+fn backoff(attempt: u32) -> Duration {
+    Duration::from_millis(min(BASE_BACKOFF_MS << attempt, MAX_BACKOFF_MS))
+}
+fn retry_after(response: &Response) -> Option<Duration> {
+    let raw = response.headers().get_raw("Retry-After")?.one()?;
+    let secs = from_utf8(raw).ok()?.trim().parse::<u64>().ok()?;
+    Some(Duration::from_secs(min(secs, MAX_BACKOFF_MS / 1000)))
+}
+
+// A HTTP client shared by all Roblox API calls. This retries requests that fail because of rate
+// limits or server errors, and makes other requests to a rate limited endpoint wait until the
+// limit is over, so large mass updates do not intermittently fail.
+pub struct ApiClient {
+    client: Client, endpoints: Mutex<HashMap<&'static str, EndpointState>>,
+    in_flight: InFlightCounter,
+}
+impl ApiClient {
+    pub fn new() -> ApiClient {
+        ApiClient {
+            client: Client::new(), endpoints: Mutex::new(HashMap::new()),
+            in_flight: InFlightCounter::default(),
+        }
+    }
+
+    fn with_state<R, F>(&self, endpoint: &'static str, f: F) -> R
+        where F: FnOnce(&mut EndpointState) -> R {
+        f(self.endpoints.lock().entry(endpoint).or_insert_with(EndpointState::default))
+    }
+    fn wait_for_endpoint(&self, endpoint: &'static str) {
+        loop {
+            let blocked_until = self.with_state(endpoint, |x| x.blocked_until);
+            match blocked_until {
+                Some(until) if until > Instant::now() => thread::sleep(until - Instant::now()),
+                _ => return,
+            }
+        }
+    }
+    fn block_endpoint(&self, endpoint: &'static str, duration: Duration) {
+        let until = Instant::now() + duration;
+        self.with_state(endpoint, |x| if x.blocked_until.map_or(true, |x| x < until) {
+            x.blocked_until = Some(until);
+        })
+    }
+
+    // Sends a request, retrying it with exponential backoff if it fails. `endpoint` is a short
+    // name used to group rate limits and metrics, e.g. `"groups"`.
+    //
+    // Responses with other error statuses are returned as is, so callers can handle them.
+    pub fn send<F>(&self, endpoint: &'static str, f: F) -> Result<Response>
+        where F: Fn(&Client) -> reqwest::Result<Response> {
+        self.send_with_retries(endpoint, true, f)
+    }
+    // Sends a request that changes something, e.g. publishing a place. Roblox may have acted on
+    // a request that failed with a server error or lost connection, so these are only retried
+    // when rate limited, as those requests were never processed.
+    pub fn send_write<F>(&self, endpoint: &'static str, f: F) -> Result<Response>
+        where F: Fn(&Client) -> reqwest::Result<Response> {
+        self.send_with_retries(endpoint, false, f)
+    }
+    fn send_with_retries<F>(
+        &self, endpoint: &'static str, retry_failures: bool, f: F,
+    ) -> Result<Response> where F: Fn(&Client) -> reqwest::Result<Response> {
+        let _span = logger::span(format!("roblox {}", endpoint));
+        let _in_flight = self.in_flight.enter();
+        let mut attempt = 0;
+        loop {
+            self.wait_for_endpoint(endpoint);
+            self.with_state(endpoint, |x| x.metrics.requests += 1);
+
+            let (result, delay, rate_limited) = match f(&self.client) {
+                Ok(response) => if is_retryable(response.status()) {
+                    let delay = retry_after(&response).unwrap_or_else(|| backoff(attempt));
+                    let rate_limited = response.status() == StatusCode::TooManyRequests;
+                    if rate_limited {
+                        self.with_state(endpoint, |x| x.metrics.rate_limited += 1);
+                        self.block_endpoint(endpoint, delay);
+                    }
+                    (Ok(response), delay, rate_limited)
+                } else {
+                    return Ok(response)
+                },
+                Err(e) => (Err(e.into()), backoff(attempt), false),
+            };
+
+            if attempt == MAX_RETRIES || !(retry_failures || rate_limited) {
+                self.with_state(endpoint, |x| x.metrics.failures += 1);
+                return result
+            }
+            debug!("Retrying request to Roblox {} API in {} ms.", endpoint,
+                   delay.as_secs() * 1000 + delay.subsec_nanos() as u64 / 1000000);
+            self.with_state(endpoint, |x| x.metrics.retries += 1);
+            thread::sleep(delay);
+            attempt += 1;
+        }
+    }
+
+    pub fn get(&self, endpoint: &'static str, uri: &str) -> Result<Response> {
+        let uri = route(uri);
+        self.send(endpoint, |client| client.get(&uri).send())
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.count()
+    }
+    pub fn metrics(&self) -> Vec<(&'static str, EndpointMetrics)> {
+        let mut metrics: Vec<_> =
+            self.endpoints.lock().iter().map(|(&k, v)| (k, v.metrics)).collect();
+        metrics.sort_by_key(|x| x.0);
+        metrics
+    }
+}
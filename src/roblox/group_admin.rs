@@ -0,0 +1,266 @@
+use errors::*;
+use percent_encoding::{percent_encode, QUERY_ENCODE_SET};
+use reqwest::{Method, Response, StatusCode};
+use reqwest::header::{ContentType, Headers};
+use roblox::RobloxUserID;
+use roblox::api::CLIENT;
+use roblox::session::RobloxSession;
+use serde_json;
+use serde_json::Value;
+
+// The credentials of an account that is allowed to change ranks and manage join requests in a
+// group.
+#[derive(Copy, Clone)]
+pub enum GroupCredentials<'a> {
+    // An Open Cloud API key with the group:write scope.
+    OpenCloud(&'a str),
+    // The .ROBLOSECURITY cookie of a group member with permission to manage lower ranks.
+    Cookie(&'a str),
+}
+
+#[derive(Deserialize)]
+struct RobloxGroupRole {
+    id: u64,
+    rank: u32,
+}
+
+#[derive(Deserialize)]
+struct RobloxGroupRolesLookup {
+    roles: Vec<RobloxGroupRole>,
+}
+
+#[derive(Serialize)]
+struct RobloxSetRankRequest {
+    #[serde(rename = "roleId")] role_id: u64,
+}
+
+#[derive(Deserialize)]
+struct OpenCloudMembership {
+    path: String,
+}
+
+#[derive(Deserialize)]
+struct OpenCloudMembershipsLookup {
+    #[serde(rename = "groupMemberships", default)] memberships: Vec<OpenCloudMembership>,
+}
+
+#[derive(Serialize)]
+struct OpenCloudSetRankRequest {
+    role: String,
+}
+
+#[derive(Deserialize)]
+struct RobloxJoinRequester {
+    #[serde(rename = "userId")] user_id: u64,
+}
+
+#[derive(Deserialize)]
+struct RobloxJoinRequest {
+    requester: RobloxJoinRequester,
+}
+
+#[derive(Deserialize)]
+struct RobloxJoinRequestsLookup {
+    data: Vec<RobloxJoinRequest>,
+    #[serde(rename = "nextPageCursor")] next_page_cursor: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OpenCloudJoinRequest {
+    user: String,
+}
+
+#[derive(Deserialize)]
+struct OpenCloudJoinRequestsLookup {
+    #[serde(rename = "groupJoinRequests", default)] join_requests: Vec<OpenCloudJoinRequest>,
+    #[serde(rename = "nextPageToken", default)] next_page_token: Option<String>,
+}
+
+// Join requests are read at most this many pages at a time. Any past that are handled on a later
+// check, once the earlier ones have been accepted or declined.
+const MAX_JOIN_REQUEST_PAGES: u32 = 10;
+
+// Roblox reports errors as `{"errors": [{"message": ...}]}` on the web APIs, and as
+// `{"message": ...}` on Open Cloud.
+fn error_message(response: &mut Response) -> String {
+    let text = match response.text() {
+        Ok(text) => text,
+        Err(_) => return format!("{}", response.status()),
+    };
+    let message = serde_json::from_str::<Value>(&text).ok().and_then(|json| {
+        json["errors"][0]["message"].as_str().or_else(|| json["message"].as_str())
+            .map(|x| x.to_owned())
+    });
+    match message {
+        Some(message) => message,
+        None if text.trim().is_empty() => format!("{}", response.status()),
+        None => text,
+    }
+}
+
+// `action` describes what was being done, e.g. "change ranks".
+fn check_response(mut response: Response, group_id: u64, action: &str) -> Result<Response> {
+    match response.status() {
+        status if status.is_success() => Ok(response),
+        StatusCode::Unauthorized =>
+            cmd_error!("The Roblox credentials used to manage groups are invalid or have \
+                        expired."),
+        StatusCode::Forbidden =>
+            cmd_error!("The Roblox credentials used to manage groups are not allowed to {} in \
+                        group {}: {}", action, group_id, error_message(&mut response)),
+        _ =>
+            cmd_error!("Roblox could not {} in group {}: {}",
+                       action, group_id, error_message(&mut response)),
+    }
+}
+
+fn open_cloud_headers(api_key: &str) -> Headers {
+    let mut headers = Headers::new();
+    headers.set(ContentType::json());
+    headers.set_raw("x-api-key", api_key.to_owned());
+    headers
+}
+
+fn find_role_id(group_id: u64, rank: u32) -> Result<u64> {
+    let uri = format!("https://groups.roblox.com/v1/groups/{}/roles", group_id);
+    let json = CLIENT.get("groups", &uri)?.error_for_status()?.text()?;
+    serde_json::from_str::<RobloxGroupRolesLookup>(&json)?.roles.into_iter()
+        .find(|x| x.rank == rank).map(|x| x.id)
+        .to_cmd_err(|| format!("Group {} has no role with rank {}.", group_id, rank))
+}
+
+fn set_role_with_cookie(
+    cookie: &str, group_id: u64, user: RobloxUserID, role_id: u64,
+) -> Result<()> {
+    let uri = format!("https://groups.roblox.com/v1/groups/{}/users/{}", group_id, user.0);
+    let body = serde_json::to_string(&RobloxSetRankRequest { role_id })?;
+    let response = RobloxSession { cookie }.request("group_admin", Method::Patch, &uri,
+                                                    Some(&body))?;
+    check_response(response, group_id, "change ranks")?;
+    Ok(())
+}
+
+fn set_role_with_open_cloud(
+    api_key: &str, group_id: u64, user: RobloxUserID, role_id: u64,
+) -> Result<()> {
+    let filter = format!("user == 'users/{}'", user.0);
+    let uri = format!("https://apis.roblox.com/cloud/v2/groups/{}/memberships\
+                       ?maxPageSize=1&filter={}",
+                      group_id, percent_encode(filter.as_bytes(), QUERY_ENCODE_SET));
+    let response = CLIENT.send("opencloud", |client| {
+        client.get(&uri).headers(open_cloud_headers(api_key)).send()
+    })?;
+    let json = check_response(response, group_id, "change ranks")?.text()?;
+    let membership = serde_json::from_str::<OpenCloudMembershipsLookup>(&json)?.memberships
+        .into_iter().next()
+        .to_cmd_err(|| format!("Roblox user #{} is not a member of group {}.", user.0, group_id))?;
+
+    let uri = format!("https://apis.roblox.com/cloud/v2/{}", membership.path);
+    let body = serde_json::to_string(&OpenCloudSetRankRequest {
+        role: format!("groups/{}/roles/{}", group_id, role_id),
+    })?;
+    let response = CLIENT.send("opencloud", |client| {
+        client.request(Method::Patch, &uri).headers(open_cloud_headers(api_key))
+            .body(body.clone()).send()
+    })?;
+    check_response(response, group_id, "change ranks")?;
+    Ok(())
+}
+
+// Changes the rank of a member of a group. The credentials must belong to an account that ranks
+// above both the member's current rank and the new one.
+pub fn set_group_rank(
+    credentials: GroupCredentials, group_id: u64, user: RobloxUserID, rank: u32,
+) -> Result<()> {
+    cmd_ensure!(rank > 0 && rank < 255, "Only ranks between 1 and 254 can be given to members.");
+    let role_id = find_role_id(group_id, rank)?;
+    match credentials {
+        GroupCredentials::OpenCloud(api_key) =>
+            set_role_with_open_cloud(api_key, group_id, user, role_id),
+        GroupCredentials::Cookie(cookie) =>
+            set_role_with_cookie(cookie, group_id, user, role_id),
+    }
+}
+
+fn list_join_requests_with_cookie(cookie: &str, group_id: u64) -> Result<Vec<RobloxUserID>> {
+    let mut users = Vec::new();
+    let mut cursor = None;
+    for _ in 0..MAX_JOIN_REQUEST_PAGES {
+        let uri = format!("https://groups.roblox.com/v1/groups/{}/join-requests\
+                           ?limit=100&sortOrder=Asc&cursor={}",
+                          group_id, cursor.as_ref().map_or("", |x: &String| x.as_str()));
+        let response = RobloxSession { cookie }.request("group_admin", Method::Get, &uri, None)?;
+        let json = check_response(response, group_id, "manage join requests")?.text()?;
+        let page = serde_json::from_str::<RobloxJoinRequestsLookup>(&json)?;
+        users.extend(page.data.into_iter().map(|x| RobloxUserID(x.requester.user_id)));
+        match page.next_page_cursor {
+            Some(next) => cursor = Some(next),
+            None => break,
+        }
+    }
+    Ok(users)
+}
+
+fn list_join_requests_with_open_cloud(
+    api_key: &str, group_id: u64,
+) -> Result<Vec<RobloxUserID>> {
+    let mut users = Vec::new();
+    let mut page_token = None;
+    for _ in 0..MAX_JOIN_REQUEST_PAGES {
+        let uri = format!("https://apis.roblox.com/cloud/v2/groups/{}/join-requests\
+                           ?maxPageSize=100&pageToken={}",
+                          group_id, page_token.as_ref().map_or("", |x: &String| x.as_str()));
+        let response = CLIENT.send("opencloud", |client| {
+            client.get(&uri).headers(open_cloud_headers(api_key)).send()
+        })?;
+        let json = check_response(response, group_id, "manage join requests")?.text()?;
+        let page = serde_json::from_str::<OpenCloudJoinRequestsLookup>(&json)?;
+        for request in page.join_requests {
+            // Users are given as `users/<id>`.
+            let id = request.user.rsplit('/').next().and_then(|x| x.parse().ok());
+            match id {
+                Some(id) => users.push(RobloxUserID(id)),
+                None => warn!("Ignoring join request from unknown user '{}'.", request.user),
+            }
+        }
+        match page.next_page_token {
+            Some(ref next) if !next.is_empty() => page_token = Some(next.clone()),
+            _ => break,
+        }
+    }
+    Ok(users)
+}
+
+// Returns the users waiting for a request to join a group to be accepted, oldest first.
+pub fn list_join_requests(
+    credentials: GroupCredentials, group_id: u64,
+) -> Result<Vec<RobloxUserID>> {
+    match credentials {
+        GroupCredentials::OpenCloud(api_key) =>
+            list_join_requests_with_open_cloud(api_key, group_id),
+        GroupCredentials::Cookie(cookie) => list_join_requests_with_cookie(cookie, group_id),
+    }
+}
+
+// Accepts a user's request to join a group, or declines it if `accept` is false.
+pub fn answer_join_request(
+    credentials: GroupCredentials, group_id: u64, user: RobloxUserID, accept: bool,
+) -> Result<()> {
+    let response = match credentials {
+        GroupCredentials::OpenCloud(api_key) => {
+            let uri = format!("https://apis.roblox.com/cloud/v2/groups/{}/join-requests/{}:{}",
+                              group_id, user.0, if accept { "accept" } else { "decline" });
+            CLIENT.send_write("opencloud", |client| {
+                client.post(&uri).headers(open_cloud_headers(api_key)).body("{}".to_owned()).send()
+            })?
+        }
+        GroupCredentials::Cookie(cookie) => {
+            let uri = format!("https://groups.roblox.com/v1/groups/{}/join-requests/users/{}",
+                              group_id, user.0);
+            let method = if accept { Method::Post } else { Method::Delete };
+            RobloxSession { cookie }.request("group_admin", method, &uri, Some("{}"))?
+        }
+    };
+    check_response(response, group_id, "manage join requests")?;
+    Ok(())
+}
@@ -0,0 +1,77 @@
+// A fake Roblox API for tests. While tests are running, lookups made through the API client are
+// sent to a local server instead, which answers with responses the tests have set up. Requests
+// for anything else are answered with a 404.
+
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+struct MockServer {
+    origin: String, responses: Mutex<HashMap<String, (u16, String)>>,
+}
+
+lazy_static! {
+    static ref SERVER: MockServer = MockServer::start();
+}
+
+impl MockServer {
+    fn start() -> MockServer {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("could not start mock server");
+        let origin = format!("http://{}", listener.local_addr().unwrap());
+        thread::Builder::new().name("mock roblox server".to_owned()).spawn(move || {
+            for stream in listener.incoming() {
+                if let Ok(stream) = stream {
+                    thread::spawn(move || SERVER.handle(stream));
+                }
+            }
+        }).unwrap();
+        MockServer { origin, responses: Mutex::new(HashMap::new()) }
+    }
+
+    fn handle(&self, stream: TcpStream) {
+        let mut reader = BufReader::new(stream);
+        let mut request_line = String::new();
+        if reader.read_line(&mut request_line).is_err() {
+            return
+        }
+        let mut content_length = 0;
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header).is_err() || header.trim().is_empty() {
+                break
+            }
+            let header = header.to_lowercase();
+            if header.starts_with("content-length:") {
+                content_length = header["content-length:".len()..].trim().parse().unwrap_or(0);
+            }
+        }
+        let mut body = vec![0; content_length];
+        if reader.read_exact(&mut body).is_err() {
+            return
+        }
+
+        // The path is the original URL without its scheme, e.g. `/users.roblox.com/v1/users/1`.
+        let path = request_line.split(' ').nth(1).unwrap_or("").trim_left_matches('/');
+        let (status, body) = self.responses.lock().get(path).cloned()
+            .unwrap_or_else(|| (404, "{}".to_owned()));
+        let response = format!("HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\n\
+                                Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                               status, body.len(), body);
+        reader.into_inner().write_all(response.as_bytes()).ok();
+    }
+}
+
+// Returns where a request to the Roblox API is sent during tests.
+pub(super) fn route(uri: &str) -> String {
+    let path = uri.trim_left_matches("https://").trim_left_matches("http://");
+    format!("{}/{}", SERVER.origin, path)
+}
+
+// Makes requests to `url` be answered with `status` and `body`. Tests share the server, so each
+// should use its own Roblox user IDs.
+pub fn respond(url: &str, status: u16, body: &str) {
+    let path = url.trim_left_matches("https://").to_owned();
+    SERVER.responses.lock().insert(path, (status, body.to_owned()));
+}
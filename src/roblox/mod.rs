@@ -1,26 +1,222 @@
 mod api;
+mod cache;
+mod client;
+mod group_admin;
 mod lz4;
+#[cfg(test)] pub mod mock;
+mod oauth;
 mod place;
+mod place_xml;
 mod rules;
+mod session;
 
-pub use self::place::{create_place_file, LuaConfigEntry, LuaConfigValue};
+pub use self::api::{publish_place, publish_message, get_datastore_entry, api_metrics,
+                    in_flight_requests, check_api_reachable};
+pub use self::cache::ApiCacheMetrics;
+pub use self::client::EndpointMetrics;
+pub use self::group_admin::{GroupCredentials, list_join_requests};
+pub use self::oauth::{RobloxOAuthApp, RobloxOAuthTokens};
+pub use self::place::{create_place_file, create_xml_place_file, LuaConfigEntry,
+                      LuaConfigValue};
 pub use self::rules::{VerificationRule, VerificationSet};
+pub use self::session::{RobloxSession, RobloxFriendRequest};
+
+use self::cache::ApiCache;
+use std::sync::Arc;
+use std::time::{SystemTime, Duration};
+
+lazy_static! {
+    static ref GROUP_RANKS: ApiCache<RobloxUserID, Arc<Vec<RobloxGroupRank>>> =
+        ApiCache::new(Duration::from_secs(60 * 5));
+    static ref BADGES: ApiCache<(RobloxUserID, u64), bool> =
+        ApiCache::new(Duration::from_secs(60 * 5));
+    static ref OWNED_ITEMS: ApiCache<(RobloxUserID, u32, u64), bool> =
+        ApiCache::new(Duration::from_secs(60 * 5));
+    static ref USERNAMES: ApiCache<String, Option<(RobloxUserID, String)>> =
+        ApiCache::new(Duration::from_secs(60 * 5));
+    static ref USERNAMES_BY_ID: ApiCache<RobloxUserID, Option<String>> =
+        ApiCache::new(Duration::from_secs(60 * 5));
+    static ref DISPLAY_NAMES: ApiCache<RobloxUserID, Option<String>> =
+        ApiCache::new(Duration::from_secs(60 * 5));
+    static ref PREMIUM: ApiCache<RobloxUserID, bool> =
+        ApiCache::new(Duration::from_secs(60 * 5));
+    static ref VERIFIED_BADGES: ApiCache<RobloxUserID, bool> =
+        ApiCache::new(Duration::from_secs(60 * 5));
+    static ref HEADSHOTS: ApiCache<RobloxUserID, Option<String>> =
+        ApiCache::new(Duration::from_secs(60 * 60));
+}
+
+// Sets how long API results are cached for, and how long after that they are still used while
+// being refreshed in the background. Avatar headshots change rarely, so they have their own
+// lifetime.
+pub fn set_api_cache_lifetimes(lifetime: Duration, headshot_lifetime: Duration, stale: Duration) {
+    GROUP_RANKS.set_lifetimes(lifetime, stale);
+    BADGES.set_lifetimes(lifetime, stale);
+    OWNED_ITEMS.set_lifetimes(lifetime, stale);
+    USERNAMES.set_lifetimes(lifetime, stale);
+    USERNAMES_BY_ID.set_lifetimes(lifetime, stale);
+    DISPLAY_NAMES.set_lifetimes(lifetime, stale);
+    PREMIUM.set_lifetimes(lifetime, stale);
+    VERIFIED_BADGES.set_lifetimes(lifetime, stale);
+    HEADSHOTS.set_lifetimes(headshot_lifetime, stale);
+}
+pub fn api_cache_metrics() -> Vec<(&'static str, ApiCacheMetrics)> {
+    vec![
+        ("group ranks", GROUP_RANKS.metrics()),
+        ("badges", BADGES.metrics()),
+        ("owned items", OWNED_ITEMS.metrics()),
+        ("username lookups", USERNAMES.metrics()),
+        ("usernames by ID", USERNAMES_BY_ID.metrics()),
+        ("display names", DISPLAY_NAMES.metrics()),
+        ("premium", PREMIUM.metrics()),
+        ("verified badges", VERIFIED_BADGES.metrics()),
+        ("avatar headshots", HEADSHOTS.metrics()),
+    ]
+}
+// Removes cached API results that have expired, on the cleanup tick.
+pub fn prune_api_caches() {
+    GROUP_RANKS.prune();
+    BADGES.prune();
+    OWNED_ITEMS.prune();
+    USERNAMES.prune();
+    USERNAMES_BY_ID.prune();
+    DISPLAY_NAMES.prune();
+    PREMIUM.prune();
+    VERIFIED_BADGES.prune();
+    HEADSHOTS.prune();
+}
+// Forgets every cached API result. Returns how many were removed.
+pub fn flush_api_caches() -> usize {
+    GROUP_RANKS.flush() + BADGES.flush() + OWNED_ITEMS.flush() + USERNAMES.flush() +
+        USERNAMES_BY_ID.flush() + DISPLAY_NAMES.flush() + PREMIUM.flush() +
+        VERIFIED_BADGES.flush() + HEADSHOTS.flush()
+}
+
+#[derive(Clone, Debug)]
+pub struct RobloxGroupRank {
+    pub group_id: u64, pub group_name: String, pub rank: u32, pub rank_name: String,
+}
 
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
 pub struct RobloxUserID(pub u64);
 impl RobloxUserID {
     pub fn for_username(name: &str) -> ::errors::Result<RobloxUserID> {
-        match api::for_username(name)? {
-            Some(id) => Ok(id),
+        match Self::find_by_username(name)? {
+            Some((id, _)) => Ok(id),
             None => cmd_error!("No Roblox user named '{}' found.", name),
         }
     }
 
+    // Returns the user that currently has a username, along with the username's correct
+    // capitalization. This does not find users that have since changed their username.
+    pub fn find_by_username(
+        name: &str,
+    ) -> ::errors::Result<Option<(RobloxUserID, String)>> {
+        let name = name.trim().to_owned();
+        USERNAMES.get(&name.to_lowercase(), move || api::find_by_username(&name))
+    }
+
+    pub fn past_usernames(&self) -> ::errors::Result<Vec<String>> {
+        api::get_username_history(*self)
+    }
+
     pub fn lookup_username_opt(&self) -> ::errors::Result<Option<String>> {
-        api::lookup_username(*self)
+        let id = *self;
+        USERNAMES_BY_ID.get(self, move || api::lookup_username(id))
     }
 
     pub fn lookup_username(&self) -> ::errors::Result<String> {
         Ok(self.lookup_username_opt()??)
     }
-}
\ No newline at end of file
+
+    pub fn profile_description(&self) -> ::errors::Result<Option<String>> {
+        api::get_profile_description(*self)
+    }
+    // Returns the name shown for the user on Roblox, which unlike their username does not have to
+    // be unique. Returns `None` for accounts that do not exist.
+    pub fn display_name(&self) -> ::errors::Result<Option<String>> {
+        let id = *self;
+        DISPLAY_NAMES.get(self, move || api::get_display_name(id))
+    }
+
+    // Returns the URL of the user's avatar headshot. While Roblox is still generating it, or if
+    // the thumbnails API cannot be reached, this falls back to a URL that redirects to it.
+    pub fn avatar_headshot_url(&self) -> String {
+        let id = *self;
+        match HEADSHOTS.get(self, move || api::get_avatar_headshot(id)) {
+            Ok(Some(url)) => return url,
+            Ok(None) => HEADSHOTS.invalidate(self),
+            Err(e) => debug!("Could not look up the avatar of Roblox user #{}: {}", self.0, e),
+        }
+        format!("https://www.roblox.com/headshot-thumbnail/image\
+                 ?userId={}&width=150&height=150&format=png", self.0)
+    }
+
+    // Accounts that were deleted have no creation date, so their age cannot be checked.
+    pub fn creation_time(&self) -> ::errors::Result<SystemTime> {
+        match api::get_creation_time(*self)? {
+            Some(created) => Ok(created),
+            None => cmd_error!("Roblox user #{} was not found, so the age of the account could \
+                                not be checked.", self.0),
+        }
+    }
+
+    pub fn get_group_ranks(&self) -> ::errors::Result<Arc<Vec<RobloxGroupRank>>> {
+        let id = *self;
+        GROUP_RANKS.get(self, move || Ok(Arc::new(api::get_group_ranks(id)?)))
+    }
+    pub fn get_group_rank(&self, group_id: u64) -> ::errors::Result<Option<RobloxGroupRank>> {
+        Ok(self.get_group_ranks()?.iter().find(|x| x.group_id == group_id).cloned())
+    }
+    // Looks up the user's rank in a group without using cached ranks, for when they may have
+    // just joined it.
+    pub fn get_current_group_rank(
+        &self, group_id: u64,
+    ) -> ::errors::Result<Option<RobloxGroupRank>> {
+        GROUP_RANKS.invalidate(self);
+        self.get_group_rank(group_id)
+    }
+    pub fn set_group_rank(
+        &self, credentials: GroupCredentials, group_id: u64, rank: u32,
+    ) -> ::errors::Result<()> {
+        group_admin::set_group_rank(credentials, group_id, *self, rank)?;
+        GROUP_RANKS.invalidate(self);
+        Ok(())
+    }
+    // Accepts or declines the user's pending request to join a group.
+    pub fn answer_join_request(
+        &self, credentials: GroupCredentials, group_id: u64, accept: bool,
+    ) -> ::errors::Result<()> {
+        group_admin::answer_join_request(credentials, group_id, *self, accept)?;
+        if accept {
+            GROUP_RANKS.invalidate(self);
+        }
+        Ok(())
+    }
+
+    pub fn has_premium(&self) -> ::errors::Result<bool> {
+        let id = *self;
+        PREMIUM.get(self, move || api::has_premium(id))
+    }
+    // Whether the user has the verified badge Roblox shows next to the names of notable users.
+    pub fn has_verified_badge(&self) -> ::errors::Result<bool> {
+        let id = *self;
+        VERIFIED_BADGES.get(self, move || api::has_verified_badge(id))
+    }
+
+    pub fn has_badge(&self, badge_id: u64) -> ::errors::Result<bool> {
+        let id = *self;
+        BADGES.get(&(id, badge_id), move || api::has_player_badge(id, badge_id))
+    }
+
+    pub fn owns_asset(&self, asset_id: u64) -> ::errors::Result<bool> {
+        let id = *self;
+        OWNED_ITEMS.get(&(id, api::ITEM_TYPE_ASSET, asset_id),
+                        move || api::owns_item(id, api::ITEM_TYPE_ASSET, asset_id))
+    }
+    pub fn owns_gamepass(&self, gamepass_id: u64) -> ::errors::Result<bool> {
+        let id = *self;
+        OWNED_ITEMS.get(&(id, api::ITEM_TYPE_GAMEPASS, gamepass_id),
+                        move || api::owns_item(id, api::ITEM_TYPE_GAMEPASS, gamepass_id))
+    }
+}
@@ -0,0 +1,121 @@
+use errors::*;
+use reqwest::{StatusCode, Url};
+use reqwest::header::{Authorization, Bearer};
+use roblox::RobloxUserID;
+use roblox::api::CLIENT;
+use serde_json;
+use std::time::{Duration, SystemTime};
+
+const AUTHORIZE_URL: &str = "https://apis.roblox.com/oauth/v1/authorize";
+const TOKEN_URL: &str = "https://apis.roblox.com/oauth/v1/token";
+const USER_INFO_URL: &str = "https://apis.roblox.com/oauth/v1/userinfo";
+
+// Access tokens are treated as expired a little early, so they do not expire while in use.
+const EXPIRY_MARGIN_SECS: u64 = 30;
+
+#[derive(Deserialize)]
+struct RobloxTokenResponse {
+    access_token: String,
+    refresh_token: Option<String>,
+    expires_in: u64,
+}
+
+#[derive(Deserialize)]
+struct RobloxUserInfo {
+    sub: String,
+    preferred_username: Option<String>,
+}
+
+// The tokens returned when a user signs in with their Roblox account.
+#[derive(Clone, Debug)]
+pub struct RobloxOAuthTokens {
+    pub access_token: String, pub refresh_token: Option<String>, pub expires_at: SystemTime,
+}
+impl RobloxOAuthTokens {
+    pub fn is_expired(&self) -> bool {
+        SystemTime::now() + Duration::from_secs(EXPIRY_MARGIN_SECS) >= self.expires_at
+    }
+}
+
+// An OAuth application registered on the Roblox creator dashboard.
+#[derive(Copy, Clone)]
+pub struct RobloxOAuthApp<'a> {
+    pub client_id: &'a str, pub client_secret: &'a str,
+}
+impl <'a> RobloxOAuthApp<'a> {
+    // Returns the URL users are sent to in order to sign in with their Roblox account. Roblox
+    // redirects them back to `redirect_uri` with a code to pass to `exchange_code`.
+    pub fn authorize_url(&self, redirect_uri: &str, state: &str) -> Result<String> {
+        let mut url = Url::parse(AUTHORIZE_URL)?;
+        url.query_pairs_mut()
+            .append_pair("client_id", self.client_id)
+            .append_pair("redirect_uri", redirect_uri)
+            .append_pair("scope", "openid profile")
+            .append_pair("response_type", "code")
+            .append_pair("state", state);
+        Ok(url.into_string())
+    }
+
+    fn request_tokens(&self, grant: &[(&str, &str)]) -> Result<RobloxOAuthTokens> {
+        let mut params = vec![("client_id", self.client_id), ("client_secret", self.client_secret)];
+        params.extend_from_slice(grant);
+        let mut response = CLIENT.send_write("oauth", |client| {
+            client.post(TOKEN_URL).form(&params).send()
+        })?;
+        match response.status() {
+            status if status.is_success() => { }
+            StatusCode::BadRequest | StatusCode::Unauthorized =>
+                cmd_error!("Roblox did not accept the sign in. It may have expired, or already \
+                            been used. Please sign in again."),
+            status => bail!("Roblox returned {} when requesting OAuth tokens.", status),
+        }
+        let tokens = serde_json::from_str::<RobloxTokenResponse>(&response.text()?)?;
+        Ok(RobloxOAuthTokens {
+            access_token: tokens.access_token, refresh_token: tokens.refresh_token,
+            expires_at: SystemTime::now() + Duration::from_secs(tokens.expires_in),
+        })
+    }
+
+    // Exchanges the code from a sign in for tokens. `redirect_uri` must be the one the sign in
+    // was started with.
+    pub fn exchange_code(&self, code: &str, redirect_uri: &str) -> Result<RobloxOAuthTokens> {
+        self.request_tokens(&[
+            ("grant_type", "authorization_code"), ("code", code), ("redirect_uri", redirect_uri),
+        ])
+    }
+
+    // Replaces tokens that have expired with new ones. Roblox issues a new refresh token each
+    // time one is used, so the old tokens cannot be used again afterwards.
+    pub fn refresh(&self, tokens: &mut RobloxOAuthTokens) -> Result<()> {
+        if tokens.is_expired() {
+            let refresh_token = tokens.refresh_token.clone()
+                .to_cmd_err(|| "The Roblox sign in has expired. Please sign in again.")?;
+            *tokens = self.request_tokens(&[
+                ("grant_type", "refresh_token"), ("refresh_token", refresh_token.as_str()),
+            ])?;
+        }
+        Ok(())
+    }
+
+    // Returns the Roblox account the tokens belong to, along with its current username.
+    pub fn get_user(
+        &self, tokens: &mut RobloxOAuthTokens,
+    ) -> Result<(RobloxUserID, Option<String>)> {
+        self.refresh(tokens)?;
+        let json = CLIENT.send("oauth", |client| {
+            let auth = Authorization(Bearer { token: tokens.access_token.clone() });
+            client.get(USER_INFO_URL).header(auth).send()
+        })?.error_for_status()?.text()?;
+        let info = serde_json::from_str::<RobloxUserInfo>(&json)?;
+        let id = info.sub.parse().to_cmd_err(|| "Roblox returned an invalid user ID.")?;
+        Ok((RobloxUserID(id), info.preferred_username))
+    }
+
+    // Exchanges the code from a sign in for the Roblox account that signed in.
+    pub fn sign_in(
+        &self, code: &str, redirect_uri: &str,
+    ) -> Result<(RobloxUserID, Option<String>)> {
+        let mut tokens = self.exchange_code(code, redirect_uri)?;
+        self.get_user(&mut tokens)
+    }
+}
@@ -1,6 +1,7 @@
 use byteorder::*;
 use errors::*;
 use roblox::lz4;
+use roblox::place_xml::write_rblx_xml;
 use std::borrow::Cow;
 use std::collections::HashMap;
 use std::fmt::{Display, Formatter, Write as FmtWrite, Result as FmtResult};
@@ -10,19 +11,19 @@ use uuid::Uuid;
 const RBLX_HEADER: &[u8] = b"<roblox!\x89\xff\r\n\x1a\n\x00\x00";
 const RBLX_END: &[u8] = b"\x00\x00\x00\x00\t\x00\x00\x00\x00\x00\x00\x00</roblox>";
 
-const INST_HEADER: u32 = 0x494E5354;
-const PROP_HEADER: u32 = 0x50524F50;
+pub(super) const INST_HEADER: u32 = 0x494E5354;
+pub(super) const PROP_HEADER: u32 = 0x50524F50;
 const END0_HEADER: u32 = 0x454E4400;
 
-const STRING_TYPE: u8 = 0x1;
+pub(super) const STRING_TYPE: u8 = 0x1;
 
 #[derive(Clone, Debug)]
-enum RblxCompressed<'a> {
+pub(super) enum RblxCompressed<'a> {
     Compressed { decompressed_len: u32, data: &'a [u8] },
     Decompressed(Vec<u8>),
 }
 impl <'a> RblxCompressed<'a> {
-    fn decompress(&'a self) -> Result<Cow<'a, [u8]>> {
+    pub(super) fn decompress(&'a self) -> Result<Cow<'a, [u8]>> {
         match *self {
             RblxCompressed::Compressed { decompressed_len, data } => {
                 Ok(Cow::from(lz4::decompress(data, decompressed_len as usize)?))
@@ -45,13 +46,13 @@ impl <'a> RblxCompressed<'a> {
 }
 
 #[derive(Clone, Debug)]
-struct RblxEntry<'a> {
-    kind: u32, data: RblxCompressed<'a>,
+pub(super) struct RblxEntry<'a> {
+    pub(super) kind: u32, pub(super) data: RblxCompressed<'a>,
 }
 
 #[derive(Clone, Debug)]
-struct RblxData<'a> {
-    type_count: u32, inst_count: u32, entries: Vec<RblxEntry<'a>>,
+pub(super) struct RblxData<'a> {
+    type_count: u32, inst_count: u32, pub(super) entries: Vec<RblxEntry<'a>>,
 }
 
 fn read_cursor_slice<'a>(cursor: &mut Cursor<&'a [u8]>, len: usize) -> &'a [u8] {
@@ -229,7 +230,7 @@ fn write_rblx_container<W: Write>(w: &mut W, rblx: &RblxData) -> Result<()> {
 
 #[derive(Clone, Debug)]
 pub enum LuaConfigValue<'a> {
-    Binary(Cow<'a, [u8]>), String(Cow<'a, str>), Double(f64), Nil,
+    Binary(Cow<'a, [u8]>), String(Cow<'a, str>), Double(f64), Bool(bool), Nil,
 }
 impl <'a> Display for LuaConfigValue<'a> {
     fn fmt(&self, f: &mut Formatter) -> FmtResult {
@@ -244,6 +245,7 @@ impl <'a> Display for LuaConfigValue<'a> {
             }
             LuaConfigValue::String(ref s) => write!(f, "[[{}]]", s.replace("]", "]]..']'..[[")),
             LuaConfigValue::Double(val) => val.fmt(f),
+            LuaConfigValue::Bool(val) => val.fmt(f),
             LuaConfigValue::Nil => f.write_str("nil"),
         }
     }
@@ -278,6 +280,11 @@ impl <'a> From<Vec<u8>> for LuaConfigValue<'a> {
         LuaConfigValue::Binary(Cow::from(b))
     }
 }
+impl <'a> From<bool> for LuaConfigValue<'a> {
+    fn from(b: bool) -> Self {
+        LuaConfigValue::Bool(b)
+    }
+}
 impl <'a> From<i8> for LuaConfigValue<'a> {
     fn from(i: i8) -> Self {
         LuaConfigValue::Double(i as f64)
@@ -363,14 +370,14 @@ fn make_config(config: &[LuaConfigEntry], is_server: bool) -> Result<String> {
 }
 
 const PLACE_TEMPLATE: &[u8] = include_bytes!("place-template.rbxl");
-const TEMPLATE_VERSION: &str = "1";
+const TEMPLATE_VERSION: &str = "2";
 lazy_static! {
     static ref CONFIG_UUID_NAMESPACE: Uuid =
         "5314b09e-e38b-11e7-952b-5ef6654dc049".parse().unwrap();
 }
 
-pub fn create_place_file(overwrite_template: Option<&[u8]>,
-                         config: &[LuaConfigEntry]) -> Result<Vec<u8>> {
+fn configure_place<'a>(overwrite_template: Option<&'a [u8]>,
+                       config: &[LuaConfigEntry]) -> Result<RblxData<'a>> {
     let place_file = overwrite_template.unwrap_or(PLACE_TEMPLATE);
     let mut place = parse_rblx_container(place_file)?;
     let mut version_found = false;
@@ -430,8 +437,16 @@ pub fn create_place_file(overwrite_template: Option<&[u8]>,
             "Place has no client config ModuleScript!");
     ensure!(template_message, "Place has no template marker TextLabel!");
     ensure!(version_found, "Place has no version property!");
-
+    Ok(place)
+}
+pub fn create_place_file(overwrite_template: Option<&[u8]>,
+                         config: &[LuaConfigEntry]) -> Result<Vec<u8>> {
+    let place = configure_place(overwrite_template, config)?;
     let mut cursor = Cursor::new(Vec::new());
     write_rblx_container(&mut cursor, &place)?;
     Ok(cursor.into_inner())
+}
+pub fn create_xml_place_file(overwrite_template: Option<&[u8]>,
+                             config: &[LuaConfigEntry]) -> Result<Vec<u8>> {
+    write_rblx_xml(&configure_place(overwrite_template, config)?)
 }
\ No newline at end of file
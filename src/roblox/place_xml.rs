@@ -0,0 +1,549 @@
+use byteorder::*;
+use errors::*;
+use roblox::place::*;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::io::{Read, Cursor};
+
+// Converts the binary place format (.rbxl) into the XML place format (.rbxlx).
+//
+// Only the property types used by verification place templates are supported. Other types are
+// rejected rather than dropped, so an exported place is never silently missing properties.
+
+const PRNT_HEADER: u32 = 0x50524E54;
+
+const BOOL_TYPE: u8 = 0x2;
+const INT_TYPE: u8 = 0x3;
+const FLOAT_TYPE: u8 = 0x4;
+const DOUBLE_TYPE: u8 = 0x5;
+const UDIM_TYPE: u8 = 0x6;
+const UDIM2_TYPE: u8 = 0x7;
+const BRICK_COLOR_TYPE: u8 = 0xB;
+const COLOR3_TYPE: u8 = 0xC;
+const VECTOR2_TYPE: u8 = 0xD;
+const VECTOR3_TYPE: u8 = 0xE;
+const CFRAME_TYPE: u8 = 0x10;
+const ENUM_TYPE: u8 = 0x12;
+const REF_TYPE: u8 = 0x13;
+const RECT2D_TYPE: u8 = 0x18;
+const PHYSICAL_PROPERTIES_TYPE: u8 = 0x19;
+const COLOR3_UINT8_TYPE: u8 = 0x1A;
+const INT64_TYPE: u8 = 0x1B;
+
+// Properties stored as strings in the binary format that hold arbitrary bytes. Other strings are
+// written as binary too if they cannot be written as XML text.
+const BINARY_STRING_PROPERTIES: &[&str] = &[
+    "Tags", "AttributesSerialize", "SmoothGrid", "PhysicsGrid", "MaterialColors",
+];
+
+const XML_HEADER: &str =
+    "<roblox xmlns:xmime=\"http://www.w3.org/2005/05/xmlmime\" \
+     xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" \
+     xsi:noNamespaceSchemaLocation=\"http://www.roblox.com/roblox.xsd\" version=\"4\">\n\
+     \t<External>null</External>\n\
+     \t<External>nil</External>\n";
+const XML_FOOTER: &str = "</roblox>\n";
+
+enum PropertyValue {
+    String(Vec<u8>), Bool(bool), Int(i32), Float(f32), Double(f64), UDim(f32, i32),
+    UDim2(f32, i32, f32, i32), BrickColor(u32), Color3(f32, f32, f32), Vector2(f32, f32),
+    Vector3(f32, f32, f32), CFrame([f32; 3], [f32; 9]), Enum(u32), Ref(i32),
+    Rect2D(f32, f32, f32, f32), PhysicalProperties(Option<[f32; 5]>), Color3uint8(u8, u8, u8),
+    Int64(i64),
+}
+
+struct Instance {
+    class: String, properties: Vec<(String, PropertyValue)>,
+}
+
+fn read_bytes<R: Read>(r: &mut R, len: usize) -> Result<Vec<u8>> {
+    let mut vec = vec![0u8; len];
+    r.read_exact(&mut vec)?;
+    Ok(vec)
+}
+fn read_string<R: Read>(r: &mut R) -> Result<Vec<u8>> {
+    let len = r.read_u32::<LE>()?;
+    read_bytes(r, len as usize)
+}
+
+// Arrays of numbers are stored with the bytes of each value interleaved, so the first byte of
+// every value comes first, then the second byte of every value, and so on.
+fn read_interleaved<R: Read>(r: &mut R, count: usize, width: usize) -> Result<Vec<u64>> {
+    let data = read_bytes(r, count * width)?;
+    Ok((0..count).map(|i| {
+        (0..width).fold(0u64, |acc, j| (acc << 8) | data[j * count + i] as u64)
+    }).collect())
+}
+fn read_u32s<R: Read>(r: &mut R, count: usize) -> Result<Vec<u32>> {
+    Ok(read_interleaved(r, count, 4)?.into_iter().map(|x| x as u32).collect())
+}
+// Signed integers are stored with the sign in the lowest bit.
+fn read_i32s<R: Read>(r: &mut R, count: usize) -> Result<Vec<i32>> {
+    Ok(read_u32s(r, count)?.into_iter().map(|x| (x >> 1) as i32 ^ -((x & 1) as i32)).collect())
+}
+fn read_i64s<R: Read>(r: &mut R, count: usize) -> Result<Vec<i64>> {
+    Ok(read_interleaved(r, count, 8)?.into_iter()
+        .map(|x| (x >> 1) as i64 ^ -((x & 1) as i64)).collect())
+}
+// Floats are stored with the sign bit rotated to the lowest bit.
+fn read_f32s<R: Read>(r: &mut R, count: usize) -> Result<Vec<f32>> {
+    Ok(read_u32s(r, count)?.into_iter().map(|x| f32::from_bits(x.rotate_right(1))).collect())
+}
+// Referents are stored as the difference from the previous referent.
+fn read_referents<R: Read>(r: &mut R, count: usize) -> Result<Vec<i32>> {
+    let mut last = 0;
+    Ok(read_i32s(r, count)?.into_iter().map(|x| {
+        last += x;
+        last
+    }).collect())
+}
+
+// Returns the rotation matrix of a CFrame stored as one of the 24 axis aligned orientations.
+fn cframe_rotation(id: u8) -> Result<[f32; 9]> {
+    fn axis(id: u8) -> [f32; 3] {
+        let mut axis = [0.0; 3];
+        axis[(id % 3) as usize] = if id < 3 { 1.0 } else { -1.0 };
+        axis
+    }
+    ensure!(id >= 2 && id <= 0x23 && ((id - 1) / 6) % 3 != ((id - 1) % 6) % 3,
+            "unknown CFrame orientation {}", id);
+    let (x, y) = (axis((id - 1) / 6), axis((id - 1) % 6));
+    let z = [x[1] * y[2] - x[2] * y[1], x[2] * y[0] - x[0] * y[2], x[0] * y[1] - x[1] * y[0]];
+    Ok([x[0], x[1], x[2], y[0], y[1], y[2], z[0], z[1], z[2]])
+}
+
+fn parse_property_values(
+    cursor: &mut Cursor<&[u8]>, name: &str, data_type: u8, count: usize,
+) -> Result<Vec<PropertyValue>> {
+    Ok(match data_type {
+        STRING_TYPE => (0..count).map(|_| read_string(cursor).map(PropertyValue::String))
+            .collect::<Result<_>>()?,
+        BOOL_TYPE => read_bytes(cursor, count)?.into_iter()
+            .map(|x| PropertyValue::Bool(x != 0)).collect(),
+        INT_TYPE => read_i32s(cursor, count)?.into_iter().map(PropertyValue::Int).collect(),
+        FLOAT_TYPE => read_f32s(cursor, count)?.into_iter().map(PropertyValue::Float).collect(),
+        DOUBLE_TYPE => (0..count).map(|_| Ok(PropertyValue::Double(cursor.read_f64::<LE>()?)))
+            .collect::<Result<_>>()?,
+        UDIM_TYPE => {
+            let scale = read_f32s(cursor, count)?;
+            let offset = read_i32s(cursor, count)?;
+            (0..count).map(|i| PropertyValue::UDim(scale[i], offset[i])).collect()
+        }
+        UDIM2_TYPE => {
+            let (scale_x, scale_y) = (read_f32s(cursor, count)?, read_f32s(cursor, count)?);
+            let (offset_x, offset_y) = (read_i32s(cursor, count)?, read_i32s(cursor, count)?);
+            (0..count).map(|i| {
+                PropertyValue::UDim2(scale_x[i], offset_x[i], scale_y[i], offset_y[i])
+            }).collect()
+        }
+        BRICK_COLOR_TYPE =>
+            read_u32s(cursor, count)?.into_iter().map(PropertyValue::BrickColor).collect(),
+        COLOR3_TYPE => {
+            let (r, g) = (read_f32s(cursor, count)?, read_f32s(cursor, count)?);
+            let b = read_f32s(cursor, count)?;
+            (0..count).map(|i| PropertyValue::Color3(r[i], g[i], b[i])).collect()
+        }
+        VECTOR2_TYPE => {
+            let (x, y) = (read_f32s(cursor, count)?, read_f32s(cursor, count)?);
+            (0..count).map(|i| PropertyValue::Vector2(x[i], y[i])).collect()
+        }
+        VECTOR3_TYPE => {
+            let (x, y) = (read_f32s(cursor, count)?, read_f32s(cursor, count)?);
+            let z = read_f32s(cursor, count)?;
+            (0..count).map(|i| PropertyValue::Vector3(x[i], y[i], z[i])).collect()
+        }
+        CFRAME_TYPE => {
+            let mut rotations = Vec::new();
+            for _ in 0..count {
+                let id = cursor.read_u8()?;
+                rotations.push(if id == 0 {
+                    let mut rotation = [0.0; 9];
+                    for value in &mut rotation {
+                        *value = cursor.read_f32::<LE>()?;
+                    }
+                    rotation
+                } else {
+                    cframe_rotation(id)?
+                });
+            }
+            let (x, y) = (read_f32s(cursor, count)?, read_f32s(cursor, count)?);
+            let z = read_f32s(cursor, count)?;
+            rotations.into_iter().enumerate()
+                .map(|(i, rotation)| PropertyValue::CFrame([x[i], y[i], z[i]], rotation))
+                .collect()
+        }
+        ENUM_TYPE => read_u32s(cursor, count)?.into_iter().map(PropertyValue::Enum).collect(),
+        REF_TYPE => read_referents(cursor, count)?.into_iter().map(PropertyValue::Ref).collect(),
+        RECT2D_TYPE => {
+            let (min_x, min_y) = (read_f32s(cursor, count)?, read_f32s(cursor, count)?);
+            let (max_x, max_y) = (read_f32s(cursor, count)?, read_f32s(cursor, count)?);
+            (0..count).map(|i| {
+                PropertyValue::Rect2D(min_x[i], min_y[i], max_x[i], max_y[i])
+            }).collect()
+        }
+        PHYSICAL_PROPERTIES_TYPE => {
+            let mut values = Vec::new();
+            for _ in 0..count {
+                values.push(PropertyValue::PhysicalProperties(if cursor.read_u8()? != 0 {
+                    let mut properties = [0.0; 5];
+                    for value in &mut properties {
+                        *value = cursor.read_f32::<LE>()?;
+                    }
+                    Some(properties)
+                } else {
+                    None
+                }));
+            }
+            values
+        }
+        COLOR3_UINT8_TYPE => {
+            let (r, g) = (read_bytes(cursor, count)?, read_bytes(cursor, count)?);
+            let b = read_bytes(cursor, count)?;
+            (0..count).map(|i| PropertyValue::Color3uint8(r[i], g[i], b[i])).collect()
+        }
+        INT64_TYPE => read_i64s(cursor, count)?.into_iter().map(PropertyValue::Int64).collect(),
+        _ => bail!("property '{}' has an unsupported type {:#x}", name, data_type),
+    })
+}
+
+struct PlaceTree {
+    instances: HashMap<i32, Instance>, children: HashMap<i32, Vec<i32>>,
+}
+fn parse_place_tree(rblx: &RblxData) -> Result<PlaceTree> {
+    let mut instances = HashMap::new();
+    let mut types = HashMap::new();
+    let mut children = HashMap::new();
+    for entry in &rblx.entries {
+        let data = entry.data.decompress()?;
+        let mut cursor = Cursor::new(data.as_ref());
+        match entry.kind {
+            INST_HEADER => {
+                let type_id = cursor.read_u32::<LE>()?;
+                let class = String::from_utf8(read_string(&mut cursor)?)?;
+                cursor.read_u8()?;
+                let count = cursor.read_u32::<LE>()? as usize;
+                let referents = read_referents(&mut cursor, count)?;
+                for &referent in &referents {
+                    instances.insert(referent, Instance {
+                        class: class.clone(), properties: Vec::new(),
+                    });
+                }
+                types.insert(type_id, referents);
+            }
+            PROP_HEADER => {
+                let type_id = cursor.read_u32::<LE>()?;
+                let name = String::from_utf8(read_string(&mut cursor)?)?;
+                let data_type = cursor.read_u8()?;
+                let referents = match types.get(&type_id) {
+                    Some(referents) => referents,
+                    None => bail!("property '{}' found for an unknown type", name),
+                };
+                let values =
+                    parse_property_values(&mut cursor, &name, data_type, referents.len())?;
+                for (referent, value) in referents.iter().zip(values) {
+                    if let Some(instance) = instances.get_mut(referent) {
+                        instance.properties.push((name.clone(), value));
+                    }
+                }
+            }
+            PRNT_HEADER => {
+                cursor.read_u8()?;
+                let count = cursor.read_u32::<LE>()? as usize;
+                let child_ids = read_referents(&mut cursor, count)?;
+                let parent_ids = read_referents(&mut cursor, count)?;
+                for (child, parent) in child_ids.into_iter().zip(parent_ids) {
+                    children.entry(parent).or_insert_with(Vec::new).push(child);
+                }
+            }
+            _ => { }
+        }
+    }
+    Ok(PlaceTree { instances, children })
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8] =
+        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter().enumerate()
+            .fold(0u32, |acc, (i, &x)| acc | (x as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+fn is_binary_char(c: char) -> bool {
+    (c as u32) < 0x20 && c != '\t' && c != '\n' && c != '\r'
+}
+fn escape(s: &str) -> String {
+    let mut out = String::new();
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\r' => out.push_str("&#13;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+fn float(f: f32) -> String {
+    if f.is_nan() {
+        "NAN".to_owned()
+    } else if f.is_infinite() && f > 0.0 {
+        "INF".to_owned()
+    } else if f.is_infinite() {
+        "-INF".to_owned()
+    } else {
+        f.to_string()
+    }
+}
+
+fn write_property(out: &mut String, name: &str, value: &PropertyValue) -> Result<()> {
+    let name = escape(name);
+    match *value {
+        PropertyValue::String(ref data) => match String::from_utf8(data.clone()) {
+            Ok(ref text) if !BINARY_STRING_PROPERTIES.contains(&name.as_str()) &&
+                            !text.chars().any(is_binary_char) => {
+                let tag = if name == "Source" { "ProtectedString" } else { "string" };
+                write!(out, "<{0} name=\"{1}\">{2}</{0}>", tag, name, escape(text))?
+            }
+            _ => write!(out, "<BinaryString name=\"{}\">{}</BinaryString>", name, base64(data))?,
+        },
+        PropertyValue::Bool(b) => write!(out, "<bool name=\"{}\">{}</bool>", name, b)?,
+        PropertyValue::Int(i) => write!(out, "<int name=\"{}\">{}</int>", name, i)?,
+        PropertyValue::Float(f) =>
+            write!(out, "<float name=\"{}\">{}</float>", name, float(f))?,
+        PropertyValue::Double(d) => write!(out, "<double name=\"{}\">{}</double>", name, d)?,
+        PropertyValue::UDim(s, o) =>
+            write!(out, "<UDim name=\"{}\"><S>{}</S><O>{}</O></UDim>", name, float(s), o)?,
+        PropertyValue::UDim2(xs, xo, ys, yo) =>
+            write!(out, "<UDim2 name=\"{}\"><XS>{}</XS><XO>{}</XO><YS>{}</YS><YO>{}</YO></UDim2>",
+                   name, float(xs), xo, float(ys), yo)?,
+        PropertyValue::BrickColor(c) => write!(out, "<int name=\"{}\">{}</int>", name, c)?,
+        PropertyValue::Color3(r, g, b) =>
+            write!(out, "<Color3 name=\"{}\"><R>{}</R><G>{}</G><B>{}</B></Color3>",
+                   name, float(r), float(g), float(b))?,
+        PropertyValue::Vector2(x, y) =>
+            write!(out, "<Vector2 name=\"{}\"><X>{}</X><Y>{}</Y></Vector2>",
+                   name, float(x), float(y))?,
+        PropertyValue::Vector3(x, y, z) =>
+            write!(out, "<Vector3 name=\"{}\"><X>{}</X><Y>{}</Y><Z>{}</Z></Vector3>",
+                   name, float(x), float(y), float(z))?,
+        PropertyValue::CFrame(ref position, ref rotation) => {
+            write!(out, "<CoordinateFrame name=\"{}\"><X>{}</X><Y>{}</Y><Z>{}</Z>",
+                   name, float(position[0]), float(position[1]), float(position[2]))?;
+            for (i, value) in rotation.iter().enumerate() {
+                write!(out, "<R{0}{1}>{2}</R{0}{1}>", i / 3, i % 3, float(*value))?;
+            }
+            write!(out, "</CoordinateFrame>")?
+        }
+        PropertyValue::Enum(e) => write!(out, "<token name=\"{}\">{}</token>", name, e)?,
+        PropertyValue::Ref(-1) => write!(out, "<Ref name=\"{}\">null</Ref>", name)?,
+        PropertyValue::Ref(r) => write!(out, "<Ref name=\"{}\">RBX{}</Ref>", name, r)?,
+        PropertyValue::Rect2D(min_x, min_y, max_x, max_y) =>
+            write!(out, "<Rect2D name=\"{}\"><min><X>{}</X><Y>{}</Y></min>\
+                         <max><X>{}</X><Y>{}</Y></max></Rect2D>",
+                   name, float(min_x), float(min_y), float(max_x), float(max_y))?,
+        PropertyValue::PhysicalProperties(None) =>
+            write!(out, "<PhysicalProperties name=\"{}\"><CustomPhysics>false</CustomPhysics>\
+                         </PhysicalProperties>", name)?,
+        PropertyValue::PhysicalProperties(Some(ref p)) =>
+            write!(out, "<PhysicalProperties name=\"{}\"><CustomPhysics>true</CustomPhysics>\
+                         <Density>{}</Density><Friction>{}</Friction>\
+                         <Elasticity>{}</Elasticity><FrictionWeight>{}</FrictionWeight>\
+                         <ElasticityWeight>{}</ElasticityWeight></PhysicalProperties>",
+                   name, float(p[0]), float(p[1]), float(p[2]), float(p[3]), float(p[4]))?,
+        PropertyValue::Color3uint8(r, g, b) =>
+            write!(out, "<Color3uint8 name=\"{}\">{}</Color3uint8>", name,
+                   0xFF000000u32 | (r as u32) << 16 | (g as u32) << 8 | b as u32)?,
+        PropertyValue::Int64(i) => write!(out, "<int64 name=\"{}\">{}</int64>", name, i)?,
+    }
+    Ok(())
+}
+fn write_instance(out: &mut String, tree: &PlaceTree, referent: i32, depth: usize) -> Result<()> {
+    let indent = "\t".repeat(depth);
+    let instance = match tree.instances.get(&referent) {
+        Some(instance) => instance,
+        None => bail!("place hierarchy refers to an unknown instance {}", referent),
+    };
+    writeln!(out, "{}<Item class=\"{}\" referent=\"RBX{}\">",
+             indent, escape(&instance.class), referent)?;
+    writeln!(out, "{}\t<Properties>", indent)?;
+    for &(ref name, ref value) in &instance.properties {
+        write!(out, "{}\t\t", indent)?;
+        write_property(out, name, value)?;
+        writeln!(out)?;
+    }
+    writeln!(out, "{}\t</Properties>", indent)?;
+    if let Some(children) = tree.children.get(&referent) {
+        for &child in children {
+            write_instance(out, tree, child, depth + 1)?;
+        }
+    }
+    writeln!(out, "{}</Item>", indent)?;
+    Ok(())
+}
+
+pub(super) fn write_rblx_xml(rblx: &RblxData) -> Result<Vec<u8>> {
+    let tree = parse_place_tree(rblx)?;
+    let mut out = XML_HEADER.to_owned();
+    if let Some(roots) = tree.children.get(&-1) {
+        for &root in roots {
+            write_instance(&mut out, &tree, root, 1)?;
+        }
+    }
+    out.push_str(XML_FOOTER);
+    Ok(out.into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interleave(values: &[u64], width: usize) -> Vec<u8> {
+        let mut data = vec![0u8; values.len() * width];
+        for (i, value) in values.iter().enumerate() {
+            for j in 0..width {
+                data[j * values.len() + i] = (value >> (8 * (width - 1 - j))) as u8;
+            }
+        }
+        data
+    }
+    fn encode_i32(x: i32) -> u64 {
+        ((x << 1) ^ (x >> 31)) as u32 as u64
+    }
+
+    #[test]
+    fn interleaved_round_trip() {
+        let ints = [0, 1, -1, 300, -300, i32::max_value(), i32::min_value()];
+        let data = interleave(&ints.iter().map(|&x| encode_i32(x)).collect::<Vec<_>>(), 4);
+        assert_eq!(read_i32s(&mut Cursor::new(&data), ints.len()).unwrap(), ints);
+
+        let floats = [0.0, -0.0, 1.5, -2.25, 1e30, ::std::f32::INFINITY];
+        let data = interleave(&floats.iter()
+            .map(|x| x.to_bits().rotate_left(1) as u64).collect::<Vec<_>>(), 4);
+        let read = read_f32s(&mut Cursor::new(&data), floats.len()).unwrap();
+        assert_eq!(read.iter().map(|x| x.to_bits()).collect::<Vec<_>>(),
+                   floats.iter().map(|x| x.to_bits()).collect::<Vec<_>>());
+
+        let int64s = [0i64, -1, 1 << 40, -(1 << 40)];
+        let data = interleave(&int64s.iter()
+            .map(|&x| ((x << 1) ^ (x >> 63)) as u64).collect::<Vec<_>>(), 8);
+        assert_eq!(read_i64s(&mut Cursor::new(&data), int64s.len()).unwrap(), int64s);
+
+        let referents = [-1, 0, 1, 5, 2];
+        let mut last = 0;
+        let deltas = referents.iter().map(|&x| {
+            let delta = encode_i32(x - last);
+            last = x;
+            delta
+        }).collect::<Vec<_>>();
+        let data = interleave(&deltas, 4);
+        assert_eq!(read_referents(&mut Cursor::new(&data), referents.len()).unwrap(), referents);
+    }
+
+    #[test]
+    fn cframe_rotations() {
+        assert_eq!(cframe_rotation(0x02).unwrap(), [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0]);
+        assert!(cframe_rotation(0x01).is_err());
+        assert!(cframe_rotation(0x24).is_err());
+    }
+
+    #[test]
+    fn base64_encoding() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(escape("<a href=\"x\">&\r</a>"),
+                   "&lt;a href=&quot;x&quot;&gt;&amp;&#13;&lt;/a&gt;");
+        assert_eq!(escape("plain\ttext\n"), "plain\ttext\n");
+    }
+
+    fn property(name: &str, value: PropertyValue) -> String {
+        let mut out = String::new();
+        write_property(&mut out, name, &value).unwrap();
+        out
+    }
+
+    #[test]
+    fn string_properties() {
+        assert_eq!(property("Source", PropertyValue::String(b"print(\"<3\")".to_vec())),
+                   "<ProtectedString name=\"Source\">print(&quot;&lt;3&quot;)</ProtectedString>");
+        assert_eq!(property("Name", PropertyValue::String(b"A & B".to_vec())),
+                   "<string name=\"Name\">A &amp; B</string>");
+        assert_eq!(property("Value", PropertyValue::String(b"a\x01".to_vec())),
+                   "<BinaryString name=\"Value\">YQE=</BinaryString>");
+        assert_eq!(property("Value", PropertyValue::String(vec![0xFF])),
+                   "<BinaryString name=\"Value\">/w==</BinaryString>");
+        assert_eq!(property("Tags", PropertyValue::String(b"abc".to_vec())),
+                   "<BinaryString name=\"Tags\">YWJj</BinaryString>");
+    }
+
+    #[test]
+    fn other_properties() {
+        assert_eq!(property("Size", PropertyValue::Float(::std::f32::NAN)),
+                   "<float name=\"Size\">NAN</float>");
+        assert_eq!(property("Size", PropertyValue::Float(::std::f32::NEG_INFINITY)),
+                   "<float name=\"Size\">-INF</float>");
+        assert_eq!(property("Parent", PropertyValue::Ref(-1)), "<Ref name=\"Parent\">null</Ref>");
+        assert_eq!(property("Target", PropertyValue::Ref(7)),
+                   "<Ref name=\"Target\">RBX7</Ref>");
+        assert_eq!(property("Color", PropertyValue::Color3uint8(0x12, 0x34, 0x56)),
+                   format!("<Color3uint8 name=\"Color\">{}</Color3uint8>", 0xFF123456u32));
+    }
+
+    #[test]
+    fn instance_hierarchy() {
+        let mut instances = HashMap::new();
+        instances.insert(0, Instance {
+            class: "Folder".to_owned(),
+            properties: vec![("Name".to_owned(), PropertyValue::String(b"<root>".to_vec()))],
+        });
+        instances.insert(1, Instance {
+            class: "BoolValue".to_owned(),
+            properties: vec![("Value".to_owned(), PropertyValue::Bool(true))],
+        });
+        let mut children = HashMap::new();
+        children.insert(0, vec![1]);
+        let tree = PlaceTree { instances, children };
+
+        let mut out = String::new();
+        write_instance(&mut out, &tree, 0, 0).unwrap();
+        assert_eq!(out, "<Item class=\"Folder\" referent=\"RBX0\">\n\
+                         \t<Properties>\n\
+                         \t\t<string name=\"Name\">&lt;root&gt;</string>\n\
+                         \t</Properties>\n\
+                         \t<Item class=\"BoolValue\" referent=\"RBX1\">\n\
+                         \t\t<Properties>\n\
+                         \t\t\t<bool name=\"Value\">true</bool>\n\
+                         \t\t</Properties>\n\
+                         \t</Item>\n\
+                         </Item>\n");
+        assert!(write_instance(&mut String::new(), &tree, 2, 0).is_err());
+    }
+
+    #[test]
+    fn template_conversion() {
+        let xml = create_xml_place_file(None, &[]).unwrap();
+        let xml = String::from_utf8(xml).unwrap();
+        assert!(xml.starts_with(XML_HEADER));
+        assert!(xml.ends_with(XML_FOOTER));
+        assert!(xml.contains("<Item class=\"ModuleScript\""));
+        assert!(xml.contains("local config = {}\nreturn config"));
+    }
+}
@@ -14,6 +14,8 @@ const DEFAULT_RULE_DEFS: &[(&str, &str)] = &[
     ("TBC", "badge(Turbo Builders Club)"),
     ("OBC", "badge(Outrageous Builders Club)"),
     ("DevForum", "dev_trust_level(2+)"),
+    ("Premium", "has_premium()"),
+    ("VerifiedBadge", "has_verified_badge()"),
     ("RobloxAdmin", "badge(Administrator) or group(1200769)"),
     // TODO: Allow using rank names
     ("FormerAccelerator", "group(2868472, 6)"),
@@ -35,12 +37,14 @@ lazy_static!(
 
 #[derive(Debug)]
 enum Token<'a> {
-    Term(&'a str, &'a str), Literal(bool), Not, Or, And, OpenParen, CloseParen,
+    Term(&'a str, &'a str), Compare(Condition), Literal(bool), Not, Or, And,
+    OpenParen, CloseParen,
 }
 impl <'a> fmt::Display for Token<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Token::Term(start, body) => write!(f, "{}({})", start, body),
+            Token::Compare(cond)     => write!(f, "{}", cond),
             Token::Literal(b)        => write!(f, "{}", b),
             Token::Not               => write!(f, "not"),
             Token::Or                => write!(f, "or"),
@@ -75,6 +79,9 @@ fn tokenize_rule(rule: &str) -> Result<VecDeque<Token>> {
     let mut tokens = VecDeque::new();
     while current_pos < rule.len() {
         current_pos = advance_whitespace(rule, current_pos);
+        if current_pos == rule.len() {
+            break
+        }
         match rule[current_pos] {
             b'(' => {
                 tokens.push_back(Token::OpenParen);
@@ -111,6 +118,28 @@ fn tokenize_rule(rule: &str) -> Result<VecDeque<Token>> {
                     }
                 }
             }
+            b'>' | b'<' | b'=' => {
+                let op_start = current_pos;
+                current_pos += 1;
+                if current_pos < rule.len() && rule[current_pos] == b'=' {
+                    current_pos += 1;
+                }
+                let op = from_utf8(&rule[op_start..current_pos])?;
+                current_pos = advance_whitespace(rule, current_pos);
+                let num_start = current_pos;
+                while current_pos < rule.len() && rule[current_pos].is_ascii_digit() {
+                    current_pos += 1;
+                }
+                let num = from_utf8(&rule[num_start..current_pos])?;
+                let num = num.parse()
+                    .to_cmd_err(|| format!("Expected a number after '{}'.", op))?;
+                tokens.push_back(Token::Compare(match op {
+                    ">=" => Condition::GreaterOrEqual(num),
+                    "<=" => Condition::LessOrEqual(num),
+                    "=" | "==" => Condition::Equals(num),
+                    _ => cmd_error!("Unknown comparison '{}'. Use '>=', '<=' or '=='.", op),
+                }));
+            }
             c => cmd_error!("Unexpected character: '{}'", c),
         }
     }
@@ -137,6 +166,15 @@ impl Condition {
         }
     }
 }
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Condition::Equals(i) => write!(f, "== {}", i),
+            Condition::GreaterOrEqual(i) => write!(f, ">= {}", i),
+            Condition::LessOrEqual(i) => write!(f, "<= {}", i),
+        }
+    }
+}
 fn parse_condition(condition: &str) -> Result<Condition> {
     if condition.ends_with('+') {
         Ok(Condition::GreaterOrEqual(condition[..condition.len()-1].parse()?))
@@ -167,9 +205,12 @@ enum RuleOp {
     CheckBadge(String),
     CheckPlayerBadge(u64),
     CheckOwnsAsset(u64),
+    CheckOwnsGamepass(u64),
     CheckInGroup(u64, Option<Condition>),
     CheckDevTrustLevel(Condition),
     CheckIsBanned,
+    CheckHasPremium,
+    CheckHasVerifiedBadge,
 }
 impl RuleOp {
     pub fn stack_change(&self) -> isize {
@@ -184,18 +225,24 @@ impl RuleOp {
             RuleOp::CheckBadge(_)              =>  1,
             RuleOp::CheckPlayerBadge(_)        =>  1,
             RuleOp::CheckOwnsAsset(_)          =>  1,
+            RuleOp::CheckOwnsGamepass(_)       =>  1,
             RuleOp::CheckInGroup(_, _)         =>  1,
             RuleOp::CheckDevTrustLevel(_)      =>  1,
             RuleOp::CheckIsBanned              =>  1,
+            RuleOp::CheckHasPremium            =>  1,
+            RuleOp::CheckHasVerifiedBadge      =>  1,
         }
     }
 }
 
-fn parse_term(start: &str, body: &str) -> Result<RuleOp> {
+fn parse_term(start: &str, body: &str, compare: Option<Condition>) -> Result<RuleOp> {
+    if compare.is_some() && start != "group" && start != "dev_trust_level" {
+        cmd_error!("{}({}) cannot be compared to a number.", start, body)
+    }
     match start {
         "badge" =>
             Ok(RuleOp::CheckBadge(body.to_owned())),
-        "player_badge" => {
+        "player_badge" | "has_badge" => {
             let badge = body.parse()
                 .to_cmd_err(|| format!("Badge id is not a number: {}", body))?;
             Ok(RuleOp::CheckPlayerBadge(badge))
@@ -205,7 +252,16 @@ fn parse_term(start: &str, body: &str) -> Result<RuleOp> {
                 .to_cmd_err(|| format!("Asset id is not a number: {}", body))?;
             Ok(RuleOp::CheckOwnsAsset(asset))
         }
+        "owns_gamepass" => {
+            let gamepass = body.parse()
+                .to_cmd_err(|| format!("Gamepass id is not a number: {}", body))?;
+            Ok(RuleOp::CheckOwnsGamepass(gamepass))
+        }
         "dev_trust_level" => {
+            if let Some(compare) = compare {
+                cmd_ensure!(body == "", "dev_trust_level cannot take a parameter when compared.");
+                return Ok(RuleOp::CheckDevTrustLevel(compare))
+            }
             let level = parse_condition(body)
                 .to_cmd_err(|| format!("Invalid trust level: {}", body))?;
             Ok(RuleOp::CheckDevTrustLevel(level))
@@ -216,8 +272,10 @@ fn parse_term(start: &str, body: &str) -> Result<RuleOp> {
             let group = group.parse()
                 .to_cmd_err(|| format!("Group ID is not a number: {}", group))?;
             if split.len() == 1 {
-                Ok(RuleOp::CheckInGroup(group, None))
+                Ok(RuleOp::CheckInGroup(group, compare))
             } else if split.len() == 2 {
+                cmd_ensure!(compare.is_none(),
+                            "group({}) cannot have both a rank parameter and a comparison.", body);
                 let level = split[1].trim();
                 let level = parse_condition(level)
                     .to_cmd_err(|| format!("Invalid group level: {}", level))?;
@@ -230,6 +288,14 @@ fn parse_term(start: &str, body: &str) -> Result<RuleOp> {
             ensure!(body == "", "is_banned takes no parameters.");
             Ok(RuleOp::CheckIsBanned)
         }
+        "has_premium" => {
+            cmd_ensure!(body == "", "has_premium takes no parameters.");
+            Ok(RuleOp::CheckHasPremium)
+        }
+        "has_verified_badge" => {
+            cmd_ensure!(body == "", "has_verified_badge takes no parameters.");
+            Ok(RuleOp::CheckHasVerifiedBadge)
+        }
         _ => cmd_error!("Unknown term {}({})", start, body),
     }
 }
@@ -313,7 +379,16 @@ impl VerificationRule {
                         is_operand_context = false;
                     }
                     Token::Term(start, body) => {
+                        let compare = match tokens.front() {
+                            Some(&Token::Compare(compare)) => Some(compare),
+                            _ => None,
+                        };
+                        if compare.is_some() {
+                            tokens.pop_front();
+                        }
                         if start == "custom_rule" || start == "builtin_rule" {
+                            cmd_ensure!(compare.is_none(),
+                                        "{}({}) cannot be compared to a number.", start, body);
                             let source = if start == "custom_rule" {
                                 RuleSource::CustomRule(body.to_owned())
                             } else {
@@ -330,7 +405,7 @@ impl VerificationRule {
                             };
                             ctx.push_term(RuleOp::Read(no))
                         } else {
-                            ctx.push_term(parse_term(start, body)?)
+                            ctx.push_term(parse_term(start, body, compare)?)
                         }
                         is_operand_context = false;
                     }
@@ -395,13 +470,16 @@ fn option_cache<T, F>(opt: &mut Option<T>, f: F) -> Result<&T> where F: FnOnce()
 
 struct VerificationCountContext {
     username: bool, dev_trust_level: bool, badges: bool, groups: bool,
-    profile_exists: bool, player_badges: HashSet<u64>, owns_asset: HashSet<u64>,
+    profile_exists: bool, premium: bool, verified_badge: bool, player_badges: HashSet<u64>,
+    owns_asset: HashSet<u64>, owns_gamepass: HashSet<u64>,
 }
 impl VerificationCountContext {
     fn new() -> VerificationCountContext {
         VerificationCountContext {
             username: false, dev_trust_level: false, badges: false, groups: false,
-            profile_exists: false, player_badges: HashSet::new(), owns_asset: HashSet::new(),
+            profile_exists: false, premium: false, verified_badge: false,
+            player_badges: HashSet::new(), owns_asset: HashSet::new(),
+            owns_gamepass: HashSet::new(),
         }
     }
 
@@ -421,12 +499,21 @@ impl VerificationCountContext {
     fn uses_groups(&mut self) {
         self.groups = true;
     }
+    fn uses_premium(&mut self) {
+        self.premium = true;
+    }
+    fn uses_verified_badge(&mut self) {
+        self.verified_badge = true;
+    }
     fn uses_has_player_badge(&mut self, badge_id: u64) {
         self.player_badges.insert(badge_id);
     }
     fn uses_owns_asset(&mut self, asset_id: u64) {
         self.owns_asset.insert(asset_id);
     }
+    fn uses_owns_gamepass(&mut self, gamepass_id: u64) {
+        self.owns_gamepass.insert(gamepass_id);
+    }
 
     fn count(&self) -> usize {
         let mut count = 0;
@@ -434,8 +521,11 @@ impl VerificationCountContext {
         if self.dev_trust_level { count += 1 }
         if self.badges          { count += 1 }
         if self.groups          { count += 1 }
+        if self.premium         { count += 1 }
+        if self.verified_badge  { count += 1 }
         count += self.player_badges.len();
         count += self.owns_asset.len();
+        count += self.owns_gamepass.len();
         count
     }
 }
@@ -445,6 +535,7 @@ struct VerificationContext {
     username: Option<String>, is_banned: Option<bool>, dev_trust_level: Option<Option<u32>>,
     badges: Option<HashSet<String>>, groups: Option<HashMap<u64, u32>>,
     player_badges: HashMap<u64, bool>, owns_asset: HashMap<u64, bool>,
+    owns_gamepass: HashMap<u64, bool>,
 }
 impl VerificationContext {
     fn new(user_id: RobloxUserID) -> VerificationContext {
@@ -452,6 +543,7 @@ impl VerificationContext {
             user_id,
             username: None, dev_trust_level: None, is_banned: None, badges: None, groups: None,
             player_badges: HashMap::new(), owns_asset: HashMap::new(),
+            owns_gamepass: HashMap::new(),
         }
     }
 
@@ -475,13 +567,15 @@ impl VerificationContext {
     }
     fn groups(&mut self) -> Result<&HashMap<u64, u32>> {
         let id = self.user_id;
-        option_cache(&mut self.groups, || api::get_player_groups(id))
+        option_cache(&mut self.groups, || {
+            Ok(id.get_group_ranks()?.iter().map(|x| (x.group_id, x.rank)).collect())
+        })
     }
     fn has_player_badge(&mut self, badge_id: u64) -> Result<bool> {
         match self.player_badges.get(&badge_id) {
             Some(&b) => Ok(b),
             None => {
-                let result = api::has_player_badge(self.user_id, badge_id)?;
+                let result = self.user_id.has_badge(badge_id)?;
                 self.player_badges.insert(badge_id, result);
                 Ok(result)
             }
@@ -491,12 +585,22 @@ impl VerificationContext {
         match self.owns_asset.get(&asset_id) {
             Some(&b) => Ok(b),
             None => {
-                let result = api::owns_asset(self.user_id, asset_id)?;
+                let result = self.user_id.owns_asset(asset_id)?;
                 self.owns_asset.insert(asset_id, result);
                 Ok(result)
             }
         }
     }
+    fn owns_gamepass(&mut self, gamepass_id: u64) -> Result<bool> {
+        match self.owns_gamepass.get(&gamepass_id) {
+            Some(&b) => Ok(b),
+            None => {
+                let result = self.user_id.owns_gamepass(gamepass_id)?;
+                self.owns_gamepass.insert(gamepass_id, result);
+                Ok(result)
+            }
+        }
+    }
 }
 
 struct RuleResolutionContext {
@@ -680,9 +784,12 @@ impl VerificationSet {
                 RuleOp::CheckBadge(_) => ctx.uses_badges(),
                 RuleOp::CheckPlayerBadge(id) => ctx.uses_has_player_badge(id),
                 RuleOp::CheckOwnsAsset(asset) => ctx.uses_owns_asset(asset),
+                RuleOp::CheckOwnsGamepass(gamepass) => ctx.uses_owns_gamepass(gamepass),
                 RuleOp::CheckInGroup(_, _) => ctx.uses_groups(),
                 RuleOp::CheckDevTrustLevel(_) => ctx.uses_dev_trust_level(),
                 RuleOp::CheckIsBanned => ctx.uses_is_banned(),
+                RuleOp::CheckHasPremium => ctx.uses_premium(),
+                RuleOp::CheckHasVerifiedBadge => ctx.uses_verified_badge(),
                 _ => { }
             }
         }
@@ -730,6 +837,7 @@ impl VerificationSet {
                 RuleOp::CheckBadge(ref name) => state.push(ctx.badges()?.contains(name)),
                 RuleOp::CheckPlayerBadge(id) => state.push(ctx.has_player_badge(id)?),
                 RuleOp::CheckOwnsAsset(asset) => state.push(ctx.owns_asset(asset)?),
+                RuleOp::CheckOwnsGamepass(gamepass) => state.push(ctx.owns_gamepass(gamepass)?),
                 RuleOp::CheckInGroup(group, None) =>
                     state.push(ctx.groups()?.contains_key(&group)),
                 RuleOp::CheckInGroup(group, Some(check)) =>
@@ -744,6 +852,10 @@ impl VerificationSet {
                     }),
                 RuleOp::CheckIsBanned =>
                     state.push(ctx.is_banned()?),
+                RuleOp::CheckHasPremium =>
+                    state.push(ctx.user_id.has_premium()?),
+                RuleOp::CheckHasVerifiedBadge =>
+                    state.push(ctx.user_id.has_verified_badge()?),
             }
             ip += 1;
         }
@@ -0,0 +1,135 @@
+use chrono::DateTime;
+use errors::*;
+use reqwest::{Client, Method, Response, StatusCode};
+use reqwest::header::{ContentType, Headers};
+use roblox::RobloxUserID;
+use roblox::api::CLIENT;
+use serde_json;
+use std::time::SystemTime;
+
+// Only this many pages of incoming friend requests are read each time they are checked.
+const MAX_FRIEND_REQUEST_PAGES: usize = 5;
+
+#[derive(Deserialize)]
+struct RobloxAuthenticatedUser {
+    id: u64,
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct RobloxFriendRequestInfo {
+    #[serde(rename = "sentAt")] sent_at: String,
+}
+
+#[derive(Deserialize)]
+struct RobloxFriendRequestLookup {
+    id: u64,
+    #[serde(rename = "friendRequest")] friend_request: RobloxFriendRequestInfo,
+}
+
+#[derive(Deserialize)]
+struct RobloxFriendRequestsLookup {
+    data: Vec<RobloxFriendRequestLookup>,
+    #[serde(rename = "nextPageCursor")] next_page_cursor: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct RobloxFriendRequest {
+    pub sender: RobloxUserID, pub sent_at: SystemTime,
+}
+
+// A Roblox account signed in with its .ROBLOSECURITY cookie, used for APIs that act on behalf
+// of a specific account.
+#[derive(Copy, Clone)]
+pub struct RobloxSession<'a> {
+    pub cookie: &'a str,
+}
+impl <'a> RobloxSession<'a> {
+    // Roblox rejects writes without a CSRF token, and returns the token to use in the response,
+    // so requests rejected this way are sent again with it.
+    pub(super) fn request(
+        &self, endpoint: &'static str, method: Method, uri: &str, body: Option<&str>,
+    ) -> Result<Response> {
+        let build = |client: &Client, csrf_token: Option<&str>| {
+            let mut headers = Headers::new();
+            headers.set_raw("Cookie", format!(".ROBLOSECURITY={}", self.cookie));
+            if let Some(token) = csrf_token {
+                headers.set_raw("X-CSRF-TOKEN", token.to_owned());
+            }
+            if body.is_some() {
+                headers.set(ContentType::json());
+            }
+            let mut request = client.request(method.clone(), uri);
+            request.headers(headers);
+            if let Some(body) = body {
+                request.body(body.to_owned());
+            }
+            request.send()
+        };
+        let send = |csrf_token: Option<&str>| if method == Method::Get {
+            CLIENT.send(endpoint, |client| build(client, csrf_token))
+        } else {
+            CLIENT.send_write(endpoint, |client| build(client, csrf_token))
+        };
+
+        let response = send(None)?;
+        if response.status() == StatusCode::Forbidden {
+            let csrf_token = response.headers().get_raw("X-CSRF-TOKEN").and_then(|x| x.one())
+                .map(|x| String::from_utf8_lossy(x).into_owned());
+            if let Some(csrf_token) = csrf_token {
+                return send(Some(&csrf_token))
+            }
+        }
+        Ok(response)
+    }
+    fn request_checked(
+        &self, endpoint: &'static str, method: Method, uri: &str, body: Option<&str>,
+    ) -> Result<Response> {
+        let response = self.request(endpoint, method, uri, body)?;
+        cmd_ensure!(response.status() != StatusCode::Unauthorized,
+                    "The Roblox account cookie is invalid or has expired. Please ask the bot \
+                     owner to fix this.");
+        Ok(response.error_for_status()?)
+    }
+
+    // Returns the ID and username of the signed in account.
+    pub fn authenticated_user(&self) -> Result<(RobloxUserID, String)> {
+        let json = self.request_checked(
+            "users", Method::Get, "https://users.roblox.com/v1/users/authenticated", None,
+        )?.text()?;
+        let user = serde_json::from_str::<RobloxAuthenticatedUser>(&json)?;
+        Ok((RobloxUserID(user.id), user.name))
+    }
+
+    // Returns the friend requests the signed in account has received, newest first.
+    pub fn friend_requests(&self) -> Result<Vec<RobloxFriendRequest>> {
+        let mut requests = Vec::new();
+        let mut cursor = None;
+        for _ in 0..MAX_FRIEND_REQUEST_PAGES {
+            let uri = match cursor {
+                Some(ref cursor) => format!("https://friends.roblox.com/v1/my/friends/requests\
+                                             ?limit=100&cursor={}", cursor),
+                None => "https://friends.roblox.com/v1/my/friends/requests?limit=100".to_owned(),
+            };
+            let json = self.request_checked("friends", Method::Get, &uri, None)?.text()?;
+            let page = serde_json::from_str::<RobloxFriendRequestsLookup>(&json)?;
+            for request in page.data {
+                let sent_at = DateTime::parse_from_rfc3339(&request.friend_request.sent_at)?;
+                requests.push(RobloxFriendRequest {
+                    sender: RobloxUserID(request.id), sent_at: sent_at.into(),
+                });
+            }
+            cursor = page.next_page_cursor;
+            if cursor.is_none() {
+                break
+            }
+        }
+        Ok(requests)
+    }
+
+    pub fn decline_friend_request(&self, user: RobloxUserID) -> Result<()> {
+        let uri = format!("https://friends.roblox.com/v1/users/{}/decline-friend-request", user.0);
+        self.request_checked("friends", Method::Post, &uri, None)?;
+        Ok(())
+    }
+}
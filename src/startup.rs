@@ -1,4 +1,5 @@
 use core::*;
+use database;
 use database::Database;
 use error_report;
 use errors::*;
@@ -8,15 +9,24 @@ use std::env;
 use std::fs::{File, OpenOptions};
 use std::path::{Path, PathBuf};
 use std::process::abort;
+use std::time::Duration;
 
 const LOCK_FILE_NAME: &str = "Sylph-Verifier.lock";
 const DB_FILE_NAME: &str = "Sylph-Verifier.db";
+const DEFAULT_LEASE_SECS: u64 = 30;
 
-fn check_lock<P: AsRef<Path>>(path: P) -> Result<File> {
+// Instances in high availability mode take a shared lock, so several of them can be run from the
+// same directory, while the lease decides which one runs the bot. Anything else, including
+// restoring a backup, needs the directory to itself.
+fn check_lock<P: AsRef<Path>>(path: P, shared: bool) -> Result<File> {
     let mut options = OpenOptions::new();
     options.create(true).read(true).write(true);
     let lock_file = options.open(path)?;
-    lock_file.try_lock_exclusive()?;
+    if shared {
+        lock_file.try_lock_shared()?;
+    } else {
+        lock_file.try_lock_exclusive()?;
+    }
     Ok(lock_file)
 }
 fn in_path<P: AsRef<Path>>(root_path: P, file: &str) -> PathBuf {
@@ -48,33 +58,102 @@ fn get_root_path() -> PathBuf {
     }
 }
 
+fn restore(db_path: &Path, backup_path: &Path) {
+    match database::restore(db_path, backup_path) {
+        Ok((schema_version, old_path)) => {
+            println!("Restored database (schema version {}) from '{}'.",
+                     schema_version, backup_path.display());
+            if let Some(old_path) = old_path {
+                println!("The previous database has been moved to '{}'.", old_path.display());
+            }
+            if let Err(e) = EventLog::reset_head(&event_log_key_path(db_path)) {
+                println!("Could not reset the event log head: {}", e);
+            }
+        }
+        Err(e) => println!("Could not restore database: {}", e),
+    }
+}
+
 pub fn start() {
     env::set_var("RUST_FAILURE_BACKTRACE", "1");
 
+    // Read settings given in the environment.
+    let env_config = match EnvConfig::load() {
+        Ok(env_config) => env_config,
+        Err(e) => {
+            println!("Could not read settings from environment variables: {}", e);
+            return
+        }
+    };
+
     // Find paths
     let root_path = get_root_path();
-    let db_path = in_path(&root_path, DB_FILE_NAME);
+    let db_path = match env_config.database_path {
+        Some(ref path) => root_path.join(path),
+        None => in_path(&root_path, DB_FILE_NAME),
+    };
 
     // Acquire the lock file.
-    let _lock = match check_lock(in_path(&root_path, LOCK_FILE_NAME)) {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let shared = env_config.high_availability && args.is_empty();
+    let _lock = match check_lock(in_path(&root_path, LOCK_FILE_NAME), shared) {
         Ok(lock) => lock,
+        Err(_) if env_config.high_availability => {
+            println!("Another instance of Sylph-Verifier is running from this directory without \
+                      high availability mode, or a backup is being restored.");
+            abort()
+        }
         Err(_) => {
-            println!("Only one instance of Sylph-Verifier may be launched at once.");
+            println!("Only one instance of Sylph-Verifier may be launched at once. Set \
+                      SYLPH_HIGH_AVAILABILITY to run several instances sharing a database.");
             abort()
         }
     };
 
+    // Handle command line operations that must run while the bot is stopped.
+    if args.len() > 0 {
+        if args[0] == "restore" && args.len() == 2 {
+            restore(&db_path, Path::new(&args[1]))
+        } else {
+            println!("Usage: sylph-verifier [restore <backup file>]")
+        }
+        return
+    }
+
     // Setup logging
     logger::init(&root_path).expect("failed to setup logging");
     error_report::init(&root_path);
+    if let Some(level) = env_config.log_level {
+        logger::set_level_override("sylph_verifier", Some(level));
+    }
 
     // Start bot proper
     error_report::catch_error(move || {
         debug!("Root directory: {}", root_path.display());
         debug!("Database path: {}", db_path.display());
 
-        let database = Database::new(db_path)?;
-        VerifierCore::new(root_path, database)?.start()?;
-        Ok(())
+        let database = Database::new(&db_path)?;
+        let lease = if env_config.high_availability {
+            let lease_time = env_config.leader_lease_seconds.unwrap_or(DEFAULT_LEASE_SECS);
+            Some(LeaderLease::new(database.clone(), Duration::from_secs(lease_time)))
+        } else {
+            None
+        };
+
+        // In high availability mode, the bot is started again after losing the leader lease,
+        // once this instance can take it back.
+        loop {
+            if let Some(ref lease) = lease {
+                lease.wait_for_leadership()?;
+            }
+            let core = VerifierCore::new(root_path.clone(), &db_path, database.clone(),
+                                         lease.clone())?;
+            core.config().apply_env(&env_config);
+            core.start()?;
+            match lease {
+                Some(ref lease) if lease.is_lost() => { }
+                _ => return Ok(()),
+            }
+        }
     }).ok();
 }
\ No newline at end of file
@@ -1,9 +1,14 @@
 use errors::*;
 use parking_lot::{Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
 use reqwest;
+use reqwest::Method;
+use reqwest::header::{ContentType, Headers};
+use serde::Serialize;
+use serde_json;
+use serenity;
 use serenity::model::prelude::*;
 use std::borrow::Borrow;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::hash::Hash;
 use std::mem::drop;
 use std::sync::Arc;
@@ -43,6 +48,22 @@ pub fn to_english_time_precise(secs: u64) -> String {
 pub fn english_time_diff(from: SystemTime, to: SystemTime) -> String {
     to_english_time(to.duration_since(from).map(|x| x.as_secs()).unwrap_or(0))
 }
+// Parses a duration such as `30m`, `12h`, `3d` or `2w`.
+pub fn parse_duration(s: &str) -> Result<Duration> {
+    let s = s.trim();
+    let unit_secs = match s.chars().last() {
+        Some('m') => 60,
+        Some('h') => 60 * 60,
+        Some('d') => 60 * 60 * 24,
+        Some('w') => 60 * 60 * 24 * 7,
+        _ => cmd_error!("Please give a duration such as `30m`, `12h`, `3d` or `2w`."),
+    };
+    let count = match s[..s.len() - 1].parse::<u64>() {
+        Ok(count) if count != 0 => count,
+        _ => cmd_error!("Please give a duration such as `30m`, `12h`, `3d` or `2w`."),
+    };
+    Ok(Duration::from_secs(count.saturating_mul(unit_secs)))
+}
 
 // Time to i64
 pub fn time_from_i64(time: i64) -> SystemTime {
@@ -69,6 +90,19 @@ pub fn time_to_i64(time: SystemTime) -> i64 {
     }
 }
 
+// The source of the current time for code where time matters to correctness, such as token
+// epochs and verification cooldowns. Production code uses `SystemClock`; a different clock can be
+// passed in where a fixed or controllable time is needed.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> SystemTime;
+}
+pub struct SystemClock;
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
 // MultiMutex implementation
 pub struct MultiMutexGuard<T: Hash + Eq>(Arc<Mutex<HashSet<T>>>, T);
 impl <T: Hash + Eq> Drop for MultiMutexGuard<T> {
@@ -99,6 +133,25 @@ impl <T: Hash + Eq + Clone> MultiMutex<T> {
     }
 }
 
+// Counts operations that are in progress, so shutdown can wait for them to finish.
+#[derive(Default)]
+pub struct InFlightCounter(AtomicUsize);
+pub struct InFlightGuard<'a>(&'a AtomicUsize);
+impl <'a> Drop for InFlightGuard<'a> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+impl InFlightCounter {
+    pub fn enter(&self) -> InFlightGuard {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        InFlightGuard(&self.0)
+    }
+    pub fn count(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
 // Concurrent cache implementation
 // TODO: Add *optional* support for tracking last access in this.
 pub struct ConcurrentCache<K: Clone + Eq + Hash + Sync, V: Sync> {
@@ -161,6 +214,113 @@ impl <K: Clone + Eq + Hash + Sync, V: Sync> ConcurrentCache<K, V> {
     }
 }
 
+// Least recently used cache implementation
+#[derive(Copy, Clone, Debug)]
+pub struct LruCacheMetrics {
+    pub hits: usize, pub misses: usize, pub entries: usize, pub capacity: usize,
+}
+
+struct LruCacheData<K, V> {
+    entries: HashMap<K, (V, u64)>, order: BTreeMap<u64, K>, tick: u64, generation: u64,
+}
+pub struct LruCache<K: Clone + Eq + Hash, V: Clone> {
+    data: Mutex<LruCacheData<K, V>>, capacity: usize, hits: AtomicUsize, misses: AtomicUsize,
+}
+impl <K: Clone + Eq + Hash, V: Clone> LruCache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        LruCache {
+            data: Mutex::new(LruCacheData {
+                entries: HashMap::new(), order: BTreeMap::new(), tick: 0, generation: 0,
+            }),
+            capacity, hits: AtomicUsize::new(0), misses: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn get<F>(&self, k: &K, f: F) -> Result<V> where F: FnOnce() -> Result<V> {
+        let generation = {
+            let mut data = self.data.lock();
+            let data = &mut *data;
+            data.tick += 1;
+            if let Some(&mut (ref value, ref mut last_used)) = data.entries.get_mut(k) {
+                data.order.remove(last_used);
+                *last_used = data.tick;
+                data.order.insert(data.tick, k.clone());
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return Ok(value.clone())
+            }
+            data.generation
+        };
+        self.misses.fetch_add(1, Ordering::Relaxed);
+
+        let value = f()?;
+        let mut data = self.data.lock();
+        let data = &mut *data;
+        // Values loaded while something was invalidated may already be out of date.
+        if data.generation == generation && !data.entries.contains_key(k) {
+            data.tick += 1;
+            data.entries.insert(k.clone(), (value.clone(), data.tick));
+            data.order.insert(data.tick, k.clone());
+            while data.entries.len() > self.capacity {
+                let oldest = *data.order.keys().next().unwrap();
+                let oldest = data.order.remove(&oldest).unwrap();
+                data.entries.remove(&oldest);
+            }
+        }
+        Ok(value)
+    }
+    pub fn invalidate(&self, k: &K) {
+        let mut data = self.data.lock();
+        data.generation += 1;
+        if let Some((_, last_used)) = data.entries.remove(k) {
+            data.order.remove(&last_used);
+        }
+    }
+    pub fn clear_cache(&self) {
+        let mut data = self.data.lock();
+        data.generation += 1;
+        data.entries = HashMap::new();
+        data.order = BTreeMap::new();
+    }
+
+    pub fn metrics(&self) -> LruCacheMetrics {
+        LruCacheMetrics {
+            hits: self.hits.load(Ordering::Relaxed), misses: self.misses.load(Ordering::Relaxed),
+            entries: self.data.lock().entries.len(), capacity: self.capacity,
+        }
+    }
+}
+
+// Username similarity
+pub fn levenshtein_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..b.len() + 1).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitute = diagonal + if ca == cb { 0 } else { 1 };
+            diagonal = row[j + 1];
+            row[j + 1] = substitute.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+// Returns the first protected name that `name` is similar to but does not exactly match, ignoring
+// case. Exact matches are skipped, as those can only be the protected account itself.
+pub fn find_similar_name<'a, I>(
+    name: &str, protected: I, max_distance: usize,
+) -> Option<&'a str> where I: IntoIterator<Item = &'a str> {
+    let name = name.to_lowercase();
+    for protected_name in protected {
+        let distance = levenshtein_distance(&name, &protected_name.to_lowercase());
+        if distance != 0 && distance <= max_distance {
+            return Some(protected_name)
+        }
+    }
+    None
+}
+
 // Command IDs
 static COMMAND_ID: AtomicUsize = AtomicUsize::new(0);
 pub fn command_id() -> usize {
@@ -177,6 +337,63 @@ pub fn sprunge(text: &str) -> Result<String> {
     Ok(result.text()?.trim().to_string())
 }
 
+// Raw Discord API requests, for features Serenity does not support yet. Every raw request uses
+// this one API version, so the payloads only need to match what it expects.
+pub const DISCORD_API_BASE: &str = "https://discord.com/api/v10";
+pub fn discord_request<T: Serialize>(
+    token: &str, method: Method, uri: &str, body: &T,
+) -> Result<reqwest::Response> {
+    let mut headers = Headers::new();
+    headers.set(ContentType::json());
+    headers.set_raw("Authorization", format!("Bot {}", token));
+    Ok(reqwest::Client::new().request(method, uri).headers(headers)
+        .body(serde_json::to_string(body)?).send()?.error_for_status()?)
+}
+pub fn discord_get(token: &str, uri: &str) -> Result<reqwest::Response> {
+    let mut headers = Headers::new();
+    headers.set_raw("Authorization", format!("Bot {}", token));
+    Ok(reqwest::Client::new().get(uri).headers(headers).send()?.error_for_status()?)
+}
+
+// Message components and embeds for raw Discord API requests.
+pub const COMPONENT_ACTION_ROW: u8 = 1;
+pub const COMPONENT_BUTTON: u8 = 2;
+pub const BUTTON_STYLE_SECONDARY: u8 = 2;
+pub const BUTTON_STYLE_SUCCESS: u8 = 3;
+pub const BUTTON_STYLE_DANGER: u8 = 4;
+
+#[derive(Serialize)]
+pub struct EmbedThumbnailPayload {
+    pub url: String,
+}
+
+#[derive(Serialize)]
+pub struct EmbedPayload {
+    pub title: &'static str, pub description: String, pub color: u32,
+    #[serde(skip_serializing_if = "Option::is_none")] pub thumbnail: Option<EmbedThumbnailPayload>,
+}
+
+#[derive(Serialize)]
+pub struct ButtonPayload {
+    #[serde(rename = "type")] pub kind: u8, pub style: u8, pub label: &'static str,
+    pub custom_id: String,
+}
+
+#[derive(Serialize)]
+pub struct ActionRowPayload {
+    #[serde(rename = "type")] pub kind: u8, pub components: Vec<ButtonPayload>,
+}
+
+#[derive(Serialize)]
+pub struct MessagePayload {
+    pub embeds: Vec<EmbedPayload>, pub components: Vec<ActionRowPayload>,
+}
+
+#[derive(Deserialize)]
+pub struct CreatedMessage {
+    pub id: MessageId,
+}
+
 // TODO: Wait for Serenity's heirarchy functions to account for owners/etc.
 #[derive(Ord, PartialOrd, Eq, PartialEq)]
 enum RolePosition {
@@ -210,4 +427,20 @@ pub fn can_member_access_role(member: &Member, role: RoleId) -> Result<bool> {
 }
 pub fn can_member_access_member(from: &Member, to: &Member) -> Result<bool> {
     Ok(can_access(member_position(from)?, member_position(to)?))
-}
\ No newline at end of file
+}
+
+pub fn get_discord_username(discord_id: UserId) -> String {
+    match discord_id.find() {
+        Some(x) => x.read().tag(),
+        None => match discord_id.get() {
+            Ok(x) => x.tag(),
+            Err(_) => format!("(discord uid #{})", discord_id.0),
+        }
+    }
+}
+pub fn member_guilds(discord_id: UserId) -> Vec<GuildId> {
+    serenity::CACHE.read().guilds.iter()
+        .filter(|&(_, guild)| guild.read().members.contains_key(&discord_id))
+        .map(|(&id, _)| id)
+        .collect()
+}
@@ -0,0 +1,557 @@
+use constant_time_eq::constant_time_eq;
+use core::{AdminAction, ConfigManager, ConfigKeys, CoreRef, Verifier};
+use errors::*;
+use hmac::{Hmac, Mac};
+use hyper::header::{Authorization, Bearer, CacheControl, CacheDirective, ContentType};
+use hyper::method::Method;
+use hyper::mime::{Mime, TopLevel, SubLevel};
+use hyper::server::{Server, Handler, Listening, Request, Response};
+use hyper::uri::RequestUri;
+use parking_lot::Mutex;
+use roblox;
+use roblox::RobloxUserID;
+use serde_json;
+use serenity::model::prelude::*;
+use sha2::Sha256;
+use std::collections::{HashMap, BTreeMap};
+use std::fmt::Write;
+use std::io::Read;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use util;
+
+mod oauth;
+
+pub use self::oauth::CODE_PAGE_PATH;
+
+use self::oauth::OAuthHandler;
+
+// Requests to the badge and private APIs are counted per IP address over this window.
+const RATE_LIMIT_WINDOW_SECS: u64 = 60;
+const BADGE_MAX_AGE_SECS: u32 = 60 * 5;
+const BADGE_COLOUR_LABEL: &str = "#555";
+const BADGE_COLOUR_VERIFIED: &str = "#43b581";
+const BADGE_COLOUR_UNVERIFIED: &str = "#747f8d";
+
+// Uptime monitors can check this, and it is served without an API key.
+const HEALTH_PATH: &str = "/healthz";
+// Reaching the Roblox API takes a request of its own, so results are reused for this long.
+const HEALTH_ROBLOX_CHECK_SECS: u64 = 60;
+
+// The verification place sends codes entered in it here.
+pub const PLACE_PUSH_PATH: &str = "/api/v1/place/verify";
+const MAX_PLACE_PUSH_BYTES: u64 = 1024;
+
+// Badge URLs are signed so websites can only show the accounts a server has made badges for,
+// rather than looking up any Roblox account.
+pub fn badge_signature(secret: &str, guild_id: GuildId, roblox_id: RobloxUserID) -> String {
+    let mut mac = Hmac::<Sha256>::new(secret.as_bytes()).unwrap();
+    mac.input(format!("badge:{}:{}", guild_id.0, roblox_id.0).as_bytes());
+    let mut signature = String::new();
+    for byte in mac.result().code() {
+        write!(signature, "{:02x}", byte).unwrap();
+    }
+    signature
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+        .replace('"', "&quot;").replace('\'', "&apos;")
+}
+
+// Renders a two part badge in the style of shields.io. Text widths are estimated, as the font
+// used to display the badge is not known.
+fn render_badge(label: &str, status: &str, colour: &str) -> String {
+    let text_width = |text: &str| text.chars().count() * 7 + 10;
+    let (label_width, status_width) = (text_width(label), text_width(status));
+    let width = label_width + status_width;
+    let (label, status) = (escape_xml(label), escape_xml(status));
+    format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"20\" \
+              role=\"img\" aria-label=\"{label}: {status}\">\
+           <title>{label}: {status}</title>\
+           <rect width=\"{label_width}\" height=\"20\" fill=\"{label_colour}\"/>\
+           <rect x=\"{label_width}\" width=\"{status_width}\" height=\"20\" \
+                 fill=\"{colour}\"/>\
+           <g fill=\"#fff\" text-anchor=\"middle\" \
+              font-family=\"Verdana,Geneva,DejaVu Sans,sans-serif\" font-size=\"11\">\
+             <text x=\"{label_center}\" y=\"14\">{label}</text>\
+             <text x=\"{status_center}\" y=\"14\">{status}</text>\
+           </g>\
+         </svg>",
+        width = width, label = label, status = status, label_width = label_width,
+        status_width = status_width, label_colour = BADGE_COLOUR_LABEL, colour = colour,
+        label_center = label_width / 2, status_center = label_width + status_width / 2,
+    )
+}
+
+// Splits a request URI into its path and query string.
+fn split_uri(uri: &RequestUri) -> Option<(&str, &str)> {
+    match *uri {
+        RequestUri::AbsolutePath(ref path) => {
+            let mut split = path.splitn(2, '?');
+            Some((split.next().unwrap_or(""), split.next().unwrap_or("")))
+        }
+        _ => None,
+    }
+}
+
+fn query_param<'a>(query: &'a str, name: &str) -> Option<&'a str> {
+    query.split('&').filter_map(|x| {
+        let mut split = x.splitn(2, '=');
+        match (split.next(), split.next()) {
+            (Some(key), Some(value)) if key == name => Some(value),
+            _ => None,
+        }
+    }).next()
+}
+
+#[derive(Serialize)]
+struct ErrorResponse {
+    error: &'static str,
+}
+
+#[derive(Serialize)]
+struct LinkResponse {
+    verified: bool,
+    discord_id: Option<String>,
+    roblox_id: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct PlacePushRequest {
+    roblox_id: u64,
+    code: String,
+}
+
+#[derive(Serialize)]
+struct PlacePushResponse {
+    accepted: bool,
+}
+
+#[derive(Serialize)]
+struct DeletionReceiptResponse {
+    receipt_id: String,
+    discord_id: String,
+    purged_at: i64,
+    removed: BTreeMap<&'static str, usize>,
+}
+
+#[derive(Serialize)]
+struct PendingActionResponse {
+    action_id: u64,
+    expires_at: i64,
+}
+
+#[derive(Serialize)]
+struct HealthResponse {
+    status: &'static str,
+    discord_connected: bool,
+    discord_shards: usize,
+    discord_shards_connected: usize,
+    database_ok: bool,
+    roblox_api_ok: bool,
+    last_verification: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct BadgeResponse {
+    verified: bool,
+    roblox_id: u64,
+    guild_name: String,
+}
+
+// Counts the requests made by each IP address. Addresses that have not made requests recently
+// are removed on the cleanup tick rather than on every request.
+#[derive(Clone)]
+struct RateLimiter(Arc<Mutex<HashMap<IpAddr, (Instant, u32)>>>);
+impl RateLimiter {
+    fn new() -> RateLimiter {
+        RateLimiter(Arc::new(Mutex::new(HashMap::new())))
+    }
+    fn is_rate_limited(&self, limit: u32, req: &Request) -> bool {
+        if limit == 0 {
+            return false
+        }
+        let now = Instant::now();
+        let window = Duration::from_secs(RATE_LIMIT_WINDOW_SECS);
+        let mut requests = self.0.lock();
+        let entry = requests.entry(req.remote_addr.ip()).or_insert((now, 0));
+        if now >= entry.0 + window {
+            *entry = (now, 0);
+        }
+        entry.1 += 1;
+        entry.1 > limit
+    }
+    fn prune(&self) {
+        let now = Instant::now();
+        let window = Duration::from_secs(RATE_LIMIT_WINDOW_SECS);
+        let mut requests = self.0.lock();
+        requests.retain(|_, &mut (start, _)| now < start + window);
+        requests.shrink_to_fit();
+    }
+}
+
+struct ApiHandler {
+    config: ConfigManager, core_ref: CoreRef, verifier: Verifier,
+    badge_requests: RateLimiter, api_requests: RateLimiter, oauth: OAuthHandler,
+    roblox_health: Mutex<Option<(Instant, bool)>>,
+}
+impl ApiHandler {
+    fn error(status: StatusCode, error: &'static str) -> Result<(StatusCode, String)> {
+        Ok((status, serde_json::to_string(&ErrorResponse { error })?))
+    }
+    fn link(discord_id: Option<UserId>, roblox_id: Option<RobloxUserID>) -> Result<String> {
+        Ok(serde_json::to_string(&LinkResponse {
+            verified: discord_id.is_some() && roblox_id.is_some(),
+            discord_id: discord_id.map(|x| x.0.to_string()),
+            roblox_id: roblox_id.map(|x| x.0),
+        })?)
+    }
+
+    fn is_authorized(&self, req: &Request) -> Result<bool> {
+        let api_key = match self.config.get(None, ConfigKeys::WebApiKey)? {
+            Some(api_key) => api_key,
+            None => return Ok(false),
+        };
+        Ok(match req.headers.get::<Authorization<Bearer>>() {
+            Some(auth) => constant_time_eq(auth.0.token.as_bytes(), api_key.as_bytes()),
+            None => false,
+        })
+    }
+
+    // The public badge API, e.g. `/api/v1/badge/<roblox id>.svg?guild=<id>&sig=<signature>`.
+    fn route_badge(&self, req: &Request, file: &str, query: &str) -> Result<(StatusCode, String)> {
+        if req.method != Method::Get {
+            return Self::error(StatusCode::MethodNotAllowed, "method not allowed")
+        }
+        let secret = match self.config.get(None, ConfigKeys::WebBadgeSecret)? {
+            Some(secret) => secret,
+            None => return Self::error(StatusCode::NotFound, "not found"),
+        };
+        let limit = self.config.get(None, ConfigKeys::WebBadgeRateLimit)?;
+        if self.badge_requests.is_rate_limited(limit, req) {
+            return Self::error(StatusCode::TooManyRequests, "too many requests")
+        }
+
+        let (roblox_id, is_svg) = match file.rfind('.') {
+            Some(i) if &file[i..] == ".svg" || &file[i..] == ".json" =>
+                (&file[..i], &file[i..] == ".svg"),
+            _ => return Self::error(StatusCode::NotFound, "not found"),
+        };
+        let roblox_id = match roblox_id.parse() {
+            Ok(id) => RobloxUserID(id),
+            Err(_) => return Self::error(StatusCode::BadRequest, "invalid roblox id"),
+        };
+        let guild_id = match query_param(query, "guild").and_then(|x| x.parse().ok()) {
+            Some(id) => GuildId(id),
+            None => return Self::error(StatusCode::BadRequest, "invalid guild id"),
+        };
+        let signature = query_param(query, "sig").unwrap_or("");
+        let expected = badge_signature(&secret, guild_id, roblox_id);
+        if !constant_time_eq(signature.as_bytes(), expected.as_bytes()) {
+            return Self::error(StatusCode::Forbidden, "invalid signature")
+        }
+
+        let guild = match guild_id.find() {
+            Some(guild) => guild,
+            None => return Self::error(StatusCode::NotFound, "unknown guild"),
+        };
+        let verified = match self.verifier.get_verified_discord_user(roblox_id)? {
+            Some(discord_id) => guild.read().members.contains_key(&discord_id),
+            None => false,
+        };
+        let guild_name = guild.read().name.clone();
+        Ok((StatusCode::Ok, if is_svg {
+            let label = format!("Verified with {}", guild_name);
+            if verified {
+                render_badge(&label, "verified", BADGE_COLOUR_VERIFIED)
+            } else {
+                render_badge(&label, "not verified", BADGE_COLOUR_UNVERIFIED)
+            }
+        } else {
+            serde_json::to_string(&BadgeResponse { verified, roblox_id: roblox_id.0, guild_name })?
+        }))
+    }
+
+    // Codes entered in the verification place, sent by its servers as a JSON object of the form
+    // `{"roblox_id": <id>, "code": "<code>"}`, authorized with the `place_push_secret` setting.
+    fn route_place_push(&self, req: &mut Request) -> Result<(StatusCode, String)> {
+        if req.method != Method::Post {
+            return Self::error(StatusCode::MethodNotAllowed, "method not allowed")
+        }
+        let secret = match self.config.get(None, ConfigKeys::PlacePushSecret)? {
+            Some(secret) => secret,
+            None => return Self::error(StatusCode::NotFound, "not found"),
+        };
+        let authorized = match req.headers.get::<Authorization<Bearer>>() {
+            Some(auth) => constant_time_eq(auth.0.token.as_bytes(), secret.as_bytes()),
+            None => false,
+        };
+        if !authorized {
+            return Self::error(StatusCode::Unauthorized, "unauthorized")
+        }
+
+        let mut body = String::new();
+        if req.by_ref().take(MAX_PLACE_PUSH_BYTES).read_to_string(&mut body).is_err() {
+            return Self::error(StatusCode::BadRequest, "bad request")
+        }
+        let push = match serde_json::from_str::<PlacePushRequest>(&body) {
+            Ok(push) => push,
+            Err(_) => return Self::error(StatusCode::BadRequest, "invalid request"),
+        };
+        let core = match self.core_ref.get_core() {
+            Some(ref core) if core.is_running() => core.clone(),
+            _ => return Self::error(StatusCode::ServiceUnavailable, "bot is restarting"),
+        };
+        if core.enter_place_code(&push.code, RobloxUserID(push.roblox_id)) {
+            Ok((StatusCode::Accepted, serde_json::to_string(&PlacePushResponse {
+                accepted: true,
+            })?))
+        } else {
+            Self::error(StatusCode::NotFound, "invalid or expired code")
+        }
+    }
+
+    // Removes everything stored about a Discord user, for data deletion requests, and returns a
+    // receipt of what was removed. If `action_approvers` is set, the purge is instead held until
+    // another admin confirms it, as if the bot owner had requested it, and the ID of the pending
+    // action is returned with status 202.
+    fn route_purge(&self, discord_id: &str) -> Result<(StatusCode, String)> {
+        let discord_id = match discord_id.parse() {
+            Ok(id) => UserId(id),
+            Err(_) => return Self::error(StatusCode::BadRequest, "invalid discord id"),
+        };
+        let core = match self.core_ref.get_core() {
+            Some(ref core) if core.is_running() => core.clone(),
+            _ => return Self::error(StatusCode::ServiceUnavailable, "bot is restarting"),
+        };
+        if core.admin_actions().requires_confirmation()? {
+            let requested_by = match self.config.get(None, ConfigKeys::BotOwnerId)? {
+                Some(owner_id) => UserId(owner_id),
+                None => return Self::error(StatusCode::InternalServerError,
+                                           "bot_owner_id must be set to request admin actions"),
+            };
+            let pending = core.admin_actions().request(AdminAction::PurgeUser { discord_id },
+                                                       requested_by)?;
+            return Ok((StatusCode::Accepted, serde_json::to_string(&PendingActionResponse {
+                action_id: pending.id,
+                expires_at: util::time_to_i64(pending.expires_at),
+            })?))
+        }
+        let receipt = match core.purge_user(discord_id)? {
+            Some(receipt) => receipt,
+            None => return Self::error(StatusCode::Conflict, "user is currently verifying"),
+        };
+        Ok((StatusCode::Ok, serde_json::to_string(&DeletionReceiptResponse {
+            receipt_id: receipt.receipt_id,
+            discord_id: discord_id.0.to_string(),
+            purged_at: util::time_to_i64(receipt.purged_at),
+            removed: receipt.removed.into_iter().collect(),
+        })?))
+    }
+
+    fn is_roblox_reachable(&self) -> bool {
+        let mut roblox_health = self.roblox_health.lock();
+        if let Some((checked, reachable)) = *roblox_health {
+            if checked.elapsed() < Duration::from_secs(HEALTH_ROBLOX_CHECK_SECS) {
+                return reachable
+            }
+        }
+        let reachable = match roblox::check_api_reachable() {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("Health check could not reach the Roblox API: {}", e);
+                false
+            }
+        };
+        *roblox_health = Some((Instant::now(), reachable));
+        reachable
+    }
+
+    // Reports whether the bot is working, with status 503 if it is not connected to Discord or
+    // cannot use its database. Problems reaching Roblox are reported as `degraded`, as the bot
+    // still works for users who are already verified.
+    fn route_health(&self, req: &Request) -> Result<(StatusCode, String)> {
+        if req.method != Method::Get {
+            return Self::error(StatusCode::MethodNotAllowed, "method not allowed")
+        }
+        let core = match self.core_ref.get_core() {
+            Some(ref core) if core.is_running() => core.clone(),
+            _ => return Self::error(StatusCode::ServiceUnavailable, "bot is restarting"),
+        };
+        let shards = core.discord().shard_statuses();
+        let discord_shards_connected = shards.iter().filter(|x| x.stage == "Connected").count();
+        let discord_connected = !shards.is_empty() && discord_shards_connected == shards.len();
+        let database_ok = match core.check_database() {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("Health check could not use the database: {}", e);
+                false
+            }
+        };
+        let last_verification = if database_ok {
+            core.verifier().last_verification_time()?.map(util::time_to_i64)
+        } else {
+            None
+        };
+        let roblox_api_ok = self.is_roblox_reachable();
+
+        let (status, status_name) = if !discord_connected || !database_ok {
+            (StatusCode::ServiceUnavailable, "down")
+        } else if !roblox_api_ok {
+            (StatusCode::Ok, "degraded")
+        } else {
+            (StatusCode::Ok, "ok")
+        };
+        Ok((status, serde_json::to_string(&HealthResponse {
+            status: status_name, discord_connected, discord_shards: shards.len(),
+            discord_shards_connected, database_ok, roblox_api_ok, last_verification,
+        })?))
+    }
+
+    fn route(&self, req: &Request) -> Result<(StatusCode, String)> {
+        let (path, query) = match split_uri(&req.uri) {
+            Some(split) => split,
+            None => return Self::error(StatusCode::BadRequest, "bad request"),
+        };
+        if path == HEALTH_PATH {
+            return self.route_health(req)
+        }
+        let components: Vec<&str> = path.trim_matches('/').split('/').collect();
+        if components.len() == 4 && components[..3] == ["api", "v1", "badge"] {
+            return self.route_badge(req, components[3], query)
+        }
+
+        if req.method != Method::Get && req.method != Method::Delete {
+            return Self::error(StatusCode::MethodNotAllowed, "method not allowed")
+        }
+        // Checked before the API key, so that keys cannot be guessed quickly.
+        let limit = self.config.get(None, ConfigKeys::WebApiRateLimit)?;
+        if self.api_requests.is_rate_limited(limit, req) {
+            return Self::error(StatusCode::TooManyRequests, "too many requests")
+        }
+        if !self.is_authorized(req)? {
+            return Self::error(StatusCode::Unauthorized, "unauthorized")
+        }
+        if components.len() != 3 || components[0] != "api" {
+            return Self::error(StatusCode::NotFound, "not found")
+        }
+        if req.method == Method::Delete {
+            return match components[1] {
+                "discord" => self.route_purge(components[2]),
+                _ => Self::error(StatusCode::MethodNotAllowed, "method not allowed"),
+            }
+        }
+        match components[1] {
+            "verified" => {
+                let roblox_id = match components[2].parse() {
+                    Ok(id) => RobloxUserID(id),
+                    Err(_) => return Self::error(StatusCode::BadRequest, "invalid roblox id"),
+                };
+                let discord_id = self.verifier.get_verified_discord_user(roblox_id)?;
+                Ok((StatusCode::Ok, Self::link(discord_id, Some(roblox_id))?))
+            }
+            "discord" => {
+                let discord_id = match components[2].parse() {
+                    Ok(id) => UserId(id),
+                    Err(_) => return Self::error(StatusCode::BadRequest, "invalid discord id"),
+                };
+                let roblox_id = self.verifier.get_verified_roblox_user(discord_id)?;
+                Ok((StatusCode::Ok, Self::link(Some(discord_id), roblox_id)?))
+            }
+            _ => Self::error(StatusCode::NotFound, "not found"),
+        }
+    }
+}
+impl Handler for ApiHandler {
+    fn handle(&self, mut req: Request, mut res: Response) {
+        if split_uri(&req.uri).map_or(false, |x| x.0.starts_with(oauth::PATH_PREFIX)) {
+            return self.oauth.handle(&req, res)
+        }
+        let result = if split_uri(&req.uri).map_or(false, |x| x.0 == PLACE_PUSH_PATH) {
+            self.route_place_push(&mut req)
+        } else {
+            self.route(&req)
+        };
+        let (status, body) = match result {
+            Ok(result) => result,
+            Err(e) => {
+                error!("Error while handling web API request to {}: {}", req.uri, e);
+                (StatusCode::InternalServerError,
+                 "{\"error\":\"internal server error\"}".to_owned())
+            }
+        };
+        *res.status_mut() = status;
+        let path = split_uri(&req.uri).map_or("", |x| x.0);
+        if status == StatusCode::Ok && path.starts_with("/api/v1/badge/") {
+            res.headers_mut().set(if path.ends_with(".svg") {
+                ContentType(Mime(TopLevel::Image, SubLevel::Ext("svg+xml".to_owned()), vec![]))
+            } else {
+                ContentType::json()
+            });
+            res.headers_mut().set(CacheControl(vec![
+                CacheDirective::Public, CacheDirective::MaxAge(BADGE_MAX_AGE_SECS),
+            ]));
+            res.headers_mut().set_raw("Access-Control-Allow-Origin", vec![b"*".to_vec()]);
+        } else {
+            res.headers_mut().set(ContentType::json());
+        }
+        if let Err(e) = res.send(body.as_bytes()) {
+            debug!("Could not send web API response: {}", e);
+        }
+    }
+}
+
+struct WebServerData {
+    config: ConfigManager, core_ref: CoreRef, verifier: Verifier,
+    badge_requests: RateLimiter, api_requests: RateLimiter,
+    listening: Mutex<Option<Listening>>,
+}
+
+#[derive(Clone)]
+pub struct WebServer(Arc<WebServerData>);
+impl WebServer {
+    pub fn new(config: ConfigManager, core_ref: CoreRef, verifier: Verifier) -> WebServer {
+        WebServer(Arc::new(WebServerData {
+            config, core_ref, verifier,
+            badge_requests: RateLimiter::new(), api_requests: RateLimiter::new(),
+            listening: Mutex::new(None),
+        }))
+    }
+
+    pub fn start(&self) -> Result<()> {
+        let mut listening = self.0.listening.lock();
+        ensure!(listening.is_none(), "Web server already started.");
+        if let Some(address) = self.0.config.get(None, ConfigKeys::WebServerAddress)? {
+            let handler = ApiHandler {
+                config: self.0.config.clone(), core_ref: self.0.core_ref.clone(),
+                verifier: self.0.verifier.clone(),
+                badge_requests: self.0.badge_requests.clone(),
+                api_requests: self.0.api_requests.clone(),
+                oauth: OAuthHandler::new(self.0.config.clone(), self.0.core_ref.clone()),
+                roblox_health: Mutex::new(None),
+            };
+            *listening = Some(Server::http(address.as_str())?.handle(handler)?);
+            info!("Web API listening on {}.", address);
+        }
+        Ok(())
+    }
+    pub fn stop(&self) -> Result<()> {
+        if let Some(mut listening) = self.0.listening.lock().take() {
+            listening.close()?;
+            info!("Web API stopped.");
+        }
+        Ok(())
+    }
+    pub fn restart(&self) -> Result<()> {
+        self.stop()?;
+        self.start()
+    }
+
+    pub fn on_cleanup_tick(&self) {
+        self.0.badge_requests.prune();
+        self.0.api_requests.prune();
+    }
+}
@@ -0,0 +1,297 @@
+use commands::verify_in_guild;
+use constant_time_eq::constant_time_eq;
+use core::{ConfigManager, ConfigKeys, CoreRef, OAuthVerification};
+use errors::*;
+use hyper::header::{ContentType, Cookie, Location, SetCookie};
+use hyper::server::{Request, Response};
+use parking_lot::Mutex;
+use percent_encoding::percent_decode;
+use rand::{Rng, OsRng};
+use reqwest;
+use reqwest::Url;
+use reqwest::header::{Authorization, Bearer};
+use roblox::RobloxOAuthApp;
+use serde_json;
+use serenity::model::prelude::*;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+use super::{escape_xml, query_param, split_uri};
+use util::DISCORD_API_BASE;
+
+pub const PATH_PREFIX: &str = "/verify/";
+// Shows users the code from a Roblox sign in started by the `verify_oauth` command.
+pub const CODE_PAGE_PATH: &str = "/verify/callback/code";
+
+const DISCORD_CALLBACK_PATH: &str = "/verify/callback/discord";
+const ROBLOX_CALLBACK_PATH: &str = "/verify/callback/roblox";
+const STATE_COOKIE: &str = "sylphie_oauth_state";
+const SESSION_EXPIRY_SECS: u64 = 60 * 10;
+const MAX_SESSIONS: usize = 10000;
+
+#[derive(Deserialize)]
+struct DiscordTokenResponse {
+    access_token: String,
+}
+
+#[derive(Deserialize)]
+struct DiscordCurrentUser {
+    id: UserId,
+}
+
+struct OAuthSession {
+    guild_id: GuildId, discord_id: Option<UserId>, started: Instant,
+}
+
+enum OAuthResponse {
+    Redirect { location: String, cookie: Option<String> },
+    Page { status: StatusCode, message: String },
+}
+
+fn page<S: Into<String>>(status: StatusCode, message: S) -> Result<OAuthResponse> {
+    Ok(OAuthResponse::Page { status, message: message.into() })
+}
+
+fn new_state() -> Result<String> {
+    let mut rng = OsRng::new()?;
+    let mut state = String::new();
+    for _ in 0..4 {
+        write!(state, "{:08x}", rng.next_u32())?;
+    }
+    Ok(state)
+}
+
+fn decoded_param(query: &str, name: &str) -> Result<Option<String>> {
+    match query_param(query, name) {
+        Some(value) => Ok(Some(percent_decode(value.as_bytes()).decode_utf8()?.into_owned())),
+        None => Ok(None),
+    }
+}
+
+// Lets users verify by signing in to their Discord and Roblox accounts on the web server, rather
+// than with a code from the verification place:
+//
+// * `/verify/<server id>` starts a sign in for a server, and sends the user to Discord.
+// * Discord sends the user back to `/verify/callback/discord`, which sends them on to Roblox.
+// * Roblox sends the user back to `/verify/callback/roblox`, where the verification is made.
+//
+// The state passed through each sign in is also stored in a cookie, so a link to the middle of
+// someone else's sign in cannot be used to link their account to yours.
+pub(super) struct OAuthHandler {
+    config: ConfigManager, core_ref: CoreRef, sessions: Mutex<HashMap<String, OAuthSession>>,
+}
+impl OAuthHandler {
+    pub fn new(config: ConfigManager, core_ref: CoreRef) -> OAuthHandler {
+        OAuthHandler { config, core_ref, sessions: Mutex::new(HashMap::new()) }
+    }
+
+    fn public_url(&self) -> Result<String> {
+        self.config.get(None, ConfigKeys::WebPublicUrl)?
+            .to_cmd_err(|| "Signing in on the website is not set up on this bot.")
+    }
+    fn discord_app(&self) -> Result<(String, String)> {
+        let client_id = self.config.get(None, ConfigKeys::DiscordOAuthClientId)?;
+        let client_secret = self.config.get(None, ConfigKeys::DiscordOAuthClientSecret)?;
+        match (client_id, client_secret) {
+            (Some(client_id), Some(client_secret)) => Ok((client_id, client_secret)),
+            _ => cmd_error!("Signing in with Discord is not set up on this bot."),
+        }
+    }
+    fn roblox_app(&self) -> Result<(String, String)> {
+        let client_id = self.config.get(None, ConfigKeys::RobloxOAuthClientId)?;
+        let client_secret = self.config.get(None, ConfigKeys::RobloxOAuthClientSecret)?;
+        match (client_id, client_secret) {
+            (Some(client_id), Some(client_secret)) => Ok((client_id, client_secret)),
+            _ => cmd_error!("Signing in with Roblox is not set up on this bot."),
+        }
+    }
+
+    fn state_cookie(&self, state: &str) -> Result<String> {
+        let secure = self.public_url()?.starts_with("https://");
+        Ok(format!("{}={}; Path={}; Max-Age={}; HttpOnly; SameSite=Lax{}",
+                   STATE_COOKIE, state, PATH_PREFIX.trim_right_matches('/'),
+                   SESSION_EXPIRY_SECS, if secure { "; Secure" } else { "" }))
+    }
+
+    // Checks that a callback belongs to a sign in started in the same browser, and returns the
+    // code the user signed in with.
+    fn check_callback(&self, req: &Request, query: &str) -> Result<(String, String)> {
+        if let Some(error) = decoded_param(query, "error")? {
+            cmd_ensure!(error != "access_denied", "The sign in was cancelled.");
+            cmd_error!("The sign in failed: {}", error);
+        }
+        let state = decoded_param(query, "state")?.unwrap_or_default();
+        let cookie_state = req.headers.get::<Cookie>().and_then(|cookies| {
+            cookies.iter().filter_map(|x| {
+                let mut split = x.trim().splitn(2, '=');
+                match (split.next(), split.next()) {
+                    (Some(STATE_COOKIE), Some(value)) => Some(value.to_owned()),
+                    _ => None,
+                }
+            }).next()
+        }).unwrap_or_default();
+        cmd_ensure!(!state.is_empty() &&
+                    constant_time_eq(state.as_bytes(), cookie_state.as_bytes()),
+                    "This sign in was started in another browser. Please start again.");
+        let code = decoded_param(query, "code")?
+            .to_cmd_err(|| "The sign in did not return a code. Please start again.")?;
+        Ok((state, code))
+    }
+
+    fn start(&self, guild_id: &str) -> Result<OAuthResponse> {
+        let guild_id = match guild_id.parse() {
+            Ok(id) => GuildId(id),
+            Err(_) => return page(StatusCode::NotFound, "This page does not exist."),
+        };
+        cmd_ensure!(guild_id.find().is_some(), "This bot is not in that server.");
+        cmd_ensure!(self.config.get(None, ConfigKeys::VerificationMethodOAuth)? &&
+                    self.config.get(Some(guild_id), ConfigKeys::VerificationMethodOAuth)?,
+                    "Signing in on the website is not enabled on that server.");
+        let (client_id, _) = self.discord_app()?;
+        self.roblox_app()?;
+        let redirect_uri = format!("{}{}", self.public_url()?, DISCORD_CALLBACK_PATH);
+
+        let state = new_state()?;
+        {
+            let mut sessions = self.sessions.lock();
+            let expiry = Duration::from_secs(SESSION_EXPIRY_SECS);
+            sessions.retain(|_, session| session.started.elapsed() < expiry);
+            cmd_ensure!(sessions.len() < MAX_SESSIONS,
+                        "Too many people are signing in right now. Please try again later.");
+            sessions.insert(state.clone(), OAuthSession {
+                guild_id, discord_id: None, started: Instant::now(),
+            });
+        }
+
+        let mut url = Url::parse(&format!("{}/oauth2/authorize", DISCORD_API_BASE))?;
+        url.query_pairs_mut()
+            .append_pair("client_id", &client_id)
+            .append_pair("redirect_uri", &redirect_uri)
+            .append_pair("response_type", "code")
+            .append_pair("scope", "identify")
+            .append_pair("state", &state);
+        Ok(OAuthResponse::Redirect {
+            location: url.into_string(), cookie: Some(self.state_cookie(&state)?),
+        })
+    }
+
+    fn discord_callback(&self, req: &Request, query: &str) -> Result<OAuthResponse> {
+        let (state, code) = self.check_callback(req, query)?;
+        cmd_ensure!(self.sessions.lock().get(&state).map_or(false, |x| x.discord_id.is_none()),
+                    "This sign in has expired. Please start again.");
+
+        let (client_id, client_secret) = self.discord_app()?;
+        let redirect_uri = format!("{}{}", self.public_url()?, DISCORD_CALLBACK_PATH);
+        let params = [
+            ("client_id", client_id.as_str()), ("client_secret", client_secret.as_str()),
+            ("grant_type", "authorization_code"), ("code", code.as_str()),
+            ("redirect_uri", redirect_uri.as_str()),
+        ];
+        let client = reqwest::Client::new();
+        let mut response = client.post(&format!("{}/oauth2/token", DISCORD_API_BASE))
+            .form(&params).send()?;
+        cmd_ensure!(response.status().is_success(),
+                    "Discord did not accept the sign in. Please start again.");
+        let token = serde_json::from_str::<DiscordTokenResponse>(&response.text()?)?;
+        let user = client.get(&format!("{}/users/@me", DISCORD_API_BASE))
+            .header(Authorization(Bearer { token: token.access_token }))
+            .send()?.error_for_status()?.text()?;
+        let discord_id = serde_json::from_str::<DiscordCurrentUser>(&user)?.id;
+
+        match self.sessions.lock().get_mut(&state) {
+            Some(session) => session.discord_id = Some(discord_id),
+            None => cmd_error!("This sign in has expired. Please start again."),
+        }
+        let (client_id, client_secret) = self.roblox_app()?;
+        let app = RobloxOAuthApp { client_id: &client_id, client_secret: &client_secret };
+        let redirect_uri = format!("{}{}", self.public_url()?, ROBLOX_CALLBACK_PATH);
+        Ok(OAuthResponse::Redirect {
+            location: app.authorize_url(&redirect_uri, &state)?, cookie: None,
+        })
+    }
+
+    fn roblox_callback(&self, req: &Request, query: &str) -> Result<OAuthResponse> {
+        let (state, code) = self.check_callback(req, query)?;
+        let (guild_id, discord_id) = match self.sessions.lock().remove(&state) {
+            Some(OAuthSession { guild_id, discord_id: Some(discord_id), .. }) =>
+                (guild_id, discord_id),
+            _ => cmd_error!("This sign in has expired. Please start again."),
+        };
+
+        let (client_id, client_secret) = self.roblox_app()?;
+        let app = RobloxOAuthApp { client_id: &client_id, client_secret: &client_secret };
+        let redirect_uri = format!("{}{}", self.public_url()?, ROBLOX_CALLBACK_PATH);
+        let (roblox_id, roblox_name) = app.sign_in(&code, &redirect_uri)?;
+        let roblox_name = match roblox_name {
+            Some(name) => name,
+            None => roblox_id.lookup_username()?,
+        };
+
+        let core = match self.core_ref.get_core() {
+            Some(ref core) if core.is_running() => core.clone(),
+            _ => cmd_error!("The bot is currently restarting. Please try again later."),
+        };
+        let guild = guild_id.find().to_cmd_err(|| "This bot is no longer in that server.")?;
+        let user = guild.read().members.get(&discord_id).map(|x| x.user.read().clone())
+            .to_cmd_err(|| "You must join the server before you can verify in it.")?;
+        let prefix = core.config().get(Some(guild_id), ConfigKeys::CommandPrefix)?;
+        let message = verify_in_guild(&core, &prefix, guild_id, &user, &roblox_name,
+                                      &OAuthVerification, &roblox_id.0.to_string(),
+                                      |_| Ok(String::new()))?;
+        page(StatusCode::Ok, message)
+    }
+
+    fn route(&self, req: &Request) -> Result<OAuthResponse> {
+        let (path, query) = match split_uri(&req.uri) {
+            Some(split) => split,
+            None => return page(StatusCode::BadRequest, "Bad request."),
+        };
+        match path {
+            DISCORD_CALLBACK_PATH => self.discord_callback(req, query),
+            ROBLOX_CALLBACK_PATH => self.roblox_callback(req, query),
+            CODE_PAGE_PATH => match decoded_param(query, "code")? {
+                Some(code) => page(StatusCode::Ok, format!(
+                    "Your sign in code is: {} Copy it into the command you used to sign in.", code,
+                )),
+                None => page(StatusCode::BadRequest, "The sign in did not return a code."),
+            },
+            _ => self.start(&path[PATH_PREFIX.len()..]),
+        }
+    }
+
+    pub fn handle(&self, req: &Request, mut res: Response) {
+        let response = match self.route(req) {
+            Ok(response) => response,
+            Err(Error::CommandError(message)) => OAuthResponse::Page {
+                status: StatusCode::BadRequest, message: message.into_owned(),
+            },
+            Err(e) => {
+                error!("Error while handling web verification request to {}: {}", req.uri, e);
+                OAuthResponse::Page {
+                    status: StatusCode::InternalServerError,
+                    message: "An internal error occurred. Please try again later.".to_owned(),
+                }
+            }
+        };
+        let body = match response {
+            OAuthResponse::Redirect { location, cookie } => {
+                *res.status_mut() = StatusCode::Found;
+                res.headers_mut().set(Location(location));
+                if let Some(cookie) = cookie {
+                    res.headers_mut().set(SetCookie(vec![cookie]));
+                }
+                String::new()
+            }
+            OAuthResponse::Page { status, message } => {
+                *res.status_mut() = status;
+                res.headers_mut().set(ContentType::html());
+                format!("<!DOCTYPE html><html><head><meta charset=\"utf-8\">\
+                         <title>Verification</title></head><body><p>{}</p></body></html>",
+                        escape_xml(&message))
+            }
+        };
+        if let Err(e) = res.send(body.as_bytes()) {
+            debug!("Could not send web verification response: {}", e);
+        }
+    }
+}